@@ -1,9 +1,9 @@
-use std::{path::PathBuf, str::FromStr};
+use std::{net::SocketAddr, path::PathBuf, str::FromStr};
 
 #[cfg(feature = "schema")]
 use schemars::JsonSchema;
 
-use clap::{Args, Parser, Subcommand};
+use clap::{Args, Parser, Subcommand, ValueEnum};
 use serde::de::Error;
 
 /// The greatest of all reverse proxies, and
@@ -17,6 +17,16 @@ pub struct Cli {
     /// Log requests if enabled
     #[clap(short, long, default_value = "true")]
     pub log: Option<bool>,
+    /// Number of worker threads (default: number of cpu cores)
+    #[clap(short, long)]
+    pub workers: Option<usize>,
+    /// Drain connections for the configured drain period on SIGTERM before exiting
+    #[clap(long)]
+    pub drain_then_exit: bool,
+    /// Load module and middleware plugins from shared libraries
+    #[cfg(feature = "plugins")]
+    #[clap(long = "plugin", global = true)]
+    pub plugins: Vec<PathBuf>,
     /// Command for bob to run
     #[clap(subcommand)]
     pub command: Option<Command>,
@@ -35,9 +45,37 @@ pub enum Command {
     /// A quick reverse proxy
     #[cfg(feature = "rproxy")]
     ReverseProxy(RevProxyCmd),
+    /// A static response server
+    Static(StaticCmd),
+    /// A quick redirect server
+    Redirect(RedirectCmd),
     /// Generate a hashed password for basic-auth
     #[cfg(feature = "authn")]
     Passwd(GenPasswdCmd),
+    /// Reload configuration of a running instance
+    Reload(ControlCmd),
+    /// Gracefully stop a running instance
+    Stop(ControlCmd),
+    /// Query status of a running instance
+    Status(ControlCmd),
+    /// Generate a signed token enabling request-scoped debug flags
+    DebugToken(DebugTokenCmd),
+    /// Print the fully resolved configuration with secrets redacted
+    PrintConfig(RunCmd),
+    /// Report which server, directive and modules would handle a request
+    RouteTest(RouteTestCmd),
+    /// Replay captured requests against a server
+    #[cfg(feature = "replay")]
+    Replay(ReplayCmd),
+    /// Compare responses of two servers for captured requests
+    #[cfg(feature = "replay")]
+    Diff(DiffCmd),
+    /// Generate a locally trusted certificate for development
+    #[cfg(feature = "cert")]
+    Cert(CertCmd),
+    /// Convert an nginx or Caddyfile config into bob config
+    #[cfg(feature = "convert")]
+    Convert(ConvertCmd),
     /// Generate json schema for documentation
     #[cfg(feature = "schema")]
     Schema(SchemaCmd),
@@ -65,11 +103,173 @@ impl Default for RunCmd {
     }
 }
 
+#[derive(Args, Debug)]
+pub struct ControlCmd {
+    /// Path of configuration used by the running instance.
+    #[clap(short, long, default_value = "./config.yaml")]
+    pub config: PathBuf,
+    /// Admin api address or unix socket path overriding config
+    #[clap(short, long)]
+    pub admin: Option<String>,
+    /// Admin api bearer token overriding config
+    #[clap(short, long)]
+    pub token: Option<String>,
+    /// Process id to signal instead of using the admin api
+    #[clap(short, long, conflicts_with = "admin")]
+    pub pid: Option<u32>,
+}
+
+#[derive(Args, Debug)]
+pub struct DebugTokenCmd {
+    /// Flags enabled by the token
+    #[clap(required = true)]
+    pub flags: Vec<String>,
+    /// Shared secret overriding the config debug_flags secret
+    #[clap(short, long)]
+    pub secret: Option<String>,
+    /// Path of configuration containing the shared secret
+    #[clap(short, long, default_value = "./config.yaml")]
+    pub config: PathBuf,
+    /// Duration the token remains valid
+    #[clap(short, long, default_value = "1h")]
+    pub ttl: Duration,
+}
+
+#[derive(Args, Debug)]
+pub struct RouteTestCmd {
+    /// Request method
+    pub method: String,
+    /// Request url or path (e.g. https://example.com/api or /api)
+    pub url: String,
+    /// Host header overriding the host of the url
+    #[clap(long)]
+    pub host: Option<String>,
+    /// Set a request header (repeatable)
+    #[clap(short = 'H', long)]
+    pub header: Vec<Header>,
+    /// Client address the request originates from
+    #[clap(long)]
+    pub peer: Option<SocketAddr>,
+    /// Label of the listener the request arrives on
+    #[clap(long)]
+    pub listener: Option<String>,
+    /// Path of configuration to load
+    #[clap(short, long, default_value = "./config.yaml")]
+    pub config: PathBuf,
+}
+
+#[cfg(feature = "replay")]
+#[derive(Args, Debug)]
+pub struct ReplayCmd {
+    /// HAR files or raw http request dumps to replay
+    #[clap(required = true)]
+    pub input: Vec<PathBuf>,
+    /// Base url requests are replayed against
+    #[clap(short, long, required_unless_present = "config")]
+    pub target: Option<Uri>,
+    /// Start server from config and replay against its first listener
+    #[clap(short, long, conflicts_with = "target")]
+    pub config: Option<PathBuf>,
+    /// Maximum number of requests in-flight at once
+    #[clap(short = 'n', long, default_value = "1")]
+    pub concurrency: usize,
+    /// Per-request timeout
+    #[clap(long, default_value = "30s")]
+    pub timeout: Duration,
+    /// Disable TLS verification
+    #[clap(long)]
+    pub insecure: bool,
+}
+
+#[cfg(feature = "replay")]
+#[derive(Args, Debug)]
+pub struct DiffCmd {
+    /// HAR files or raw http request dumps to send
+    #[clap(required = true)]
+    pub input: Vec<PathBuf>,
+    /// Base url of the current server
+    #[clap(short, long)]
+    pub baseline: Uri,
+    /// Base url of the server being validated
+    #[clap(short, long)]
+    pub candidate: Uri,
+    /// Compare sha256 hashes of response bodies
+    #[clap(long)]
+    pub bodies: bool,
+    /// Response header excluded from comparison (repeatable)
+    #[clap(short, long)]
+    pub ignore_header: Vec<String>,
+    /// Maximum number of requests in-flight at once
+    #[clap(short = 'n', long, default_value = "1")]
+    pub concurrency: usize,
+    /// Per-request timeout
+    #[clap(long, default_value = "30s")]
+    pub timeout: Duration,
+    /// Disable TLS verification
+    #[clap(long)]
+    pub insecure: bool,
+}
+
+#[cfg(feature = "cert")]
+#[derive(Args, Debug)]
+pub struct CertCmd {
+    /// Hostnames and ip addresses covered by the certificate
+    #[clap(required_unless_present = "install")]
+    pub hosts: Vec<String>,
+    /// Directory certificate and key are written to
+    #[clap(short, long, default_value = ".")]
+    pub output: PathBuf,
+    /// Directory of the local certificate authority (default: $XDG_DATA_HOME/bob)
+    #[clap(long)]
+    pub ca_dir: Option<PathBuf>,
+    /// Duration the certificate remains valid
+    #[clap(long, default_value = "825days")]
+    pub valid: Duration,
+    /// Trust the local certificate authority in the system trust store
+    #[clap(long)]
+    pub install: bool,
+}
+
+#[cfg(feature = "convert")]
+#[derive(Args, Debug)]
+pub struct ConvertCmd {
+    /// nginx.conf or Caddyfile to convert
+    pub input: PathBuf,
+    /// Format of the input (default: detected from the filename)
+    #[clap(short, long)]
+    pub format: Option<ConvertFormat>,
+    /// File the generated config is written to (default: stdout)
+    #[clap(short, long)]
+    pub output: Option<PathBuf>,
+}
+
+/// Configuration format understood by [`ConvertCmd`].
+#[cfg(feature = "convert")]
+#[derive(Clone, Copy, Debug, ValueEnum)]
+pub enum ConvertFormat {
+    Nginx,
+    Caddy,
+}
+
 #[cfg(feature = "schema")]
 #[derive(Args, Debug)]
 pub struct SchemaCmd {
-    #[clap(short, long, default_value = "schema.json")]
-    pub output: PathBuf,
+    /// Output file (default: schema.json or config-reference.md)
+    #[clap(short, long)]
+    pub output: Option<PathBuf>,
+    /// Format of the generated schema
+    #[clap(short, long, value_enum, default_value = "json")]
+    pub format: SchemaFormat,
+}
+
+/// Output format of [`SchemaCmd`].
+#[cfg(feature = "schema")]
+#[derive(Clone, Copy, Debug, ValueEnum)]
+pub enum SchemaFormat {
+    /// JSON schema
+    Json,
+    /// Human readable markdown config reference
+    Markdown,
 }
 
 #[cfg(feature = "authn")]
@@ -93,12 +293,14 @@ pub struct FastCgiCmd {
     /// Supported index files when accessing directory
     #[clap(short, long, default_value = "index.php")]
     pub index: Vec<String>,
-    /// The address to which to bind the listener
+    /// The address to which to bind the listener (repeatable)
     #[clap(short, long, default_value = "localhost:8000")]
-    pub listen: String,
+    pub listen: Vec<String>,
     /// The path to the root of the site
     #[clap(short, long, default_value = ".")]
     pub root: PathBuf,
+    #[clap(flatten)]
+    pub tls: TlsArgs,
 }
 
 #[cfg(feature = "fileserver")]
@@ -110,9 +312,9 @@ pub struct FileServerCmd {
     /// Supported index files when browsing is disabled
     #[clap(short, long, default_value = "index.html")]
     pub index: Vec<String>,
-    /// The address to which to bind the listener
+    /// The address to which to bind the listener (repeatable)
     #[clap(short, long, default_value = "localhost:8000")]
-    pub listen: String,
+    pub listen: Vec<String>,
     /// The path to the root of the site
     #[clap(short, long, default_value = ".")]
     pub root: PathBuf,
@@ -122,6 +324,15 @@ pub struct FileServerCmd {
     /// Open server in browser
     #[clap(long)]
     pub open: bool,
+    /// Share on the local network and print a QR code of the url
+    #[cfg(feature = "share")]
+    #[clap(long)]
+    pub share: bool,
+    #[clap(flatten)]
+    pub tls: TlsArgs,
+    #[cfg(feature = "authn")]
+    #[clap(flatten)]
+    pub auth: AuthArgs,
 }
 
 #[cfg(feature = "rproxy")]
@@ -130,9 +341,9 @@ pub struct RevProxyCmd {
     /// Set upstream Host header to address of upstream
     #[clap(short, long)]
     pub change_host_header: bool,
-    /// Address used to recieve traffic
+    /// Address used to recieve traffic (repeatable)
     #[clap(short, long, default_value = "localhost:8000")]
-    pub from: String,
+    pub from: Vec<String>,
     /// Disable TLS verification
     #[clap(long)]
     pub insecure: bool,
@@ -151,14 +362,86 @@ pub struct RevProxyCmd {
     /// Open server in browser
     #[clap(long)]
     pub open: bool,
+    #[clap(flatten)]
+    pub tls: TlsArgs,
+    #[cfg(feature = "authn")]
+    #[clap(flatten)]
+    pub auth: AuthArgs,
+}
+
+#[derive(Args, Debug)]
+pub struct StaticCmd {
+    /// Response status code
+    #[clap(short, long, default_value = "200")]
+    pub status: u16,
+    /// Response body
+    #[clap(short, long, conflicts_with = "body_file")]
+    pub body: Option<String>,
+    /// File containing the response body
+    #[clap(long)]
+    pub body_file: Option<PathBuf>,
+    /// Response content type (default: text/html)
+    #[clap(short, long)]
+    pub content_type: Option<String>,
+    /// Set a response header (repeatable)
+    #[clap(short = 'H', long)]
+    pub header: Vec<Header>,
+    /// The address to which to bind the listener
+    #[clap(short, long, default_value = "localhost:8000")]
+    pub listen: String,
+    #[clap(flatten)]
+    pub tls: TlsArgs,
+}
+
+#[derive(Args, Debug)]
+pub struct RedirectCmd {
+    /// Address used to recieve traffic
+    #[clap(short, long, default_value = "localhost:8000")]
+    pub from: String,
+    /// URI clients are redirected to
+    #[clap(short, long)]
+    pub to: String,
+    /// Redirect status code
+    #[clap(short, long, default_value = "302")]
+    pub status: u16,
+    /// Append the request path and query to the redirect URI
+    #[clap(short, long)]
+    pub preserve_path: bool,
+    #[clap(flatten)]
+    pub tls: TlsArgs,
+}
+
+/// Basic-auth options shared by quick server commands
+#[cfg(feature = "authn")]
+#[derive(Args, Debug, Default)]
+pub struct AuthArgs {
+    /// Require basic-auth credentials formatted as user:pass (repeatable)
+    #[clap(long)]
+    pub auth: Vec<String>,
+    /// Require basic-auth credentials from htpasswd file (repeatable)
+    #[clap(long)]
+    pub htpasswd: Vec<PathBuf>,
+}
+
+/// HTTPS options shared by quick server commands
+#[derive(Args, Debug, Default)]
+pub struct TlsArgs {
+    /// PEM certificate chain enabling https
+    #[clap(long, requires = "key")]
+    pub cert: Option<PathBuf>,
+    /// PEM private key of the certificate
+    #[clap(long, requires = "cert")]
+    pub key: Option<PathBuf>,
+    /// Enable https with a generated self-signed certificate
+    #[cfg(feature = "cert")]
+    #[clap(long, conflicts_with_all = ["cert", "key"])]
+    pub self_signed: bool,
 }
 
 /// Header key/value pair parsed from a string
-#[cfg(feature = "rproxy")]
 #[derive(Clone, Debug)]
 pub struct Header(pub String, pub String);
 
-#[cfg(feature = "rproxy")]
 impl FromStr for Header {
     type Err = std::io::Error;
 
@@ -211,6 +494,12 @@ impl JsonSchema for Duration {
     }
 }
 
+impl std::fmt::Display for Duration {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}", humantime::format_duration(self.0))
+    }
+}
+
 /// Resource URI object and parser.
 #[derive(Clone, Debug)]
 pub struct Uri(pub actix_http::Uri);
@@ -224,6 +513,12 @@ impl FromStr for Uri {
     }
 }
 
+impl std::fmt::Display for Uri {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}", self.0)
+    }
+}
+
 #[cfg(feature = "schema")]
 impl JsonSchema for Uri {
     fn schema_name() -> std::borrow::Cow<'static, str> {
@@ -252,5 +547,21 @@ macro_rules! de_fromstr {
     };
 }
 
+#[macro_export]
+macro_rules! ser_display {
+    ($s:ident) => {
+        impl serde::Serialize for $s {
+            fn serialize<S>(&self, serializer: S) -> Result<S::Ok, S::Error>
+            where
+                S: serde::Serializer,
+            {
+                serializer.collect_str(self)
+            }
+        }
+    };
+}
+
 de_fromstr!(Duration);
 de_fromstr!(Uri);
+ser_display!(Duration);
+ser_display!(Uri);
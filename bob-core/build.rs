@@ -0,0 +1,18 @@
+use std::process::Command;
+
+/// Expose the compiler version so plugins built differently are refused.
+fn rustc_version() -> String {
+    let rustc = std::env::var("RUSTC").unwrap_or_else(|_| "rustc".to_owned());
+    Command::new(rustc)
+        .arg("--version")
+        .output()
+        .ok()
+        .and_then(|out| String::from_utf8(out.stdout).ok())
+        .map(|version| version.trim().to_owned())
+        .unwrap_or_else(|| "unknown".to_owned())
+}
+
+fn main() {
+    println!("cargo:rerun-if-env-changed=RUSTC");
+    println!("cargo:rustc-env=BOB_RUSTC_VERSION={}", rustc_version());
+}
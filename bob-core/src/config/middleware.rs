@@ -0,0 +1,3180 @@
+//! Middleware Configuration
+
+#[cfg(feature = "schema")]
+use schemars::JsonSchema;
+
+use actix_chain::Wrappable;
+use serde::{Deserialize, Serialize};
+
+use super::Spec;
+
+#[cfg(any(feature = "authn", feature = "ipban", feature = "tarpit"))]
+use super::IpMatch;
+#[cfg(any(
+    feature = "authn",
+    feature = "ipfilter",
+    feature = "captcha",
+    feature = "openapi",
+    feature = "honeypot",
+    feature = "tarpit"
+))]
+use super::PathMatch;
+#[cfg(any(
+    feature = "authn",
+    feature = "ipban",
+    feature = "captcha",
+    feature = "useragent",
+    feature = "exec",
+    feature = "tarpit"
+))]
+use super::default_duration;
+#[cfg(feature = "authn")]
+use crate::services::{
+    bypass::{Bypass, Predicate, is_preflight},
+    lockout,
+};
+#[cfg(feature = "authn")]
+use actix_web::dev::ServiceRequest;
+#[cfg(any(
+    feature = "authn",
+    feature = "ipban",
+    feature = "captcha",
+    feature = "useragent",
+    feature = "honeypot",
+    feature = "exec",
+    feature = "tarpit"
+))]
+use bob_cli::Duration;
+#[cfg(feature = "authn")]
+use std::rc::Rc;
+
+/// Middleware configuration for request processing.
+#[cfg_attr(feature = "schema", derive(JsonSchema))]
+#[derive(Debug, Clone, Deserialize, Serialize)]
+#[serde(tag = "middleware", deny_unknown_fields)]
+pub enum Middleware {
+    /// Configuration for [`actix_authn::basic::BasicAuthSession`] Middleware.
+    #[cfg(feature = "authn")]
+    #[serde(alias = "basic_auth")]
+    AuthBasic(auth_basic::Config),
+    /// Configuration for [`actix_authn::basic::BasicAuthSession`] Middleware.
+    #[cfg(feature = "authn")]
+    #[serde(alias = "basic_auth_session")]
+    AuthSession(auth_session::Config),
+    /// Configuration for [`actix_ipware`] Middleware.
+    #[cfg(feature = "ipware")]
+    #[serde(alias = "ipware")]
+    Ipware(ipware::Config),
+    /// Configuration for [`crate::services::ipfilter`] Middleware.
+    #[cfg(feature = "ipfilter")]
+    #[serde(alias = "filter")]
+    Ipfilter(ipfilter::Config),
+    /// Configuration for [`crate::services::ban`] Middleware.
+    #[cfg(feature = "ipban")]
+    #[serde(alias = "ban", alias = "ipban")]
+    IpBan(ipban::Config),
+    /// Configuration for [`crate::services::captcha`] Middleware.
+    #[cfg(feature = "captcha")]
+    #[serde(alias = "captcha", alias = "challenge")]
+    Captcha(captcha::Config),
+    /// Configuration for [`crate::services::useragent`] Middleware.
+    #[cfg(feature = "useragent")]
+    #[serde(alias = "user_agent", alias = "useragent", alias = "bots")]
+    UserAgent(useragent::Config),
+    /// Configuration for [`crate::services::honeypot`] Middleware.
+    #[cfg(feature = "honeypot")]
+    #[serde(alias = "honeypot", alias = "trap")]
+    Honeypot(honeypot::Config),
+    /// Configuration for [`crate::services::exec`] Middleware.
+    #[cfg(feature = "exec")]
+    #[serde(alias = "exec", alias = "exec_hook")]
+    Exec(exec::Config),
+    /// Configuration for [`crate::services::tarpit`] Middleware.
+    #[cfg(feature = "tarpit")]
+    #[serde(alias = "tarpit")]
+    Tarpit(tarpit::Config),
+    /// Configuration for [`crate::services::geoip`] Middleware.
+    #[cfg(feature = "geoip")]
+    #[serde(alias = "geoip")]
+    GeoIp(geoip::Config),
+    /// Configuration for [`crate::services::modsecurity`] Middleware.
+    #[cfg(feature = "modsecurity")]
+    #[serde(alias = "modsecurity")]
+    ModSecurity(modsecurity::Config),
+    /// Configuration for [`crate::services::openapi`] Middleware.
+    #[cfg(feature = "openapi")]
+    #[serde(alias = "openapi")]
+    OpenApi(openapi::Config),
+    /// Configuration for [`actix_rewrite`] Middleware.
+    #[cfg(feature = "rewrite")]
+    #[serde(alias = "rewrite")]
+    Rewrite(rewrite::Config),
+    /// Configuration for [`actix_extensible_rate_limit`] Middleware
+    #[cfg(feature = "ratelimit")]
+    #[serde(alias = "ratelimit")]
+    Ratelimit(ratelimit::Config),
+    /// Configuration for [`actix_timeout`] Middleware
+    #[cfg(feature = "timeout")]
+    #[serde(alias = "timeout")]
+    Timeout(timeout::Config),
+    /// Configuration for [`crate::services::cors`] Middleware
+    #[cfg(feature = "cors")]
+    #[serde(alias = "cors")]
+    Cors(cors::Config),
+    /// Configuration for [`crate::services::dictionary`] Middleware
+    #[cfg(feature = "compress")]
+    #[serde(alias = "compress", alias = "zstd_dictionary")]
+    Compress(compress::Config),
+    /// Configuration for [`crate::services::transform`] Middleware
+    #[cfg(feature = "transform")]
+    #[serde(alias = "transform", alias = "body_filter")]
+    Transform(transform::Config),
+    /// Configuration for [`crate::services::rebinding`] Middleware
+    #[cfg(feature = "rebinding")]
+    #[serde(alias = "rebinding", alias = "dns_rebinding")]
+    Rebinding(rebinding::Config),
+    /// Configuration for [`crate::services::request_id`] Middleware
+    #[cfg(feature = "requestid")]
+    #[serde(alias = "request_id", alias = "requestid")]
+    RequestId(request_id::Config),
+    /// Configuration for [`crate::services::script`] Middleware
+    #[cfg(feature = "script")]
+    #[serde(alias = "script", alias = "rhai")]
+    Script(script::Config),
+    /// Configuration for [`crate::services::wasm`] Middleware
+    #[cfg(feature = "wasm")]
+    #[serde(alias = "wasm", alias = "wasm_filter")]
+    Wasm(wasm::Config),
+    /// Middleware provided by a registered [`crate::plugin::BobMiddleware`].
+    #[serde(skip_deserializing, rename = "plugin")]
+    Plugin(crate::plugin::PluginMiddleware),
+}
+
+impl Middleware {
+    /// Basic authorization middleware using credentials from htpasswd files.
+    #[cfg(feature = "authn")]
+    pub fn basic_auth(htpasswd: Vec<std::path::PathBuf>) -> Self {
+        Self::AuthBasic(auth_basic::Config {
+            htpasswd,
+            ..Default::default()
+        })
+    }
+
+    /// Validate and prepare middleware resources ahead of construction.
+    ///
+    /// Called once per process during configuration loading so invalid
+    /// settings produce a clean startup error instead of a worker panic.
+    pub fn prepare(&self, spec: &Spec) -> anyhow::Result<()> {
+        match self {
+            #[cfg(feature = "modsecurity")]
+            Self::ModSecurity(config) => config.prepare(spec),
+            #[cfg(feature = "rewrite")]
+            Self::Rewrite(config) => config.prepare(spec),
+            #[cfg(feature = "authn")]
+            Self::AuthSession(config) => config.prepare(spec),
+            #[cfg(feature = "ratelimit")]
+            Self::Ratelimit(config) => config.prepare(spec),
+            #[cfg(feature = "compress")]
+            Self::Compress(config) => config.prepare(spec),
+            #[cfg(feature = "geoip")]
+            Self::GeoIp(config) => config.prepare(spec),
+            #[cfg(feature = "ipban")]
+            Self::IpBan(config) => config.prepare(spec),
+            #[cfg(feature = "captcha")]
+            Self::Captcha(config) => config.prepare(spec),
+            #[cfg(feature = "transform")]
+            Self::Transform(config) => config.prepare(spec),
+            #[cfg(feature = "useragent")]
+            Self::UserAgent(config) => config.prepare(spec),
+            #[cfg(feature = "honeypot")]
+            Self::Honeypot(config) => config.prepare(spec),
+            #[cfg(feature = "cors")]
+            Self::Cors(config) => config.prepare(spec),
+            #[cfg(feature = "exec")]
+            Self::Exec(config) => config.prepare(spec),
+            #[cfg(feature = "tarpit")]
+            Self::Tarpit(config) => config.prepare(spec),
+            #[cfg(feature = "requestid")]
+            Self::RequestId(config) => config.prepare(spec),
+            #[cfg(feature = "openapi")]
+            Self::OpenApi(config) => config.prepare(spec),
+            #[cfg(feature = "script")]
+            Self::Script(config) => config.prepare(spec),
+            #[cfg(feature = "wasm")]
+            Self::Wasm(config) => config.prepare(spec),
+            Self::Plugin(config) => config.prepare(spec),
+            #[allow(unreachable_patterns)]
+            _ => Ok(()),
+        }
+    }
+
+    /// Check if middleware resolves the effective client address.
+    ///
+    /// Server level client address middleware is applied outermost so
+    /// every other component agrees on the client peer address.
+    #[inline]
+    pub fn resolves_client(&self) -> bool {
+        #[cfg(feature = "ipware")]
+        return matches!(self, Self::Ipware(_));
+
+        #[cfg(not(feature = "ipware"))]
+        false
+    }
+
+    /// Check if middleware trusts upstream proxies to report the client.
+    ///
+    /// Forwarded request details such as the scheme are only
+    /// honored when a trusted proxy is configured.
+    #[inline]
+    pub fn trusts_proxies(&self) -> bool {
+        #[cfg(feature = "ipware")]
+        return matches!(self, Self::Ipware(config) if config.trusts_proxies());
+
+        #[cfg(not(feature = "ipware"))]
+        false
+    }
+
+    /// Establish external connections required by the middleware.
+    ///
+    /// Called once per process after [`Middleware::prepare`].
+    pub async fn connect(&self) -> anyhow::Result<()> {
+        match self {
+            #[cfg(feature = "redis")]
+            Self::AuthSession(config) => config.connect().await,
+            #[cfg(feature = "modsecurity")]
+            Self::ModSecurity(config) => config.connect().await,
+            #[allow(unreachable_patterns)]
+            _ => Ok(()),
+        }
+    }
+
+    /// Wrap Chain/Link in all of the established middleware.
+    pub fn wrap<W: Wrappable>(&self, wrap: W, spec: &Spec) -> W {
+        match self {
+            #[cfg(feature = "authn")]
+            Self::AuthBasic(config) => config.wrap(wrap, spec),
+            #[cfg(feature = "authn")]
+            Self::AuthSession(config) => config.wrap(wrap, spec),
+            #[cfg(feature = "ipware")]
+            Self::Ipware(config) => config.wrap(wrap, spec),
+            #[cfg(feature = "ipfilter")]
+            Self::Ipfilter(config) => config.wrap(wrap, spec),
+            #[cfg(feature = "ipban")]
+            Self::IpBan(config) => config.wrap(wrap, spec),
+            #[cfg(feature = "geoip")]
+            Self::GeoIp(config) => config.wrap(wrap, spec),
+            #[cfg(feature = "useragent")]
+            Self::UserAgent(config) => config.wrap(wrap, spec),
+            #[cfg(feature = "honeypot")]
+            Self::Honeypot(config) => config.wrap(wrap, spec),
+            #[cfg(feature = "exec")]
+            Self::Exec(config) => config.wrap(wrap, spec),
+            #[cfg(feature = "tarpit")]
+            Self::Tarpit(config) => config.wrap(wrap, spec),
+            #[cfg(feature = "captcha")]
+            Self::Captcha(config) => config.wrap(wrap, spec),
+            #[cfg(feature = "modsecurity")]
+            Self::ModSecurity(config) => config.wrap(wrap, spec),
+            #[cfg(feature = "openapi")]
+            Self::OpenApi(config) => config.wrap(wrap, spec),
+            #[cfg(feature = "rewrite")]
+            Self::Rewrite(config) => config.wrap(wrap, spec),
+            #[cfg(feature = "ratelimit")]
+            Self::Ratelimit(config) => config.wrap(wrap, spec),
+            #[cfg(feature = "timeout")]
+            Self::Timeout(config) => config.wrap(wrap, spec),
+            #[cfg(feature = "cors")]
+            Self::Cors(config) => config.wrap(wrap, spec),
+            #[cfg(feature = "compress")]
+            Self::Compress(config) => config.wrap(wrap, spec),
+            #[cfg(feature = "transform")]
+            Self::Transform(config) => config.wrap(wrap, spec),
+            #[cfg(feature = "rebinding")]
+            Self::Rebinding(config) => config.wrap(wrap, spec),
+            #[cfg(feature = "requestid")]
+            Self::RequestId(config) => config.wrap(wrap, spec),
+            #[cfg(feature = "script")]
+            Self::Script(config) => config.wrap(wrap, spec),
+            #[cfg(feature = "wasm")]
+            Self::Wasm(config) => config.wrap(wrap, spec),
+            Self::Plugin(config) => config.wrap(wrap, spec),
+        }
+    }
+}
+
+/// Rule definitions loaded once during config validation
+/// and shared between every worker actix-web creates.
+#[cfg(any(feature = "modsecurity", feature = "rewrite"))]
+#[derive(Clone, Default)]
+struct RuleSet(std::sync::Arc<std::sync::OnceLock<String>>);
+
+#[cfg(any(feature = "modsecurity", feature = "rewrite"))]
+impl RuleSet {
+    /// Load inline rules and rule files into a single shared ruleset.
+    fn load(&self, rules: &Option<String>, files: &[std::path::PathBuf]) -> anyhow::Result<&str> {
+        use anyhow::Context;
+        if let Some(rules) = self.0.get() {
+            return Ok(rules);
+        }
+        let mut combined = rules.clone().unwrap_or_default();
+        for path in files {
+            let content = std::fs::read_to_string(path)
+                .with_context(|| format!("failed to read rules file {path:?}"))?;
+            combined.push('\n');
+            combined.push_str(&content);
+        }
+        Ok(self.0.get_or_init(|| combined))
+    }
+}
+
+#[cfg(any(feature = "modsecurity", feature = "rewrite"))]
+impl std::fmt::Debug for RuleSet {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "RuleSet {{}}")
+    }
+}
+
+/// Request conditions allowing a client to skip middleware.
+///
+/// A request matching any of the configured conditions is excluded.
+#[cfg(feature = "authn")]
+#[cfg_attr(feature = "schema", derive(JsonSchema))]
+#[derive(Debug, Clone, Default, Deserialize, Serialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct Exceptions {
+    /// Client IP addresses, CIDR ranges or address ranges to exclude.
+    ips: Vec<IpMatch>,
+    /// Request path globs to exclude.
+    paths: Vec<PathMatch>,
+    /// Request methods to exclude (such as `OPTIONS`).
+    methods: Vec<String>,
+}
+
+#[cfg(feature = "authn")]
+impl Exceptions {
+    /// Check if no exception conditions are configured.
+    #[inline]
+    pub fn is_empty(&self) -> bool {
+        self.ips.is_empty() && self.paths.is_empty() && self.methods.is_empty()
+    }
+
+    /// Check if request matches any of the exception conditions.
+    pub fn matches(&self, req: &ServiceRequest) -> bool {
+        let ip = req.peer_addr().map(|addr| addr.ip());
+        ip.is_some_and(|ip| self.ips.iter().any(|m| m.contains(&ip)))
+            || self.paths.iter().any(|p| p.matches_strict(req.path()))
+            || self
+                .methods
+                .iter()
+                .any(|m| m.eq_ignore_ascii_case(req.method().as_str()))
+    }
+
+    /// Produce [`crate::services::bypass::Predicate`] from exceptions.
+    ///
+    /// CORS preflights are included when `preflight` is enabled.
+    /// Returns `None` when no requests should bypass.
+    pub fn predicate(&self, preflight: bool) -> Option<Predicate> {
+        let exceptions = self.clone();
+        match (self.is_empty(), preflight) {
+            (true, false) => None,
+            (true, true) => Some(Rc::new(is_preflight)),
+            (false, false) => Some(Rc::new(move |req| exceptions.matches(req))),
+            (false, true) => Some(Rc::new(move |req| {
+                is_preflight(req) || exceptions.matches(req)
+            })),
+        }
+    }
+}
+
+/// Failed authentication lockout settings.
+#[cfg(feature = "authn")]
+#[cfg_attr(feature = "schema", derive(JsonSchema))]
+#[derive(Debug, Clone, Default, Deserialize, Serialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct LockoutCfg {
+    /// Enable failed authentication tracking if true.
+    ///
+    /// Default is false
+    enable: bool,
+    /// Failed attempts allowed per username and IP before lockout.
+    ///
+    /// Default is 5
+    max_failures: Option<u32>,
+    /// Window in which failed attempts are counted.
+    ///
+    /// Default is 15m
+    window: Option<Duration>,
+    /// Duration of lockout once max failures is exceeded.
+    ///
+    /// Default is 15m
+    lock_duration: Option<Duration>,
+    /// Initial delay applied after a failure, doubled on every failure.
+    ///
+    /// Default is 250ms
+    delay: Option<Duration>,
+    /// Maximum delay applied to any single attempt.
+    ///
+    /// Default is 10s
+    max_delay: Option<Duration>,
+    /// Client addresses exempt from lockout.
+    exempt: Vec<IpMatch>,
+
+    // global initialization for failure tracking.
+    // avoids recreating the state for every worker actix-web creates.
+    #[serde(default, skip)]
+    state: lockout::State,
+}
+
+#[cfg(feature = "authn")]
+impl LockoutCfg {
+    /// Produce [`crate::services::lockout::Lockout`] from config.
+    pub fn factory(&self) -> Option<lockout::Lockout> {
+        if !self.enable {
+            return None;
+        }
+        let settings = lockout::Settings {
+            max_failures: self.max_failures.unwrap_or(5),
+            window: default_duration(&self.window, 15 * 60),
+            lock_duration: default_duration(&self.lock_duration, 15 * 60),
+            base_delay: self
+                .delay
+                .as_ref()
+                .map(|d| d.0)
+                .unwrap_or(std::time::Duration::from_millis(250)),
+            max_delay: default_duration(&self.max_delay, 10),
+            exempt: self.exempt.clone(),
+        };
+        Some(lockout::Lockout::new(settings, self.state.clone()))
+    }
+}
+
+/// HTTP Basic Authorization Middleware
+#[cfg(feature = "authn")]
+mod auth_basic {
+    use std::{fmt::Debug, path::PathBuf};
+
+    use super::*;
+    use crate::services::reload::{Reload, Watcher};
+
+    use actix_authn::{
+        Authn,
+        basic::{Basic, BasicAuth},
+    };
+
+    #[cfg_attr(feature = "schema", derive(JsonSchema))]
+    #[derive(Debug, Clone, Default, Deserialize, Serialize)]
+    #[serde(deny_unknown_fields)]
+    pub struct Config {
+        /// Cache size linked to authentication lookup
+        cache_size: Option<usize>,
+        /// Htpasswd filepaths to load credentials from.
+        htpasswd: Vec<PathBuf>,
+        /// Reload credentials when htpasswd files change.
+        ///
+        /// Default is false
+        #[serde(default)]
+        watch: bool,
+        /// Interval between htpasswd modification checks.
+        ///
+        /// Default is 5s
+        watch_interval: Option<Duration>,
+        /// Request conditions which skip authentication.
+        #[serde(default)]
+        except: Exceptions,
+        /// Failed authentication lockout settings.
+        #[serde(default)]
+        lockout: LockoutCfg,
+
+        // global initialization for htpasswd watcher.
+        // avoids spawning a watcher for every worker actix-web creates.
+        #[serde(default, skip)]
+        watcher: Watcher,
+    }
+
+    impl Config {
+        /// Produce [`actix_authn::Authn`] from config.
+        pub fn factory(&self, _spec: &Spec) -> Authn<BasicAuth> {
+            let mut auth =
+                Basic::default().cache_size(self.cache_size.unwrap_or(u16::MAX as usize));
+            auth = self
+                .htpasswd
+                .iter()
+                .fold(auth, |auth, path| auth.htpasswd(path));
+            Authn::new(auth.build())
+        }
+
+        /// Wrap Chain/Link with configured middleware.
+        pub fn wrap<W: Wrappable>(&self, w: W, spec: &Spec) -> W {
+            let w = self.wrap_auth(w, spec);
+            match self.lockout.factory() {
+                Some(lockout) => w.wrap_with(lockout),
+                None => w,
+            }
+        }
+
+        /// Wrap Chain/Link with authentication middleware.
+        fn wrap_auth<W: Wrappable>(&self, w: W, spec: &Spec) -> W {
+            let skip = self.except.predicate(spec.config.preflight_bypass);
+            if !self.watch {
+                return match skip {
+                    None => w.wrap_with(self.factory(spec)),
+                    Some(skip) => w.wrap_with(Bypass::new(self.factory(spec), skip)),
+                };
+            }
+            let interval = default_duration(&self.watch_interval, 5);
+            let generation = self.watcher.watch(&self.htpasswd, interval);
+            let config = self.clone();
+            let root = spec.config.clone();
+            let auth = Reload::new(move || config.factory(&Spec::new(&root)), generation);
+            match skip {
+                None => w.wrap_with(auth),
+                Some(skip) => w.wrap_with(Bypass::new(auth, skip)),
+            }
+        }
+    }
+}
+
+/// HTTP Basic Authorization with Cookie Session Middleware
+#[cfg(feature = "authn")]
+mod auth_session {
+    use std::{
+        fmt::Debug,
+        path::PathBuf,
+        sync::{Arc, OnceLock},
+    };
+
+    use super::*;
+    use crate::services::{
+        key_rotation::KeyRotation,
+        reload::{Reload, Watcher},
+    };
+
+    use actix_authn::{
+        Authn,
+        basic::{Basic, BasicAuthSession},
+    };
+    use actix_session::config::BrowserSession;
+    use actix_web::cookie::Key;
+
+    /// Derivation wrapper around [`actix_web::cookie::Key`]
+    #[derive(Clone)]
+    struct CookieKey(Key);
+
+    impl Debug for CookieKey {
+        fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+            write!(f, "CookieKey {{}}")
+        }
+    }
+
+    impl Default for CookieKey {
+        fn default() -> Self {
+            Self(Key::generate())
+        }
+    }
+
+    /// Signing keys loaded once from their configured sources.
+    #[derive(Clone, Default)]
+    struct LoadedKeys(Arc<OnceLock<(Key, Vec<Key>)>>);
+
+    impl Debug for LoadedKeys {
+        fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+            write!(f, "LoadedKeys {{}}")
+        }
+    }
+
+    /// Session storage backend.
+    #[cfg_attr(feature = "schema", derive(JsonSchema))]
+    #[derive(Debug, Clone, Default, Deserialize, Serialize)]
+    #[serde(tag = "type", rename_all = "lowercase", deny_unknown_fields)]
+    pub enum StoreCfg {
+        /// Store session state within the encrypted cookie.
+        #[default]
+        Cookie,
+        /// Store session state within redis shared between instances.
+        #[cfg(feature = "redis")]
+        Redis {
+            /// Redis connection URL (such as `redis://127.0.0.1:6379`).
+            url: crate::config::Secret,
+        },
+    }
+
+    /// Derivation wrapper around [`actix_session::storage::RedisSessionStore`]
+    #[cfg(feature = "redis")]
+    #[derive(Clone, Default)]
+    struct RedisStore(
+        std::sync::Arc<std::sync::OnceLock<actix_session::storage::RedisSessionStore>>,
+    );
+
+    #[cfg(feature = "redis")]
+    impl Debug for RedisStore {
+        fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+            write!(f, "RedisStore {{}}")
+        }
+    }
+
+    /// Session signing key source.
+    ///
+    /// Key material must be base64 encoded and at least 32 bytes long.
+    #[cfg_attr(feature = "schema", derive(JsonSchema))]
+    #[derive(Debug, Clone, Default, Deserialize, Serialize)]
+    #[serde(default, deny_unknown_fields)]
+    pub struct KeySource {
+        /// Filepath to read key material from.
+        file: Option<PathBuf>,
+        /// Environment variable to read key material from.
+        env: Option<String>,
+    }
+
+    impl KeySource {
+        /// Load [`actix_web::cookie::Key`] from key source.
+        pub fn load(&self) -> anyhow::Result<Key> {
+            use anyhow::{Context, anyhow};
+            use base64::{Engine, prelude::BASE64_STANDARD};
+
+            let encoded = match (self.file.as_ref(), self.env.as_ref()) {
+                (Some(path), None) => std::fs::read_to_string(path)
+                    .with_context(|| format!("failed to read session key {path:?}"))?,
+                (None, Some(env)) => std::env::var(env)
+                    .with_context(|| format!("missing session key env {env:?}"))?,
+                _ => return Err(anyhow!("session key requires either a file or env")),
+            };
+            let material = BASE64_STANDARD
+                .decode(encoded.trim())
+                .context("session key is not valid base64")?;
+            if material.len() < 32 {
+                return Err(anyhow!("session key must be at least 32 bytes"));
+            }
+            Ok(Key::derive_from(&material))
+        }
+    }
+
+    #[cfg_attr(feature = "schema", derive(JsonSchema))]
+    #[derive(Debug, Clone, Default, Deserialize, Serialize)]
+    #[serde(deny_unknown_fields)]
+    pub struct Config {
+        /// Htpasswd filepaths to load credentials from.
+        htpasswd: Vec<PathBuf>,
+        /// Cookie name associated with session.
+        cookie_name: Option<String>,
+        /// Cache size linked to authentication lookup
+        ///
+        /// Default is u16::MAX
+        cache_size: Option<usize>,
+        /// Reload credentials when htpasswd files change.
+        ///
+        /// Default is false
+        #[serde(default)]
+        watch: bool,
+        /// Interval between htpasswd modification checks.
+        ///
+        /// Default is 5s
+        watch_interval: Option<Duration>,
+        /// Request conditions which skip authentication.
+        #[serde(default)]
+        except: Exceptions,
+        /// Failed authentication lockout settings.
+        #[serde(default)]
+        lockout: LockoutCfg,
+        /// Persistent session signing key.
+        ///
+        /// Default is a random key generated at startup.
+        secret_key: Option<KeySource>,
+        /// Previous signing keys still accepted for existing sessions.
+        #[serde(default)]
+        previous_keys: Vec<KeySource>,
+        /// Session state storage backend.
+        ///
+        /// Default is cookie
+        #[serde(default)]
+        store: StoreCfg,
+
+        // global initialization for cookie-key via config.
+        // avoids recreating the key for every worker actix-web creates.
+        #[serde(default, skip)]
+        key: CookieKey,
+
+        // global initialization for configured signing keys.
+        // avoids re-reading key sources for every worker actix-web creates.
+        #[serde(default, skip)]
+        loaded_keys: LoadedKeys,
+
+        // global initialization for htpasswd watcher.
+        // avoids spawning a watcher for every worker actix-web creates.
+        #[serde(default, skip)]
+        watcher: Watcher,
+
+        // global initialization for redis connection.
+        // avoids reconnecting for every worker actix-web creates.
+        #[cfg(feature = "redis")]
+        #[serde(default, skip)]
+        redis: RedisStore,
+    }
+
+    impl Config {
+        /// Load the configured session signing keys.
+        pub fn prepare(&self, _spec: &Spec) -> anyhow::Result<()> {
+            if self.loaded_keys.0.get().is_some() {
+                return Ok(());
+            }
+            let key = match self.secret_key.as_ref() {
+                Some(source) => source.load()?,
+                None => self.key.0.clone(),
+            };
+            let previous = self
+                .previous_keys
+                .iter()
+                .map(KeySource::load)
+                .collect::<anyhow::Result<Vec<_>>>()?;
+            let _ = self.loaded_keys.0.set((key, previous));
+            Ok(())
+        }
+
+        /// Connect to the configured session store.
+        #[cfg(feature = "redis")]
+        pub async fn connect(&self) -> anyhow::Result<()> {
+            use actix_session::storage::RedisSessionStore;
+            use anyhow::Context;
+
+            if let StoreCfg::Redis { url } = &self.store {
+                let store = RedisSessionStore::new(url.0.as_str())
+                    .await
+                    .with_context(|| format!("failed to connect to redis {url:?}"))?;
+                let _ = self.redis.0.set(store);
+            }
+            Ok(())
+        }
+
+        /// Produce [`actix_authn::Authn`] from config.
+        pub fn factory(&self, _spec: &Spec) -> Authn<BasicAuthSession> {
+            let mut auth =
+                Basic::default().cache_size(self.cache_size.unwrap_or(u16::MAX as usize));
+            auth = self
+                .htpasswd
+                .iter()
+                .fold(auth, |auth, path| auth.htpasswd(path));
+            Authn::new(auth.build_session())
+        }
+
+        /// Wrap Chain/Link with configured middleware.
+        pub fn wrap<W: Wrappable>(&self, w: W, spec: &Spec) -> W {
+            use actix_session::SessionMiddleware;
+            use actix_session::config::SessionLifecycle;
+            use actix_session::storage::CookieSessionStore;
+            use actix_web::cookie::time::Duration;
+
+            let cookie_name = self
+                .cookie_name
+                .clone()
+                .unwrap_or_else(|| "authn".to_owned());
+            let lifecycle = SessionLifecycle::BrowserSession(
+                BrowserSession::default().state_ttl(Duration::HOUR * 24),
+            );
+
+            let (key, previous) = self
+                .loaded_keys
+                .0
+                .get()
+                .cloned()
+                .expect("session keys loaded during config load");
+
+            macro_rules! session {
+                ($store:expr) => {
+                    SessionMiddleware::builder($store, key.clone())
+                        .cookie_name(cookie_name.clone())
+                        .session_lifecycle(lifecycle.clone())
+                        .build()
+                };
+            }
+
+            let w = self.wrap_auth(w, spec);
+            let w = match self.lockout.factory() {
+                Some(lockout) => w.wrap_with(lockout),
+                None => w,
+            };
+            let w = match &self.store {
+                StoreCfg::Cookie => w.wrap_with(session!(CookieSessionStore::default())),
+                #[cfg(feature = "redis")]
+                StoreCfg::Redis { .. } => {
+                    let store = self
+                        .redis
+                        .0
+                        .get()
+                        .expect("redis session store not connected");
+                    w.wrap_with(session!(store.clone()))
+                }
+            };
+            match previous.is_empty() {
+                true => w,
+                false => w.wrap_with(KeyRotation::new(&cookie_name, key, previous)),
+            }
+        }
+
+        /// Wrap Chain/Link with authentication middleware.
+        fn wrap_auth<W: Wrappable>(&self, w: W, spec: &Spec) -> W {
+            let skip = self.except.predicate(spec.config.preflight_bypass);
+            if !self.watch {
+                return match skip {
+                    None => w.wrap_with(self.factory(spec)),
+                    Some(skip) => w.wrap_with(Bypass::new(self.factory(spec), skip)),
+                };
+            }
+            let interval = default_duration(&self.watch_interval, 5);
+            let generation = self.watcher.watch(&self.htpasswd, interval);
+            let config = self.clone();
+            let root = spec.config.clone();
+            let auth = Reload::new(move || config.factory(&Spec::new(&root)), generation);
+            match skip {
+                None => w.wrap_with(auth),
+                Some(skip) => w.wrap_with(Bypass::new(auth, skip)),
+            }
+        }
+    }
+}
+
+/// IpWare Client-IP Translation Middleware.
+#[cfg(feature = "ipware")]
+mod ipware {
+    use std::str::FromStr;
+
+    use super::*;
+    use actix_ipware::{IpWare, Middleware};
+    use actix_web::http::header::HeaderName;
+
+    /// IpWare middleware configuration.
+    #[cfg_attr(feature = "schema", derive(JsonSchema))]
+    #[derive(Debug, Clone, Default, Deserialize, Serialize)]
+    #[serde(default, deny_unknown_fields)]
+    pub struct Config {
+        /// Allow fake/broken ips in trusted headers if false.
+        ///
+        /// Default is true
+        strict: Option<bool>,
+        /// Trusted headers to parse client IP address from.
+        trusted_headers: Vec<String>,
+        /// Number of expected proxy jumps to be trusted.
+        proxy_count: Option<u16>,
+        /// List of trusted upstream proxy globs.
+        trusted_proxies: Vec<String>,
+        /// Allow untrusted client IP assignments.
+        ///
+        /// Default is false
+        allow_untrusted: bool,
+    }
+
+    impl Config {
+        /// Produce [`actix_ipware::Middleware`] from config.
+        pub fn factory(&self, _spec: &Spec) -> Middleware {
+            let mut ipware = IpWare::empty();
+            self.trusted_headers
+                .iter()
+                .filter_map(|header| HeaderName::from_str(header).ok())
+                .fold(&mut ipware, |ipw, header| ipw.trust_header(header));
+            self.trusted_proxies
+                .iter()
+                .fold(&mut ipware, |ipw, proxy| ipw.trust_proxy(proxy));
+            ipware.proxy_count(self.proxy_count);
+            Middleware::new(ipware)
+                .strict(self.strict.unwrap_or(true))
+                .allow_untrusted(self.allow_untrusted)
+        }
+
+        /// Check if any upstream proxy is trusted.
+        #[inline]
+        pub fn trusts_proxies(&self) -> bool {
+            !self.trusted_proxies.is_empty() || self.proxy_count.is_some_and(|n| n > 0)
+        }
+
+        /// Wrap Chain/Link with configured middleware.
+        pub fn wrap<W: Wrappable>(&self, w: W, spec: &Spec) -> W {
+            w.wrap_with(self.factory(spec))
+        }
+    }
+}
+
+/// IpFilter IP Whitelist/Blacklist Middleware.
+///
+/// It's highly recomended to use this middleware
+/// in conjunction with [`ipware`].
+#[cfg(feature = "ipfilter")]
+mod ipfilter {
+    use super::*;
+    use crate::services::ipfilter::{IpFilter, Settings};
+
+    /// IP Filter middleware configuration.
+    #[cfg_attr(feature = "schema", derive(JsonSchema))]
+    #[derive(Debug, Clone, Default, Deserialize, Serialize)]
+    #[serde(default, deny_unknown_fields)]
+    pub struct Config {
+        /// Always allowed client addresses, CIDR ranges or address ranges.
+        #[serde(alias = "allow")]
+        whitelist: Vec<IpMatch>,
+        /// Always denied client addresses, CIDR ranges or address ranges.
+        #[serde(alias = "block", alias = "deny")]
+        blacklist: Vec<IpMatch>,
+        /// Path globs to specifically include for protection.
+        #[serde(alias = "include", alias = "limit")]
+        protect: Vec<PathMatch>,
+        /// Paths globs to specifically exclude from protection.
+        exclude: Vec<PathMatch>,
+    }
+
+    impl Config {
+        /// Produce [`crate::services::ipfilter::IpFilter`] from config.
+        pub fn factory(&self, _spec: &Spec) -> IpFilter {
+            IpFilter::new(Settings {
+                allow: self.whitelist.clone(),
+                block: self.blacklist.clone(),
+                protect: self.protect.clone(),
+                exclude: self.exclude.clone(),
+            })
+        }
+
+        /// Wrap Chain/Link with configured middleware.
+        pub fn wrap<W: Wrappable>(&self, w: W, spec: &Spec) -> W {
+            w.wrap_with(self.factory(spec))
+        }
+    }
+}
+
+/// Dynamic IP Banning Middleware
+#[cfg(feature = "ipban")]
+mod ipban {
+    use actix_web::http::StatusCode;
+
+    use super::*;
+    use crate::services::ban::{IpBan, Settings, State};
+
+    /// Dynamic IP banning middleware configuration.
+    #[cfg_attr(feature = "schema", derive(JsonSchema))]
+    #[derive(Debug, Clone, Default, Deserialize, Serialize)]
+    #[serde(default, deny_unknown_fields)]
+    pub struct Config {
+        /// Response status codes counted as offenses.
+        ///
+        /// Default is [401, 403, 404]
+        statuses: Option<Vec<u16>>,
+        /// Offenses allowed per client IP within the window before banning.
+        ///
+        /// Default is 20
+        max_offenses: Option<u32>,
+        /// Window in which offenses are counted.
+        ///
+        /// Default is 1m
+        window: Option<Duration>,
+        /// Duration of the ban once max offenses is exceeded.
+        ///
+        /// Default is 10m
+        #[serde(alias = "ban_time")]
+        ban_duration: Option<Duration>,
+        /// Client addresses exempt from banning.
+        #[serde(alias = "ignore")]
+        exempt: Vec<IpMatch>,
+
+        // global initialization for offense tracking.
+        // avoids recreating the state for every worker actix-web creates.
+        #[serde(skip)]
+        state: State,
+    }
+
+    impl Config {
+        /// Status codes counted as offenses.
+        fn statuses(&self) -> anyhow::Result<Vec<StatusCode>> {
+            let statuses = self.statuses.as_deref().unwrap_or(&[401, 403, 404]);
+            statuses
+                .iter()
+                .map(|code| {
+                    StatusCode::from_u16(*code)
+                        .map_err(|_| anyhow::anyhow!("invalid ban status code {code}"))
+                })
+                .collect()
+        }
+
+        /// Validate configured status codes.
+        pub fn prepare(&self, _spec: &Spec) -> anyhow::Result<()> {
+            if self.max_offenses == Some(0) {
+                anyhow::bail!("ban max_offenses must be greater than zero");
+            }
+            self.statuses().map(|_| ())
+        }
+
+        /// Produce [`crate::services::ban::IpBan`] from config.
+        pub fn factory(&self, _spec: &Spec) -> IpBan {
+            let settings = Settings {
+                statuses: self
+                    .statuses()
+                    .expect("ban statuses validated during config load"),
+                max_offenses: self.max_offenses.unwrap_or(20),
+                window: default_duration(&self.window, 60),
+                ban_duration: default_duration(&self.ban_duration, 10 * 60),
+                exempt: self.exempt.clone(),
+            };
+            IpBan::new(settings, self.state.clone())
+        }
+
+        /// Wrap Chain/Link with configured middleware.
+        pub fn wrap<W: Wrappable>(&self, w: W, spec: &Spec) -> W {
+            w.wrap_with(self.factory(spec))
+        }
+    }
+}
+
+/// CAPTCHA Challenge Middleware
+#[cfg(feature = "captcha")]
+mod captcha {
+    use std::sync::Arc;
+
+    use super::*;
+    use crate::{
+        services::captcha::{Captcha, Provider, Settings, cookie_key},
+        tls::client::build_tls_config,
+    };
+
+    /// Supported CAPTCHA providers.
+    #[cfg_attr(feature = "schema", derive(JsonSchema))]
+    #[derive(Debug, Clone, Default, Deserialize, Serialize)]
+    #[serde(rename_all = "lowercase")]
+    pub enum ProviderCfg {
+        /// Cloudflare Turnstile.
+        #[default]
+        Turnstile,
+        /// hCaptcha.
+        HCaptcha,
+    }
+
+    /// CAPTCHA challenge middleware configuration.
+    #[cfg_attr(feature = "schema", derive(JsonSchema))]
+    #[derive(Debug, Clone, Deserialize, Serialize)]
+    #[serde(deny_unknown_fields)]
+    pub struct Config {
+        /// Challenge provider.
+        ///
+        /// Default is turnstile
+        #[serde(default)]
+        provider: ProviderCfg,
+        /// Public site key issued by the provider.
+        site_key: String,
+        /// Secret key issued by the provider.
+        ///
+        /// Remembered challenge cookies are signed with a key derived from it.
+        secret: crate::config::Secret,
+        /// Cookie remembering successful challenges.
+        ///
+        /// Default is `bob_challenge`
+        #[serde(default)]
+        cookie: Option<String>,
+        /// Duration successful challenges are remembered.
+        ///
+        /// Default is 1h
+        #[serde(default)]
+        duration: Option<Duration>,
+        /// Require remembered challenges to come from the same client address.
+        ///
+        /// Default is true
+        #[serde(default)]
+        bind_ip: Option<bool>,
+        /// Path challenge responses are submitted to.
+        ///
+        /// Default is `/.bob/challenge`
+        #[serde(default)]
+        verify_path: Option<String>,
+        /// Path globs never challenged.
+        #[serde(default)]
+        exclude: Vec<PathMatch>,
+        /// Timeout verifying tokens with the provider.
+        ///
+        /// Default is 10s
+        #[serde(default)]
+        timeout: Option<Duration>,
+    }
+
+    impl Config {
+        /// Validate challenge settings.
+        pub fn prepare(&self, _spec: &Spec) -> anyhow::Result<()> {
+            if self.site_key.is_empty() || self.secret.is_empty() {
+                anyhow::bail!("captcha requires a site_key and secret");
+            }
+            if !self.verify_path().starts_with('/') {
+                anyhow::bail!("captcha verify_path must start with '/'");
+            }
+            Ok(())
+        }
+
+        #[inline]
+        fn verify_path(&self) -> &str {
+            self.verify_path.as_deref().unwrap_or("/.bob/challenge")
+        }
+
+        /// Produce [`crate::services::captcha::Captcha`] from config.
+        pub fn factory(&self, _spec: &Spec) -> Captcha {
+            let connector = awc::Connector::new().rustls_0_23(Arc::new(build_tls_config(true)));
+            let client = awc::ClientBuilder::new()
+                .connector(connector)
+                .timeout(default_duration(&self.timeout, 10))
+                .finish();
+            let settings = Settings {
+                provider: match self.provider {
+                    ProviderCfg::Turnstile => Provider::Turnstile,
+                    ProviderCfg::HCaptcha => Provider::HCaptcha,
+                },
+                site_key: self.site_key.clone(),
+                secret: self.secret.0.clone(),
+                cookie_key: cookie_key(&self.secret),
+                cookie: self
+                    .cookie
+                    .clone()
+                    .unwrap_or_else(|| "bob_challenge".to_owned()),
+                duration: default_duration(&self.duration, 60 * 60),
+                bind_ip: self.bind_ip.unwrap_or(true),
+                verify_path: self.verify_path().to_owned(),
+                exclude: self.exclude.clone(),
+            };
+            Captcha::new(settings, client)
+        }
+
+        /// Wrap Chain/Link with configured middleware.
+        pub fn wrap<W: Wrappable>(&self, w: W, spec: &Spec) -> W {
+            w.wrap_with(self.factory(spec))
+        }
+    }
+}
+
+/// User-Agent and Bot Signature Filter Middleware
+#[cfg(feature = "useragent")]
+mod useragent {
+    use actix_web::http::StatusCode;
+
+    use super::*;
+    use crate::services::{
+        crawlers::AI_CRAWLERS,
+        useragent::{Action, Rule, Settings, UserAgentFilter},
+    };
+
+    /// Maintained signature lists.
+    #[cfg_attr(feature = "schema", derive(JsonSchema))]
+    #[derive(Debug, Clone, Deserialize, Serialize)]
+    #[serde(rename_all = "snake_case")]
+    pub enum Preset {
+        /// AI crawlers and training data collectors.
+        AiCrawlers,
+    }
+
+    impl Preset {
+        #[inline]
+        fn signatures(&self) -> &'static [&'static str] {
+            match self {
+                Self::AiCrawlers => AI_CRAWLERS,
+            }
+        }
+    }
+
+    /// Action taken for matching clients.
+    #[cfg_attr(feature = "schema", derive(JsonSchema))]
+    #[derive(Debug, Clone, Default, Deserialize, Serialize)]
+    #[serde(rename_all = "snake_case")]
+    pub enum ActionCfg {
+        /// Reject the request immediately.
+        #[default]
+        Block,
+        /// Delay the client before rejecting the request.
+        Tarpit,
+        /// Serve a static response body.
+        Respond,
+    }
+
+    /// User-agent signature rule.
+    #[cfg_attr(feature = "schema", derive(JsonSchema))]
+    #[derive(Debug, Clone, Default, Deserialize, Serialize)]
+    #[serde(default, deny_unknown_fields)]
+    pub struct RuleCfg {
+        /// Case-insensitive substrings matched against the user-agent.
+        #[serde(alias = "match")]
+        patterns: Vec<String>,
+        /// Maintained signature lists included in the rule.
+        presets: Vec<Preset>,
+        /// Match requests without a user-agent.
+        ///
+        /// Default is false
+        empty: bool,
+        /// Action taken for matching clients.
+        ///
+        /// Default is block
+        action: ActionCfg,
+        /// Response status code.
+        ///
+        /// Default is 403 (200 for respond)
+        status: Option<u16>,
+        /// Delay applied before responding to tarpitted clients.
+        ///
+        /// Default is 10s
+        delay: Option<Duration>,
+        /// Response body served by respond.
+        body: Option<String>,
+        /// Response content-type served by respond.
+        ///
+        /// Default is text/plain
+        content_type: Option<String>,
+    }
+
+    impl RuleCfg {
+        /// Build [`crate::services::useragent::Rule`] from config.
+        fn build(&self) -> anyhow::Result<Rule> {
+            if self.patterns.is_empty() && self.presets.is_empty() && !self.empty {
+                anyhow::bail!("user-agent rule requires patterns, presets or empty");
+            }
+            let default = match self.action {
+                ActionCfg::Respond => 200,
+                _ => 403,
+            };
+            let code = self.status.unwrap_or(default);
+            let status = StatusCode::from_u16(code)
+                .map_err(|_| anyhow::anyhow!("invalid user-agent status code {code}"))?;
+            let action = match self.action {
+                ActionCfg::Block => Action::Block,
+                ActionCfg::Tarpit => Action::Tarpit(default_duration(&self.delay, 10)),
+                ActionCfg::Respond => Action::Respond {
+                    body: self.body.clone().unwrap_or_default(),
+                    content_type: self
+                        .content_type
+                        .clone()
+                        .unwrap_or_else(|| "text/plain; charset=utf-8".to_owned()),
+                },
+            };
+            let signatures = self
+                .patterns
+                .iter()
+                .map(String::as_str)
+                .chain(
+                    self.presets
+                        .iter()
+                        .flat_map(|p| p.signatures().iter().copied()),
+                )
+                .map(|s| s.to_ascii_lowercase())
+                .collect();
+            Ok(Rule {
+                signatures,
+                empty: self.empty,
+                action,
+                status,
+            })
+        }
+    }
+
+    /// User-agent filter middleware configuration.
+    #[cfg_attr(feature = "schema", derive(JsonSchema))]
+    #[derive(Debug, Clone, Default, Deserialize, Serialize)]
+    #[serde(default, deny_unknown_fields)]
+    pub struct Config {
+        /// Case-insensitive substrings exempting clients from every rule.
+        allow: Vec<String>,
+        /// Ordered rules, the first matching rule applies.
+        rules: Vec<RuleCfg>,
+    }
+
+    impl Config {
+        /// Build all configured rules.
+        fn rules(&self) -> anyhow::Result<Vec<Rule>> {
+            self.rules.iter().map(RuleCfg::build).collect()
+        }
+
+        /// Validate configured rules.
+        pub fn prepare(&self, _spec: &Spec) -> anyhow::Result<()> {
+            self.rules().map(|_| ())
+        }
+
+        /// Produce [`crate::services::useragent::UserAgentFilter`] from config.
+        pub fn factory(&self, _spec: &Spec) -> UserAgentFilter {
+            UserAgentFilter::new(Settings {
+                allow: self.allow.iter().map(|s| s.to_ascii_lowercase()).collect(),
+                rules: self
+                    .rules()
+                    .expect("user-agent rules validated during config load"),
+            })
+        }
+
+        /// Wrap Chain/Link with configured middleware.
+        pub fn wrap<W: Wrappable>(&self, w: W, spec: &Spec) -> W {
+            w.wrap_with(self.factory(spec))
+        }
+    }
+}
+
+/// Honeypot Trap Path Middleware
+#[cfg(feature = "honeypot")]
+mod honeypot {
+    use actix_web::http::StatusCode;
+
+    use super::*;
+    use crate::services::honeypot::{Honeypot, Settings};
+
+    /// Honeypot trap path middleware configuration.
+    #[cfg_attr(feature = "schema", derive(JsonSchema))]
+    #[derive(Debug, Clone, Deserialize, Serialize)]
+    #[serde(deny_unknown_fields)]
+    pub struct Config {
+        /// Decoy path globs no legitimate client requests.
+        ///
+        /// Example: [/wp-login.php, /.env, /.git/*]
+        paths: Vec<PathMatch>,
+        /// Response status code.
+        ///
+        /// Default is 404
+        #[serde(default)]
+        status: Option<u16>,
+        /// Response body.
+        #[serde(default)]
+        body: Option<String>,
+        /// Response content-type.
+        ///
+        /// Default is text/plain
+        #[serde(default)]
+        content_type: Option<String>,
+        /// Ban trapped clients immediately for this duration.
+        ///
+        /// Otherwise hits are counted as offenses by the `ipban`
+        /// middleware wrapping this one.
+        #[serde(default)]
+        ban: Option<Duration>,
+    }
+
+    impl Config {
+        /// Configured response status code.
+        fn status(&self) -> anyhow::Result<StatusCode> {
+            let code = self.status.unwrap_or(404);
+            StatusCode::from_u16(code)
+                .map_err(|_| anyhow::anyhow!("invalid honeypot status code {code}"))
+        }
+
+        /// Validate trap settings.
+        pub fn prepare(&self, _spec: &Spec) -> anyhow::Result<()> {
+            if self.paths.is_empty() {
+                anyhow::bail!("honeypot requires at least one trap path");
+            }
+            #[cfg(not(feature = "ipban"))]
+            if self.ban.is_some() {
+                anyhow::bail!("honeypot ban requires the ipban feature");
+            }
+            self.status().map(|_| ())
+        }
+
+        /// Produce [`crate::services::honeypot::Honeypot`] from config.
+        pub fn factory(&self, _spec: &Spec) -> Honeypot {
+            Honeypot::new(Settings {
+                paths: self.paths.clone(),
+                status: self
+                    .status()
+                    .expect("honeypot status validated during config load"),
+                body: self.body.clone().unwrap_or_default(),
+                content_type: self
+                    .content_type
+                    .clone()
+                    .unwrap_or_else(|| "text/plain; charset=utf-8".to_owned()),
+                #[cfg(feature = "ipban")]
+                ban: self.ban.as_ref().map(|d| d.0),
+            })
+        }
+
+        /// Wrap Chain/Link with configured middleware.
+        pub fn wrap<W: Wrappable>(&self, w: W, spec: &Spec) -> W {
+            w.wrap_with(self.factory(spec))
+        }
+    }
+}
+
+/// External Command Hook Middleware
+#[cfg(feature = "exec")]
+mod exec {
+    use std::{collections::BTreeMap, path::PathBuf};
+
+    use super::*;
+    use crate::services::{
+        cgi::{Active, DEFAULT_MAX_OUTPUT, Program, program_env},
+        exec::{Exec, Settings},
+    };
+
+    /// External command hook middleware configuration.
+    #[cfg_attr(feature = "schema", derive(JsonSchema))]
+    #[derive(Debug, Clone, Deserialize, Serialize)]
+    #[serde(deny_unknown_fields)]
+    pub struct Config {
+        /// Program run for every request.
+        ///
+        /// Exit status zero allows the request, anything else denies it.
+        #[serde(alias = "program")]
+        command: PathBuf,
+        /// Arguments passed to the program.
+        #[serde(default)]
+        args: Vec<String>,
+        /// Working directory the program is run within.
+        #[serde(default)]
+        workdir: Option<PathBuf>,
+        /// Additional environment variables passed to the program.
+        ///
+        /// `PATH` is inherited from bob unless configured here.
+        #[serde(default)]
+        env: BTreeMap<String, String>,
+        /// Maximum time allowed for the program to complete.
+        ///
+        /// Default is 5s
+        #[serde(default)]
+        timeout: Option<Duration>,
+        /// Maximum number of programs running at once across all workers.
+        ///
+        /// Default is 16
+        #[serde(default)]
+        max_concurrent: Option<usize>,
+        /// Pass requests on when the program cannot be run.
+        ///
+        /// Default is false
+        #[serde(default)]
+        fail_open: bool,
+
+        // global running program count.
+        // shares the concurrency limit between every worker actix-web creates.
+        #[serde(skip)]
+        active: Active,
+    }
+
+    impl Config {
+        /// Validate program and limits.
+        pub fn prepare(&self, _spec: &Spec) -> anyhow::Result<()> {
+            if !self.command.is_file() {
+                anyhow::bail!("exec command {:?} does not exist", self.command);
+            }
+            if let Some(workdir) = self.workdir.as_ref()
+                && !workdir.is_dir()
+            {
+                anyhow::bail!("exec workdir {workdir:?} is not a directory");
+            }
+            if self.max_concurrent == Some(0) {
+                anyhow::bail!("exec max_concurrent must be greater than zero");
+            }
+            Ok(())
+        }
+
+        /// Produce [`crate::services::exec::Exec`] from config.
+        pub fn factory(&self, _spec: &Spec) -> Exec {
+            let env = program_env(&self.env);
+            let settings = Settings {
+                program: Program {
+                    command: self.command.clone(),
+                    args: self.args.clone(),
+                    workdir: self.workdir.clone(),
+                    timeout: default_duration(&self.timeout, 5),
+                    max_output: DEFAULT_MAX_OUTPUT,
+                },
+                env,
+                max_concurrent: self.max_concurrent.unwrap_or(16),
+                fail_open: self.fail_open,
+            };
+            Exec::new(settings, self.active.clone())
+        }
+
+        /// Wrap Chain/Link with configured middleware.
+        pub fn wrap<W: Wrappable>(&self, w: W, spec: &Spec) -> W {
+            w.wrap_with(self.factory(spec))
+        }
+    }
+}
+
+/// Request Tarpit Middleware
+#[cfg(feature = "tarpit")]
+mod tarpit {
+    use actix_web::http::StatusCode;
+
+    use super::*;
+    use crate::services::tarpit::{Held, Settings, Tarpit};
+
+    /// Request tarpit middleware configuration.
+    #[cfg_attr(feature = "schema", derive(JsonSchema))]
+    #[derive(Debug, Clone, Default, Deserialize, Serialize)]
+    #[serde(default, deny_unknown_fields)]
+    pub struct Config {
+        /// Client addresses, CIDR ranges or address ranges to delay.
+        ips: Vec<IpMatch>,
+        /// Case-insensitive substrings matched against the user-agent.
+        user_agents: Vec<String>,
+        /// Path globs to delay.
+        paths: Vec<PathMatch>,
+        /// Delay clients currently banned by the `ipban` middleware.
+        ///
+        /// Default is false
+        banned: bool,
+        /// Time requests are held before being answered.
+        ///
+        /// Default is 10s
+        delay: Option<Duration>,
+        /// Response status code.
+        ///
+        /// Default is 403
+        status: Option<u16>,
+        /// Pass delayed requests on rather than answering them.
+        ///
+        /// Default is false
+        forward: bool,
+        /// Maximum number of requests held at once across all workers.
+        ///
+        /// Requests beyond the limit are answered immediately.
+        /// Default is 1024
+        max_held: Option<usize>,
+
+        // global held request count.
+        // shares the limit between every worker actix-web creates.
+        #[serde(skip)]
+        held: Held,
+    }
+
+    impl Config {
+        /// Configured response status code.
+        fn status(&self) -> anyhow::Result<StatusCode> {
+            let code = self.status.unwrap_or(403);
+            StatusCode::from_u16(code)
+                .map_err(|_| anyhow::anyhow!("invalid tarpit status code {code}"))
+        }
+
+        /// Validate tarpit settings.
+        pub fn prepare(&self, _spec: &Spec) -> anyhow::Result<()> {
+            let empty = self.ips.is_empty() && self.user_agents.is_empty() && self.paths.is_empty();
+            if empty && !self.banned {
+                anyhow::bail!("tarpit requires ips, user_agents, paths or banned");
+            }
+            #[cfg(not(feature = "ipban"))]
+            if self.banned {
+                anyhow::bail!("tarpit banned requires the ipban feature");
+            }
+            self.status().map(|_| ())
+        }
+
+        /// Produce [`crate::services::tarpit::Tarpit`] from config.
+        pub fn factory(&self, _spec: &Spec) -> Tarpit {
+            let settings = Settings {
+                ips: self.ips.clone(),
+                user_agents: self
+                    .user_agents
+                    .iter()
+                    .map(|agent| agent.to_ascii_lowercase())
+                    .collect(),
+                paths: self.paths.clone(),
+                #[cfg(feature = "ipban")]
+                banned: self.banned,
+                delay: default_duration(&self.delay, 10),
+                status: self
+                    .status()
+                    .expect("tarpit status validated during config load"),
+                forward: self.forward,
+                max_held: self.max_held.unwrap_or(1024),
+            };
+            Tarpit::new(settings, self.held.clone())
+        }
+
+        /// Wrap Chain/Link with configured middleware.
+        pub fn wrap<W: Wrappable>(&self, w: W, spec: &Spec) -> W {
+            w.wrap_with(self.factory(spec))
+        }
+    }
+}
+
+/// Request-ID Middleware
+#[cfg(feature = "requestid")]
+mod request_id {
+    use actix_web::http::header::HeaderName;
+
+    use super::*;
+    use crate::services::request_id::{RequestId, Settings};
+
+    /// Request-ID middleware configuration.
+    #[cfg_attr(feature = "schema", derive(JsonSchema))]
+    #[derive(Debug, Clone, Default, Deserialize, Serialize)]
+    #[serde(default, deny_unknown_fields)]
+    pub struct Config {
+        /// Header carrying the request identifier.
+        ///
+        /// Default is X-Request-Id
+        header: Option<String>,
+        /// Honor valid identifiers sent by clients.
+        ///
+        /// Default is true
+        honor_incoming: Option<bool>,
+        /// Return the identifier in the response.
+        ///
+        /// Default is true
+        response: Option<bool>,
+    }
+
+    impl Config {
+        /// Configured request-id header.
+        fn header(&self) -> anyhow::Result<HeaderName> {
+            let header = self.header.as_deref().unwrap_or("x-request-id");
+            HeaderName::try_from(header)
+                .map_err(|_| anyhow::anyhow!("invalid request-id header {header:?}"))
+        }
+
+        /// Validate request-id header.
+        pub fn prepare(&self, _spec: &Spec) -> anyhow::Result<()> {
+            self.header().map(|_| ())
+        }
+
+        /// Produce [`crate::services::request_id::RequestId`] from config.
+        pub fn factory(&self, _spec: &Spec) -> RequestId {
+            RequestId::new(Settings {
+                header: self
+                    .header()
+                    .expect("request-id header validated during config load"),
+                honor_incoming: self.honor_incoming.unwrap_or(true),
+                response: self.response.unwrap_or(true),
+            })
+        }
+
+        /// Wrap Chain/Link with configured middleware.
+        pub fn wrap<W: Wrappable>(&self, w: W, spec: &Spec) -> W {
+            w.wrap_with(self.factory(spec))
+        }
+    }
+}
+
+/// GeoIP Filtering Middleware
+#[cfg(feature = "geoip")]
+mod geoip {
+    use std::{
+        fmt::Debug,
+        path::{Path, PathBuf},
+        sync::{Arc, OnceLock},
+    };
+
+    use anyhow::Context;
+    use maxminddb::Reader;
+
+    use super::*;
+    use crate::services::geoip::{Database, GeoIp, Settings};
+
+    /// Databases loaded once and shared between every worker.
+    #[derive(Clone, Default)]
+    struct Loaded(Arc<OnceLock<(Option<Database>, Option<Database>)>>);
+
+    impl Debug for Loaded {
+        fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+            write!(f, "Loaded {{}}")
+        }
+    }
+
+    /// GeoIP middleware configuration.
+    #[cfg_attr(feature = "schema", derive(JsonSchema))]
+    #[derive(Debug, Clone, Default, Deserialize, Serialize)]
+    #[serde(default, deny_unknown_fields)]
+    pub struct Config {
+        /// MaxMind/DB-IP database containing country records.
+        #[serde(alias = "country_database")]
+        database: Option<PathBuf>,
+        /// MaxMind/DB-IP database containing autonomous system records.
+        asn_database: Option<PathBuf>,
+        /// ISO country codes permitted when non-empty.
+        #[serde(alias = "allow")]
+        allow_countries: Vec<String>,
+        /// ISO country codes always denied.
+        #[serde(alias = "deny", alias = "block")]
+        deny_countries: Vec<String>,
+        /// Autonomous system numbers permitted when non-empty.
+        allow_asn: Vec<u32>,
+        /// Autonomous system numbers always denied.
+        #[serde(alias = "block_asn")]
+        deny_asn: Vec<u32>,
+        /// Deny clients whose location cannot be determined.
+        deny_unknown: bool,
+        /// Inject `X-Geo-Country` and `X-Geo-ASN` headers for upstreams.
+        ///
+        /// Default is true
+        headers: Option<bool>,
+
+        // global initialization for loaded databases.
+        // avoids re-reading database files for every worker actix-web creates.
+        #[serde(skip)]
+        loaded: Loaded,
+    }
+
+    /// Read database file into memory.
+    fn open(path: &Path) -> anyhow::Result<Database> {
+        let reader = Reader::open_readfile(path)
+            .with_context(|| format!("failed to read geoip database {path:?}"))?;
+        Ok(Arc::new(reader))
+    }
+
+    impl Config {
+        /// Load configured databases into the shared database set.
+        fn load(&self) -> anyhow::Result<&(Option<Database>, Option<Database>)> {
+            if let Some(loaded) = self.loaded.0.get() {
+                return Ok(loaded);
+            }
+            if self.database.is_none() && self.asn_database.is_none() {
+                anyhow::bail!("geoip requires a database or asn_database");
+            }
+            let country = self.database.as_deref().map(open).transpose()?;
+            let asn = self.asn_database.as_deref().map(open).transpose()?;
+            Ok(self.loaded.0.get_or_init(|| (country, asn)))
+        }
+
+        /// Validate and load configured databases.
+        pub fn prepare(&self, _spec: &Spec) -> anyhow::Result<()> {
+            self.load().map(|_| ())
+        }
+
+        /// Produce [`crate::services::geoip::GeoIp`] from config.
+        pub fn factory(&self, _spec: &Spec) -> GeoIp {
+            let (country_db, asn_db) = self
+                .load()
+                .expect("geoip databases validated during config load")
+                .clone();
+            let upper = |codes: &[String]| codes.iter().map(|c| c.to_ascii_uppercase()).collect();
+            GeoIp::new(Settings {
+                country_db,
+                asn_db,
+                allow_countries: upper(&self.allow_countries),
+                deny_countries: upper(&self.deny_countries),
+                allow_asn: self.allow_asn.clone(),
+                deny_asn: self.deny_asn.clone(),
+                deny_unknown: self.deny_unknown,
+                headers: self.headers.unwrap_or(true),
+            })
+        }
+
+        /// Wrap Chain/Link with configured middleware.
+        pub fn wrap<W: Wrappable>(&self, w: W, spec: &Spec) -> W {
+            w.wrap_with(self.factory(spec))
+        }
+    }
+}
+
+/// OWASP ModSecurity Middleware
+#[cfg(feature = "modsecurity")]
+mod modsecurity {
+    use std::{
+        path::PathBuf,
+        sync::{Arc, OnceLock},
+    };
+
+    use super::*;
+    use crate::services::{
+        crs::{self, Crs},
+        modsecurity::{
+            DEFAULT_MAX_REQUEST_BODY, DEFAULT_MAX_RESPONSE_BODY, LimitAction, Modsecurity,
+            Settings, SharedRules, load_rules,
+        },
+        spool,
+    };
+
+    /// Rules parsed once and shared between every worker.
+    #[derive(Clone, Default)]
+    struct Parsed(Arc<OnceLock<Arc<SharedRules>>>);
+
+    impl std::fmt::Debug for Parsed {
+        fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+            write!(f, "Parsed {{}}")
+        }
+    }
+
+    /// Core Rule Set loaded once and shared between every worker.
+    #[derive(Clone, Default)]
+    struct LoadedCrs(Arc<OnceLock<Arc<Crs>>>);
+
+    impl std::fmt::Debug for LoadedCrs {
+        fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+            write!(f, "LoadedCrs {{}}")
+        }
+    }
+
+    /// Action taken for bodies exceeding the inspection limit.
+    #[cfg_attr(feature = "schema", derive(JsonSchema))]
+    #[derive(Debug, Clone, Copy, Default, Deserialize, Serialize)]
+    #[serde(rename_all = "snake_case")]
+    pub enum LimitActionCfg {
+        /// Inspect the body up to the limit and pass the remainder through.
+        #[default]
+        Skip,
+        /// Reject the request or response.
+        Reject,
+    }
+
+    /// Modsecurity middleware configuration.
+    #[cfg_attr(feature = "schema", derive(JsonSchema))]
+    #[derive(Debug, Clone, Default, Deserialize, Serialize)]
+    #[serde(default, deny_unknown_fields)]
+    pub struct Config {
+        /// Plaintext rules contained within a single string.
+        rules: Option<String>,
+        /// List of additional files to load rules from.
+        rule_files: Vec<PathBuf>,
+        /// Max request body size inspected by the ruleset.
+        ///
+        /// Default is 12.5MiB
+        max_request_body_size: Option<usize>,
+        /// Max response body size inspected by the ruleset.
+        ///
+        /// Default is 512KiB
+        max_response_body_size: Option<usize>,
+        /// Action taken for bodies exceeding the max inspected size.
+        ///
+        /// Default is skip
+        body_limit_action: LimitActionCfg,
+        /// Inspect response bodies.
+        ///
+        /// Default is true
+        inspect_response_body: Option<bool>,
+        /// Body size held in memory before spilling to a temporary file.
+        ///
+        /// Default is 1MiB
+        memory_body_size: Option<usize>,
+        /// Directory bodies are spilled to.
+        ///
+        /// Default is the system temporary directory
+        spill_dir: Option<PathBuf>,
+        /// Download and load the OWASP Core Rule Set.
+        crs: bool,
+        /// Pinned Core Rule Set release version.
+        ///
+        /// Default is 4.7.0
+        crs_version: Option<String>,
+        /// Expected sha-256 checksum (hex) of the release archive.
+        crs_checksum: Option<String>,
+        /// Directory Core Rule Set releases are cached in.
+        ///
+        /// Default is `$XDG_CACHE_HOME/bob/crs`
+        crs_dir: Option<PathBuf>,
+        /// Core Rule Set paranoia level between 1 and 4.
+        ///
+        /// Default is 1
+        paranoia_level: Option<u8>,
+
+        // global initialization for loaded rules.
+        // avoids re-reading rule files for every worker actix-web creates.
+        #[serde(skip)]
+        ruleset: RuleSet,
+
+        // global initialization for the core rule set.
+        // avoids re-reading crs files for every worker actix-web creates.
+        #[serde(skip)]
+        loaded_crs: LoadedCrs,
+
+        // global initialization for the parsed rules.
+        // avoids parsing the full ruleset for every worker actix-web creates.
+        #[serde(skip)]
+        parsed: Parsed,
+    }
+
+    impl Config {
+        /// Validate rules and load them into the shared ruleset.
+        pub fn prepare(&self, _spec: &Spec) -> anyhow::Result<()> {
+            match self.crs {
+                // core rule set is only available once connected
+                true => {
+                    let rules = self.ruleset.load(&self.rules, &self.rule_files)?;
+                    load_rules(None, rules)
+                        .map_err(|err| anyhow::anyhow!("invalid modsecurity rules: {err:?}"))?;
+                }
+                false => {
+                    self.parse()?;
+                }
+            }
+            if let Some(dir) = self.spill_dir.as_ref()
+                && !dir.is_dir()
+            {
+                anyhow::bail!("modsecurity spill_dir {dir:?} is not a directory");
+            }
+            if !self.crs
+                && (self.crs_version.is_some()
+                    || self.crs_checksum.is_some()
+                    || self.crs_dir.is_some()
+                    || self.paranoia_level.is_some())
+            {
+                anyhow::bail!("modsecurity crs settings require crs to be enabled");
+            }
+            if let Some(version) = self.crs_version.as_ref() {
+                crs::validate_version(version)?;
+            }
+            if !(1..=4).contains(&self.paranoia_level()) {
+                anyhow::bail!("modsecurity paranoia_level must be between 1 and 4");
+            }
+            Ok(())
+        }
+
+        #[inline]
+        fn paranoia_level(&self) -> u8 {
+            self.paranoia_level.unwrap_or(1)
+        }
+
+        /// Download and load the configured core rule set.
+        pub async fn connect(&self) -> anyhow::Result<()> {
+            use anyhow::Context;
+            if !self.crs || self.loaded_crs.0.get().is_some() {
+                return Ok(());
+            }
+            let dir = self.crs_dir.clone().unwrap_or_else(crs::default_dir);
+            let version = self
+                .crs_version
+                .clone()
+                .unwrap_or_else(|| crs::DEFAULT_VERSION.to_owned());
+            let checksum = self.crs_checksum.clone();
+            let paranoia_level = self.paranoia_level();
+            let inspect_response = self.inspect_response_body.unwrap_or(true);
+            let plain = self.ruleset.load(&self.rules, &self.rule_files)?.to_owned();
+            let (loaded, rules) = actix_web::rt::task::spawn_blocking(move || {
+                let root = crs::install(&dir, &version, checksum.as_deref())?;
+                let loaded = crs::load(&root, paranoia_level, inspect_response)?;
+                let rules = load_rules(Some(&loaded), &plain)
+                    .map_err(|err| anyhow::anyhow!("invalid crs rules: {err:?}"))?;
+                Ok::<_, anyhow::Error>((loaded, rules))
+            })
+            .await
+            .context("crs bootstrap task failed")??;
+            log::info!(
+                "loaded {} owasp crs rule files at paranoia level {paranoia_level}",
+                loaded.rules.len()
+            );
+            let _ = self.loaded_crs.0.set(Arc::new(loaded));
+            let _ = self.parsed.0.set(Arc::new(rules));
+            Ok(())
+        }
+
+        /// Parse core rule set and plaintext rules into the shared ruleset.
+        fn parse(&self) -> anyhow::Result<Arc<SharedRules>> {
+            if let Some(rules) = self.parsed.0.get() {
+                return Ok(Arc::clone(rules));
+            }
+            let plain = self.ruleset.load(&self.rules, &self.rule_files)?;
+            let crs = match self.crs {
+                true => Some(
+                    self.loaded_crs
+                        .0
+                        .get()
+                        .expect("modsecurity crs loaded during config connect"),
+                ),
+                false => None,
+            };
+            let rules = load_rules(crs.map(|crs| crs.as_ref()), plain)
+                .map_err(|err| anyhow::anyhow!("invalid modsecurity rules: {err:?}"))?;
+            Ok(Arc::clone(self.parsed.0.get_or_init(|| Arc::new(rules))))
+        }
+
+        /// Produce [`crate::services::modsecurity::Modsecurity`] from config.
+        pub fn factory(&self, _spec: &Spec) -> Modsecurity {
+            Modsecurity::new(Settings {
+                rules: self
+                    .parse()
+                    .expect("modsecurity rules validated during config load"),
+                max_request_body_size: self
+                    .max_request_body_size
+                    .unwrap_or(DEFAULT_MAX_REQUEST_BODY),
+                max_response_body_size: self
+                    .max_response_body_size
+                    .unwrap_or(DEFAULT_MAX_RESPONSE_BODY),
+                limit_action: match self.body_limit_action {
+                    LimitActionCfg::Skip => LimitAction::Skip,
+                    LimitActionCfg::Reject => LimitAction::Reject,
+                },
+                inspect_response: self.inspect_response_body.unwrap_or(true),
+                spool: spool::Settings {
+                    memory_limit: self.memory_body_size.unwrap_or(1024 * 1024),
+                    directory: self.spill_dir.clone().unwrap_or_else(std::env::temp_dir),
+                },
+            })
+        }
+
+        /// Wrap Chain/Link with configured middleware.
+        pub fn wrap<W: Wrappable>(&self, w: W, spec: &Spec) -> W {
+            w.wrap_with(self.factory(spec))
+        }
+    }
+}
+
+/// OpenAPI Routing and Validation Middleware
+#[cfg(feature = "openapi")]
+mod openapi {
+    use std::{
+        fmt::Debug,
+        path::PathBuf,
+        sync::{Arc, OnceLock},
+    };
+
+    use super::*;
+    use crate::services::openapi::{Document, OpenApi, Settings};
+
+    /// Document parsed once and shared between every worker.
+    #[derive(Clone, Default)]
+    struct Loaded(Arc<OnceLock<Arc<Document>>>);
+
+    impl Debug for Loaded {
+        fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+            write!(f, "Loaded {{}}")
+        }
+    }
+
+    /// OpenAPI validation middleware configuration.
+    #[cfg_attr(feature = "schema", derive(JsonSchema))]
+    #[derive(Debug, Clone, Deserialize, Serialize)]
+    #[serde(deny_unknown_fields)]
+    pub struct Config {
+        /// OpenAPI 3 document in YAML or JSON format.
+        spec: PathBuf,
+        /// Path prefix stripped before matching document paths.
+        #[serde(default)]
+        base_path: Option<String>,
+        /// Validate request bodies against the declared schemas.
+        ///
+        /// Default is true
+        #[serde(default)]
+        validate_body: Option<bool>,
+        /// Largest request body buffered for validation in bytes.
+        ///
+        /// Default is 1MiB
+        #[serde(default)]
+        max_body_size: Option<usize>,
+        /// Path globs passed through without validation.
+        #[serde(default)]
+        exclude: Vec<PathMatch>,
+
+        // global initialization for the parsed document.
+        // avoids re-reading the spec for every worker actix-web creates.
+        #[serde(skip)]
+        loaded: Loaded,
+    }
+
+    impl Config {
+        /// Load and parse the document into the shared document.
+        fn load(&self) -> anyhow::Result<&Arc<Document>> {
+            use anyhow::Context;
+            if let Some(document) = self.loaded.0.get() {
+                return Ok(document);
+            }
+            let source = std::fs::read_to_string(&self.spec)
+                .with_context(|| format!("failed to read openapi spec {:?}", self.spec))?;
+            let document = Document::parse(&source)
+                .with_context(|| format!("invalid openapi spec {:?}", self.spec))?;
+            Ok(self.loaded.0.get_or_init(|| Arc::new(document)))
+        }
+
+        /// Validate and load the configured document.
+        pub fn prepare(&self, _spec: &Spec) -> anyhow::Result<()> {
+            self.load().map(|_| ())
+        }
+
+        /// Produce [`crate::services::openapi::OpenApi`] from config.
+        pub fn factory(&self, spec: &Spec) -> OpenApi {
+            let document = self
+                .load()
+                .expect("openapi spec validated during config load");
+            let base_path = self.base_path.as_deref().unwrap_or_default();
+            OpenApi::new(Settings {
+                document: Arc::clone(document),
+                base_path: base_path.trim_end_matches('/').to_owned(),
+                validate_body: self.validate_body.unwrap_or(true),
+                max_body_size: self.max_body_size.unwrap_or(1024 * 1024),
+                exclude: self.exclude.clone(),
+                preflight: spec.config.preflight_bypass,
+            })
+        }
+
+        /// Wrap Chain/Link with configured middleware.
+        pub fn wrap<W: Wrappable>(&self, w: W, spec: &Spec) -> W {
+            w.wrap_with(self.factory(spec))
+        }
+    }
+}
+
+/// Apache2 Inspired `mod_rewrite` module
+#[cfg(feature = "rewrite")]
+mod rewrite {
+    use std::path::PathBuf;
+
+    use super::*;
+    use actix_rewrite::{Engine, Middleware, ServerCtx};
+
+    const SERVER_SOFTWARE: &str = concat!(env!("CARGO_PKG_NAME"), " ", env!("CARGO_PKG_VERSION"));
+
+    /// `mod_rewrite` middleware configuration.
+    #[cfg_attr(feature = "schema", derive(JsonSchema))]
+    #[derive(Debug, Clone, Default, Deserialize, Serialize)]
+    #[serde(default, deny_unknown_fields)]
+    pub struct Config {
+        /// Plaintext rules contained within a single string.
+        ///
+        /// See [`actix_rewrite::Engine::add_rules`] for more info.
+        rules: Option<String>,
+        /// List of additional files to load rules from.
+        rule_files: Vec<PathBuf>,
+        /// Max number of iterations allowed for looping rulesets.
+        ///
+        /// Default is 10.
+        max_iterations: Option<usize>,
+
+        // global initialization for loaded rules.
+        // avoids re-reading rule files for every worker actix-web creates.
+        #[serde(skip)]
+        ruleset: RuleSet,
+    }
+
+    impl Config {
+        /// Build [`actix_rewrite::Middleware`] from config.
+        fn build(&self, spec: &Spec) -> anyhow::Result<Middleware> {
+            use anyhow::Context;
+            let root = spec
+                .config
+                .root
+                .clone()
+                .and_then(|s| s.to_str().map(|s| s.to_owned()))
+                .unwrap_or_default();
+            let ctx = ServerCtx::default()
+                .document_root(root)
+                .server_software(SERVER_SOFTWARE);
+            let rules = self.ruleset.load(&self.rules, &self.rule_files)?;
+            let rewrite = Engine::new()
+                .server_context(ctx)
+                .rules(rules)
+                .context("invalid rewrite rules")?;
+            Ok(rewrite.middleware())
+        }
+
+        /// Validate rules and load them into the shared ruleset.
+        pub fn prepare(&self, spec: &Spec) -> anyhow::Result<()> {
+            self.build(spec).map(|_| ())
+        }
+
+        /// Produce [`actix_rewrite::Middleware`] from config.
+        pub fn factory(&self, spec: &Spec) -> Middleware {
+            self.build(spec)
+                .expect("rewrite rules validated during config load")
+        }
+
+        /// Wrap Chain/Link with configured middleware.
+        pub fn wrap<W: Wrappable>(&self, w: W, spec: &Spec) -> W {
+            w.wrap_with(self.factory(spec))
+        }
+    }
+}
+
+/// Ratelimitting controls middleware.
+#[cfg(feature = "ratelimit")]
+mod ratelimit {
+    use std::fmt::Debug;
+
+    use super::*;
+    use crate::config::default_duration;
+
+    use actix_extensible_rate_limit::{
+        RateLimiter,
+        backend::{SimpleInputFunctionBuilder, SimpleOutput, memory::InMemoryBackend},
+    };
+    use actix_web::{
+        HttpResponse,
+        dev::ServiceRequest,
+        http::{StatusCode, header},
+    };
+    use std::rc::Rc;
+
+    use crate::services::bypass::{Bypass, is_preflight};
+    use bob_cli::Duration;
+
+    /// Derivation wrapper around [`InMemoryBackend`]
+    #[derive(Clone)]
+    struct MemoryBackend(InMemoryBackend);
+
+    impl Debug for MemoryBackend {
+        fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+            write!(f, "MemoryBackend {{}}")
+        }
+    }
+
+    impl Default for MemoryBackend {
+        fn default() -> Self {
+            Self(InMemoryBackend::builder().build())
+        }
+    }
+
+    /// Longest header or cookie value (in bytes) used as a key component.
+    const MAX_KEY_VALUE: usize = 256;
+
+    /// Request attribute used to build ratelimit keys.
+    ///
+    /// Headers and cookies are client controlled, so they are only
+    /// accepted alongside `peer_ip` where rotating the value cannot
+    /// escape limits tied to the client address.
+    /// Missing attributes fall back to the peer IP so requests
+    /// without the attribute are still limited.
+    #[cfg_attr(feature = "schema", derive(JsonSchema))]
+    #[derive(Debug, Clone, Deserialize, Serialize)]
+    #[serde(rename_all = "snake_case")]
+    pub enum KeySource {
+        /// Connection peer address (resolved client IP when ipware is enabled).
+        #[serde(alias = "real_ip")]
+        PeerIp,
+        /// Request path.
+        Path,
+        /// Value of the specified request header (e.g. an API key).
+        Header(String),
+        /// Value of the specified request cookie.
+        Cookie(String),
+        /// Claim from a bearer JWT verified with `jwt_secret`.
+        JwtClaim(String),
+    }
+
+    impl KeySource {
+        /// Extract key component from request if available.
+        fn extract(&self, req: &ServiceRequest, secret: Option<&str>) -> Option<String> {
+            match self {
+                Self::PeerIp => peer_ip(req),
+                Self::Path => Some(req.path().to_owned()),
+                Self::Header(name) => req
+                    .headers()
+                    .get(name.as_str())
+                    .and_then(|v| v.to_str().ok())
+                    .map(bounded),
+                Self::Cookie(name) => req.cookie(name).map(|c| bounded(c.value())),
+                Self::JwtClaim(claim) => jwt_claim(req, secret?, claim),
+            }
+        }
+
+        /// Check if the attribute is freely chosen by the client.
+        #[inline]
+        fn client_controlled(&self) -> bool {
+            matches!(self, Self::Header(_) | Self::Cookie(_))
+        }
+    }
+
+    /// Truncate client supplied value to [`MAX_KEY_VALUE`] bytes.
+    fn bounded(value: &str) -> String {
+        let mut end = value.len().min(MAX_KEY_VALUE);
+        while !value.is_char_boundary(end) {
+            end -= 1;
+        }
+        value[..end].to_owned()
+    }
+
+    #[inline]
+    fn peer_ip(req: &ServiceRequest) -> Option<String> {
+        req.peer_addr().map(|addr| addr.ip().to_string())
+    }
+
+    /// Retrieve claim from a bearer token signed with HS256.
+    ///
+    /// Tokens with an invalid signature, another algorithm or an
+    /// expired `exp` claim yield nothing and fall back to the peer IP.
+    fn jwt_claim(req: &ServiceRequest, secret: &str, claim: &str) -> Option<String> {
+        use base64::{Engine, prelude::BASE64_URL_SAFE_NO_PAD};
+        use hmac::{Hmac, Mac};
+        use sha2::Sha256;
+
+        let auth = req.headers().get(header::AUTHORIZATION)?.to_str().ok()?;
+        let (scheme, token) = auth.split_once(' ')?;
+        if !scheme.eq_ignore_ascii_case("bearer") {
+            return None;
+        }
+        let (signed, signature) = token.trim().rsplit_once('.')?;
+        let (head, payload) = signed.split_once('.')?;
+        let decode = |part: &str| BASE64_URL_SAFE_NO_PAD.decode(part.trim_end_matches('='));
+        let head: serde_json::Value = serde_json::from_slice(&decode(head).ok()?).ok()?;
+        if head.get("alg")?.as_str()? != "HS256" {
+            return None;
+        }
+        let mut mac = Hmac::<Sha256>::new_from_slice(secret.as_bytes()).ok()?;
+        mac.update(signed.as_bytes());
+        mac.verify_slice(&decode(signature).ok()?).ok()?;
+
+        let claims: serde_json::Value = serde_json::from_slice(&decode(payload).ok()?).ok()?;
+        if let Some(exp) = claims.get("exp") {
+            let now = std::time::SystemTime::now()
+                .duration_since(std::time::UNIX_EPOCH)
+                .ok()?
+                .as_secs();
+            if exp.as_u64()? <= now {
+                return None;
+            }
+        }
+        match claims.get(claim)? {
+            serde_json::Value::String(value) => Some(value.clone()),
+            value => Some(value.to_string()),
+        }
+    }
+
+    /// Additional ratelimit rule evaluated with the primary limit.
+    #[cfg_attr(feature = "schema", derive(JsonSchema))]
+    #[derive(Debug, Clone, Deserialize, Serialize)]
+    #[serde(deny_unknown_fields)]
+    pub struct Tier {
+        /// Request limit
+        limit: u64,
+        /// Ratelimit control period
+        ///
+        /// Default is 1s
+        #[serde(default)]
+        period: Option<Duration>,
+    }
+
+    /// Ratelimit rejection response configuration.
+    ///
+    /// Body supports `{limit}`, `{remaining}` and `{retry_after}` placeholders.
+    #[cfg_attr(feature = "schema", derive(JsonSchema))]
+    #[derive(Debug, Clone, Deserialize, Serialize)]
+    #[serde(default, deny_unknown_fields)]
+    pub struct Rejection {
+        /// Rejection status code
+        ///
+        /// Default is 429
+        status_code: Option<u16>,
+        /// Include Retry-After header if enabled
+        ///
+        /// Default is true
+        retry_after: bool,
+        /// Rejection body template
+        body: Option<String>,
+        /// Content type override
+        ///
+        /// Default is text/plain
+        content_type: Option<String>,
+    }
+
+    impl Default for Rejection {
+        fn default() -> Self {
+            Self {
+                status_code: None,
+                retry_after: true,
+                body: None,
+                content_type: None,
+            }
+        }
+    }
+
+    impl Rejection {
+        /// Build rejection response from ratelimit output.
+        fn response(&self, status: StatusCode, output: &SimpleOutput) -> HttpResponse {
+            let retry_after = output
+                .reset
+                .saturating_duration_since(std::time::Instant::now())
+                .as_secs()
+                .max(1);
+            let mut builder = HttpResponse::build(status);
+            if self.retry_after {
+                builder.insert_header((header::RETRY_AFTER, retry_after.to_string()));
+            }
+            let ctype = self
+                .content_type
+                .as_deref()
+                .unwrap_or("text/plain; charset=utf-8");
+            builder.insert_header((header::CONTENT_TYPE, ctype));
+            let body = self
+                .body
+                .as_deref()
+                .unwrap_or("Too Many Requests")
+                .replace("{limit}", &output.limit.to_string())
+                .replace("{remaining}", &output.remaining.to_string())
+                .replace("{retry_after}", &retry_after.to_string());
+            builder.body(body)
+        }
+    }
+
+    /// Ratelimitter middleware configuration.
+    #[cfg_attr(feature = "schema", derive(JsonSchema))]
+    #[derive(Debug, Clone, Default, Deserialize, Serialize)]
+    #[serde(deny_unknown_fields)]
+    pub struct Config {
+        /// Request limit
+        limit: u64,
+        /// Ratelimit control period
+        ///
+        /// Default is 1s
+        #[serde(default)]
+        period: Option<Duration>,
+        /// Additional limits enforced alongside the primary limit.
+        ///
+        /// Allows short bursts while still blocking sustained abuse.
+        #[serde(default)]
+        tiers: Vec<Tier>,
+        /// Request attributes used to discriminate ratelimit buckets.
+        ///
+        /// Default is the peer IP
+        #[serde(default, with = "serde_yaml::with::singleton_map_recursive")]
+        #[cfg_attr(feature = "schema", schemars(with = "Vec<KeySource>"))]
+        key: Vec<KeySource>,
+        /// Shared secret verifying HS256 bearer tokens for `jwt_claim` keys.
+        #[serde(default)]
+        jwt_secret: Option<crate::config::Secret>,
+        /// Discriminate ratelimit by IP and Path if enabled
+        ///
+        /// Default is false
+        #[serde(default)]
+        use_path: bool,
+        /// Allow request by default if backend fails to respond in time
+        ///
+        /// Default is false
+        #[serde(default)]
+        fail_open: bool,
+        /// Include ratelimit explanation headers if enabled
+        ///
+        /// Default is false
+        #[serde(default)]
+        response_headers: bool,
+        /// Response returned when requests are ratelimited
+        #[serde(default)]
+        rejection: Option<Rejection>,
+
+        // global initialization for ratelimit backend.
+        // avoids recreating the backend for every worker actix-web creates.
+        #[serde(default, skip)]
+        backend: MemoryBackend,
+    }
+
+    impl Config {
+        /// Validate the configured keys and rejection response.
+        pub fn prepare(&self, _spec: &Spec) -> anyhow::Result<()> {
+            let claims = self.key.iter().any(|k| matches!(k, KeySource::JwtClaim(_)));
+            if claims && self.jwt_secret.as_ref().is_none_or(|s| s.is_empty()) {
+                anyhow::bail!("ratelimit jwt_claim keys require a jwt_secret");
+            }
+            let controlled = self.key.iter().any(|k| k.client_controlled());
+            let peer = self.key.iter().any(|k| matches!(k, KeySource::PeerIp));
+            if controlled && !peer {
+                anyhow::bail!("ratelimit header and cookie keys must be combined with peer_ip");
+            }
+            if let Some(rejection) = self.rejection.as_ref() {
+                let status_code = rejection.status_code.unwrap_or(429);
+                StatusCode::from_u16(status_code)
+                    .map_err(|_| anyhow::anyhow!("invalid rejection status {status_code}"))?;
+            }
+            Ok(())
+        }
+
+        // ratelimiter generics make it annoying to export as a type
+        // from a function cause they cause type errors when passing it
+        // into `wrap_with`. instead we go directly to wrap with builder
+        // to avoid that nonsense.
+
+        /// Wrap Chain/Link with configured middleware.
+        ///
+        /// Every tier is enforced by a separate limiter sharing
+        /// the same backend with tier-specific keys.
+        pub fn wrap<W: Wrappable>(&self, mut w: W, spec: &Spec) -> W {
+            let keys = match self.key.is_empty() {
+                true => vec![KeySource::PeerIp],
+                false => self.key.clone(),
+            };
+            let primary = Tier {
+                limit: self.limit,
+                period: self.period.clone(),
+            };
+            let tiers = std::iter::once(&primary).chain(self.tiers.iter());
+            for (n, tier) in tiers.enumerate() {
+                let period = default_duration(&tier.period, 1);
+                let keys = keys.clone();
+                let secret = self.jwt_secret.clone();
+                let mut input =
+                    SimpleInputFunctionBuilder::new(period, tier.limit).custom_fn(move |req| {
+                        let secret = secret.as_deref();
+                        let key = keys
+                            .iter()
+                            .map(|k| k.extract(req, secret).or_else(|| peer_ip(req)))
+                            .map(|k| k.unwrap_or_default())
+                            .collect::<Vec<_>>()
+                            .join("|");
+                        Ok(format!("{n}|{key}"))
+                    });
+                if self.use_path {
+                    input = input.path_key();
+                }
+
+                let mut middleware = RateLimiter::builder(self.backend.0.clone(), input.build())
+                    .fail_open(self.fail_open);
+                if self.response_headers && n == 0 {
+                    middleware = middleware.add_headers();
+                }
+                if let Some(rejection) = self.rejection.clone() {
+                    let status_code = rejection.status_code.unwrap_or(429);
+                    let status = StatusCode::from_u16(status_code)
+                        .expect("ratelimit rejection status validated during config load");
+                    middleware = middleware
+                        .request_denied_response(move |output| rejection.response(status, output));
+                }
+                w = match spec.config.preflight_bypass {
+                    true => w.wrap_with(Bypass::new(middleware.build(), Rc::new(is_preflight))),
+                    false => w.wrap_with(middleware.build()),
+                };
+            }
+            w
+        }
+    }
+
+    #[cfg(test)]
+    mod tests {
+        use actix_web::{cookie::Cookie, test::TestRequest};
+
+        use super::*;
+
+        fn config(yaml: &str) -> Config {
+            serde_yaml::from_str(yaml).expect("valid ratelimit config")
+        }
+
+        #[test]
+        fn header_and_cookie_keys() {
+            let req = TestRequest::default()
+                .insert_header(("x-api-key", "tenant-a"))
+                .cookie(Cookie::new("session", "abc"))
+                .to_srv_request();
+            let header = KeySource::Header("X-Api-Key".to_owned());
+            let cookie = KeySource::Cookie("session".to_owned());
+            assert_eq!(header.extract(&req, None).as_deref(), Some("tenant-a"));
+            assert_eq!(cookie.extract(&req, None).as_deref(), Some("abc"));
+
+            let req = TestRequest::default()
+                .insert_header(("x-api-key", "k".repeat(1024)))
+                .to_srv_request();
+            assert_eq!(
+                header.extract(&req, None).map(|v| v.len()),
+                Some(MAX_KEY_VALUE)
+            );
+            assert_eq!(cookie.extract(&req, None), None);
+        }
+
+        #[test]
+        fn client_controlled_keys_require_peer_ip() {
+            let server = crate::config::ServerConfig::default();
+            let spec = Spec::new(&server);
+            let rejected = config("limit: 10\nkey: [{header: X-Api-Key}]");
+            assert!(rejected.prepare(&spec).is_err());
+            let rejected = config("limit: 10\nkey: [path, {cookie: session}]");
+            assert!(rejected.prepare(&spec).is_err());
+            let accepted = config("limit: 10\nkey: [peer_ip, {header: X-Api-Key}]");
+            assert!(accepted.prepare(&spec).is_ok());
+        }
+    }
+}
+
+/// Processing Timeout Middleware.
+#[cfg(feature = "timeout")]
+mod timeout {
+
+    use super::*;
+    use actix_timeout::Timeout;
+
+    /// Timeout middleware configuration.
+    #[cfg_attr(feature = "schema", derive(JsonSchema))]
+    #[derive(Debug, Clone, Default, Deserialize, Serialize)]
+    #[serde(default, deny_unknown_fields)]
+    pub struct Config {
+        /// Timeout duration in miliseconds
+        duration: u64,
+    }
+
+    impl Config {
+        /// Produce [`actix_timeout::Timeout`] from config.
+        pub fn factory(&self, _spec: &Spec) -> Timeout {
+            Timeout::from_millis(self.duration)
+        }
+
+        /// Wrap Chain/Link with configured middleware.
+        pub fn wrap<W: Wrappable>(&self, w: W, spec: &Spec) -> W {
+            w.wrap_with(self.factory(spec))
+        }
+    }
+}
+
+/// DNS Rebinding Protection Middleware.
+#[cfg(feature = "rebinding")]
+mod rebinding {
+    use super::*;
+    use crate::{
+        config::DomainMatch,
+        services::rebinding::{Rebinding, Settings},
+    };
+
+    /// DNS rebinding protection middleware configuration.
+    #[cfg_attr(feature = "schema", derive(JsonSchema))]
+    #[derive(Debug, Clone, Deserialize, Serialize)]
+    #[serde(default, deny_unknown_fields)]
+    pub struct Config {
+        /// Host names accepted in addition to the server names.
+        allowed_hosts: Vec<DomainMatch>,
+        /// Accept requests addressed directly to an IP.
+        ///
+        /// Default is true
+        allow_ip_hosts: bool,
+        /// Reject hosts resolving to private, loopback or link-local ranges.
+        ///
+        /// Only applies when no host names are expected. Default is true
+        block_private: bool,
+    }
+
+    impl Default for Config {
+        fn default() -> Self {
+            Self {
+                allowed_hosts: vec![],
+                allow_ip_hosts: true,
+                block_private: true,
+            }
+        }
+    }
+
+    impl Config {
+        /// Produce [`crate::services::rebinding::Rebinding`] from config.
+        pub fn factory(&self, spec: &Spec) -> Rebinding {
+            let allowed = self
+                .allowed_hosts
+                .iter()
+                .chain(spec.config.server_name.iter())
+                .cloned()
+                .collect();
+            Rebinding::new(Settings {
+                allowed,
+                allow_ip_hosts: self.allow_ip_hosts,
+                block_private: self.block_private,
+            })
+        }
+
+        /// Wrap Chain/Link with configured middleware.
+        pub fn wrap<W: Wrappable>(&self, w: W, spec: &Spec) -> W {
+            w.wrap_with(self.factory(spec))
+        }
+    }
+}
+
+/// Cross-Origin Resource Sharing Middleware.
+#[cfg(feature = "cors")]
+mod cors {
+    use super::*;
+    use crate::{
+        config::PathMatch,
+        services::cors::{Cors, Settings},
+    };
+
+    /// CORS middleware configuration.
+    #[cfg_attr(feature = "schema", derive(JsonSchema))]
+    #[derive(Debug, Clone, Default, Deserialize, Serialize)]
+    #[serde(default, deny_unknown_fields)]
+    pub struct Config {
+        /// Allowed origin globs.
+        ///
+        /// Default allows any origin
+        allow_origins: Vec<PathMatch>,
+        /// Allowed request methods.
+        ///
+        /// Default is GET, HEAD, POST, PUT, PATCH, DELETE, OPTIONS
+        allow_methods: Vec<String>,
+        /// Allowed request headers.
+        ///
+        /// Default reflects the headers requested by the client
+        allow_headers: Vec<String>,
+        /// Response headers exposed to client scripts.
+        expose_headers: Vec<String>,
+        /// Allow credentials to be included with requests.
+        ///
+        /// Requires `allow_origins` to be set. Default is false
+        allow_credentials: bool,
+        /// Seconds browsers may cache preflight responses.
+        max_age: Option<u64>,
+    }
+
+    impl Config {
+        /// Validate CORS policy settings.
+        pub fn prepare(&self, _spec: &Spec) -> anyhow::Result<()> {
+            if self.allow_credentials && self.allow_origins.is_empty() {
+                return Err(anyhow::anyhow!(
+                    "allow_credentials requires explicit allow_origins"
+                ));
+            }
+            Ok(())
+        }
+
+        /// Produce [`crate::services::cors::Cors`] from config.
+        pub fn factory(&self, _spec: &Spec) -> Cors {
+            let methods = match self.allow_methods.is_empty() {
+                true => ["GET", "HEAD", "POST", "PUT", "PATCH", "DELETE", "OPTIONS"]
+                    .map(String::from)
+                    .to_vec(),
+                false => self.allow_methods.clone(),
+            };
+            Cors::new(Settings {
+                origins: self.allow_origins.iter().map(|p| p.0.clone()).collect(),
+                methods,
+                headers: self.allow_headers.clone(),
+                expose_headers: self.expose_headers.clone(),
+                credentials: self.allow_credentials,
+                max_age: self.max_age,
+            })
+        }
+
+        /// Wrap Chain/Link with configured middleware.
+        pub fn wrap<W: Wrappable>(&self, w: W, spec: &Spec) -> W {
+            w.wrap_with(self.factory(spec))
+        }
+    }
+}
+
+/// Shared Dictionary Compression Middleware.
+#[cfg(feature = "compress")]
+mod compress {
+    use std::{
+        fmt::Debug,
+        path::PathBuf,
+        sync::{Arc, OnceLock},
+    };
+
+    use super::*;
+    use crate::{
+        config::PathMatch,
+        services::dictionary::{Dictionary, DictionaryCompress},
+    };
+
+    /// Derivation wrapper around loaded [`Dictionary`] instances.
+    ///
+    /// Dictionaries are loaded once and shared between every worker.
+    #[derive(Clone, Default)]
+    struct Loaded(Arc<OnceLock<Vec<Arc<Dictionary>>>>);
+
+    impl Debug for Loaded {
+        fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+            write!(f, "Loaded {{}}")
+        }
+    }
+
+    /// Compression algorithm used with shared dictionaries.
+    #[cfg_attr(feature = "schema", derive(JsonSchema))]
+    #[derive(Debug, Clone, Default, Deserialize, Serialize)]
+    #[serde(rename_all = "lowercase")]
+    pub enum Algorithm {
+        /// Dictionary compressed zstd (`dcz`).
+        #[default]
+        Zstd,
+    }
+
+    /// Shared compression dictionary configuration.
+    #[cfg_attr(feature = "schema", derive(JsonSchema))]
+    #[derive(Debug, Clone, Deserialize, Serialize)]
+    #[serde(deny_unknown_fields)]
+    pub struct DictionaryCfg {
+        /// Path to the raw dictionary file.
+        path: PathBuf,
+        /// Request path glob of responses compressed with the dictionary.
+        ///
+        /// Also advertised to clients within `Use-As-Dictionary`.
+        #[serde(rename = "match")]
+        matches: String,
+        /// Response content-types eligible for compression.
+        ///
+        /// Default is any content-type
+        #[serde(default)]
+        content_types: Vec<String>,
+        /// Request path the dictionary itself is served from.
+        #[serde(default)]
+        serve_at: Option<String>,
+    }
+
+    /// Shared dictionary compression middleware configuration.
+    #[cfg_attr(feature = "schema", derive(JsonSchema))]
+    #[derive(Debug, Clone, Default, Deserialize, Serialize)]
+    #[serde(default, deny_unknown_fields)]
+    pub struct Config {
+        /// Compression algorithm.
+        ///
+        /// Default is zstd
+        algorithm: Algorithm,
+        /// Compression level.
+        ///
+        /// Default is 3
+        level: Option<i32>,
+        /// Dictionaries available for compression.
+        dictionaries: Vec<DictionaryCfg>,
+
+        // global initialization for loaded dictionaries.
+        // avoids re-reading dictionary files for every worker actix-web creates.
+        #[serde(skip)]
+        loaded: Loaded,
+    }
+
+    impl Config {
+        /// Load dictionaries into the shared dictionary set.
+        fn load(&self) -> anyhow::Result<&Vec<Arc<Dictionary>>> {
+            use anyhow::Context;
+            if let Some(loaded) = self.loaded.0.get() {
+                return Ok(loaded);
+            }
+            let Algorithm::Zstd = self.algorithm;
+            let level = self.level.unwrap_or(3);
+            let mut dictionaries = Vec::with_capacity(self.dictionaries.len());
+            for cfg in self.dictionaries.iter() {
+                let raw = std::fs::read(&cfg.path)
+                    .with_context(|| format!("failed to read dictionary {:?}", cfg.path))?;
+                let pattern: PathMatch = cfg
+                    .matches
+                    .parse()
+                    .with_context(|| format!("invalid dictionary match {:?}", cfg.matches))?;
+                dictionaries.push(Arc::new(Dictionary::new(
+                    raw,
+                    level,
+                    pattern.0,
+                    cfg.matches.clone(),
+                    cfg.content_types.clone(),
+                    cfg.serve_at.clone(),
+                )));
+            }
+            Ok(self.loaded.0.get_or_init(|| dictionaries))
+        }
+
+        /// Validate and load configured dictionaries.
+        pub fn prepare(&self, _spec: &Spec) -> anyhow::Result<()> {
+            self.load().map(|_| ())
+        }
+
+        /// Produce [`crate::services::dictionary::DictionaryCompress`] from config.
+        pub fn factory(&self, _spec: &Spec) -> DictionaryCompress {
+            let dictionaries = self
+                .load()
+                .expect("compression dictionaries validated during config load");
+            DictionaryCompress::new(dictionaries.clone())
+        }
+
+        /// Wrap Chain/Link with configured middleware.
+        pub fn wrap<W: Wrappable>(&self, w: W, spec: &Spec) -> W {
+            w.wrap_with(self.factory(spec))
+        }
+    }
+}
+
+/// Streaming Body Transformation Middleware.
+#[cfg(feature = "transform")]
+mod transform {
+    use super::*;
+    use crate::services::transform::{BodyTransform, Inject, Pipeline, Rechunk, Substitute};
+
+    /// Position content is injected at within html documents.
+    #[cfg_attr(feature = "schema", derive(JsonSchema))]
+    #[derive(Debug, Clone, Default, Deserialize, Serialize)]
+    #[serde(rename_all = "snake_case")]
+    pub enum Position {
+        /// Before the closing `</head>` tag.
+        Head,
+        /// Before the closing `</body>` tag.
+        #[default]
+        Body,
+    }
+
+    impl Position {
+        #[inline]
+        fn marker(&self) -> &'static str {
+            match self {
+                Self::Head => "</head>",
+                Self::Body => "</body>",
+            }
+        }
+    }
+
+    /// Single body transformation step.
+    #[cfg_attr(feature = "schema", derive(JsonSchema))]
+    #[derive(Debug, Clone, Deserialize, Serialize)]
+    #[serde(tag = "filter", rename_all = "snake_case", deny_unknown_fields)]
+    pub enum FilterCfg {
+        /// Replace occurrences of a string within the body.
+        Substitute {
+            /// String to search for.
+            from: String,
+            /// Replacement string.
+            to: String,
+            /// Only replace the first occurrence if true.
+            ///
+            /// Default is false
+            #[serde(default)]
+            once: bool,
+        },
+        /// Inject content into html documents.
+        Inject {
+            /// Content to insert.
+            content: String,
+            /// Position content is inserted at.
+            ///
+            /// Default is body
+            #[serde(default)]
+            position: Position,
+        },
+        /// Re-chunk the body into fixed size chunks.
+        Rechunk {
+            /// Chunk size in bytes.
+            size: usize,
+        },
+        /// Streaming zstd compression for clients accepting it.
+        #[cfg(feature = "compress")]
+        Zstd {
+            /// Compression level.
+            ///
+            /// Default is 3
+            #[serde(default)]
+            level: Option<i32>,
+        },
+    }
+
+    /// Body transformation middleware configuration.
+    #[cfg_attr(feature = "schema", derive(JsonSchema))]
+    #[derive(Debug, Clone, Default, Deserialize, Serialize)]
+    #[serde(default, deny_unknown_fields)]
+    pub struct Config {
+        /// Response content-types eligible for transformation.
+        ///
+        /// Default is any content-type
+        content_types: Vec<String>,
+        /// Ordered transformations applied to response bodies.
+        filters: Vec<FilterCfg>,
+    }
+
+    impl Config {
+        /// Validate configured filters.
+        pub fn prepare(&self, _spec: &Spec) -> anyhow::Result<()> {
+            if self.filters.is_empty() {
+                anyhow::bail!("transform requires at least one filter");
+            }
+            for filter in self.filters.iter() {
+                match filter {
+                    FilterCfg::Substitute { from, .. } if from.is_empty() => {
+                        anyhow::bail!("transform substitute requires a non-empty from")
+                    }
+                    FilterCfg::Rechunk { size: 0 } => {
+                        anyhow::bail!("transform rechunk size must be greater than zero")
+                    }
+                    #[cfg(feature = "compress")]
+                    FilterCfg::Zstd { level: Some(level) } if !(1..=22).contains(level) => {
+                        anyhow::bail!("transform zstd level must be between 1 and 22")
+                    }
+                    _ => {}
+                }
+            }
+            Ok(())
+        }
+
+        /// Produce [`crate::services::transform::BodyTransform`] from config.
+        pub fn factory(&self, _spec: &Spec) -> BodyTransform {
+            let pipeline = Pipeline::default().content_types(self.content_types.clone());
+            let pipeline = self
+                .filters
+                .iter()
+                .fold(pipeline, |pipeline, filter| match filter {
+                    FilterCfg::Substitute { from, to, once } => {
+                        let substitute = Substitute::new(from.clone(), to.clone());
+                        match once {
+                            true => pipeline.filter(substitute.limit(1)),
+                            false => pipeline.filter(substitute),
+                        }
+                    }
+                    FilterCfg::Inject { content, position } => {
+                        pipeline.filter(Inject::new(position.marker(), content))
+                    }
+                    FilterCfg::Rechunk { size } => pipeline.filter(Rechunk(*size)),
+                    #[cfg(feature = "compress")]
+                    FilterCfg::Zstd { level } => {
+                        pipeline.filter(crate::services::transform::Zstd(level.unwrap_or(3)))
+                    }
+                });
+            BodyTransform::new(pipeline)
+        }
+
+        /// Wrap Chain/Link with configured middleware.
+        pub fn wrap<W: Wrappable>(&self, w: W, spec: &Spec) -> W {
+            w.wrap_with(self.factory(spec))
+        }
+    }
+}
+
+/// WebAssembly Filter Middleware.
+#[cfg(feature = "wasm")]
+mod wasm {
+    use std::{
+        fmt::Debug,
+        path::PathBuf,
+        sync::{Arc, OnceLock},
+    };
+
+    use super::*;
+    use crate::services::wasm::{DEFAULT_FUEL, DEFAULT_MAX_MEMORY, Filter, Settings, Wasm};
+
+    /// Filter compiled once and shared between every worker.
+    #[derive(Clone, Default)]
+    struct Compiled(Arc<OnceLock<Arc<Filter>>>);
+
+    impl Debug for Compiled {
+        fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+            write!(f, "Compiled {{}}")
+        }
+    }
+
+    /// WebAssembly filter middleware configuration.
+    #[cfg_attr(feature = "schema", derive(JsonSchema))]
+    #[derive(Debug, Clone, Deserialize, Serialize)]
+    #[serde(deny_unknown_fields)]
+    pub struct Config {
+        /// Compiled WebAssembly filter module.
+        #[serde(alias = "module", alias = "path")]
+        filter: PathBuf,
+        /// Filter specific configuration exposed as the `config` property.
+        #[serde(default)]
+        config: Option<String>,
+        /// Buffer request bodies so the filter can read them.
+        ///
+        /// Default is false
+        #[serde(default)]
+        request_body: bool,
+        /// Buffer response bodies so the filter can read them.
+        ///
+        /// Default is false
+        #[serde(default)]
+        response_body: bool,
+        /// Maximum buffered body size in bytes.
+        ///
+        /// Default is 1MiB
+        #[serde(default)]
+        max_body_size: Option<usize>,
+        /// Instruction budget available to the filter per request.
+        ///
+        /// Default is 100000000
+        #[serde(default)]
+        fuel: Option<u64>,
+        /// Maximum linear memory of a filter instance in bytes.
+        ///
+        /// Default is 64MiB
+        #[serde(default)]
+        max_memory: Option<usize>,
+        /// Pass requests on unfiltered when the filter fails.
+        ///
+        /// Default is false
+        #[serde(default)]
+        fail_open: bool,
+
+        // global initialization for the compiled filter.
+        // avoids compiling the module for every worker actix-web creates.
+        #[serde(skip)]
+        compiled: Compiled,
+    }
+
+    impl Config {
+        /// Compile configured filter into the shared filter cell.
+        fn load(&self) -> anyhow::Result<Arc<Filter>> {
+            if let Some(filter) = self.compiled.0.get() {
+                return Ok(Arc::clone(filter));
+            }
+            let filter = Arc::new(Filter::load(&self.filter)?);
+            Ok(Arc::clone(self.compiled.0.get_or_init(|| filter)))
+        }
+
+        /// Validate and compile the configured filter.
+        pub fn prepare(&self, _spec: &Spec) -> anyhow::Result<()> {
+            self.load().map(|_| ())
+        }
+
+        /// Produce [`crate::services::wasm::Wasm`] from config.
+        pub fn factory(&self, _spec: &Spec) -> Wasm {
+            Wasm::new(Settings {
+                filter: self
+                    .load()
+                    .expect("wasm filter validated during config load"),
+                config: self.config.clone().unwrap_or_default(),
+                request_body: self.request_body,
+                response_body: self.response_body,
+                max_body_size: self.max_body_size.unwrap_or(1024 * 1024),
+                fuel: self.fuel.unwrap_or(DEFAULT_FUEL),
+                max_memory: self.max_memory.unwrap_or(DEFAULT_MAX_MEMORY),
+                fail_open: self.fail_open,
+            })
+        }
+
+        /// Wrap Chain/Link with configured middleware.
+        pub fn wrap<W: Wrappable>(&self, w: W, spec: &Spec) -> W {
+            w.wrap_with(self.factory(spec))
+        }
+    }
+}
+
+/// Embedded Scripting Middleware.
+#[cfg(feature = "script")]
+mod script {
+    use std::{
+        fmt::Debug,
+        path::PathBuf,
+        sync::{Arc, OnceLock},
+    };
+
+    use anyhow::Context;
+
+    use super::*;
+    use crate::services::script::{Script, Scripts};
+
+    /// Scripts compiled once and shared between every worker.
+    #[derive(Clone, Default)]
+    struct Compiled(Arc<OnceLock<Arc<Scripts>>>);
+
+    impl Debug for Compiled {
+        fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+            write!(f, "Compiled {{}}")
+        }
+    }
+
+    /// Read script source from inline configuration or file.
+    fn source(inline: &Option<String>, file: &Option<PathBuf>) -> anyhow::Result<Option<String>> {
+        match (inline, file) {
+            (Some(_), Some(_)) => Err(anyhow::anyhow!(
+                "script and script file are mutually exclusive"
+            )),
+            (Some(source), None) => Ok(Some(source.clone())),
+            (None, Some(path)) => std::fs::read_to_string(path)
+                .with_context(|| format!("failed to read script {path:?}"))
+                .map(Some),
+            (None, None) => Ok(None),
+        }
+    }
+
+    /// Rhai scripting middleware configuration.
+    #[cfg_attr(feature = "schema", derive(JsonSchema))]
+    #[derive(Debug, Clone, Default, Deserialize, Serialize)]
+    #[serde(default, deny_unknown_fields)]
+    pub struct Config {
+        /// Inline script run against every request.
+        #[serde(alias = "script")]
+        request: Option<String>,
+        /// File containing the script run against every request.
+        request_file: Option<PathBuf>,
+        /// Inline script run against every response.
+        response: Option<String>,
+        /// File containing the script run against every response.
+        response_file: Option<PathBuf>,
+        /// Maximum number of operations a script may run per request.
+        ///
+        /// Default is 100000
+        max_operations: Option<u64>,
+
+        // global initialization for the compiled scripts.
+        // avoids compiling the scripts for every worker actix-web creates.
+        #[serde(skip)]
+        compiled: Compiled,
+    }
+
+    impl Config {
+        /// Compile configured scripts into the shared script cell.
+        fn load(&self) -> anyhow::Result<Arc<Scripts>> {
+            if let Some(scripts) = self.compiled.0.get() {
+                return Ok(Arc::clone(scripts));
+            }
+            let request = source(&self.request, &self.request_file)?;
+            let response = source(&self.response, &self.response_file)?;
+            let scripts = Scripts::compile(
+                request.as_deref(),
+                response.as_deref(),
+                self.max_operations.unwrap_or(100_000),
+            )?;
+            Ok(Arc::clone(
+                self.compiled.0.get_or_init(|| Arc::new(scripts)),
+            ))
+        }
+
+        /// Validate and compile the configured scripts.
+        pub fn prepare(&self, _spec: &Spec) -> anyhow::Result<()> {
+            self.load().map(|_| ())
+        }
+
+        /// Produce [`crate::services::script::Script`] from config.
+        pub fn factory(&self, _spec: &Spec) -> Script {
+            Script::new(self.load().expect("scripts validated during config load"))
+        }
+
+        /// Wrap Chain/Link with configured middleware.
+        pub fn wrap<W: Wrappable>(&self, w: W, spec: &Spec) -> W {
+            w.wrap_with(self.factory(spec))
+        }
+    }
+}
@@ -0,0 +1,2323 @@
+//! Configuration Serializer/Deserializer Types
+
+use std::{
+    collections::{BTreeMap, BTreeSet},
+    net::{IpAddr, Ipv4Addr, Ipv6Addr, SocketAddr},
+    path::PathBuf,
+    str::FromStr,
+    sync::Arc,
+};
+
+#[cfg(feature = "schema")]
+use schemars::JsonSchema;
+
+use actix_chain::Chain;
+use actix_web::{guard::Guard, http::header};
+use anyhow::{Context, Result, anyhow};
+use bob_cli::{Duration, Uri, de_fromstr, ser_display};
+use bob_guard::MatcherGuard;
+use serde::{
+    Deserialize, Serialize,
+    de::{self, Error, Unexpected},
+};
+
+use crate::{hooks::Event, services::anonymize::Anonymizer};
+
+pub mod middleware;
+pub mod modules;
+
+pub use middleware::Middleware;
+pub use modules::{Module, ModuleConfig};
+
+/// Read all server configurations from a config file.
+pub fn read_config(path: &PathBuf) -> Result<Vec<ServerConfig>> {
+    let s = std::fs::read_to_string(path).context("failed to read config")?;
+    let configs: Vec<ServerConfig> = serde_yaml::from_str(&s).context("invalid config")?;
+    match configs.is_empty() {
+        true => Err(anyhow!("config: {path:?} is empty")),
+        false => Ok(configs),
+    }
+}
+
+/// Validate and prepare all configured middleware and modules before startup.
+///
+/// Every server is validated before returning so all configuration
+/// errors are reported at once rather than only the first.
+pub fn prepare_config(configs: &[ServerConfig]) -> Result<()> {
+    let mut errors = Vec::new();
+    let defaults = configs
+        .iter()
+        .filter(|c| !c.disable && c.default_server)
+        .count();
+    if defaults > 1 {
+        errors.push(anyhow!(
+            "{defaults} servers set default_server, only one is allowed"
+        ));
+    }
+    let labels: Vec<&str> = configs
+        .iter()
+        .filter(|c| !c.disable)
+        .flat_map(|c| c.listen.iter())
+        .filter_map(|l| l.label.as_deref())
+        .collect();
+    for (n, config) in configs.iter().enumerate() {
+        let spec = Spec::new(config);
+        if let Some(admin) = config.admin.as_ref()
+            && admin.socket.is_none()
+            && admin.token.is_none()
+        {
+            errors.push(anyhow!(
+                "server[{n}]: admin api bound to an address requires a token"
+            ));
+        }
+        if let Some(code) = config.unmatched_status
+            && actix_web::http::StatusCode::from_u16(code).is_err()
+        {
+            errors.push(anyhow!("server[{n}]: invalid unmatched_status {code}"));
+        }
+        for label in config.listeners.iter() {
+            if !labels.contains(&label.as_str()) {
+                errors.push(anyhow!("server[{n}]: no listener labeled {label:?}"));
+            }
+        }
+        for (path, middleware) in config.all_middleware() {
+            let result = middleware
+                .prepare(&spec)
+                .with_context(|| format!("server[{n}]{path}: invalid middleware"));
+            errors.extend(result.err());
+        }
+        for (path, module) in config.all_modules() {
+            let result = module
+                .prepare(&spec)
+                .with_context(|| format!("server[{n}]{path}: invalid module"));
+            errors.extend(result.err());
+        }
+        if config.logging.sample == Some(0) {
+            errors.push(anyhow!(
+                "server[{n}]: logging sample must be greater than zero"
+            ));
+        }
+        let logging = &config.logging;
+        if logging.sample.is_some_and(|n| n > 1)
+            && logging.format.is_some()
+            && logging.access_log.is_none()
+        {
+            errors.push(anyhow!(
+                "server[{n}]: logging sample does not support a custom format without access_log"
+            ));
+        }
+        let result = config
+            .timeouts
+            .validate()
+            .with_context(|| format!("server[{n}]: invalid timeouts"));
+        errors.extend(result.err());
+        if let Some(canonical) = config.canonical.as_ref() {
+            let result = canonical
+                .validate()
+                .with_context(|| format!("server[{n}]: invalid canonical"));
+            errors.extend(result.err());
+        }
+        if let Some(flags) = config.debug_flags.as_ref() {
+            let result = flags
+                .validate()
+                .with_context(|| format!("server[{n}]: invalid debug_flags"));
+            errors.extend(result.err());
+        }
+        for (d, directive) in config.directives.iter().enumerate() {
+            let toggles = directive.skip_flags();
+            if !toggles.is_empty() && config.debug_flags.is_none() {
+                errors.push(anyhow!(
+                    "server[{n}].directives[{d}]: skip_flag requires debug_flags"
+                ));
+            }
+            if toggles.len() > MAX_SKIP_FLAGS {
+                errors.push(anyhow!(
+                    "server[{n}].directives[{d}]: at most {MAX_SKIP_FLAGS} distinct skip_flag values are supported"
+                ));
+            }
+        }
+        #[cfg(feature = "acme")]
+        if let Some(on_demand) = config.on_demand_tls.as_ref() {
+            let result = on_demand
+                .validate()
+                .with_context(|| format!("server[{n}]: invalid on_demand_tls"));
+            errors.extend(result.err());
+        }
+        #[cfg(feature = "acme")]
+        let on_demand = config.on_demand_tls.is_some();
+        #[cfg(not(feature = "acme"))]
+        let on_demand = false;
+        for (l, listen) in config.listen.iter().enumerate() {
+            let Some(ssl) = listen.ssl.as_ref() else {
+                continue;
+            };
+            #[cfg(feature = "ocsp")]
+            if let Some(ocsp) = ssl.ocsp.as_ref()
+                && ocsp.refresh.as_ref().is_some_and(|d| d.0.is_zero())
+            {
+                errors.push(anyhow!(
+                    "server[{n}].listen[{l}]: ocsp refresh must be greater than zero"
+                ));
+            }
+            #[cfg(feature = "keystore")]
+            if let Some(passphrase) = ssl.key_passphrase.as_ref() {
+                if ssl.certificate_key.is_none() {
+                    errors.push(anyhow!(
+                        "server[{n}].listen[{l}]: key_passphrase requires a certificate_key"
+                    ));
+                }
+                let result = passphrase
+                    .load()
+                    .with_context(|| format!("server[{n}].listen[{l}]: invalid key_passphrase"));
+                errors.extend(result.err());
+            }
+            #[cfg(feature = "keystore")]
+            let bundled = ssl
+                .certificate_key
+                .as_deref()
+                .is_some_and(crate::tls::keys::is_pkcs12);
+            #[cfg(not(feature = "keystore"))]
+            let bundled = false;
+            match (&ssl.certificate, &ssl.certificate_key) {
+                (Some(_), Some(_)) => {}
+                (None, Some(_)) if bundled => {}
+                (None, None) if on_demand || ssl.store.is_some() => {}
+                (None, None) => errors.push(anyhow!(
+                    "server[{n}].listen[{l}]: ssl requires a certificate, store or on_demand_tls"
+                )),
+                _ => errors.push(anyhow!(
+                    "server[{n}].listen[{l}]: certificate and certificate_key must be set together"
+                )),
+            }
+        }
+        for (d, directive) in config.directives.iter().enumerate() {
+            if let Some(timeouts) = directive.timeouts.as_ref() {
+                let result = timeouts
+                    .validate()
+                    .with_context(|| format!("server[{n}].directives[{d}]: invalid timeouts"));
+                errors.extend(result.err());
+            }
+            if let Some(budget) = directive.budget.as_ref() {
+                let result = budget
+                    .validate()
+                    .with_context(|| format!("server[{n}].directives[{d}]: invalid budget"));
+                errors.extend(result.err());
+            }
+            if directive.fallback.is_some() && directive.budget.is_none() {
+                errors.push(anyhow!(
+                    "server[{n}].directives[{d}]: fallback requires a budget"
+                ));
+            }
+        }
+    }
+    errors.extend(process_wide(configs, "connections.max_connections", |c| {
+        c.connections.max_connections
+    }));
+    errors.extend(process_wide(
+        configs,
+        "connections.max_connection_rate",
+        |c| c.connections.max_connection_rate,
+    ));
+    errors.extend(process_wide(configs, "connections.keep_alive", |c| {
+        c.connections.keep_alive.as_ref().map(|t| t.0)
+    }));
+    errors.extend(process_wide(configs, "slow_clients.header_timeout", |c| {
+        c.slow_clients.header_timeout.as_ref().map(|t| t.0)
+    }));
+    match errors.len() {
+        0 => {
+            for config in configs {
+                let _ = config.load_state.prepared.set(());
+            }
+            Ok(())
+        }
+        1 => Err(errors.remove(0)),
+        count => {
+            let details: Vec<String> = errors.iter().map(|err| format!("  {err:#}")).collect();
+            Err(anyhow!(
+                "{count} configuration errors:\n{}",
+                details.join("\n")
+            ))
+        }
+    }
+}
+
+/// Reject process-wide settings configured differently between servers.
+fn process_wide<T: Ord + std::fmt::Debug>(
+    configs: &[ServerConfig],
+    name: &str,
+    f: impl Fn(&ServerConfig) -> Option<T>,
+) -> Option<anyhow::Error> {
+    let values: BTreeSet<T> = configs
+        .iter()
+        .filter(|c| !c.disable)
+        .filter_map(f)
+        .collect();
+    (values.len() > 1)
+        .then(|| anyhow!("{name} applies to the whole process but servers configure {values:?}"))
+}
+
+/// Establish external connections required by configured middleware.
+///
+/// Called once per process after the configuration has been prepared.
+pub async fn connect_config(configs: &[ServerConfig]) -> Result<()> {
+    for (n, config) in configs.iter().enumerate() {
+        for (path, middleware) in config.all_middleware() {
+            middleware
+                .connect()
+                .await
+                .with_context(|| format!("server[{n}]{path}: middleware connection failed"))?;
+        }
+        let _ = config.load_state.connected.set(());
+    }
+    Ok(())
+}
+
+/// Server specific configuration settings.
+#[cfg_attr(feature = "schema", derive(JsonSchema))]
+#[derive(Clone, Debug, Default, Deserialize, Serialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct ServerConfig {
+    /// Disable configuration from initialization within server.
+    pub disable: bool,
+    /// List of configurations for binding server addresses.
+    pub listen: Vec<ListenCfg>,
+    /// Labels of the listeners the server is reachable through.
+    ///
+    /// Listeners are declared with a `label` by any server.
+    /// Default is every listener
+    pub listeners: Vec<String>,
+    /// Configuration settings for logging.
+    pub logging: LoggingCfg,
+    /// List of domain-names matchers with the server.
+    ///
+    /// Once registered, the server will only respond to
+    /// requests with `Host` set to the relevant matchers.
+    /// Matching ignores case and any port unless `server_name_port` is set.
+    pub server_name: Vec<DomainMatch>,
+    /// Require a port given within `Host` to match a listener port.
+    ///
+    /// Default is false
+    pub server_name_port: bool,
+    /// Handle requests whose `Host` matches no other server's `server_name`.
+    ///
+    /// Only a single enabled server may be the default server.
+    pub default_server: bool,
+    /// Status returned for requests matching no server when
+    /// there is no default server.
+    ///
+    /// `444` answers with an empty `444` response and closes the connection.
+    /// The first enabled server configuring a status is used.
+    ///
+    /// Default is 421
+    pub unmatched_status: Option<u16>,
+    /// Configuration settings for middlware within server instance.
+    pub middleware: Vec<Middleware>,
+    /// Request handling directives associated with server instance.
+    pub directives: Vec<DirectiveCfg>,
+    /// Default root filepath for various request handling modules.
+    pub root: Option<PathBuf>,
+    /// List of supported index file patterns when requesting resources.
+    ///
+    /// Default is [index.html, ]
+    pub index: Vec<String>,
+    /// Default maximum buffer-size when reading messages into memory.
+    pub body_buffer_size: Option<usize>,
+    /// Maximum accepted request body size in bytes.
+    ///
+    /// Larger requests are rejected with 413 before being buffered.
+    pub max_body_size: Option<u64>,
+    /// Sanitizes error-messages produced by configured modules when enabled.
+    ///
+    /// Default is true
+    pub sanitize_errors: Option<bool>,
+    /// Allow CORS preflight requests to skip authentication and ratelimits.
+    ///
+    /// Default is false
+    pub preflight_bypass: bool,
+    /// Connection handling and keep-alive settings.
+    pub connections: ConnectionCfg,
+    /// Worker and runtime sizing settings.
+    pub runtime: RuntimeCfg,
+    /// Slow client (slowloris) protection settings.
+    pub slow_clients: SlowClientCfg,
+    /// Release responses whose client stops reading for longer than this.
+    ///
+    /// Disabled by default
+    pub stalled_client_timeout: Option<Duration>,
+    /// Configuration hash reporting settings.
+    pub config_hash: ConfigHashCfg,
+    /// Request/response metrics collection settings.
+    pub metrics: MetricsCfg,
+    /// External commands run on server lifecycle events.
+    pub hooks: HooksCfg,
+    /// Administrative control api settings.
+    ///
+    /// Only a single admin listener is spawned for the whole process
+    /// using the first enabled server that configures one.
+    pub admin: Option<AdminCfg>,
+    /// Request-scoped debug flags enabled by signed tokens.
+    pub debug_flags: Option<DebugFlagsCfg>,
+    /// Canonical host and scheme requests are redirected to.
+    pub canonical: Option<CanonicalCfg>,
+    /// Request phase timeouts for every directive.
+    pub timeouts: TimeoutsCfg,
+    /// Generated `/robots.txt` served ahead of every directive.
+    pub robots: Option<modules::robots::Config>,
+    /// On-demand certificate issuance for TLS listeners.
+    #[cfg(feature = "acme")]
+    pub on_demand_tls: Option<OnDemandTlsCfg>,
+    /// Load progress recorded by [`prepare_config`] and [`connect_config`].
+    #[serde(skip)]
+    pub load_state: LoadState,
+}
+
+/// Load progress of a server configuration.
+///
+/// Services are only assembled from configuration whose resources
+/// were loaded by [`prepare_config`] and [`connect_config`].
+#[derive(Clone, Debug, Default)]
+pub struct LoadState {
+    prepared: std::sync::OnceLock<()>,
+    connected: std::sync::OnceLock<()>,
+}
+
+/// Request-scoped debug flag settings.
+#[cfg_attr(feature = "schema", derive(JsonSchema))]
+#[derive(Clone, Debug, Default, Deserialize, Serialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct DebugFlagsCfg {
+    /// Shared secret used to sign and verify debug tokens.
+    pub secret: Secret,
+    /// Header carrying the debug token.
+    ///
+    /// Default is `X-Debug-Flags`
+    pub header: Option<String>,
+    /// Cookie carrying the debug token.
+    ///
+    /// Default is `debug_flags`
+    pub cookie: Option<String>,
+    /// Flags tokens are permitted to enable.
+    ///
+    /// Default is any flag
+    pub allowed: Vec<String>,
+}
+
+impl DebugFlagsCfg {
+    /// Produce [`crate::services::flags::DebugFlags`] from config.
+    pub fn factory(&self) -> crate::services::flags::DebugFlags {
+        let header = self.header.as_deref().unwrap_or("X-Debug-Flags");
+        crate::services::flags::DebugFlags::new(crate::services::flags::Settings {
+            secret: self.secret.0.clone(),
+            header: header::HeaderName::try_from(header).expect("debug flag header validated"),
+            cookie: self
+                .cookie
+                .clone()
+                .unwrap_or_else(|| "debug_flags".to_owned()),
+            allowed: self.allowed.clone(),
+        })
+    }
+
+    /// Validate debug flag settings.
+    fn validate(&self) -> Result<()> {
+        if self.secret.len() < 16 {
+            anyhow::bail!("debug_flags secret must be at least 16 characters");
+        }
+        if let Some(name) = self.header.as_deref() {
+            header::HeaderName::try_from(name)
+                .with_context(|| format!("invalid debug_flags header {name:?}"))?;
+        }
+        Ok(())
+    }
+}
+
+/// Administrative control api settings.
+#[cfg_attr(feature = "schema", derive(JsonSchema))]
+#[derive(Clone, Debug, Default, Deserialize, Serialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct AdminCfg {
+    /// Local address to bind admin api to.
+    ///
+    /// Default is 127.0.0.1:2019
+    pub listen: Option<String>,
+    /// Unix socket path to bind admin api to instead of an address.
+    pub socket: Option<PathBuf>,
+    /// Bearer token required by every endpoint except health checks.
+    ///
+    /// Required unless bound to a unix socket.
+    pub token: Option<Secret>,
+}
+
+impl AdminCfg {
+    #[inline]
+    pub fn listen(&self) -> &str {
+        self.listen.as_deref().unwrap_or("127.0.0.1:2019")
+    }
+
+    /// Check if the request carries the configured bearer token.
+    ///
+    /// Always true when no token is configured.
+    pub fn authorized(&self, req: &actix_web::HttpRequest) -> bool {
+        use sha2::{Digest, Sha256};
+        let Some(token) = self.token.as_ref() else {
+            return true;
+        };
+        let given = req
+            .headers()
+            .get(actix_web::http::header::AUTHORIZATION)
+            .and_then(|v| v.to_str().ok())
+            .and_then(|v| v.strip_prefix("Bearer "))
+            .unwrap_or_default();
+        // compare fixed length digests without exiting early
+        let (given, expected) = (
+            Sha256::digest(given.trim()),
+            Sha256::digest(token.as_bytes()),
+        );
+        given
+            .iter()
+            .zip(expected.iter())
+            .fold(0, |diff, (a, b)| diff | (a ^ b))
+            == 0
+    }
+}
+
+/// External commands run on server lifecycle events.
+#[cfg_attr(feature = "schema", derive(JsonSchema))]
+#[derive(Clone, Debug, Default, Deserialize, Serialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct HooksCfg {
+    /// Commands run before the configuration is reloaded.
+    pub pre_reload: Vec<HookCfg>,
+    /// Commands run after the configuration is reloaded.
+    pub post_reload: Vec<HookCfg>,
+    /// Commands run before the server begins shutting down.
+    pub pre_shutdown: Vec<HookCfg>,
+    /// Commands run after a TLS certificate is renewed.
+    pub cert_renewed: Vec<HookCfg>,
+}
+
+impl HooksCfg {
+    /// Retrieve hooks configured for the specified event.
+    pub fn get(&self, event: Event) -> &[HookCfg] {
+        match event {
+            Event::PreReload => &self.pre_reload,
+            Event::PostReload => &self.post_reload,
+            Event::PreShutdown => &self.pre_shutdown,
+            Event::CertRenewed => &self.cert_renewed,
+        }
+    }
+}
+
+/// Individual lifecycle hook command.
+#[cfg_attr(feature = "schema", derive(JsonSchema))]
+#[derive(Clone, Debug, Deserialize, Serialize)]
+#[serde(deny_unknown_fields)]
+pub struct HookCfg {
+    /// Command executed when the event occurs.
+    pub command: PathBuf,
+    /// Arguments passed to the command.
+    #[serde(default)]
+    pub args: Vec<String>,
+    /// Maximum time the command may run before it is killed.
+    ///
+    /// Default is 30s
+    #[serde(default)]
+    pub timeout: Option<Duration>,
+}
+
+/// Connection handling and keep-alive settings.
+///
+/// Settings apply to the whole process and must be the same for
+/// every server configuring them.
+#[cfg_attr(feature = "schema", derive(JsonSchema))]
+#[derive(Clone, Debug, Default, Deserialize, Serialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct ConnectionCfg {
+    /// Keep-alive duration for idle connections. `0s` disables keep-alive.
+    ///
+    /// Default is 5s
+    pub keep_alive: Option<Duration>,
+    /// Maximum number of concurrent connections per worker.
+    ///
+    /// Default is 25000
+    pub max_connections: Option<usize>,
+    /// Maximum number of concurrent TLS handshakes per worker.
+    ///
+    /// Default is 256
+    pub max_connection_rate: Option<usize>,
+}
+
+/// Worker and runtime sizing settings.
+///
+/// Settings apply to the whole process. When multiple servers
+/// configure the same setting the lowest value is used.
+#[cfg_attr(feature = "schema", derive(JsonSchema))]
+#[derive(Clone, Debug, Default, Deserialize, Serialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct RuntimeCfg {
+    /// Number of worker threads handling requests.
+    ///
+    /// Default is the number of physical cpu cores
+    pub workers: Option<usize>,
+    /// Maximum blocking threads per worker used for file and process io.
+    ///
+    /// Default is 512 divided by the number of workers
+    pub max_blocking_threads: Option<usize>,
+    /// Time given to workers to finish requests on graceful shutdown.
+    ///
+    /// Rounded up to whole seconds.
+    /// Default is 30s
+    pub shutdown_timeout: Option<Duration>,
+    /// File the process id is written to while running.
+    ///
+    /// Only the first enabled server configuring this is used.
+    pub pid_file: Option<PathBuf>,
+    /// Time connections are drained for before exiting on drain requests.
+    ///
+    /// Applies to the whole process using the lowest configured value.
+    /// Default is 15s
+    pub drain_period: Option<Duration>,
+    /// Path serving readiness status on the server listeners.
+    ///
+    /// Responds `503 Service Unavailable` once draining begins.
+    pub readiness_path: Option<String>,
+}
+
+/// Slow client (slowloris) protection settings.
+#[cfg_attr(feature = "schema", derive(JsonSchema))]
+#[derive(Clone, Debug, Default, Deserialize, Serialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct SlowClientCfg {
+    /// Maximum time allowed to receive request headers.
+    ///
+    /// Applies to the whole process and must be the same for every
+    /// server configuring it.
+    /// Default is 5s
+    #[serde(alias = "client_request_timeout")]
+    pub header_timeout: Option<Duration>,
+    /// Maximum time allowed to receive the complete request body.
+    pub body_timeout: Option<Duration>,
+    /// Minimum request body transfer-rate in bytes per second.
+    pub min_body_rate: Option<u64>,
+    /// Time allowed before the minimum transfer-rate is enforced.
+    ///
+    /// Default is 5s
+    pub min_rate_grace: Option<Duration>,
+}
+
+impl SlowClientCfg {
+    /// Check if request body enforcement is enabled.
+    #[inline]
+    pub fn enforce_body(&self) -> bool {
+        self.body_timeout.is_some() || self.min_body_rate.is_some()
+    }
+}
+
+/// Request/response metrics collection settings.
+#[cfg_attr(feature = "schema", derive(JsonSchema))]
+#[derive(Clone, Debug, Default, Deserialize, Serialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct MetricsCfg {
+    /// Track request and response body size histograms per directive.
+    ///
+    /// Default is false
+    pub body_sizes: bool,
+    /// Log requests whose request or response body exceeds this many bytes.
+    pub log_larger_than: Option<u64>,
+}
+
+/// Configuration hash reporting settings.
+///
+/// The hash covers the complete effective configuration
+/// of every server and is identical across all servers.
+#[cfg_attr(feature = "schema", derive(JsonSchema))]
+#[derive(Clone, Debug, Default, Deserialize, Serialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct ConfigHashCfg {
+    /// Serve configuration hash at `/-/config-hash` if enabled.
+    ///
+    /// Default is false
+    pub endpoint: bool,
+    /// Response header to report configuration hash within.
+    pub header: Option<String>,
+}
+
+/// Sensitive configuration value redacted from debug and serialized output.
+///
+/// Only [`config_hash`] sees a digest of the value, so the
+/// configuration hash still changes whenever the secret is rotated.
+#[cfg_attr(feature = "schema", derive(JsonSchema))]
+#[derive(Clone, Default, Deserialize)]
+#[serde(transparent)]
+pub struct Secret(pub String);
+
+impl std::ops::Deref for Secret {
+    type Target = str;
+
+    #[inline]
+    fn deref(&self) -> &Self::Target {
+        &self.0
+    }
+}
+
+impl std::fmt::Debug for Secret {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str("<redacted>")
+    }
+}
+
+thread_local! {
+    /// Set while [`config_hash`] serializes the configuration.
+    static HASHING: std::cell::Cell<bool> = const { std::cell::Cell::new(false) };
+}
+
+impl Serialize for Secret {
+    /// Serialize as `<redacted>` or as a digest while hashing the configuration.
+    fn serialize<S>(&self, serializer: S) -> std::result::Result<S::Ok, S::Error>
+    where
+        S: serde::Serializer,
+    {
+        if !HASHING.get() {
+            return serializer.serialize_str("<redacted>");
+        }
+        use sha2::{Digest, Sha256};
+        let digest = Sha256::digest(self.0.as_bytes());
+        serializer.collect_str(&format_args!("{digest:x}"))
+    }
+}
+
+/// Compute deterministic hash of the effective loaded configuration.
+///
+/// Hashes the serialized configuration so runtime state skipped by
+/// serde is ignored while secrets contribute a digest of their value.
+pub fn config_hash(configs: &[ServerConfig]) -> String {
+    use sha2::{Digest, Sha256};
+    HASHING.set(true);
+    let encoded = serde_json::to_vec(configs);
+    HASHING.set(false);
+    let digest = Sha256::digest(encoded.expect("config serialization failed"));
+    digest.iter().map(|b| format!("{b:02x}")).collect()
+}
+
+impl ServerConfig {
+    /// Check if the configuration was prepared and connected.
+    pub fn loaded(&self) -> Result<()> {
+        if self.load_state.prepared.get().is_none() {
+            anyhow::bail!("configuration was not prepared with prepare_config");
+        }
+        if self.load_state.connected.get().is_none() {
+            anyhow::bail!("configuration was not connected with connect_config");
+        }
+        Ok(())
+    }
+
+    /// Check if the server is reachable through the labeled listener.
+    pub fn attached(&self, label: Option<&str>) -> bool {
+        self.listeners.is_empty()
+            || label.is_some_and(|label| self.listeners.iter().any(|l| l == label))
+    }
+
+    /// Iterate all directive components with their config path.
+    fn all_components(&self) -> impl Iterator<Item = (String, &Component)> {
+        self.directives
+            .iter()
+            .enumerate()
+            .flat_map(|(d, directive)| {
+                let construct =
+                    directive
+                        .construct
+                        .iter()
+                        .enumerate()
+                        .map(move |(c, component)| {
+                            (format!(".directives[{d}].construct[{c}]"), component)
+                        });
+                let fallback = directive.fallback.iter().flat_map(move |fallback| {
+                    fallback.iter().enumerate().map(move |(c, component)| {
+                        (format!(".directives[{d}].fallback[{c}]"), component)
+                    })
+                });
+                construct.chain(fallback)
+            })
+    }
+
+    /// Iterate all server and directive middleware with their config path.
+    pub fn all_middleware(&self) -> impl Iterator<Item = (String, &Middleware)> {
+        let server = self
+            .middleware
+            .iter()
+            .enumerate()
+            .map(|(i, m)| (format!(".middleware[{i}]"), m));
+        let directives = self
+            .all_components()
+            .filter_map(|(path, component)| Some((path, component.middleware()?)));
+        server.chain(directives)
+    }
+
+    /// Iterate all directive modules with their config path.
+    pub fn all_modules(&self) -> impl Iterator<Item = (String, &ModuleConfig)> {
+        self.all_components()
+            .filter_map(|(path, component)| match component {
+                Component::Module(m) => Some((path, &m.module)),
+                _ => None,
+            })
+    }
+
+    /// Iterate all reverse-proxy upstreams with their directive location.
+    #[cfg(feature = "rproxy")]
+    pub fn upstreams(&self) -> impl Iterator<Item = (String, UpstreamAddr)> {
+        self.directives.iter().flat_map(|directive| {
+            let location = directive.location.clone().unwrap_or_else(|| "/".to_owned());
+            directive.construct.iter().flat_map(move |c| match c {
+                Component::Module(Module {
+                    module: ModuleConfig::ReverseProxy(proxy),
+                    ..
+                }) => vec![(location.clone(), proxy.resolve.clone())],
+                Component::Module(Module {
+                    module: ModuleConfig::Pool(pool),
+                    ..
+                }) => pool
+                    .upstreams
+                    .iter()
+                    .map(|upstream| (location.clone(), upstream.uri().clone().into()))
+                    .collect(),
+                _ => vec![],
+            })
+        })
+    }
+}
+
+/// Logging level configuration
+#[derive(Clone, Debug)]
+pub struct LogLevel(pub log::Level);
+
+impl std::fmt::Display for LogLevel {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}", self.0)
+    }
+}
+
+#[cfg(feature = "schema")]
+impl JsonSchema for LogLevel {
+    fn schema_name() -> std::borrow::Cow<'static, str> {
+        "LogLevel".into()
+    }
+    fn schema_id() -> std::borrow::Cow<'static, str> {
+        concat!(module_path!(), "::LogLevel").into()
+    }
+    fn json_schema(_gen: &mut schemars::SchemaGenerator) -> schemars::Schema {
+        schemars::json_schema!({ "type": "string" })
+    }
+}
+
+impl FromStr for LogLevel {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> std::result::Result<Self, Self::Err> {
+        Ok(Self(log::Level::from_str(s)?))
+    }
+}
+
+/// Logging level filter configuration
+#[derive(Clone, Debug)]
+pub struct LogFilter(pub log::LevelFilter);
+
+impl std::fmt::Display for LogFilter {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}", self.0)
+    }
+}
+
+#[cfg(feature = "schema")]
+impl JsonSchema for LogFilter {
+    fn schema_name() -> std::borrow::Cow<'static, str> {
+        "LogFilter".into()
+    }
+    fn schema_id() -> std::borrow::Cow<'static, str> {
+        concat!(module_path!(), "::LogFilter").into()
+    }
+    fn json_schema(_gen: &mut schemars::SchemaGenerator) -> schemars::Schema {
+        schemars::json_schema!({ "type": "string" })
+    }
+}
+
+impl FromStr for LogFilter {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> std::result::Result<Self, Self::Err> {
+        Ok(Self(log::LevelFilter::from_str(s)?))
+    }
+}
+
+/// Logging Configuration settings
+#[cfg_attr(feature = "schema", derive(JsonSchema))]
+#[derive(Clone, Debug, Default, Deserialize, Serialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct LoggingCfg {
+    /// Disable logging if set to true
+    pub disable: bool,
+    /// Logging level attached to log.
+    ///
+    /// Default is INFO
+    pub log_level: Option<LogLevel>,
+    /// Custom [`actix_web::middleware::Logger`] format string.
+    ///
+    /// Supports `%{ip}xo`, `%{user}xi`, `%{tls_sni}xi`, `%{tls_alpn}xi`,
+    /// `%{tls_version}xi`, `%{tls_cipher}xi`, `%{geo_country}xo` and
+    /// `%{geo_asn}xo` in addition to the standard placeholders.
+    pub format: Option<String>,
+    /// Write access logs into per-host files instead of the process log.
+    ///
+    /// `{server_name}` within the path is replaced with the request host
+    /// (such as `/var/log/bob/{server_name}.access.log`).
+    pub access_log: Option<String>,
+    /// Request path globs never written to the access log.
+    pub skip_paths: Vec<PathMatch>,
+    /// Only log 1 in every N successful (below 400) requests.
+    ///
+    /// Requests answered with an error status are always logged. Sampled
+    /// entries use the access log line format, so a custom `format` is
+    /// rejected unless `access_log` is also set.
+    /// Default is every request
+    pub sample: Option<u64>,
+    /// Use IpWare Middleware RealIP if enabled.
+    ///
+    /// Default is true
+    #[cfg(feature = "ipware")]
+    pub use_ipware: Option<bool>,
+    /// Anonymize client identifiers within access logs.
+    pub anonymize: Option<AnonymizeCfg>,
+    /// Process-wide application log output settings.
+    ///
+    /// Only the first enabled server configuring this is used
+    /// and changes require a restart to apply.
+    pub process: Option<ProcessLogCfg>,
+}
+
+/// Process-wide application log output settings.
+#[cfg_attr(feature = "schema", derive(JsonSchema))]
+#[derive(Clone, Debug, Default, Deserialize, Serialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct ProcessLogCfg {
+    /// Log output destination (`stderr`, `stdout` or a file path).
+    ///
+    /// Default is stderr
+    pub target: LogTarget,
+    /// Default log level for all modules.
+    ///
+    /// Default is WARN
+    pub level: Option<LogFilter>,
+    /// Log level overrides for specific modules.
+    ///
+    /// Default is `bob` and `actix_web::middleware::logger` at INFO
+    pub modules: BTreeMap<String, LogFilter>,
+    /// Log line format.
+    ///
+    /// Default is pretty
+    pub format: LogFormat,
+    /// Colorize log output.
+    ///
+    /// Default is auto
+    pub color: LogColor,
+    /// Include the logging module within each line.
+    ///
+    /// Default is false
+    pub show_module: bool,
+}
+
+/// Log output destination.
+#[derive(Clone, Debug, Default)]
+pub enum LogTarget {
+    #[default]
+    Stderr,
+    Stdout,
+    File(PathBuf),
+}
+
+impl std::fmt::Display for LogTarget {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::Stderr => write!(f, "stderr"),
+            Self::Stdout => write!(f, "stdout"),
+            Self::File(path) => write!(f, "{}", path.display()),
+        }
+    }
+}
+
+#[cfg(feature = "schema")]
+impl JsonSchema for LogTarget {
+    fn schema_name() -> std::borrow::Cow<'static, str> {
+        "LogTarget".into()
+    }
+    fn schema_id() -> std::borrow::Cow<'static, str> {
+        concat!(module_path!(), "::LogTarget").into()
+    }
+    fn json_schema(_gen: &mut schemars::SchemaGenerator) -> schemars::Schema {
+        schemars::json_schema!({ "type": "string" })
+    }
+}
+
+impl FromStr for LogTarget {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> std::result::Result<Self, Self::Err> {
+        Ok(match s {
+            "stderr" => Self::Stderr,
+            "stdout" => Self::Stdout,
+            "" => return Err(anyhow!("empty log target")),
+            path => Self::File(PathBuf::from(path)),
+        })
+    }
+}
+
+/// Log line format.
+#[cfg_attr(feature = "schema", derive(JsonSchema))]
+#[derive(Clone, Debug, Default, Deserialize, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum LogFormat {
+    /// Timestamped human readable lines.
+    #[default]
+    Pretty,
+    /// Single json object per line.
+    Json,
+    /// Level and message only.
+    Compact,
+}
+
+/// Log output color control.
+#[cfg_attr(feature = "schema", derive(JsonSchema))]
+#[derive(Clone, Debug, Default, Deserialize, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum LogColor {
+    /// Colorize when writing to a terminal.
+    #[default]
+    Auto,
+    Always,
+    Never,
+}
+
+/// Access log anonymization settings.
+#[cfg_attr(feature = "schema", derive(JsonSchema))]
+#[derive(Clone, Debug, Deserialize, Serialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct AnonymizeCfg {
+    /// Number of leading IPv4 address bits kept.
+    ///
+    /// Default is 24 (zero the last octet)
+    pub ipv4_prefix: u8,
+    /// Number of leading IPv6 address bits kept.
+    ///
+    /// Default is 48 (zero the last 80 bits)
+    pub ipv6_prefix: u8,
+    /// Log hashed user identifiers for authenticated requests.
+    pub hash_users: bool,
+    /// Salt used when hashing user identifiers.
+    pub salt: Option<Secret>,
+}
+
+impl Default for AnonymizeCfg {
+    fn default() -> Self {
+        Self {
+            ipv4_prefix: 24,
+            ipv6_prefix: 48,
+            hash_users: false,
+            salt: None,
+        }
+    }
+}
+
+impl AnonymizeCfg {
+    /// Build anonymizer instance from configuration.
+    pub fn anonymizer(&self) -> Anonymizer {
+        Anonymizer {
+            ipv4_prefix: self.ipv4_prefix,
+            ipv6_prefix: self.ipv6_prefix,
+            hash_users: self.hash_users,
+            salt: self.salt.as_deref().unwrap_or_default().to_owned(),
+        }
+    }
+}
+
+/// Compilation of references to config specifications
+pub struct Spec<'a> {
+    pub config: &'a ServerConfig,
+    /// Effective timeouts for the components being built.
+    pub timeouts: TimeoutsCfg,
+}
+
+impl<'a> Spec<'a> {
+    /// Specification using the server level settings.
+    pub fn new(config: &'a ServerConfig) -> Self {
+        Self {
+            config,
+            timeouts: config.timeouts.clone(),
+        }
+    }
+
+    /// Specification for components within the directive.
+    pub fn directive(config: &'a ServerConfig, directive: &DirectiveCfg) -> Self {
+        let timeouts = match directive.timeouts.as_ref() {
+            Some(timeouts) => timeouts.or(&config.timeouts),
+            None => config.timeouts.clone(),
+        };
+        Self { config, timeouts }
+    }
+}
+
+/// Split host header value into its name and optional port.
+///
+/// Brackets surrounding IPv6 addresses are removed from the name.
+pub fn split_host(host: &str) -> (&str, Option<u16>) {
+    let (name, port) = match host.strip_prefix('[') {
+        Some(v6) => match v6.split_once(']') {
+            Some((addr, rest)) => (addr, rest.strip_prefix(':')),
+            None => (v6, None),
+        },
+        None => match host.split_once(':') {
+            Some((name, port)) => (name, Some(port)),
+            None => (host, None),
+        },
+    };
+    (name, port.and_then(|p| p.parse().ok()))
+}
+
+/// Retrieve requested host from the `Host` header.
+///
+/// Falls back to the URI authority for HTTP/2 requests
+/// that only carry the `:authority` pseudo-header.
+pub fn request_host(head: &actix_web::dev::RequestHead) -> Option<&str> {
+    match head.headers.get(header::HOST) {
+        Some(host) => host.to_str().ok(),
+        None => head.uri.authority().map(|a| a.as_str()),
+    }
+}
+
+/// Domain matcher expression.
+///
+/// Uses glob syntax and matches case-insensitively.
+#[derive(Debug, Clone)]
+pub struct DomainMatch(pub glob::Pattern);
+
+impl DomainMatch {
+    /// Check if the domain name (without port) matches the expression.
+    pub fn matches(&self, name: &str) -> bool {
+        let options = glob::MatchOptions {
+            case_sensitive: false,
+            ..Default::default()
+        };
+        self.0.matches_with(name.trim_end_matches('.'), options)
+    }
+}
+
+impl std::fmt::Display for DomainMatch {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}", self.0.as_str())
+    }
+}
+
+impl Guard for DomainMatch {
+    fn check(&self, ctx: &actix_web::guard::GuardContext<'_>) -> bool {
+        request_host(ctx.head()).is_some_and(|host| self.matches(split_host(host).0))
+    }
+}
+
+/// Guard matching requests against all configured server names.
+///
+/// When ports are given, requests naming an explicit port in the
+/// `Host` must use one of them.
+#[derive(Debug, Clone)]
+pub struct ServerNameGuard {
+    pub names: Vec<DomainMatch>,
+    pub ports: Vec<u16>,
+}
+
+impl Guard for ServerNameGuard {
+    fn check(&self, ctx: &actix_web::guard::GuardContext<'_>) -> bool {
+        let Some((name, port)) = request_host(ctx.head()).map(split_host) else {
+            return false;
+        };
+        if let Some(port) = port
+            && !self.ports.is_empty()
+            && !self.ports.contains(&port)
+        {
+            return false;
+        }
+        self.names.iter().any(|d| d.matches(name))
+    }
+}
+
+impl FromStr for DomainMatch {
+    type Err = glob::PatternError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let glob = glob::Pattern::new(s)?;
+        Ok(Self(glob))
+    }
+}
+
+#[cfg(feature = "schema")]
+impl JsonSchema for DomainMatch {
+    fn schema_name() -> std::borrow::Cow<'static, str> {
+        "DomainMatch".into()
+    }
+    fn schema_id() -> std::borrow::Cow<'static, str> {
+        concat!(module_path!(), "::DomainMatch").into()
+    }
+    fn json_schema(_gen: &mut schemars::SchemaGenerator) -> schemars::Schema {
+        schemars::json_schema!({ "type": "string" })
+    }
+}
+
+/// IP address matcher expression.
+///
+/// Supports single addresses (`10.0.0.1`), CIDR notation
+/// (`10.0.0.0/8`, `2001:db8::/32`) and inclusive ranges
+/// (`10.0.0.1-10.0.0.20`).
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct IpMatch {
+    start: u128,
+    end: u128,
+    ipv4: bool,
+}
+
+impl IpMatch {
+    /// Check if address is contained within the match expression.
+    pub fn contains(&self, ip: &IpAddr) -> bool {
+        let (ipv4, bits) = ip_bits(ip);
+        self.ipv4 == ipv4 && self.start <= bits && bits <= self.end
+    }
+}
+
+/// Convert address into (is-ipv4, numeric-value).
+#[inline]
+fn ip_bits(ip: &IpAddr) -> (bool, u128) {
+    match ip.to_canonical() {
+        IpAddr::V4(ip) => (true, ip.to_bits() as u128),
+        IpAddr::V6(ip) => (false, ip.to_bits()),
+    }
+}
+
+impl FromStr for IpMatch {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let s = s.trim();
+        if let Some((start, end)) = s.split_once('-') {
+            let (v4, start) = ip_bits(&IpAddr::from_str(start.trim())?);
+            let (v4e, end) = ip_bits(&IpAddr::from_str(end.trim())?);
+            if v4 != v4e || start > end {
+                return Err(anyhow!("invalid ip range: {s:?}"));
+            }
+            return Ok(Self {
+                start,
+                end,
+                ipv4: v4,
+            });
+        }
+        let (addr, prefix) = match s.split_once('/') {
+            Some((addr, prefix)) => (addr, Some(prefix.parse::<u32>()?)),
+            None => (s, None),
+        };
+        let (ipv4, bits) = ip_bits(&IpAddr::from_str(addr)?);
+        let width = if ipv4 { 32 } else { 128 };
+        let prefix = prefix.unwrap_or(width);
+        if prefix > width {
+            return Err(anyhow!("invalid cidr prefix: {s:?}"));
+        }
+        let host = width - prefix;
+        let mask = match host {
+            128 => u128::MAX,
+            host => (1u128 << host) - 1,
+        };
+        Ok(Self {
+            start: bits & !mask,
+            end: bits | mask,
+            ipv4,
+        })
+    }
+}
+
+impl std::fmt::Display for IpMatch {
+    /// Format as a single address, CIDR block or range.
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let addr = |bits: u128| match self.ipv4 {
+            true => IpAddr::V4(Ipv4Addr::from_bits(bits as u32)),
+            false => IpAddr::V6(Ipv6Addr::from_bits(bits)),
+        };
+        let span = self.end - self.start;
+        let aligned = span.checked_add(1).is_none_or(|n| n.is_power_of_two());
+        match (span, aligned && self.start & span == 0) {
+            (0, _) => write!(f, "{}", addr(self.start)),
+            (_, true) => {
+                let width = if self.ipv4 { 32 } else { 128 };
+                write!(f, "{}/{}", addr(self.start), width - span.count_ones())
+            }
+            (_, false) => write!(f, "{}-{}", addr(self.start), addr(self.end)),
+        }
+    }
+}
+
+#[cfg(feature = "schema")]
+impl JsonSchema for IpMatch {
+    fn schema_name() -> std::borrow::Cow<'static, str> {
+        "IpMatch".into()
+    }
+    fn schema_id() -> std::borrow::Cow<'static, str> {
+        concat!(module_path!(), "::IpMatch").into()
+    }
+    fn json_schema(_gen: &mut schemars::SchemaGenerator) -> schemars::Schema {
+        schemars::json_schema!({ "type": "string" })
+    }
+}
+
+/// Request path matcher expression.
+///
+/// Uses glob syntax.
+#[derive(Debug, Clone)]
+pub struct PathMatch(pub glob::Pattern);
+
+impl PathMatch {
+    #[inline]
+    pub fn matches(&self, path: &str) -> bool {
+        self.0.matches(path)
+    }
+
+    /// Check if a raw request path matches without ambiguity.
+    ///
+    /// `*` never matches across `/` and only paths already in
+    /// normal form (see [`normalize_path`]) can match, so encoded or
+    /// dot-segment variants of a matching path are never granted an
+    /// exemption.
+    pub fn matches_strict(&self, path: &str) -> bool {
+        normalize_path(path).is_some_and(|normal| normal == path)
+            && self.0.matches_with(path, STRICT_MATCH)
+    }
+
+    /// Check if the normal form of a raw request path matches.
+    ///
+    /// Paths that cannot be normalized always match so that they
+    /// stay covered by protections.
+    pub fn matches_normalized(&self, path: &str) -> bool {
+        normalize_path(path).is_none_or(|normal| self.0.matches(&normal))
+    }
+}
+
+/// Glob options used when matching request paths for access control.
+const STRICT_MATCH: glob::MatchOptions = glob::MatchOptions {
+    case_sensitive: true,
+    require_literal_separator: true,
+    require_literal_leading_dot: false,
+};
+
+/// Normalize a raw request path.
+///
+/// Percent-decodes the path, treats `\` as a separator, drops empty
+/// and `.` segments and resolves `..` segments. Returns `None` when
+/// the path is not valid UTF-8 once decoded or escapes the root.
+pub fn normalize_path(path: &str) -> Option<String> {
+    let decoded = percent_encoding::percent_decode_str(path)
+        .decode_utf8()
+        .ok()?;
+    let mut segments: Vec<&str> = Vec::new();
+    for segment in decoded.split(['/', '\\']) {
+        match segment {
+            "" | "." => {}
+            ".." => {
+                segments.pop()?;
+            }
+            segment => segments.push(segment),
+        }
+    }
+    let trailing = decoded.ends_with(['/', '\\']) && !segments.is_empty();
+    let mut normal = format!("/{}", segments.join("/"));
+    if trailing {
+        normal.push('/');
+    }
+    Some(normal)
+}
+
+impl FromStr for PathMatch {
+    type Err = glob::PatternError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        Ok(Self(glob::Pattern::new(s)?))
+    }
+}
+
+impl std::fmt::Display for PathMatch {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}", self.0.as_str())
+    }
+}
+
+#[cfg(feature = "schema")]
+impl JsonSchema for PathMatch {
+    fn schema_name() -> std::borrow::Cow<'static, str> {
+        "PathMatch".into()
+    }
+    fn schema_id() -> std::borrow::Cow<'static, str> {
+        concat!(module_path!(), "::PathMatch").into()
+    }
+    fn json_schema(_gen: &mut schemars::SchemaGenerator) -> schemars::Schema {
+        schemars::json_schema!({ "type": "string" })
+    }
+}
+
+/// Reverse-proxy upstream address.
+///
+/// Either an http(s) url or `unix:` followed by a unix socket path.
+#[derive(Debug, Clone)]
+pub enum UpstreamAddr {
+    Http(Uri),
+    Unix(PathBuf),
+}
+
+impl UpstreamAddr {
+    /// Url requests are built against.
+    ///
+    /// Unix socket upstreams are addressed as `http://localhost`.
+    pub fn uri(&self) -> actix_web::http::Uri {
+        match self {
+            Self::Http(uri) => uri.0.clone(),
+            Self::Unix(_) => actix_web::http::Uri::from_static("http://localhost"),
+        }
+    }
+}
+
+impl std::fmt::Display for UpstreamAddr {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::Http(uri) => write!(f, "{}", uri.0),
+            Self::Unix(path) => write!(f, "unix:{}", path.display()),
+        }
+    }
+}
+
+impl From<Uri> for UpstreamAddr {
+    fn from(value: Uri) -> Self {
+        Self::Http(value)
+    }
+}
+
+impl FromStr for UpstreamAddr {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.strip_prefix("unix:") {
+            Some("") => Err("empty unix socket path".to_owned()),
+            Some(path) => Ok(Self::Unix(PathBuf::from(path))),
+            None => Ok(Self::Http(Uri::from_str(s)?)),
+        }
+    }
+}
+
+#[cfg(feature = "schema")]
+impl JsonSchema for UpstreamAddr {
+    fn schema_name() -> std::borrow::Cow<'static, str> {
+        "UpstreamAddr".into()
+    }
+    fn schema_id() -> std::borrow::Cow<'static, str> {
+        concat!(module_path!(), "::UpstreamAddr").into()
+    }
+    fn json_schema(_gen: &mut schemars::SchemaGenerator) -> schemars::Schema {
+        schemars::json_schema!({ "type": "string" })
+    }
+}
+
+/// TLS Configuration for server listener.
+///
+/// Certificates may only be omitted when issued on demand.
+#[cfg_attr(feature = "schema", derive(JsonSchema))]
+#[derive(Debug, Clone, Default, Deserialize, Serialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct SSLCfg {
+    /// TLS Certificate public key.
+    pub certificate: Option<PathBuf>,
+    /// TLS Certificate private key.
+    ///
+    /// May be an encrypted PEM or a `.p12`/`.pfx` bundle which
+    /// also provides the certificate chain.
+    pub certificate_key: Option<PathBuf>,
+    /// Passphrase used to decrypt the private key.
+    #[cfg(feature = "keystore")]
+    pub key_passphrase: Option<PassphraseSource>,
+    /// Certificate store every certificate is discovered from.
+    ///
+    /// Certificates are selected by the server names they cover.
+    pub store: Option<CertStoreCfg>,
+    /// Staple OCSP responses to the listener certificates.
+    #[cfg(feature = "ocsp")]
+    pub ocsp: Option<OcspCfg>,
+}
+
+/// Private key passphrase source.
+#[cfg(feature = "keystore")]
+#[cfg_attr(feature = "schema", derive(JsonSchema))]
+#[derive(Debug, Clone, Default, Deserialize, Serialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct PassphraseSource {
+    /// Filepath to read the passphrase from.
+    file: Option<PathBuf>,
+    /// Environment variable to read the passphrase from.
+    env: Option<String>,
+}
+
+#[cfg(feature = "keystore")]
+impl PassphraseSource {
+    /// Load passphrase from its source.
+    ///
+    /// A single trailing newline is removed from passphrase files.
+    pub fn load(&self) -> Result<String> {
+        match (self.file.as_ref(), self.env.as_ref()) {
+            (Some(path), None) => {
+                let passphrase = std::fs::read_to_string(path)
+                    .with_context(|| format!("failed to read key passphrase {path:?}"))?;
+                let passphrase = passphrase.strip_suffix('\n').unwrap_or(&passphrase);
+                Ok(passphrase
+                    .strip_suffix('\r')
+                    .unwrap_or(passphrase)
+                    .to_owned())
+            }
+            (None, Some(env)) => {
+                std::env::var(env).with_context(|| format!("missing key passphrase env {env:?}"))
+            }
+            _ => Err(anyhow!("key passphrase requires either a file or env")),
+        }
+    }
+}
+
+/// OCSP stapling settings for listener certificates.
+#[cfg(feature = "ocsp")]
+#[cfg_attr(feature = "schema", derive(JsonSchema))]
+#[derive(Debug, Clone, Default, Deserialize, Serialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct OcspCfg {
+    /// Interval between OCSP response refreshes.
+    ///
+    /// Default is 1h
+    pub refresh: Option<Duration>,
+    /// Time allowed for the OCSP responder to reply.
+    ///
+    /// Default is 10s
+    pub timeout: Option<Duration>,
+}
+
+#[cfg(feature = "ocsp")]
+impl OcspCfg {
+    /// Produce [`crate::tls::ocsp::Settings`] from config.
+    pub fn settings(&self) -> crate::tls::ocsp::Settings {
+        crate::tls::ocsp::Settings {
+            refresh: default_duration(&self.refresh, 60 * 60),
+            timeout: default_duration(&self.timeout, 10),
+        }
+    }
+}
+
+/// TLS certificate storage backend.
+#[cfg_attr(feature = "schema", derive(JsonSchema))]
+#[derive(Debug, Clone, Deserialize, Serialize)]
+#[serde(tag = "backend", rename_all = "snake_case", deny_unknown_fields)]
+pub enum CertStoreCfg {
+    /// Files within a local directory.
+    Directory {
+        /// Directory containing certificates and keys.
+        path: PathBuf,
+    },
+    /// Strings within a redis database shared between instances.
+    Redis {
+        /// Redis url as `redis[s]://[:password@]host[:port][/db]`.
+        url: Secret,
+        /// Prefix added to every stored key.
+        ///
+        /// Default is `bob:certs:`
+        #[serde(default)]
+        prefix: Option<String>,
+        /// Time allowed for every redis operation.
+        ///
+        /// Default is 5s
+        #[serde(default)]
+        timeout: Option<Duration>,
+    },
+    /// External program invoked for every operation.
+    Command {
+        /// Program run as `<command> [args] get|put|list [key]`.
+        command: PathBuf,
+        /// Arguments passed ahead of the operation.
+        #[serde(default)]
+        args: Vec<String>,
+        /// Time allowed for every invocation.
+        ///
+        /// Default is 10s
+        #[serde(default)]
+        timeout: Option<Duration>,
+    },
+}
+
+impl CertStoreCfg {
+    /// Open the configured [`crate::tls::store::CertStore`].
+    pub fn build(&self) -> Result<Arc<dyn crate::tls::store::CertStore>> {
+        use crate::tls::store::{CommandStore, DirectoryStore, RedisStore};
+        Ok(match self {
+            Self::Directory { path } => Arc::new(
+                DirectoryStore::new(path.to_owned())
+                    .with_context(|| format!("failed to open certificate store {path:?}"))?,
+            ),
+            Self::Redis {
+                url,
+                prefix,
+                timeout,
+            } => Arc::new(RedisStore::new(
+                &url.0,
+                prefix.clone().unwrap_or_else(|| "bob:certs:".to_owned()),
+                default_duration(timeout, 5),
+            )?),
+            Self::Command {
+                command,
+                args,
+                timeout,
+            } => Arc::new(CommandStore::new(
+                command.to_owned(),
+                args.clone(),
+                default_duration(timeout, 10),
+            )),
+        })
+    }
+}
+
+/// On-demand TLS certificate issuance settings.
+///
+/// Certificates are issued through ACME during the first TLS handshake
+/// for an allowed server name without a configured certificate.
+#[cfg(feature = "acme")]
+#[cfg_attr(feature = "schema", derive(JsonSchema))]
+#[derive(Debug, Clone, Deserialize, Serialize)]
+#[serde(deny_unknown_fields)]
+pub struct OnDemandTlsCfg {
+    /// Server names certificates may be issued for.
+    ///
+    /// Uses glob syntax.
+    pub allow: Vec<DomainMatch>,
+    /// ACME directory url of the certificate authority.
+    ///
+    /// Default is Let's Encrypt production
+    #[serde(default)]
+    pub directory: Option<String>,
+    /// Contact email registered with the ACME account.
+    #[serde(default)]
+    pub email: Option<String>,
+    /// Store certificates and account credentials are kept in.
+    ///
+    /// Default is the `acme` directory
+    #[serde(default)]
+    pub storage: Option<CertStoreCfg>,
+    /// Url asked to approve every name before a certificate is issued.
+    ///
+    /// Called as `<ask>?domain=<name>`, any 2xx status approves the name.
+    #[serde(default)]
+    pub ask: Option<String>,
+    /// Maximum certificates issued within `issuance_window`.
+    ///
+    /// Default is 10
+    #[serde(default)]
+    pub max_issuances: Option<usize>,
+    /// Maximum certificates issued for a single name within `issuance_window`.
+    ///
+    /// Default is 3
+    #[serde(default)]
+    pub max_name_issuances: Option<usize>,
+    /// Window used to rate limit issuance.
+    ///
+    /// Default is 1h
+    #[serde(default)]
+    pub issuance_window: Option<Duration>,
+    /// Time before retrying a failed issuance for the same name.
+    ///
+    /// Default is 10m
+    #[serde(default)]
+    pub retry_after: Option<Duration>,
+    /// Renew certificates once they expire within this period.
+    ///
+    /// Default is 30days
+    #[serde(default)]
+    pub renew_before: Option<Duration>,
+    /// Request the `www` or apex counterpart and the server's other literal
+    /// names (including the `canonical` host) on the same certificate.
+    ///
+    /// Names redirected by the server's `canonical` host are bundled with
+    /// the canonical host rather than their `www` or apex counterpart.
+    ///
+    /// Only names permitted by `allow` are bundled.
+    /// Default is false
+    #[serde(default)]
+    pub bundle_aliases: bool,
+}
+
+#[cfg(feature = "acme")]
+impl OnDemandTlsCfg {
+    /// Validate allowlist and directory.
+    fn validate(&self) -> Result<()> {
+        if self.allow.is_empty() {
+            anyhow::bail!("on_demand_tls requires at least one allowed name");
+        }
+        if let Some(directory) = self.directory.as_deref()
+            && !directory.starts_with("https://")
+        {
+            anyhow::bail!("on_demand_tls directory {directory:?} must use https");
+        }
+        if let Some(ask) = self.ask.as_deref()
+            && !ask.starts_with("http://")
+            && !ask.starts_with("https://")
+        {
+            anyhow::bail!("on_demand_tls ask {ask:?} must be an http(s) url");
+        }
+        if self.max_issuances == Some(0) {
+            anyhow::bail!("on_demand_tls max_issuances must be greater than zero");
+        }
+        if self.max_name_issuances == Some(0) {
+            anyhow::bail!("on_demand_tls max_name_issuances must be greater than zero");
+        }
+        Ok(())
+    }
+
+    /// Produce [`crate::tls::acme::Settings`] from the server config.
+    pub fn settings(&self, server: &ServerConfig) -> Result<crate::tls::acme::Settings> {
+        let normalize = |name: &str| {
+            split_host(name)
+                .0
+                .trim_end_matches('.')
+                .to_ascii_lowercase()
+        };
+        let canonical = server
+            .canonical
+            .as_ref()
+            .and_then(|c| c.host.as_deref())
+            .map(normalize);
+        let mut aliases: Vec<String> = server
+            .server_name
+            .iter()
+            .map(|name| normalize(&name.0))
+            .chain(canonical.clone())
+            .filter(|name| !name.is_empty() && !name.contains(['*', '?', '[']))
+            .collect();
+        aliases.sort();
+        aliases.dedup();
+        let storage = self.storage.clone().unwrap_or(CertStoreCfg::Directory {
+            path: PathBuf::from("acme"),
+        });
+        Ok(crate::tls::acme::Settings {
+            allow: self.allow.clone(),
+            directory: self
+                .directory
+                .clone()
+                .unwrap_or_else(|| crate::tls::acme::LETS_ENCRYPT.to_owned()),
+            contact: self
+                .email
+                .iter()
+                .map(|email| format!("mailto:{email}"))
+                .collect(),
+            store: storage.build()?,
+            ask: self.ask.clone(),
+            max_issuances: self.max_issuances.unwrap_or(10),
+            max_name_issuances: self.max_name_issuances.unwrap_or(3),
+            window: default_duration(&self.issuance_window, 60 * 60),
+            retry_after: default_duration(&self.retry_after, 10 * 60),
+            renew_before: default_duration(&self.renew_before, 30 * 24 * 60 * 60),
+            bundle: self.bundle_aliases,
+            aliases,
+            canonical,
+            hooks: server.hooks.cert_renewed.clone(),
+        })
+    }
+}
+
+/// Server listener bindings configuration.
+#[cfg_attr(feature = "schema", derive(JsonSchema))]
+#[derive(Debug, Clone, Deserialize, Serialize)]
+#[serde(deny_unknown_fields)]
+pub struct ListenCfg {
+    /// Label servers reference to attach themselves to the listener.
+    #[serde(default)]
+    pub label: Option<String>,
+    /// Port server will bind to.
+    pub port: u16,
+    /// Host address server will bind to.
+    pub host: Option<String>,
+    /// SSL configuration for listener.
+    pub ssl: Option<SSLCfg>,
+    /// Maximum number of pending connections waiting to be accepted.
+    ///
+    /// Default is 2048
+    #[serde(default)]
+    pub backlog: Option<u32>,
+    /// Connection acceptance rules applied before tls and http processing.
+    #[serde(default)]
+    pub accept: AcceptCfg,
+}
+
+impl ListenCfg {
+    #[inline]
+    pub fn host(&self) -> &str {
+        self.host.as_deref().unwrap_or("0.0.0.0")
+    }
+    #[inline]
+    pub fn address(&self) -> (String, u16) {
+        (self.host().to_owned(), self.port)
+    }
+    /// Configured address formatted as `host:port`.
+    pub fn bind_address(&self) -> String {
+        match self.host().contains(':') {
+            true => format!("[{}]:{}", self.host(), self.port),
+            false => format!("{}:{}", self.host(), self.port),
+        }
+    }
+    #[inline]
+    pub fn backlog(&self) -> u32 {
+        self.backlog.unwrap_or(2048)
+    }
+}
+
+impl From<SocketAddr> for ListenCfg {
+    fn from(value: SocketAddr) -> Self {
+        Self {
+            label: None,
+            port: value.port(),
+            host: Some(value.ip().to_string()),
+            ssl: None,
+            backlog: None,
+            accept: AcceptCfg::default(),
+        }
+    }
+}
+
+/// Listener connection acceptance configuration.
+///
+/// Rules are evaluated as soon as connections are accepted so refused
+/// clients never reach the tls handshake or http parser.
+#[cfg_attr(feature = "schema", derive(JsonSchema))]
+#[derive(Debug, Clone, Default, Deserialize, Serialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct AcceptCfg {
+    /// Source addresses allowed to connect.
+    ///
+    /// Every address is allowed when empty.
+    pub allow: Vec<IpMatch>,
+    /// Source addresses refused regardless of the allow list.
+    pub deny: Vec<IpMatch>,
+    /// Maximum concurrent connections from a single source address.
+    pub max_per_source: Option<usize>,
+}
+
+/// Module or Middleware Component
+#[cfg_attr(feature = "schema", derive(JsonSchema))]
+#[derive(Debug, Clone)]
+pub enum Component {
+    Middleware(Middleware),
+    Module(Module),
+    /// Middleware skipped by requests carrying the `skip_flag` debug flag.
+    Toggled {
+        middleware: Middleware,
+        skip_flag: String,
+    },
+}
+
+impl Component {
+    /// Apply component to Chain.
+    pub fn apply(&self, chain: Chain, spec: &Spec) -> Chain {
+        match &self {
+            Component::Module(m) => chain.link(m.link(spec)),
+            Component::Middleware(m) => m.wrap(chain, spec),
+            Component::Toggled { middleware, .. } => middleware.wrap(chain, spec),
+        }
+    }
+
+    /// Configured middleware when the component is middleware.
+    pub fn middleware(&self) -> Option<&Middleware> {
+        match self {
+            Component::Middleware(m) | Component::Toggled { middleware: m, .. } => Some(m),
+            Component::Module(_) => None,
+        }
+    }
+
+    /// Debug flag skipping the component when any.
+    pub fn skip_flag(&self) -> Option<&str> {
+        match self {
+            Component::Toggled { skip_flag, .. } => Some(skip_flag),
+            _ => None,
+        }
+    }
+}
+
+impl<'de> Deserialize<'de> for Component {
+    fn deserialize<D>(deserializer: D) -> Result<Self, D::Error>
+    where
+        D: serde::Deserializer<'de>,
+    {
+        let mut value = serde_yaml::Value::deserialize(deserializer)?;
+        let skip_flag = match value.get("module").is_some() {
+            true => None,
+            false => value
+                .as_mapping_mut()
+                .and_then(|map| map.remove("skip_flag"))
+                .map(serde_yaml::from_value::<String>)
+                .transpose()
+                .map_err(D::Error::custom)?,
+        };
+        let component = match crate::plugin::build(&value).map_err(D::Error::custom)? {
+            Some(component) => component,
+            None => match value.get("module").is_some() {
+                true => Component::Module(
+                    serde_yaml::from_value::<Module>(value).map_err(D::Error::custom)?,
+                ),
+                false => Component::Middleware(
+                    serde_yaml::from_value::<Middleware>(value).map_err(D::Error::custom)?,
+                ),
+            },
+        };
+        Ok(match (component, skip_flag) {
+            (Component::Middleware(middleware), Some(skip_flag)) => Component::Toggled {
+                middleware,
+                skip_flag,
+            },
+            (component, _) => component,
+        })
+    }
+}
+
+impl Serialize for Component {
+    fn serialize<S>(&self, serializer: S) -> std::result::Result<S::Ok, S::Error>
+    where
+        S: serde::Serializer,
+    {
+        match self {
+            Self::Middleware(middleware) => middleware.serialize(serializer),
+            Self::Module(module) => module.serialize(serializer),
+            Self::Toggled {
+                middleware,
+                skip_flag,
+            } => {
+                use serde::ser::Error;
+                let mut value = serde_yaml::to_value(middleware).map_err(S::Error::custom)?;
+                if let Some(map) = value.as_mapping_mut() {
+                    map.insert("skip_flag".into(), skip_flag.as_str().into());
+                }
+                value.serialize(serializer)
+            }
+        }
+    }
+}
+
+/// Group of request modules bound to a specific uri path prefix.
+#[cfg_attr(feature = "schema", derive(JsonSchema))]
+#[derive(Debug, Clone, Deserialize, Serialize)]
+#[serde(deny_unknown_fields)]
+pub struct DirectiveCfg {
+    /// List of additional web components bound to directive.
+    ///
+    /// Items are constructed in the order they're given
+    /// meaning middlewares only wrap elements defined before them.
+    pub construct: Components,
+    /// Location associated with modules
+    ///
+    /// Default is `/`
+    pub location: Option<String>,
+    /// Additional request matchers required for the directive to apply.
+    ///
+    /// See [`bob_guard`] for the builtin and registered matchers.
+    #[serde(default)]
+    pub guards: Vec<MatcherGuard>,
+    /// Maximum accepted request body size overriding the server setting.
+    #[serde(default)]
+    pub max_body_size: Option<u64>,
+    /// Latency and error budget tracked for the directive.
+    #[serde(default)]
+    pub budget: Option<BudgetCfg>,
+    /// Components serving requests while the budget is exceeded.
+    #[serde(default)]
+    pub fallback: Option<Components>,
+    /// Trailing slash redirect policy for request paths.
+    ///
+    /// Default is ignore
+    #[serde(default)]
+    pub slash_policy: SlashPolicy,
+    /// Request phase timeouts overriding the server settings.
+    #[serde(default)]
+    pub timeouts: Option<TimeoutsCfg>,
+    /// Write requests served by the directive to the access log.
+    ///
+    /// Default is true
+    #[serde(default)]
+    pub log: Option<bool>,
+}
+
+/// Maximum distinct `skip_flag` values within a single directive.
+///
+/// Every combination of flags is assembled as its own chain.
+pub const MAX_SKIP_FLAGS: usize = 4;
+
+impl DirectiveCfg {
+    /// Distinct debug flags skipping components of the directive.
+    pub fn skip_flags(&self) -> Vec<&str> {
+        let mut flags: Vec<&str> = self
+            .construct
+            .iter()
+            .filter_map(|c| c.skip_flag())
+            .collect();
+        flags.sort();
+        flags.dedup();
+        flags
+    }
+
+    /// Combinations of skipped flags the directive is assembled with.
+    ///
+    /// Larger combinations come first so requests carrying several flags
+    /// reach the chain skipping all of them, ending with the full chain.
+    pub fn skip_variants(&self) -> Vec<Vec<&str>> {
+        let flags = self.skip_flags();
+        let mut variants: Vec<Vec<&str>> = (0..1usize << flags.len())
+            .map(|mask| {
+                flags
+                    .iter()
+                    .enumerate()
+                    .filter(|(i, _)| mask & (1 << i) != 0)
+                    .map(|(_, f)| *f)
+                    .collect()
+            })
+            .collect();
+        variants.sort_by_key(|v: &Vec<&str>| std::cmp::Reverse(v.len()));
+        variants
+    }
+}
+
+/// Request phase timeout settings.
+#[cfg_attr(feature = "schema", derive(JsonSchema))]
+#[derive(Clone, Debug, Default, Deserialize, Serialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct TimeoutsCfg {
+    /// Maximum time allowed to read the complete request body.
+    pub client_read: Option<Duration>,
+    /// Maximum time allowed to connect to upstreams.
+    ///
+    /// Applies to proxy modules without their own connect timeout.
+    pub upstream_connect: Option<Duration>,
+    /// Maximum time allowed for upstreams to respond.
+    ///
+    /// Applies to proxy modules without their own request timeout.
+    pub upstream_response: Option<Duration>,
+    /// Maximum time allowed to produce a response.
+    pub total: Option<Duration>,
+}
+
+impl TimeoutsCfg {
+    /// Combine settings using the fallback for any unset timeouts.
+    pub fn or(&self, fallback: &Self) -> Self {
+        Self {
+            client_read: self.client_read.clone().or(fallback.client_read.clone()),
+            upstream_connect: self
+                .upstream_connect
+                .clone()
+                .or(fallback.upstream_connect.clone()),
+            upstream_response: self
+                .upstream_response
+                .clone()
+                .or(fallback.upstream_response.clone()),
+            total: self.total.clone().or(fallback.total.clone()),
+        }
+    }
+
+    /// Upstream connect timeout unless set by the module itself.
+    pub fn upstream_connect(&self, module: &Option<Duration>) -> Option<std::time::Duration> {
+        module
+            .as_ref()
+            .or(self.upstream_connect.as_ref())
+            .map(|d| d.0)
+    }
+
+    /// Upstream response timeout unless set by the module itself.
+    pub fn upstream_response(&self, module: &Option<Duration>) -> Option<std::time::Duration> {
+        module
+            .as_ref()
+            .or(self.upstream_response.as_ref())
+            .map(|d| d.0)
+    }
+
+    /// Validate timeout settings.
+    fn validate(&self) -> Result<()> {
+        let timeouts = [
+            ("client_read", &self.client_read),
+            ("upstream_connect", &self.upstream_connect),
+            ("upstream_response", &self.upstream_response),
+            ("total", &self.total),
+        ];
+        for (name, timeout) in timeouts {
+            if timeout.as_ref().is_some_and(|d| d.0.is_zero()) {
+                anyhow::bail!("{name} timeout must be greater than zero");
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Trailing slash redirect policy.
+#[cfg_attr(feature = "schema", derive(JsonSchema))]
+#[derive(Clone, Debug, Default, PartialEq, Eq, Deserialize, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum SlashPolicy {
+    /// Redirect directory paths without a trailing slash to include one.
+    Add,
+    /// Redirect paths with a trailing slash to remove it.
+    Strip,
+    /// Leave paths untouched.
+    #[default]
+    Ignore,
+}
+
+/// Canonical host and scheme redirect settings.
+#[cfg_attr(feature = "schema", derive(JsonSchema))]
+#[derive(Clone, Debug, Default, Deserialize, Serialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct CanonicalCfg {
+    /// Host requests are redirected to when another is requested.
+    ///
+    /// Example: `example.com` to redirect `www.example.com`
+    pub host: Option<String>,
+    /// Redirect plain http requests to https.
+    ///
+    /// Default is false
+    pub https: bool,
+    /// Redirect status code used for `GET` and `HEAD` requests.
+    ///
+    /// Other methods always use 308 to preserve the request body.
+    /// Default is 301
+    pub status: Option<u16>,
+}
+
+impl CanonicalCfg {
+    /// Configured redirect status code.
+    fn status(&self) -> Result<actix_web::http::StatusCode> {
+        let code = self.status.unwrap_or(301);
+        actix_web::http::StatusCode::from_u16(code)
+            .ok()
+            .filter(|status| status.is_redirection())
+            .ok_or_else(|| anyhow!("invalid canonical redirect status {code}"))
+    }
+
+    /// Validate canonical redirect settings.
+    fn validate(&self) -> Result<()> {
+        if self.host.as_ref().is_some_and(|host| host.is_empty()) {
+            anyhow::bail!("canonical host must not be empty");
+        }
+        self.status().map(|_| ())
+    }
+
+    /// Produce [`crate::services::canonical::Canonical`] from config.
+    ///
+    /// The forwarded scheme is only trusted when `forwarded` is set.
+    pub fn factory(&self, forwarded: bool) -> crate::services::canonical::Canonical {
+        crate::services::canonical::Canonical::new(crate::services::canonical::Settings {
+            slash: SlashPolicy::Ignore,
+            host: self.host.clone(),
+            https: self.https,
+            forwarded,
+            status: self
+                .status()
+                .expect("canonical status validated during config load"),
+        })
+    }
+}
+
+/// Directive latency and error budget settings.
+#[cfg_attr(feature = "schema", derive(JsonSchema))]
+#[derive(Debug, Clone, Default, Deserialize, Serialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct BudgetCfg {
+    /// Latency requests are expected to complete within.
+    pub max_latency: Option<Duration>,
+    /// Percentage of requests expected to complete within `max_latency`.
+    ///
+    /// Default is 95
+    pub latency_percentile: Option<f64>,
+    /// Maximum percentage of requests failing with server errors.
+    pub max_error_rate: Option<f64>,
+    /// Window requests are evaluated within.
+    ///
+    /// Default is 1m
+    pub window: Option<Duration>,
+    /// Minimum requests within the window before evaluating the budget.
+    ///
+    /// Default is 20
+    pub min_requests: Option<u32>,
+    /// Time the directive stays degraded once the budget is exceeded.
+    ///
+    /// Default is 30s
+    pub cooldown: Option<Duration>,
+
+    // global initialization for budget tracking.
+    // avoids recreating the state for every worker actix-web creates.
+    #[serde(skip)]
+    pub state: crate::services::budget::State,
+}
+
+impl BudgetCfg {
+    /// Validate budget thresholds.
+    fn validate(&self) -> Result<()> {
+        let percent = |v: Option<f64>| v.is_none_or(|v| (0.0..=100.0).contains(&v));
+        if !percent(self.latency_percentile) || !percent(self.max_error_rate) {
+            return Err(anyhow!("budget percentages must be between 0 and 100"));
+        }
+        if self.max_latency.is_none() && self.max_error_rate.is_none() {
+            return Err(anyhow!("budget requires max_latency or max_error_rate"));
+        }
+        Ok(())
+    }
+
+    /// Produce [`crate::services::budget::Budget`] from config.
+    pub fn factory(&self, location: &str) -> crate::services::budget::Budget {
+        let settings = crate::services::budget::Settings {
+            max_latency: self.max_latency.as_ref().map(|d| d.0),
+            max_slow_rate: 1.0 - self.latency_percentile.unwrap_or(95.0) / 100.0,
+            max_error_rate: self.max_error_rate.map(|rate| rate / 100.0),
+            window: default_duration(&self.window, 60),
+            min_requests: self.min_requests.unwrap_or(20),
+            cooldown: default_duration(&self.cooldown, 30),
+        };
+        crate::services::budget::Budget::new(location, settings, self.state.clone())
+    }
+}
+
+impl From<ModuleConfig> for DirectiveCfg {
+    fn from(value: ModuleConfig) -> Self {
+        Self {
+            location: None,
+            guards: vec![],
+            max_body_size: None,
+            budget: None,
+            fallback: None,
+            slash_policy: SlashPolicy::Ignore,
+            timeouts: None,
+            log: None,
+            construct: Components(vec![Component::Module(Module {
+                module: value,
+                next: None,
+            })]),
+        }
+    }
+}
+
+#[cfg_attr(feature = "schema", derive(JsonSchema))]
+#[derive(Debug, Clone, Serialize)]
+#[serde(transparent)]
+pub struct Components(Vec<Component>);
+
+impl Components {
+    #[inline]
+    pub fn iter(&self) -> impl Iterator<Item = &Component> {
+        self.0.iter()
+    }
+}
+
+impl<'de> Deserialize<'de> for Components {
+    fn deserialize<D>(deserializer: D) -> Result<Self, D::Error>
+    where
+        D: serde::Deserializer<'de>,
+    {
+        <Vec<Component> as de::Deserialize>::deserialize(deserializer).and_then(|inner| {
+            if inner.is_empty() {
+                return Err(de::Error::invalid_length(
+                    inner.len(),
+                    &"must contain a module",
+                ));
+            }
+            if !matches!(inner[0], Component::Module(_)) {
+                return Err(de::Error::invalid_type(
+                    Unexpected::StructVariant,
+                    &"first component must be a module",
+                ));
+            }
+            Ok(Components(inner))
+        })
+    }
+}
+
+de_fromstr!(DomainMatch);
+de_fromstr!(IpMatch);
+de_fromstr!(PathMatch);
+de_fromstr!(LogLevel);
+de_fromstr!(LogFilter);
+de_fromstr!(LogTarget);
+de_fromstr!(UpstreamAddr);
+ser_display!(DomainMatch);
+ser_display!(IpMatch);
+ser_display!(PathMatch);
+ser_display!(LogLevel);
+ser_display!(LogFilter);
+ser_display!(LogTarget);
+ser_display!(UpstreamAddr);
+
+/// Return option or generate default duration from seconds
+#[inline]
+pub fn default_duration(d: &Option<Duration>, default_secs: u64) -> std::time::Duration {
+    d.as_ref()
+        .map(|d| d.0)
+        .unwrap_or_else(|| std::time::Duration::from_secs(default_secs))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn pattern(s: &str) -> PathMatch {
+        s.parse().expect("valid pattern")
+    }
+
+    #[test]
+    fn normalize_path_resolves_segments() {
+        assert_eq!(normalize_path("/a/b/").as_deref(), Some("/a/b/"));
+        assert_eq!(normalize_path("/a//./b").as_deref(), Some("/a/b"));
+        assert_eq!(normalize_path("/a/../b").as_deref(), Some("/b"));
+        assert_eq!(normalize_path("/a/%2e%2e/b").as_deref(), Some("/b"));
+        assert_eq!(normalize_path("/a\\..\\b").as_deref(), Some("/b"));
+        assert_eq!(normalize_path("/"), Some("/".to_owned()));
+        assert_eq!(normalize_path("/.."), None);
+        assert_eq!(normalize_path("/%ff"), None);
+    }
+
+    #[test]
+    fn strict_match_rejects_ambiguous_paths() {
+        let public = pattern("/public/*");
+        assert!(public.matches_strict("/public/index.html"));
+        assert!(!public.matches_strict("/public/a/b"));
+        assert!(!public.matches_strict("/public/../admin"));
+        assert!(!public.matches_strict("/public/%2e%2e/admin"));
+        assert!(!public.matches_strict("/public/%2E%2E%2Fadmin"));
+        assert!(!public.matches_strict("/public//admin"));
+        assert!(!public.matches_strict("/public/..\\admin"));
+    }
+
+    #[test]
+    fn normalized_match_covers_variants() {
+        let admin = pattern("/admin/*");
+        assert!(admin.matches_normalized("/admin/users"));
+        assert!(admin.matches_normalized("/public/../admin/users"));
+        assert!(admin.matches_normalized("/%61dmin/users"));
+        assert!(admin.matches_normalized("/.."));
+        assert!(admin.matches_normalized("/admin/a/b"));
+        assert!(!admin.matches_normalized("/public/users"));
+    }
+
+    #[test]
+    fn secrets_are_redacted() {
+        let salted = |salt: &str| {
+            let mut config = ServerConfig::default();
+            config.logging.anonymize = Some(AnonymizeCfg {
+                salt: Some(Secret(salt.to_owned())),
+                ..Default::default()
+            });
+            vec![config]
+        };
+        let config = salted("hunter2");
+        let printed = serde_json::to_string(&config).expect("serializable config");
+        assert!(printed.contains("\"salt\":\"<redacted>\""));
+        assert!(!format!("{config:?}").contains("hunter2"));
+        assert_eq!(config_hash(&config), config_hash(&salted("hunter2")));
+        assert_ne!(config_hash(&config), config_hash(&salted("hunter3")));
+    }
+}
@@ -0,0 +1,2187 @@
+//! Modules Configuration
+
+#[cfg(feature = "schema")]
+use schemars::JsonSchema;
+
+use actix_chain::{Link, next};
+use actix_web::http::StatusCode;
+use anyhow::anyhow;
+use serde::{Deserialize, Serialize};
+
+use super::Spec;
+
+/// Server specific configuration modules for request processing.
+#[cfg_attr(feature = "schema", derive(JsonSchema))]
+#[derive(Clone, Debug, Deserialize, Serialize)]
+pub struct Module {
+    /// Module specific configuration.
+    #[serde(flatten)]
+    pub module: ModuleConfig,
+    /// Override of [`actix_chain::Link::next`] behavior.
+    #[serde(default)]
+    pub next: Option<Vec<u16>>,
+}
+
+impl Module {
+    /// Build [`actix_chain::Link`] from the module configuration.
+    #[inline]
+    pub fn link(&self, spec: &Spec) -> Link {
+        let mut link = self.module.link(spec);
+        if let Some(next) = self.next.as_ref() {
+            link = next
+                .iter()
+                .filter_map(|code| StatusCode::from_u16(*code).ok())
+                .map(next::IsStatus)
+                .fold(link, |link, code| link.next(code));
+        }
+        link
+    }
+}
+
+/// Configuration modules for request processing.
+#[cfg_attr(feature = "schema", derive(JsonSchema))]
+#[derive(Clone, Debug, Deserialize, Serialize)]
+#[serde(tag = "module", deny_unknown_fields)]
+pub enum ModuleConfig {
+    /// Configuration for buitltin redirect service.
+    #[serde(alias = "redirect")]
+    Redirect(redirect::Config),
+    /// Configuration for builtin static response service.
+    #[serde(alias = "static")]
+    Static(rstatic::Config),
+    /// Configuration for builtin robots.txt service.
+    #[serde(alias = "robots")]
+    Robots(robots::Config),
+    /// Configuration for [`actix_files`] service.
+    #[cfg(feature = "fileserver")]
+    #[serde(alias = "fileserver")]
+    FileServer(fileserver::Config),
+    /// Configuration for [`actix_revproxy`] service.
+    #[cfg(feature = "rproxy")]
+    #[serde(alias = "rproxy")]
+    ReverseProxy(rproxy::Config),
+    /// Configuration for [`crate::services::pool`] service.
+    #[cfg(feature = "rproxy")]
+    #[serde(alias = "pool", alias = "balance")]
+    Pool(pool::Config),
+    /// Configuration for [`crate::services::forward`] service.
+    #[cfg(feature = "rproxy")]
+    #[serde(alias = "forward", alias = "forward_proxy")]
+    ForwardProxy(forward::Config),
+    /// Configuration for [`actix_fastcgi`] service.
+    #[cfg(feature = "fastcgi")]
+    #[serde(alias = "fastcgi")]
+    FastCGI(fastcgi::Config),
+    /// Configuration for [`crate::services::dynamic`] service.
+    #[cfg(feature = "dynamic")]
+    #[serde(alias = "dynamic")]
+    Dynamic(dynamic::Config),
+    /// Configuration for [`crate::services::cgi_bin`] service.
+    #[cfg(feature = "cgi")]
+    #[serde(alias = "cgi")]
+    Cgi(cgi::Config),
+    /// Configuration for [`crate::services::git`] service.
+    #[cfg(feature = "git")]
+    #[serde(alias = "git")]
+    Git(git::Config),
+    /// Module provided by a registered [`crate::plugin::BobModule`].
+    #[serde(skip_deserializing, rename = "plugin")]
+    Plugin(crate::plugin::PluginModule),
+}
+
+impl ModuleConfig {
+    /// Validate and prepare module resources ahead of construction.
+    ///
+    /// Called once per process during configuration loading.
+    pub fn prepare(&self, spec: &Spec) -> anyhow::Result<()> {
+        match self {
+            Self::Redirect(cfg) => cfg.prepare(spec),
+            Self::Static(cfg) => cfg.prepare(spec),
+            #[cfg(feature = "fileserver")]
+            Self::FileServer(cfg) => cfg.prepare(spec),
+            #[cfg(feature = "rproxy")]
+            Self::ReverseProxy(cfg) => cfg.prepare(spec),
+            #[cfg(feature = "rproxy")]
+            Self::Pool(cfg) => cfg.prepare(spec),
+            #[cfg(feature = "rproxy")]
+            Self::ForwardProxy(cfg) => cfg.prepare(spec),
+            #[cfg(feature = "dynamic")]
+            Self::Dynamic(cfg) => cfg.prepare(spec),
+            #[cfg(feature = "cgi")]
+            Self::Cgi(cfg) => cfg.prepare(spec),
+            #[cfg(feature = "git")]
+            Self::Git(cfg) => cfg.prepare(spec),
+            Self::Plugin(cfg) => cfg.prepare(spec),
+            #[allow(unreachable_patterns)]
+            _ => Ok(()),
+        }
+    }
+
+    /// Build [`actix_chain::Link`] from the module configuration.
+    pub fn link(&self, spec: &Spec) -> Link {
+        match self {
+            Self::Redirect(cfg) => cfg.link(spec),
+            Self::Static(cfg) => cfg.link(spec),
+            Self::Robots(cfg) => cfg.link(spec),
+            #[cfg(feature = "fileserver")]
+            Self::FileServer(cfg) => cfg.link(spec),
+            #[cfg(feature = "rproxy")]
+            Self::ReverseProxy(cfg) => cfg.link(spec),
+            #[cfg(feature = "rproxy")]
+            Self::Pool(cfg) => cfg.link(spec),
+            #[cfg(feature = "rproxy")]
+            Self::ForwardProxy(cfg) => cfg.link(spec),
+            #[cfg(feature = "fastcgi")]
+            Self::FastCGI(cfg) => cfg.link(spec),
+            #[cfg(feature = "dynamic")]
+            Self::Dynamic(cfg) => cfg.link(spec),
+            #[cfg(feature = "cgi")]
+            Self::Cgi(cfg) => cfg.link(spec),
+            #[cfg(feature = "git")]
+            Self::Git(cfg) => cfg.link(spec),
+            Self::Plugin(cfg) => cfg.link(spec),
+        }
+    }
+}
+
+/// Simple HTTP redirect module
+pub mod redirect {
+    use super::*;
+
+    use actix_web::{
+        HttpResponse, Route,
+        http::{StatusCode, header},
+    };
+
+    /// Redirect module configuration
+    #[cfg_attr(feature = "schema", derive(JsonSchema))]
+    #[derive(Clone, Debug, Default, Deserialize, Serialize)]
+    #[serde(deny_unknown_fields)]
+    pub struct Config {
+        /// Redirect URI
+        pub redirect: String,
+        /// Redirect status code
+        ///
+        /// Default is 302
+        pub status_code: Option<u16>,
+        /// Append the request path and query to the redirect URI.
+        ///
+        /// Default is false
+        #[serde(default)]
+        pub preserve_path: bool,
+    }
+
+    impl Config {
+        /// Validate redirect status and location.
+        pub fn prepare(&self, _spec: &Spec) -> anyhow::Result<()> {
+            let status_code = self.status_code.unwrap_or(302);
+            let status = StatusCode::from_u16(status_code)
+                .map_err(|_| anyhow!("invalid redirect status {status_code}"))?;
+            if !status.is_redirection() {
+                return Err(anyhow!(
+                    "redirect status {status_code} is not a redirection"
+                ));
+            }
+            header::HeaderValue::from_str(&self.redirect)
+                .map_err(|_| anyhow!("invalid redirect location {:?}", self.redirect))?;
+            Ok(())
+        }
+
+        /// Produce [`actix_web::Route`] from config.
+        pub fn factory(&self) -> Route {
+            let status_code = self.status_code.unwrap_or(302);
+
+            let uri = self.redirect.to_owned();
+            let preserve_path = self.preserve_path;
+            let status = StatusCode::from_u16(status_code)
+                .expect("redirect status validated during config load");
+            actix_web::web::get().to(move |req: actix_web::HttpRequest| {
+                let location = match preserve_path {
+                    true => {
+                        // `//evil.com` appended to `/` would leave the site
+                        let path = req.uri().path_and_query().map_or("/", |p| p.as_str());
+                        let path = path.trim_start_matches(['/', '\\']);
+                        format!("{}/{path}", uri.trim_end_matches('/'))
+                    }
+                    false => uri.clone(),
+                };
+                let mut builder = HttpResponse::build(status);
+                builder.insert_header((header::LOCATION, location));
+                builder
+            })
+        }
+
+        /// Produce [`actix_chain::Link`] from config.
+        #[inline]
+        pub fn link(&self, _spec: &Spec) -> Link {
+            Link::new(self.factory())
+        }
+    }
+}
+
+/// Simple static response module
+pub mod rstatic {
+    use std::collections::BTreeMap;
+
+    use actix_web::{HttpResponse, Route};
+
+    use super::*;
+
+    /// Static response module configuration
+    #[cfg_attr(feature = "schema", derive(JsonSchema))]
+    #[derive(Clone, Debug, Default, Deserialize, Serialize)]
+    #[serde(default, deny_unknown_fields)]
+    pub struct Config {
+        /// Static body content
+        pub body: Option<String>,
+        /// Content type override
+        ///
+        /// Default is text/html
+        pub content_type: Option<String>,
+        /// Headers to append to response
+        pub headers: BTreeMap<String, String>,
+        /// Content status code
+        ///
+        /// Default is 200
+        pub status_code: Option<u16>,
+    }
+
+    impl Config {
+        /// Validate response status and headers.
+        pub fn prepare(&self, _spec: &Spec) -> anyhow::Result<()> {
+            use actix_web::http::header::{HeaderName, HeaderValue};
+            let status_code = self.status_code.unwrap_or(200);
+            StatusCode::from_u16(status_code)
+                .map_err(|_| anyhow!("invalid response status {status_code}"))?;
+            for (name, value) in self.headers.iter() {
+                HeaderName::try_from(name.as_str())
+                    .map_err(|_| anyhow!("invalid header name {name:?}"))?;
+                HeaderValue::try_from(value.as_str())
+                    .map_err(|_| anyhow!("invalid header value for {name:?}"))?;
+            }
+            Ok(())
+        }
+
+        /// Produce [`actix_web::Route`] from config.
+        pub fn factory(&self) -> Route {
+            let status_code = self.status_code.unwrap_or(200);
+            let ctype = self
+                .content_type
+                .clone()
+                .unwrap_or_else(|| "text/html; charset=UTF-8".to_owned());
+
+            let config = self.clone();
+            let status = StatusCode::from_u16(status_code)
+                .expect("response status validated during config load");
+            actix_web::web::get().to(move || {
+                let config = config.clone();
+                let mut builder = HttpResponse::build(status);
+                builder.insert_header(("Content-Type", ctype.clone()));
+                config
+                    .headers
+                    .clone()
+                    .into_iter()
+                    .fold(&mut builder, |b, (h, v)| b.append_header((h, v)));
+                async move { builder.body(config.body.unwrap_or_default()) }
+            })
+        }
+
+        /// Produce [`actix_chain::Link`] from config.
+        #[inline]
+        pub fn link(&self, _spec: &Spec) -> Link {
+            Link::new(self.factory())
+        }
+    }
+}
+
+/// Generated robots.txt module
+pub mod robots {
+    use std::fmt::Write;
+
+    use actix_web::{HttpResponse, Route, http::header};
+
+    use super::*;
+    use crate::services::crawlers::AI_CRAWLERS;
+
+    /// Maintained crawler lists disallowed from the entire site.
+    #[cfg_attr(feature = "schema", derive(JsonSchema))]
+    #[derive(Clone, Debug, Deserialize, Serialize)]
+    #[serde(rename_all = "snake_case")]
+    pub enum Preset {
+        /// AI crawlers and training data collectors.
+        AiCrawlers,
+    }
+
+    /// Crawling rules for a group of user-agents.
+    #[cfg_attr(feature = "schema", derive(JsonSchema))]
+    #[derive(Clone, Debug, Default, Deserialize, Serialize)]
+    #[serde(default, deny_unknown_fields)]
+    pub struct Group {
+        /// User-agent tokens the group applies to.
+        ///
+        /// Default is `*`
+        user_agents: Vec<String>,
+        /// Path prefixes crawlers may access.
+        allow: Vec<String>,
+        /// Path prefixes crawlers may not access.
+        disallow: Vec<String>,
+        /// Seconds crawlers should wait between requests.
+        crawl_delay: Option<u32>,
+    }
+
+    impl Group {
+        /// Write group rules in robots.txt format.
+        fn write(&self, out: &mut String) {
+            match self.user_agents.is_empty() {
+                true => out.push_str("User-agent: *\n"),
+                false => self.user_agents.iter().for_each(|agent| {
+                    let _ = writeln!(out, "User-agent: {agent}");
+                }),
+            }
+            for path in self.allow.iter() {
+                let _ = writeln!(out, "Allow: {path}");
+            }
+            for path in self.disallow.iter() {
+                let _ = writeln!(out, "Disallow: {path}");
+            }
+            if self.allow.is_empty() && self.disallow.is_empty() {
+                out.push_str("Disallow:\n");
+            }
+            if let Some(delay) = self.crawl_delay {
+                let _ = writeln!(out, "Crawl-delay: {delay}");
+            }
+        }
+    }
+
+    /// Robots.txt module configuration.
+    #[cfg_attr(feature = "schema", derive(JsonSchema))]
+    #[derive(Clone, Debug, Default, Deserialize, Serialize)]
+    #[serde(default, deny_unknown_fields)]
+    pub struct Config {
+        /// Crawling rule groups.
+        ///
+        /// Default allows every crawler access to everything
+        rules: Vec<Group>,
+        /// Crawler lists disallowed from the entire site.
+        block: Vec<Preset>,
+        /// Sitemap URLs advertised to crawlers.
+        sitemaps: Vec<String>,
+    }
+
+    impl Config {
+        /// Generate robots.txt content from config.
+        pub fn render(&self) -> String {
+            let mut out = String::new();
+            for preset in self.block.iter() {
+                let agents = match preset {
+                    Preset::AiCrawlers => AI_CRAWLERS,
+                };
+                for agent in agents {
+                    let _ = writeln!(out, "User-agent: {agent}");
+                }
+                out.push_str("Disallow: /\n\n");
+            }
+            match self.rules.is_empty() {
+                true => out.push_str("User-agent: *\nDisallow:\n"),
+                false => self.rules.iter().enumerate().for_each(|(n, group)| {
+                    if n > 0 {
+                        out.push('\n');
+                    }
+                    group.write(&mut out);
+                }),
+            }
+            if !self.sitemaps.is_empty() {
+                out.push('\n');
+            }
+            for sitemap in self.sitemaps.iter() {
+                let _ = writeln!(out, "Sitemap: {sitemap}");
+            }
+            out
+        }
+
+        /// Produce [`actix_web::Route`] from config.
+        pub fn factory(&self) -> Route {
+            let body = self.render();
+            actix_web::web::get().to(move || {
+                let body = body.clone();
+                async move {
+                    HttpResponse::Ok()
+                        .insert_header((header::CONTENT_TYPE, "text/plain; charset=utf-8"))
+                        .body(body)
+                }
+            })
+        }
+
+        /// Produce [`actix_chain::Link`] from config.
+        #[inline]
+        pub fn link(&self, _spec: &Spec) -> Link {
+            Link::new(self.factory())
+        }
+    }
+}
+
+/// Fileserver module
+#[cfg(feature = "fileserver")]
+pub mod fileserver {
+    use super::*;
+
+    use actix_chain::Wrappable;
+    use actix_files::Files;
+    use std::path::PathBuf;
+
+    use crate::services::integrity::{Integrity, Settings};
+
+    /// Detached signing key loaded once and shared between every worker.
+    #[cfg(feature = "signing")]
+    #[derive(Clone, Default)]
+    struct KeyCell(std::sync::Arc<std::sync::OnceLock<crate::services::integrity::Signer>>);
+
+    #[cfg(feature = "signing")]
+    impl std::fmt::Debug for KeyCell {
+        fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+            write!(f, "KeyCell {{}}")
+        }
+    }
+
+    /// Integrity headers emitted for served files.
+    #[cfg_attr(feature = "schema", derive(JsonSchema))]
+    #[derive(Clone, Debug, Default, Deserialize, Serialize)]
+    #[serde(default, deny_unknown_fields)]
+    pub struct IntegrityCfg {
+        /// Emit the legacy `Digest` header alongside `Repr-Digest`.
+        ///
+        /// Default is true
+        pub legacy_digest: Option<bool>,
+        /// PKCS#8 PEM encoded Ed25519 private key used to sign digests.
+        #[cfg(feature = "signing")]
+        pub signing_key: Option<PathBuf>,
+        /// Key identifier advertised alongside signatures.
+        ///
+        /// Default is derived from the public key
+        #[cfg(feature = "signing")]
+        pub key_id: Option<String>,
+
+        // global initialization for the signing key.
+        // avoids re-reading the key for every worker actix-web creates.
+        #[cfg(feature = "signing")]
+        #[serde(skip)]
+        signer: KeyCell,
+    }
+
+    impl IntegrityCfg {
+        /// Load the configured signing key into the shared key.
+        #[cfg(feature = "signing")]
+        fn signer(&self) -> anyhow::Result<Option<&crate::services::integrity::Signer>> {
+            use anyhow::Context;
+            use ed25519_dalek::pkcs8::DecodePrivateKey;
+            use sha2::{Digest, Sha256};
+
+            let Some(path) = self.signing_key.as_ref() else {
+                return Ok(None);
+            };
+            if let Some(signer) = self.signer.0.get() {
+                return Ok(Some(signer));
+            }
+            let pem = std::fs::read_to_string(path)
+                .with_context(|| format!("failed to read signing key {path:?}"))?;
+            let key = ed25519_dalek::SigningKey::from_pkcs8_pem(&pem)
+                .map_err(|err| anyhow::anyhow!("invalid ed25519 signing key {path:?}: {err}"))?;
+            let key_id = self.key_id.clone().unwrap_or_else(|| {
+                let digest = Sha256::digest(key.verifying_key().as_bytes());
+                digest[..8].iter().map(|b| format!("{b:02x}")).collect()
+            });
+            let signer = crate::services::integrity::Signer {
+                key: std::sync::Arc::new(key),
+                key_id,
+            };
+            Ok(Some(self.signer.0.get_or_init(|| signer)))
+        }
+    }
+
+    /// File-Server module configuration.
+    #[cfg_attr(feature = "schema", derive(JsonSchema))]
+    #[derive(Clone, Debug, Default, Deserialize, Serialize)]
+    #[serde(default, deny_unknown_fields)]
+    pub struct Config {
+        /// Root filepath for serving files
+        ///
+        /// Overrides [`crate::config::ServerConfig::root`]
+        pub root: Option<PathBuf>,
+        /// Allow serving hidden files that begin with a `.`
+        ///
+        /// Default is false.
+        pub hidden_files: bool,
+        /// Allow director indexing to browse files.
+        ///
+        /// Default is false
+        pub index_files: bool,
+        /// Size Threshold for Asyncly Processing Files
+        ///
+        /// Default is u16::MAX (65_365)
+        pub async_threshold: Option<u64>,
+        /// Emit digest and signature headers for served files.
+        pub integrity: Option<IntegrityCfg>,
+    }
+
+    impl Config {
+        #[inline]
+        fn root(&self, spec: &Spec) -> PathBuf {
+            self.root
+                .clone()
+                .or(spec.config.root.clone())
+                .unwrap_or_else(|| PathBuf::from("."))
+        }
+
+        /// Validate and load the configured signing key.
+        pub fn prepare(&self, _spec: &Spec) -> anyhow::Result<()> {
+            #[cfg(feature = "signing")]
+            if let Some(integrity) = self.integrity.as_ref() {
+                integrity.signer()?;
+            }
+            Ok(())
+        }
+
+        /// Produce [`actix_files::Files`] from config.
+        pub fn factory(&self, spec: &Spec) -> Files {
+            let mut files = Files::new("", self.root(spec))
+                .set_size_threshold(self.async_threshold.unwrap_or(u16::MAX as u64));
+            if self.hidden_files {
+                files = files.use_hidden_files();
+            }
+            if self.index_files {
+                files = files.show_files_listing();
+            }
+            spec.config
+                .index
+                .iter()
+                .fold(files, |files, index| files.index_file(index))
+        }
+
+        /// Produce [`crate::services::integrity::Integrity`] from config.
+        pub fn integrity(&self, spec: &Spec) -> Option<Integrity> {
+            let integrity = self.integrity.as_ref()?;
+            Some(Integrity::new(Settings {
+                root: self.root(spec),
+                index: spec.config.index.clone(),
+                legacy_digest: integrity.legacy_digest.unwrap_or(true),
+                #[cfg(feature = "signing")]
+                signer: integrity
+                    .signer()
+                    .expect("signing key validated during config load")
+                    .cloned(),
+            }))
+        }
+
+        /// Produce [`actix_chain::Link`] from config.
+        #[inline]
+        pub fn link(&self, spec: &Spec) -> Link {
+            let link = Link::new(self.factory(spec));
+            match self.integrity(spec) {
+                Some(integrity) => link.wrap_with(integrity),
+                None => link,
+            }
+        }
+    }
+}
+
+/// Upstream retry configuration shared by proxy modules
+#[cfg(feature = "rproxy")]
+pub mod retry {
+    use super::*;
+    use crate::config::Duration;
+    use crate::services::retry::{Failure, Policy};
+
+    /// Upstream failure retried by proxy modules.
+    #[cfg_attr(feature = "schema", derive(JsonSchema))]
+    #[derive(Clone, Debug, Deserialize, Serialize)]
+    #[serde(untagged)]
+    pub enum RetryOn {
+        /// Upstream response status code.
+        Status(u16),
+        /// Upstream failure kind.
+        Kind(RetryKind),
+    }
+
+    /// Upstream failure kind.
+    #[cfg_attr(feature = "schema", derive(JsonSchema))]
+    #[derive(Clone, Debug, Deserialize, Serialize)]
+    #[serde(rename_all = "snake_case")]
+    pub enum RetryKind {
+        /// Connecting to or communicating with the upstream failed.
+        ConnectError,
+        /// The upstream did not respond in time.
+        Timeout,
+    }
+
+    impl RetryOn {
+        fn failure(&self) -> anyhow::Result<Failure> {
+            Ok(match self {
+                Self::Kind(RetryKind::ConnectError) => Failure::Connect,
+                Self::Kind(RetryKind::Timeout) => Failure::Timeout,
+                Self::Status(code) => Failure::Status(
+                    StatusCode::from_u16(*code)
+                        .map_err(|_| anyhow!("invalid retry status {code}"))?,
+                ),
+            })
+        }
+    }
+
+    /// Build the retry policy from module settings.
+    ///
+    /// Connection errors, timeouts, 502 and 503 are retried when
+    /// `retry_on` is empty.
+    pub fn policy(
+        retries: Option<u32>,
+        retry_on: &[RetryOn],
+        try_timeout: &Option<Duration>,
+        non_idempotent: bool,
+    ) -> anyhow::Result<Policy> {
+        let retry_on = match retry_on.is_empty() {
+            true => vec![
+                Failure::Connect,
+                Failure::Timeout,
+                Failure::Status(StatusCode::BAD_GATEWAY),
+                Failure::Status(StatusCode::SERVICE_UNAVAILABLE),
+            ],
+            false => retry_on
+                .iter()
+                .map(RetryOn::failure)
+                .collect::<anyhow::Result<_>>()?,
+        };
+        Ok(Policy {
+            retries: retries.unwrap_or(0),
+            retry_on,
+            try_timeout: try_timeout.as_ref().map(|d| d.0),
+            non_idempotent,
+        })
+    }
+}
+
+/// ReverseProxy module
+#[cfg(feature = "rproxy")]
+pub mod rproxy {
+    use std::{collections::BTreeMap, net::IpAddr, sync::Arc};
+
+    use super::*;
+    use crate::config::{Duration, UpstreamAddr, Uri, default_duration};
+
+    use super::retry::{RetryOn, policy};
+    use crate::services::resolver::{Settings, UpstreamResolver};
+    use crate::services::retry::Policy;
+    use crate::services::unix::UnixConnector;
+    use crate::services::{
+        h2c, hop_by_hop, intercept, mirror, proxy_buffer, proxy_cookie, proxy_path, proxy_redirect,
+        spool,
+    };
+    use crate::tls::client::{build_custom_tls_config, build_tls_config};
+    use actix_chain::Wrappable;
+    use actix_revproxy::RevProxy;
+    use actix_web::http::header::{HeaderName, HeaderValue};
+
+    /// Reverse-Proxy module configuration.
+    #[cfg_attr(feature = "schema", derive(JsonSchema))]
+    #[derive(Clone, Debug, Deserialize, Serialize)]
+    #[serde(deny_unknown_fields)]
+    pub struct Config {
+        /// Proxy resolution URL or `unix:` socket path.
+        pub resolve: UpstreamAddr,
+        /// Change host to upstream address host.
+        ///
+        /// Default is false
+        #[serde(default)]
+        pub change_host: bool,
+        /// Max number of redirects allowed in client lookup.
+        ///
+        /// Default is 0.
+        pub max_redirects: Option<u8>,
+        /// Initial Connection Window Size
+        ///
+        /// Default is `u16::MAX`
+        pub initial_conn_size: Option<u32>,
+        /// Initial Window Size
+        ///
+        /// Default is `u16::MAX`
+        pub initial_window_size: Option<u32>,
+        /// Upstream request timeout.
+        ///
+        /// Default is the `upstream_response` timeout or 5s
+        pub timeout: Option<Duration>,
+        /// Verify SSL Configuration
+        ///
+        /// Default is true
+        pub verify_ssl: Option<bool>,
+        /// Upstream headers to send to server.
+        #[serde(default)]
+        pub upstream_headers: BTreeMap<String, String>,
+        /// Downstream headers to send to client.
+        #[serde(default)]
+        pub downstream_headers: BTreeMap<String, String>,
+        /// Static addresses used when resolving the upstream host fails.
+        #[serde(default)]
+        pub fallback_addresses: Vec<IpAddr>,
+        /// Reuse the last successfully resolved upstream addresses
+        /// when resolving the upstream host fails.
+        ///
+        /// Default is false
+        #[serde(default)]
+        pub last_known_fallback: bool,
+        /// Longest time resolved upstream addresses and connections
+        /// are reused before the upstream host is resolved again.
+        ///
+        /// Default is the DNS record TTL
+        #[serde(default)]
+        pub dns_max_ttl: Option<Duration>,
+        /// Rewrite upstream addresses in `Location` and `Refresh`
+        /// response headers back to the public address.
+        ///
+        /// Default is off
+        #[serde(default)]
+        pub proxy_redirect: ProxyRedirect,
+        /// Upstream `Set-Cookie` domains rewritten to a public domain.
+        ///
+        /// An empty replacement removes the domain attribute.
+        #[serde(default)]
+        pub cookie_domain: BTreeMap<String, String>,
+        /// Upstream `Set-Cookie` path prefixes rewritten to a public path.
+        ///
+        /// An empty replacement removes the path attribute.
+        #[serde(default)]
+        pub cookie_path: BTreeMap<String, String>,
+        /// Additional header names removed as hop-by-hop headers
+        /// in both directions.
+        #[serde(default)]
+        pub hop_by_hop_headers: Vec<String>,
+        /// Prefix removed from the path forwarded upstream.
+        #[serde(default)]
+        pub strip_prefix: Option<String>,
+        /// Prefix added to the path forwarded upstream.
+        #[serde(default)]
+        pub add_prefix: Option<String>,
+        /// Regex and replacement pairs applied to the forwarded path
+        /// after `strip_prefix`. Only the first matching pattern is used.
+        #[serde(default)]
+        pub rewrite: Vec<(String, String)>,
+        /// PEM encoded client certificate chain presented to upstreams.
+        ///
+        /// Requires `client_key`.
+        #[serde(default)]
+        pub client_certificate: Option<PathBuf>,
+        /// PEM encoded private key for `client_certificate`.
+        #[serde(default)]
+        pub client_key: Option<PathBuf>,
+        /// PEM encoded CA bundle trusted for upstream certificates
+        /// in place of the default webpki roots.
+        #[serde(default)]
+        pub ca_file: Option<PathBuf>,
+        /// Number of times failed requests are retried.
+        ///
+        /// Default is 0
+        #[serde(default)]
+        pub retries: Option<u32>,
+        /// Upstream failures retried.
+        ///
+        /// Default is `[connect_error, timeout, 502, 503]`
+        #[serde(default)]
+        pub retry_on: Vec<RetryOn>,
+        /// Time allowed for each individual attempt.
+        ///
+        /// Default is only the overall `timeout`
+        #[serde(default)]
+        pub try_timeout: Option<Duration>,
+        /// Also retry methods that are not idempotent such as `POST`.
+        ///
+        /// Default is false
+        #[serde(default)]
+        pub retry_non_idempotent: bool,
+        /// Shadow upstream receiving a copy of proxied requests.
+        #[serde(default)]
+        pub mirror: Option<MirrorCfg>,
+        /// Read complete request bodies before forwarding them upstream.
+        ///
+        /// Default is false
+        #[serde(default)]
+        pub buffer_requests: bool,
+        /// Read complete upstream response bodies before returning them.
+        ///
+        /// Default is false
+        #[serde(default)]
+        pub buffer_responses: bool,
+        /// Buffered body size held in memory before spilling to a
+        /// temporary file.
+        ///
+        /// Default is 64KiB
+        #[serde(default)]
+        pub buffer_memory_size: Option<usize>,
+        /// Largest body buffered before the remainder is streamed.
+        ///
+        /// Default is 1GiB
+        #[serde(default)]
+        pub buffer_max_size: Option<usize>,
+        /// Directory buffered bodies are spilled to.
+        ///
+        /// Default is the system temporary directory
+        #[serde(default)]
+        pub buffer_spill_dir: Option<PathBuf>,
+        /// Configure the proxy for gRPC upstreams.
+        ///
+        /// Requires HTTP/2 upstream connections, forwards `TE: trailers`
+        /// and disables the request timeout unless one is set.
+        ///
+        /// Default is false
+        #[serde(default)]
+        pub grpc: bool,
+        /// Speak HTTP/2 to plaintext upstreams without negotiation.
+        ///
+        /// Default is false
+        #[serde(default)]
+        pub http2_prior_knowledge: bool,
+        /// Upstream error statuses replaced by an error generated by bob.
+        #[serde(default)]
+        pub intercept_errors: Vec<u16>,
+        /// Upstream connection pool settings.
+        #[serde(default)]
+        pub connection_pool: ConnectionPoolCfg,
+    }
+
+    /// Upstream connection pool configuration.
+    #[cfg_attr(feature = "schema", derive(JsonSchema))]
+    #[derive(Clone, Debug, Default, Deserialize, Serialize)]
+    #[serde(default, deny_unknown_fields)]
+    pub struct ConnectionPoolCfg {
+        /// Maximum number of concurrent upstream connections per worker.
+        ///
+        /// Default is 100
+        pub max_connections: Option<usize>,
+        /// Time idle connections are kept open for reuse.
+        ///
+        /// Default is 15s
+        pub idle_timeout: Option<Duration>,
+        /// Time connections are reused for before being closed.
+        ///
+        /// Default is 75s
+        pub lifetime: Option<Duration>,
+        /// Time allowed to establish a connection.
+        ///
+        /// Default is 5s
+        pub connect_timeout: Option<Duration>,
+        /// Time allowed to complete the TLS handshake.
+        ///
+        /// Default is 5s
+        pub handshake_timeout: Option<Duration>,
+    }
+
+    /// Traffic mirroring configuration.
+    #[cfg_attr(feature = "schema", derive(JsonSchema))]
+    #[derive(Clone, Debug, Deserialize, Serialize)]
+    #[serde(deny_unknown_fields)]
+    pub struct MirrorCfg {
+        /// Shadow upstream base URL.
+        pub resolve: Uri,
+        /// Percentage of requests mirrored.
+        ///
+        /// Default is 100
+        #[serde(default)]
+        pub percent: Option<f64>,
+        /// Mirrored request timeout.
+        ///
+        /// Default is 5s
+        #[serde(default)]
+        pub timeout: Option<Duration>,
+        /// Verify SSL Configuration
+        ///
+        /// Default is true
+        #[serde(default)]
+        pub verify_ssl: Option<bool>,
+    }
+
+    impl MirrorCfg {
+        /// Produce [`crate::services::mirror::Mirror`] from config.
+        fn factory(&self) -> mirror::Mirror {
+            let mut connector = awc::Connector::new();
+            if !self.verify_ssl.unwrap_or(true) {
+                let config = build_custom_tls_config(false, None, None)
+                    .expect("default tls config is valid");
+                connector = connector.rustls_0_23(Arc::new(config));
+            }
+            let client = awc::ClientBuilder::new()
+                .connector(connector)
+                .no_default_headers()
+                .disable_redirects()
+                .finish();
+            let percent = self.percent.unwrap_or(100.0);
+            let settings = mirror::Settings {
+                upstream: self.resolve.0.to_string(),
+                basis_points: (percent * 100.0).round() as u32,
+                timeout: default_duration(&self.timeout, 5),
+            };
+            mirror::Mirror::new(settings, client)
+        }
+    }
+
+    /// Proxied redirect rewriting configuration.
+    #[cfg_attr(feature = "schema", derive(JsonSchema))]
+    #[derive(Clone, Debug, Deserialize, Serialize)]
+    #[serde(untagged)]
+    pub enum ProxyRedirect {
+        /// Builtin rewriting mode.
+        Mode(RedirectMode),
+        /// Explicit prefix replacement rules checked in order.
+        Rules(Vec<RedirectRule>),
+    }
+
+    impl Default for ProxyRedirect {
+        fn default() -> Self {
+            Self::Mode(RedirectMode::Off)
+        }
+    }
+
+    /// Builtin proxied redirect rewriting mode.
+    #[cfg_attr(feature = "schema", derive(JsonSchema))]
+    #[derive(Clone, Debug, Deserialize, Serialize)]
+    #[serde(rename_all = "lowercase")]
+    pub enum RedirectMode {
+        /// Pass redirects through unchanged.
+        Off,
+        /// Replace the `resolve` url with the path the proxy is mounted at.
+        Default,
+    }
+
+    /// Proxied redirect prefix replacement rule.
+    #[cfg_attr(feature = "schema", derive(JsonSchema))]
+    #[derive(Clone, Debug, Deserialize, Serialize)]
+    #[serde(deny_unknown_fields)]
+    pub struct RedirectRule {
+        /// Upstream url prefix to replace.
+        pub from: String,
+        /// Public url or path prefix to substitute.
+        ///
+        /// Default is the path the proxy is mounted at
+        #[serde(default)]
+        pub to: Option<String>,
+    }
+
+    impl Config {
+        /// Validate proxied redirect rewriting rules.
+        pub fn prepare(&self, spec: &Spec) -> anyhow::Result<()> {
+            self.tls_config()?;
+            self.retry_policy()?;
+            if let Some(percent) = self.mirror.as_ref().and_then(|m| m.percent)
+                && !(0.0..=100.0).contains(&percent)
+            {
+                return Err(anyhow!("mirror percent must be between 0 and 100"));
+            }
+            let https = self.resolve.uri().scheme_str() == Some("https");
+            if self.http2_prior_knowledge {
+                if https {
+                    return Err(anyhow!(
+                        "http2_prior_knowledge requires an http or unix upstream"
+                    ));
+                }
+                self.h2c_settings(spec)?;
+            }
+            if self.grpc && !https && !self.http2_prior_knowledge {
+                return Err(anyhow!(
+                    "grpc requires an https upstream or http2_prior_knowledge"
+                ));
+            }
+            if let Some(dir) = self.buffer_spill_dir.as_ref()
+                && !dir.is_dir()
+            {
+                return Err(anyhow!("proxy buffer_spill_dir {dir:?} is not a directory"));
+            }
+            if self.connection_pool.max_connections == Some(0) {
+                return Err(anyhow!("connection_pool max_connections must be above 0"));
+            }
+            if let Some(code) = self
+                .intercept_errors
+                .iter()
+                .find(|c| !(400..=599).contains(*c))
+            {
+                return Err(anyhow!(
+                    "intercept_errors status {code} is not an error status"
+                ));
+            }
+            for prefix in [&self.strip_prefix, &self.add_prefix].into_iter().flatten() {
+                if !prefix.starts_with('/') {
+                    return Err(anyhow!("proxy path prefix {prefix:?} must start with '/'"));
+                }
+            }
+            for (pattern, _) in self.rewrite.iter() {
+                regex::Regex::new(pattern)
+                    .map_err(|err| anyhow!("invalid proxy rewrite {pattern:?}: {err}"))?;
+            }
+            for name in self.hop_by_hop_headers.iter() {
+                HeaderName::try_from(name.as_str())
+                    .map_err(|_| anyhow!("invalid hop-by-hop header {name:?}"))?;
+            }
+            for (from, to) in self.cookie_domain.iter().chain(self.cookie_path.iter()) {
+                if from.is_empty() || to.contains([';', ',']) || to.chars().any(char::is_control) {
+                    return Err(anyhow!("invalid cookie rewrite {from:?} => {to:?}"));
+                }
+            }
+            if let ProxyRedirect::Rules(rules) = &self.proxy_redirect {
+                if rules.iter().any(|r| r.from.is_empty()) {
+                    return Err(anyhow!(
+                        "proxy_redirect rules require a non-empty from prefix"
+                    ));
+                }
+                for to in rules.iter().filter_map(|r| r.to.as_deref()) {
+                    actix_web::http::header::HeaderValue::try_from(to)
+                        .map_err(|_| anyhow!("invalid proxy_redirect replacement {to:?}"))?;
+                }
+            }
+            Ok(())
+        }
+
+        /// Proxied redirect rewriting rules in effect.
+        fn redirect_rules(&self) -> Vec<proxy_redirect::Rule> {
+            match &self.proxy_redirect {
+                ProxyRedirect::Mode(RedirectMode::Off) => vec![],
+                ProxyRedirect::Mode(RedirectMode::Default) => vec![proxy_redirect::Rule {
+                    from: self
+                        .resolve
+                        .uri()
+                        .to_string()
+                        .trim_end_matches('/')
+                        .to_owned(),
+                    to: None,
+                }],
+                ProxyRedirect::Rules(rules) => rules
+                    .iter()
+                    .map(|rule| proxy_redirect::Rule {
+                        from: rule.from.clone(),
+                        to: rule.to.clone(),
+                    })
+                    .collect(),
+            }
+        }
+
+        #[inline]
+        fn retry_policy(&self) -> anyhow::Result<Policy> {
+            policy(
+                self.retries,
+                &self.retry_on,
+                &self.try_timeout,
+                self.retry_non_idempotent,
+            )
+        }
+
+        /// Build upstream tls configuration when it differs from the default.
+        fn tls_config(&self) -> anyhow::Result<Option<rustls::ClientConfig>> {
+            let verify_ssl = self.verify_ssl.unwrap_or(true);
+            let identity = match (&self.client_certificate, &self.client_key) {
+                (Some(cert), Some(key)) => Some((cert.as_path(), key.as_path())),
+                (None, None) => None,
+                _ => {
+                    return Err(anyhow!(
+                        "client_certificate and client_key must be set together"
+                    ));
+                }
+            };
+            if verify_ssl && identity.is_none() && self.ca_file.is_none() {
+                return Ok(None);
+            }
+            build_custom_tls_config(verify_ssl, self.ca_file.as_deref(), identity).map(Some)
+        }
+
+        /// Proxied path rewriting settings if any rewrites are configured.
+        fn path_settings(&self) -> Option<proxy_path::Settings> {
+            if self.strip_prefix.is_none() && self.add_prefix.is_none() && self.rewrite.is_empty() {
+                return None;
+            }
+            Some(proxy_path::Settings {
+                strip_prefix: self.strip_prefix.clone(),
+                rewrite: self
+                    .rewrite
+                    .iter()
+                    .map(|(pattern, replace)| {
+                        let pattern = regex::Regex::new(pattern)
+                            .expect("proxy rewrite validated during config load");
+                        (pattern, replace.clone())
+                    })
+                    .collect(),
+                add_prefix: self.add_prefix.clone(),
+            })
+        }
+
+        /// Upstream request timeout from config or directive timeouts.
+        ///
+        /// Grpc streams may stay open indefinitely so have none by default.
+        fn timeout(&self, spec: &Spec) -> Option<std::time::Duration> {
+            match spec.timeouts.upstream_response(&self.timeout) {
+                Some(timeout) => Some(timeout),
+                None if self.grpc => None,
+                None => Some(std::time::Duration::from_secs(5)),
+            }
+        }
+
+        /// Build h2c upstream settings.
+        fn h2c_settings(&self, spec: &Spec) -> anyhow::Result<h2c::Settings> {
+            let headers = |headers: &BTreeMap<String, String>| {
+                headers
+                    .iter()
+                    .map(|(k, v)| {
+                        let name = HeaderName::try_from(k.as_str())
+                            .map_err(|_| anyhow!("invalid header name {k:?}"))?;
+                        let value = HeaderValue::try_from(v.as_str())
+                            .map_err(|_| anyhow!("invalid header value {v:?}"))?;
+                        Ok((name, value))
+                    })
+                    .collect::<anyhow::Result<Vec<_>>>()
+            };
+            Ok(h2c::Settings {
+                upstream: self.resolve.uri(),
+                socket: match &self.resolve {
+                    UpstreamAddr::Unix(path) => Some(path.clone()),
+                    UpstreamAddr::Http(_) => None,
+                },
+                timeout: self.timeout(spec),
+                change_host: self.change_host,
+                upstream_headers: headers(&self.upstream_headers)?,
+                downstream_headers: headers(&self.downstream_headers)?,
+            })
+        }
+
+        /// Proxy buffering settings if any buffering is enabled.
+        fn buffer_settings(&self) -> Option<proxy_buffer::Settings> {
+            if !self.buffer_requests && !self.buffer_responses {
+                return None;
+            }
+            Some(proxy_buffer::Settings {
+                requests: self.buffer_requests,
+                responses: self.buffer_responses,
+                max_size: Some(self.buffer_max_size.unwrap_or(1024 * 1024 * 1024)),
+                spool: spool::Settings {
+                    memory_limit: self.buffer_memory_size.unwrap_or(64 * 1024),
+                    directory: self
+                        .buffer_spill_dir
+                        .clone()
+                        .unwrap_or_else(std::env::temp_dir),
+                },
+            })
+        }
+
+        /// Hop-by-hop header settings.
+        fn hop_by_hop_settings(&self) -> hop_by_hop::Settings {
+            hop_by_hop::Settings {
+                extra: self
+                    .hop_by_hop_headers
+                    .iter()
+                    .map(|name| {
+                        HeaderName::try_from(name.as_str())
+                            .expect("hop-by-hop header validated during config load")
+                    })
+                    .collect(),
+                keep_te_trailers: self.grpc,
+            }
+        }
+
+        /// Proxied cookie rewriting settings if any rewrites are configured.
+        fn cookie_settings(&self) -> Option<proxy_cookie::Settings> {
+            if self.cookie_domain.is_empty() && self.cookie_path.is_empty() {
+                return None;
+            }
+            let domains = self
+                .cookie_domain
+                .iter()
+                .map(|(from, to)| (from.clone(), to.clone()))
+                .collect();
+            // longest prefix is checked first so nested paths win
+            let mut paths: Vec<_> = self
+                .cookie_path
+                .iter()
+                .map(|(from, to)| (from.clone(), to.clone()))
+                .collect();
+            paths.sort_by_key(|(from, _)| std::cmp::Reverse(from.len()));
+            Some(proxy_cookie::Settings { domains, paths })
+        }
+
+        /// Produce [`actix_revproxy::RevProxy`] from config.
+        pub fn factory(&self, spec: &Spec) -> RevProxy {
+            let mut builder = awc::ClientBuilder::new()
+                .no_default_headers()
+                .initial_connection_window_size(self.initial_conn_size.unwrap_or(u16::MAX as u32))
+                .initial_window_size(self.initial_window_size.unwrap_or(u16::MAX as u32))
+                .max_redirects(self.max_redirects.unwrap_or(0));
+            builder = match self.timeout(spec) {
+                Some(timeout) => builder.timeout(timeout),
+                None => builder.disable_timeout(),
+            };
+            let pool = &self.connection_pool;
+            let mut connector = awc::Connector::new();
+            if let Some(limit) = pool.max_connections {
+                connector = connector.limit(limit);
+            }
+            if let Some(idle) = pool.idle_timeout.as_ref() {
+                connector = connector.conn_keep_alive(idle.0);
+            }
+            // pooled connections must expire for new addresses to be used
+            let lifetime = match (pool.lifetime.as_ref(), self.dns_max_ttl.as_ref()) {
+                (Some(lifetime), Some(ttl)) => Some(lifetime.0.min(ttl.0)),
+                (lifetime, ttl) => lifetime.or(ttl).map(|d| d.0),
+            };
+            if let Some(lifetime) = lifetime {
+                connector = connector.conn_lifetime(lifetime);
+            }
+            if let Some(timeout) = spec.timeouts.upstream_connect(&pool.connect_timeout) {
+                connector = connector.timeout(timeout);
+            }
+            if let Some(timeout) = pool.handshake_timeout.as_ref() {
+                connector = connector.handshake_timeout(timeout.0);
+            }
+            let client = match &self.resolve {
+                UpstreamAddr::Unix(path) => {
+                    // socket upstreams skip dns resolution and tls entirely
+                    let unix = UnixConnector::new(path.clone());
+                    builder.connector(connector.connector(unix)).finish()
+                }
+                UpstreamAddr::Http(_) => {
+                    let mut tls = self
+                        .tls_config()
+                        .expect("upstream tls validated during config load");
+                    // grpc upstreams may only negotiate http/2 through alpn
+                    if self.grpc {
+                        let verify_ssl = self.verify_ssl.unwrap_or(true);
+                        let mut config = tls.unwrap_or_else(|| build_tls_config(verify_ssl));
+                        config.alpn_protocols = vec![b"h2".to_vec()];
+                        tls = Some(config);
+                    }
+                    if let Some(config) = tls {
+                        connector = connector.rustls_0_23(Arc::new(config));
+                    }
+                    connector = connector.resolver(UpstreamResolver::new(Settings {
+                        fallback: self.fallback_addresses.clone(),
+                        last_known: self.last_known_fallback,
+                        max_ttl: self.dns_max_ttl.as_ref().map(|d| d.0),
+                    }));
+                    builder.connector(connector).finish()
+                }
+            };
+            let mut proxy = RevProxy::new("", &self.resolve.uri()).with_client(client);
+            proxy = self
+                .upstream_headers
+                .iter()
+                .fold(proxy, |proxy, (k, v)| proxy.upstream_header(k, v));
+            proxy = self
+                .downstream_headers
+                .iter()
+                .fold(proxy, |proxy, (k, v)| proxy.downstream_header(k, v));
+            if self.change_host {
+                proxy = proxy.change_host();
+            }
+            proxy
+        }
+
+        /// Produce [`actix_chain::Link`] from config.
+        #[inline]
+        pub fn link(&self, spec: &Spec) -> Link {
+            // retries replay requests and must sit directly around the proxy
+            let mut link = match self.http2_prior_knowledge {
+                true => {
+                    let settings = self
+                        .h2c_settings(spec)
+                        .expect("h2c settings validated during config load");
+                    let h2c = h2c::H2c::new(settings);
+                    Link::new(actix_web::web::to(
+                        move |req: actix_web::HttpRequest, payload: actix_web::web::Payload| {
+                            let h2c = h2c.clone();
+                            async move { h2c.handle(req, payload).await }
+                        },
+                    ))
+                }
+                false => Link::new(self.factory(spec)),
+            };
+            let policy = self
+                .retry_policy()
+                .expect("retry policy validated during config load");
+            if policy.retries > 0 {
+                link = link.wrap_with(crate::services::retry::Retry::new(policy));
+            }
+            if let Some(settings) = self.buffer_settings() {
+                link = link.wrap_with(proxy_buffer::ProxyBuffer::new(settings));
+            }
+            if !self.intercept_errors.is_empty() {
+                let statuses = self
+                    .intercept_errors
+                    .iter()
+                    .filter_map(|code| StatusCode::from_u16(*code).ok())
+                    .collect();
+                let settings = intercept::Settings { statuses };
+                link = link.wrap_with(intercept::Intercept::new(settings));
+            }
+            if let Some(mirror) = self.mirror.as_ref() {
+                link = link.wrap_with(mirror.factory());
+            }
+            let hop_by_hop = hop_by_hop::HopByHop::new(self.hop_by_hop_settings());
+            link = link.wrap_with(hop_by_hop);
+            let rules = self.redirect_rules();
+            if !rules.is_empty() {
+                let settings = proxy_redirect::Settings { rules };
+                link = link.wrap_with(proxy_redirect::ProxyRedirect::new(settings));
+            }
+            if let Some(settings) = self.cookie_settings() {
+                link = link.wrap_with(proxy_cookie::ProxyCookie::new(settings));
+            }
+            if let Some(settings) = self.path_settings() {
+                link = link.wrap_with(proxy_path::ProxyPath::new(settings));
+            }
+            link
+        }
+    }
+}
+
+/// Sticky upstream pool module
+#[cfg(feature = "rproxy")]
+pub mod pool {
+    use std::sync::Arc;
+
+    use super::*;
+    use crate::config::{Duration, Uri, default_duration};
+
+    use super::retry::{RetryOn, policy};
+    use crate::services::pool::{Balancer, Matcher, Override, Upstream, register};
+    use crate::services::resolver::{Settings, UpstreamResolver};
+    use crate::tls::client::build_tls_config;
+    use actix_web::http::header::HeaderName;
+
+    /// Sticky upstream pool module configuration.
+    #[cfg_attr(feature = "schema", derive(JsonSchema))]
+    #[derive(Clone, Debug, Deserialize, Serialize)]
+    #[serde(deny_unknown_fields)]
+    pub struct Config {
+        /// Pool name identifying the pool across configuration reloads.
+        pub name: String,
+        /// Upstream base URLs, optionally weighted.
+        pub upstreams: Vec<UpstreamCfg>,
+        /// Requests always routed to a specific upstream.
+        ///
+        /// Checked in order before session affinity.
+        #[serde(default)]
+        pub overrides: Vec<OverrideCfg>,
+        /// Cookie pinning clients to their assigned upstream.
+        ///
+        /// Default is `bob_upstream`
+        #[serde(default)]
+        pub cookie: Option<String>,
+        /// Duration removed upstreams keep serving existing sessions.
+        ///
+        /// Default is 5m
+        #[serde(default)]
+        pub grace: Option<Duration>,
+        /// Upstream request timeout.
+        ///
+        /// Default is the `upstream_response` timeout or 30s
+        #[serde(default)]
+        pub timeout: Option<Duration>,
+        /// Verify SSL Configuration
+        ///
+        /// Default is true
+        #[serde(default)]
+        pub verify_ssl: Option<bool>,
+        /// Number of times failed requests are retried against
+        /// the next upstream in the pool.
+        ///
+        /// Default is 0
+        #[serde(default)]
+        pub retries: Option<u32>,
+        /// Upstream failures retried.
+        ///
+        /// Default is `[connect_error, timeout, 502, 503]`
+        #[serde(default)]
+        pub retry_on: Vec<RetryOn>,
+        /// Time allowed for each individual attempt.
+        ///
+        /// Default is only the overall `timeout`
+        #[serde(default)]
+        pub try_timeout: Option<Duration>,
+        /// Also retry methods that are not idempotent such as `POST`.
+        ///
+        /// Default is false
+        #[serde(default)]
+        pub retry_non_idempotent: bool,
+    }
+
+    /// Pool upstream configuration.
+    #[cfg_attr(feature = "schema", derive(JsonSchema))]
+    #[derive(Clone, Debug, Deserialize, Serialize)]
+    #[serde(untagged)]
+    pub enum UpstreamCfg {
+        /// Upstream base URL with the default weight.
+        Url(Uri),
+        /// Upstream base URL with explicit settings.
+        Weighted {
+            url: Uri,
+            /// Relative share of new sessions.
+            ///
+            /// Default is 1
+            #[serde(default)]
+            weight: Option<u32>,
+            /// Expand the hostname into a member for every resolved
+            /// address, re-resolved as the DNS records expire.
+            ///
+            /// Default is false
+            #[serde(default)]
+            discover: bool,
+        },
+    }
+
+    impl UpstreamCfg {
+        /// Upstream base URL.
+        #[inline]
+        pub fn uri(&self) -> &Uri {
+            match self {
+                Self::Url(uri) | Self::Weighted { url: uri, .. } => uri,
+            }
+        }
+        #[inline]
+        fn url(&self) -> String {
+            self.uri().0.to_string()
+        }
+        #[inline]
+        fn weight(&self) -> u32 {
+            match self {
+                Self::Url(_) => 1,
+                Self::Weighted { weight, .. } => weight.unwrap_or(1),
+            }
+        }
+        #[inline]
+        fn upstream(&self) -> Upstream {
+            Upstream {
+                url: self.url(),
+                weight: self.weight(),
+                discover: matches!(self, Self::Weighted { discover: true, .. }),
+            }
+        }
+    }
+
+    /// Pool routing override configuration.
+    #[cfg_attr(feature = "schema", derive(JsonSchema))]
+    #[derive(Clone, Debug, Deserialize, Serialize)]
+    #[serde(deny_unknown_fields)]
+    pub struct OverrideCfg {
+        /// Request header checked.
+        #[serde(default)]
+        pub header: Option<String>,
+        /// Request cookie checked.
+        #[serde(default)]
+        pub cookie: Option<String>,
+        /// Required header or cookie value.
+        ///
+        /// Default matches any value
+        #[serde(default)]
+        pub value: Option<String>,
+        /// Pool upstream receiving matching requests.
+        pub upstream: Uri,
+    }
+
+    impl OverrideCfg {
+        fn build(&self) -> anyhow::Result<Override> {
+            let matcher = match (&self.header, &self.cookie) {
+                (Some(header), None) => Matcher::Header(
+                    HeaderName::try_from(header.as_str())
+                        .map_err(|_| anyhow!("invalid override header {header:?}"))?,
+                ),
+                (None, Some(cookie)) => Matcher::Cookie(cookie.clone()),
+                _ => return Err(anyhow!("pool override requires either header or cookie")),
+            };
+            Ok(Override {
+                matcher,
+                value: self.value.clone(),
+                upstream: self.upstream.0.to_string(),
+            })
+        }
+    }
+
+    impl Config {
+        #[inline]
+        fn upstreams(&self) -> Vec<Upstream> {
+            self.upstreams.iter().map(UpstreamCfg::upstream).collect()
+        }
+
+        /// Validate upstreams, overrides and the retry policy.
+        ///
+        /// The shared pool state is only reconciled once the balancer
+        /// is built so validation never changes the live pool.
+        pub fn prepare(&self, _spec: &Spec) -> anyhow::Result<()> {
+            if self.name.is_empty() {
+                anyhow::bail!("pool requires a name");
+            }
+            if self.upstreams.is_empty() {
+                anyhow::bail!("pool {:?} requires at least one upstream", self.name);
+            }
+            if self.upstreams.iter().all(|upstream| upstream.weight() == 0) {
+                anyhow::bail!(
+                    "pool {:?} requires an upstream with non-zero weight",
+                    self.name
+                );
+            }
+            let upstreams = self.upstreams();
+            for cfg in self.overrides.iter() {
+                let item = cfg.build()?;
+                if !upstreams
+                    .iter()
+                    .any(|upstream| upstream.url == item.upstream)
+                {
+                    anyhow::bail!(
+                        "pool override upstream {:?} is not in the pool",
+                        item.upstream
+                    );
+                }
+            }
+            policy(
+                self.retries,
+                &self.retry_on,
+                &self.try_timeout,
+                self.retry_non_idempotent,
+            )?;
+            Ok(())
+        }
+
+        #[inline]
+        fn grace(&self) -> std::time::Duration {
+            default_duration(&self.grace, 5 * 60)
+        }
+
+        /// Produce [`crate::services::pool::Balancer`] from config.
+        pub fn factory(&self, spec: &Spec) -> Balancer {
+            let mut connector = awc::Connector::new();
+            if let Some(timeout) = spec.timeouts.upstream_connect(&None) {
+                connector = connector.timeout(timeout);
+            }
+            if !self.verify_ssl.unwrap_or(true) {
+                let config = build_tls_config(false);
+                connector = connector.rustls_0_23(Arc::new(config));
+            }
+            connector = connector.resolver(UpstreamResolver::new(Settings::default()));
+            let client = awc::ClientBuilder::new()
+                .connector(connector)
+                .no_default_headers()
+                .disable_redirects()
+                .timeout(
+                    spec.timeouts
+                        .upstream_response(&self.timeout)
+                        .unwrap_or(std::time::Duration::from_secs(30)),
+                )
+                .finish();
+            let (pool, discovery) = register(&self.name, &self.upstreams(), self.grace());
+            let cookie = self
+                .cookie
+                .clone()
+                .unwrap_or_else(|| "bob_upstream".to_owned());
+            let policy = policy(
+                self.retries,
+                &self.retry_on,
+                &self.try_timeout,
+                self.retry_non_idempotent,
+            )
+            .expect("retry policy validated during config load");
+            let overrides = self
+                .overrides
+                .iter()
+                .map(|cfg| {
+                    cfg.build()
+                        .expect("pool override validated during config load")
+                })
+                .collect();
+            Balancer::new(pool, discovery, cookie, client, policy, overrides)
+        }
+
+        /// Produce [`actix_chain::Link`] from config.
+        #[inline]
+        pub fn link(&self, spec: &Spec) -> Link {
+            let balancer = self.factory(spec);
+            Link::new(actix_web::web::to(
+                move |req: actix_web::HttpRequest, payload: actix_web::web::Payload| {
+                    let balancer = balancer.clone();
+                    async move { balancer.handle(req, payload).await }
+                },
+            ))
+        }
+    }
+}
+
+/// FastCGI module
+#[cfg(feature = "fastcgi")]
+pub mod fastcgi {
+    use super::*;
+
+    use actix_chain::Wrappable;
+    use actix_fastcgi::FastCGI;
+    use std::path::PathBuf;
+
+    use crate::services::deadline::Deadline;
+
+    /// FastCGI module configuration.
+    #[cfg_attr(feature = "schema", derive(JsonSchema))]
+    #[derive(Clone, Debug, Deserialize, Serialize)]
+    #[serde(deny_unknown_fields)]
+    pub struct Config {
+        /// FastCGI socket connection URI.
+        pub connect: String,
+        /// Document-Root assigned to FastCGI.
+        ///
+        /// Overrides [`crate::config::ServerConfig::root`].
+        pub root: Option<PathBuf>,
+    }
+
+    impl Config {
+        /// Produce [`actix_fastcgi::FastCGI`] from config.
+        pub fn factory(&self, spec: &Spec) -> FastCGI {
+            let root = self
+                .root
+                .clone()
+                .or(spec.config.root.clone())
+                .unwrap_or_else(|| PathBuf::from("."));
+            let fastcgi = FastCGI::new("", root, &self.connect);
+            spec.config
+                .index
+                .iter()
+                .fold(fastcgi, |fastcgi, index| fastcgi.index_file(index))
+        }
+
+        /// Produce [`actix_chain::Link`] from config.
+        ///
+        /// The `upstream_response` timeout bounds the whole fastcgi exchange.
+        #[inline]
+        pub fn link(&self, spec: &Spec) -> Link {
+            let link = Link::new(self.factory(spec));
+            match spec.timeouts.upstream_response.as_ref() {
+                Some(timeout) => link.wrap_with(Deadline::new(timeout.0)),
+                None => link,
+            }
+        }
+    }
+}
+
+/// Dynamic upstream module
+#[cfg(feature = "dynamic")]
+pub mod dynamic {
+    use std::path::PathBuf;
+
+    use super::*;
+    use crate::config::{Duration, default_duration};
+    use crate::services::{
+        cgi::{DEFAULT_MAX_OUTPUT, Program},
+        dynamic::{Dynamic, Resolver},
+    };
+
+    /// Dynamic upstream module configuration.
+    ///
+    /// Exactly one of `command` or `socket` must be configured.
+    #[cfg_attr(feature = "schema", derive(JsonSchema))]
+    #[derive(Clone, Debug, Default, Deserialize, Serialize)]
+    #[serde(default, deny_unknown_fields)]
+    pub struct Config {
+        /// Program executed per request to resolve the upstream/response.
+        pub command: Option<PathBuf>,
+        /// Additional arguments passed to the program.
+        pub args: Vec<String>,
+        /// Unix-socket service connected to per request.
+        pub socket: Option<PathBuf>,
+        /// Maximum time allowed for resolution.
+        ///
+        /// Default is 5s
+        pub timeout: Option<Duration>,
+        /// Upstream request timeout.
+        ///
+        /// Default is the `upstream_response` timeout or 30s
+        pub upstream_timeout: Option<Duration>,
+        /// Working directory for command execution.
+        ///
+        /// Overrides [`crate::config::ServerConfig::root`].
+        pub root: Option<PathBuf>,
+    }
+
+    impl Config {
+        /// Validate the configured resolver.
+        pub fn prepare(&self, _spec: &Spec) -> anyhow::Result<()> {
+            match (self.command.is_some(), self.socket.is_some()) {
+                (true, false) | (false, true) => Ok(()),
+                _ => Err(anyhow!(
+                    "dynamic module requires either a command or socket"
+                )),
+            }
+        }
+
+        /// Produce [`crate::services::dynamic::Dynamic`] from config.
+        pub fn factory(&self, spec: &Spec) -> Dynamic {
+            let timeout = default_duration(&self.timeout, 5);
+            let resolver = match (self.command.as_ref(), self.socket.as_ref()) {
+                (Some(command), None) => Resolver::Command(Program {
+                    command: command.to_owned(),
+                    args: self.args.clone(),
+                    workdir: self.root.clone().or(spec.config.root.clone()),
+                    timeout,
+                    max_output: DEFAULT_MAX_OUTPUT,
+                }),
+                (None, Some(path)) => Resolver::Socket {
+                    path: path.to_owned(),
+                    timeout,
+                },
+                _ => unreachable!("dynamic resolver validated during config load"),
+            };
+            let client = awc::ClientBuilder::new()
+                .no_default_headers()
+                .disable_redirects()
+                .timeout(
+                    spec.timeouts
+                        .upstream_response(&self.upstream_timeout)
+                        .unwrap_or(std::time::Duration::from_secs(30)),
+                )
+                .finish();
+            Dynamic::new(resolver, client)
+        }
+
+        /// Produce [`actix_chain::Link`] from config.
+        #[inline]
+        pub fn link(&self, spec: &Spec) -> Link {
+            let dynamic = self.factory(spec);
+            Link::new(actix_web::web::to(
+                move |req: actix_web::HttpRequest, payload: actix_web::web::Payload| {
+                    let dynamic = dynamic.clone();
+                    async move { dynamic.handle(req, payload).await }
+                },
+            ))
+        }
+    }
+}
+
+/// CGI script execution module
+#[cfg(feature = "cgi")]
+pub mod cgi {
+    use std::{collections::BTreeMap, path::PathBuf};
+
+    use super::*;
+    use crate::config::{Duration, default_duration};
+    use crate::services::{
+        cgi::{Active, program_env},
+        cgi_bin::{CgiBin, Settings},
+    };
+
+    /// CGI script execution module configuration.
+    #[cfg_attr(feature = "schema", derive(JsonSchema))]
+    #[derive(Clone, Debug, Default, Deserialize, Serialize)]
+    #[serde(default, deny_unknown_fields)]
+    pub struct Config {
+        /// Directory containing executable scripts.
+        ///
+        /// Overrides [`crate::config::ServerConfig::root`].
+        pub root: Option<PathBuf>,
+        /// File extensions allowed to execute.
+        ///
+        /// Any executable file is run when empty.
+        pub extensions: Vec<String>,
+        /// Interpreters used to run scripts by file extension.
+        ///
+        /// Scripts with a configured interpreter need not be executable.
+        pub interpreters: BTreeMap<String, PathBuf>,
+        /// Additional environment variables passed to scripts.
+        ///
+        /// `PATH` is inherited from bob unless configured here.
+        pub env: BTreeMap<String, String>,
+        /// Maximum time allowed for a script to complete.
+        ///
+        /// Default is 30s
+        pub timeout: Option<Duration>,
+        /// Maximum number of scripts running at once across all workers.
+        ///
+        /// Default is 16
+        pub max_concurrent: Option<usize>,
+        /// Largest request body passed to scripts.
+        ///
+        /// Default is 10MiB
+        pub max_body_size: Option<usize>,
+        /// Largest script output read before the script is failed.
+        ///
+        /// Default is 64MiB
+        pub max_output_size: Option<usize>,
+
+        // global running script count.
+        // shares the concurrency limit between every worker actix-web creates.
+        #[serde(skip)]
+        active: Active,
+    }
+
+    impl Config {
+        /// Script directory from config or server root.
+        fn root(&self, spec: &Spec) -> Option<PathBuf> {
+            self.root.clone().or(spec.config.root.clone())
+        }
+
+        /// Validate script directory and limits.
+        pub fn prepare(&self, spec: &Spec) -> anyhow::Result<()> {
+            let root = self
+                .root(spec)
+                .ok_or_else(|| anyhow!("cgi module requires a root directory"))?;
+            if !root.is_dir() {
+                return Err(anyhow!("cgi root {root:?} is not a directory"));
+            }
+            if self.max_concurrent == Some(0) {
+                return Err(anyhow!("cgi max_concurrent must be greater than zero"));
+            }
+            for (ext, interpreter) in self.interpreters.iter() {
+                if !interpreter.is_file() {
+                    return Err(anyhow!(
+                        "cgi interpreter {interpreter:?} for {ext:?} does not exist"
+                    ));
+                }
+            }
+            Ok(())
+        }
+
+        /// Produce [`crate::services::cgi_bin::CgiBin`] from config.
+        pub fn factory(&self, spec: &Spec) -> CgiBin {
+            let root = self
+                .root(spec)
+                .expect("cgi root validated during config load");
+            let env = program_env(&self.env);
+            let settings = Settings {
+                root: root.canonicalize().unwrap_or(root),
+                extensions: self
+                    .extensions
+                    .iter()
+                    .map(|ext| ext.trim_start_matches('.').to_owned())
+                    .collect(),
+                interpreters: self
+                    .interpreters
+                    .iter()
+                    .map(|(ext, path)| (ext.trim_start_matches('.').to_owned(), path.clone()))
+                    .collect(),
+                env,
+                timeout: default_duration(&self.timeout, 30),
+                max_concurrent: self.max_concurrent.unwrap_or(16),
+                max_body_size: self.max_body_size.unwrap_or(10 * 1024 * 1024),
+                max_output_size: self.max_output_size.unwrap_or(64 * 1024 * 1024),
+            };
+            CgiBin::new(settings, self.active.clone())
+        }
+
+        /// Produce [`actix_chain::Link`] from config.
+        #[inline]
+        pub fn link(&self, spec: &Spec) -> Link {
+            let cgi = self.factory(spec);
+            Link::new(actix_web::web::to(
+                move |req: actix_web::HttpRequest, payload: actix_web::web::Payload| {
+                    let cgi = cgi.clone();
+                    async move { cgi.handle(req, payload).await }
+                },
+            ))
+        }
+    }
+}
+
+/// Git smart-HTTP module
+#[cfg(feature = "git")]
+pub mod git {
+    use std::{collections::BTreeMap, path::PathBuf};
+
+    use super::*;
+    use crate::config::{Duration, default_duration};
+    use crate::services::{
+        cgi::{DEFAULT_MAX_OUTPUT, Program, program_env},
+        git::{Access, Git, Settings},
+        passwd,
+    };
+
+    /// Repository access rules.
+    #[cfg_attr(feature = "schema", derive(JsonSchema))]
+    #[derive(Clone, Debug, Deserialize, Serialize)]
+    #[serde(default, deny_unknown_fields)]
+    pub struct RepoConfig {
+        /// Allow anonymous clients to clone and fetch.
+        ///
+        /// Default is true
+        pub public: bool,
+        /// Users allowed to clone and fetch when not public.
+        ///
+        /// Any configured user when empty.
+        pub read: Vec<String>,
+        /// Users allowed to push.
+        ///
+        /// Nobody when empty.
+        pub write: Vec<String>,
+    }
+
+    impl Default for RepoConfig {
+        fn default() -> Self {
+            Self {
+                public: true,
+                read: vec![],
+                write: vec![],
+            }
+        }
+    }
+
+    /// Git smart-HTTP module configuration.
+    #[cfg_attr(feature = "schema", derive(JsonSchema))]
+    #[derive(Clone, Debug, Deserialize, Serialize)]
+    #[serde(deny_unknown_fields)]
+    pub struct Config {
+        /// Directory containing served repositories.
+        ///
+        /// Overrides [`crate::config::ServerConfig::root`].
+        #[serde(default)]
+        pub root: Option<PathBuf>,
+        /// Git executable used to run `git http-backend`.
+        ///
+        /// Default is `git` found within `PATH`
+        #[serde(default)]
+        pub git: Option<PathBuf>,
+        /// Serve repositories without a `git-daemon-export-ok` file.
+        ///
+        /// Default is true
+        #[serde(default)]
+        pub export_all: Option<bool>,
+        /// Usernames and htpasswd compatible hashes of their passwords
+        /// accepted as `Authorization` basic credentials.
+        #[serde(default)]
+        pub users: BTreeMap<String, crate::config::Secret>,
+        /// Access rules by repository path relative to the root.
+        ///
+        /// `*` applies to repositories not listed. Unlisted repositories
+        /// are public and read-only by default.
+        #[serde(default)]
+        pub repos: BTreeMap<String, RepoConfig>,
+        /// Program run to authorize requests once access rules pass.
+        ///
+        /// Receives the CGI environment along with `GIT_REPO`,
+        /// `GIT_ACCESS` and `REMOTE_USER`. Replying with a non-2xx
+        /// `Status` rejects the request with that response.
+        #[serde(default)]
+        pub auth_hook: Option<PathBuf>,
+        /// Additional environment variables passed to the backend.
+        ///
+        /// `PATH` is inherited from bob unless configured here.
+        #[serde(default)]
+        pub env: BTreeMap<String, String>,
+        /// Maximum time allowed for a request to complete.
+        ///
+        /// Default is 5m
+        #[serde(default)]
+        pub timeout: Option<Duration>,
+        /// Largest request body accepted, bounding pushes.
+        ///
+        /// Default is 100MiB
+        #[serde(default)]
+        pub max_body_size: Option<usize>,
+    }
+
+    impl Config {
+        /// Repository directory from config or server root.
+        fn root(&self, spec: &Spec) -> Option<PathBuf> {
+            self.root.clone().or(spec.config.root.clone())
+        }
+
+        /// Validate repository directory, credentials and access rules.
+        pub fn prepare(&self, spec: &Spec) -> anyhow::Result<()> {
+            let root = self
+                .root(spec)
+                .ok_or_else(|| anyhow!("git module requires a root directory"))?;
+            if !root.is_dir() {
+                return Err(anyhow!("git root {root:?} is not a directory"));
+            }
+            for (user, hash) in self.users.iter() {
+                if user.contains(':') {
+                    return Err(anyhow!("git user {user:?} contains ':'"));
+                }
+                if !passwd::supported(hash) {
+                    return Err(anyhow!(
+                        "git user {user:?} password must be a bcrypt, sha-crypt or md5-crypt hash"
+                    ));
+                }
+            }
+            for (repo, access) in self.repos.iter() {
+                if let Some(user) = access
+                    .read
+                    .iter()
+                    .chain(access.write.iter())
+                    .find(|u| !self.users.contains_key(*u))
+                {
+                    return Err(anyhow!(
+                        "git repo {repo:?} references unknown user {user:?}"
+                    ));
+                }
+            }
+            if let Some(hook) = self.auth_hook.as_ref()
+                && !hook.is_file()
+            {
+                return Err(anyhow!("git auth hook {hook:?} does not exist"));
+            }
+            Ok(())
+        }
+
+        /// Produce [`crate::services::git::Git`] from config.
+        pub fn factory(&self, spec: &Spec) -> Git {
+            let root = self
+                .root(spec)
+                .expect("git root validated during config load");
+            let root = root.canonicalize().unwrap_or(root);
+            let timeout = default_duration(&self.timeout, 5 * 60);
+            let env = program_env(&self.env);
+            let settings = Settings {
+                git: self.git.clone().unwrap_or_else(|| PathBuf::from("git")),
+                export_all: self.export_all.unwrap_or(true),
+                credentials: self
+                    .users
+                    .iter()
+                    .map(|(user, hash)| (user.clone(), hash.0.clone()))
+                    .collect(),
+                repos: self
+                    .repos
+                    .iter()
+                    .map(|(repo, access)| {
+                        let access = Access {
+                            public: access.public,
+                            read: access.read.clone(),
+                            write: access.write.clone(),
+                        };
+                        (repo.trim_matches('/').to_owned(), access)
+                    })
+                    .collect(),
+                hook: self.auth_hook.as_ref().map(|hook| Program {
+                    command: hook.to_owned(),
+                    args: vec![],
+                    workdir: Some(root.clone()),
+                    timeout,
+                    max_output: DEFAULT_MAX_OUTPUT,
+                }),
+                env,
+                timeout,
+                max_body_size: self.max_body_size.unwrap_or(100 * 1024 * 1024),
+                root,
+            };
+            Git::new(settings)
+        }
+
+        /// Produce [`actix_chain::Link`] from config.
+        #[inline]
+        pub fn link(&self, spec: &Spec) -> Link {
+            let git = self.factory(spec);
+            Link::new(actix_web::web::to(
+                move |req: actix_web::HttpRequest, payload: actix_web::web::Payload| {
+                    let git = git.clone();
+                    async move { git.handle(req, payload).await }
+                },
+            ))
+        }
+    }
+}
+
+/// Forward proxy module
+#[cfg(feature = "rproxy")]
+pub mod forward {
+    use std::collections::BTreeMap;
+
+    use super::*;
+    use crate::config::{DomainMatch, Duration};
+    use crate::services::{
+        forward::{Forward, Settings},
+        passwd,
+    };
+
+    /// Forward proxy module configuration.
+    #[cfg_attr(feature = "schema", derive(JsonSchema))]
+    #[derive(Clone, Debug, Deserialize, Serialize)]
+    #[serde(deny_unknown_fields)]
+    pub struct Config {
+        /// Destination hosts clients may connect to.
+        ///
+        /// Uses glob syntax. `*` allows every host.
+        pub allow_hosts: Vec<DomainMatch>,
+        /// Destination ports clients may connect to.
+        ///
+        /// Default is `[80, 443]`
+        #[serde(default)]
+        pub allow_ports: Option<Vec<u16>>,
+        /// Allow destinations resolving to private, loopback or
+        /// link-local addresses.
+        ///
+        /// Default is false
+        #[serde(default)]
+        pub allow_private: bool,
+        /// Usernames and htpasswd compatible hashes of their passwords
+        /// (bcrypt, sha-crypt or md5-crypt) required as
+        /// `Proxy-Authorization` basic credentials.
+        ///
+        /// Authentication is disabled when empty.
+        #[serde(default)]
+        pub users: BTreeMap<String, crate::config::Secret>,
+        /// Time allowed to connect to `CONNECT` destinations.
+        ///
+        /// Default is the `upstream_connect` timeout or 10s
+        #[serde(default)]
+        pub connect_timeout: Option<Duration>,
+        /// Forwarded request timeout.
+        ///
+        /// Default is the `upstream_response` timeout or 30s
+        #[serde(default)]
+        pub timeout: Option<Duration>,
+    }
+
+    impl Config {
+        /// Validate destination allowlists and credentials.
+        pub fn prepare(&self, _spec: &Spec) -> anyhow::Result<()> {
+            if self.allow_hosts.is_empty() {
+                return Err(anyhow!("forward proxy requires at least one allowed host"));
+            }
+            if self
+                .allow_ports
+                .as_ref()
+                .is_some_and(|ports| ports.is_empty())
+            {
+                return Err(anyhow!("forward proxy requires at least one allowed port"));
+            }
+            for (user, hash) in self.users.iter() {
+                if user.contains(':') {
+                    return Err(anyhow!("forward proxy user {user:?} contains ':'"));
+                }
+                if !passwd::supported(hash) {
+                    return Err(anyhow!(
+                        "forward proxy user {user:?} password must be a bcrypt, sha-crypt or md5-crypt hash"
+                    ));
+                }
+            }
+            Ok(())
+        }
+
+        /// Produce [`crate::services::forward::Forward`] from config.
+        pub fn factory(&self, spec: &Spec) -> Forward {
+            let settings = Settings {
+                credentials: self
+                    .users
+                    .iter()
+                    .map(|(user, hash)| (user.clone(), hash.0.clone()))
+                    .collect(),
+                hosts: self.allow_hosts.clone(),
+                ports: self.allow_ports.clone().unwrap_or_else(|| vec![80, 443]),
+                allow_private: self.allow_private,
+                connect_timeout: spec
+                    .timeouts
+                    .upstream_connect(&self.connect_timeout)
+                    .unwrap_or(std::time::Duration::from_secs(10)),
+            };
+            let client = awc::ClientBuilder::new()
+                .no_default_headers()
+                .disable_redirects()
+                .timeout(
+                    spec.timeouts
+                        .upstream_response(&self.timeout)
+                        .unwrap_or(std::time::Duration::from_secs(30)),
+                )
+                .finish();
+            Forward::new(settings, client)
+        }
+
+        /// Produce [`actix_chain::Link`] from config.
+        #[inline]
+        pub fn link(&self, spec: &Spec) -> Link {
+            let forward = self.factory(spec);
+            Link::new(actix_web::web::to(
+                move |req: actix_web::HttpRequest, payload: actix_web::web::Payload| {
+                    let forward = forward.clone();
+                    async move { forward.handle(req, payload).await }
+                },
+            ))
+        }
+    }
+}
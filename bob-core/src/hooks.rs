@@ -0,0 +1,91 @@
+//! Lifecycle Hook Scripts
+//!
+//! Runs configured external commands when server lifecycle events
+//! occur so deployments can coordinate with service discovery
+//! or notify load balancers.
+
+use std::{
+    process::{Command, Stdio},
+    time::{Duration, Instant},
+};
+
+use crate::config::{HookCfg, ServerConfig, default_duration};
+
+/// Interval used to poll for hook completion.
+const POLL_INTERVAL: Duration = Duration::from_millis(10);
+
+/// Server lifecycle events supporting hooks.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Event {
+    PreReload,
+    PostReload,
+    PreShutdown,
+    #[cfg_attr(not(feature = "acme"), allow(dead_code))]
+    CertRenewed,
+}
+
+impl Event {
+    /// Event name exported to hooks as `BOB_EVENT`.
+    pub fn name(&self) -> &'static str {
+        match self {
+            Self::PreReload => "pre_reload",
+            Self::PostReload => "post_reload",
+            Self::PreShutdown => "pre_shutdown",
+            Self::CertRenewed => "cert_renewed",
+        }
+    }
+}
+
+/// Run every hook configured for the event across all servers.
+///
+/// Hooks run sequentially in configuration order. Failures are
+/// logged but never prevent the lifecycle event from continuing.
+pub async fn fire(configs: &[ServerConfig], event: Event) {
+    let hooks: Vec<HookCfg> = configs
+        .iter()
+        .filter(|cfg| !cfg.disable)
+        .flat_map(|cfg| cfg.hooks.get(event).iter().cloned())
+        .collect();
+    run_all(hooks, event).await
+}
+
+/// Run the given hooks sequentially for the event.
+pub async fn run_all(hooks: Vec<HookCfg>, event: Event) {
+    for hook in hooks {
+        log::info!("running {} hook {:?}", event.name(), hook.command);
+        let command = hook.command.clone();
+        match actix_web::web::block(move || run(&hook, event)).await {
+            Ok(Ok(())) => log::debug!("{} hook {command:?} complete", event.name()),
+            Ok(Err(err)) => log::error!("{} hook {command:?} failed: {err}", event.name()),
+            Err(err) => log::error!("{} hook {command:?} failed: {err}", event.name()),
+        }
+    }
+}
+
+/// Run hook to completion, killing it once its timeout expires.
+fn run(hook: &HookCfg, event: Event) -> std::io::Result<()> {
+    let mut child = Command::new(&hook.command)
+        .args(&hook.args)
+        .env("BOB_EVENT", event.name())
+        .stdin(Stdio::null())
+        .spawn()?;
+
+    let deadline = Instant::now() + default_duration(&hook.timeout, 30);
+    loop {
+        if let Some(status) = child.try_wait()? {
+            return match status.success() {
+                true => Ok(()),
+                false => Err(std::io::Error::other(format!("exited with {status}"))),
+            };
+        }
+        if Instant::now() >= deadline {
+            let _ = child.kill();
+            let _ = child.wait();
+            return Err(std::io::Error::new(
+                std::io::ErrorKind::TimedOut,
+                "hook timed out",
+            ));
+        }
+        std::thread::sleep(POLL_INTERVAL);
+    }
+}
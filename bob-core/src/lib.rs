@@ -0,0 +1,626 @@
+//! Embeddable bob server.
+//!
+//! Provides the configuration types, request modules, middleware and tls
+//! setup behind the `bob` binary so other applications can serve the same
+//! routing and middleware stack programmatically.
+//!
+//! ```no_run
+//! use bob_core::{Server, config::ServerConfig};
+//!
+//! #[actix_web::main]
+//! async fn main() -> anyhow::Result<()> {
+//!     let config: ServerConfig = serde_yaml::from_str("listen: [{ port: 8000 }]")?;
+//!     Server::builder().config(config).run().await
+//! }
+//! ```
+#![cfg_attr(feature = "doc", feature(doc_cfg))]
+
+use std::{path::PathBuf, time::Duration};
+
+use actix_chain::{Chain, Link};
+use actix_web::{
+    App, Error, HttpResponse,
+    body::BoxBody,
+    dev::{Server as HttpServer, ServiceFactory, ServiceRequest, ServiceResponse},
+    http::StatusCode,
+    middleware::{DefaultHeaders, Logger},
+};
+use anyhow::{Context, Result, anyhow};
+use bob_guard::RequestFlags;
+
+pub mod config;
+pub mod hooks;
+pub mod listener;
+pub mod metrics;
+pub mod plugin;
+pub mod services;
+pub mod tls;
+
+use crate::{
+    config::{ServerConfig, ServerNameGuard, SlashPolicy, Spec},
+    tls::server::TlsInfo,
+};
+
+/// Validated configuration ready to be served.
+#[derive(Clone, Debug)]
+pub struct Server {
+    config: Vec<ServerConfig>,
+    hash: String,
+}
+
+impl Server {
+    /// Start building a server from configuration.
+    #[inline]
+    pub fn builder() -> ServerBuilder {
+        ServerBuilder::default()
+    }
+
+    /// Server configurations being served.
+    #[inline]
+    pub fn config(&self) -> &[ServerConfig] {
+        &self.config
+    }
+
+    /// Hash of the effective configuration.
+    #[inline]
+    pub fn hash(&self) -> &str {
+        &self.hash
+    }
+
+    /// Connect external services and bind all configured listeners.
+    ///
+    /// Process signals are not handled by the returned server,
+    /// use its [`actix_web::dev::ServerHandle`] to stop it instead.
+    pub async fn start(&self) -> Result<HttpServer> {
+        config::connect_config(&self.config).await?;
+        spawn_server(&self.config, self.hash.clone())
+    }
+
+    /// Serve requests until the server is stopped.
+    pub async fn run(self) -> Result<()> {
+        self.start().await?.await.context("server spawn failed")
+    }
+}
+
+/// Builder for [`Server`].
+#[derive(Debug, Default)]
+pub struct ServerBuilder {
+    config: Vec<ServerConfig>,
+}
+
+impl ServerBuilder {
+    /// Add a server configuration.
+    pub fn config(mut self, config: ServerConfig) -> Self {
+        self.config.push(config);
+        self
+    }
+
+    /// Add every server configuration within a yaml config file.
+    pub fn config_file(mut self, path: impl Into<PathBuf>) -> Result<Self> {
+        self.config.extend(config::read_config(&path.into())?);
+        Ok(self)
+    }
+
+    /// Validate and prepare the configuration.
+    pub fn build(self) -> Result<Server> {
+        if self.config.is_empty() {
+            return Err(anyhow!("no server configured"));
+        }
+        config::prepare_config(&self.config)?;
+        let hash = config::config_hash(&self.config);
+        Ok(Server {
+            config: self.config,
+            hash,
+        })
+    }
+
+    /// Validate the configuration and serve requests until stopped.
+    ///
+    /// Must be called within an actix runtime such as [`actix_web::main`].
+    pub async fn run(self) -> Result<()> {
+        self.build()?.run().await
+    }
+}
+
+#[inline]
+fn logger(config: &ServerConfig) -> Logger {
+    let use_peer_addr = use_peer_addr(config);
+    let anonymize = config.logging.anonymize.as_ref().map(|a| a.anonymizer());
+    let users = anonymize.clone().filter(|a| a.hash_users);
+    let format = match config.logging.format.as_deref() {
+        Some(format) => format,
+        None if users.is_some() => USER_LOG_FORMAT,
+        None if use_peer_addr || anonymize.is_some() => IP_LOG_FORMAT,
+        None => DEFAULT_LOG_FORMAT,
+    };
+
+    let mut log = Logger::new(format);
+    if format.contains("%{ip}xo") {
+        log = log.custom_response_replace("ip", move |res| {
+            let ip = services::access_log::client_ip(res.request(), use_peer_addr)
+                .unwrap_or_else(|| "-".to_owned());
+            match anonymize.as_ref() {
+                Some(anonymize) => anonymize.ip(&ip),
+                None => ip,
+            }
+        });
+    }
+    if format.contains("%{user}xi") {
+        log = log.custom_request_replace("user", move |req| match users.as_ref() {
+            Some(users) => users.user(req.request()),
+            None => "-".to_owned(),
+        });
+    }
+    let tls_fields: [(&str, fn(&TlsInfo) -> Option<String>); 4] = [
+        ("tls_sni", |tls| tls.sni.clone()),
+        ("tls_alpn", |tls| tls.alpn.clone()),
+        ("tls_version", |tls| Some(tls.version.clone())),
+        ("tls_cipher", |tls| Some(tls.cipher.clone())),
+    ];
+    for (label, field) in tls_fields {
+        if format.contains(&format!("%{{{label}}}xi")) {
+            log = log.custom_request_replace(label, move |req| {
+                req.conn_data::<TlsInfo>()
+                    .and_then(field)
+                    .unwrap_or_else(|| "-".to_owned())
+            });
+        }
+    }
+    #[cfg(feature = "requestid")]
+    if format.contains("%{request_id}xo") {
+        log = log.custom_response_replace("request_id", |res| {
+            res.request()
+                .extensions()
+                .get::<services::request_id::Id>()
+                .map(|id| id.0.clone())
+                .unwrap_or_else(|| "-".to_owned())
+        });
+    }
+    #[cfg(feature = "geoip")]
+    {
+        use services::geoip::GeoInfo;
+        let geo_fields: [(&str, fn(&GeoInfo) -> Option<String>); 2] = [
+            ("geo_country", |geo| geo.country.clone()),
+            ("geo_asn", |geo| geo.asn.map(|asn| asn.to_string())),
+        ];
+        for (label, field) in geo_fields {
+            if format.contains(&format!("%{{{label}}}xo")) {
+                log = log.custom_response_replace(label, move |res| {
+                    res.request()
+                        .extensions()
+                        .get::<GeoInfo>()
+                        .and_then(field)
+                        .unwrap_or_else(|| "-".to_owned())
+                });
+            }
+        }
+    }
+
+    log.log_level(log_level(config))
+}
+
+#[inline]
+fn access_log(config: &ServerConfig) -> services::access_log::AccessLog {
+    services::access_log::AccessLog::new(services::access_log::Settings {
+        template: config.logging.access_log.clone(),
+        level: log_level(config),
+        sample: config.logging.sample,
+        use_peer_addr: use_peer_addr(config),
+        anonymize: config.logging.anonymize.as_ref().map(|a| a.anonymizer()),
+        names: config.server_name.clone(),
+    })
+}
+
+#[inline]
+fn log_filter(config: &ServerConfig) -> services::log_filter::Settings {
+    services::log_filter::Settings {
+        skip_paths: config.logging.skip_paths.clone(),
+        locations: config
+            .directives
+            .iter()
+            .map(|d| {
+                let location = d.location.clone().unwrap_or_default();
+                (
+                    format!("/{}", location.trim_start_matches('/')),
+                    d.log.unwrap_or(true),
+                )
+            })
+            .collect(),
+    }
+}
+
+#[inline]
+fn log_level(config: &ServerConfig) -> log::Level {
+    config
+        .logging
+        .log_level
+        .clone()
+        .map(|l| l.0)
+        .unwrap_or(log::Level::Info)
+}
+
+/// Check if logged client address should come from IpWare.
+#[inline]
+#[cfg_attr(not(feature = "ipware"), allow(unused_variables))]
+fn use_peer_addr(config: &ServerConfig) -> bool {
+    #[cfg(feature = "ipware")]
+    return config.logging.use_ipware.unwrap_or(true);
+
+    #[cfg(not(feature = "ipware"))]
+    false
+}
+
+/// Default access log format used by [`actix_web::middleware::Logger`].
+const DEFAULT_LOG_FORMAT: &str = r#"%a "%r" %s %b "%{Referer}i" "%{User-Agent}i" %T"#;
+
+/// Access log format using the resolved (and anonymized) client address.
+const IP_LOG_FORMAT: &str = r#"%{ip}xo "%r" %s %b "%{Referer}i" "%{User-Agent}i" %T"#;
+
+/// Access log format additionally including hashed user identifiers.
+const USER_LOG_FORMAT: &str = r#"%{ip}xo %{user}xi "%r" %s %b "%{Referer}i" "%{User-Agent}i" %T"#;
+
+/// Path used to report the configuration hash when enabled.
+pub const CONFIG_HASH_PATH: &str = "-/config-hash";
+
+/// Assemble [`actix_chain::Chain`] from server configuration instance.
+///
+/// Chains may be mounted within any [`actix_web::App`] to embed the
+/// routing and middleware stack of the server. The configuration must
+/// be loaded with [`config::prepare_config`] and [`config::connect_config`].
+pub fn assemble_chain(config: &ServerConfig, hash: &str) -> Result<Chain> {
+    config.loaded()?;
+    Ok(build_chain(config, hash, None))
+}
+
+/// Assemble server chain, recording the entered server and directives
+/// into the request [`services::trace::Trace`] when an index is given.
+fn build_chain(config: &ServerConfig, hash: &str, trace: Option<usize>) -> Chain {
+    use services::trace::{Hop, Traced};
+    let mut chain = Chain::default();
+    if !config.server_name.is_empty() && !config.default_server {
+        chain = chain.guard(ServerNameGuard {
+            names: config.server_name.clone(),
+            ports: match config.server_name_port {
+                true => config.listen.iter().map(|l| l.port).collect(),
+                false => vec![],
+            },
+        });
+    }
+
+    if let Some(path) = config.runtime.readiness_path.as_ref() {
+        let route = actix_web::web::get().to(|| async { services::drain::readiness() });
+        let path = path.trim_start_matches('/');
+        chain.push_link(Chain::new(path).link(Link::new(route)).into());
+    }
+
+    if let Some(robots) = config.robots.as_ref() {
+        chain.push_link(
+            Chain::new("robots.txt")
+                .link(Link::new(robots.factory()))
+                .into(),
+        );
+    }
+
+    if config.config_hash.endpoint {
+        let hash = hash.to_owned();
+        let route = actix_web::web::get().to(move || {
+            let hash = hash.clone();
+            async move { HttpResponse::Ok().content_type("text/plain").body(hash) }
+        });
+        chain.push_link(Chain::new(CONFIG_HASH_PATH).link(Link::new(route)).into());
+    }
+
+    let spec = Spec::new(config);
+    for (d, directive) in config.directives.iter().enumerate() {
+        let location = directive.location.clone().unwrap_or_default();
+        let dspec = Spec::directive(config, directive);
+        let prefix = location.trim_start_matches('/');
+
+        // chains skipping toggled middleware are tried before the full chain
+        for skipped in directive.skip_variants() {
+            let base = directive
+                .guards
+                .iter()
+                .cloned()
+                .fold(Chain::new(prefix), |chain, guard| chain.guard(guard));
+            let base = skipped.iter().fold(base, |chain, flag| {
+                let flag = flag.to_string();
+                chain.guard(actix_web::guard::fn_guard(move |ctx| {
+                    ctx.req_data()
+                        .get::<RequestFlags>()
+                        .is_some_and(|flags| flags.contains(&flag))
+                }))
+            });
+            let mut directive_chain = directive
+                .construct
+                .iter()
+                .filter(|c| c.skip_flag().is_none_or(|flag| !skipped.contains(&flag)))
+                .fold(base.clone(), |chain, c| c.apply(chain, &dspec));
+            if let Some(budget) = directive.budget.as_ref() {
+                directive_chain = directive_chain.wrap(budget.factory(&format!("/{prefix}")));
+                if let Some(fallback) = directive.fallback.as_ref() {
+                    let degraded = services::budget::Degraded(budget.state.clone());
+                    let fallback = fallback
+                        .iter()
+                        .fold(base.clone().guard(degraded), |chain, c| {
+                            c.apply(chain, &dspec)
+                        });
+                    chain.push_link(fallback.into());
+                }
+            }
+            if directive.slash_policy != SlashPolicy::Ignore {
+                let policy = directive.slash_policy.clone();
+                let canonical = services::canonical::Settings::slash(policy);
+                directive_chain =
+                    directive_chain.wrap(services::canonical::Canonical::new(canonical));
+            }
+            if let Some(total) = dspec.timeouts.total.as_ref() {
+                let deadline = services::deadline::Deadline::new(total.0);
+                directive_chain = directive_chain.wrap(deadline);
+            }
+            if let Some(timeout) = dspec.timeouts.client_read.as_ref() {
+                directive_chain = directive_chain.wrap(services::slow_client::SlowClient::new(
+                    services::slow_client::Settings {
+                        body_timeout: Some(timeout.0),
+                        min_rate: None,
+                        grace: Duration::ZERO,
+                    },
+                ));
+            }
+            if let Some(limit) = directive.max_body_size.or(config.max_body_size) {
+                let limit = services::body_limit::BodyLimit::new(limit);
+                directive_chain = directive_chain.wrap(limit);
+            }
+            if config.metrics.body_sizes || config.metrics.log_larger_than.is_some() {
+                let metrics = services::body_metrics::SizeMetrics::new(
+                    &format!("/{prefix}"),
+                    config.metrics.log_larger_than,
+                );
+                directive_chain = directive_chain.wrap(metrics);
+            }
+            if trace.is_some() {
+                directive_chain = directive_chain.wrap(Traced(Hop::Directive(d)));
+            }
+            let link: Link = directive_chain.into();
+
+            chain.push_link(link);
+        }
+    }
+
+    chain = config
+        .middleware
+        .iter()
+        .filter(|m| !m.resolves_client())
+        .fold(chain, |chain, m| m.wrap(chain, &spec));
+    if let Some(canonical) = config.canonical.as_ref() {
+        let forwarded = config.middleware.iter().any(|m| m.trusts_proxies());
+        chain = chain.wrap(canonical.factory(forwarded));
+    }
+    if let Some(flags) = config.debug_flags.as_ref() {
+        chain = chain.wrap(flags.factory());
+    }
+    if let Some(header) = config.config_hash.header.as_ref() {
+        chain = chain.wrap(DefaultHeaders::new().add((header.as_str(), hash)));
+    }
+    chain = chain.wrap(services::drain::Drain);
+    if config.slow_clients.enforce_body() {
+        let slow = &config.slow_clients;
+        chain = chain.wrap(services::slow_client::SlowClient::new(
+            services::slow_client::Settings {
+                body_timeout: slow.body_timeout.as_ref().map(|d| d.0),
+                min_rate: slow.min_body_rate,
+                grace: config::default_duration(&slow.min_rate_grace, 5),
+            },
+        ));
+    }
+    if let Some(timeout) = config.stalled_client_timeout.as_ref() {
+        chain = chain.wrap(services::reaper::Reaper::new(timeout.0));
+    }
+    chain = chain.wrap(services::catch_panic::CatchPanic);
+    if config.sanitize_errors.unwrap_or(true) {
+        chain = chain.wrap(actix_sanitize::Sanitizer::default());
+    }
+    if !config.logging.disable {
+        let filter = log_filter(config);
+        // sampling happens once the response is known within the access log
+        let sampled = config.logging.sample.is_some_and(|n| n > 1);
+        let access = config.logging.access_log.is_some() || sampled;
+        chain = match (access, filter.is_active()) {
+            (true, false) => chain.wrap(access_log(config)),
+            (true, true) => chain.wrap(services::log_filter::LogFilter::new(
+                access_log(config),
+                filter,
+            )),
+            (false, false) => chain.wrap(logger(config)),
+            (false, true) => {
+                chain.wrap(services::log_filter::LogFilter::new(logger(config), filter))
+            }
+        };
+    }
+    // resolved client address becomes the peer address for everything within
+    chain = config
+        .middleware
+        .iter()
+        .filter(|m| m.resolves_client())
+        .fold(chain, |chain, m| m.wrap(chain, &spec));
+    if let Some(n) = trace {
+        chain = chain.wrap(Traced(Hop::Server(n)));
+    }
+
+    chain
+}
+
+/// Assemble [`actix_web::App`] served by the labeled listener.
+///
+/// Mounts every server attached to the listener with the default server
+/// last, answering hosts matching no `server_name` with the configured
+/// `unmatched_status`. Servers and directives entered by each request are
+/// recorded into its [`services::trace::Trace`] when `trace` is set.
+/// The configuration must be loaded with [`config::prepare_config`] and
+/// [`config::connect_config`].
+pub fn assemble_app(
+    config: &[ServerConfig],
+    label: Option<&str>,
+    hash: &str,
+    trace: bool,
+) -> Result<
+    App<
+        impl ServiceFactory<
+            ServiceRequest,
+            Config = (),
+            Response = ServiceResponse<BoxBody>,
+            Error = Error,
+            InitError = (),
+        > + 'static,
+    >,
+> {
+    loaded(config)?;
+    Ok(build_app(config, label, hash, trace))
+}
+
+/// Check that every server configuration was loaded.
+fn loaded(config: &[ServerConfig]) -> Result<()> {
+    config
+        .iter()
+        .enumerate()
+        .try_for_each(|(n, cfg)| cfg.loaded().with_context(|| format!("server[{n}]")))
+}
+
+/// Assemble application of the labeled listener from loaded configuration.
+fn build_app(
+    config: &[ServerConfig],
+    label: Option<&str>,
+    hash: &str,
+    trace: bool,
+) -> App<
+    impl ServiceFactory<
+        ServiceRequest,
+        Config = (),
+        Response = ServiceResponse<BoxBody>,
+        Error = Error,
+        InitError = (),
+    > + 'static,
+> {
+    let unmatched_status = config
+        .iter()
+        .filter(|c| !c.disable)
+        .find_map(|c| c.unmatched_status)
+        .and_then(|code| StatusCode::from_u16(code).ok())
+        .unwrap_or(StatusCode::MISDIRECTED_REQUEST);
+
+    // default server is mounted last to catch every unmatched host
+    let mut servers: Vec<(usize, &ServerConfig)> = config
+        .iter()
+        .enumerate()
+        .filter(|(_, cfg)| cfg.attached(label))
+        .collect();
+    servers.sort_by_key(|(_, cfg)| cfg.default_server);
+
+    let enabled = || servers.iter().filter(|(_, c)| !c.disable);
+    let named = enabled().any(|(_, c)| !c.server_name.is_empty());
+    let unmatched = named && !enabled().any(|(_, c)| c.default_server);
+    let app = servers
+        .iter()
+        .map(|(n, cfg)| build_chain(cfg, hash, trace.then_some(*n)))
+        .fold(App::new(), |app, chain| app.service(chain));
+    match unmatched {
+        true => app.default_service(unmatched_host(unmatched_status)),
+        false => app,
+    }
+}
+
+/// Response for requests matching no server's `server_name`.
+///
+/// `444` sends an empty response and closes the connection after it,
+/// since actix-web cannot drop a connection without answering like nginx.
+fn unmatched_host(status: StatusCode) -> actix_web::Route {
+    actix_web::web::to(move || async move {
+        let mut res = HttpResponse::build(status);
+        if status.as_u16() == 444 {
+            res.force_close();
+        }
+        res.finish()
+    })
+}
+
+/// Build and start http server from the loaded configuration.
+pub fn spawn_server(config: &[ServerConfig], hash: String) -> Result<HttpServer> {
+    loaded(config)?;
+    let mut sockets = listener::Sockets::default();
+    let mut listeners = listener::Listeners::default();
+    let mut plain = Vec::new();
+    let mut secure = Vec::new();
+    for listen in config
+        .iter()
+        .filter(|cfg| !cfg.disable)
+        .flat_map(|cfg| cfg.listen.iter())
+        .filter(|listen| !services::drain::is_paused(listen))
+    {
+        if listen.ssl.is_some() {
+            log::info!("spawning tls listener {:?}", listen.address());
+            secure.extend(listeners.bind(&mut sockets, listen)?);
+        } else {
+            log::info!("spawning listener {:?}", listen.address());
+            plain.extend(listeners.bind(&mut sockets, listen)?);
+        }
+    }
+    if sockets.is_empty() {
+        return Err(anyhow!("no active listeners to serve"));
+    }
+
+    // applications only mount the servers attached to the listener
+    let listeners = std::sync::Arc::new(listeners);
+    let sconfig = config.to_vec();
+    let factory = move |label: Option<&str>| build_app(&sconfig, label, &hash, false);
+    let apps = listeners.clone();
+    let mut server =
+        actix_web::HttpServer::new(move || listener::Apps::new(apps.clone(), factory.clone()))
+            .on_connect(listener::on_connect(listeners))
+            .client_disconnect_timeout(Duration::from_secs(1));
+    if let Some(workers) = lowest(config, |c| c.runtime.workers) {
+        server = server.workers(workers);
+    }
+    if let Some(max) = lowest(config, |c| c.runtime.max_blocking_threads) {
+        server = server.worker_max_blocking_threads(max);
+    }
+    let timeout = lowest(config, |c| c.runtime.shutdown_timeout.as_ref().map(|t| t.0));
+    if let Some(timeout) = timeout {
+        // actix only accepts whole seconds, so partial seconds round up
+        let secs = timeout.as_secs() + u64::from(timeout.subsec_nanos() > 0);
+        server = server.shutdown_timeout(secs);
+    }
+    // connection settings are validated to be the same for every server
+    if let Some(max) = lowest(config, |c| c.connections.max_connections) {
+        server = server.max_connections(max);
+    }
+    if let Some(max) = lowest(config, |c| c.connections.max_connection_rate) {
+        server = server.max_connection_rate(max);
+    }
+    let keep_alive = lowest(config, |c| c.connections.keep_alive.as_ref().map(|t| t.0));
+    server = server.keep_alive(keep_alive.unwrap_or(Duration::from_secs(5)));
+    let header_timeout = lowest(config, |c| {
+        c.slow_clients.header_timeout.as_ref().map(|t| t.0)
+    });
+    server = server.client_request_timeout(header_timeout.unwrap_or(Duration::from_secs(5)));
+
+    for lst in plain {
+        server = server.listen(lst)?;
+    }
+    if !secure.is_empty() {
+        let sslcfg = tls::server::build_tls_config(config)?;
+        for lst in secure {
+            server = server.listen_rustls_0_23(lst, sslcfg.clone())?;
+        }
+    }
+    sockets.commit();
+    Ok(server.disable_signals().run())
+}
+
+/// Lowest value configured across enabled servers for a process-wide setting.
+#[inline]
+pub fn lowest<T: Ord>(
+    config: &[ServerConfig],
+    f: impl Fn(&ServerConfig) -> Option<T>,
+) -> Option<T> {
+    config.iter().filter(|cfg| !cfg.disable).filter_map(f).min()
+}
@@ -0,0 +1,372 @@
+//! Server Listener Assembly
+//!
+//! Binds the configured listeners and selects the application served on
+//! each of them by the local address of the connection. Connections are
+//! checked against the listener's acceptance rules once established, and
+//! refused connections are closed without their requests reaching any
+//! application.
+
+use std::{
+    any::Any,
+    collections::{HashMap, HashSet},
+    fmt, io,
+    marker::PhantomData,
+    net::{IpAddr, Ipv4Addr, Ipv6Addr, SocketAddr, ToSocketAddrs},
+    sync::{Arc, Mutex, OnceLock},
+    time::Instant,
+};
+
+use actix_http::Request;
+use actix_service::{IntoServiceFactory, Service, ServiceFactory};
+use actix_tls::accept::rustls_0_23::TlsStream;
+use actix_web::{
+    Error, HttpResponse, ResponseError,
+    dev::{AppConfig, Extensions},
+    http::StatusCode,
+    rt::net::TcpStream,
+};
+use socket2::{Domain, Protocol, Socket, Type};
+
+use crate::{
+    config::{AcceptCfg, IpMatch, ListenCfg},
+    metrics,
+    services::{self, LocalBoxFuture},
+    tls,
+};
+
+/// Connection acceptance rules for a single listener.
+#[derive(Debug, Default)]
+struct Filter {
+    allow: Vec<IpMatch>,
+    deny: Vec<IpMatch>,
+    max_per_source: Option<usize>,
+    active: Mutex<HashMap<IpAddr, usize>>,
+}
+
+impl From<&AcceptCfg> for Filter {
+    fn from(value: &AcceptCfg) -> Self {
+        Self {
+            allow: value.allow.clone(),
+            deny: value.deny.clone(),
+            max_per_source: value.max_per_source,
+            active: Default::default(),
+        }
+    }
+}
+
+impl Filter {
+    /// Check if the source address passes the allow and deny rules.
+    fn allowed(&self, ip: &IpAddr) -> bool {
+        if self.deny.iter().any(|m| m.contains(ip)) {
+            return false;
+        }
+        self.allow.is_empty() || self.allow.iter().any(|m| m.contains(ip))
+    }
+
+    /// Admit a new connection from the source address.
+    ///
+    /// Returns a guard tracking the connection until it is dropped
+    /// or the reason the connection must be refused.
+    fn admit(self: &Arc<Self>, ip: IpAddr) -> Result<Admitted, Refused> {
+        let ip = ip.to_canonical();
+        if !self.allowed(&ip) {
+            return Err(Refused::new(ip, "denied"));
+        }
+        if let Some(max) = self.max_per_source {
+            let mut active = self.active.lock().expect("listener filter poisoned");
+            let count = active.entry(ip).or_default();
+            if *count >= max {
+                return Err(Refused::new(ip, "limit"));
+            }
+            *count += 1;
+        }
+        Ok(Admitted {
+            filter: Arc::clone(self),
+            ip,
+        })
+    }
+}
+
+/// Admitted connection released from its source's count once dropped.
+struct Admitted {
+    filter: Arc<Filter>,
+    ip: IpAddr,
+}
+
+impl Drop for Admitted {
+    fn drop(&mut self) {
+        if self.filter.max_per_source.is_none() {
+            return;
+        }
+        let mut active = self.filter.active.lock().expect("listener filter poisoned");
+        if let Some(count) = active.get_mut(&self.ip) {
+            *count = count.saturating_sub(1);
+            if *count == 0 {
+                active.remove(&self.ip);
+            }
+        }
+    }
+}
+
+/// Connection refused by the acceptance rules of its listener.
+///
+/// Every request on the connection is answered with an empty response
+/// closing the connection.
+#[derive(Clone, Copy, Debug)]
+struct Refused {
+    reason: &'static str,
+}
+
+impl Refused {
+    /// Log and count a refused connection.
+    fn new(ip: IpAddr, reason: &'static str) -> Self {
+        log::debug!("refused connection from {ip} ({reason})");
+        metrics::counter(&format!(
+            "bob_connections_refused_total{{reason={reason:?}}}"
+        ))
+        .inc();
+        Self { reason }
+    }
+}
+
+impl fmt::Display for Refused {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "connection refused ({})", self.reason)
+    }
+}
+
+impl ResponseError for Refused {
+    fn status_code(&self) -> StatusCode {
+        match self.reason {
+            "limit" => StatusCode::TOO_MANY_REQUESTS,
+            _ => StatusCode::FORBIDDEN,
+        }
+    }
+
+    fn error_response(&self) -> HttpResponse {
+        HttpResponse::build(self.status_code())
+            .force_close()
+            .finish()
+    }
+}
+
+/// Label and acceptance rules of a bound listener.
+#[derive(Debug)]
+struct Listener {
+    label: Option<String>,
+    filter: Arc<Filter>,
+}
+
+/// Listeners bound by a server keyed by their local address.
+#[derive(Debug, Default)]
+pub struct Listeners(HashMap<SocketAddr, Listener>);
+
+impl Listeners {
+    /// Bind the listening sockets of the listener configuration.
+    pub fn bind(
+        &mut self,
+        sockets: &mut Sockets,
+        listen: &ListenCfg,
+    ) -> io::Result<Vec<std::net::TcpListener>> {
+        let filter = Arc::new(Filter::from(&listen.accept));
+        listen
+            .address()
+            .to_socket_addrs()?
+            .map(|addr| {
+                let lst = sockets.listener(addr, listen.backlog())?;
+                let listener = Listener {
+                    label: listen.label.clone(),
+                    filter: Arc::clone(&filter),
+                };
+                self.0.insert(lst.local_addr()?, listener);
+                Ok(lst)
+            })
+            .collect()
+    }
+
+    /// Listener accepting connections on the local address.
+    ///
+    /// Connections to wildcard listeners report the specific address
+    /// they were made to, so the wildcard of the port is checked too.
+    fn get(&self, addr: SocketAddr) -> Option<&Listener> {
+        self.0.get(&addr).or_else(|| {
+            let any: IpAddr = match addr {
+                SocketAddr::V4(_) => Ipv4Addr::UNSPECIFIED.into(),
+                SocketAddr::V6(_) => Ipv6Addr::UNSPECIFIED.into(),
+            };
+            self.0.get(&SocketAddr::new(any, addr.port()))
+        })
+    }
+}
+
+/// Listening sockets of the running server keyed by address.
+///
+/// A server started by a reload serves duplicates of the sockets bound
+/// by the server it replaces, so a port is never bound twice and
+/// connections keep being accepted while the previous server drains.
+fn bound() -> &'static Mutex<HashMap<SocketAddr, Socket>> {
+    static BOUND: OnceLock<Mutex<HashMap<SocketAddr, Socket>>> = OnceLock::new();
+    BOUND.get_or_init(Default::default)
+}
+
+/// Listening sockets used by a server being assembled.
+///
+/// Sockets bound for new addresses are closed again unless the
+/// server is successfully assembled and [`Sockets::commit`] is called.
+#[derive(Debug, Default)]
+pub struct Sockets {
+    used: HashSet<SocketAddr>,
+    fresh: HashMap<SocketAddr, Socket>,
+}
+
+impl Sockets {
+    /// Listening socket for the address.
+    ///
+    /// Duplicates the socket of the running server when it already
+    /// listens on the address, otherwise binds a new one. Using the
+    /// same address twice fails with [`io::ErrorKind::AddrInUse`].
+    fn listener(&mut self, addr: SocketAddr, backlog: u32) -> io::Result<std::net::TcpListener> {
+        if !self.used.insert(addr) {
+            return Err(io::Error::new(
+                io::ErrorKind::AddrInUse,
+                format!("address {addr} is used by more than one listener"),
+            ));
+        }
+        let backlog = backlog.min(i32::MAX as u32) as i32;
+        let running = bound().lock().expect("listener sockets poisoned");
+        if let Some(socket) = running.get(&addr) {
+            // relisten to apply the backlog of the new configuration
+            socket.listen(backlog)?;
+            return Ok(socket.try_clone()?.into());
+        }
+        drop(running);
+        let socket = Socket::new(Domain::for_address(addr), Type::STREAM, Some(Protocol::TCP))?;
+        socket.set_reuse_address(true)?;
+        socket.set_nonblocking(true)?;
+        socket.bind(&addr.into())?;
+        socket.listen(backlog)?;
+        let listener = socket.try_clone()?.into();
+        self.fresh.insert(addr, socket);
+        Ok(listener)
+    }
+
+    /// Check if no listening sockets are used.
+    #[inline]
+    pub fn is_empty(&self) -> bool {
+        self.used.is_empty()
+    }
+
+    /// Hand the sockets over to the next reload.
+    ///
+    /// Sockets of addresses no longer used are released and close
+    /// once the previous server stops.
+    pub fn commit(self) {
+        let mut running = bound().lock().expect("listener sockets poisoned");
+        running.retain(|addr, _| self.used.contains(addr));
+        running.extend(self.fresh);
+    }
+}
+
+/// Collect connection data and apply acceptance rules to new connections.
+///
+/// Registered as the `on_connect` callback of the http server.
+pub fn on_connect(
+    listeners: Arc<Listeners>,
+) -> impl Fn(&dyn Any, &mut Extensions) + Send + Sync + 'static {
+    move |conn, ext| {
+        ext.insert(services::drain::ConnectedAt(Instant::now()));
+        tls::server::on_connect(conn, ext);
+        let stream = conn.downcast_ref::<TcpStream>().or_else(|| {
+            conn.downcast_ref::<TlsStream<TcpStream>>()
+                .map(|s| s.get_ref().0)
+        });
+        let Some((local, peer)) =
+            stream.and_then(|s| Some((s.local_addr().ok()?, s.peer_addr().ok()?)))
+        else {
+            ext.insert(Refused { reason: "denied" });
+            return;
+        };
+        let Some(listener) = listeners.get(local) else {
+            return;
+        };
+        match listener.filter.admit(peer.ip()) {
+            Ok(admitted) => {
+                ext.insert(admitted);
+            }
+            Err(refused) => {
+                ext.insert(refused);
+            }
+        }
+    }
+}
+
+/// Application factory serving the servers attached to each listener.
+///
+/// The application is selected by the listener address actix passes
+/// along with the configuration of every connection service.
+pub struct Apps<F, S> {
+    listeners: Arc<Listeners>,
+    factory: F,
+    _app: PhantomData<fn() -> S>,
+}
+
+impl<F, S> Apps<F, S> {
+    /// Create application factory producing apps by listener label.
+    pub fn new(listeners: Arc<Listeners>, factory: F) -> Self {
+        Self {
+            listeners,
+            factory,
+            _app: PhantomData,
+        }
+    }
+}
+
+impl<F, I, S> ServiceFactory<Request> for Apps<F, S>
+where
+    F: Fn(Option<&str>) -> I,
+    I: IntoServiceFactory<S, Request>,
+    S: ServiceFactory<Request, Config = AppConfig, Error = Error> + 'static,
+    S::Future: 'static,
+{
+    type Response = S::Response;
+    type Error = Error;
+    type Config = AppConfig;
+    type Service = Closing<S::Service>;
+    type InitError = S::InitError;
+    type Future = LocalBoxFuture<Result<Self::Service, Self::InitError>>;
+
+    fn new_service(&self, cfg: AppConfig) -> Self::Future {
+        let label = self
+            .listeners
+            .get(cfg.local_addr())
+            .and_then(|l| l.label.clone());
+        let app = (self.factory)(label.as_deref())
+            .into_factory()
+            .new_service(cfg);
+        Box::pin(async move { app.await.map(|service| Closing { service }) })
+    }
+}
+
+/// Application service closing connections refused by their listener.
+pub struct Closing<S> {
+    service: S,
+}
+
+impl<S> Service<Request> for Closing<S>
+where
+    S: Service<Request, Error = Error> + 'static,
+    S::Future: 'static,
+{
+    type Response = S::Response;
+    type Error = Error;
+    type Future = LocalBoxFuture<Result<Self::Response, Self::Error>>;
+
+    actix_service::forward_ready!(service);
+
+    fn call(&self, req: Request) -> Self::Future {
+        if let Some(refused) = req.conn_data::<Refused>().copied() {
+            return Box::pin(std::future::ready(Err(refused.into())));
+        }
+        Box::pin(self.service.call(req))
+    }
+}
@@ -0,0 +1,113 @@
+//! Process-wide Runtime Metrics
+//!
+//! Minimal registry of named counters shared between all workers.
+//! Histograms are composed from cumulative counters using prometheus
+//! style `_bucket`, `_sum` and `_count` names.
+
+use std::{
+    collections::BTreeMap,
+    sync::{
+        Mutex, OnceLock,
+        atomic::{AtomicU64, Ordering},
+    },
+};
+
+/// Monotonically increasing metric counter.
+#[derive(Debug, Default)]
+pub struct Counter(AtomicU64);
+
+impl Counter {
+    /// Increment counter by one.
+    #[inline]
+    pub fn inc(&self) {
+        self.add(1);
+    }
+    /// Increment counter by the specified amount.
+    #[inline]
+    pub fn add(&self, n: u64) {
+        self.0.fetch_add(n, Ordering::Relaxed);
+    }
+    /// Overwrite counter value for gauge style metrics.
+    #[inline]
+    pub fn set(&self, n: u64) {
+        self.0.store(n, Ordering::Relaxed);
+    }
+    /// Retrieve current counter value.
+    #[inline]
+    pub fn get(&self) -> u64 {
+        self.0.load(Ordering::Relaxed)
+    }
+}
+
+fn counters() -> &'static Mutex<BTreeMap<String, &'static Counter>> {
+    static COUNTERS: OnceLock<Mutex<BTreeMap<String, &'static Counter>>> = OnceLock::new();
+    COUNTERS.get_or_init(Default::default)
+}
+
+/// Retrieve (or register) counter with the specified name.
+pub fn counter(name: &str) -> &'static Counter {
+    let mut counters = counters().lock().expect("metrics registry poisoned");
+    if let Some(counter) = counters.get(name) {
+        return counter;
+    }
+    let counter: &'static Counter = Box::leak(Box::default());
+    counters.insert(name.to_owned(), counter);
+    counter
+}
+
+/// Collect snapshot of all registered metrics.
+pub fn snapshot() -> Vec<(String, u64)> {
+    counters()
+        .lock()
+        .expect("metrics registry poisoned")
+        .iter()
+        .map(|(name, counter)| (name.clone(), counter.get()))
+        .collect()
+}
+
+/// Default byte-size histogram bucket boundaries.
+pub const SIZE_BUCKETS: &[u64] = &[
+    256, 1024, 4096, 16384, 65536, 262144, 1048576, 4194304, 16777216, 67108864,
+];
+
+/// Cumulative histogram built from registered counters.
+#[derive(Clone, Debug)]
+pub struct Histogram {
+    buckets: Vec<(u64, &'static Counter)>,
+    sum: &'static Counter,
+    count: &'static Counter,
+}
+
+impl Histogram {
+    /// Record an observed value.
+    pub fn observe(&self, value: u64) {
+        self.buckets
+            .iter()
+            .filter(|(bound, _)| value <= *bound)
+            .for_each(|(_, counter)| counter.inc());
+        self.sum.add(value);
+        self.count.inc();
+    }
+}
+
+/// Retrieve (or register) histogram with the specified name and labels.
+///
+/// Labels are given in prometheus format without braces (`a="b",c="d"`).
+pub fn histogram(name: &str, labels: &str, bounds: &[u64]) -> Histogram {
+    let sep = if labels.is_empty() { "" } else { "," };
+    let labeled = |suffix: &str| match labels.is_empty() {
+        true => format!("{name}_{suffix}"),
+        false => format!("{name}_{suffix}{{{labels}}}"),
+    };
+    Histogram {
+        buckets: bounds
+            .iter()
+            .map(|bound| {
+                let name = format!("{name}_bucket{{{labels}{sep}le=\"{bound}\"}}");
+                (*bound, counter(&name))
+            })
+            .collect(),
+        sum: counter(&labeled("sum")),
+        count: counter(&labeled("count")),
+    }
+}
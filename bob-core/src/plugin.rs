@@ -0,0 +1,525 @@
+//! External Module and Middleware Plugins
+//!
+//! Downstream crates implement [`BobModule`] or [`BobMiddleware`] and
+//! register a factory using [`register_module`] or [`register_middleware`]
+//! before the configuration is loaded. Registered kinds may then be used
+//! within a directive's `construct` configuration like any builtin:
+//!
+//! ```yaml
+//! directives:
+//!   - location: /hello
+//!     construct:
+//!       - module: hello
+//!         greeting: world
+//!       - middleware: audit
+//! ```
+//!
+//! Names of builtin kinds cannot be registered.
+//!
+//! With the `plugins` feature enabled, [`load`] additionally registers
+//! kinds from a dynamically loaded library declared with
+//! [`export_plugin`](crate::export_plugin).
+
+use std::{
+    collections::HashMap,
+    ffi::CStr,
+    fmt::Debug,
+    rc::Rc,
+    sync::{Arc, OnceLock, RwLock},
+};
+
+use actix_chain::Link;
+use actix_web::{
+    Error,
+    body::{BoxBody, MessageBody},
+    dev::{Service, ServiceRequest, ServiceResponse, Transform, always_ready},
+};
+use anyhow::bail;
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    config::{Component, Middleware, Module, ModuleConfig, Spec},
+    services::LocalBoxFuture,
+};
+
+/// Configuration key used to select the module implementation.
+pub const MODULE_KEY: &str = "module";
+
+/// Configuration key used to select the middleware implementation.
+pub const MIDDLEWARE_KEY: &str = "middleware";
+
+/// Version plugin libraries must be built with.
+///
+/// Combines the `bob-core` version with the compiler version since
+/// plugins share the unstable Rust ABI with the host.
+pub const PLUGIN_VERSION: &CStr = match CStr::from_bytes_with_nul(
+    concat!(
+        env!("CARGO_PKG_VERSION"),
+        " (",
+        env!("BOB_RUSTC_VERSION"),
+        ")\0"
+    )
+    .as_bytes(),
+) {
+    Ok(version) => version,
+    Err(_) => panic!("invalid plugin version"),
+};
+
+/// Request module provided by a downstream crate.
+pub trait BobModule: Debug + Send + Sync {
+    /// Validate and prepare module resources ahead of construction.
+    ///
+    /// Called once per process during configuration loading.
+    fn prepare(&self, _spec: &Spec) -> anyhow::Result<()> {
+        Ok(())
+    }
+
+    /// Build [`actix_chain::Link`] handling requests for the module.
+    ///
+    /// Called once per worker while assembling the server chain.
+    fn link(&self, spec: &Spec) -> Link;
+}
+
+/// Middleware provided by a downstream crate.
+pub trait BobMiddleware: Debug + Send + Sync {
+    /// Validate and prepare middleware resources ahead of construction.
+    ///
+    /// Called once per process during configuration loading.
+    fn prepare(&self, _spec: &Spec) -> anyhow::Result<()> {
+        Ok(())
+    }
+
+    /// Process request, passing it on to the wrapped service using `next`.
+    fn call(
+        &self,
+        req: ServiceRequest,
+        next: Next,
+    ) -> LocalBoxFuture<Result<ServiceResponse<BoxBody>, Error>>;
+}
+
+/// Factory used to construct [`BobModule`] instances from configuration.
+pub trait ModuleFactory: Send + Sync {
+    /// Build module from the raw configuration value.
+    ///
+    /// The value includes the `module` key used to select the factory.
+    fn build(&self, config: serde_yaml::Value) -> Result<Arc<dyn BobModule>, String>;
+}
+
+impl<F> ModuleFactory for F
+where
+    F: Fn(serde_yaml::Value) -> Result<Arc<dyn BobModule>, String> + Send + Sync,
+{
+    #[inline]
+    fn build(&self, config: serde_yaml::Value) -> Result<Arc<dyn BobModule>, String> {
+        (self)(config)
+    }
+}
+
+/// Factory used to construct [`BobMiddleware`] instances from configuration.
+pub trait MiddlewareFactory: Send + Sync {
+    /// Build middleware from the raw configuration value.
+    ///
+    /// The value includes the `middleware` key used to select the factory.
+    fn build(&self, config: serde_yaml::Value) -> Result<Arc<dyn BobMiddleware>, String>;
+}
+
+impl<F> MiddlewareFactory for F
+where
+    F: Fn(serde_yaml::Value) -> Result<Arc<dyn BobMiddleware>, String> + Send + Sync,
+{
+    #[inline]
+    fn build(&self, config: serde_yaml::Value) -> Result<Arc<dyn BobMiddleware>, String> {
+        (self)(config)
+    }
+}
+
+type Registry<T> = RwLock<HashMap<String, Arc<T>>>;
+
+/// Global module registry.
+fn modules() -> &'static Registry<dyn ModuleFactory> {
+    static REGISTRY: OnceLock<Registry<dyn ModuleFactory>> = OnceLock::new();
+    REGISTRY.get_or_init(Default::default)
+}
+
+/// Global middleware registry.
+fn middleware() -> &'static Registry<dyn MiddlewareFactory> {
+    static REGISTRY: OnceLock<Registry<dyn MiddlewareFactory>> = OnceLock::new();
+    REGISTRY.get_or_init(Default::default)
+}
+
+/// Register a new module factory under the specified name.
+///
+/// Registering an existing name replaces the previous factory while
+/// names of builtin modules are rejected.
+pub fn register_module<F: ModuleFactory + 'static>(name: &str, factory: F) -> anyhow::Result<()> {
+    insert_module(name, Arc::new(factory))
+}
+
+/// Register a new middleware factory under the specified name.
+///
+/// Registering an existing name replaces the previous factory while
+/// names of builtin middleware are rejected.
+pub fn register_middleware<F: MiddlewareFactory + 'static>(
+    name: &str,
+    factory: F,
+) -> anyhow::Result<()> {
+    insert_middleware(name, Arc::new(factory))
+}
+
+/// Check if the name selects a builtin kind of the tagged config.
+///
+/// Only unknown names fail with an unknown variant error, while builtin
+/// names at most miss required fields.
+fn is_builtin<T: for<'de> Deserialize<'de>>(key: &str, name: &str) -> bool {
+    let mut config = serde_yaml::Mapping::new();
+    config.insert(key.into(), name.into());
+    match serde_yaml::from_value::<T>(serde_yaml::Value::Mapping(config)) {
+        Ok(_) => true,
+        Err(err) => !err.to_string().contains("unknown variant"),
+    }
+}
+
+fn insert_module(name: &str, factory: Arc<dyn ModuleFactory>) -> anyhow::Result<()> {
+    if is_builtin::<ModuleConfig>(MODULE_KEY, name) {
+        bail!("plugin module {name:?} conflicts with a builtin module");
+    }
+    modules()
+        .write()
+        .expect("module registry poisoned")
+        .insert(name.to_owned(), factory);
+    Ok(())
+}
+
+fn insert_middleware(name: &str, factory: Arc<dyn MiddlewareFactory>) -> anyhow::Result<()> {
+    if is_builtin::<Middleware>(MIDDLEWARE_KEY, name) {
+        bail!("plugin middleware {name:?} conflicts with a builtin middleware");
+    }
+    middleware()
+        .write()
+        .expect("middleware registry poisoned")
+        .insert(name.to_owned(), factory);
+    Ok(())
+}
+
+/// List all registered module names.
+pub fn registered_modules() -> Vec<String> {
+    let mut names: Vec<String> = modules()
+        .read()
+        .expect("module registry poisoned")
+        .keys()
+        .cloned()
+        .collect();
+    names.sort();
+    names
+}
+
+/// List all registered middleware names.
+pub fn registered_middleware() -> Vec<String> {
+    let mut names: Vec<String> = middleware()
+        .read()
+        .expect("middleware registry poisoned")
+        .keys()
+        .cloned()
+        .collect();
+    names.sort();
+    names
+}
+
+/// Build plugin deserializing config directly into its type.
+///
+/// Removes the selection key before deserializing.
+pub fn from_config<T>(key: &str, mut config: serde_yaml::Value) -> Result<T, String>
+where
+    T: for<'de> Deserialize<'de>,
+{
+    if let Some(map) = config.as_mapping_mut() {
+        map.remove(key);
+    }
+    serde_yaml::from_value(config).map_err(|e| e.to_string())
+}
+
+/// Build module by deserializing config directly into its type.
+pub fn module_from_config<M>(config: serde_yaml::Value) -> Result<Arc<dyn BobModule>, String>
+where
+    M: BobModule + for<'de> Deserialize<'de> + 'static,
+{
+    Ok(Arc::new(from_config::<M>(MODULE_KEY, config)?))
+}
+
+/// Build middleware by deserializing config directly into its type.
+pub fn middleware_from_config<M>(
+    config: serde_yaml::Value,
+) -> Result<Arc<dyn BobMiddleware>, String>
+where
+    M: BobMiddleware + for<'de> Deserialize<'de> + 'static,
+{
+    Ok(Arc::new(from_config::<M>(MIDDLEWARE_KEY, config)?))
+}
+
+/// Build component from a registered plugin if its kind is registered.
+///
+/// Returns `None` when the configuration selects a builtin kind.
+pub fn build(value: &serde_yaml::Value) -> Result<Option<Component>, String> {
+    if let Some(name) = value.get(MODULE_KEY).and_then(|v| v.as_str()) {
+        let Some(factory) = modules()
+            .read()
+            .expect("module registry poisoned")
+            .get(name)
+            .cloned()
+        else {
+            return Ok(None);
+        };
+        let mut config = value.clone();
+        let next = match config.as_mapping_mut().and_then(|m| m.remove("next")) {
+            Some(next) => serde_yaml::from_value(next).map_err(|e| e.to_string())?,
+            None => None,
+        };
+        let module = factory
+            .build(config)
+            .map_err(|err| format!("module {name:?}: {err}"))?;
+        return Ok(Some(Component::Module(Module {
+            module: ModuleConfig::Plugin(PluginModule {
+                name: name.to_owned(),
+                module,
+            }),
+            next,
+        })));
+    }
+    if let Some(name) = value.get(MIDDLEWARE_KEY).and_then(|v| v.as_str()) {
+        let Some(factory) = middleware()
+            .read()
+            .expect("middleware registry poisoned")
+            .get(name)
+            .cloned()
+        else {
+            return Ok(None);
+        };
+        let middleware = factory
+            .build(value.clone())
+            .map_err(|err| format!("middleware {name:?}: {err}"))?;
+        return Ok(Some(Component::Middleware(Middleware::Plugin(
+            PluginMiddleware {
+                name: name.to_owned(),
+                middleware,
+            },
+        ))));
+    }
+    Ok(None)
+}
+
+/// Configured module provided by a registered plugin.
+///
+/// Only the name is serialized since plugin settings are opaque.
+#[derive(Clone, Debug, Serialize)]
+pub struct PluginModule {
+    /// Registered name of the module.
+    pub name: String,
+    /// Module built from the configuration.
+    #[serde(skip)]
+    pub module: Arc<dyn BobModule>,
+}
+
+impl PluginModule {
+    /// Validate and prepare module resources ahead of construction.
+    #[inline]
+    pub fn prepare(&self, spec: &Spec) -> anyhow::Result<()> {
+        self.module.prepare(spec)
+    }
+
+    /// Build [`actix_chain::Link`] from the plugin module.
+    #[inline]
+    pub fn link(&self, spec: &Spec) -> Link {
+        self.module.link(spec)
+    }
+}
+
+/// Configured middleware provided by a registered plugin.
+///
+/// Only the name is serialized since plugin settings are opaque.
+#[derive(Clone, Debug, Serialize)]
+pub struct PluginMiddleware {
+    /// Registered name of the middleware.
+    pub name: String,
+    /// Middleware built from the configuration.
+    #[serde(skip)]
+    pub middleware: Arc<dyn BobMiddleware>,
+}
+
+impl PluginMiddleware {
+    /// Validate and prepare middleware resources ahead of construction.
+    #[inline]
+    pub fn prepare(&self, spec: &Spec) -> anyhow::Result<()> {
+        self.middleware.prepare(spec)
+    }
+
+    /// Wrap Chain/Link with the plugin middleware.
+    #[inline]
+    pub fn wrap<W: actix_chain::Wrappable>(&self, w: W, _spec: &Spec) -> W {
+        w.wrap_with(PluginWrap(Arc::clone(&self.middleware)))
+    }
+}
+
+/// Handle to the service wrapped by a [`BobMiddleware`].
+#[derive(Clone)]
+pub struct Next(Rc<dyn Fn(ServiceRequest) -> LocalBoxFuture<Result<ServiceResponse, Error>>>);
+
+impl Next {
+    /// Pass the request on to the wrapped service.
+    #[inline]
+    pub fn call(&self, req: ServiceRequest) -> LocalBoxFuture<Result<ServiceResponse, Error>> {
+        (self.0)(req)
+    }
+}
+
+/// Middleware adapting a [`BobMiddleware`] into an actix transform.
+#[derive(Clone)]
+pub struct PluginWrap(Arc<dyn BobMiddleware>);
+
+impl<S, B> Transform<S, ServiceRequest> for PluginWrap
+where
+    S: Service<ServiceRequest, Response = ServiceResponse<B>, Error = Error> + 'static,
+    B: MessageBody + 'static,
+{
+    type Response = ServiceResponse<BoxBody>;
+    type Error = Error;
+    type Transform = PluginService;
+    type InitError = ();
+    type Future = std::future::Ready<Result<Self::Transform, Self::InitError>>;
+
+    fn new_transform(&self, service: S) -> Self::Future {
+        let service = Rc::new(service);
+        let next = Next(Rc::new(move |req| {
+            let service = Rc::clone(&service);
+            Box::pin(async move {
+                let res = service.call(req).await?;
+                Ok(res.map_into_boxed_body())
+            })
+        }));
+        std::future::ready(Ok(PluginService {
+            middleware: Arc::clone(&self.0),
+            next,
+        }))
+    }
+}
+
+/// Service produced by [`PluginWrap`] middleware.
+pub struct PluginService {
+    middleware: Arc<dyn BobMiddleware>,
+    next: Next,
+}
+
+impl Service<ServiceRequest> for PluginService {
+    type Response = ServiceResponse<BoxBody>;
+    type Error = Error;
+    type Future = LocalBoxFuture<Result<Self::Response, Self::Error>>;
+
+    always_ready!();
+
+    #[inline]
+    fn call(&self, req: ServiceRequest) -> Self::Future {
+        self.middleware.call(req, self.next.clone())
+    }
+}
+
+/// Registration handle passed to dynamically loaded plugins.
+///
+/// Registers into the host registries rather than the copy of this
+/// crate linked into the plugin library itself.
+pub struct Registrar {
+    module: fn(&str, Arc<dyn ModuleFactory>) -> anyhow::Result<()>,
+    middleware: fn(&str, Arc<dyn MiddlewareFactory>) -> anyhow::Result<()>,
+    errors: Vec<anyhow::Error>,
+}
+
+impl Registrar {
+    /// Register a new module factory under the specified name.
+    pub fn module<F: ModuleFactory + 'static>(&mut self, name: &str, factory: F) {
+        if let Err(err) = (self.module)(name, Arc::new(factory)) {
+            self.errors.push(err);
+        }
+    }
+
+    /// Register a new middleware factory under the specified name.
+    pub fn middleware<F: MiddlewareFactory + 'static>(&mut self, name: &str, factory: F) {
+        if let Err(err) = (self.middleware)(name, Arc::new(factory)) {
+            self.errors.push(err);
+        }
+    }
+}
+
+/// Export the plugin entrypoints of a dynamically loaded library.
+///
+/// Declares `bob_plugin_version`, reporting the [`PLUGIN_VERSION`] the
+/// library was built with, and `bob_plugin_register` calling the given
+/// registration function.
+///
+/// ```ignore
+/// fn register(registrar: &mut bob_core::plugin::Registrar) {
+///     registrar.module("hello", bob_core::plugin::module_from_config::<Hello>);
+/// }
+///
+/// bob_core::export_plugin!(register);
+/// ```
+#[macro_export]
+macro_rules! export_plugin {
+    ($register:path) => {
+        #[unsafe(no_mangle)]
+        pub extern "C" fn bob_plugin_version() -> *const ::std::ffi::c_char {
+            $crate::plugin::PLUGIN_VERSION.as_ptr()
+        }
+
+        #[unsafe(no_mangle)]
+        pub fn bob_plugin_register(registrar: &mut $crate::plugin::Registrar) {
+            $register(registrar)
+        }
+    };
+}
+
+/// Name of the version function exported by plugin libraries.
+#[cfg(feature = "plugins")]
+pub const VERSION_SYMBOL: &[u8] = b"bob_plugin_version";
+
+/// Name of the registration function exported by plugin libraries.
+#[cfg(feature = "plugins")]
+pub const REGISTER_SYMBOL: &[u8] = b"bob_plugin_register";
+
+/// Load plugin library and register the kinds it provides.
+///
+/// The library must be declared with [`export_plugin`](crate::export_plugin)
+/// and built with the same compiler and `bob-core` version as the host
+/// since no stable ABI is provided. Libraries reporting a different
+/// [`PLUGIN_VERSION`] are refused before any Rust code within them is
+/// called. Loaded libraries are never unloaded.
+#[cfg(feature = "plugins")]
+#[cfg_attr(feature = "doc", doc(cfg(feature = "plugins")))]
+pub fn load(path: &std::path::Path) -> anyhow::Result<()> {
+    use anyhow::Context;
+    let library = unsafe { libloading::Library::new(path) }
+        .with_context(|| format!("failed to load plugin {path:?}"))?;
+    let errors = {
+        let version = unsafe {
+            library.get::<unsafe extern "C" fn() -> *const std::ffi::c_char>(VERSION_SYMBOL)
+        }
+        .with_context(|| format!("plugin {path:?} missing bob_plugin_version"))?;
+        let version = unsafe { CStr::from_ptr(version()) };
+        if version != PLUGIN_VERSION {
+            bail!("plugin {path:?} built for {version:?}, expected {PLUGIN_VERSION:?}");
+        }
+        let register = unsafe { library.get::<fn(&mut Registrar)>(REGISTER_SYMBOL) }
+            .with_context(|| format!("plugin {path:?} missing bob_plugin_register"))?;
+        let mut registrar = Registrar {
+            module: insert_module,
+            middleware: insert_middleware,
+            errors: Vec::new(),
+        };
+        register(&mut registrar);
+        registrar.errors
+    };
+    // registered factories reference code within the library
+    std::mem::forget(library);
+    if let Some(err) = errors.into_iter().next() {
+        return Err(err.context(format!("plugin {path:?} registration failed")));
+    }
+    log::info!("loaded plugin {path:?}");
+    Ok(())
+}
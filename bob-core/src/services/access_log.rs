@@ -0,0 +1,259 @@
+//! Per-Host Access Log Middleware
+//!
+//! Writes access log entries into files selected by the configured server
+//! name matching the request using a path template such as
+//! `/var/log/bob/{server_name}.access.log`, or into the process log when
+//! no template is configured. Sampling is decided once the response is
+//! known so error responses are always written.
+
+use std::{
+    cell::Cell,
+    collections::HashMap,
+    fs::{File, OpenOptions},
+    io::{LineWriter, Write},
+    path::PathBuf,
+    rc::Rc,
+    sync::{Arc, Mutex, OnceLock},
+    time::Instant,
+};
+
+use actix_web::{
+    Error, HttpRequest,
+    body::{BodySize, MessageBody},
+    dev::{Service, ServiceRequest, ServiceResponse, Transform},
+    http::header,
+};
+
+use super::{LocalBoxFuture, anonymize::Anonymizer};
+use crate::config::DomainMatch;
+
+/// Template placeholder replaced with the matched server name.
+pub const HOST_PLACEHOLDER: &str = "{server_name}";
+
+/// Maximum number of open log file handles.
+const MAX_OPEN_FILES: usize = 256;
+
+/// Fallback name used when the request matches no configured server name.
+const DEFAULT_HOST: &str = "default";
+
+type Handle = Arc<Mutex<LineWriter<File>>>;
+
+/// Process-wide cache of open log files shared between workers.
+fn files() -> &'static Mutex<HashMap<PathBuf, Handle>> {
+    static FILES: OnceLock<Mutex<HashMap<PathBuf, Handle>>> = OnceLock::new();
+    FILES.get_or_init(Default::default)
+}
+
+/// Retrieve cached handle or open log file for appending.
+fn open(path: PathBuf) -> std::io::Result<Handle> {
+    let mut files = files().lock().expect("log file cache poisoned");
+    if let Some(handle) = files.get(&path) {
+        return Ok(Arc::clone(handle));
+    }
+    if files.len() >= MAX_OPEN_FILES {
+        log::warn!("access log file cache full. closing open handles");
+        files.clear();
+    }
+    if let Some(parent) = path.parent() {
+        std::fs::create_dir_all(parent)?;
+    }
+    let file = OpenOptions::new().create(true).append(true).open(&path)?;
+    let handle = Arc::new(Mutex::new(LineWriter::new(file)));
+    files.insert(path, Arc::clone(&handle));
+    Ok(handle)
+}
+
+/// Remove port from the host.
+fn strip_port(host: &str) -> &str {
+    match host.strip_prefix('[') {
+        Some(v6) => v6.split_once(']').map(|(addr, _)| addr).unwrap_or(v6),
+        None => host.split_once(':').map(|(name, _)| name).unwrap_or(host),
+    }
+}
+
+/// Convert host into a safe filename component.
+///
+/// Strips any port, lowercases and replaces every character
+/// outside of `[a-z0-9.-]` so hosts cannot escape the log directory.
+pub fn safe_host(host: &str) -> String {
+    let name: String = strip_port(host)
+        .chars()
+        .map(|c| c.to_ascii_lowercase())
+        .map(
+            |c| match c.is_ascii_alphanumeric() || c == '-' || c == '.' {
+                true => c,
+                false => '_',
+            },
+        )
+        .collect();
+    match name.trim_matches('.').is_empty() {
+        true => DEFAULT_HOST.to_owned(),
+        false => name,
+    }
+}
+
+/// Access log entry formatting settings.
+#[derive(Clone, Debug)]
+pub struct Settings {
+    /// Log file path template, writing to the process log when unset.
+    pub template: Option<String>,
+    /// Level used for entries written to the process log.
+    pub level: log::Level,
+    /// Only log 1 in every N successful responses.
+    pub sample: Option<u64>,
+    /// Use peer address instead of forwarding headers for client IP.
+    pub use_peer_addr: bool,
+    /// Anonymize client identifiers within log entries.
+    pub anonymize: Option<Anonymizer>,
+    /// Configured server names selecting the log file.
+    pub names: Vec<DomainMatch>,
+}
+
+impl Settings {
+    /// Configured server name matching the request host.
+    ///
+    /// Wildcard names log under their pattern, so clients can never
+    /// choose the log file by sending arbitrary hosts.
+    pub fn server_name(&self, host: &str) -> String {
+        let host = strip_port(host);
+        match self.names.iter().find(|name| name.matches(host)) {
+            Some(name) => safe_host(name.0.as_str()),
+            None => DEFAULT_HOST.to_owned(),
+        }
+    }
+}
+
+/// Resolve client address string used within log entries.
+pub fn client_ip(req: &HttpRequest, use_peer_addr: bool) -> Option<String> {
+    match use_peer_addr {
+        true => req.peer_addr().map(|a| a.ip().to_string()),
+        false => req
+            .connection_info()
+            .realip_remote_addr()
+            .map(|a| a.to_owned()),
+    }
+}
+
+/// Per-host access log middleware.
+#[derive(Clone)]
+pub struct AccessLog(Rc<Settings>);
+
+impl AccessLog {
+    /// Create new access log middleware from settings.
+    pub fn new(settings: Settings) -> Self {
+        Self(Rc::new(settings))
+    }
+}
+
+impl<S, B> Transform<S, ServiceRequest> for AccessLog
+where
+    S: Service<ServiceRequest, Response = ServiceResponse<B>, Error = Error> + 'static,
+    B: MessageBody + 'static,
+{
+    type Response = ServiceResponse<B>;
+    type Error = Error;
+    type Transform = AccessLogService<S>;
+    type InitError = ();
+    type Future = std::future::Ready<Result<Self::Transform, Self::InitError>>;
+
+    fn new_transform(&self, service: S) -> Self::Future {
+        std::future::ready(Ok(AccessLogService {
+            service: Rc::new(service),
+            settings: Rc::clone(&self.0),
+            counter: Rc::new(Cell::new(0)),
+        }))
+    }
+}
+
+/// Service produced by [`AccessLog`] middleware.
+pub struct AccessLogService<S> {
+    service: Rc<S>,
+    settings: Rc<Settings>,
+    counter: Rc<Cell<u64>>,
+}
+
+impl<S, B> Service<ServiceRequest> for AccessLogService<S>
+where
+    S: Service<ServiceRequest, Response = ServiceResponse<B>, Error = Error> + 'static,
+    B: MessageBody + 'static,
+{
+    type Response = ServiceResponse<B>;
+    type Error = Error;
+    type Future = LocalBoxFuture<Result<Self::Response, Self::Error>>;
+
+    actix_web::dev::forward_ready!(service);
+
+    fn call(&self, req: ServiceRequest) -> Self::Future {
+        let start = Instant::now();
+        let service = Rc::clone(&self.service);
+        let settings = Rc::clone(&self.settings);
+        let counter = Rc::clone(&self.counter);
+        Box::pin(async move {
+            let res = service.call(req).await?;
+            if res.status().as_u16() >= 400 || sampled(&settings, &counter) {
+                write_entry(&settings, &res, start);
+            }
+            Ok(res)
+        })
+    }
+}
+
+/// Check if the successful response is within the logged sample.
+fn sampled(settings: &Settings, counter: &Cell<u64>) -> bool {
+    let Some(every) = settings.sample.filter(|n| *n > 1) else {
+        return true;
+    };
+    let count = counter.get();
+    counter.set(count.wrapping_add(1));
+    count % every == 0
+}
+
+/// Format and write access log entry for the response.
+fn write_entry<B: MessageBody>(settings: &Settings, res: &ServiceResponse<B>, start: Instant) {
+    let req = res.request();
+    let info = req.connection_info();
+    let ip = client_ip(req, settings.use_peer_addr).unwrap_or_else(|| "-".to_owned());
+    let ip = match settings.anonymize.as_ref() {
+        Some(anonymize) => anonymize.ip(&ip),
+        None => ip,
+    };
+    let user = match settings.anonymize.as_ref().filter(|a| a.hash_users) {
+        Some(anonymize) => format!(" {}", anonymize.user(req)),
+        None => String::new(),
+    };
+    let header = |name: header::HeaderName| {
+        req.headers()
+            .get(name)
+            .and_then(|v| v.to_str().ok())
+            .unwrap_or("-")
+            .to_owned()
+    };
+    let size = match res.response().body().size() {
+        BodySize::Sized(size) => size.to_string(),
+        _ => "-".to_owned(),
+    };
+    let line = format!(
+        r#"{ip}{user} "{} {} {:?}" {} {size} "{}" "{}" {:.6}"#,
+        req.method(),
+        req.uri(),
+        req.version(),
+        res.status().as_u16(),
+        header(header::REFERER),
+        header(header::USER_AGENT),
+        start.elapsed().as_secs_f64(),
+    );
+
+    let Some(template) = settings.template.as_ref() else {
+        log::log!(settings.level, "{line}");
+        return;
+    };
+    let host = settings.server_name(info.host());
+    let path = PathBuf::from(template.replace(HOST_PLACEHOLDER, &host));
+    let result = open(path.clone()).and_then(|handle| {
+        let mut file = handle.lock().expect("log file poisoned");
+        writeln!(file, "{line}")
+    });
+    if let Err(err) = result {
+        log::error!("failed to write access log {path:?}: {err:?}");
+    }
+}
@@ -0,0 +1,89 @@
+//! Access Log Anonymization Utilities
+//!
+//! Truncates client addresses and hashes user identifiers so access logs
+//! can be retained without storing personal data.
+
+use std::net::{IpAddr, SocketAddr};
+
+use actix_web::{HttpRequest, http::header};
+use base64::{Engine, prelude::BASE64_STANDARD};
+use sha2::{Digest, Sha256};
+
+/// Number of hex characters kept from hashed identifiers.
+const HASH_LEN: usize = 16;
+
+/// Client identifier anonymization settings.
+#[derive(Clone, Debug)]
+pub struct Anonymizer {
+    /// Number of leading IPv4 address bits preserved.
+    pub ipv4_prefix: u8,
+    /// Number of leading IPv6 address bits preserved.
+    pub ipv6_prefix: u8,
+    /// Hash user identifiers rather than omitting them.
+    pub hash_users: bool,
+    /// Salt prepended to user identifiers before hashing.
+    pub salt: String,
+}
+
+impl Anonymizer {
+    /// Truncate client address to the configured prefix length.
+    ///
+    /// Values that cannot be parsed as an address are replaced
+    /// entirely to avoid leaking unexpected data.
+    pub fn ip(&self, addr: &str) -> String {
+        let ip = match addr.parse::<IpAddr>() {
+            Ok(ip) => ip,
+            Err(_) => match addr.parse::<SocketAddr>() {
+                Ok(addr) => addr.ip(),
+                Err(_) => return "-".to_owned(),
+            },
+        };
+        match ip {
+            IpAddr::V4(ip) => {
+                let mask = mask_bits(self.ipv4_prefix.min(32) as u32, 32) as u32;
+                IpAddr::from((u32::from(ip) & mask).to_be_bytes())
+            }
+            IpAddr::V6(ip) => {
+                let mask = mask_bits(self.ipv6_prefix.min(128) as u32, 128);
+                IpAddr::from((u128::from(ip) & mask).to_be_bytes())
+            }
+        }
+        .to_string()
+    }
+
+    /// Generate hashed identifier for the authenticated request user.
+    ///
+    /// Currently only basic authentication usernames are recognized.
+    pub fn user(&self, req: &HttpRequest) -> String {
+        match self.hash_users.then(|| basic_user(req)).flatten() {
+            Some(user) => {
+                let digest = Sha256::digest(format!("{}{user}", self.salt).as_bytes());
+                let hex: String = digest.iter().map(|b| format!("{b:02x}")).collect();
+                hex[..HASH_LEN].to_owned()
+            }
+            None => "-".to_owned(),
+        }
+    }
+}
+
+/// Generate bitmask with the leading `prefix` bits set.
+#[inline]
+fn mask_bits(prefix: u32, width: u32) -> u128 {
+    match prefix {
+        0 => 0,
+        prefix => (u128::MAX << (128 - prefix)) >> (128 - width),
+    }
+}
+
+/// Parse username from basic authorization header.
+fn basic_user(req: &HttpRequest) -> Option<String> {
+    let value = req.headers().get(header::AUTHORIZATION)?.to_str().ok()?;
+    let (scheme, creds) = value.split_once(' ')?;
+    if !scheme.eq_ignore_ascii_case("basic") {
+        return None;
+    }
+    let decoded = BASE64_STANDARD.decode(creds.trim()).ok()?;
+    let decoded = String::from_utf8(decoded).ok()?;
+    let (user, _) = decoded.split_once(':')?;
+    Some(user.to_owned())
+}
@@ -0,0 +1,278 @@
+//! Dynamic IP Banning Middleware
+//!
+//! Counts offending responses (such as bursts of `401`, `403` or `404`
+//! responses and WAF blocks) per client IP and temporarily bans clients
+//! exceeding the configured threshold. Bans are shared process-wide so
+//! they can be listed and cleared through the admin api.
+
+use std::{
+    collections::HashMap,
+    net::IpAddr,
+    rc::Rc,
+    sync::{Arc, Mutex, OnceLock},
+    time::{Duration, Instant},
+};
+
+use actix_web::{
+    Error, HttpResponse,
+    body::{EitherBody, MessageBody},
+    dev::{Service, ServiceRequest, ServiceResponse, Transform},
+    http::{ConnectionType, StatusCode, header},
+};
+
+use super::LocalBoxFuture;
+use crate::{config::IpMatch, metrics};
+
+/// Maximum number of tracked clients before expired entries are purged.
+const PURGE_THRESHOLD: usize = 4096;
+
+/// Marker inserted into request extensions by other components
+/// to report the request as an offense regardless of its status.
+#[derive(Clone, Copy, Debug)]
+pub struct Offense;
+
+/// Active ban details.
+#[derive(Clone, Debug)]
+pub struct Ban {
+    pub until: Instant,
+    pub offenses: u32,
+    pub reason: String,
+}
+
+fn bans() -> &'static Mutex<HashMap<IpAddr, Ban>> {
+    static BANS: OnceLock<Mutex<HashMap<IpAddr, Ban>>> = OnceLock::new();
+    BANS.get_or_init(Default::default)
+}
+
+/// List all currently active bans.
+pub fn list() -> Vec<(IpAddr, Ban)> {
+    let now = Instant::now();
+    let mut bans = bans().lock().expect("ban list poisoned");
+    bans.retain(|_, ban| ban.until > now);
+    let mut list: Vec<_> = bans.iter().map(|(ip, ban)| (*ip, ban.clone())).collect();
+    list.sort_by_key(|(ip, _)| *ip);
+    list
+}
+
+/// Remove ban for the client address.
+///
+/// Returns false if the address was not banned.
+pub fn unban(ip: &IpAddr) -> bool {
+    let now = Instant::now();
+    let mut bans = bans().lock().expect("ban list poisoned");
+    bans.remove(ip).is_some_and(|ban| ban.until > now)
+}
+
+/// Remove all active bans returning the number removed.
+pub fn clear() -> usize {
+    let now = Instant::now();
+    let mut bans = bans().lock().expect("ban list poisoned");
+    let count = bans.values().filter(|ban| ban.until > now).count();
+    bans.clear();
+    count
+}
+
+/// Immediately ban the client address for the given duration.
+///
+/// Extends rather than shortens any existing ban.
+pub fn ban(ip: IpAddr, duration: Duration, reason: String) {
+    let now = Instant::now();
+    let until = now + duration;
+    let mut bans = bans().lock().expect("ban list poisoned");
+    if bans.len() > PURGE_THRESHOLD {
+        bans.retain(|_, ban| ban.until > now);
+    }
+    let offenses = bans.get(&ip).map(|ban| ban.offenses + 1).unwrap_or(1);
+    if bans.get(&ip).is_some_and(|ban| ban.until >= until) {
+        return;
+    }
+    log::warn!("banning {ip} for {duration:?} ({reason})");
+    metrics::counter("bob_bans_total").inc();
+    bans.insert(
+        ip,
+        Ban {
+            until,
+            offenses,
+            reason,
+        },
+    );
+}
+
+/// Remaining ban duration for the client address.
+pub fn banned(ip: &IpAddr) -> Option<Duration> {
+    let now = Instant::now();
+    let bans = bans().lock().expect("ban list poisoned");
+    bans.get(ip)
+        .filter(|ban| ban.until > now)
+        .map(|ban| ban.until - now)
+}
+
+/// Ban behavior settings.
+#[derive(Clone, Debug)]
+pub struct Settings {
+    /// Response status codes counted as offenses.
+    pub statuses: Vec<StatusCode>,
+    /// Offenses allowed within the window before the client is banned.
+    pub max_offenses: u32,
+    /// Window in which offenses are counted.
+    pub window: Duration,
+    /// Duration of the ban once max offenses is exceeded.
+    pub ban_duration: Duration,
+    /// Client addresses exempt from banning.
+    pub exempt: Vec<IpMatch>,
+}
+
+#[derive(Debug)]
+struct Entry {
+    offenses: u32,
+    first_offense: Instant,
+}
+
+/// Shared offense tracking state.
+#[derive(Clone, Default)]
+pub struct State(Arc<Mutex<HashMap<IpAddr, Entry>>>);
+
+impl std::fmt::Debug for State {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "State {{}}")
+    }
+}
+
+impl State {
+    /// Record offense for the client and ban once threshold is reached.
+    fn offense(&self, ip: IpAddr, reason: String, settings: &Settings) {
+        let now = Instant::now();
+        let mut map = self.0.lock().expect("ban state poisoned");
+        if map.len() > PURGE_THRESHOLD {
+            map.retain(|_, e| now.duration_since(e.first_offense) <= settings.window);
+        }
+        let entry = map.entry(ip).or_insert(Entry {
+            offenses: 0,
+            first_offense: now,
+        });
+        if now.duration_since(entry.first_offense) > settings.window {
+            entry.offenses = 0;
+            entry.first_offense = now;
+        }
+        entry.offenses += 1;
+        if entry.offenses < settings.max_offenses {
+            return;
+        }
+        let offenses = entry.offenses;
+        map.remove(&ip);
+        drop(map);
+
+        log::warn!(
+            "banning {ip} for {:?} after {offenses} offenses ({reason})",
+            settings.ban_duration
+        );
+        metrics::counter("bob_bans_total").inc();
+        let mut bans = bans().lock().expect("ban list poisoned");
+        if bans.len() > PURGE_THRESHOLD {
+            bans.retain(|_, ban| ban.until > now);
+        }
+        bans.insert(
+            ip,
+            Ban {
+                until: now + settings.ban_duration,
+                offenses,
+                reason,
+            },
+        );
+    }
+}
+
+/// Dynamic IP banning middleware.
+pub struct IpBan {
+    settings: Rc<Settings>,
+    state: State,
+}
+
+impl IpBan {
+    /// Create new ban middleware with settings and shared state.
+    pub fn new(settings: Settings, state: State) -> Self {
+        Self {
+            settings: Rc::new(settings),
+            state,
+        }
+    }
+}
+
+impl<S, B> Transform<S, ServiceRequest> for IpBan
+where
+    S: Service<ServiceRequest, Response = ServiceResponse<B>, Error = Error> + 'static,
+    B: MessageBody + 'static,
+{
+    type Response = ServiceResponse<EitherBody<B>>;
+    type Error = Error;
+    type Transform = IpBanService<S>;
+    type InitError = ();
+    type Future = std::future::Ready<Result<Self::Transform, Self::InitError>>;
+
+    fn new_transform(&self, service: S) -> Self::Future {
+        std::future::ready(Ok(IpBanService {
+            service: Rc::new(service),
+            settings: Rc::clone(&self.settings),
+            state: self.state.clone(),
+        }))
+    }
+}
+
+/// Service produced by [`IpBan`] middleware.
+pub struct IpBanService<S> {
+    service: Rc<S>,
+    settings: Rc<Settings>,
+    state: State,
+}
+
+impl<S, B> Service<ServiceRequest> for IpBanService<S>
+where
+    S: Service<ServiceRequest, Response = ServiceResponse<B>, Error = Error> + 'static,
+    B: MessageBody + 'static,
+{
+    type Response = ServiceResponse<EitherBody<B>>;
+    type Error = Error;
+    type Future = LocalBoxFuture<Result<Self::Response, Self::Error>>;
+
+    actix_web::dev::forward_ready!(service);
+
+    fn call(&self, req: ServiceRequest) -> Self::Future {
+        let ip = req
+            .peer_addr()
+            .map(|addr| addr.ip())
+            .filter(|ip| !self.settings.exempt.iter().any(|m| m.contains(ip)));
+        if let Some(remaining) = ip.as_ref().and_then(banned) {
+            let mut res = HttpResponse::Forbidden()
+                .insert_header((header::RETRY_AFTER, remaining.as_secs().max(1).to_string()))
+                .finish();
+            res.head_mut().set_connection_type(ConnectionType::Close);
+            return Box::pin(async move { Ok(req.into_response(res).map_into_right_body()) });
+        }
+
+        let service = Rc::clone(&self.service);
+        let settings = Rc::clone(&self.settings);
+        let state = self.state.clone();
+        Box::pin(async move {
+            let Some(ip) = ip else {
+                return Ok(service.call(req).await?.map_into_left_body());
+            };
+            let request = req.request().clone();
+            let path = req.path().to_owned();
+            let result = service.call(req).await;
+
+            let status = match result.as_ref() {
+                Ok(res) => res.status(),
+                Err(err) => err.as_response_error().status_code(),
+            };
+            let reason = match request.extensions().get::<Offense>() {
+                Some(_) => Some(format!("offense at {path}")),
+                None if settings.statuses.contains(&status) => Some(format!("{status} at {path}")),
+                None => None,
+            };
+            if let Some(reason) = reason {
+                state.offense(ip, reason, &settings);
+            }
+            Ok(result?.map_into_left_body())
+        })
+    }
+}
@@ -0,0 +1,121 @@
+//! Request Body Size Limit Middleware
+//!
+//! Rejects oversized uploads with `413 Payload Too Large` before any
+//! request module buffers the body, similar to nginx's
+//! `client_max_body_size`.
+
+use std::{
+    pin::Pin,
+    rc::Rc,
+    task::{Context, Poll},
+};
+
+use actix_web::{
+    Error, HttpResponse,
+    body::{EitherBody, MessageBody},
+    dev::{Payload, Service, ServiceRequest, ServiceResponse, Transform},
+    error::PayloadError,
+    http::header,
+    web::Bytes,
+};
+use futures_core::Stream;
+
+use super::LocalBoxFuture;
+
+/// Request body size limit middleware.
+#[derive(Clone)]
+pub struct BodyLimit {
+    limit: u64,
+}
+
+impl BodyLimit {
+    /// Create new body limit middleware allowing at most `limit` bytes.
+    pub fn new(limit: u64) -> Self {
+        Self { limit }
+    }
+}
+
+impl<S, B> Transform<S, ServiceRequest> for BodyLimit
+where
+    S: Service<ServiceRequest, Response = ServiceResponse<B>, Error = Error> + 'static,
+    B: MessageBody + 'static,
+{
+    type Response = ServiceResponse<EitherBody<B>>;
+    type Error = Error;
+    type Transform = BodyLimitService<S>;
+    type InitError = ();
+    type Future = std::future::Ready<Result<Self::Transform, Self::InitError>>;
+
+    fn new_transform(&self, service: S) -> Self::Future {
+        std::future::ready(Ok(BodyLimitService {
+            service: Rc::new(service),
+            limit: self.limit,
+        }))
+    }
+}
+
+/// Service produced by [`BodyLimit`] middleware.
+pub struct BodyLimitService<S> {
+    service: Rc<S>,
+    limit: u64,
+}
+
+impl<S, B> Service<ServiceRequest> for BodyLimitService<S>
+where
+    S: Service<ServiceRequest, Response = ServiceResponse<B>, Error = Error> + 'static,
+    B: MessageBody + 'static,
+{
+    type Response = ServiceResponse<EitherBody<B>>;
+    type Error = Error;
+    type Future = LocalBoxFuture<Result<Self::Response, Self::Error>>;
+
+    actix_web::dev::forward_ready!(service);
+
+    fn call(&self, mut req: ServiceRequest) -> Self::Future {
+        let length = req
+            .headers()
+            .get(header::CONTENT_LENGTH)
+            .and_then(|v| v.to_str().ok())
+            .and_then(|v| v.parse::<u64>().ok());
+        if length.is_some_and(|length| length > self.limit) {
+            let res = HttpResponse::PayloadTooLarge().finish();
+            return Box::pin(async move { Ok(req.into_response(res).map_into_right_body()) });
+        }
+
+        // bodies without a declared length are counted while streaming
+        let payload = LimitedPayload {
+            payload: req.take_payload(),
+            remaining: self.limit,
+        };
+        req.set_payload(Payload::Stream {
+            payload: Box::pin(payload),
+        });
+
+        let service = Rc::clone(&self.service);
+        Box::pin(async move { service.call(req).await.map(|res| res.map_into_left_body()) })
+    }
+}
+
+/// Payload stream wrapper failing once the size limit is exceeded.
+struct LimitedPayload {
+    payload: Payload,
+    remaining: u64,
+}
+
+impl Stream for LimitedPayload {
+    type Item = Result<Bytes, PayloadError>;
+
+    fn poll_next(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Self::Item>> {
+        let this = self.get_mut();
+        match Pin::new(&mut this.payload).poll_next(cx) {
+            Poll::Ready(Some(Ok(chunk))) => match this.remaining.checked_sub(chunk.len() as u64) {
+                Some(remaining) => {
+                    this.remaining = remaining;
+                    Poll::Ready(Some(Ok(chunk)))
+                }
+                None => Poll::Ready(Some(Err(PayloadError::Overflow))),
+            },
+            poll => poll,
+        }
+    }
+}
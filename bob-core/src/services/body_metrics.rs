@@ -0,0 +1,161 @@
+//! Request/Response Body Size Metrics
+//!
+//! Records body size distributions for a directive and optionally
+//! logs requests whose bodies exceed a configured size.
+
+use std::{
+    pin::Pin,
+    rc::Rc,
+    task::{Context, Poll},
+};
+
+use actix_web::{
+    Error,
+    body::{BodySize, BoxBody, MessageBody},
+    dev::{Service, ServiceRequest, ServiceResponse, Transform},
+    http::header,
+    web::Bytes,
+};
+
+use super::LocalBoxFuture;
+use crate::metrics::{self, Histogram, SIZE_BUCKETS};
+
+const REQUEST_METRIC: &str = "bob_request_size_bytes";
+const RESPONSE_METRIC: &str = "bob_response_size_bytes";
+
+struct Inner {
+    requests: Histogram,
+    responses: Histogram,
+    log_larger_than: Option<u64>,
+}
+
+/// Body size metrics middleware.
+#[derive(Clone)]
+pub struct SizeMetrics(Rc<Inner>);
+
+impl SizeMetrics {
+    /// Create new size metrics middleware labeled by directive location.
+    pub fn new(location: &str, log_larger_than: Option<u64>) -> Self {
+        let labels = format!("directive={location:?}");
+        Self(Rc::new(Inner {
+            requests: metrics::histogram(REQUEST_METRIC, &labels, SIZE_BUCKETS),
+            responses: metrics::histogram(RESPONSE_METRIC, &labels, SIZE_BUCKETS),
+            log_larger_than,
+        }))
+    }
+}
+
+impl<S, B> Transform<S, ServiceRequest> for SizeMetrics
+where
+    S: Service<ServiceRequest, Response = ServiceResponse<B>, Error = Error> + 'static,
+    B: MessageBody + 'static,
+{
+    type Response = ServiceResponse<CountingBody>;
+    type Error = Error;
+    type Transform = SizeMetricsService<S>;
+    type InitError = ();
+    type Future = std::future::Ready<Result<Self::Transform, Self::InitError>>;
+
+    fn new_transform(&self, service: S) -> Self::Future {
+        std::future::ready(Ok(SizeMetricsService {
+            service: Rc::new(service),
+            inner: Rc::clone(&self.0),
+        }))
+    }
+}
+
+/// Service produced by [`SizeMetrics`] middleware.
+pub struct SizeMetricsService<S> {
+    service: Rc<S>,
+    inner: Rc<Inner>,
+}
+
+impl<S, B> Service<ServiceRequest> for SizeMetricsService<S>
+where
+    S: Service<ServiceRequest, Response = ServiceResponse<B>, Error = Error> + 'static,
+    B: MessageBody + 'static,
+{
+    type Response = ServiceResponse<CountingBody>;
+    type Error = Error;
+    type Future = LocalBoxFuture<Result<Self::Response, Self::Error>>;
+
+    actix_web::dev::forward_ready!(service);
+
+    fn call(&self, req: ServiceRequest) -> Self::Future {
+        let request_size = req
+            .headers()
+            .get(header::CONTENT_LENGTH)
+            .and_then(|v| v.to_str().ok())
+            .and_then(|v| v.parse::<u64>().ok())
+            .unwrap_or(0);
+        self.inner.requests.observe(request_size);
+
+        let limit = self.inner.log_larger_than;
+        let context = limit.map(|_| {
+            let ip = req.peer_addr().map(|a| a.ip().to_string());
+            let ip = ip.unwrap_or_else(|| "-".to_owned());
+            format!("{ip} \"{} {}\"", req.method(), req.uri())
+        });
+        if let (Some(limit), Some(context)) = (limit, context.as_ref())
+            && request_size > limit
+        {
+            log::warn!("{context} request body of {request_size} bytes exceeds {limit}");
+        }
+
+        let service = Rc::clone(&self.service);
+        let inner = Rc::clone(&self.inner);
+        Box::pin(async move {
+            let res = service.call(req).await?;
+            Ok(res.map_body(move |_, body| CountingBody {
+                body: body.boxed(),
+                size: 0,
+                inner,
+                context,
+            }))
+        })
+    }
+}
+
+/// Response body wrapper recording the number of bytes streamed.
+pub struct CountingBody {
+    body: BoxBody,
+    size: u64,
+    inner: Rc<Inner>,
+    context: Option<String>,
+}
+
+impl MessageBody for CountingBody {
+    type Error = Box<dyn std::error::Error>;
+
+    #[inline]
+    fn size(&self) -> BodySize {
+        self.body.size()
+    }
+
+    fn poll_next(
+        self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+    ) -> Poll<Option<Result<Bytes, Self::Error>>> {
+        let this = self.get_mut();
+        let poll = Pin::new(&mut this.body).poll_next(cx);
+        if let Poll::Ready(Some(Ok(chunk))) = &poll {
+            this.size += chunk.len() as u64;
+        }
+        poll
+    }
+}
+
+impl Drop for CountingBody {
+    fn drop(&mut self) {
+        self.inner.responses.observe(self.size);
+        let context = self.context.as_ref();
+        if let (Some(limit), Some(context)) = (self.inner.log_larger_than, context)
+            && self.size > limit
+        {
+            log::warn!(
+                "{context} response body of {} bytes exceeds {limit}",
+                self.size
+            );
+        }
+    }
+}
@@ -0,0 +1,245 @@
+//! Directive Latency and Error Budgets
+//!
+//! Tracks latency and server errors of a directive within a rolling
+//! window. Once the configured budget is exceeded the directive is
+//! flagged as degraded for a cooldown period, exposing the state via
+//! metrics and the admin api and optionally routing requests to a
+//! fallback chain until the cooldown expires.
+
+use std::{
+    rc::Rc,
+    sync::{Arc, Mutex},
+    time::{Duration, Instant},
+};
+
+use actix_web::{
+    Error,
+    body::MessageBody,
+    dev::{Service, ServiceRequest, ServiceResponse, Transform},
+    guard::{Guard, GuardContext},
+};
+
+use super::LocalBoxFuture;
+use crate::metrics::{self, Counter};
+
+/// Budget thresholds for a directive.
+#[derive(Clone, Debug)]
+pub struct Settings {
+    /// Latency requests are expected to complete within.
+    pub max_latency: Option<Duration>,
+    /// Fraction of requests permitted to exceed the max latency.
+    pub max_slow_rate: f64,
+    /// Fraction of requests permitted to fail with server errors.
+    pub max_error_rate: Option<f64>,
+    /// Window requests are evaluated within.
+    pub window: Duration,
+    /// Minimum requests within the window before evaluating.
+    pub min_requests: u32,
+    /// Time the directive stays degraded once the budget is exceeded.
+    pub cooldown: Duration,
+}
+
+#[derive(Debug)]
+struct Window {
+    started: Instant,
+    total: u32,
+    slow: u32,
+    errors: u32,
+    degraded_until: Option<Instant>,
+}
+
+impl Default for Window {
+    fn default() -> Self {
+        Self {
+            started: Instant::now(),
+            total: 0,
+            slow: 0,
+            errors: 0,
+            degraded_until: None,
+        }
+    }
+}
+
+/// Shared budget tracking state for a single directive.
+#[derive(Clone, Default)]
+pub struct State(Arc<Mutex<Window>>);
+
+impl std::fmt::Debug for State {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "State {{}}")
+    }
+}
+
+/// Point in time summary of budget state.
+#[derive(Clone, Debug)]
+pub struct Summary {
+    /// Directive is currently degraded.
+    pub degraded: bool,
+    /// Requests recorded in the current window.
+    pub total: u32,
+    /// Requests exceeding the max latency in the current window.
+    pub slow: u32,
+    /// Requests failing with server errors in the current window.
+    pub errors: u32,
+}
+
+impl State {
+    /// Check if the directive is currently degraded.
+    pub fn degraded(&self) -> bool {
+        let window = self.0.lock().expect("budget state poisoned");
+        window
+            .degraded_until
+            .is_some_and(|until| until > Instant::now())
+    }
+
+    /// Summarize current window and degraded state.
+    pub fn summary(&self) -> Summary {
+        let window = self.0.lock().expect("budget state poisoned");
+        Summary {
+            degraded: window.degraded_until.is_some_and(|t| t > Instant::now()),
+            total: window.total,
+            slow: window.slow,
+            errors: window.errors,
+        }
+    }
+
+    /// Record completed request and evaluate the budget.
+    ///
+    /// Returns true if the budget was breached by this request.
+    fn record(&self, settings: &Settings, elapsed: Duration, error: bool) -> bool {
+        let now = Instant::now();
+        let mut window = self.0.lock().expect("budget state poisoned");
+        if now.duration_since(window.started) > settings.window {
+            window.started = now;
+            window.total = 0;
+            window.slow = 0;
+            window.errors = 0;
+        }
+        window.total += 1;
+        if settings.max_latency.is_some_and(|max| elapsed > max) {
+            window.slow += 1;
+        }
+        if error {
+            window.errors += 1;
+        }
+        if window.total < settings.min_requests
+            || window.degraded_until.is_some_and(|until| until > now)
+        {
+            return false;
+        }
+        let total = window.total as f64;
+        let slow =
+            settings.max_latency.is_some() && window.slow as f64 / total > settings.max_slow_rate;
+        let errors = settings
+            .max_error_rate
+            .is_some_and(|max| window.errors as f64 / total > max);
+        if !slow && !errors {
+            return false;
+        }
+        window.degraded_until = Some(now + settings.cooldown);
+        window.started = now;
+        window.total = 0;
+        window.slow = 0;
+        window.errors = 0;
+        true
+    }
+}
+
+/// Guard matching requests while the directive is degraded.
+pub struct Degraded(pub State);
+
+impl Guard for Degraded {
+    #[inline]
+    fn check(&self, _ctx: &GuardContext<'_>) -> bool {
+        self.0.degraded()
+    }
+}
+
+/// Budget tracking middleware.
+pub struct Budget {
+    location: Rc<str>,
+    settings: Rc<Settings>,
+    state: State,
+}
+
+impl Budget {
+    /// Create new budget middleware for the directive location.
+    pub fn new(location: &str, settings: Settings, state: State) -> Self {
+        Self {
+            location: location.into(),
+            settings: Rc::new(settings),
+            state,
+        }
+    }
+}
+
+impl<S, B> Transform<S, ServiceRequest> for Budget
+where
+    S: Service<ServiceRequest, Response = ServiceResponse<B>, Error = Error> + 'static,
+    B: MessageBody + 'static,
+{
+    type Response = ServiceResponse<B>;
+    type Error = Error;
+    type Transform = BudgetService<S>;
+    type InitError = ();
+    type Future = std::future::Ready<Result<Self::Transform, Self::InitError>>;
+
+    fn new_transform(&self, service: S) -> Self::Future {
+        let labels = format!("location={:?}", self.location);
+        std::future::ready(Ok(BudgetService {
+            service: Rc::new(service),
+            location: Rc::clone(&self.location),
+            settings: Rc::clone(&self.settings),
+            state: self.state.clone(),
+            breaches: metrics::counter(&format!("bob_budget_breaches_total{{{labels}}}")),
+            degraded: metrics::counter(&format!("bob_budget_degraded{{{labels}}}")),
+        }))
+    }
+}
+
+/// Service produced by [`Budget`] middleware.
+pub struct BudgetService<S> {
+    service: Rc<S>,
+    location: Rc<str>,
+    settings: Rc<Settings>,
+    state: State,
+    breaches: &'static Counter,
+    degraded: &'static Counter,
+}
+
+impl<S, B> Service<ServiceRequest> for BudgetService<S>
+where
+    S: Service<ServiceRequest, Response = ServiceResponse<B>, Error = Error> + 'static,
+    B: MessageBody + 'static,
+{
+    type Response = ServiceResponse<B>;
+    type Error = Error;
+    type Future = LocalBoxFuture<Result<Self::Response, Self::Error>>;
+
+    actix_web::dev::forward_ready!(service);
+
+    fn call(&self, req: ServiceRequest) -> Self::Future {
+        let service = Rc::clone(&self.service);
+        let location = Rc::clone(&self.location);
+        let settings = Rc::clone(&self.settings);
+        let state = self.state.clone();
+        let (breaches, degraded) = (self.breaches, self.degraded);
+        Box::pin(async move {
+            let start = Instant::now();
+            let result = service.call(req).await;
+            let error = match result.as_ref() {
+                Ok(res) => res.status().is_server_error(),
+                Err(err) => err.as_response_error().status_code().is_server_error(),
+            };
+            if state.record(&settings, start.elapsed(), error) {
+                log::warn!(
+                    "directive {location:?} exceeded its budget. degraded for {:?}",
+                    settings.cooldown
+                );
+                breaches.inc();
+            }
+            degraded.set(state.degraded() as u64);
+            result
+        })
+    }
+}
@@ -0,0 +1,119 @@
+//! Conditional Middleware Bypass
+//!
+//! Wraps an inner middleware so requests matching a predicate skip
+//! the inner middleware entirely and are passed directly to the
+//! wrapped service.
+
+use std::{
+    rc::Rc,
+    task::{Context, Poll},
+};
+
+use actix_web::{
+    Error,
+    body::{EitherBody, MessageBody},
+    dev::{Service, ServiceRequest, ServiceResponse, Transform},
+    http::header,
+};
+
+use super::{LocalBoxFuture, RcService};
+
+/// Request predicate used to determine bypass.
+pub type Predicate = Rc<dyn Fn(&ServiceRequest) -> bool>;
+
+/// Middleware skipping the inner middleware when predicate matches.
+pub struct Bypass<T> {
+    inner: T,
+    skip: Predicate,
+}
+
+impl<T> Bypass<T> {
+    /// Wrap inner middleware with bypass predicate.
+    pub fn new(inner: T, skip: Predicate) -> Self {
+        Self { inner, skip }
+    }
+}
+
+impl<S, B, T, B2> Transform<S, ServiceRequest> for Bypass<T>
+where
+    S: Service<ServiceRequest, Response = ServiceResponse<B>, Error = Error> + 'static,
+    B: MessageBody + 'static,
+    T: Transform<
+            RcService<S>,
+            ServiceRequest,
+            Response = ServiceResponse<B2>,
+            Error = Error,
+            InitError = (),
+        >,
+    T::Future: 'static,
+    T::Transform: 'static,
+    B2: MessageBody + 'static,
+{
+    type Response = ServiceResponse<EitherBody<B2, B>>;
+    type Error = Error;
+    type Transform = BypassService<S, T::Transform>;
+    type InitError = ();
+    type Future = LocalBoxFuture<Result<Self::Transform, Self::InitError>>;
+
+    fn new_transform(&self, service: S) -> Self::Future {
+        let service = Rc::new(service);
+        let skip = Rc::clone(&self.skip);
+        let inner = self.inner.new_transform(RcService(Rc::clone(&service)));
+        Box::pin(async move {
+            Ok(BypassService {
+                service,
+                inner: Rc::new(inner.await?),
+                skip,
+            })
+        })
+    }
+}
+
+/// Service produced by [`Bypass`] middleware.
+pub struct BypassService<S, T> {
+    service: Rc<S>,
+    inner: Rc<T>,
+    skip: Predicate,
+}
+
+impl<S, B, T, B2> Service<ServiceRequest> for BypassService<S, T>
+where
+    S: Service<ServiceRequest, Response = ServiceResponse<B>, Error = Error> + 'static,
+    B: MessageBody + 'static,
+    T: Service<ServiceRequest, Response = ServiceResponse<B2>, Error = Error> + 'static,
+    B2: MessageBody + 'static,
+{
+    type Response = ServiceResponse<EitherBody<B2, B>>;
+    type Error = Error;
+    type Future = LocalBoxFuture<Result<Self::Response, Self::Error>>;
+
+    #[inline]
+    fn poll_ready(&self, cx: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
+        self.inner.poll_ready(cx)
+    }
+
+    fn call(&self, req: ServiceRequest) -> Self::Future {
+        match (self.skip)(&req) {
+            true => {
+                let service = Rc::clone(&self.service);
+                Box::pin(async move { Ok(service.call(req).await?.map_into_right_body()) })
+            }
+            false => {
+                let inner = Rc::clone(&self.inner);
+                Box::pin(async move { Ok(inner.call(req).await?.map_into_left_body()) })
+            }
+        }
+    }
+}
+
+/// Check if request is a CORS preflight request.
+///
+/// Browsers never attach credentials to preflights so they
+/// commonly need to skip authentication and ratelimiting.
+pub fn is_preflight(req: &ServiceRequest) -> bool {
+    req.method() == actix_web::http::Method::OPTIONS
+        && req.headers().contains_key(header::ORIGIN)
+        && req
+            .headers()
+            .contains_key(header::ACCESS_CONTROL_REQUEST_METHOD)
+}
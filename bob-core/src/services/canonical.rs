@@ -0,0 +1,265 @@
+//! Canonical URL Redirect Middleware
+//!
+//! Redirects requests to their canonical url before they reach any
+//! module, normalizing the trailing slash of directory urls and moving
+//! clients onto the canonical host and https scheme.
+//!
+//! The requested host is always read from the request itself and the
+//! scheme from the listener, unless trusted proxies are configured to
+//! forward it, so spoofed forwarding headers cannot steer redirects.
+
+use std::rc::Rc;
+
+use actix_web::{
+    Error, HttpResponse,
+    body::{EitherBody, MessageBody},
+    dev::{Service, ServiceRequest, ServiceResponse, Transform},
+    http::{Method, StatusCode, header},
+};
+
+use super::LocalBoxFuture;
+use crate::config::{SlashPolicy, request_host, split_host};
+
+/// Canonical redirect settings.
+#[derive(Clone, Debug)]
+pub struct Settings {
+    /// Trailing slash policy applied to request paths.
+    pub slash: SlashPolicy,
+    /// Host requests are redirected to when another is requested.
+    pub host: Option<String>,
+    /// Redirect plain http requests to https.
+    pub https: bool,
+    /// Trust the scheme reported by forwarding proxy headers.
+    pub forwarded: bool,
+    /// Redirect status used for `GET` and `HEAD` requests.
+    ///
+    /// Other methods use `308 Permanent Redirect` to preserve the body.
+    pub status: StatusCode,
+}
+
+impl Settings {
+    /// Settings only applying a trailing slash policy.
+    pub fn slash(policy: SlashPolicy) -> Self {
+        Self {
+            slash: policy,
+            host: None,
+            https: false,
+            forwarded: false,
+            status: StatusCode::MOVED_PERMANENTLY,
+        }
+    }
+}
+
+/// Canonical redirect middleware.
+#[derive(Clone)]
+pub struct Canonical(Rc<Settings>);
+
+impl Canonical {
+    /// Create new canonical redirect middleware from settings.
+    pub fn new(settings: Settings) -> Self {
+        Self(Rc::new(settings))
+    }
+}
+
+impl<S, B> Transform<S, ServiceRequest> for Canonical
+where
+    S: Service<ServiceRequest, Response = ServiceResponse<B>, Error = Error> + 'static,
+    B: MessageBody + 'static,
+{
+    type Response = ServiceResponse<EitherBody<B>>;
+    type Error = Error;
+    type Transform = CanonicalService<S>;
+    type InitError = ();
+    type Future = std::future::Ready<Result<Self::Transform, Self::InitError>>;
+
+    fn new_transform(&self, service: S) -> Self::Future {
+        std::future::ready(Ok(CanonicalService {
+            service: Rc::new(service),
+            settings: Rc::clone(&self.0),
+        }))
+    }
+}
+
+/// Service produced by [`Canonical`] middleware.
+pub struct CanonicalService<S> {
+    service: Rc<S>,
+    settings: Rc<Settings>,
+}
+
+/// Apply the trailing slash policy to the path.
+///
+/// Slashes are only added to paths whose last segment has no
+/// file extension, so `/docs` becomes `/docs/` but `/app.js` is kept.
+fn apply_slash(policy: &SlashPolicy, path: &str) -> Option<String> {
+    match policy {
+        SlashPolicy::Ignore => None,
+        SlashPolicy::Add => {
+            let last = path.rsplit('/').next().unwrap_or_default();
+            (!path.ends_with('/') && !last.contains('.')).then(|| format!("{path}/"))
+        }
+        SlashPolicy::Strip => {
+            let trimmed = path.trim_end_matches('/');
+            (path.len() > 1 && trimmed.len() < path.len()).then(|| match trimmed {
+                "" => "/".to_owned(),
+                trimmed => trimmed.to_owned(),
+            })
+        }
+    }
+}
+
+/// Build the canonical location if it differs from the request.
+///
+/// `scheme` and `host` describe the request as received while
+/// `path` and `query` are taken from its uri.
+fn location(
+    settings: &Settings,
+    scheme: &str,
+    host: &str,
+    path: &str,
+    query: &str,
+) -> Option<String> {
+    let slashed = apply_slash(&settings.slash, path);
+    let upgrade = settings.https && scheme == "http";
+    let (name, _) = split_host(host);
+    let rehost = settings
+        .host
+        .as_ref()
+        .filter(|host| !split_host(host).0.eq_ignore_ascii_case(name));
+    if slashed.is_none() && !upgrade && rehost.is_none() {
+        return None;
+    }
+
+    // `//evil.com` would be followed as a protocol-relative url
+    let path = format!(
+        "/{}",
+        slashed
+            .as_deref()
+            .unwrap_or(path)
+            .trim_start_matches(['/', '\\'])
+    );
+    let query = match query {
+        "" => String::new(),
+        query => format!("?{query}"),
+    };
+    if !upgrade && rehost.is_none() {
+        return Some(format!("{path}{query}"));
+    }
+    let scheme = if upgrade { "https" } else { scheme };
+    // the port of a plain http listener is never valid for https
+    let host = match (rehost, upgrade) {
+        (Some(host), _) => host.as_str(),
+        (None, true) => name,
+        (None, false) => host,
+    };
+    Some(format!("{scheme}://{host}{path}{query}"))
+}
+
+impl<S> CanonicalService<S> {
+    /// Build the canonical location if it differs from the request.
+    fn location(&self, req: &ServiceRequest) -> Option<String> {
+        let settings = &self.settings;
+        let config = req.app_config();
+        let scheme = match (settings.forwarded, config.secure()) {
+            (true, _) => req.connection_info().scheme().to_owned(),
+            (false, true) => "https".to_owned(),
+            (false, false) => "http".to_owned(),
+        };
+        let host = request_host(req.head()).unwrap_or(config.host());
+        location(settings, &scheme, host, req.path(), req.query_string())
+    }
+}
+
+impl<S, B> Service<ServiceRequest> for CanonicalService<S>
+where
+    S: Service<ServiceRequest, Response = ServiceResponse<B>, Error = Error> + 'static,
+    B: MessageBody + 'static,
+{
+    type Response = ServiceResponse<EitherBody<B>>;
+    type Error = Error;
+    type Future = LocalBoxFuture<Result<Self::Response, Self::Error>>;
+
+    actix_web::dev::forward_ready!(service);
+
+    fn call(&self, req: ServiceRequest) -> Self::Future {
+        let Some(location) = self.location(&req) else {
+            let service = Rc::clone(&self.service);
+            return Box::pin(async move { Ok(service.call(req).await?.map_into_left_body()) });
+        };
+        let status = match *req.method() {
+            Method::GET | Method::HEAD => self.settings.status,
+            _ => StatusCode::PERMANENT_REDIRECT,
+        };
+        let res = HttpResponse::build(status)
+            .insert_header((header::LOCATION, location))
+            .finish();
+        Box::pin(async move { Ok(req.into_response(res).map_into_right_body()) })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn settings(host: Option<&str>, https: bool) -> Settings {
+        Settings {
+            slash: SlashPolicy::Ignore,
+            host: host.map(str::to_owned),
+            https,
+            forwarded: false,
+            status: StatusCode::MOVED_PERMANENTLY,
+        }
+    }
+
+    #[test]
+    fn add_slash_skips_files() {
+        let add = SlashPolicy::Add;
+        assert_eq!(apply_slash(&add, "/docs").as_deref(), Some("/docs/"));
+        assert_eq!(apply_slash(&add, "/docs/"), None);
+        assert_eq!(apply_slash(&add, "/app.js"), None);
+        assert_eq!(apply_slash(&add, "/"), None);
+    }
+
+    #[test]
+    fn strip_slash_keeps_root() {
+        let strip = SlashPolicy::Strip;
+        assert_eq!(apply_slash(&strip, "/docs/").as_deref(), Some("/docs"));
+        assert_eq!(apply_slash(&strip, "/docs//").as_deref(), Some("/docs"));
+        assert_eq!(apply_slash(&strip, "//").as_deref(), Some("/"));
+        assert_eq!(apply_slash(&strip, "/"), None);
+        assert_eq!(apply_slash(&strip, "/docs"), None);
+        assert_eq!(apply_slash(&SlashPolicy::Ignore, "/docs/"), None);
+    }
+
+    #[test]
+    fn location_upgrades_scheme_and_host() {
+        let https = settings(None, true);
+        assert_eq!(location(&https, "https", "example.com", "/a", ""), None);
+        assert_eq!(
+            location(&https, "http", "example.com:8080", "/a", "b=1").as_deref(),
+            Some("https://example.com/a?b=1")
+        );
+
+        let rehost = settings(Some("www.example.com"), false);
+        assert_eq!(location(&rehost, "http", "WWW.example.com", "/", ""), None);
+        assert_eq!(
+            location(&rehost, "http", "example.com", "/a", "").as_deref(),
+            Some("http://www.example.com/a")
+        );
+    }
+
+    #[test]
+    fn location_never_protocol_relative() {
+        let add = Settings {
+            slash: SlashPolicy::Add,
+            ..settings(None, false)
+        };
+        assert_eq!(
+            location(&add, "http", "example.com", "//evil", "").as_deref(),
+            Some("/evil/")
+        );
+        assert_eq!(
+            location(&add, "http", "example.com", "/\\evil", "").as_deref(),
+            Some("/evil/")
+        );
+    }
+}
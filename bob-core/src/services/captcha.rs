@@ -0,0 +1,377 @@
+//! CAPTCHA Challenge Middleware
+//!
+//! Unverified clients are served an interstitial page embedding a
+//! Cloudflare Turnstile or hCaptcha widget. Solved challenges are
+//! verified server-side with the provider and remembered using a
+//! signed cookie so clients are not challenged on every request.
+
+use std::{
+    collections::HashMap,
+    net::IpAddr,
+    rc::Rc,
+    time::{Duration, SystemTime, UNIX_EPOCH},
+};
+
+use actix_web::{
+    Error, HttpResponse,
+    body::{EitherBody, MessageBody},
+    cookie::{Cookie, SameSite},
+    dev::{Service, ServiceRequest, ServiceResponse, Transform},
+    http::{Method, header},
+    web,
+};
+use hmac::{Hmac, Mac};
+use serde::Deserialize;
+use sha2::Sha256;
+
+use super::LocalBoxFuture;
+use crate::config::PathMatch;
+
+type HmacSha256 = Hmac<Sha256>;
+
+/// CAPTCHA provider used to challenge clients.
+#[derive(Clone, Copy, Debug)]
+pub enum Provider {
+    Turnstile,
+    HCaptcha,
+}
+
+impl Provider {
+    /// Script embedding the provider widget.
+    fn script(&self) -> &'static str {
+        match self {
+            Self::Turnstile => "https://challenges.cloudflare.com/turnstile/v0/api.js",
+            Self::HCaptcha => "https://js.hcaptcha.com/1/api.js",
+        }
+    }
+    /// Class name of the provider widget element.
+    fn widget(&self) -> &'static str {
+        match self {
+            Self::Turnstile => "cf-turnstile",
+            Self::HCaptcha => "h-captcha",
+        }
+    }
+    /// Form field containing the solved challenge token.
+    fn field(&self) -> &'static str {
+        match self {
+            Self::Turnstile => "cf-turnstile-response",
+            Self::HCaptcha => "h-captcha-response",
+        }
+    }
+    /// Server-side token verification endpoint.
+    fn verify_url(&self) -> &'static str {
+        match self {
+            Self::Turnstile => "https://challenges.cloudflare.com/turnstile/v0/siteverify",
+            Self::HCaptcha => "https://api.hcaptcha.com/siteverify",
+        }
+    }
+}
+
+/// CAPTCHA challenge settings.
+#[derive(Clone, Debug)]
+pub struct Settings {
+    /// Challenge provider.
+    pub provider: Provider,
+    /// Public site key embedded into the challenge page.
+    pub site_key: String,
+    /// Secret key used to verify tokens with the provider.
+    pub secret: String,
+    /// Key signing remembered challenge cookies, see [`cookie_key`].
+    pub cookie_key: [u8; 32],
+    /// Cookie remembering successful challenges.
+    pub cookie: String,
+    /// Duration successful challenges are remembered.
+    pub duration: Duration,
+    /// Bind remembered challenges to the client address.
+    pub bind_ip: bool,
+    /// Path challenge responses are submitted to.
+    pub verify_path: String,
+    /// Paths never challenged.
+    pub exclude: Vec<PathMatch>,
+}
+
+/// Seconds since the unix epoch.
+#[inline]
+fn now() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|d| d.as_secs())
+        .unwrap_or_default()
+}
+
+/// Escape text for use within html attributes.
+fn escape(text: &str) -> String {
+    text.chars()
+        .fold(String::with_capacity(text.len()), |mut out, c| {
+            match c {
+                '&' => out.push_str("&amp;"),
+                '<' => out.push_str("&lt;"),
+                '>' => out.push_str("&gt;"),
+                '"' => out.push_str("&quot;"),
+                '\'' => out.push_str("&#39;"),
+                c => out.push(c),
+            }
+            out
+        })
+}
+
+/// Derive the cookie signing key from the provider secret.
+///
+/// Keeps the provider secret itself from being used as the hmac key.
+pub fn cookie_key(secret: &str) -> [u8; 32] {
+    let mut mac = HmacSha256::new_from_slice(secret.as_bytes()).expect("hmac accepts any key");
+    mac.update(b"bob captcha cookie");
+    mac.finalize().into_bytes().into()
+}
+
+/// Only permit local redirects after verification.
+///
+/// Browsers ignore control characters and treat backslashes as slashes,
+/// so targets such as `/\t/evil.com` are rejected as well.
+fn safe_redirect(target: Option<&String>) -> &str {
+    match target {
+        Some(t)
+            if t.starts_with('/')
+                && !t.starts_with("//")
+                && !t.chars().any(|c| c == '\\' || c.is_control()) =>
+        {
+            t
+        }
+        _ => "/",
+    }
+}
+
+/// Provider token verification response.
+#[derive(Debug, Deserialize)]
+struct Verification {
+    success: bool,
+    #[serde(default, rename = "error-codes")]
+    error_codes: Vec<String>,
+}
+
+impl Settings {
+    /// Produce cookie signature for expiry and optional client address.
+    fn signature(&self, expires: u64, ip: Option<IpAddr>) -> HmacSha256 {
+        let mut mac = HmacSha256::new_from_slice(&self.cookie_key).expect("hmac accepts any key");
+        mac.update(expires.to_string().as_bytes());
+        if self.bind_ip
+            && let Some(ip) = ip
+        {
+            mac.update(b"|");
+            mac.update(ip.to_string().as_bytes());
+        }
+        mac
+    }
+
+    /// Generate signed cookie value for a solved challenge.
+    fn sign(&self, ip: Option<IpAddr>) -> String {
+        let expires = now() + self.duration.as_secs();
+        let digest = self.signature(expires, ip).finalize().into_bytes();
+        let hex: String = digest.iter().map(|b| format!("{b:02x}")).collect();
+        format!("{expires}.{hex}")
+    }
+
+    /// Check if the request carries a valid challenge cookie.
+    fn verified(&self, req: &ServiceRequest) -> bool {
+        let Some(cookie) = req.cookie(&self.cookie) else {
+            return false;
+        };
+        let Some((expires, hex)) = cookie.value().split_once('.') else {
+            return false;
+        };
+        let Ok(expires) = expires.parse::<u64>() else {
+            return false;
+        };
+        if expires < now() || hex.len() % 2 != 0 {
+            return false;
+        }
+        let digest = (0..hex.len())
+            .step_by(2)
+            .map(|i| u8::from_str_radix(hex.get(i..i + 2)?, 16).ok())
+            .collect::<Option<Vec<u8>>>();
+        let ip = req.peer_addr().map(|addr| addr.ip());
+        digest.is_some_and(|d| self.signature(expires, ip).verify_slice(&d).is_ok())
+    }
+
+    /// Check if the request path is protected by the challenge.
+    fn protects(&self, path: &str) -> bool {
+        !self.exclude.iter().any(|p| p.matches(path))
+    }
+
+    /// Render the interstitial challenge page.
+    fn page(&self, redirect: &str) -> HttpResponse {
+        let body = format!(
+            r#"<!DOCTYPE html>
+<html>
+<head>
+<meta charset="utf-8">
+<meta name="viewport" content="width=device-width, initial-scale=1">
+<meta name="robots" content="noindex">
+<title>Checking your browser</title>
+<script src="{script}" async defer></script>
+</head>
+<body style="font-family: sans-serif; text-align: center; margin-top: 15vh">
+<h1>Checking your browser</h1>
+<p>Please complete the challenge below to continue.</p>
+<form method="post" action="{action}">
+<input type="hidden" name="redirect" value="{redirect}">
+<div class="{widget}" data-sitekey="{site_key}" data-callback="bobChallengeSolved"></div>
+<noscript><p>JavaScript is required to complete the challenge.</p></noscript>
+<button type="submit">Continue</button>
+</form>
+<script>function bobChallengeSolved() {{ document.forms[0].submit(); }}</script>
+</body>
+</html>
+"#,
+            script = self.provider.script(),
+            action = escape(&self.verify_path),
+            redirect = escape(redirect),
+            widget = self.provider.widget(),
+            site_key = escape(&self.site_key),
+        );
+        HttpResponse::Forbidden()
+            .insert_header((header::CACHE_CONTROL, "no-store"))
+            .content_type("text/html; charset=utf-8")
+            .body(body)
+    }
+
+    /// Verify solved challenge token with the provider.
+    async fn verify_token(
+        &self,
+        client: &awc::Client,
+        token: &str,
+        ip: Option<IpAddr>,
+    ) -> Result<bool, String> {
+        let mut form = vec![
+            ("secret", self.secret.clone()),
+            ("response", token.to_owned()),
+        ];
+        if let Some(ip) = ip {
+            form.push(("remoteip", ip.to_string()));
+        }
+        let mut res = client
+            .post(self.provider.verify_url())
+            .send_form(&form)
+            .await
+            .map_err(|err| err.to_string())?;
+        let verification: Verification = res.json().await.map_err(|err| err.to_string())?;
+        if !verification.success {
+            log::debug!(
+                "captcha verification failed: {:?}",
+                verification.error_codes
+            );
+        }
+        Ok(verification.success)
+    }
+}
+
+/// CAPTCHA challenge middleware.
+pub struct Captcha {
+    settings: Rc<Settings>,
+    client: awc::Client,
+}
+
+impl Captcha {
+    /// Create new challenge middleware from settings and http client.
+    pub fn new(settings: Settings, client: awc::Client) -> Self {
+        Self {
+            settings: Rc::new(settings),
+            client,
+        }
+    }
+}
+
+impl<S, B> Transform<S, ServiceRequest> for Captcha
+where
+    S: Service<ServiceRequest, Response = ServiceResponse<B>, Error = Error> + 'static,
+    B: MessageBody + 'static,
+{
+    type Response = ServiceResponse<EitherBody<B>>;
+    type Error = Error;
+    type Transform = CaptchaService<S>;
+    type InitError = ();
+    type Future = std::future::Ready<Result<Self::Transform, Self::InitError>>;
+
+    fn new_transform(&self, service: S) -> Self::Future {
+        std::future::ready(Ok(CaptchaService {
+            service: Rc::new(service),
+            settings: Rc::clone(&self.settings),
+            client: self.client.clone(),
+        }))
+    }
+}
+
+/// Service produced by [`Captcha`] middleware.
+pub struct CaptchaService<S> {
+    service: Rc<S>,
+    settings: Rc<Settings>,
+    client: awc::Client,
+}
+
+impl<S, B> Service<ServiceRequest> for CaptchaService<S>
+where
+    S: Service<ServiceRequest, Response = ServiceResponse<B>, Error = Error> + 'static,
+    B: MessageBody + 'static,
+{
+    type Response = ServiceResponse<EitherBody<B>>;
+    type Error = Error;
+    type Future = LocalBoxFuture<Result<Self::Response, Self::Error>>;
+
+    actix_web::dev::forward_ready!(service);
+
+    fn call(&self, mut req: ServiceRequest) -> Self::Future {
+        let settings = Rc::clone(&self.settings);
+        if req.method() == Method::POST && req.path() == settings.verify_path {
+            let client = self.client.clone();
+            return Box::pin(async move {
+                let form = req
+                    .extract::<web::Form<HashMap<String, String>>>()
+                    .await
+                    .map(|f| f.into_inner())
+                    .unwrap_or_default();
+                let redirect = safe_redirect(form.get("redirect")).to_owned();
+                let ip = req.peer_addr().map(|addr| addr.ip());
+                let token = form.get(settings.provider.field()).cloned();
+                let verified = match token {
+                    Some(token) => settings
+                        .verify_token(&client, &token, ip)
+                        .await
+                        .inspect_err(|err| log::error!("captcha verification error: {err}"))
+                        .unwrap_or(false),
+                    None => false,
+                };
+                if !verified {
+                    let res = settings.page(&redirect);
+                    return Ok(req.into_response(res).map_into_right_body());
+                }
+                let secure = req.connection_info().scheme() == "https";
+                let cookie = Cookie::build(settings.cookie.clone(), settings.sign(ip))
+                    .path("/")
+                    .http_only(true)
+                    .secure(secure)
+                    .same_site(SameSite::Lax)
+                    .max_age(actix_web::cookie::time::Duration::seconds(
+                        settings.duration.as_secs() as i64,
+                    ))
+                    .finish();
+                let res = HttpResponse::SeeOther()
+                    .insert_header((header::LOCATION, redirect))
+                    .cookie(cookie)
+                    .finish();
+                Ok(req.into_response(res).map_into_right_body())
+            });
+        }
+
+        if !settings.protects(req.path()) || settings.verified(&req) {
+            let service = Rc::clone(&self.service);
+            return Box::pin(async move { Ok(service.call(req).await?.map_into_left_body()) });
+        }
+        let redirect = req
+            .uri()
+            .path_and_query()
+            .map(|pq| pq.as_str().to_owned())
+            .unwrap_or_else(|| "/".to_owned());
+        let res = settings.page(&redirect);
+        Box::pin(async move { Ok(req.into_response(res).map_into_right_body()) })
+    }
+}
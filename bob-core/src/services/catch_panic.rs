@@ -0,0 +1,116 @@
+//! Panic Recovery Middleware
+//!
+//! Converts panics raised while processing a request into
+//! `500 Internal Server Error` responses rather than letting
+//! them unwind through and kill the worker thread.
+
+use std::{
+    any::Any,
+    future::Future,
+    panic::{AssertUnwindSafe, catch_unwind},
+    pin::Pin,
+    rc::Rc,
+    task::{Context, Poll},
+};
+
+use actix_web::{
+    Error, HttpResponse,
+    body::{EitherBody, MessageBody},
+    dev::{Service, ServiceRequest, ServiceResponse, Transform},
+};
+
+use super::LocalBoxFuture;
+use crate::metrics;
+
+/// Metric counting the number of recovered panics.
+pub const PANIC_METRIC: &str = "bob_worker_panics_total";
+
+/// Future wrapper catching panics raised while polling.
+struct CatchUnwind<F>(Pin<Box<F>>);
+
+impl<F: Future> Future for CatchUnwind<F> {
+    type Output = Result<F::Output, Box<dyn Any + Send>>;
+
+    fn poll(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Self::Output> {
+        let fut = self.0.as_mut();
+        match catch_unwind(AssertUnwindSafe(|| fut.poll(cx))) {
+            Ok(Poll::Pending) => Poll::Pending,
+            Ok(Poll::Ready(out)) => Poll::Ready(Ok(out)),
+            Err(panic) => Poll::Ready(Err(panic)),
+        }
+    }
+}
+
+/// Extract readable message from panic payload.
+fn panic_message(panic: &(dyn Any + Send)) -> &str {
+    panic
+        .downcast_ref::<&str>()
+        .copied()
+        .or_else(|| panic.downcast_ref::<String>().map(|s| s.as_str()))
+        .unwrap_or("unknown panic")
+}
+
+/// Panic recovery middleware.
+#[derive(Clone, Debug, Default)]
+pub struct CatchPanic;
+
+impl<S, B> Transform<S, ServiceRequest> for CatchPanic
+where
+    S: Service<ServiceRequest, Response = ServiceResponse<B>, Error = Error> + 'static,
+    B: MessageBody + 'static,
+{
+    type Response = ServiceResponse<EitherBody<B>>;
+    type Error = Error;
+    type Transform = CatchPanicService<S>;
+    type InitError = ();
+    type Future = std::future::Ready<Result<Self::Transform, Self::InitError>>;
+
+    fn new_transform(&self, service: S) -> Self::Future {
+        std::future::ready(Ok(CatchPanicService {
+            service: Rc::new(service),
+        }))
+    }
+}
+
+/// Service produced by [`CatchPanic`] middleware.
+pub struct CatchPanicService<S> {
+    service: Rc<S>,
+}
+
+impl<S, B> Service<ServiceRequest> for CatchPanicService<S>
+where
+    S: Service<ServiceRequest, Response = ServiceResponse<B>, Error = Error> + 'static,
+    B: MessageBody + 'static,
+{
+    type Response = ServiceResponse<EitherBody<B>>;
+    type Error = Error;
+    type Future = LocalBoxFuture<Result<Self::Response, Self::Error>>;
+
+    actix_web::dev::forward_ready!(service);
+
+    fn call(&self, req: ServiceRequest) -> Self::Future {
+        let service = Rc::clone(&self.service);
+        let request = req.request().clone();
+        Box::pin(async move {
+            let fut = catch_unwind(AssertUnwindSafe(|| service.call(req)));
+            let result = match fut {
+                Ok(fut) => CatchUnwind(Box::pin(fut)).await,
+                Err(panic) => Err(panic),
+            };
+            match result {
+                Ok(res) => Ok(res?.map_into_left_body()),
+                Err(panic) => {
+                    metrics::counter(PANIC_METRIC).inc();
+                    log::error!(
+                        "panic while processing {} {}: {}",
+                        request.method(),
+                        request.path(),
+                        panic_message(panic.as_ref())
+                    );
+                    let response = HttpResponse::InternalServerError().finish();
+                    Ok(ServiceResponse::new(request, response).map_into_right_body())
+                }
+            }
+        })
+    }
+}
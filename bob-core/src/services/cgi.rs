@@ -0,0 +1,335 @@
+//! CGI Environment and Response Utilities
+
+use std::{
+    collections::BTreeMap,
+    io::{self, Read, Write},
+    path::PathBuf,
+    process::{Command, ExitStatus, Stdio},
+    sync::{
+        Arc,
+        atomic::{AtomicUsize, Ordering},
+        mpsc::{self, RecvTimeoutError},
+    },
+    time::{Duration, Instant},
+};
+
+use actix_web::{HttpRequest, HttpResponse, http::StatusCode, web::Bytes};
+
+const SERVER_SOFTWARE: &str = concat!(env!("CARGO_PKG_NAME"), "/", env!("CARGO_PKG_VERSION"));
+
+/// Interval used to poll for child process completion.
+const POLL_INTERVAL: Duration = Duration::from_millis(5);
+
+/// Default largest output read from hook programs.
+pub const DEFAULT_MAX_OUTPUT: usize = 16 * 1024 * 1024;
+
+/// Collect configured program environment, inheriting `PATH` when unset.
+pub fn program_env(configured: &BTreeMap<String, String>) -> Vec<(String, String)> {
+    let mut env: Vec<(String, String)> = configured
+        .iter()
+        .map(|(k, v)| (k.clone(), v.clone()))
+        .collect();
+    if !configured.contains_key("PATH")
+        && let Ok(path) = std::env::var("PATH")
+    {
+        env.push(("PATH".to_owned(), path));
+    }
+    env
+}
+
+/// Generate standard CGI/1.1 environment variables from request.
+pub fn request_env(req: &HttpRequest) -> Vec<(String, String)> {
+    let info = req.connection_info();
+    let host = info.host().to_owned();
+    let (name, port) = match host.rsplit_once(':') {
+        Some((name, port)) => (name.to_owned(), port.to_owned()),
+        None => (
+            host,
+            if info.scheme() == "https" {
+                "443"
+            } else {
+                "80"
+            }
+            .to_owned(),
+        ),
+    };
+
+    let mut env = vec![
+        ("GATEWAY_INTERFACE".to_owned(), "CGI/1.1".to_owned()),
+        ("SERVER_SOFTWARE".to_owned(), SERVER_SOFTWARE.to_owned()),
+        ("SERVER_PROTOCOL".to_owned(), format!("{:?}", req.version())),
+        ("SERVER_NAME".to_owned(), name),
+        ("SERVER_PORT".to_owned(), port),
+        ("REQUEST_SCHEME".to_owned(), info.scheme().to_owned()),
+        ("REQUEST_METHOD".to_owned(), req.method().to_string()),
+        ("REQUEST_URI".to_owned(), req.uri().to_string()),
+        (
+            "PATH_INFO".to_owned(),
+            req.match_info().unprocessed().to_owned(),
+        ),
+        ("QUERY_STRING".to_owned(), req.query_string().to_owned()),
+    ];
+    if let Some(peer) = req.peer_addr() {
+        env.push(("REMOTE_ADDR".to_owned(), peer.ip().to_string()));
+        env.push(("REMOTE_PORT".to_owned(), peer.port().to_string()));
+    }
+    for (name, value) in req.headers().iter() {
+        // `Proxy` would become `HTTP_PROXY` and redirect outgoing
+        // requests made by the program (httpoxy)
+        if name.as_str() == "proxy" {
+            continue;
+        }
+        let Ok(value) = value.to_str() else { continue };
+        let key = match name.as_str() {
+            "content-type" => "CONTENT_TYPE".to_owned(),
+            "content-length" => "CONTENT_LENGTH".to_owned(),
+            name => format!("HTTP_{}", name.to_uppercase().replace('-', "_")),
+        };
+        env.push((key, value.to_owned()));
+    }
+    env
+}
+
+/// External program invocation settings.
+#[derive(Clone, Debug)]
+pub struct Program {
+    pub command: PathBuf,
+    pub args: Vec<String>,
+    pub workdir: Option<PathBuf>,
+    pub timeout: Duration,
+    /// Largest output read before the program is failed.
+    pub max_output: usize,
+}
+
+impl Program {
+    /// Run program to completion with the given environment and stdin.
+    ///
+    /// Fails if the program exits unsuccessfully without any output.
+    /// This call blocks and should only be run within [`actix_web::web::block`].
+    pub fn run(&self, env: Vec<(String, String)>, stdin: Option<Bytes>) -> io::Result<Vec<u8>> {
+        let (status, stdout) = self.execute(env, stdin)?;
+        if !status.success() && stdout.is_empty() {
+            return Err(io::Error::other(format!("program exited with {status}")));
+        }
+        Ok(stdout)
+    }
+
+    /// Run program to completion returning its exit status and output.
+    ///
+    /// This call blocks and should only be run within [`actix_web::web::block`].
+    pub fn execute(
+        &self,
+        env: Vec<(String, String)>,
+        stdin: Option<Bytes>,
+    ) -> io::Result<(ExitStatus, Vec<u8>)> {
+        let mut cmd = Command::new(&self.command);
+        cmd.args(&self.args)
+            .env_clear()
+            .envs(env)
+            .stdin(Stdio::piped())
+            .stdout(Stdio::piped())
+            .stderr(Stdio::inherit());
+        if let Some(workdir) = self.workdir.as_ref() {
+            cmd.current_dir(workdir);
+        }
+        let mut child = cmd.spawn()?;
+
+        // write stdin from separate thread to avoid deadlocking on full pipes
+        let mut input = child.stdin.take().expect("missing child stdin");
+        std::thread::spawn(move || {
+            if let Some(stdin) = stdin {
+                let _ = input.write_all(&stdin);
+            }
+        });
+        let output = child.stdout.take().expect("missing child stdout");
+        let limit = self.max_output;
+        let (tx, rx) = mpsc::channel();
+        std::thread::spawn(move || {
+            // closing the pipe once over the limit stops the program writing
+            let mut buf = Vec::new();
+            let result = output
+                .take(limit.saturating_add(1) as u64)
+                .read_to_end(&mut buf)
+                .map(|_| buf);
+            let _ = tx.send(result);
+        });
+
+        let deadline = Instant::now() + self.timeout;
+        loop {
+            if let Some(status) = child.try_wait()? {
+                // background descendants may hold the output pipe open
+                let left = deadline.saturating_duration_since(Instant::now());
+                let stdout = match rx.recv_timeout(left) {
+                    Ok(stdout) => stdout?,
+                    Err(RecvTimeoutError::Timeout) => {
+                        return Err(io::Error::new(
+                            io::ErrorKind::TimedOut,
+                            "program output not closed before timeout",
+                        ));
+                    }
+                    Err(RecvTimeoutError::Disconnected) => {
+                        return Err(io::Error::other("stdout reader panicked"));
+                    }
+                };
+                if stdout.len() > self.max_output {
+                    return Err(io::Error::other(format!(
+                        "program output exceeded {} bytes",
+                        self.max_output
+                    )));
+                }
+                return Ok((status, stdout));
+            }
+            if Instant::now() >= deadline {
+                let _ = child.kill();
+                let _ = child.wait();
+                return Err(io::Error::new(io::ErrorKind::TimedOut, "program timed out"));
+            }
+            std::thread::sleep(POLL_INTERVAL);
+        }
+    }
+}
+
+/// Count of programs currently running shared between workers.
+#[derive(Clone, Default)]
+pub struct Active(Arc<AtomicUsize>);
+
+impl std::fmt::Debug for Active {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "Active({})", self.0.load(Ordering::Relaxed))
+    }
+}
+
+impl Active {
+    /// Reserve a slot for a running program if below the limit.
+    pub fn acquire(&self, max: usize) -> Option<Slot> {
+        self.0
+            .fetch_update(Ordering::AcqRel, Ordering::Acquire, |n| {
+                (n < max).then_some(n + 1)
+            })
+            .ok()
+            .map(|_| Slot(Arc::clone(&self.0)))
+    }
+}
+
+/// Reserved running program slot released on drop.
+pub struct Slot(Arc<AtomicUsize>);
+
+impl Drop for Slot {
+    fn drop(&mut self) {
+        self.0.fetch_sub(1, Ordering::AcqRel);
+    }
+}
+
+/// Parsed CGI style response output.
+#[derive(Debug, Default)]
+pub struct CgiResponse {
+    pub status: Option<StatusCode>,
+    pub headers: Vec<(String, String)>,
+    pub body: Bytes,
+}
+
+impl CgiResponse {
+    /// Parse CGI response from program output.
+    ///
+    /// Output is expected to be a series of `Name: Value` header lines
+    /// followed by an empty line and then the response body.
+    pub fn parse(output: &[u8]) -> io::Result<Self> {
+        let (head, body) = match find_split(output) {
+            Some((end, start)) => (&output[..end], &output[start..]),
+            None => (output, &[][..]),
+        };
+        let head = std::str::from_utf8(head).map_err(io::Error::other)?;
+
+        let mut response = Self {
+            body: Bytes::copy_from_slice(body),
+            ..Default::default()
+        };
+        for line in head.lines().map(|l| l.trim()).filter(|l| !l.is_empty()) {
+            let (name, value) = line
+                .split_once(':')
+                .ok_or_else(|| io::Error::other(format!("invalid header line {line:?}")))?;
+            let (name, value) = (name.trim(), value.trim());
+            if name.eq_ignore_ascii_case("status") {
+                let code = value.split_whitespace().next().unwrap_or_default();
+                let code = code.parse::<u16>().map_err(io::Error::other)?;
+                response.status = Some(StatusCode::from_u16(code).map_err(io::Error::other)?);
+                continue;
+            }
+            response.headers.push((name.to_owned(), value.to_owned()));
+        }
+        Ok(response)
+    }
+
+    /// Retrieve first header value matching the specified name.
+    pub fn header(&self, name: &str) -> Option<&str> {
+        self.headers
+            .iter()
+            .find(|(h, _)| h.eq_ignore_ascii_case(name))
+            .map(|(_, v)| v.as_str())
+    }
+
+    /// Convert parsed output into [`actix_web::HttpResponse`].
+    pub fn into_response(self) -> HttpResponse {
+        let status = match self.status {
+            Some(status) => status,
+            None if self.header("location").is_some() => StatusCode::FOUND,
+            None => StatusCode::OK,
+        };
+        let mut builder = HttpResponse::build(status);
+        self.headers
+            .into_iter()
+            .fold(&mut builder, |b, (h, v)| b.append_header((h, v)));
+        builder.body(self.body)
+    }
+}
+
+/// Find header/body split returning (header-end, body-start).
+#[inline]
+fn find_split(output: &[u8]) -> Option<(usize, usize)> {
+    let crlf = output.windows(4).position(|w| w == b"\r\n\r\n");
+    let lf = output.windows(2).position(|w| w == b"\n\n");
+    match (crlf, lf) {
+        (Some(c), Some(l)) if l < c => Some((l, l + 2)),
+        (Some(c), _) => Some((c, c + 4)),
+        (None, Some(l)) => Some((l, l + 2)),
+        (None, None) => None,
+    }
+}
+
+#[cfg(all(test, unix))]
+mod tests {
+    use super::*;
+
+    fn shell(script: &str, timeout: Duration, max_output: usize) -> Program {
+        Program {
+            command: PathBuf::from("/bin/sh"),
+            args: vec!["-c".to_owned(), script.to_owned()],
+            workdir: None,
+            timeout,
+            max_output,
+        }
+    }
+
+    #[test]
+    fn uncapped_output() {
+        let program = shell("printf hello", Duration::from_secs(5), usize::MAX);
+        assert_eq!(program.run(vec![], None).unwrap(), b"hello");
+    }
+
+    #[test]
+    fn capped_output() {
+        let program = shell("printf hello", Duration::from_secs(5), 4);
+        assert!(program.run(vec![], None).is_err());
+        let program = shell("printf hello", Duration::from_secs(5), 5);
+        assert_eq!(program.run(vec![], None).unwrap(), b"hello");
+    }
+
+    #[test]
+    fn background_output_holder_times_out() {
+        let start = Instant::now();
+        let program = shell("sleep 5 & printf hello", Duration::from_millis(200), 64);
+        let err = program.run(vec![], None).unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::TimedOut);
+        assert!(start.elapsed() < Duration::from_secs(2));
+    }
+}
@@ -0,0 +1,227 @@
+//! CGI Script Execution Service
+//!
+//! Executes scripts stored beneath a configured directory for every
+//! request addressed to them, following classic `cgi-bin` conventions.
+//! The first path segment resolving to a file names the script and any
+//! remaining segments are passed along as `PATH_INFO`. Scripts run with
+//! the standard CGI/1.1 environment, the buffered request body on stdin
+//! and their output parsed as a CGI response.
+
+use std::{
+    collections::BTreeMap,
+    io,
+    path::{Path, PathBuf},
+    sync::Arc,
+    time::Duration,
+};
+
+use actix_web::{
+    Error, HttpRequest, HttpResponse,
+    error::{
+        ErrorGatewayTimeout, ErrorInternalServerError, ErrorNotFound, ErrorPayloadTooLarge,
+        ErrorServiceUnavailable,
+    },
+    web,
+};
+
+use super::cgi::{Active, CgiResponse, Program, request_env};
+
+/// CGI script execution settings.
+#[derive(Clone, Debug)]
+pub struct Settings {
+    /// Directory containing executable scripts.
+    pub root: PathBuf,
+    /// File extensions allowed to execute. Any file when empty.
+    pub extensions: Vec<String>,
+    /// Interpreters used to run scripts by file extension.
+    pub interpreters: BTreeMap<String, PathBuf>,
+    /// Additional environment variables passed to scripts.
+    pub env: Vec<(String, String)>,
+    /// Maximum time allowed for a script to complete.
+    pub timeout: Duration,
+    /// Maximum number of scripts running at once.
+    pub max_concurrent: usize,
+    /// Largest request body passed to scripts.
+    pub max_body_size: usize,
+    /// Largest script output read.
+    pub max_output_size: usize,
+}
+
+/// Script resolved from the request path.
+#[derive(Debug)]
+struct Script {
+    /// Absolute path of the script file.
+    filename: PathBuf,
+    /// Script path relative to the configured root.
+    name: String,
+    /// Remaining request path following the script.
+    path_info: String,
+}
+
+/// Check if any of the file's executable permission bits are set.
+#[cfg(unix)]
+fn has_exec_bit(meta: &std::fs::Metadata) -> bool {
+    use std::os::unix::fs::PermissionsExt;
+    meta.permissions().mode() & 0o111 != 0
+}
+
+/// Platforms without executable bits only run scripts with an interpreter.
+#[cfg(not(unix))]
+fn has_exec_bit(_meta: &std::fs::Metadata) -> bool {
+    false
+}
+
+impl Settings {
+    /// Resolve the script addressed by the request path.
+    ///
+    /// This call blocks and should only be run within [`actix_web::web::block`].
+    fn resolve(&self, path: &str) -> Option<Script> {
+        let segments: Vec<&str> = path.split('/').filter(|s| !s.is_empty()).collect();
+        let mut filename = self.root.clone();
+        for (n, segment) in segments.iter().enumerate() {
+            // never expose hidden files or escape the script directory
+            if segment.starts_with('.') || segment.contains('\\') {
+                return None;
+            }
+            filename.push(segment);
+            let meta = std::fs::metadata(&filename).ok()?;
+            if meta.is_dir() {
+                continue;
+            }
+            if !meta.is_file() || !self.executable(&filename, &meta) {
+                return None;
+            }
+            let rest = &segments[n + 1..];
+            return Some(Script {
+                filename,
+                name: format!("/{}", segments[..=n].join("/")),
+                path_info: match rest.is_empty() {
+                    true => String::new(),
+                    false => format!("/{}", rest.join("/")),
+                },
+            });
+        }
+        None
+    }
+
+    /// Check if the file is allowed to be executed.
+    fn executable(&self, path: &Path, meta: &std::fs::Metadata) -> bool {
+        let ext = path.extension().and_then(|e| e.to_str()).unwrap_or("");
+        if !self.extensions.is_empty() && !self.extensions.iter().any(|e| e == ext) {
+            return false;
+        }
+        self.interpreters.contains_key(ext) || has_exec_bit(meta)
+    }
+
+    /// Build program invocation for the resolved script.
+    fn program(&self, script: &Script) -> Program {
+        let ext = script
+            .filename
+            .extension()
+            .and_then(|e| e.to_str())
+            .unwrap_or("");
+        let (command, args) = match self.interpreters.get(ext) {
+            Some(interpreter) => (
+                interpreter.to_owned(),
+                vec![script.filename.to_string_lossy().into_owned()],
+            ),
+            None => (script.filename.clone(), vec![]),
+        };
+        Program {
+            command,
+            args,
+            workdir: script.filename.parent().map(|p| p.to_owned()),
+            timeout: self.timeout,
+            max_output: self.max_output_size,
+        }
+    }
+}
+
+/// CGI script execution service.
+#[derive(Clone, Debug)]
+pub struct CgiBin {
+    settings: Arc<Settings>,
+    active: Active,
+}
+
+impl CgiBin {
+    /// Create new CGI service from settings and shared running count.
+    pub fn new(settings: Settings, active: Active) -> Self {
+        Self {
+            settings: Arc::new(settings),
+            active,
+        }
+    }
+
+    /// Execute the requested script and return its response.
+    pub async fn handle(
+        &self,
+        req: HttpRequest,
+        payload: web::Payload,
+    ) -> Result<HttpResponse, Error> {
+        // read the body first so slow uploads never hold a running slot
+        let body = payload
+            .to_bytes_limited(self.settings.max_body_size)
+            .await
+            .map_err(|_| ErrorPayloadTooLarge("request body too large"))??;
+        let Some(slot) = self.active.acquire(self.settings.max_concurrent) else {
+            log::warn!("cgi concurrency limit reached");
+            return Err(ErrorServiceUnavailable("too many running scripts"));
+        };
+
+        let unprocessed = req.match_info().unprocessed().to_owned();
+        let base = req
+            .path()
+            .strip_suffix(&unprocessed)
+            .unwrap_or_default()
+            .trim_end_matches('/')
+            .to_owned();
+        let mut env = request_env(&req);
+        env.retain(|(k, _)| k != "PATH_INFO");
+        env.extend(self.settings.env.iter().cloned());
+
+        let settings = Arc::clone(&self.settings);
+        let resolved = web::block(move || settings.resolve(&unprocessed)).await?;
+        let Some(script) = resolved else {
+            log::debug!("cgi script not found for {:?}", req.path());
+            return Err(ErrorNotFound("script not found"));
+        };
+        log::debug!("cgi executing {:?}", script.filename);
+
+        let filename = script.filename.to_string_lossy().into_owned();
+        env.extend([
+            ("SCRIPT_NAME".to_owned(), format!("{base}{}", script.name)),
+            ("SCRIPT_FILENAME".to_owned(), filename),
+            ("PATH_INFO".to_owned(), script.path_info.clone()),
+            (
+                "DOCUMENT_ROOT".to_owned(),
+                self.settings.root.to_string_lossy().into_owned(),
+            ),
+            ("REDIRECT_STATUS".to_owned(), "200".to_owned()),
+        ]);
+        if !body.is_empty() && !env.iter().any(|(k, _)| k == "CONTENT_LENGTH") {
+            env.push(("CONTENT_LENGTH".to_owned(), body.len().to_string()));
+        }
+
+        let program = self.settings.program(&script);
+        let output = web::block(move || program.run(env, Some(body)))
+            .await?
+            .map_err(|err| match err.kind() {
+                io::ErrorKind::TimedOut => {
+                    log::error!("cgi script {:?} timed out", script.filename);
+                    ErrorGatewayTimeout("script timed out")
+                }
+                _ => {
+                    log::error!("cgi script {:?} failed: {err:?}", script.filename);
+                    ErrorInternalServerError("script failed")
+                }
+            })?;
+        drop(slot);
+
+        let response = CgiResponse::parse(&output).map_err(|err| {
+            log::error!("cgi script returned invalid response: {err:?}");
+            ErrorInternalServerError("invalid script response")
+        })?;
+        Ok(response.into_response())
+    }
+}
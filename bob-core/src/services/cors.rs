@@ -0,0 +1,175 @@
+//! Cross-Origin Resource Sharing (CORS) Middleware
+//!
+//! Answers preflight requests directly and attaches CORS headers
+//! to responses for allowed origins.
+
+use std::rc::Rc;
+
+use actix_web::{
+    Error, HttpResponse,
+    body::{EitherBody, MessageBody},
+    dev::{Service, ServiceRequest, ServiceResponse, Transform},
+    http::header::{self, HeaderMap, HeaderName, HeaderValue},
+};
+
+use super::{LocalBoxFuture, bypass::is_preflight};
+
+/// CORS policy settings.
+#[derive(Clone, Debug)]
+pub struct Settings {
+    /// Allowed origin globs. Empty allows any origin.
+    pub origins: Vec<glob::Pattern>,
+    /// Allowed request methods.
+    pub methods: Vec<String>,
+    /// Allowed request headers. Empty reflects the requested headers.
+    pub headers: Vec<String>,
+    /// Response headers exposed to scripts.
+    pub expose_headers: Vec<String>,
+    /// Allow credentials (cookies, authorization) to be included.
+    pub credentials: bool,
+    /// Seconds preflight results may be cached for.
+    pub max_age: Option<u64>,
+}
+
+impl Settings {
+    /// Determine `Access-Control-Allow-Origin` value for request origin.
+    fn allow_origin(&self, origin: &str) -> Option<String> {
+        // credentials are never combined with any origin, see `prepare`
+        if self.origins.is_empty() {
+            return (!self.credentials).then(|| "*".to_owned());
+        }
+        self.origins
+            .iter()
+            .any(|p| p.matches(origin))
+            .then(|| origin.to_owned())
+    }
+
+    /// Insert CORS headers shared by preflight and actual responses.
+    fn apply(&self, headers: &mut HeaderMap, origin: String) {
+        // keep any vary entries already set by the wrapped service
+        let varies = headers
+            .get_all(header::VARY)
+            .filter_map(|v| v.to_str().ok())
+            .flat_map(|v| v.split(','))
+            .any(|v| v.trim().eq_ignore_ascii_case("origin"));
+        if origin != "*" && !varies {
+            headers.append(header::VARY, HeaderValue::from_static("Origin"));
+        }
+        let mut insert = |name: HeaderName, value: &str| {
+            if let Ok(value) = HeaderValue::from_str(value) {
+                headers.insert(name, value);
+            }
+        };
+        insert(header::ACCESS_CONTROL_ALLOW_ORIGIN, &origin);
+        if self.credentials {
+            insert(header::ACCESS_CONTROL_ALLOW_CREDENTIALS, "true");
+        }
+        if !self.expose_headers.is_empty() {
+            insert(
+                header::ACCESS_CONTROL_EXPOSE_HEADERS,
+                &self.expose_headers.join(", "),
+            );
+        }
+    }
+
+    /// Build response to preflight request.
+    fn preflight(&self, req: &ServiceRequest) -> HttpResponse {
+        let mut res = HttpResponse::NoContent().finish();
+        let Some(origin) = origin(req).and_then(|o| self.allow_origin(&o)) else {
+            return res;
+        };
+        let headers = res.headers_mut();
+        self.apply(headers, origin);
+        if let Ok(methods) = HeaderValue::from_str(&self.methods.join(", ")) {
+            headers.insert(header::ACCESS_CONTROL_ALLOW_METHODS, methods);
+        }
+        let allow_headers = match self.headers.is_empty() {
+            true => req
+                .headers()
+                .get(header::ACCESS_CONTROL_REQUEST_HEADERS)
+                .cloned(),
+            false => HeaderValue::from_str(&self.headers.join(", ")).ok(),
+        };
+        if let Some(allow_headers) = allow_headers {
+            headers.insert(header::ACCESS_CONTROL_ALLOW_HEADERS, allow_headers);
+        }
+        if let Some(max_age) = self.max_age {
+            headers.insert(header::ACCESS_CONTROL_MAX_AGE, max_age.into());
+        }
+        res
+    }
+}
+
+#[inline]
+fn origin(req: &ServiceRequest) -> Option<String> {
+    req.headers()
+        .get(header::ORIGIN)
+        .and_then(|o| o.to_str().ok())
+        .map(|o| o.to_owned())
+}
+
+/// CORS middleware.
+#[derive(Clone)]
+pub struct Cors(Rc<Settings>);
+
+impl Cors {
+    /// Create new CORS middleware from settings.
+    pub fn new(settings: Settings) -> Self {
+        Self(Rc::new(settings))
+    }
+}
+
+impl<S, B> Transform<S, ServiceRequest> for Cors
+where
+    S: Service<ServiceRequest, Response = ServiceResponse<B>, Error = Error> + 'static,
+    B: MessageBody + 'static,
+{
+    type Response = ServiceResponse<EitherBody<B>>;
+    type Error = Error;
+    type Transform = CorsService<S>;
+    type InitError = ();
+    type Future = std::future::Ready<Result<Self::Transform, Self::InitError>>;
+
+    fn new_transform(&self, service: S) -> Self::Future {
+        std::future::ready(Ok(CorsService {
+            service: Rc::new(service),
+            settings: Rc::clone(&self.0),
+        }))
+    }
+}
+
+/// Service produced by [`Cors`] middleware.
+pub struct CorsService<S> {
+    service: Rc<S>,
+    settings: Rc<Settings>,
+}
+
+impl<S, B> Service<ServiceRequest> for CorsService<S>
+where
+    S: Service<ServiceRequest, Response = ServiceResponse<B>, Error = Error> + 'static,
+    B: MessageBody + 'static,
+{
+    type Response = ServiceResponse<EitherBody<B>>;
+    type Error = Error;
+    type Future = LocalBoxFuture<Result<Self::Response, Self::Error>>;
+
+    actix_web::dev::forward_ready!(service);
+
+    fn call(&self, req: ServiceRequest) -> Self::Future {
+        let settings = Rc::clone(&self.settings);
+        if is_preflight(&req) {
+            let res = settings.preflight(&req);
+            return Box::pin(async move { Ok(req.into_response(res).map_into_right_body()) });
+        }
+
+        let service = Rc::clone(&self.service);
+        let allowed = origin(&req).and_then(|o| settings.allow_origin(&o));
+        Box::pin(async move {
+            let mut res = service.call(req).await?;
+            if let Some(origin) = allowed {
+                settings.apply(res.headers_mut(), origin);
+            }
+            Ok(res.map_into_left_body())
+        })
+    }
+}
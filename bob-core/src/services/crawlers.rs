@@ -0,0 +1,43 @@
+//! Crawler Signatures
+//!
+//! Maintained user-agent tokens of well known crawlers shared by the
+//! user-agent filter and the generated robots.txt presets.
+
+/// Known AI crawler and training data collection user-agent signatures.
+pub const AI_CRAWLERS: &[&str] = &[
+    "AI2Bot",
+    "Ai2Bot-Dolma",
+    "Amazonbot",
+    "anthropic-ai",
+    "Applebot-Extended",
+    "Bytespider",
+    "CCBot",
+    "ChatGPT-User",
+    "Claude-Web",
+    "ClaudeBot",
+    "cohere-ai",
+    "cohere-training-data-crawler",
+    "Diffbot",
+    "DuckAssistBot",
+    "FacebookBot",
+    "FriendlyCrawler",
+    "Google-Extended",
+    "GoogleOther",
+    "GPTBot",
+    "ICC-Crawler",
+    "ImagesiftBot",
+    "img2dataset",
+    "Kangaroo Bot",
+    "Meta-ExternalAgent",
+    "Meta-ExternalFetcher",
+    "OAI-SearchBot",
+    "omgili",
+    "PerplexityBot",
+    "Perplexity-User",
+    "PetalBot",
+    "Scrapy",
+    "Timpibot",
+    "VelenPublicWebCrawler",
+    "Webzio-Extended",
+    "YouBot",
+];
@@ -0,0 +1,134 @@
+//! OWASP Core Rule Set Bootstrap
+//!
+//! Downloads a pinned release of the OWASP Core Rule Set (CRS) into a
+//! local cache directory and assembles the engine setup required to run
+//! it with modsecurity. Releases already present in the cache directory
+//! are used as-is, so pre-populating it allows fully offline installs.
+
+use std::{
+    fs,
+    io::Read,
+    path::{Path, PathBuf},
+};
+
+use anyhow::{Context, Result, anyhow, bail};
+use sha2::{Digest, Sha256};
+
+/// CRS release used when no version is pinned.
+pub const DEFAULT_VERSION: &str = "4.7.0";
+
+/// Largest release archive accepted for download.
+const MAX_ARCHIVE_SIZE: u64 = 64 * 1024 * 1024;
+
+/// Loaded Core Rule Set.
+#[derive(Clone, Debug)]
+pub struct Crs {
+    /// Engine and CRS setup rules loaded ahead of the rule files.
+    pub setup: String,
+    /// Rule files in load order.
+    pub rules: Vec<PathBuf>,
+}
+
+/// Default directory CRS releases are cached in.
+pub fn default_dir() -> PathBuf {
+    std::env::var_os("XDG_CACHE_HOME")
+        .map(PathBuf::from)
+        .or_else(|| std::env::var_os("HOME").map(|home| Path::new(&home).join(".cache")))
+        .unwrap_or_else(std::env::temp_dir)
+        .join("bob")
+        .join("crs")
+}
+
+/// Check the version is a plain release number safe to use in paths.
+pub fn validate_version(version: &str) -> Result<()> {
+    let valid = !version.is_empty()
+        && version
+            .split('.')
+            .all(|n| !n.is_empty() && n.chars().all(|c| c.is_ascii_digit()));
+    match valid {
+        true => Ok(()),
+        false => Err(anyhow!("invalid crs version {version:?}")),
+    }
+}
+
+/// Download and extract the release unless already cached.
+///
+/// Returns the directory containing the extracted release.
+pub fn install(dir: &Path, version: &str, checksum: Option<&str>) -> Result<PathBuf> {
+    let root = dir.join(version);
+    if root.join("rules").is_dir() {
+        log::debug!("using cached crs {version} at {root:?}");
+        return Ok(root);
+    }
+
+    let url =
+        format!("https://github.com/coreruleset/coreruleset/archive/refs/tags/v{version}.tar.gz");
+    log::info!("downloading owasp crs {version} from {url:?}");
+    let mut archive = Vec::new();
+    ureq::get(&url)
+        .call()
+        .with_context(|| format!("failed to download crs {version}"))?
+        .into_reader()
+        .take(MAX_ARCHIVE_SIZE)
+        .read_to_end(&mut archive)
+        .with_context(|| format!("failed to download crs {version}"))?;
+
+    if let Some(expected) = checksum {
+        let digest: [u8; 32] = Sha256::digest(&archive).into();
+        let actual: String = digest.iter().map(|b| format!("{b:02x}")).collect();
+        if !actual.eq_ignore_ascii_case(expected.trim()) {
+            bail!("crs {version} checksum mismatch (expected {expected}, got {actual})");
+        }
+    }
+
+    fs::create_dir_all(dir).with_context(|| format!("failed to create crs dir {dir:?}"))?;
+    let partial = dir.join(format!(".{version}.partial"));
+    if partial.exists() {
+        fs::remove_dir_all(&partial)
+            .with_context(|| format!("failed to clear partial crs {partial:?}"))?;
+    }
+    let decoder = flate2::read::GzDecoder::new(archive.as_slice());
+    tar::Archive::new(decoder)
+        .unpack(&partial)
+        .with_context(|| format!("failed to extract crs {version}"))?;
+
+    // release archives contain a single `coreruleset-<version>` directory
+    let extracted = partial.join(format!("coreruleset-{version}"));
+    if !extracted.join("rules").is_dir() {
+        bail!("crs {version} archive is missing its rules directory");
+    }
+    fs::rename(&extracted, &root).with_context(|| format!("failed to install crs to {root:?}"))?;
+    let _ = fs::remove_dir_all(&partial);
+    log::info!("installed owasp crs {version} to {root:?}");
+    Ok(root)
+}
+
+/// Assemble the rule set from an extracted release.
+pub fn load(root: &Path, paranoia_level: u8, inspect_response: bool) -> Result<Crs> {
+    let example = root.join("crs-setup.conf.example");
+    let crs_setup = fs::read_to_string(&example)
+        .with_context(|| format!("failed to read crs setup {example:?}"))?;
+
+    let response_access = if inspect_response { "On" } else { "Off" };
+    let setup = format!(
+        "SecRuleEngine On\n\
+         SecRequestBodyAccess On\n\
+         SecResponseBodyAccess {response_access}\n\
+         {crs_setup}\n\
+         SecAction \"id:900000,phase:1,pass,t:none,nolog,\
+         setvar:tx.paranoia_level={paranoia_level},\
+         setvar:tx.blocking_paranoia_level={paranoia_level}\"\n"
+    );
+
+    let dir = root.join("rules");
+    let mut rules = fs::read_dir(&dir)
+        .with_context(|| format!("failed to read crs rules {dir:?}"))?
+        .filter_map(|entry| entry.ok().map(|e| e.path()))
+        .filter(|path| path.extension().is_some_and(|ext| ext == "conf"))
+        .collect::<Vec<_>>();
+    rules.sort();
+    if rules.is_empty() {
+        bail!("no crs rules found in {dir:?}");
+    }
+    Ok(Crs { setup, rules })
+}
@@ -0,0 +1,83 @@
+//! Request Deadline Middleware
+//!
+//! Answers requests with `504 Gateway Timeout` once the wrapped service
+//! fails to produce a response within the configured time, dropping any
+//! work still in progress.
+
+use std::{rc::Rc, time::Duration};
+
+use actix_web::{
+    Error, HttpResponse,
+    body::{EitherBody, MessageBody},
+    dev::{Service, ServiceRequest, ServiceResponse, Transform},
+};
+
+use super::LocalBoxFuture;
+
+/// Request deadline middleware.
+#[derive(Clone)]
+pub struct Deadline {
+    timeout: Duration,
+}
+
+impl Deadline {
+    /// Create new deadline middleware allowing `timeout` per request.
+    pub fn new(timeout: Duration) -> Self {
+        Self { timeout }
+    }
+}
+
+impl<S, B> Transform<S, ServiceRequest> for Deadline
+where
+    S: Service<ServiceRequest, Response = ServiceResponse<B>, Error = Error> + 'static,
+    B: MessageBody + 'static,
+{
+    type Response = ServiceResponse<EitherBody<B>>;
+    type Error = Error;
+    type Transform = DeadlineService<S>;
+    type InitError = ();
+    type Future = std::future::Ready<Result<Self::Transform, Self::InitError>>;
+
+    fn new_transform(&self, service: S) -> Self::Future {
+        std::future::ready(Ok(DeadlineService {
+            service: Rc::new(service),
+            timeout: self.timeout,
+        }))
+    }
+}
+
+/// Service produced by [`Deadline`] middleware.
+pub struct DeadlineService<S> {
+    service: Rc<S>,
+    timeout: Duration,
+}
+
+impl<S, B> Service<ServiceRequest> for DeadlineService<S>
+where
+    S: Service<ServiceRequest, Response = ServiceResponse<B>, Error = Error> + 'static,
+    B: MessageBody + 'static,
+{
+    type Response = ServiceResponse<EitherBody<B>>;
+    type Error = Error;
+    type Future = LocalBoxFuture<Result<Self::Response, Self::Error>>;
+
+    actix_web::dev::forward_ready!(service);
+
+    fn call(&self, req: ServiceRequest) -> Self::Future {
+        // keep a handle to the request for answering after the timeout
+        let http = req.request().clone();
+        let path = req.path().to_owned();
+        let call = self.service.call(req);
+        let timeout = self.timeout;
+        Box::pin(async move {
+            match actix_web::rt::time::timeout(timeout, call).await {
+                Ok(res) => Ok(res?.map_into_left_body()),
+                Err(_) => {
+                    log::warn!("request {path} exceeded deadline of {timeout:?}");
+                    let res = HttpResponse::GatewayTimeout().finish();
+                    Ok(ServiceResponse::new(http, res).map_into_right_body())
+                }
+            }
+        })
+    }
+}
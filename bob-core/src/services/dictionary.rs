@@ -0,0 +1,226 @@
+//! Shared Dictionary Compression Middleware
+//!
+//! Implements dictionary compressed zstd (`dcz`) responses from the
+//! Compression Dictionary Transport specification. Clients advertise
+//! dictionaries they already hold via `Available-Dictionary` and
+//! matching responses are compressed against that dictionary.
+
+use std::{rc::Rc, sync::Arc};
+
+use actix_web::{
+    Error, HttpResponse,
+    body::{self, BodySize, BoxBody, MessageBody},
+    dev::{Service, ServiceRequest, ServiceResponse, Transform},
+    http::header::{self, HeaderValue},
+};
+use base64::{Engine, prelude::BASE64_STANDARD};
+use sha2::{Digest, Sha256};
+use zstd::dict::EncoderDictionary;
+
+use super::LocalBoxFuture;
+
+/// Content-Encoding token for dictionary compressed zstd.
+const ENCODING: &str = "dcz";
+
+/// Magic header prefixed to dictionary compressed zstd streams.
+const DCZ_MAGIC: [u8; 8] = [0x5e, 0x2a, 0x4d, 0x18, 0x20, 0x00, 0x00, 0x00];
+
+/// Largest response body buffered for compression.
+const MAX_COMPRESS_SIZE: u64 = 8 * 1024 * 1024;
+
+/// Loaded compression dictionary.
+pub struct Dictionary {
+    /// Request path glob responses must match.
+    pub pattern: glob::Pattern,
+    /// `Use-As-Dictionary` match expression advertised to clients.
+    pub advertise: String,
+    /// Response content-type prefixes eligible for compression.
+    pub content_types: Vec<String>,
+    /// Path the raw dictionary is served from.
+    pub serve_at: Option<String>,
+    raw: Vec<u8>,
+    hash: [u8; 32],
+    encoded_hash: String,
+    encoder: EncoderDictionary<'static>,
+}
+
+impl Dictionary {
+    /// Build dictionary from raw bytes at the given compression level.
+    pub fn new(
+        raw: Vec<u8>,
+        level: i32,
+        pattern: glob::Pattern,
+        advertise: String,
+        content_types: Vec<String>,
+        serve_at: Option<String>,
+    ) -> Self {
+        let hash: [u8; 32] = Sha256::digest(&raw).into();
+        let encoded_hash = format!(":{}:", BASE64_STANDARD.encode(hash));
+        let encoder = EncoderDictionary::copy(&raw, level);
+        Self {
+            pattern,
+            advertise,
+            content_types,
+            serve_at,
+            raw,
+            hash,
+            encoded_hash,
+            encoder,
+        }
+    }
+
+    /// Check if dictionary applies to the response content-type.
+    fn applies_to(&self, content_type: &str) -> bool {
+        self.content_types.is_empty()
+            || self
+                .content_types
+                .iter()
+                .any(|ctype| content_type.starts_with(ctype.as_str()))
+    }
+
+    /// Compress body using the dictionary in `dcz` format.
+    fn compress(&self, data: &[u8]) -> std::io::Result<Vec<u8>> {
+        let mut compressor = zstd::bulk::Compressor::with_prepared_dictionary(&self.encoder)?;
+        let compressed = compressor.compress(data)?;
+        let mut out = Vec::with_capacity(DCZ_MAGIC.len() + self.hash.len() + compressed.len());
+        out.extend_from_slice(&DCZ_MAGIC);
+        out.extend_from_slice(&self.hash);
+        out.extend_from_slice(&compressed);
+        Ok(out)
+    }
+
+    /// Build response serving the raw dictionary.
+    fn serve(&self) -> HttpResponse {
+        HttpResponse::Ok()
+            .content_type("application/octet-stream")
+            .insert_header(("Use-As-Dictionary", format!("match={:?}", self.advertise)))
+            .insert_header((header::CACHE_CONTROL, "public, max-age=86400"))
+            .body(self.raw.clone())
+    }
+}
+
+/// Dictionary compression middleware.
+#[derive(Clone)]
+pub struct DictionaryCompress(Rc<Vec<Arc<Dictionary>>>);
+
+impl DictionaryCompress {
+    /// Create new compression middleware from loaded dictionaries.
+    pub fn new(dictionaries: Vec<Arc<Dictionary>>) -> Self {
+        Self(Rc::new(dictionaries))
+    }
+}
+
+impl<S, B> Transform<S, ServiceRequest> for DictionaryCompress
+where
+    S: Service<ServiceRequest, Response = ServiceResponse<B>, Error = Error> + 'static,
+    B: MessageBody + 'static,
+{
+    type Response = ServiceResponse<BoxBody>;
+    type Error = Error;
+    type Transform = DictionaryCompressService<S>;
+    type InitError = ();
+    type Future = std::future::Ready<Result<Self::Transform, Self::InitError>>;
+
+    fn new_transform(&self, service: S) -> Self::Future {
+        std::future::ready(Ok(DictionaryCompressService {
+            service: Rc::new(service),
+            dictionaries: Rc::clone(&self.0),
+        }))
+    }
+}
+
+/// Service produced by [`DictionaryCompress`] middleware.
+pub struct DictionaryCompressService<S> {
+    service: Rc<S>,
+    dictionaries: Rc<Vec<Arc<Dictionary>>>,
+}
+
+impl<S> DictionaryCompressService<S> {
+    /// Find dictionary advertised by the client for the request.
+    fn negotiate(&self, req: &ServiceRequest) -> Option<Arc<Dictionary>> {
+        let accepts = req
+            .headers()
+            .get(header::ACCEPT_ENCODING)
+            .and_then(|v| v.to_str().ok())
+            .is_some_and(|v| {
+                v.split(',')
+                    .any(|e| e.split(';').next().unwrap_or_default().trim() == ENCODING)
+            });
+        if !accepts {
+            return None;
+        }
+        let available = req.headers().get("Available-Dictionary")?.to_str().ok()?;
+        self.dictionaries
+            .iter()
+            .find(|d| d.encoded_hash == available.trim() && d.pattern.matches(req.path()))
+            .cloned()
+    }
+}
+
+impl<S, B> Service<ServiceRequest> for DictionaryCompressService<S>
+where
+    S: Service<ServiceRequest, Response = ServiceResponse<B>, Error = Error> + 'static,
+    B: MessageBody + 'static,
+{
+    type Response = ServiceResponse<BoxBody>;
+    type Error = Error;
+    type Future = LocalBoxFuture<Result<Self::Response, Self::Error>>;
+
+    actix_web::dev::forward_ready!(service);
+
+    fn call(&self, req: ServiceRequest) -> Self::Future {
+        let serve = self
+            .dictionaries
+            .iter()
+            .find(|d| d.serve_at.as_deref() == Some(req.path()))
+            .cloned();
+        if let Some(dictionary) = serve {
+            let res = dictionary.serve();
+            return Box::pin(async move { Ok(req.into_response(res)) });
+        }
+
+        let dictionary = self.negotiate(&req);
+        let service = Rc::clone(&self.service);
+        Box::pin(async move {
+            let res = service.call(req).await?;
+            let Some(dictionary) = dictionary else {
+                return Ok(res.map_into_boxed_body());
+            };
+            let ctype = res
+                .headers()
+                .get(header::CONTENT_TYPE)
+                .and_then(|v| v.to_str().ok())
+                .unwrap_or_default();
+            let eligible = !res.headers().contains_key(header::CONTENT_ENCODING)
+                && dictionary.applies_to(ctype)
+                && matches!(res.response().body().size(), BodySize::Sized(n) if n <= MAX_COMPRESS_SIZE);
+            if !eligible {
+                return Ok(res.map_into_boxed_body());
+            }
+
+            let (req, res) = res.into_parts();
+            let (mut head, body) = res.into_parts();
+            let data = body::to_bytes(body).await.map_err(|err| {
+                let err: Box<dyn std::error::Error> = err.into();
+                actix_web::error::ErrorInternalServerError(err.to_string())
+            })?;
+            let res = match dictionary.compress(&data) {
+                Ok(compressed) => {
+                    let headers = head.headers_mut();
+                    headers.insert(header::CONTENT_ENCODING, HeaderValue::from_static(ENCODING));
+                    headers.append(
+                        header::VARY,
+                        HeaderValue::from_static("accept-encoding, available-dictionary"),
+                    );
+                    headers.remove(header::CONTENT_LENGTH);
+                    head.set_body(compressed).map_into_boxed_body()
+                }
+                Err(err) => {
+                    log::error!("dictionary compression failed: {err}");
+                    head.set_body(data).map_into_boxed_body()
+                }
+            };
+            Ok(ServiceResponse::new(req, res))
+        })
+    }
+}
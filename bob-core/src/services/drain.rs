@@ -0,0 +1,177 @@
+//! Connection Draining Middleware
+//!
+//! While drain mode is enabled, responses ask clients to close
+//! their keep-alive connections so load balancers can move traffic
+//! away from the instance without dropping in-flight requests.
+//! Before exiting, connections accepted once the drain began are
+//! refused with `503 Service Unavailable`.
+//!
+//! Individual listeners can also be paused by label or address: the
+//! server is rebuilt without them so their sockets stop accepting,
+//! while existing connections finish on the previous server.
+
+use std::{
+    collections::BTreeSet,
+    rc::Rc,
+    sync::{
+        Mutex, OnceLock,
+        atomic::{AtomicBool, Ordering},
+    },
+    time::Instant,
+};
+
+use actix_web::{
+    Error, HttpResponse,
+    body::{EitherBody, MessageBody},
+    dev::{Service, ServiceRequest, ServiceResponse, Transform},
+    http::{ConnectionType, header},
+};
+
+use super::LocalBoxFuture;
+use crate::config::ListenCfg;
+
+static DRAINING: AtomicBool = AtomicBool::new(false);
+static REFUSING: OnceLock<Instant> = OnceLock::new();
+
+/// Time a connection was accepted.
+///
+/// Inserted into connection data when connections are accepted.
+#[derive(Clone, Copy, Debug)]
+pub struct ConnectedAt(pub Instant);
+
+fn paused() -> &'static Mutex<BTreeSet<String>> {
+    static PAUSED: OnceLock<Mutex<BTreeSet<String>>> = OnceLock::new();
+    PAUSED.get_or_init(Default::default)
+}
+
+/// Check if drain mode is currently enabled.
+#[inline]
+pub fn draining() -> bool {
+    DRAINING.load(Ordering::Relaxed)
+}
+
+/// Enable or disable drain mode for the whole process.
+#[inline]
+pub fn set_draining(enable: bool) {
+    DRAINING.store(enable, Ordering::Relaxed);
+}
+
+/// Refuse requests on connections accepted from now on.
+///
+/// Used while draining before exit and cannot be undone.
+#[inline]
+pub fn refuse_new_connections() {
+    let _ = REFUSING.set(Instant::now());
+}
+
+/// Readiness probe response failing while draining.
+pub fn readiness() -> HttpResponse {
+    match draining() {
+        true => HttpResponse::ServiceUnavailable()
+            .content_type("text/plain")
+            .body("draining\n"),
+        false => HttpResponse::Ok()
+            .content_type("text/plain")
+            .body("ready\n"),
+    }
+}
+
+/// Pause the listeners with the label or `host:port` address.
+///
+/// Takes effect once the server is rebuilt. Returns false if the
+/// listeners were already paused.
+pub fn pause(name: &str) -> bool {
+    let mut paused = paused().lock().expect("paused listeners poisoned");
+    paused.insert(name.to_owned())
+}
+
+/// Resume the listeners with the label or `host:port` address.
+///
+/// Takes effect once the server is rebuilt. Returns false if the
+/// listeners were not paused.
+pub fn resume(name: &str) -> bool {
+    let mut paused = paused().lock().expect("paused listeners poisoned");
+    paused.remove(name)
+}
+
+/// Check if the listener is paused by its label or address.
+pub fn is_paused(listen: &ListenCfg) -> bool {
+    let paused = paused().lock().expect("paused listeners poisoned");
+    listen
+        .label
+        .as_ref()
+        .is_some_and(|label| paused.contains(label))
+        || paused.contains(&listen.bind_address())
+}
+
+/// Labels and addresses of the paused listeners.
+pub fn paused_listeners() -> BTreeSet<String> {
+    paused().lock().expect("paused listeners poisoned").clone()
+}
+
+/// Replace the paused listeners, such as after failing to rebuild the server.
+pub fn set_paused(names: BTreeSet<String>) {
+    *paused().lock().expect("paused listeners poisoned") = names;
+}
+
+/// Connection draining middleware.
+#[derive(Clone, Debug, Default)]
+pub struct Drain;
+
+impl<S, B> Transform<S, ServiceRequest> for Drain
+where
+    S: Service<ServiceRequest, Response = ServiceResponse<B>, Error = Error> + 'static,
+    B: MessageBody + 'static,
+{
+    type Response = ServiceResponse<EitherBody<B>>;
+    type Error = Error;
+    type Transform = DrainService<S>;
+    type InitError = ();
+    type Future = std::future::Ready<Result<Self::Transform, Self::InitError>>;
+
+    fn new_transform(&self, service: S) -> Self::Future {
+        std::future::ready(Ok(DrainService {
+            service: Rc::new(service),
+        }))
+    }
+}
+
+/// Service produced by [`Drain`] middleware.
+pub struct DrainService<S> {
+    service: Rc<S>,
+}
+
+impl<S, B> Service<ServiceRequest> for DrainService<S>
+where
+    S: Service<ServiceRequest, Response = ServiceResponse<B>, Error = Error> + 'static,
+    B: MessageBody + 'static,
+{
+    type Response = ServiceResponse<EitherBody<B>>;
+    type Error = Error;
+    type Future = LocalBoxFuture<Result<Self::Response, Self::Error>>;
+
+    actix_web::dev::forward_ready!(service);
+
+    fn call(&self, req: ServiceRequest) -> Self::Future {
+        if let Some(since) = REFUSING.get() {
+            let connected = req.conn_data::<ConnectedAt>().map(|c| c.0);
+            if connected.is_none_or(|at| at >= *since) {
+                let mut res = HttpResponse::ServiceUnavailable()
+                    .insert_header((header::RETRY_AFTER, "30"))
+                    .finish();
+                res.head_mut().set_connection_type(ConnectionType::Close);
+                return Box::pin(async move { Ok(req.into_response(res).map_into_right_body()) });
+            }
+        }
+
+        let service = Rc::clone(&self.service);
+        Box::pin(async move {
+            let mut res = service.call(req).await?;
+            if draining() {
+                let head = res.response_mut().head_mut();
+                head.set_connection_type(ConnectionType::Close);
+            }
+            Ok(res.map_into_left_body())
+        })
+    }
+}
@@ -0,0 +1,207 @@
+//! Dynamic Upstream Resolution Service
+//!
+//! Invokes an external program or unix-socket service for every request
+//! to compute either the upstream to proxy the request towards or the
+//! complete response to return. Communication uses CGI conventions:
+//! request metadata is passed as CGI environment variables and the
+//! reply is parsed as a CGI response.
+//!
+//! A reply including an `Upstream` header is proxied to the given
+//! base URL with the original path and query appended. Any additional
+//! reply headers are attached to the upstream request. Replies without
+//! an `Upstream` header are returned to the client directly.
+//!
+//! Hop-by-hop headers are removed from resolver replies and from
+//! upstream responses before they are forwarded.
+
+use std::{
+    io::{self, Read, Write},
+    os::unix::net::UnixStream,
+    path::PathBuf,
+    time::{Duration, Instant},
+};
+
+use actix_web::{
+    Error, HttpRequest, HttpResponse,
+    error::{ErrorBadGateway, ErrorGatewayTimeout, ErrorInternalServerError},
+    http::header::{HeaderMap, HeaderName, HeaderValue},
+    web,
+};
+
+use super::cgi::{CgiResponse, DEFAULT_MAX_OUTPUT, Program, request_env};
+use super::hop_by_hop;
+
+/// Reply header declaring the upstream to proxy towards.
+pub const UPSTREAM_HEADER: &str = "upstream";
+
+/// Resolution target invoked for every request.
+#[derive(Clone, Debug)]
+pub enum Resolver {
+    /// Program spawned per request.
+    Command(Program),
+    /// Unix-socket service connected to per request.
+    Socket { path: PathBuf, timeout: Duration },
+}
+
+impl Resolver {
+    /// Query resolver using the specified request environment.
+    ///
+    /// This call blocks and should only be run within [`actix_web::web::block`].
+    fn query(&self, env: Vec<(String, String)>) -> io::Result<Vec<u8>> {
+        match self {
+            Self::Command(program) => program.run(env, None),
+            Self::Socket { path, timeout } => {
+                let deadline = Instant::now() + *timeout;
+                let mut stream = UnixStream::connect(path)?;
+                stream.set_write_timeout(Some(remaining(deadline)?))?;
+                let mut message = env
+                    .into_iter()
+                    .map(|(k, v)| format!("{k}={}\n", v.replace('\n', " ")))
+                    .collect::<String>();
+                message.push('\n');
+                stream.write_all(message.as_bytes())?;
+                stream.shutdown(std::net::Shutdown::Write)?;
+
+                // per-read timeouts are shortened so the reply as a whole
+                // must complete before the deadline
+                let mut buf = Vec::new();
+                let mut chunk = [0u8; 8192];
+                let mut reader = (&stream).take(DEFAULT_MAX_OUTPUT as u64 + 1);
+                loop {
+                    stream.set_read_timeout(Some(remaining(deadline)?))?;
+                    match reader.read(&mut chunk) {
+                        Ok(0) => break,
+                        Ok(n) => buf.extend_from_slice(&chunk[..n]),
+                        Err(err) if err.kind() == io::ErrorKind::Interrupted => continue,
+                        Err(err) => return Err(err),
+                    }
+                }
+                if buf.len() > DEFAULT_MAX_OUTPUT {
+                    return Err(io::Error::other(format!(
+                        "resolver reply exceeded {DEFAULT_MAX_OUTPUT} bytes"
+                    )));
+                }
+                Ok(buf)
+            }
+        }
+    }
+}
+
+/// Time left until the deadline or [`io::ErrorKind::TimedOut`] once passed.
+fn remaining(deadline: Instant) -> io::Result<Duration> {
+    deadline
+        .checked_duration_since(Instant::now())
+        .filter(|left| !left.is_zero())
+        .ok_or_else(|| io::Error::new(io::ErrorKind::TimedOut, "resolver timed out"))
+}
+
+/// Remove hop-by-hop headers from resolver reply headers.
+fn strip_hop_by_hop(headers: &mut Vec<(String, String)>) {
+    let mut map = HeaderMap::new();
+    for (name, value) in headers.iter() {
+        if let (Ok(name), Ok(value)) = (
+            HeaderName::try_from(name.as_str()),
+            HeaderValue::try_from(value.as_str()),
+        ) {
+            map.append(name, value);
+        }
+    }
+    hop_by_hop::remove(&mut map);
+    headers.retain(|(name, _)| map.contains_key(name.as_str()));
+}
+
+/// Dynamic upstream resolution service.
+#[derive(Clone)]
+pub struct Dynamic {
+    resolver: Resolver,
+    client: awc::Client,
+}
+
+impl Dynamic {
+    /// Create new dynamic service with resolver and upstream client.
+    pub fn new(resolver: Resolver, client: awc::Client) -> Self {
+        Self { resolver, client }
+    }
+
+    /// Resolve and complete the request.
+    pub async fn handle(
+        &self,
+        req: HttpRequest,
+        payload: web::Payload,
+    ) -> Result<HttpResponse, Error> {
+        let env = request_env(&req);
+        let resolver = self.resolver.clone();
+        let output = web::block(move || resolver.query(env))
+            .await?
+            .map_err(|err| match err.kind() {
+                io::ErrorKind::TimedOut | io::ErrorKind::WouldBlock => {
+                    log::error!("dynamic resolver timed out");
+                    ErrorGatewayTimeout("resolver timed out")
+                }
+                _ => {
+                    log::error!("dynamic resolver failed: {err:?}");
+                    ErrorInternalServerError(err)
+                }
+            })?;
+        let mut response = CgiResponse::parse(&output).map_err(|err| {
+            log::error!("dynamic resolver returned invalid response: {err:?}");
+            ErrorInternalServerError(err)
+        })?;
+        strip_hop_by_hop(&mut response.headers);
+
+        match response.header(UPSTREAM_HEADER).map(|u| u.to_owned()) {
+            Some(upstream) => self.proxy(req, payload, &upstream, response).await,
+            None => Ok(response.into_response()),
+        }
+    }
+
+    /// Proxy request to the resolved upstream.
+    async fn proxy(
+        &self,
+        req: HttpRequest,
+        payload: web::Payload,
+        upstream: &str,
+        response: CgiResponse,
+    ) -> Result<HttpResponse, Error> {
+        let path = req
+            .uri()
+            .path_and_query()
+            .map(|p| p.as_str())
+            .unwrap_or("/");
+        let url = format!("{}{path}", upstream.trim_end_matches('/'));
+        log::debug!("dynamic upstream resolved to {url:?}");
+
+        let mut request = self
+            .client
+            .request_from(url.as_str(), req.head())
+            .no_decompress();
+        if let Some(peer) = req.peer_addr() {
+            request = request.insert_header(("x-forwarded-for", peer.ip().to_string()));
+        }
+        request = response
+            .headers
+            .into_iter()
+            .filter(|(h, _)| !h.eq_ignore_ascii_case(UPSTREAM_HEADER))
+            .fold(request, |r, (h, v)| r.insert_header((h, v)));
+
+        let upstream = request.send_stream(payload).await.map_err(|err| {
+            log::error!("dynamic upstream {url:?} failed: {err:?}");
+            ErrorBadGateway(err)
+        })?;
+        let mut headers = upstream.headers().clone();
+        hop_by_hop::remove(&mut headers);
+        let mut builder = HttpResponse::build(upstream.status());
+        headers
+            .into_iter()
+            .fold(&mut builder, |b, (h, v)| b.append_header((h, v)));
+        Ok(builder.streaming(upstream))
+    }
+}
+
+impl std::fmt::Debug for Dynamic {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("Dynamic")
+            .field("resolver", &self.resolver)
+            .finish()
+    }
+}
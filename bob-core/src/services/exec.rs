@@ -0,0 +1,168 @@
+//! External Command Hook Middleware
+//!
+//! Runs a configured program for every request with the standard CGI/1.1
+//! environment describing it. A successful exit allows the request and any
+//! `Name: Value` header lines written to stdout are added to the request
+//! before it is passed on. Any other exit status denies the request, with
+//! the output parsed as a CGI response answering it (`403 Forbidden`
+//! unless a `Status` or `Location` is given).
+
+use std::{io, rc::Rc};
+
+use actix_web::{
+    Error, HttpResponse,
+    body::{EitherBody, MessageBody},
+    dev::{Service, ServiceRequest, ServiceResponse, Transform},
+    http::{
+        StatusCode,
+        header::{HeaderName, HeaderValue},
+    },
+    web,
+};
+
+use super::{
+    LocalBoxFuture,
+    cgi::{Active, CgiResponse, Program, request_env},
+};
+
+/// Exec hook middleware settings.
+pub struct Settings {
+    /// Program run for every request.
+    pub program: Program,
+    /// Additional environment variables passed to the program.
+    pub env: Vec<(String, String)>,
+    /// Maximum number of programs running at once.
+    pub max_concurrent: usize,
+    /// Pass requests on when the program cannot be run.
+    pub fail_open: bool,
+}
+
+/// Exec hook middleware.
+#[derive(Clone)]
+pub struct Exec {
+    settings: Rc<Settings>,
+    active: Active,
+}
+
+impl Exec {
+    /// Create new exec hook middleware from settings and shared running count.
+    pub fn new(settings: Settings, active: Active) -> Self {
+        Self {
+            settings: Rc::new(settings),
+            active,
+        }
+    }
+}
+
+impl<S, B> Transform<S, ServiceRequest> for Exec
+where
+    S: Service<ServiceRequest, Response = ServiceResponse<B>, Error = Error> + 'static,
+    B: MessageBody + 'static,
+{
+    type Response = ServiceResponse<EitherBody<B>>;
+    type Error = Error;
+    type Transform = ExecService<S>;
+    type InitError = ();
+    type Future = std::future::Ready<Result<Self::Transform, Self::InitError>>;
+
+    fn new_transform(&self, service: S) -> Self::Future {
+        std::future::ready(Ok(ExecService {
+            service: Rc::new(service),
+            settings: Rc::clone(&self.settings),
+            active: self.active.clone(),
+        }))
+    }
+}
+
+/// Service produced by [`Exec`] middleware.
+pub struct ExecService<S> {
+    service: Rc<S>,
+    settings: Rc<Settings>,
+    active: Active,
+}
+
+/// Response used when the program could not be run.
+fn failure(err: &io::Error) -> HttpResponse {
+    match err.kind() {
+        io::ErrorKind::TimedOut => HttpResponse::GatewayTimeout().finish(),
+        io::ErrorKind::WouldBlock => HttpResponse::ServiceUnavailable().finish(),
+        _ => HttpResponse::InternalServerError().finish(),
+    }
+}
+
+impl<S, B> Service<ServiceRequest> for ExecService<S>
+where
+    S: Service<ServiceRequest, Response = ServiceResponse<B>, Error = Error> + 'static,
+    B: MessageBody + 'static,
+{
+    type Response = ServiceResponse<EitherBody<B>>;
+    type Error = Error;
+    type Future = LocalBoxFuture<Result<Self::Response, Self::Error>>;
+
+    actix_web::dev::forward_ready!(service);
+
+    fn call(&self, mut req: ServiceRequest) -> Self::Future {
+        let service = Rc::clone(&self.service);
+        let settings = Rc::clone(&self.settings);
+        let active = self.active.clone();
+        Box::pin(async move {
+            let mut env = request_env(req.request());
+            env.extend(settings.env.iter().cloned());
+
+            let outcome = match active.acquire(settings.max_concurrent) {
+                Some(slot) => {
+                    let program = settings.program.clone();
+                    web::block(move || {
+                        let _slot = slot;
+                        program.execute(env, None)
+                    })
+                    .await
+                    .unwrap_or_else(|err| Err(io::Error::other(err)))
+                }
+                None => Err(io::Error::new(
+                    io::ErrorKind::WouldBlock,
+                    "too many running hooks",
+                )),
+            };
+            let outcome =
+                outcome.and_then(|(status, output)| Ok((status, CgiResponse::parse(&output)?)));
+
+            let (status, mut response) = match outcome {
+                Ok(outcome) => outcome,
+                Err(err) => {
+                    log::error!("exec hook {:?} failed: {err}", settings.program.command);
+                    if settings.fail_open {
+                        return Ok(service.call(req).await?.map_into_left_body());
+                    }
+                    let res = failure(&err);
+                    return Ok(req.into_response(res).map_into_right_body());
+                }
+            };
+
+            if !status.success() {
+                log::debug!(
+                    "exec hook denied {} {} ({status})",
+                    req.method(),
+                    req.path()
+                );
+                if response.status.is_none() && response.header("location").is_none() {
+                    response.status = Some(StatusCode::FORBIDDEN);
+                }
+                let res = response.into_response();
+                return Ok(req.into_response(res).map_into_right_body());
+            }
+            for (name, value) in response.headers {
+                match (
+                    HeaderName::from_bytes(name.as_bytes()),
+                    HeaderValue::from_str(&value),
+                ) {
+                    (Ok(name), Ok(value)) => {
+                        req.headers_mut().insert(name, value);
+                    }
+                    _ => log::warn!("exec hook returned invalid header {name:?}: {value:?}"),
+                }
+            }
+            Ok(service.call(req).await?.map_into_left_body())
+        })
+    }
+}
@@ -0,0 +1,245 @@
+//! Request-Scoped Debug Flags
+//!
+//! Verifies debug tokens supplied by a header or cookie and exposes
+//! the enabled flags to directive guards through [`RequestFlags`],
+//! allowing individual requests to be routed differently, skip toggled
+//! middleware or be traced verbosely without changing the configuration
+//! for everyone.
+//!
+//! Tokens take the form `<flag>+<flag>.<expires>.<signature>` where
+//! `expires` is a unix timestamp and `signature` the hex encoded
+//! HMAC-SHA256 of everything before it using the shared secret.
+
+use std::{
+    rc::Rc,
+    time::{Duration, SystemTime, UNIX_EPOCH},
+};
+
+use actix_web::{
+    Error, HttpMessage,
+    body::MessageBody,
+    dev::{Service, ServiceRequest, ServiceResponse, Transform},
+    http::header::{self, HeaderMap, HeaderName, HeaderValue},
+};
+use bob_guard::RequestFlags;
+use hmac::{Hmac, Mac};
+use sha2::Sha256;
+
+use super::LocalBoxFuture;
+
+/// Flag enabling verbose request/response logging.
+pub const TRACE_FLAG: &str = "trace";
+
+/// Headers carrying credentials redacted from trace logs.
+const REDACTED: [HeaderName; 4] = [
+    header::AUTHORIZATION,
+    header::PROXY_AUTHORIZATION,
+    header::COOKIE,
+    header::SET_COOKIE,
+];
+
+type HmacSha256 = Hmac<Sha256>;
+
+/// Seconds since the unix epoch.
+#[inline]
+fn now() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|d| d.as_secs())
+        .unwrap_or_default()
+}
+
+/// Produce hex encoded signature for the token payload.
+fn signature(secret: &str, payload: &str) -> HmacSha256 {
+    let mut mac = HmacSha256::new_from_slice(secret.as_bytes()).expect("hmac accepts any key");
+    mac.update(payload.as_bytes());
+    mac
+}
+
+/// Generate signed token enabling flags for the given duration.
+pub fn sign(secret: &str, flags: &[String], ttl: Duration) -> String {
+    let payload = format!("{}.{}", flags.join("+"), now() + ttl.as_secs());
+    let digest = signature(secret, &payload).finalize().into_bytes();
+    let hex: String = digest.iter().map(|b| format!("{b:02x}")).collect();
+    format!("{payload}.{hex}")
+}
+
+/// Verify token signature and expiry returning enabled flags.
+pub fn verify(secret: &str, token: &str) -> Option<Vec<String>> {
+    let (payload, hex) = token.trim().rsplit_once('.')?;
+    let (flags, expires) = payload.rsplit_once('.')?;
+    if hex.len() % 2 != 0 {
+        return None;
+    }
+    let digest = (0..hex.len())
+        .step_by(2)
+        .map(|i| u8::from_str_radix(hex.get(i..i + 2)?, 16).ok())
+        .collect::<Option<Vec<u8>>>()?;
+    signature(secret, payload).verify_slice(&digest).ok()?;
+    if expires.parse::<u64>().ok()? < now() {
+        return None;
+    }
+    Some(
+        flags
+            .split('+')
+            .filter(|f| !f.is_empty())
+            .map(|f| f.to_owned())
+            .collect(),
+    )
+}
+
+/// Header name and value pairs with credentials redacted for logging.
+fn redacted(headers: &HeaderMap) -> Vec<(&str, &str)> {
+    headers
+        .iter()
+        .map(|(name, value)| match REDACTED.contains(name) {
+            true => (name.as_str(), "[redacted]"),
+            false => (name.as_str(), value.to_str().unwrap_or("[binary]")),
+        })
+        .collect()
+}
+
+/// Remove the named cookie from every request `Cookie` header.
+fn strip_cookie(headers: &mut HeaderMap, name: &str) {
+    let values: Vec<HeaderValue> = headers
+        .get_all(header::COOKIE)
+        .filter_map(|value| {
+            let Ok(cookies) = value.to_str() else {
+                return Some(value.clone());
+            };
+            let kept: Vec<&str> = cookies
+                .split(';')
+                .map(str::trim)
+                .filter(|c| !c.is_empty())
+                .filter(|c| c.split_once('=').is_none_or(|(n, _)| n.trim() != name))
+                .collect();
+            match kept.is_empty() {
+                true => None,
+                false => HeaderValue::from_str(&kept.join("; ")).ok(),
+            }
+        })
+        .collect();
+    headers.remove(header::COOKIE);
+    for value in values {
+        headers.append(header::COOKIE, value);
+    }
+}
+
+/// Debug flag settings.
+#[derive(Clone, Debug)]
+pub struct Settings {
+    /// Shared secret used to sign tokens.
+    pub secret: String,
+    /// Header carrying the debug token.
+    pub header: HeaderName,
+    /// Cookie carrying the debug token.
+    pub cookie: String,
+    /// Flags tokens are permitted to enable when non-empty.
+    pub allowed: Vec<String>,
+}
+
+impl Settings {
+    /// Retrieve and verify debug token from the request.
+    fn flags(&self, req: &ServiceRequest) -> Option<RequestFlags> {
+        let token = match req.headers().get(&self.header) {
+            Some(value) => value.to_str().ok()?.to_owned(),
+            None => req.cookie(&self.cookie)?.value().to_owned(),
+        };
+        let Some(flags) = verify(&self.secret, &token) else {
+            log::debug!("rejected invalid or expired debug token");
+            return None;
+        };
+        let flags = flags
+            .into_iter()
+            .filter(|f| self.allowed.is_empty() || self.allowed.contains(f))
+            .collect();
+        Some(RequestFlags(flags))
+    }
+}
+
+/// Debug flag verification middleware.
+#[derive(Clone)]
+pub struct DebugFlags(Rc<Settings>);
+
+impl DebugFlags {
+    /// Create new debug flag middleware from settings.
+    pub fn new(settings: Settings) -> Self {
+        Self(Rc::new(settings))
+    }
+}
+
+impl<S, B> Transform<S, ServiceRequest> for DebugFlags
+where
+    S: Service<ServiceRequest, Response = ServiceResponse<B>, Error = Error> + 'static,
+    B: MessageBody + 'static,
+{
+    type Response = ServiceResponse<B>;
+    type Error = Error;
+    type Transform = DebugFlagsService<S>;
+    type InitError = ();
+    type Future = std::future::Ready<Result<Self::Transform, Self::InitError>>;
+
+    fn new_transform(&self, service: S) -> Self::Future {
+        std::future::ready(Ok(DebugFlagsService {
+            service: Rc::new(service),
+            settings: Rc::clone(&self.0),
+        }))
+    }
+}
+
+/// Service produced by [`DebugFlags`] middleware.
+pub struct DebugFlagsService<S> {
+    service: Rc<S>,
+    settings: Rc<Settings>,
+}
+
+impl<S, B> Service<ServiceRequest> for DebugFlagsService<S>
+where
+    S: Service<ServiceRequest, Response = ServiceResponse<B>, Error = Error> + 'static,
+    B: MessageBody + 'static,
+{
+    type Response = ServiceResponse<B>;
+    type Error = Error;
+    type Future = LocalBoxFuture<Result<Self::Response, Self::Error>>;
+
+    actix_web::dev::forward_ready!(service);
+
+    fn call(&self, mut req: ServiceRequest) -> Self::Future {
+        let flags = self.settings.flags(&req);
+        // debug tokens are never forwarded to upstreams
+        req.headers_mut().remove(&self.settings.header);
+        strip_cookie(req.headers_mut(), &self.settings.cookie);
+
+        let trace = flags.as_ref().is_some_and(|f| f.contains(TRACE_FLAG));
+        if let Some(flags) = flags {
+            req.extensions_mut().insert(flags);
+        }
+        if trace {
+            log::info!(
+                target: "bob::trace",
+                "{:?} {} {} {:?} headers={:?}",
+                req.peer_addr(),
+                req.method(),
+                req.uri(),
+                req.version(),
+                redacted(req.headers())
+            );
+        }
+
+        let service = Rc::clone(&self.service);
+        Box::pin(async move {
+            let res = service.call(req).await?;
+            if trace {
+                log::info!(
+                    target: "bob::trace",
+                    "{} {} -> {} headers={:?}",
+                    res.request().method(),
+                    res.request().uri(),
+                    res.status(),
+                    redacted(res.headers())
+                );
+            }
+            Ok(res)
+        })
+    }
+}
@@ -0,0 +1,269 @@
+//! Forward Proxy Service
+//!
+//! Proxies requests for arbitrary destinations on behalf of clients.
+//! `CONNECT` requests open a raw tcp tunnel to the requested authority
+//! while requests using an absolute URI are forwarded with the http
+//! client, with hop-by-hop headers removed in both directions.
+//! Destinations must match the configured host and port allowlists and
+//! clients may be required to authenticate using `Proxy-Authorization`
+//! basic credentials. Destination names are resolved
+//! before connecting and private addresses are refused unless allowed, with
+//! the connection made to the exact address that was checked.
+
+use std::{
+    collections::HashMap,
+    future::poll_fn,
+    net::SocketAddr,
+    pin::Pin,
+    rc::Rc,
+    task::{Context, Poll},
+    time::Duration,
+};
+
+use actix_http::ConnectionType;
+use actix_web::{
+    Error, HttpRequest, HttpResponse,
+    error::{ErrorBadGateway, ErrorGatewayTimeout},
+    http::{Method, header},
+    rt::net::TcpStream,
+    web::{self, Bytes},
+};
+use futures_core::Stream;
+use tokio::{
+    io::{AsyncRead, AsyncWriteExt, ReadBuf},
+    net::tcp::{OwnedReadHalf, OwnedWriteHalf},
+};
+
+use super::{hop_by_hop, is_private, passwd};
+use crate::config::DomainMatch;
+
+/// Size of chunks read from tunneled connections.
+const CHUNK_SIZE: usize = 16 * 1024;
+
+/// Forward proxy settings.
+#[derive(Clone, Debug)]
+pub struct Settings {
+    /// Usernames and htpasswd compatible hashes of their passwords.
+    ///
+    /// Authentication is disabled when empty.
+    pub credentials: HashMap<String, String>,
+    /// Destination hosts clients may connect to.
+    pub hosts: Vec<DomainMatch>,
+    /// Destination ports clients may connect to.
+    pub ports: Vec<u16>,
+    /// Allow destinations resolving to private, loopback or link-local addresses.
+    pub allow_private: bool,
+    /// Time allowed to connect to tunnel destinations.
+    pub connect_timeout: Duration,
+}
+
+impl Settings {
+    /// Check if the request carries valid proxy credentials.
+    async fn authorized(&self, req: &HttpRequest) -> bool {
+        if self.credentials.is_empty() {
+            return true;
+        }
+        let Some((user, password)) = req
+            .headers()
+            .get(header::PROXY_AUTHORIZATION)
+            .and_then(passwd::basic)
+        else {
+            return false;
+        };
+        match self.credentials.get(&user) {
+            Some(hash) => passwd::verify(password, hash.clone()).await,
+            None => false,
+        }
+    }
+
+    /// Check if the destination is allowed.
+    fn allowed(&self, host: &str, port: u16) -> bool {
+        let host = host.trim_matches(['[', ']']);
+        self.ports.contains(&port) && self.hosts.iter().any(|m| m.matches(host))
+    }
+
+    /// Resolve the destination to the first permitted address.
+    ///
+    /// Connecting to the returned address rather than the name prevents
+    /// a second lookup from rebinding the destination to another address.
+    async fn resolve(&self, host: &str, port: u16) -> Result<SocketAddr, HttpResponse> {
+        let host = host.trim_matches(['[', ']']);
+        let lookup = tokio::net::lookup_host((host, port));
+        let addrs = match actix_web::rt::time::timeout(self.connect_timeout, lookup).await {
+            Ok(Ok(addrs)) => addrs.collect::<Vec<_>>(),
+            Ok(Err(err)) => {
+                log::debug!("forward proxy failed to resolve {host}: {err}");
+                return Err(HttpResponse::BadGateway().body("destination unresolvable"));
+            }
+            Err(_) => return Err(HttpResponse::GatewayTimeout().body("destination timed out")),
+        };
+        let permitted = addrs
+            .iter()
+            .find(|addr| self.allow_private || !is_private(&addr.ip().to_canonical()));
+        match permitted {
+            Some(addr) => Ok(*addr),
+            None if addrs.is_empty() => {
+                Err(HttpResponse::BadGateway().body("destination unresolvable"))
+            }
+            None => {
+                log::warn!("forward proxy destination {host} resolves to a private address");
+                Err(HttpResponse::Forbidden().finish())
+            }
+        }
+    }
+}
+
+/// Response requesting proxy authentication.
+fn authenticate() -> HttpResponse {
+    HttpResponse::ProxyAuthenticationRequired()
+        .insert_header((header::PROXY_AUTHENTICATE, "Basic realm=\"bob\""))
+        .finish()
+}
+
+/// Copy the request payload into the tunnel until the client closes it.
+async fn upload(mut payload: web::Payload, mut write: OwnedWriteHalf) {
+    while let Some(chunk) = poll_fn(|cx| Pin::new(&mut payload).poll_next(cx)).await {
+        let chunk = match chunk {
+            Ok(chunk) => chunk,
+            Err(err) => {
+                log::debug!("forward proxy tunnel closed by client: {err}");
+                break;
+            }
+        };
+        if let Err(err) = write.write_all(&chunk).await {
+            log::debug!("forward proxy tunnel closed by destination: {err}");
+            return;
+        }
+    }
+    let _ = write.shutdown().await;
+}
+
+/// Destination half of a tunnel streamed back to the client.
+struct Tunnel(OwnedReadHalf);
+
+impl Stream for Tunnel {
+    type Item = Result<Bytes, std::io::Error>;
+
+    fn poll_next(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Self::Item>> {
+        let mut buf = vec![0; CHUNK_SIZE];
+        let mut read = ReadBuf::new(&mut buf);
+        match Pin::new(&mut self.0).poll_read(cx, &mut read) {
+            Poll::Pending => Poll::Pending,
+            Poll::Ready(Err(err)) => Poll::Ready(Some(Err(err))),
+            Poll::Ready(Ok(())) if read.filled().is_empty() => Poll::Ready(None),
+            Poll::Ready(Ok(())) => {
+                let n = read.filled().len();
+                buf.truncate(n);
+                Poll::Ready(Some(Ok(Bytes::from(buf))))
+            }
+        }
+    }
+}
+
+/// Forward proxy service.
+#[derive(Clone)]
+pub struct Forward {
+    settings: Rc<Settings>,
+    client: awc::Client,
+}
+
+impl Forward {
+    /// Create new forward proxy service with settings and http client.
+    pub fn new(settings: Settings, client: awc::Client) -> Self {
+        Self {
+            settings: Rc::new(settings),
+            client,
+        }
+    }
+
+    /// Proxy the request to its requested destination.
+    pub async fn handle(
+        &self,
+        req: HttpRequest,
+        payload: web::Payload,
+    ) -> Result<HttpResponse, Error> {
+        if !self.settings.authorized(&req).await {
+            log::debug!("forward proxy authentication failed");
+            return Ok(authenticate());
+        }
+        let uri = req.uri();
+        let Some(host) = uri.host() else {
+            return Ok(HttpResponse::BadRequest().body("absolute uri required"));
+        };
+        let port = uri.port_u16().unwrap_or(match uri.scheme_str() {
+            Some("https") => 443,
+            _ => 80,
+        });
+        if !self.settings.allowed(host, port) {
+            log::warn!("forward proxy destination {host}:{port} denied");
+            return Ok(HttpResponse::Forbidden().finish());
+        }
+        let addr = match self.settings.resolve(host, port).await {
+            Ok(addr) => addr,
+            Err(res) => return Ok(res),
+        };
+        match req.method() == Method::CONNECT {
+            true => self.tunnel(addr, payload).await,
+            false => self.forward(&req, addr, payload).await,
+        }
+    }
+
+    /// Open a tcp tunnel to the destination.
+    async fn tunnel(&self, addr: SocketAddr, payload: web::Payload) -> Result<HttpResponse, Error> {
+        let connect = TcpStream::connect(addr);
+        let stream = actix_web::rt::time::timeout(self.settings.connect_timeout, connect)
+            .await
+            .map_err(|_| {
+                log::error!("forward proxy connect to {addr} timed out");
+                ErrorGatewayTimeout("destination timed out")
+            })?
+            .map_err(|err| {
+                log::error!("forward proxy connect to {addr} failed: {err}");
+                ErrorBadGateway("destination unreachable")
+            })?;
+        log::debug!("forward proxy tunnel opened to {addr}");
+        let (read, write) = stream.into_split();
+        actix_web::rt::spawn(upload(payload, write));
+
+        // the tunnel is raw bytes rather than a chunked response body
+        let mut res = HttpResponse::Ok().streaming(Tunnel(read));
+        res.head_mut().set_connection_type(ConnectionType::Upgrade);
+        res.head_mut().no_chunking(true);
+        Ok(res)
+    }
+
+    /// Forward an absolute URI request with the http client.
+    async fn forward(
+        &self,
+        req: &HttpRequest,
+        addr: SocketAddr,
+        payload: web::Payload,
+    ) -> Result<HttpResponse, Error> {
+        let url = req.uri().to_string();
+        let mut request = self
+            .client
+            .request_from(url.as_str(), req.head())
+            .address(addr)
+            .no_decompress();
+        hop_by_hop::remove(request.headers_mut());
+        let upstream = request.send_stream(payload).await.map_err(|err| {
+            log::error!("forward proxy request to {url:?} failed: {err:?}");
+            ErrorBadGateway(err)
+        })?;
+        let mut headers = upstream.headers().clone();
+        hop_by_hop::remove(&mut headers);
+        let mut builder = HttpResponse::build(upstream.status());
+        headers
+            .into_iter()
+            .fold(&mut builder, |b, (h, v)| b.append_header((h, v)));
+        Ok(builder.streaming(upstream))
+    }
+}
+
+impl std::fmt::Debug for Forward {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("Forward")
+            .field("settings", &self.settings)
+            .finish()
+    }
+}
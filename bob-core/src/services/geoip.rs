@@ -0,0 +1,179 @@
+//! GeoIP Filtering Middleware
+//!
+//! Looks up the client address within a MaxMind/DB-IP database,
+//! rejects requests from denied countries or autonomous systems and
+//! exposes the result to upstreams, guards and the access log.
+
+use std::{net::IpAddr, rc::Rc, sync::Arc};
+
+use actix_web::{
+    Error, HttpResponse,
+    body::{EitherBody, MessageBody},
+    dev::{Service, ServiceRequest, ServiceResponse, Transform},
+    http::header::{HeaderName, HeaderValue},
+};
+use maxminddb::{Reader, geoip2};
+
+use super::LocalBoxFuture;
+
+/// Header containing the client ISO country code.
+pub const COUNTRY_HEADER: HeaderName = HeaderName::from_static("x-geo-country");
+
+/// Header containing the client autonomous system number.
+pub const ASN_HEADER: HeaderName = HeaderName::from_static("x-geo-asn");
+
+/// Shared in-memory GeoIP database.
+pub type Database = Arc<Reader<Vec<u8>>>;
+
+/// Client location attached to request extensions.
+#[derive(Clone, Debug, Default)]
+pub struct GeoInfo {
+    pub country: Option<String>,
+    pub asn: Option<u32>,
+}
+
+/// GeoIP filter settings.
+#[derive(Clone)]
+pub struct Settings {
+    /// Database containing country records.
+    pub country_db: Option<Database>,
+    /// Database containing autonomous system records.
+    pub asn_db: Option<Database>,
+    /// Country codes permitted when non-empty.
+    pub allow_countries: Vec<String>,
+    /// Country codes always rejected.
+    pub deny_countries: Vec<String>,
+    /// Autonomous system numbers permitted when non-empty.
+    pub allow_asn: Vec<u32>,
+    /// Autonomous system numbers always rejected.
+    pub deny_asn: Vec<u32>,
+    /// Reject clients whose location could not be determined.
+    pub deny_unknown: bool,
+    /// Inject location headers into the request for upstreams.
+    pub headers: bool,
+}
+
+impl Settings {
+    /// Lookup location information for the client address.
+    fn lookup(&self, ip: IpAddr) -> GeoInfo {
+        let country = self.country_db.as_ref().and_then(|db| {
+            db.lookup::<geoip2::Country>(ip)
+                .inspect_err(|err| log::debug!("geoip country lookup failed for {ip}: {err}"))
+                .ok()
+                .flatten()
+                .and_then(|c| c.country)
+                .and_then(|c| c.iso_code)
+                .map(|code| code.to_ascii_uppercase())
+        });
+        let asn = self.asn_db.as_ref().and_then(|db| {
+            db.lookup::<geoip2::Asn>(ip)
+                .inspect_err(|err| log::debug!("geoip asn lookup failed for {ip}: {err}"))
+                .ok()
+                .flatten()
+                .and_then(|a| a.autonomous_system_number)
+        });
+        GeoInfo { country, asn }
+    }
+
+    /// Check if client location is permitted by the filter.
+    ///
+    /// `deny_unknown` only applies to lookups with a configured database.
+    fn permits(&self, geo: &GeoInfo) -> bool {
+        let country = match geo.country.as_ref() {
+            Some(country) => {
+                !self.deny_countries.contains(country)
+                    && (self.allow_countries.is_empty() || self.allow_countries.contains(country))
+            }
+            None => {
+                self.country_db.is_none() || (!self.deny_unknown && self.allow_countries.is_empty())
+            }
+        };
+        let asn = match geo.asn {
+            Some(asn) => {
+                !self.deny_asn.contains(&asn)
+                    && (self.allow_asn.is_empty() || self.allow_asn.contains(&asn))
+            }
+            None => self.asn_db.is_none() || (!self.deny_unknown && self.allow_asn.is_empty()),
+        };
+        country && asn
+    }
+}
+
+/// GeoIP filter middleware.
+#[derive(Clone)]
+pub struct GeoIp(Rc<Settings>);
+
+impl GeoIp {
+    /// Create new geoip middleware from settings.
+    pub fn new(settings: Settings) -> Self {
+        Self(Rc::new(settings))
+    }
+}
+
+impl<S, B> Transform<S, ServiceRequest> for GeoIp
+where
+    S: Service<ServiceRequest, Response = ServiceResponse<B>, Error = Error> + 'static,
+    B: MessageBody + 'static,
+{
+    type Response = ServiceResponse<EitherBody<B>>;
+    type Error = Error;
+    type Transform = GeoIpService<S>;
+    type InitError = ();
+    type Future = std::future::Ready<Result<Self::Transform, Self::InitError>>;
+
+    fn new_transform(&self, service: S) -> Self::Future {
+        std::future::ready(Ok(GeoIpService {
+            service: Rc::new(service),
+            settings: Rc::clone(&self.0),
+        }))
+    }
+}
+
+/// Service produced by [`GeoIp`] middleware.
+pub struct GeoIpService<S> {
+    service: Rc<S>,
+    settings: Rc<Settings>,
+}
+
+impl<S, B> Service<ServiceRequest> for GeoIpService<S>
+where
+    S: Service<ServiceRequest, Response = ServiceResponse<B>, Error = Error> + 'static,
+    B: MessageBody + 'static,
+{
+    type Response = ServiceResponse<EitherBody<B>>;
+    type Error = Error;
+    type Future = LocalBoxFuture<Result<Self::Response, Self::Error>>;
+
+    actix_web::dev::forward_ready!(service);
+
+    fn call(&self, mut req: ServiceRequest) -> Self::Future {
+        let geo = req
+            .peer_addr()
+            .map(|addr| self.settings.lookup(addr.ip()))
+            .unwrap_or_default();
+        if !self.settings.permits(&geo) {
+            log::debug!("geoip rejected {:?} {geo:?}", req.peer_addr());
+            let res = HttpResponse::Forbidden().finish();
+            return Box::pin(async move { Ok(req.into_response(res).map_into_right_body()) });
+        }
+
+        if self.settings.headers {
+            // never trust location headers supplied by the client
+            let headers = req.headers_mut();
+            headers.remove(COUNTRY_HEADER);
+            headers.remove(ASN_HEADER);
+            if let Some(country) = geo.country.as_ref()
+                && let Ok(value) = HeaderValue::from_str(country)
+            {
+                headers.insert(COUNTRY_HEADER, value);
+            }
+            if let Some(asn) = geo.asn {
+                headers.insert(ASN_HEADER, HeaderValue::from(asn));
+            }
+        }
+        req.extensions_mut().insert(geo);
+
+        let service = Rc::clone(&self.service);
+        Box::pin(async move { Ok(service.call(req).await?.map_into_left_body()) })
+    }
+}
@@ -0,0 +1,279 @@
+//! Git Smart-HTTP Service
+//!
+//! Serves git repositories over the smart HTTP protocol by invoking
+//! `git http-backend` as a CGI program for every request. Access is
+//! checked per repository before the backend runs: anonymous clients may
+//! be allowed to clone while pushes always require `Authorization` basic
+//! credentials of a user granted write access. An optional authorization hook may further accept or
+//! reject requests using CGI conventions.
+
+use std::{collections::HashMap, io, path::PathBuf, sync::Arc, time::Duration};
+
+use actix_web::{
+    Error, HttpRequest, HttpResponse,
+    error::{ErrorGatewayTimeout, ErrorInternalServerError, ErrorNotFound, ErrorPayloadTooLarge},
+    http::header,
+    web::{self, Bytes},
+};
+
+use super::cgi::{CgiResponse, Program, request_env};
+use super::passwd;
+
+/// Path segments marking the end of the repository path.
+const SERVICE_SEGMENTS: &[&str] = &[
+    "HEAD",
+    "info",
+    "objects",
+    "git-upload-pack",
+    "git-receive-pack",
+];
+
+/// Access rules for a repository.
+#[derive(Clone, Debug)]
+pub struct Access {
+    /// Allow anonymous clients to clone and fetch.
+    pub public: bool,
+    /// Users allowed to clone and fetch. Any user when empty.
+    pub read: Vec<String>,
+    /// Users allowed to push. Nobody when empty.
+    pub write: Vec<String>,
+}
+
+impl Default for Access {
+    fn default() -> Self {
+        Self {
+            public: true,
+            read: vec![],
+            write: vec![],
+        }
+    }
+}
+
+impl Access {
+    /// Check if the user is permitted the requested operation.
+    fn permits(&self, user: Option<&str>, write: bool) -> bool {
+        let Some(user) = user else {
+            return !write && self.public;
+        };
+        match write {
+            true => self.write.iter().any(|u| u == user),
+            false => self.public || self.read.is_empty() || self.read.iter().any(|u| u == user),
+        }
+    }
+}
+
+/// Git smart-HTTP settings.
+#[derive(Clone, Debug)]
+pub struct Settings {
+    /// Directory containing served repositories.
+    pub root: PathBuf,
+    /// Git executable invoked as `git http-backend`.
+    pub git: PathBuf,
+    /// Serve every repository without a `git-daemon-export-ok` file.
+    pub export_all: bool,
+    /// Usernames and htpasswd compatible hashes of their passwords.
+    pub credentials: HashMap<String, String>,
+    /// Access rules by repository path. `*` applies to unlisted repositories.
+    pub repos: HashMap<String, Access>,
+    /// Program run to authorize requests after access rules pass.
+    pub hook: Option<Program>,
+    /// Additional environment variables passed to the backend.
+    pub env: Vec<(String, String)>,
+    /// Maximum time allowed for the backend to complete.
+    pub timeout: Duration,
+    /// Largest request body passed to the backend.
+    pub max_body_size: usize,
+}
+
+impl Settings {
+    /// Access rules for the repository.
+    fn access(&self, repo: &str) -> Access {
+        self.repos
+            .get(repo)
+            .or_else(|| self.repos.get("*"))
+            .cloned()
+            .unwrap_or_default()
+    }
+
+    /// Authenticate the request returning the user if credentials are present.
+    ///
+    /// Returns an error when credentials are present but invalid.
+    async fn authenticate(&self, req: &HttpRequest) -> Result<Option<String>, ()> {
+        let Some(value) = req.headers().get(header::AUTHORIZATION) else {
+            return Ok(None);
+        };
+        let (user, password) = passwd::basic(value).ok_or(())?;
+        match self.credentials.get(&user) {
+            Some(hash) if passwd::verify(password, hash.clone()).await => Ok(Some(user)),
+            _ => Err(()),
+        }
+    }
+}
+
+/// Response requesting client authentication.
+fn challenge() -> HttpResponse {
+    HttpResponse::Unauthorized()
+        .insert_header((header::WWW_AUTHENTICATE, "Basic realm=\"git\""))
+        .finish()
+}
+
+/// Split repository path from the request path.
+fn repository(path: &str) -> Option<String> {
+    let segments: Vec<&str> = path.split('/').filter(|s| !s.is_empty()).collect();
+    if segments.iter().any(|s| s.starts_with('.')) {
+        return None;
+    }
+    let end = segments
+        .iter()
+        .position(|s| SERVICE_SEGMENTS.contains(s))
+        .filter(|n| *n > 0)?;
+    Some(segments[..end].join("/"))
+}
+
+/// Check if the request pushes to the repository.
+fn is_push(req: &HttpRequest) -> bool {
+    req.path().ends_with("/git-receive-pack")
+        || req
+            .query_string()
+            .split('&')
+            .any(|q| q == "service=git-receive-pack")
+}
+
+/// Run program with the environment and stdin in a blocking thread.
+async fn run(
+    program: Program,
+    env: Vec<(String, String)>,
+    stdin: Option<Bytes>,
+) -> Result<CgiResponse, Error> {
+    let command = program.command.clone();
+    let output = web::block(move || program.run(env, stdin))
+        .await?
+        .map_err(|err| match err.kind() {
+            io::ErrorKind::TimedOut => {
+                log::error!("git program {command:?} timed out");
+                ErrorGatewayTimeout("git timed out")
+            }
+            _ => {
+                log::error!("git program {command:?} failed: {err:?}");
+                ErrorInternalServerError("git failed")
+            }
+        })?;
+    CgiResponse::parse(&output).map_err(|err| {
+        log::error!("git program {command:?} returned invalid response: {err:?}");
+        ErrorInternalServerError("invalid git response")
+    })
+}
+
+/// Git smart-HTTP service.
+#[derive(Clone, Debug)]
+pub struct Git(Arc<Settings>);
+
+impl Git {
+    /// Create new git service from settings.
+    pub fn new(settings: Settings) -> Self {
+        Self(Arc::new(settings))
+    }
+
+    /// Authorize and serve the git request.
+    pub async fn handle(
+        &self,
+        req: HttpRequest,
+        payload: web::Payload,
+    ) -> Result<HttpResponse, Error> {
+        let settings = &self.0;
+        let Some(repo) = repository(req.match_info().unprocessed()) else {
+            return Err(ErrorNotFound("repository not found"));
+        };
+        let Ok(user) = settings.authenticate(&req).await else {
+            log::warn!("git authentication failed for {repo:?}");
+            return Ok(challenge());
+        };
+        let push = is_push(&req);
+        if !settings.access(&repo).permits(user.as_deref(), push) {
+            log::debug!("git access to {repo:?} denied for {user:?}");
+            return Ok(match user {
+                Some(_) => HttpResponse::Forbidden().finish(),
+                None => challenge(),
+            });
+        }
+
+        let mut env = request_env(&req);
+        env.extend(settings.env.iter().cloned());
+        env.push(("GIT_REPO".to_owned(), repo.clone()));
+        env.push((
+            "GIT_ACCESS".to_owned(),
+            match push {
+                true => "write",
+                false => "read",
+            }
+            .to_owned(),
+        ));
+        if let Some(user) = user.as_ref() {
+            env.push(("REMOTE_USER".to_owned(), user.to_owned()));
+        }
+        if let Some(hook) = settings.hook.clone() {
+            let response = run(hook, env.clone(), None).await?;
+            if response.status.is_some_and(|s| !s.is_success()) {
+                log::debug!("git hook rejected {repo:?} for {user:?}");
+                return Ok(response.into_response());
+            }
+        }
+
+        let body = payload
+            .to_bytes_limited(settings.max_body_size)
+            .await
+            .map_err(|_| ErrorPayloadTooLarge("request body too large"))??;
+        env.retain(|(k, _)| k != "CONTENT_LENGTH");
+        env.push(("CONTENT_LENGTH".to_owned(), body.len().to_string()));
+        env.push((
+            "GIT_PROJECT_ROOT".to_owned(),
+            settings.root.to_string_lossy().into_owned(),
+        ));
+        if settings.export_all {
+            env.push(("GIT_HTTP_EXPORT_ALL".to_owned(), "1".to_owned()));
+        }
+        let backend = Program {
+            command: settings.git.clone(),
+            args: vec!["http-backend".to_owned()],
+            workdir: Some(settings.root.clone()),
+            timeout: settings.timeout,
+            // pack responses grow with the repository and are not capped
+            max_output: usize::MAX,
+        };
+        log::debug!("git serving {repo:?} for {user:?} (push={push})");
+        Ok(run(backend, env, Some(body)).await?.into_response())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn write_access_is_opt_in() {
+        let access = Access::default();
+        assert!(access.permits(None, false));
+        assert!(access.permits(Some("alice"), false));
+        assert!(!access.permits(None, true));
+        assert!(!access.permits(Some("alice"), true));
+
+        let access = Access {
+            write: vec!["alice".to_owned()],
+            ..Access::default()
+        };
+        assert!(access.permits(Some("alice"), true));
+        assert!(!access.permits(Some("bob"), true));
+    }
+
+    #[test]
+    fn private_read_access() {
+        let access = Access {
+            public: false,
+            read: vec!["bob".to_owned()],
+            write: vec![],
+        };
+        assert!(!access.permits(None, false));
+        assert!(access.permits(Some("bob"), false));
+        assert!(!access.permits(Some("alice"), false));
+    }
+}
@@ -0,0 +1,313 @@
+//! HTTP/2 Prior Knowledge Upstream Service
+//!
+//! Proxies requests to plaintext upstreams speaking HTTP/2 directly
+//! (h2c with prior knowledge) rather than downgrading to HTTP/1.1 as the
+//! default http client does. A single multiplexed connection is shared
+//! by all requests of a worker and re-established once it closes.
+//! Request bodies are streamed upstream while the response is received
+//! so bidirectional streams such as gRPC calls are supported.
+//!
+//! The server cannot send trailers to the client, so trailers of
+//! responses without a body, such as a gRPC `grpc-status` sent after
+//! empty response headers, are relayed as response headers instead.
+
+use std::{
+    cell::RefCell,
+    future::poll_fn,
+    path::PathBuf,
+    pin::Pin,
+    rc::Rc,
+    task::{Context, Poll, ready},
+    time::Duration,
+};
+
+use actix_web::{
+    Error, HttpRequest, HttpResponse,
+    error::{ErrorBadGateway, ErrorGatewayTimeout},
+    http::{
+        Uri,
+        header::{self, HeaderName, HeaderValue},
+    },
+    rt::net::{TcpStream, UnixStream},
+    web::{self, Bytes},
+};
+use futures_core::Stream;
+use h2::{RecvStream, SendStream, client::SendRequest};
+use tokio::io::{AsyncRead, AsyncWrite};
+
+/// Connection specific headers forbidden in HTTP/2 requests.
+const CONNECTION_HEADERS: &[&str] = &[
+    "connection",
+    "host",
+    "keep-alive",
+    "proxy-connection",
+    "transfer-encoding",
+    "upgrade",
+];
+
+/// h2c upstream settings.
+#[derive(Clone, Debug)]
+pub struct Settings {
+    /// Upstream base url.
+    pub upstream: Uri,
+    /// Unix socket connected to in place of the upstream address.
+    pub socket: Option<PathBuf>,
+    /// Time allowed for the upstream to respond.
+    pub timeout: Option<Duration>,
+    /// Replace the request authority with the upstream authority.
+    pub change_host: bool,
+    /// Headers added to requests sent upstream.
+    pub upstream_headers: Vec<(HeaderName, HeaderValue)>,
+    /// Headers added to responses sent to the client.
+    pub downstream_headers: Vec<(HeaderName, HeaderValue)>,
+}
+
+/// Log upstream failures and convert them into an error.
+fn upstream_error(err: h2::Error) -> Error {
+    log::error!("h2c upstream failed: {err}");
+    ErrorBadGateway("upstream request failed")
+}
+
+/// Complete the HTTP/2 handshake and drive the connection in the background.
+async fn handshake<T>(io: T) -> Result<SendRequest<Bytes>, h2::Error>
+where
+    T: AsyncRead + AsyncWrite + Unpin + 'static,
+{
+    let (send, conn) = h2::client::handshake(io).await?;
+    actix_web::rt::spawn(async move {
+        if let Err(err) = conn.await {
+            log::debug!("h2c upstream connection closed: {err}");
+        }
+    });
+    Ok(send)
+}
+
+/// Stream the request payload upstream respecting flow control.
+async fn upload(mut payload: web::Payload, mut stream: SendStream<Bytes>) {
+    while let Some(chunk) = poll_fn(|cx| Pin::new(&mut payload).poll_next(cx)).await {
+        let mut chunk = match chunk {
+            Ok(chunk) => chunk,
+            Err(err) => {
+                log::debug!("h2c request body failed: {err}");
+                stream.send_reset(h2::Reason::CANCEL);
+                return;
+            }
+        };
+        while !chunk.is_empty() {
+            stream.reserve_capacity(chunk.len());
+            let capacity = match poll_fn(|cx| stream.poll_capacity(cx)).await {
+                Some(Ok(capacity)) => capacity.min(chunk.len()),
+                Some(Err(err)) => {
+                    log::debug!("h2c request stream failed: {err}");
+                    return;
+                }
+                None => return,
+            };
+            if let Err(err) = stream.send_data(chunk.split_to(capacity), false) {
+                log::debug!("h2c request stream failed: {err}");
+                return;
+            }
+        }
+    }
+    if let Err(err) = stream.send_data(Bytes::new(), true) {
+        log::debug!("h2c request stream failed: {err}");
+    }
+}
+
+/// Upstream response body releasing flow control capacity as it is read.
+struct Body {
+    /// Chunk already received before the response was sent.
+    first: Option<Bytes>,
+    stream: RecvStream,
+}
+
+impl Stream for Body {
+    type Item = Result<Bytes, h2::Error>;
+
+    fn poll_next(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Self::Item>> {
+        if let Some(first) = self.first.take() {
+            return Poll::Ready(Some(Ok(first)));
+        }
+        let chunk = ready!(self.stream.poll_data(cx));
+        if let Some(Ok(chunk)) = chunk.as_ref() {
+            let _ = self.stream.flow_control().release_capacity(chunk.len());
+        }
+        Poll::Ready(chunk)
+    }
+}
+
+/// h2c upstream proxy service.
+#[derive(Clone)]
+pub struct H2c {
+    settings: Rc<Settings>,
+    conn: Rc<RefCell<Option<SendRequest<Bytes>>>>,
+}
+
+impl H2c {
+    /// Create new h2c upstream service from settings.
+    pub fn new(settings: Settings) -> Self {
+        Self {
+            settings: Rc::new(settings),
+            conn: Default::default(),
+        }
+    }
+
+    /// Open a new connection to the upstream.
+    async fn connect(&self) -> Result<SendRequest<Bytes>, String> {
+        let send = match self.settings.socket.as_ref() {
+            Some(path) => {
+                let io = UnixStream::connect(path)
+                    .await
+                    .map_err(|err| err.to_string())?;
+                handshake(io).await
+            }
+            None => {
+                let uri = &self.settings.upstream;
+                let host = uri.host().ok_or("missing upstream host")?;
+                let port = uri.port_u16().unwrap_or(80);
+                let io = TcpStream::connect((host.trim_matches(['[', ']']), port))
+                    .await
+                    .map_err(|err| err.to_string())?;
+                let _ = io.set_nodelay(true);
+                handshake(io).await
+            }
+        };
+        send.map_err(|err| err.to_string())
+    }
+
+    /// Retrieve a ready request sender, reconnecting when required.
+    async fn sender(&self) -> Result<SendRequest<Bytes>, Error> {
+        let cached = self.conn.borrow().clone();
+        if let Some(send) = cached {
+            match send.ready().await {
+                Ok(send) => return Ok(send),
+                Err(err) => log::debug!("h2c upstream connection unusable: {err}"),
+            }
+        }
+        let send = self.connect().await.map_err(|err| {
+            log::error!("h2c upstream connection failed: {err}");
+            ErrorBadGateway("upstream connection failed")
+        })?;
+        *self.conn.borrow_mut() = Some(send.clone());
+        send.ready().await.map_err(upstream_error)
+    }
+
+    /// Build the upstream request from the client request.
+    fn request(&self, req: &HttpRequest) -> Result<http::Request<()>, Error> {
+        let base = &self.settings.upstream;
+        let rest = req.match_info().unprocessed();
+        let path = format!(
+            "{}/{}",
+            base.path().trim_end_matches('/'),
+            rest.trim_start_matches('/')
+        );
+        let path = match req.query_string() {
+            "" => path,
+            query => format!("{path}?{query}"),
+        };
+        let authority = match self.settings.change_host {
+            true => base.authority().map(|a| a.as_str().to_owned()),
+            false => Some(req.connection_info().host().to_owned()),
+        };
+        let uri = Uri::builder()
+            .scheme("http")
+            .authority(authority.unwrap_or_else(|| "localhost".to_owned()))
+            .path_and_query(path)
+            .build()
+            .map_err(|err| {
+                log::error!("invalid h2c upstream uri: {err}");
+                ErrorBadGateway("invalid upstream uri")
+            })?;
+
+        let mut request = http::Request::new(());
+        *request.method_mut() = req.method().clone();
+        *request.uri_mut() = uri;
+        let headers = request.headers_mut();
+        for (name, value) in req.headers().iter() {
+            if CONNECTION_HEADERS.contains(&name.as_str()) {
+                continue;
+            }
+            // the body is uploaded right away without awaiting `100 Continue`
+            if name == header::EXPECT {
+                continue;
+            }
+            // only `TE: trailers` is permitted over http/2
+            if name == header::TE && !value.as_bytes().eq_ignore_ascii_case(b"trailers") {
+                continue;
+            }
+            headers.append(name.clone(), value.clone());
+        }
+        if let Some(peer) = req.peer_addr()
+            && let Ok(value) = HeaderValue::try_from(peer.ip().to_string())
+        {
+            headers.insert(HeaderName::from_static("x-forwarded-for"), value);
+        }
+        for (name, value) in self.settings.upstream_headers.iter() {
+            headers.insert(name.clone(), value.clone());
+        }
+        Ok(request)
+    }
+
+    /// Proxy the request to the upstream.
+    pub async fn handle(
+        &self,
+        req: HttpRequest,
+        payload: web::Payload,
+    ) -> Result<HttpResponse, Error> {
+        let request = self.request(&req)?;
+        let mut send = self.sender().await?;
+        let (response, stream) = send.send_request(request, false).map_err(upstream_error)?;
+        actix_web::rt::spawn(upload(payload, stream));
+
+        let response = match self.settings.timeout {
+            Some(limit) => actix_web::rt::time::timeout(limit, response)
+                .await
+                .map_err(|_| {
+                    log::error!("h2c upstream timed out");
+                    ErrorGatewayTimeout("upstream timed out")
+                })?,
+            None => response.await,
+        }
+        .map_err(upstream_error)?;
+
+        let (mut parts, mut stream) = response.into_parts();
+        // trailers can only be relayed as headers when no body precedes them
+        let first = match stream.is_end_stream() {
+            true => None,
+            false => poll_fn(|cx| stream.poll_data(cx))
+                .await
+                .transpose()
+                .map_err(upstream_error)?,
+        };
+        match first.as_ref() {
+            Some(chunk) => {
+                let _ = stream.flow_control().release_capacity(chunk.len());
+            }
+            None => {
+                if let Some(trailers) = stream.trailers().await.map_err(upstream_error)? {
+                    parts.headers.extend(trailers);
+                }
+            }
+        }
+
+        let mut builder = HttpResponse::build(parts.status);
+        parts.headers.iter().fold(&mut builder, |b, (h, v)| {
+            b.append_header((h.clone(), v.clone()))
+        });
+        self.settings
+            .downstream_headers
+            .iter()
+            .fold(&mut builder, |b, (h, v)| {
+                b.insert_header((h.clone(), v.clone()))
+            });
+        Ok(builder.streaming(Body { first, stream }))
+    }
+}
+
+impl std::fmt::Debug for H2c {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("H2c")
+            .field("settings", &self.settings)
+            .finish()
+    }
+}
@@ -0,0 +1,127 @@
+//! Honeypot Trap Path Middleware
+//!
+//! Requests to decoy paths that no legitimate client requests (such as
+//! `/wp-login.php` or `/.env`) are logged, answered with a configured
+//! response and reported to the dynamic ip banning subsystem.
+
+use std::rc::Rc;
+
+#[cfg(feature = "ipban")]
+use actix_web::HttpMessage;
+use actix_web::{
+    Error, HttpResponse,
+    body::{EitherBody, MessageBody},
+    dev::{Service, ServiceRequest, ServiceResponse, Transform},
+    http::{StatusCode, header},
+};
+
+use super::LocalBoxFuture;
+use crate::{config::PathMatch, metrics};
+
+/// Honeypot settings.
+#[derive(Clone, Debug)]
+pub struct Settings {
+    /// Decoy path globs.
+    pub paths: Vec<PathMatch>,
+    /// Response status code.
+    pub status: StatusCode,
+    /// Response body.
+    pub body: String,
+    /// Response content-type.
+    pub content_type: String,
+    /// Ban clients immediately for this duration.
+    ///
+    /// Otherwise hits are reported as offenses to the ban middleware.
+    #[cfg(feature = "ipban")]
+    pub ban: Option<std::time::Duration>,
+}
+
+/// Honeypot middleware.
+#[derive(Clone)]
+pub struct Honeypot(Rc<Settings>);
+
+impl Honeypot {
+    /// Create new honeypot middleware from settings.
+    pub fn new(settings: Settings) -> Self {
+        Self(Rc::new(settings))
+    }
+}
+
+impl<S, B> Transform<S, ServiceRequest> for Honeypot
+where
+    S: Service<ServiceRequest, Response = ServiceResponse<B>, Error = Error> + 'static,
+    B: MessageBody + 'static,
+{
+    type Response = ServiceResponse<EitherBody<B>>;
+    type Error = Error;
+    type Transform = HoneypotService<S>;
+    type InitError = ();
+    type Future = std::future::Ready<Result<Self::Transform, Self::InitError>>;
+
+    fn new_transform(&self, service: S) -> Self::Future {
+        std::future::ready(Ok(HoneypotService {
+            service: Rc::new(service),
+            settings: Rc::clone(&self.0),
+        }))
+    }
+}
+
+/// Service produced by [`Honeypot`] middleware.
+pub struct HoneypotService<S> {
+    service: Rc<S>,
+    settings: Rc<Settings>,
+}
+
+impl<S> HoneypotService<S> {
+    /// Log and report the trapped client.
+    fn trap(&self, req: &ServiceRequest) {
+        let ip = req.peer_addr().map(|addr| addr.ip());
+        let agent = req
+            .headers()
+            .get(header::USER_AGENT)
+            .and_then(|v| v.to_str().ok())
+            .unwrap_or("-");
+        let client = ip.map(|ip| ip.to_string());
+        log::warn!(
+            "honeypot hit {} \"{} {}\" \"{agent}\"",
+            client.as_deref().unwrap_or("-"),
+            req.method(),
+            req.uri()
+        );
+        metrics::counter("bob_honeypot_hits_total").inc();
+
+        #[cfg(feature = "ipban")]
+        if let (Some(ip), Some(duration)) = (ip, self.settings.ban) {
+            let reason = format!("honeypot at {}", req.path());
+            super::ban::ban(ip, duration, reason);
+            return;
+        }
+        #[cfg(feature = "ipban")]
+        req.extensions_mut().insert(super::ban::Offense);
+    }
+}
+
+impl<S, B> Service<ServiceRequest> for HoneypotService<S>
+where
+    S: Service<ServiceRequest, Response = ServiceResponse<B>, Error = Error> + 'static,
+    B: MessageBody + 'static,
+{
+    type Response = ServiceResponse<EitherBody<B>>;
+    type Error = Error;
+    type Future = LocalBoxFuture<Result<Self::Response, Self::Error>>;
+
+    actix_web::dev::forward_ready!(service);
+
+    fn call(&self, req: ServiceRequest) -> Self::Future {
+        let settings = &self.settings;
+        if settings.paths.iter().any(|p| p.matches(req.path())) {
+            self.trap(&req);
+            let res = HttpResponse::build(settings.status)
+                .insert_header((header::CONTENT_TYPE, settings.content_type.as_str()))
+                .body(settings.body.clone());
+            return Box::pin(async move { Ok(req.into_response(res).map_into_right_body()) });
+        }
+        let service = Rc::clone(&self.service);
+        Box::pin(async move { Ok(service.call(req).await?.map_into_left_body()) })
+    }
+}
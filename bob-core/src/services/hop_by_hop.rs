@@ -0,0 +1,185 @@
+//! Hop-by-Hop Header Middleware
+//!
+//! Removes hop-by-hop headers (RFC 7230 section 6.1) from requests
+//! before they are proxied upstream and from upstream responses before
+//! they are returned to the client. Headers named by the `Connection`
+//! header are removed as well. `Upgrade` is kept on upgrade requests and
+//! on the `101 Switching Protocols` response negotiating the upgrade.
+//! `TE: trailers` may optionally be forwarded as required by gRPC.
+//! `Expect: 100-continue` is forwarded on requests declaring a body, so
+//! the upstream may refuse the body before it is uploaded, and removed
+//! from bodyless requests which have nothing to wait for.
+
+use std::rc::Rc;
+
+use actix_web::{
+    Error,
+    body::MessageBody,
+    dev::{Service, ServiceRequest, ServiceResponse, Transform},
+    http::{
+        StatusCode,
+        header::{self, HeaderMap, HeaderName},
+    },
+};
+
+use super::LocalBoxFuture;
+
+/// Hop-by-hop headers always removed.
+const HOP_BY_HOP: &[&str] = &[
+    "connection",
+    "keep-alive",
+    "proxy-authenticate",
+    "proxy-authorization",
+    "proxy-connection",
+    "te",
+    "trailer",
+    "transfer-encoding",
+    "upgrade",
+];
+
+/// Hop-by-hop header settings.
+#[derive(Clone, Debug, Default)]
+pub struct Settings {
+    /// Additional header names treated as hop-by-hop.
+    pub extra: Vec<HeaderName>,
+    /// Forward `TE: trailers` on requests.
+    pub keep_te_trailers: bool,
+}
+
+/// Check if the `TE` header accepts trailers.
+fn accepts_trailers(headers: &HeaderMap) -> bool {
+    headers
+        .get_all(header::TE)
+        .filter_map(|v| v.to_str().ok())
+        .flat_map(|v| v.split(','))
+        .any(|token| token.trim().eq_ignore_ascii_case("trailers"))
+}
+
+/// Check if the request declares a non-empty body.
+fn has_body(headers: &HeaderMap) -> bool {
+    headers.contains_key(header::TRANSFER_ENCODING)
+        || headers
+            .get(header::CONTENT_LENGTH)
+            .and_then(|v| v.to_str().ok())
+            .and_then(|v| v.trim().parse::<u64>().ok())
+            .is_some_and(|len| len > 0)
+}
+
+/// Check if the `Connection` header requests a protocol upgrade.
+fn is_upgrade(headers: &HeaderMap) -> bool {
+    headers.contains_key(header::UPGRADE)
+        && headers
+            .get_all(header::CONNECTION)
+            .filter_map(|v| v.to_str().ok())
+            .flat_map(|v| v.split(','))
+            .any(|token| token.trim().eq_ignore_ascii_case("upgrade"))
+}
+
+impl Settings {
+    /// Remove hop-by-hop headers from the header map.
+    ///
+    /// Upgrade negotiation headers are retained when `keep_upgrade` is set.
+    fn strip(&self, headers: &mut HeaderMap, keep_upgrade: bool) {
+        let listed: Vec<HeaderName> = headers
+            .get_all(header::CONNECTION)
+            .filter_map(|v| v.to_str().ok())
+            .flat_map(|v| v.split(','))
+            .filter_map(|token| HeaderName::try_from(token.trim()).ok())
+            .collect();
+        let names = HOP_BY_HOP
+            .iter()
+            .map(|name| HeaderName::from_static(name))
+            .chain(self.extra.iter().cloned())
+            .chain(listed);
+        for name in names {
+            if keep_upgrade && (name == header::UPGRADE || name == header::CONNECTION) {
+                continue;
+            }
+            headers.remove(name);
+        }
+        if keep_upgrade {
+            headers.insert(
+                header::CONNECTION,
+                header::HeaderValue::from_static("upgrade"),
+            );
+        }
+    }
+}
+
+/// Remove the standard hop-by-hop headers and those named by `Connection`.
+pub fn remove(headers: &mut HeaderMap) {
+    Settings::default().strip(headers, false);
+}
+
+/// Hop-by-hop header middleware.
+#[derive(Clone)]
+pub struct HopByHop(Rc<Settings>);
+
+impl HopByHop {
+    /// Create new hop-by-hop header middleware from settings.
+    pub fn new(settings: Settings) -> Self {
+        Self(Rc::new(settings))
+    }
+}
+
+impl<S, B> Transform<S, ServiceRequest> for HopByHop
+where
+    S: Service<ServiceRequest, Response = ServiceResponse<B>, Error = Error> + 'static,
+    B: MessageBody + 'static,
+{
+    type Response = ServiceResponse<B>;
+    type Error = Error;
+    type Transform = HopByHopService<S>;
+    type InitError = ();
+    type Future = std::future::Ready<Result<Self::Transform, Self::InitError>>;
+
+    fn new_transform(&self, service: S) -> Self::Future {
+        std::future::ready(Ok(HopByHopService {
+            service: Rc::new(service),
+            settings: Rc::clone(&self.0),
+        }))
+    }
+}
+
+/// Service produced by [`HopByHop`] middleware.
+pub struct HopByHopService<S> {
+    service: Rc<S>,
+    settings: Rc<Settings>,
+}
+
+impl<S, B> Service<ServiceRequest> for HopByHopService<S>
+where
+    S: Service<ServiceRequest, Response = ServiceResponse<B>, Error = Error> + 'static,
+    B: MessageBody + 'static,
+{
+    type Response = ServiceResponse<B>;
+    type Error = Error;
+    type Future = LocalBoxFuture<Result<Self::Response, Self::Error>>;
+
+    actix_web::dev::forward_ready!(service);
+
+    fn call(&self, mut req: ServiceRequest) -> Self::Future {
+        let upgrade = is_upgrade(req.headers());
+        let trailers = self.settings.keep_te_trailers && accepts_trailers(req.headers());
+        let expect = has_body(req.headers());
+        self.settings.strip(req.headers_mut(), upgrade);
+        if !expect {
+            req.headers_mut().remove(header::EXPECT);
+        }
+        if trailers {
+            let value = header::HeaderValue::from_static("trailers");
+            req.headers_mut().insert(header::TE, value);
+        }
+
+        let service = Rc::clone(&self.service);
+        let settings = Rc::clone(&self.settings);
+        Box::pin(async move {
+            let mut res = service.call(req).await?;
+            let negotiated = upgrade
+                && res.status() == StatusCode::SWITCHING_PROTOCOLS
+                && is_upgrade(res.headers());
+            settings.strip(res.headers_mut(), negotiated);
+            Ok(res)
+        })
+    }
+}
@@ -0,0 +1,271 @@
+//! Static Content Integrity Headers
+//!
+//! Emits `Repr-Digest` (RFC 9530) and legacy `Digest` headers for files
+//! served by the fileserver so downstream mirrors and clients can verify
+//! the integrity of distributed artifacts. When a signing key is
+//! configured, a detached Ed25519 HTTP message signature (RFC 9421)
+//! covering the `Repr-Digest` header is emitted as well.
+//!
+//! Digests are computed from the file on disk and cached per worker
+//! until the file's size or modification time changes.
+
+use std::{
+    cell::RefCell,
+    collections::HashMap,
+    fs::File,
+    io,
+    path::{Path, PathBuf},
+    rc::Rc,
+    time::SystemTime,
+};
+
+use actix_web::{
+    Error,
+    body::{BodySize, MessageBody},
+    dev::{Service, ServiceRequest, ServiceResponse, Transform},
+    http::{
+        StatusCode,
+        header::{HeaderName, HeaderValue},
+    },
+    web,
+};
+use base64::{Engine, prelude::BASE64_STANDARD};
+use sha2::{Digest, Sha256};
+
+use super::LocalBoxFuture;
+
+/// Cached digest entries retained per worker before the cache is reset.
+const CACHE_LIMIT: usize = 4096;
+
+const REPR_DIGEST: &str = "repr-digest";
+const DIGEST: &str = "digest";
+#[cfg(feature = "signing")]
+const SIGNATURE_INPUT: &str = "signature-input";
+#[cfg(feature = "signing")]
+const SIGNATURE: &str = "signature";
+
+/// Detached signature key.
+#[cfg(feature = "signing")]
+#[derive(Clone)]
+pub struct Signer {
+    /// Ed25519 signing key.
+    pub key: std::sync::Arc<ed25519_dalek::SigningKey>,
+    /// Key identifier advertised in the signature parameters.
+    pub key_id: String,
+}
+
+#[cfg(feature = "signing")]
+impl Signer {
+    /// Produce `Signature-Input` and `Signature` values covering `Repr-Digest`.
+    fn sign(&self, repr_digest: &str) -> (String, String) {
+        use ed25519_dalek::Signer as _;
+        let params = format!(
+            "(\"repr-digest\");keyid=\"{}\";alg=\"ed25519\"",
+            self.key_id
+        );
+        let base = format!("\"repr-digest\": {repr_digest}\n\"@signature-params\": {params}");
+        let signature = self.key.sign(base.as_bytes());
+        let signature = BASE64_STANDARD.encode(signature.to_bytes());
+        (format!("sig1={params}"), format!("sig1=:{signature}:"))
+    }
+}
+
+#[cfg(feature = "signing")]
+impl std::fmt::Debug for Signer {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("Signer")
+            .field("key_id", &self.key_id)
+            .finish()
+    }
+}
+
+/// Integrity header settings.
+#[derive(Clone, Debug)]
+pub struct Settings {
+    /// Fileserver document root.
+    pub root: PathBuf,
+    /// Index files served for directories.
+    pub index: Vec<String>,
+    /// Emit the legacy `Digest` header alongside `Repr-Digest`.
+    pub legacy_digest: bool,
+    /// Detached signature key.
+    #[cfg(feature = "signing")]
+    pub signer: Option<Signer>,
+}
+
+impl Settings {
+    /// Resolve the file served for the request path.
+    ///
+    /// Paths which cannot be resolved unambiguously are skipped.
+    fn resolve(&self, path: &str) -> Option<PathBuf> {
+        let mut file = self.root.clone();
+        for segment in path.split('/') {
+            let segment = percent_encoding::percent_decode_str(segment)
+                .decode_utf8()
+                .ok()?;
+            match segment.as_ref() {
+                "" | "." => continue,
+                ".." => return None,
+                segment if segment.contains(['/', '\\']) => return None,
+                segment => file.push(segment),
+            }
+        }
+        if file.is_dir() {
+            return self
+                .index
+                .iter()
+                .map(|index| file.join(index))
+                .find(|index| index.is_file());
+        }
+        Some(file)
+    }
+}
+
+/// Computed integrity headers for a single file.
+#[derive(Debug)]
+struct Headers {
+    len: u64,
+    modified: Option<SystemTime>,
+    values: Vec<(HeaderName, HeaderValue)>,
+}
+
+/// Compute the sha-256 digest of the file.
+fn digest(path: &Path) -> io::Result<(u64, Option<SystemTime>, [u8; 32])> {
+    let mut file = File::open(path)?;
+    let meta = file.metadata()?;
+    let mut hasher = Sha256::new();
+    io::copy(&mut file, &mut hasher)?;
+    Ok((meta.len(), meta.modified().ok(), hasher.finalize().into()))
+}
+
+/// Integrity header middleware.
+#[derive(Clone)]
+pub struct Integrity(Rc<Settings>);
+
+impl Integrity {
+    /// Create new integrity header middleware from settings.
+    pub fn new(settings: Settings) -> Self {
+        Self(Rc::new(settings))
+    }
+}
+
+impl<S, B> Transform<S, ServiceRequest> for Integrity
+where
+    S: Service<ServiceRequest, Response = ServiceResponse<B>, Error = Error> + 'static,
+    B: MessageBody + 'static,
+{
+    type Response = ServiceResponse<B>;
+    type Error = Error;
+    type Transform = IntegrityService<S>;
+    type InitError = ();
+    type Future = std::future::Ready<Result<Self::Transform, Self::InitError>>;
+
+    fn new_transform(&self, service: S) -> Self::Future {
+        std::future::ready(Ok(IntegrityService {
+            service: Rc::new(service),
+            settings: Rc::clone(&self.0),
+            cache: Default::default(),
+        }))
+    }
+}
+
+/// Service produced by [`Integrity`] middleware.
+pub struct IntegrityService<S> {
+    service: Rc<S>,
+    settings: Rc<Settings>,
+    cache: Rc<RefCell<HashMap<PathBuf, Rc<Headers>>>>,
+}
+
+/// Lookup or compute the integrity headers for the file.
+async fn headers(
+    settings: &Settings,
+    cache: &RefCell<HashMap<PathBuf, Rc<Headers>>>,
+    path: PathBuf,
+) -> io::Result<Rc<Headers>> {
+    let meta = std::fs::metadata(&path)?;
+    let cached = cache.borrow().get(&path).cloned();
+    if let Some(headers) = cached
+        && headers.len == meta.len()
+        && headers.modified == meta.modified().ok()
+    {
+        return Ok(headers);
+    }
+
+    let file = path.clone();
+    let (len, modified, hash) = web::block(move || digest(&file))
+        .await
+        .map_err(io::Error::other)??;
+    let hash = BASE64_STANDARD.encode(hash);
+    let repr_digest = format!("sha-256=:{hash}:");
+    let mut values = Vec::new();
+    if settings.legacy_digest {
+        values.push((DIGEST, format!("SHA-256={hash}")));
+    }
+    #[cfg(feature = "signing")]
+    if let Some(signer) = settings.signer.as_ref() {
+        let (input, signature) = signer.sign(&repr_digest);
+        values.push((SIGNATURE_INPUT, input));
+        values.push((SIGNATURE, signature));
+    }
+    values.push((REPR_DIGEST, repr_digest));
+    let values = values
+        .into_iter()
+        .map(|(name, value)| {
+            let value = HeaderValue::try_from(value).map_err(io::Error::other)?;
+            Ok((HeaderName::from_static(name), value))
+        })
+        .collect::<io::Result<_>>()?;
+
+    let headers = Rc::new(Headers {
+        len,
+        modified,
+        values,
+    });
+    let mut cache = cache.borrow_mut();
+    if cache.len() >= CACHE_LIMIT {
+        cache.clear();
+    }
+    cache.insert(path, Rc::clone(&headers));
+    Ok(headers)
+}
+
+impl<S, B> Service<ServiceRequest> for IntegrityService<S>
+where
+    S: Service<ServiceRequest, Response = ServiceResponse<B>, Error = Error> + 'static,
+    B: MessageBody + 'static,
+{
+    type Response = ServiceResponse<B>;
+    type Error = Error;
+    type Future = LocalBoxFuture<Result<Self::Response, Self::Error>>;
+
+    actix_web::dev::forward_ready!(service);
+
+    fn call(&self, req: ServiceRequest) -> Self::Future {
+        let path = self.settings.resolve(req.match_info().unprocessed());
+        let service = Rc::clone(&self.service);
+        let settings = Rc::clone(&self.settings);
+        let cache = Rc::clone(&self.cache);
+        Box::pin(async move {
+            let mut res = service.call(req).await?;
+            let Some(path) = path.filter(|_| res.status() == StatusCode::OK) else {
+                return Ok(res);
+            };
+            match headers(&settings, &cache, path.clone()).await {
+                // only trust the resolved file when it matches the served body
+                Ok(headers) => match res.response().body().size() {
+                    BodySize::Sized(size) if size != headers.len => {
+                        log::debug!("served body does not match {path:?}. skipping digest");
+                    }
+                    _ => {
+                        let map = res.headers_mut();
+                        for (name, value) in headers.values.iter() {
+                            map.insert(name.clone(), value.clone());
+                        }
+                    }
+                },
+                Err(err) => log::warn!("failed to compute digest for {path:?}: {err}"),
+            }
+            Ok(res)
+        })
+    }
+}
@@ -0,0 +1,91 @@
+//! Upstream Error Interception Middleware
+//!
+//! Replaces upstream responses with configured error statuses by an
+//! error generated by bob itself, discarding the upstream headers and
+//! body so backend error pages and stack traces never reach clients.
+//! The status is kept, so the response is rendered like any other bob
+//! error and module `next` overrides still fall through on it.
+
+use std::rc::Rc;
+
+use actix_web::{
+    Error,
+    body::{BoxBody, MessageBody},
+    dev::{Service, ServiceRequest, ServiceResponse, Transform},
+    error::InternalError,
+    http::StatusCode,
+};
+
+use super::LocalBoxFuture;
+
+/// Error interception settings.
+#[derive(Clone, Debug, Default)]
+pub struct Settings {
+    /// Upstream statuses replaced by a generated error.
+    pub statuses: Vec<StatusCode>,
+}
+
+/// Upstream error interception middleware.
+#[derive(Clone)]
+pub struct Intercept(Rc<Settings>);
+
+impl Intercept {
+    /// Create new error interception middleware from settings.
+    pub fn new(settings: Settings) -> Self {
+        Self(Rc::new(settings))
+    }
+}
+
+impl<S, B> Transform<S, ServiceRequest> for Intercept
+where
+    S: Service<ServiceRequest, Response = ServiceResponse<B>, Error = Error> + 'static,
+    B: MessageBody + 'static,
+{
+    type Response = ServiceResponse<BoxBody>;
+    type Error = Error;
+    type Transform = InterceptService<S>;
+    type InitError = ();
+    type Future = std::future::Ready<Result<Self::Transform, Self::InitError>>;
+
+    fn new_transform(&self, service: S) -> Self::Future {
+        std::future::ready(Ok(InterceptService {
+            service: Rc::new(service),
+            settings: Rc::clone(&self.0),
+        }))
+    }
+}
+
+/// Service produced by [`Intercept`] middleware.
+pub struct InterceptService<S> {
+    service: Rc<S>,
+    settings: Rc<Settings>,
+}
+
+impl<S, B> Service<ServiceRequest> for InterceptService<S>
+where
+    S: Service<ServiceRequest, Response = ServiceResponse<B>, Error = Error> + 'static,
+    B: MessageBody + 'static,
+{
+    type Response = ServiceResponse<BoxBody>;
+    type Error = Error;
+    type Future = LocalBoxFuture<Result<Self::Response, Self::Error>>;
+
+    actix_web::dev::forward_ready!(service);
+
+    fn call(&self, req: ServiceRequest) -> Self::Future {
+        let service = Rc::clone(&self.service);
+        let settings = Rc::clone(&self.settings);
+        Box::pin(async move {
+            let res = service.call(req).await?;
+            let status = res.status();
+            // errors raised by the proxy itself are already bob errors
+            if res.response().error().is_some() || !settings.statuses.contains(&status) {
+                return Ok(res.map_into_boxed_body());
+            }
+            log::debug!("intercepted upstream error status {status}");
+            let reason = status.canonical_reason().unwrap_or("upstream error");
+            let err = InternalError::new(reason, status);
+            Ok(ServiceResponse::from_err(err, res.into_parts().0))
+        })
+    }
+}
@@ -0,0 +1,138 @@
+//! IP Allow/Block List Middleware
+//!
+//! Rejects requests from client addresses within the block list unless
+//! they are also within the allow list. Addresses are matched by CIDR
+//! notation or ranges against the ipware resolved client address.
+
+use std::rc::Rc;
+
+use actix_web::{
+    Error, HttpResponse,
+    body::{EitherBody, MessageBody},
+    dev::{Service, ServiceRequest, ServiceResponse, Transform},
+};
+
+use super::LocalBoxFuture;
+use crate::config::{IpMatch, PathMatch};
+
+/// IP filter settings.
+#[derive(Clone, Debug, Default)]
+pub struct Settings {
+    /// Addresses always permitted.
+    pub allow: Vec<IpMatch>,
+    /// Addresses rejected unless also permitted.
+    pub block: Vec<IpMatch>,
+    /// Paths protected by the filter when non-empty.
+    pub protect: Vec<PathMatch>,
+    /// Paths never protected by the filter.
+    pub exclude: Vec<PathMatch>,
+}
+
+impl Settings {
+    /// Check if request path is covered by the filter.
+    ///
+    /// Protected paths are matched against the normalized path and only
+    /// paths already in normal form can be excluded.
+    fn protects(&self, path: &str) -> bool {
+        (self.protect.is_empty() || self.protect.iter().any(|p| p.matches_normalized(path)))
+            && !self.exclude.iter().any(|p| p.matches_strict(path))
+    }
+
+    /// Check if request is permitted by the filter.
+    fn permits(&self, req: &ServiceRequest) -> bool {
+        if !self.protects(req.path()) {
+            return true;
+        }
+        let Some(ip) = req.peer_addr().map(|addr| addr.ip()) else {
+            return self.block.is_empty();
+        };
+        self.allow.iter().any(|m| m.contains(&ip)) || !self.block.iter().any(|m| m.contains(&ip))
+    }
+}
+
+/// IP filter middleware.
+#[derive(Clone)]
+pub struct IpFilter(Rc<Settings>);
+
+impl IpFilter {
+    /// Create new ip filter middleware from settings.
+    pub fn new(settings: Settings) -> Self {
+        Self(Rc::new(settings))
+    }
+}
+
+impl<S, B> Transform<S, ServiceRequest> for IpFilter
+where
+    S: Service<ServiceRequest, Response = ServiceResponse<B>, Error = Error> + 'static,
+    B: MessageBody + 'static,
+{
+    type Response = ServiceResponse<EitherBody<B>>;
+    type Error = Error;
+    type Transform = IpFilterService<S>;
+    type InitError = ();
+    type Future = std::future::Ready<Result<Self::Transform, Self::InitError>>;
+
+    fn new_transform(&self, service: S) -> Self::Future {
+        std::future::ready(Ok(IpFilterService {
+            service: Rc::new(service),
+            settings: Rc::clone(&self.0),
+        }))
+    }
+}
+
+/// Service produced by [`IpFilter`] middleware.
+pub struct IpFilterService<S> {
+    service: Rc<S>,
+    settings: Rc<Settings>,
+}
+
+impl<S, B> Service<ServiceRequest> for IpFilterService<S>
+where
+    S: Service<ServiceRequest, Response = ServiceResponse<B>, Error = Error> + 'static,
+    B: MessageBody + 'static,
+{
+    type Response = ServiceResponse<EitherBody<B>>;
+    type Error = Error;
+    type Future = LocalBoxFuture<Result<Self::Response, Self::Error>>;
+
+    actix_web::dev::forward_ready!(service);
+
+    fn call(&self, req: ServiceRequest) -> Self::Future {
+        if !self.settings.permits(&req) {
+            log::debug!("ipfilter rejected {:?} {}", req.peer_addr(), req.path());
+            let res = HttpResponse::Forbidden().finish();
+            return Box::pin(async move { Ok(req.into_response(res).map_into_right_body()) });
+        }
+        let service = Rc::clone(&self.service);
+        Box::pin(async move { Ok(service.call(req).await?.map_into_left_body()) })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn exclude_ignores_traversal_variants() {
+        let settings = Settings {
+            exclude: vec!["/public/*".parse().unwrap()],
+            ..Default::default()
+        };
+        assert!(!settings.protects("/public/index.html"));
+        assert!(settings.protects("/public/../admin"));
+        assert!(settings.protects("/public/%2e%2e/admin"));
+        assert!(settings.protects("/public/a/b"));
+    }
+
+    #[test]
+    fn protect_matches_normalized_path() {
+        let settings = Settings {
+            protect: vec!["/admin/*".parse().unwrap()],
+            ..Default::default()
+        };
+        assert!(settings.protects("/admin/users"));
+        assert!(settings.protects("/public/../admin/users"));
+        assert!(settings.protects("/%61dmin/users"));
+        assert!(!settings.protects("/public/users"));
+    }
+}
@@ -0,0 +1,132 @@
+//! Session Cookie Key Rotation Middleware
+//!
+//! Re-encrypts incoming session cookies sealed with a previous key
+//! using the current key, allowing sessions to survive key rotation.
+
+use std::{
+    rc::Rc,
+    task::{Context, Poll},
+};
+
+use actix_web::{
+    Error,
+    cookie::{Cookie, CookieJar, Key},
+    dev::{Service, ServiceRequest, ServiceResponse, Transform},
+    http::header::{self, HeaderValue},
+};
+
+/// Session cookie key rotation middleware.
+#[derive(Clone)]
+pub struct KeyRotation {
+    cookie_name: Rc<str>,
+    current: Key,
+    previous: Rc<[Key]>,
+}
+
+impl KeyRotation {
+    /// Create key rotation middleware for the specified cookie.
+    pub fn new(cookie_name: &str, current: Key, previous: Vec<Key>) -> Self {
+        Self {
+            cookie_name: cookie_name.into(),
+            current,
+            previous: previous.into(),
+        }
+    }
+
+    /// Re-encrypt cookie value with current key if sealed with a previous key.
+    fn rotate(&self, value: &str) -> Option<String> {
+        let mut jar = CookieJar::new();
+        jar.add_original(Cookie::new(self.cookie_name.to_string(), value.to_owned()));
+        if jar.private(&self.current).get(&self.cookie_name).is_some() {
+            return None;
+        }
+        let plain = self
+            .previous
+            .iter()
+            .find_map(|key| jar.private(key).get(&self.cookie_name))?;
+        let mut rotated = CookieJar::new();
+        rotated.private_mut(&self.current).add(plain);
+        rotated.get(&self.cookie_name).map(|c| c.value().to_owned())
+    }
+
+    /// Rewrite request cookie header with rotated session cookie.
+    fn rewrite(&self, req: &mut ServiceRequest) {
+        let Some(raw) = req
+            .headers()
+            .get(header::COOKIE)
+            .and_then(|h| h.to_str().ok())
+            .map(|h| h.to_owned())
+        else {
+            return;
+        };
+        let mut changed = false;
+        let cookies: Vec<String> = Cookie::split_parse(raw.as_str())
+            .filter_map(|c| c.ok())
+            .map(|c| match c.name() == &*self.cookie_name {
+                true => match self.rotate(c.value()) {
+                    Some(value) => {
+                        changed = true;
+                        format!("{}={value}", c.name())
+                    }
+                    None => c.stripped().to_string(),
+                },
+                false => c.stripped().to_string(),
+            })
+            .collect();
+        if !changed {
+            return;
+        }
+        log::debug!(
+            "rotated session cookie {:?} to current key",
+            self.cookie_name
+        );
+        if let Ok(value) = HeaderValue::from_str(&cookies.join("; ")) {
+            req.headers_mut().insert(header::COOKIE, value);
+        }
+    }
+}
+
+impl<S, B> Transform<S, ServiceRequest> for KeyRotation
+where
+    S: Service<ServiceRequest, Response = ServiceResponse<B>, Error = Error> + 'static,
+{
+    type Response = ServiceResponse<B>;
+    type Error = Error;
+    type Transform = KeyRotationService<S>;
+    type InitError = ();
+    type Future = std::future::Ready<Result<Self::Transform, Self::InitError>>;
+
+    fn new_transform(&self, service: S) -> Self::Future {
+        std::future::ready(Ok(KeyRotationService {
+            service,
+            rotation: self.clone(),
+        }))
+    }
+}
+
+/// Service produced by [`KeyRotation`] middleware.
+pub struct KeyRotationService<S> {
+    service: S,
+    rotation: KeyRotation,
+}
+
+impl<S, B> Service<ServiceRequest> for KeyRotationService<S>
+where
+    S: Service<ServiceRequest, Response = ServiceResponse<B>, Error = Error> + 'static,
+{
+    type Response = ServiceResponse<B>;
+    type Error = Error;
+    type Future = S::Future;
+
+    #[inline]
+    fn poll_ready(&self, cx: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
+        self.service.poll_ready(cx)
+    }
+
+    fn call(&self, mut req: ServiceRequest) -> Self::Future {
+        if !self.rotation.previous.is_empty() {
+            self.rotation.rewrite(&mut req);
+        }
+        self.service.call(req)
+    }
+}
@@ -0,0 +1,515 @@
+//! Failed Authentication Lockout Middleware
+//!
+//! Tracks authentication attempts per username and client IP,
+//! delaying subsequent attempts exponentially and temporarily locking
+//! out clients that exceed the maximum number of failures. Attempts
+//! are counted before they are forwarded, so concurrent guesses share
+//! the same allowance, while credentials that already succeeded for
+//! the client are not held back.
+
+use std::{
+    collections::{BTreeMap, HashMap},
+    hash::{BuildHasher, RandomState},
+    net::IpAddr,
+    rc::Rc,
+    sync::{Arc, Mutex},
+    task::{Context, Poll},
+    time::{Duration, Instant},
+};
+
+use actix_web::{
+    Error, HttpResponse,
+    body::{EitherBody, MessageBody},
+    dev::{Service, ServiceRequest, ServiceResponse, Transform},
+    http::{StatusCode, header},
+};
+use base64::{Engine, prelude::BASE64_STANDARD};
+
+use super::LocalBoxFuture;
+use crate::config::IpMatch;
+
+/// Maximum number of tracked clients, least recently seen are evicted first.
+///
+/// Locked out clients are never evicted.
+const MAX_ENTRIES: usize = 4096;
+
+/// Maximum number of usernames tracked for a single client address.
+const MAX_ENTRIES_PER_IP: usize = 64;
+
+/// Longest username prefix (in bytes) used to track a client.
+const MAX_USERNAME: usize = 64;
+
+/// Lockout behavior settings.
+#[derive(Clone, Debug)]
+pub struct Settings {
+    /// Failures allowed before the client is locked out.
+    pub max_failures: u32,
+    /// Window in which failures are counted.
+    pub window: Duration,
+    /// Duration of lockout once max failures is exceeded.
+    pub lock_duration: Duration,
+    /// Initial delay applied after the first failure.
+    pub base_delay: Duration,
+    /// Maximum delay applied to any single attempt.
+    pub max_delay: Duration,
+    /// Client addresses exempt from tracking.
+    pub exempt: Vec<IpMatch>,
+}
+
+type Key = (String, IpAddr);
+
+#[derive(Debug)]
+struct Entry {
+    /// Failed and in-flight attempts within the window.
+    attempts: u32,
+    first_attempt: Instant,
+    locked_until: Option<Instant>,
+    /// Hash of the credentials that last succeeded.
+    verified: Option<u64>,
+    /// Position within the recently seen order.
+    seen: u64,
+}
+
+impl Entry {
+    /// Check if the client is currently locked out.
+    #[inline]
+    fn is_locked(&self, now: Instant) -> bool {
+        self.locked_until.is_some_and(|until| until > now)
+    }
+}
+
+/// Tracked clients ordered by when they were last seen.
+#[derive(Debug, Default)]
+struct Tracked {
+    entries: HashMap<Key, Entry>,
+    recent: BTreeMap<u64, Key>,
+    per_ip: HashMap<IpAddr, usize>,
+    tick: u64,
+}
+
+impl Tracked {
+    /// Entry of the client marked as most recently seen.
+    ///
+    /// The least recently seen unlocked clients are evicted to make room
+    /// for new ones. Returns `None` when every username tracked for the
+    /// client address is locked out.
+    fn touch(&mut self, key: &Key, now: Instant) -> Option<&mut Entry> {
+        self.tick += 1;
+        match self.entries.get(key) {
+            Some(entry) => {
+                self.recent.remove(&entry.seen);
+            }
+            None => {
+                let ip = key.1;
+                if self.per_ip.get(&ip).copied().unwrap_or_default() >= MAX_ENTRIES_PER_IP
+                    && !self.evict(now, |k| k.1 == ip)
+                {
+                    return None;
+                }
+                if self.entries.len() >= MAX_ENTRIES {
+                    // grows past the limit only while every client is locked
+                    self.evict(now, |_| true);
+                }
+                let entry = Entry {
+                    attempts: 0,
+                    first_attempt: now,
+                    locked_until: None,
+                    verified: None,
+                    seen: 0,
+                };
+                self.entries.insert(key.clone(), entry);
+                *self.per_ip.entry(ip).or_default() += 1;
+            }
+        }
+        self.recent.insert(self.tick, key.clone());
+        let entry = self.entries.get_mut(key).expect("entry was just inserted");
+        entry.seen = self.tick;
+        Some(entry)
+    }
+
+    /// Evict the least recently seen unlocked client matching the filter.
+    fn evict(&mut self, now: Instant, filter: impl Fn(&Key) -> bool) -> bool {
+        let evicted = self
+            .recent
+            .iter()
+            .find(|(_, key)| filter(key) && !self.entries[*key].is_locked(now))
+            .map(|(seen, _)| *seen);
+        let Some(key) = evicted.and_then(|seen| self.recent.remove(&seen)) else {
+            return false;
+        };
+        self.entries.remove(&key);
+        if let Some(count) = self.per_ip.get_mut(&key.1) {
+            *count -= 1;
+            if *count == 0 {
+                self.per_ip.remove(&key.1);
+            }
+        }
+        true
+    }
+}
+
+/// Outcome of counting an attempt.
+#[derive(Debug, PartialEq, Eq)]
+enum Attempt {
+    /// Attempt was counted and is forwarded after the delay.
+    Counted(Duration),
+    /// Credentials already succeeded for the client.
+    Verified,
+    /// Client is locked out or its allowance is used up by
+    /// attempts still in flight for the remaining duration.
+    Rejected(Duration),
+}
+
+/// Shared failure tracking state.
+#[derive(Clone, Default)]
+pub struct State(Arc<(Mutex<Tracked>, RandomState)>);
+
+impl std::fmt::Debug for State {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "State {{}}")
+    }
+}
+
+impl State {
+    #[inline]
+    fn tracked(&self) -> std::sync::MutexGuard<'_, Tracked> {
+        self.0.0.lock().expect("lockout state poisoned")
+    }
+
+    /// Hash of the credentials presented by the client.
+    #[inline]
+    fn credentials(&self, value: &[u8]) -> u64 {
+        self.0.1.hash_one(value)
+    }
+
+    /// Count an attempt before it is forwarded.
+    fn attempt(&self, key: &Key, credentials: u64, settings: &Settings) -> Attempt {
+        let now = Instant::now();
+        let mut tracked = self.tracked();
+        let Some(entry) = tracked.touch(key, now) else {
+            return Attempt::Rejected(settings.lock_duration);
+        };
+        if let Some(until) = entry.locked_until {
+            if until > now {
+                return Attempt::Rejected(until - now);
+            }
+            entry.locked_until = None;
+            entry.attempts = 0;
+            entry.first_attempt = now;
+        }
+        if entry.verified == Some(credentials) {
+            return Attempt::Verified;
+        }
+        if now.duration_since(entry.first_attempt) > settings.window {
+            entry.attempts = 0;
+            entry.first_attempt = now;
+        }
+        if entry.attempts >= settings.max_failures {
+            return Attempt::Rejected(Duration::from_secs(1));
+        }
+        entry.attempts += 1;
+        let delay = match entry.attempts - 1 {
+            0 => Duration::ZERO,
+            failures => settings.base_delay * 2u32.pow(failures.saturating_sub(1).min(16)),
+        };
+        Attempt::Counted(delay.min(settings.max_delay))
+    }
+
+    /// Remaining lock duration if the client is locked out.
+    fn locked(&self, key: &Key) -> Option<Duration> {
+        let now = Instant::now();
+        let tracked = self.tracked();
+        let until = tracked.entries.get(key)?.locked_until?;
+        (until > now).then(|| until - now)
+    }
+
+    /// Record failed attempt for the client.
+    ///
+    /// Attempts are already counted unless the credentials were verified.
+    fn failure(&self, key: &Key, counted: bool, settings: &Settings) {
+        let now = Instant::now();
+        let mut tracked = self.tracked();
+        let Some(entry) = tracked.touch(key, now) else {
+            return;
+        };
+        entry.verified = None;
+        if !counted {
+            entry.attempts += 1;
+        }
+        if entry.attempts >= settings.max_failures && entry.locked_until.is_none() {
+            log::warn!(
+                "locking out user {:?} from {} after {} failed attempts",
+                key.0,
+                key.1,
+                entry.attempts
+            );
+            entry.locked_until = Some(now + settings.lock_duration);
+        }
+    }
+
+    /// Clear tracked failures after a successful attempt.
+    fn success(&self, key: &Key, credentials: u64) {
+        let now = Instant::now();
+        let mut tracked = self.tracked();
+        let Some(entry) = tracked.touch(key, now) else {
+            return;
+        };
+        entry.attempts = 0;
+        entry.first_attempt = now;
+        entry.verified = Some(credentials);
+    }
+}
+
+/// Extract username from basic authorization header.
+///
+/// Long usernames are truncated to [`MAX_USERNAME`] bytes.
+fn basic_username(req: &ServiceRequest) -> Option<String> {
+    let value = req.headers().get(header::AUTHORIZATION)?.to_str().ok()?;
+    let (scheme, token) = value.split_once(' ')?;
+    if !scheme.eq_ignore_ascii_case("basic") {
+        return None;
+    }
+    let decoded = BASE64_STANDARD.decode(token.trim()).ok()?;
+    let decoded = String::from_utf8(decoded).ok()?;
+    let (user, _) = decoded.split_once(':')?;
+    let mut end = user.len().min(MAX_USERNAME);
+    while !user.is_char_boundary(end) {
+        end -= 1;
+    }
+    Some(user[..end].to_owned())
+}
+
+/// Failed authentication lockout middleware.
+pub struct Lockout {
+    settings: Rc<Settings>,
+    state: State,
+}
+
+impl Lockout {
+    /// Create new lockout middleware with settings and shared state.
+    pub fn new(settings: Settings, state: State) -> Self {
+        Self {
+            settings: Rc::new(settings),
+            state,
+        }
+    }
+}
+
+impl<S, B> Transform<S, ServiceRequest> for Lockout
+where
+    S: Service<ServiceRequest, Response = ServiceResponse<B>, Error = Error> + 'static,
+    B: MessageBody + 'static,
+{
+    type Response = ServiceResponse<EitherBody<B>>;
+    type Error = Error;
+    type Transform = LockoutService<S>;
+    type InitError = ();
+    type Future = std::future::Ready<Result<Self::Transform, Self::InitError>>;
+
+    fn new_transform(&self, service: S) -> Self::Future {
+        std::future::ready(Ok(LockoutService {
+            service: Rc::new(service),
+            settings: Rc::clone(&self.settings),
+            state: self.state.clone(),
+        }))
+    }
+}
+
+/// Service produced by [`Lockout`] middleware.
+pub struct LockoutService<S> {
+    service: Rc<S>,
+    settings: Rc<Settings>,
+    state: State,
+}
+
+impl<S, B> Service<ServiceRequest> for LockoutService<S>
+where
+    S: Service<ServiceRequest, Response = ServiceResponse<B>, Error = Error> + 'static,
+    B: MessageBody + 'static,
+{
+    type Response = ServiceResponse<EitherBody<B>>;
+    type Error = Error;
+    type Future = LocalBoxFuture<Result<Self::Response, Self::Error>>;
+
+    actix_web::dev::forward_ready!(service);
+
+    fn call(&self, req: ServiceRequest) -> Self::Future {
+        let service = Rc::clone(&self.service);
+        let settings = Rc::clone(&self.settings);
+        let state = self.state.clone();
+        Box::pin(async move {
+            let ip = req.peer_addr().map(|addr| addr.ip());
+            let key = match (basic_username(&req), ip) {
+                (Some(user), Some(ip)) if !settings.exempt.iter().any(|m| m.contains(&ip)) => {
+                    (user, ip)
+                }
+                _ => return Ok(service.call(req).await?.map_into_left_body()),
+            };
+
+            let credentials = req
+                .headers()
+                .get(header::AUTHORIZATION)
+                .map(|value| state.credentials(value.as_bytes()))
+                .unwrap_or_default();
+            let counted = match state.attempt(&key, credentials, &settings) {
+                Attempt::Rejected(remaining) => return Ok(too_many(req, remaining)),
+                Attempt::Verified => false,
+                Attempt::Counted(delay) => {
+                    if !delay.is_zero() {
+                        actix_web::rt::time::sleep(delay).await;
+                        // failures completed during the delay may have locked the client
+                        if let Some(remaining) = state.locked(&key) {
+                            return Ok(too_many(req, remaining));
+                        }
+                    }
+                    true
+                }
+            };
+
+            // other responses leave the attempt counted until the window passes
+            let res = service.call(req).await?;
+            let status = res.status();
+            if status == StatusCode::UNAUTHORIZED {
+                state.failure(&key, counted, &settings);
+            } else if status.is_success() || status.is_redirection() {
+                state.success(&key, credentials);
+            }
+            Ok(res.map_into_left_body())
+        })
+    }
+}
+
+/// Reject an attempt from a locked out client.
+fn too_many<B>(req: ServiceRequest, remaining: Duration) -> ServiceResponse<EitherBody<B>> {
+    let response = HttpResponse::build(StatusCode::TOO_MANY_REQUESTS)
+        .insert_header((header::RETRY_AFTER, remaining.as_secs().max(1)))
+        .finish();
+    req.into_response(response).map_into_right_body()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn settings() -> Settings {
+        Settings {
+            max_failures: 3,
+            window: Duration::from_secs(60),
+            lock_duration: Duration::from_secs(60),
+            base_delay: Duration::from_millis(100),
+            max_delay: Duration::from_secs(1),
+            exempt: vec![],
+        }
+    }
+
+    fn key(user: &str) -> Key {
+        (user.to_owned(), "127.0.0.1".parse().unwrap())
+    }
+
+    #[test]
+    fn concurrent_attempts_share_allowance() {
+        let (state, settings, key) = (State::default(), settings(), key("admin"));
+        let outcomes: Vec<_> = (0..5).map(|n| state.attempt(&key, n, &settings)).collect();
+        assert_eq!(outcomes[0], Attempt::Counted(Duration::ZERO));
+        assert_eq!(outcomes[1], Attempt::Counted(Duration::from_millis(100)));
+        assert_eq!(outcomes[2], Attempt::Counted(Duration::from_millis(200)));
+        assert!(matches!(outcomes[3], Attempt::Rejected(_)));
+        assert!(matches!(outcomes[4], Attempt::Rejected(_)));
+    }
+
+    #[test]
+    fn failures_lock_client() {
+        let (state, settings, key) = (State::default(), settings(), key("admin"));
+        for n in 0..3 {
+            assert!(matches!(
+                state.attempt(&key, n, &settings),
+                Attempt::Counted(_)
+            ));
+            state.failure(&key, true, &settings);
+        }
+        assert!(state.locked(&key).is_some());
+        assert!(matches!(
+            state.attempt(&key, 9, &settings),
+            Attempt::Rejected(_)
+        ));
+    }
+
+    #[test]
+    fn verified_credentials_skip_allowance() {
+        let (state, settings, key) = (State::default(), settings(), key("admin"));
+        assert!(matches!(
+            state.attempt(&key, 1, &settings),
+            Attempt::Counted(_)
+        ));
+        state.success(&key, 1);
+        for _ in 0..10 {
+            assert_eq!(state.attempt(&key, 1, &settings), Attempt::Verified);
+        }
+        assert!(matches!(
+            state.attempt(&key, 2, &settings),
+            Attempt::Counted(_)
+        ));
+        state.failure(&key, true, &settings);
+        assert!(matches!(
+            state.attempt(&key, 1, &settings),
+            Attempt::Counted(_)
+        ));
+    }
+
+    #[test]
+    fn lock_survives_username_flood() {
+        let (state, settings) = (State::default(), settings());
+        let locked = key("admin");
+        for n in 0..3 {
+            state.attempt(&locked, n, &settings);
+            state.failure(&locked, true, &settings);
+        }
+        for n in 0..MAX_ENTRIES {
+            state.attempt(&key(&format!("user{n}")), 0, &settings);
+        }
+        assert!(state.locked(&locked).is_some());
+        assert!(matches!(
+            state.attempt(&locked, 9, &settings),
+            Attempt::Rejected(_)
+        ));
+        let tracked = state.tracked();
+        assert_eq!(tracked.per_ip[&locked.1], MAX_ENTRIES_PER_IP);
+        assert_eq!(tracked.entries.len(), MAX_ENTRIES_PER_IP);
+    }
+
+    #[test]
+    fn locked_usernames_fill_client_allowance() {
+        let (state, settings) = (State::default(), settings());
+        for n in 0..MAX_ENTRIES_PER_IP {
+            let user = key(&format!("user{n}"));
+            for _ in 0..3 {
+                state.attempt(&user, 0, &settings);
+                state.failure(&user, true, &settings);
+            }
+        }
+        assert!(matches!(
+            state.attempt(&key("admin"), 0, &settings),
+            Attempt::Rejected(_)
+        ));
+        assert!(state.locked(&key("user0")).is_some());
+    }
+
+    #[test]
+    fn tracked_clients_are_bounded() {
+        let (state, settings) = (State::default(), settings());
+        let locked = key("admin");
+        for n in 0..3 {
+            state.attempt(&locked, n, &settings);
+            state.failure(&locked, true, &settings);
+        }
+        for n in 0..MAX_ENTRIES as u32 {
+            let ip = IpAddr::from(std::net::Ipv4Addr::from(0x0a00_0000 + n));
+            state.attempt(&("user".to_owned(), ip), 0, &settings);
+        }
+        assert!(state.locked(&locked).is_some());
+        let tracked = state.tracked();
+        assert_eq!(tracked.entries.len(), MAX_ENTRIES);
+        assert_eq!(tracked.recent.len(), MAX_ENTRIES);
+        assert_eq!(tracked.per_ip.len(), MAX_ENTRIES);
+    }
+}
@@ -0,0 +1,141 @@
+//! Access Log Filtering
+//!
+//! Wraps the access log middleware so requests to noisy paths and
+//! directives with logging disabled are passed directly to the wrapped
+//! service without being logged.
+
+use std::{
+    rc::Rc,
+    task::{Context, Poll},
+};
+
+use actix_web::{
+    Error,
+    body::{EitherBody, MessageBody},
+    dev::{Service, ServiceRequest, ServiceResponse, Transform},
+};
+
+use super::{LocalBoxFuture, RcService};
+use crate::config::PathMatch;
+
+/// Access log filter settings.
+#[derive(Clone, Debug)]
+pub struct Settings {
+    /// Request path globs never logged.
+    pub skip_paths: Vec<PathMatch>,
+    /// Directive locations and whether their requests are logged.
+    pub locations: Vec<(String, bool)>,
+}
+
+impl Settings {
+    /// Check if filtering is required for these settings.
+    pub fn is_active(&self) -> bool {
+        !self.skip_paths.is_empty() || self.locations.iter().any(|(_, log)| !log)
+    }
+
+    /// Check if requests to the path are never logged.
+    fn skipped(&self, path: &str) -> bool {
+        if self.skip_paths.iter().any(|p| p.matches(path)) {
+            return true;
+        }
+        // the most specific directive location decides
+        self.locations
+            .iter()
+            .filter(|(location, _)| within(location, path))
+            .max_by_key(|(location, _)| location.len())
+            .is_some_and(|(_, log)| !log)
+    }
+}
+
+/// Check if the path is within the directive location.
+fn within(location: &str, path: &str) -> bool {
+    let location = location.trim_end_matches('/');
+    match path.strip_prefix(location) {
+        Some(rest) => rest.is_empty() || rest.starts_with('/'),
+        None => false,
+    }
+}
+
+/// Middleware filtering requests written by the inner access logger.
+pub struct LogFilter<T> {
+    inner: T,
+    settings: Rc<Settings>,
+}
+
+impl<T> LogFilter<T> {
+    /// Wrap inner access log middleware with filter settings.
+    pub fn new(inner: T, settings: Settings) -> Self {
+        Self {
+            inner,
+            settings: Rc::new(settings),
+        }
+    }
+}
+
+impl<S, B, T, B2> Transform<S, ServiceRequest> for LogFilter<T>
+where
+    S: Service<ServiceRequest, Response = ServiceResponse<B>, Error = Error> + 'static,
+    B: MessageBody + 'static,
+    T: Transform<
+            RcService<S>,
+            ServiceRequest,
+            Response = ServiceResponse<B2>,
+            Error = Error,
+            InitError = (),
+        >,
+    T::Future: 'static,
+    T::Transform: 'static,
+    B2: MessageBody + 'static,
+{
+    type Response = ServiceResponse<EitherBody<B2, B>>;
+    type Error = Error;
+    type Transform = LogFilterService<S, T::Transform>;
+    type InitError = ();
+    type Future = LocalBoxFuture<Result<Self::Transform, Self::InitError>>;
+
+    fn new_transform(&self, service: S) -> Self::Future {
+        let service = Rc::new(service);
+        let settings = Rc::clone(&self.settings);
+        let inner = self.inner.new_transform(RcService(Rc::clone(&service)));
+        Box::pin(async move {
+            Ok(LogFilterService {
+                service,
+                inner: Rc::new(inner.await?),
+                settings,
+            })
+        })
+    }
+}
+
+/// Service produced by [`LogFilter`] middleware.
+pub struct LogFilterService<S, T> {
+    service: Rc<S>,
+    inner: Rc<T>,
+    settings: Rc<Settings>,
+}
+
+impl<S, B, T, B2> Service<ServiceRequest> for LogFilterService<S, T>
+where
+    S: Service<ServiceRequest, Response = ServiceResponse<B>, Error = Error> + 'static,
+    B: MessageBody + 'static,
+    T: Service<ServiceRequest, Response = ServiceResponse<B2>, Error = Error> + 'static,
+    B2: MessageBody + 'static,
+{
+    type Response = ServiceResponse<EitherBody<B2, B>>;
+    type Error = Error;
+    type Future = LocalBoxFuture<Result<Self::Response, Self::Error>>;
+
+    #[inline]
+    fn poll_ready(&self, cx: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
+        self.inner.poll_ready(cx)
+    }
+
+    fn call(&self, req: ServiceRequest) -> Self::Future {
+        let service = Rc::clone(&self.service);
+        if self.settings.skipped(req.path()) {
+            return Box::pin(async move { Ok(service.call(req).await?.map_into_right_body()) });
+        }
+        let inner = Rc::clone(&self.inner);
+        Box::pin(async move { Ok(inner.call(req).await?.map_into_left_body()) })
+    }
+}
@@ -0,0 +1,147 @@
+//! Traffic Mirroring Middleware
+//!
+//! Duplicates a share of proxied requests to a shadow upstream in the
+//! background. Mirrored responses are discarded and mirror failures never
+//! affect the client, allowing new backend versions to be tested against
+//! live traffic. Request bodies are buffered so they can be sent to both
+//! upstreams, limiting mirroring to requests with a replayable body.
+
+use std::{
+    cell::Cell,
+    rc::Rc,
+    time::{Duration, Instant},
+};
+
+use actix_web::{
+    Error,
+    body::MessageBody,
+    dev::{Payload, Service, ServiceRequest, ServiceResponse, Transform},
+};
+
+use super::{
+    LocalBoxFuture,
+    retry::{buffer, replayable},
+};
+use crate::metrics;
+
+/// Traffic mirroring settings.
+#[derive(Clone, Debug)]
+pub struct Settings {
+    /// Shadow upstream base url.
+    pub upstream: String,
+    /// Share of requests mirrored in hundredths of a percent.
+    pub basis_points: u32,
+    /// Mirrored request timeout.
+    pub timeout: Duration,
+}
+
+/// Traffic mirroring middleware.
+#[derive(Clone)]
+pub struct Mirror {
+    settings: Rc<Settings>,
+    client: awc::Client,
+}
+
+impl Mirror {
+    /// Create new traffic mirroring middleware from settings.
+    pub fn new(settings: Settings, client: awc::Client) -> Self {
+        Self {
+            settings: Rc::new(settings),
+            client,
+        }
+    }
+}
+
+impl<S, B> Transform<S, ServiceRequest> for Mirror
+where
+    S: Service<ServiceRequest, Response = ServiceResponse<B>, Error = Error> + 'static,
+    B: MessageBody + 'static,
+{
+    type Response = ServiceResponse<B>;
+    type Error = Error;
+    type Transform = MirrorService<S>;
+    type InitError = ();
+    type Future = std::future::Ready<Result<Self::Transform, Self::InitError>>;
+
+    fn new_transform(&self, service: S) -> Self::Future {
+        std::future::ready(Ok(MirrorService {
+            service: Rc::new(service),
+            settings: Rc::clone(&self.settings),
+            client: self.client.clone(),
+            count: Default::default(),
+        }))
+    }
+}
+
+/// Service produced by [`Mirror`] middleware.
+pub struct MirrorService<S> {
+    service: Rc<S>,
+    settings: Rc<Settings>,
+    client: awc::Client,
+    count: Rc<Cell<u64>>,
+}
+
+impl<S> MirrorService<S> {
+    /// Check if the next request is sampled for mirroring.
+    ///
+    /// Spreads mirrored requests evenly rather than randomly so the
+    /// configured share is exact over any window of requests.
+    fn sample(&self) -> bool {
+        let n = self.count.get();
+        self.count.set(n.wrapping_add(1));
+        let share = self.settings.basis_points as u64;
+        (n + 1) * share / 10_000 > n * share / 10_000
+    }
+}
+
+impl<S, B> Service<ServiceRequest> for MirrorService<S>
+where
+    S: Service<ServiceRequest, Response = ServiceResponse<B>, Error = Error> + 'static,
+    B: MessageBody + 'static,
+{
+    type Response = ServiceResponse<B>;
+    type Error = Error;
+    type Future = LocalBoxFuture<Result<Self::Response, Self::Error>>;
+
+    actix_web::dev::forward_ready!(service);
+
+    fn call(&self, mut req: ServiceRequest) -> Self::Future {
+        let service = Rc::clone(&self.service);
+        if !self.sample() || !replayable(req.headers()) {
+            return Box::pin(async move { service.call(req).await });
+        }
+        let settings = Rc::clone(&self.settings);
+        let client = self.client.clone();
+        Box::pin(async move {
+            let body = buffer(req.take_payload()).await?;
+            req.set_payload(Payload::from(body.clone()));
+
+            let path = req.match_info().unprocessed();
+            let url = match req.query_string() {
+                "" => format!("{}{path}", settings.upstream.trim_end_matches('/')),
+                query => format!("{}{path}?{query}", settings.upstream.trim_end_matches('/')),
+            };
+            let mut request = client
+                .request_from(url.as_str(), req.head())
+                .timeout(settings.timeout)
+                .no_decompress();
+            if let Some(peer) = req.peer_addr() {
+                request = request.insert_header(("x-forwarded-for", peer.ip().to_string()));
+            }
+            actix_web::rt::spawn(async move {
+                let start = Instant::now();
+                let outcome = match request.send_body(body).await {
+                    Ok(res) => res.status().as_u16().to_string(),
+                    Err(err) => {
+                        log::debug!("mirror request to {url:?} failed: {err}");
+                        "error".to_owned()
+                    }
+                };
+                log::trace!("mirrored {url:?} ({outcome}) in {:?}", start.elapsed());
+                metrics::counter(&format!("bob_mirror_requests_total{{outcome={outcome:?}}}"))
+                    .inc();
+            });
+            service.call(req).await
+        })
+    }
+}
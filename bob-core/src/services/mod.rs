@@ -0,0 +1,175 @@
+//! Builtin Service Implementations
+//!
+//! Request modules and middleware implemented directly within bob
+//! rather than provided by an external actix service crate.
+
+use std::{
+    future::Future,
+    net::IpAddr,
+    pin::Pin,
+    rc::Rc,
+    task::{Context, Poll},
+};
+
+use actix_web::dev::Service;
+
+pub mod access_log;
+pub mod anonymize;
+#[cfg(feature = "ipban")]
+pub mod ban;
+pub mod body_limit;
+pub mod body_metrics;
+pub mod budget;
+pub mod bypass;
+pub mod canonical;
+#[cfg(feature = "captcha")]
+pub mod captcha;
+pub mod catch_panic;
+#[cfg(any(
+    feature = "dynamic",
+    feature = "cgi",
+    feature = "git",
+    feature = "exec"
+))]
+pub mod cgi;
+#[cfg(feature = "cgi")]
+pub mod cgi_bin;
+#[cfg(feature = "cors")]
+pub mod cors;
+pub mod crawlers;
+#[cfg(feature = "modsecurity")]
+pub mod crs;
+pub mod deadline;
+#[cfg(feature = "compress")]
+pub mod dictionary;
+pub mod drain;
+#[cfg(feature = "dynamic")]
+pub mod dynamic;
+#[cfg(feature = "exec")]
+pub mod exec;
+pub mod flags;
+#[cfg(feature = "rproxy")]
+pub mod forward;
+#[cfg(feature = "geoip")]
+pub mod geoip;
+#[cfg(feature = "git")]
+pub mod git;
+#[cfg(feature = "rproxy")]
+pub mod h2c;
+#[cfg(feature = "honeypot")]
+pub mod honeypot;
+#[cfg(any(feature = "rproxy", feature = "dynamic"))]
+pub mod hop_by_hop;
+#[cfg(feature = "fileserver")]
+pub mod integrity;
+#[cfg(feature = "rproxy")]
+pub mod intercept;
+#[cfg(feature = "ipfilter")]
+pub mod ipfilter;
+#[cfg(feature = "authn")]
+pub mod key_rotation;
+#[cfg(feature = "authn")]
+pub mod lockout;
+pub mod log_filter;
+#[cfg(feature = "rproxy")]
+pub mod mirror;
+#[cfg(feature = "modsecurity")]
+pub mod modsecurity;
+#[cfg(feature = "openapi")]
+pub mod openapi;
+#[cfg(any(feature = "rproxy", feature = "git"))]
+pub mod passwd;
+#[cfg(feature = "rproxy")]
+pub mod pool;
+#[cfg(feature = "rproxy")]
+pub mod proxy_buffer;
+#[cfg(feature = "rproxy")]
+pub mod proxy_cookie;
+#[cfg(feature = "rproxy")]
+pub mod proxy_path;
+#[cfg(feature = "rproxy")]
+pub mod proxy_redirect;
+pub mod reaper;
+#[cfg(feature = "rebinding")]
+pub mod rebinding;
+#[cfg(feature = "authn")]
+pub mod reload;
+#[cfg(feature = "requestid")]
+pub mod request_id;
+#[cfg(feature = "rproxy")]
+pub mod resolver;
+#[cfg(feature = "rproxy")]
+pub mod retry;
+#[cfg(feature = "script")]
+pub mod script;
+pub mod slow_client;
+#[cfg(any(feature = "modsecurity", feature = "rproxy"))]
+pub mod spool;
+#[cfg(feature = "tarpit")]
+pub mod tarpit;
+pub mod trace;
+#[cfg(feature = "transform")]
+pub mod transform;
+#[cfg(feature = "rproxy")]
+pub mod unix;
+#[cfg(feature = "useragent")]
+pub mod useragent;
+#[cfg(feature = "wasm")]
+pub mod wasm;
+
+/// Boxed non-send future used by service implementations.
+pub type LocalBoxFuture<T> = Pin<Box<dyn Future<Output = T>>>;
+
+/// Reference counted service allowing a single service
+/// to be shared between multiple middleware instances.
+pub struct RcService<S>(pub Rc<S>);
+
+impl<S> Clone for RcService<S> {
+    #[inline]
+    fn clone(&self) -> Self {
+        Self(Rc::clone(&self.0))
+    }
+}
+
+impl<S, Req> Service<Req> for RcService<S>
+where
+    S: Service<Req>,
+{
+    type Response = S::Response;
+    type Error = S::Error;
+    type Future = S::Future;
+
+    #[inline]
+    fn poll_ready(&self, cx: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
+        self.0.poll_ready(cx)
+    }
+
+    #[inline]
+    fn call(&self, req: Req) -> Self::Future {
+        self.0.call(req)
+    }
+}
+
+/// Check if address belongs to a private, loopback or link-local range.
+pub fn is_private(ip: &IpAddr) -> bool {
+    match ip {
+        IpAddr::V4(ip) => {
+            let [a, b, ..] = ip.octets();
+            ip.is_private()
+                || ip.is_loopback()
+                || ip.is_link_local()
+                || ip.is_unspecified()
+                || (a == 100 && (64..128).contains(&b))
+        }
+        IpAddr::V6(ip) => {
+            if let Some(ip) = ip.to_ipv4_mapped() {
+                return is_private(&IpAddr::V4(ip));
+            }
+            let first = ip.segments()[0];
+            ip.is_loopback()
+                || ip.is_unspecified()
+                || (first & 0xfe00) == 0xfc00
+                || (first & 0xffc0) == 0xfe80
+        }
+    }
+}
@@ -0,0 +1,349 @@
+//! ModSecurity Web Application Firewall Middleware
+//!
+//! Runs every request and response through a libmodsecurity transaction.
+//! Bodies are fed to the transaction chunk by chunk as they stream in and
+//! are retained in a [`Spool`] for replay, spilling to temporary files
+//! once they exceed the in-memory limit. Bodies larger than the inspection
+//! limit are either rejected or only inspected up to the limit.
+
+use std::{pin::Pin, rc::Rc, sync::Arc};
+
+use ::modsecurity::{Intervention, ModSecurity, Rules, Transaction};
+use actix_web::{
+    Error, HttpResponse,
+    body::{BodyStream, BoxBody, MessageBody, SizedStream},
+    dev::{Payload, Service, ServiceRequest, ServiceResponse, Transform},
+    error::ErrorInternalServerError,
+    http::{StatusCode, Version, header},
+    web::Bytes,
+};
+use futures_core::Stream;
+
+use super::{
+    LocalBoxFuture,
+    crs::Crs,
+    spool::{self, BodyChunks, Chain, Spool},
+};
+
+/// Action taken for bodies exceeding the inspection limit.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum LimitAction {
+    /// Inspect the body up to the limit and pass the remainder through.
+    #[default]
+    Skip,
+    /// Reject the message entirely.
+    Reject,
+}
+
+/// Default maximum request body bytes inspected.
+pub const DEFAULT_MAX_REQUEST_BODY: usize = 13107200;
+/// Default maximum response body bytes inspected.
+pub const DEFAULT_MAX_RESPONSE_BODY: usize = 524288;
+
+/// Rules parsed once and shared between every worker.
+pub struct SharedRules(Rules);
+
+// SAFETY: libmodsecurity never modifies a parsed ruleset once loaded and
+// supports sharing it between transactions running on any thread.
+unsafe impl Send for SharedRules {}
+unsafe impl Sync for SharedRules {}
+
+/// ModSecurity middleware settings.
+pub struct Settings {
+    /// Parsed core rule set and plaintext ruleset.
+    pub rules: Arc<SharedRules>,
+    /// Maximum request body bytes inspected.
+    pub max_request_body_size: usize,
+    /// Maximum response body bytes inspected.
+    pub max_response_body_size: usize,
+    /// Action taken for bodies exceeding the inspection limit.
+    pub limit_action: LimitAction,
+    /// Inspect response bodies.
+    pub inspect_response: bool,
+    /// Body buffering settings.
+    pub spool: spool::Settings,
+}
+
+/// Per-worker libmodsecurity engine using the shared rules.
+struct Engine {
+    modsec: ModSecurity,
+    settings: Rc<Settings>,
+}
+
+impl Engine {
+    /// Start a new transaction for a single request.
+    fn transaction(&self) -> Result<Transaction<'_>, Error> {
+        self.modsec
+            .transaction_builder()
+            .with_rules(&self.settings.rules.0)
+            .build()
+            .map_err(|err| internal("failed to start transaction", err))
+    }
+}
+
+/// Log failed modsecurity operations and convert them into an error.
+fn internal(msg: &str, err: impl std::fmt::Debug) -> Error {
+    log::error!("modsecurity {msg}: {err:?}");
+    ErrorInternalServerError("modsecurity failure")
+}
+
+/// Build the response for a transaction intervention.
+fn intervention(intervention: Intervention) -> HttpResponse {
+    if let Some(log) = intervention.log() {
+        log::warn!("modsecurity intervention: {log}");
+    }
+    if let Some(url) = intervention.url() {
+        return HttpResponse::Found()
+            .insert_header((header::LOCATION, url))
+            .finish();
+    }
+    let status = u16::try_from(intervention.status())
+        .ok()
+        .and_then(|code| StatusCode::from_u16(code).ok())
+        .filter(|status| !status.is_success())
+        .unwrap_or(StatusCode::FORBIDDEN);
+    HttpResponse::new(status)
+}
+
+/// Check transaction for a disruptive intervention.
+#[inline]
+fn check(tx: &mut Transaction<'_>) -> Option<HttpResponse> {
+    tx.intervention()
+        .filter(|i| i.disruptive())
+        .map(intervention)
+}
+
+#[inline]
+fn protocol(version: Version) -> &'static str {
+    match version {
+        Version::HTTP_09 => "0.9",
+        Version::HTTP_10 => "1.0",
+        Version::HTTP_2 => "2.0",
+        Version::HTTP_3 => "3.0",
+        _ => "1.1",
+    }
+}
+
+/// Result of feeding a body to the transaction.
+enum Inspected<S> {
+    /// Body was inspected and is replayed along with any uninspected rest.
+    Replay(Chain<S>),
+    /// Body exceeded the inspection limit and is rejected.
+    TooLarge,
+}
+
+/// Feed a body stream to the transaction while spooling it for replay.
+async fn inspect<S, E>(
+    mut body: S,
+    limit: usize,
+    settings: &Settings,
+    mut append: impl FnMut(&[u8]) -> Result<(), Error>,
+) -> Result<Inspected<S>, Error>
+where
+    S: Stream<Item = Result<Bytes, E>> + Unpin,
+    E: Into<Error>,
+{
+    let mut spool = Spool::new(settings.spool.clone());
+    let mut rest = None;
+    while let Some(chunk) = std::future::poll_fn(|cx| Pin::new(&mut body).poll_next(cx)).await {
+        let chunk = chunk.map_err(Into::into)?;
+        let allowed = limit.saturating_sub(spool.size());
+        if chunk.len() > allowed {
+            if settings.limit_action == LimitAction::Reject {
+                return Ok(Inspected::TooLarge);
+            }
+            append(&chunk[..allowed])?;
+            log::debug!("modsecurity inspection limit reached. skipping remaining body");
+            spool
+                .push(chunk)
+                .await
+                .map_err(|err| internal("spool failed", err))?;
+            rest = Some(body);
+            break;
+        }
+        append(&chunk)?;
+        spool
+            .push(chunk)
+            .await
+            .map_err(|err| internal("spool failed", err))?;
+    }
+    let replay = spool
+        .finish()
+        .await
+        .map_err(|err| internal("spool failed", err))?;
+    Ok(Inspected::Replay(Chain::new(replay, rest)))
+}
+
+/// Parse the core rule set followed by the plaintext ruleset.
+pub fn load_rules(
+    crs: Option<&Crs>,
+    plain: &str,
+) -> Result<SharedRules, ::modsecurity::ModSecurityError> {
+    let mut rules = Rules::new();
+    if let Some(crs) = crs {
+        rules.add_plain(&crs.setup)?;
+        for file in crs.rules.iter() {
+            rules.add_file(file)?;
+        }
+    }
+    rules.add_plain(plain)?;
+    Ok(SharedRules(rules))
+}
+
+/// ModSecurity middleware.
+#[derive(Clone)]
+pub struct Modsecurity(Rc<Settings>);
+
+impl Modsecurity {
+    /// Create new modsecurity middleware from settings.
+    pub fn new(settings: Settings) -> Self {
+        Self(Rc::new(settings))
+    }
+}
+
+impl<S, B> Transform<S, ServiceRequest> for Modsecurity
+where
+    S: Service<ServiceRequest, Response = ServiceResponse<B>, Error = Error> + 'static,
+    B: MessageBody + 'static,
+{
+    type Response = ServiceResponse<BoxBody>;
+    type Error = Error;
+    type Transform = ModsecurityService<S>;
+    type InitError = ();
+    type Future = std::future::Ready<Result<Self::Transform, Self::InitError>>;
+
+    fn new_transform(&self, service: S) -> Self::Future {
+        std::future::ready(Ok(ModsecurityService {
+            service: Rc::new(service),
+            engine: Rc::new(Engine {
+                modsec: ModSecurity::default(),
+                settings: Rc::clone(&self.0),
+            }),
+        }))
+    }
+}
+
+/// Service produced by [`Modsecurity`] middleware.
+pub struct ModsecurityService<S> {
+    service: Rc<S>,
+    engine: Rc<Engine>,
+}
+
+impl<S, B> Service<ServiceRequest> for ModsecurityService<S>
+where
+    S: Service<ServiceRequest, Response = ServiceResponse<B>, Error = Error> + 'static,
+    B: MessageBody + 'static,
+{
+    type Response = ServiceResponse<BoxBody>;
+    type Error = Error;
+    type Future = LocalBoxFuture<Result<Self::Response, Self::Error>>;
+
+    actix_web::dev::forward_ready!(service);
+
+    fn call(&self, mut req: ServiceRequest) -> Self::Future {
+        let service = Rc::clone(&self.service);
+        let engine = Rc::clone(&self.engine);
+        Box::pin(async move {
+            let settings = &engine.settings;
+            let mut tx = engine.transaction()?;
+            let version = protocol(req.version());
+
+            // request line and headers
+            let peer = req.peer_addr();
+            let local = req.app_config().local_addr();
+            tx.process_connection(
+                &peer.map(|p| p.ip().to_string()).unwrap_or_default(),
+                peer.map(|p| p.port() as i32).unwrap_or_default(),
+                &local.ip().to_string(),
+                local.port() as i32,
+            )
+            .map_err(|err| internal("connection processing failed", err))?;
+            tx.process_uri(&req.uri().to_string(), req.method().as_str(), version)
+                .map_err(|err| internal("uri processing failed", err))?;
+            for (name, value) in req.headers() {
+                let value = String::from_utf8_lossy(value.as_bytes());
+                tx.add_request_header(name.as_str(), &value)
+                    .map_err(|err| internal("request header processing failed", err))?;
+            }
+            tx.process_request_headers()
+                .map_err(|err| internal("request header processing failed", err))?;
+            if let Some(res) = check(&mut tx) {
+                return Ok(req.into_response(res));
+            }
+
+            // request body
+            let payload = req.take_payload();
+            let limit = settings.max_request_body_size;
+            let inspected = inspect(payload, limit, settings, |chunk| {
+                tx.append_request_body(chunk)
+                    .map_err(|err| internal("request body processing failed", err))
+            })
+            .await?;
+            let replay = match inspected {
+                Inspected::Replay(replay) => replay,
+                Inspected::TooLarge => {
+                    log::warn!("modsecurity request body exceeds inspection limit");
+                    let res = HttpResponse::PayloadTooLarge().finish();
+                    return Ok(req.into_response(res));
+                }
+            };
+            tx.process_request_body()
+                .map_err(|err| internal("request body processing failed", err))?;
+            if let Some(res) = check(&mut tx) {
+                return Ok(req.into_response(res));
+            }
+            req.set_payload(Payload::Stream {
+                payload: Box::pin(replay),
+            });
+
+            // response headers
+            let res = service.call(req).await?;
+            let status = res.status();
+            for (name, value) in res.headers() {
+                let value = String::from_utf8_lossy(value.as_bytes());
+                tx.add_response_header(name.as_str(), &value)
+                    .map_err(|err| internal("response header processing failed", err))?;
+            }
+            tx.process_response_headers(status.as_u16() as i32, &format!("HTTP {version}"))
+                .map_err(|err| internal("response header processing failed", err))?;
+            if let Some(block) = check(&mut tx) {
+                let _ = tx.process_logging();
+                return Ok(res.into_response(block));
+            }
+            if !settings.inspect_response {
+                let _ = tx.process_logging();
+                return Ok(res.map_into_boxed_body());
+            }
+
+            // response body
+            let (request, response) = res.into_parts();
+            let (response, body) = response.into_parts();
+            let limit = settings.max_response_body_size;
+            let inspected = inspect(BodyChunks(Box::pin(body)), limit, settings, |chunk| {
+                tx.append_response_body(chunk)
+                    .map_err(|err| internal("response body processing failed", err))
+            })
+            .await?;
+            let replay = match inspected {
+                Inspected::Replay(replay) => replay,
+                Inspected::TooLarge => {
+                    log::warn!("modsecurity response body exceeds inspection limit");
+                    let res = HttpResponse::BadGateway().finish();
+                    return Ok(ServiceResponse::new(request, res));
+                }
+            };
+            tx.process_response_body()
+                .map_err(|err| internal("response body processing failed", err))?;
+            let block = check(&mut tx);
+            let _ = tx.process_logging();
+            let res = match block {
+                Some(block) => block,
+                None => match replay.size() {
+                    Some(size) => response.set_body(SizedStream::new(size, replay).boxed()),
+                    None => response.set_body(BodyStream::new(replay).boxed()),
+                },
+            };
+            Ok(ServiceResponse::new(request, res))
+        })
+    }
+}
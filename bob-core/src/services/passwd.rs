@@ -0,0 +1,34 @@
+//! Password Hash Verification
+//!
+//! Checks `Basic` credentials against htpasswd compatible crypt(3)
+//! hashes, the same formats accepted by the basic auth middleware.
+//! Comparisons are constant-time and hashing runs on the blocking pool.
+
+use actix_web::http::header::HeaderValue;
+use base64::{Engine, prelude::BASE64_STANDARD};
+
+/// Hash prefixes of the supported crypt(3) formats.
+const PREFIXES: &[&str] = &["$2a$", "$2b$", "$2y$", "$6$", "$5$", "$1$"];
+
+/// Check if the hash uses a supported format.
+///
+/// Generate hashes with `htpasswd -nB user` or `mkpasswd -m sha-512`.
+pub fn supported(hash: &str) -> bool {
+    PREFIXES.iter().any(|prefix| hash.starts_with(prefix))
+}
+
+/// Decode username and password from a `Basic` authorization value.
+pub fn basic(value: &HeaderValue) -> Option<(String, String)> {
+    let encoded = value.to_str().ok()?.strip_prefix("Basic ")?;
+    let decoded = BASE64_STANDARD.decode(encoded.trim()).ok()?;
+    let decoded = String::from_utf8(decoded).ok()?;
+    let (user, password) = decoded.split_once(':')?;
+    Some((user.to_owned(), password.to_owned()))
+}
+
+/// Verify the password against the hash.
+pub async fn verify(password: String, hash: String) -> bool {
+    actix_web::web::block(move || crypt3_rs::unix::verify(password, &hash))
+        .await
+        .unwrap_or(false)
+}
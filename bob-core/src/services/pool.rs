@@ -0,0 +1,719 @@
+//! Sticky Upstream Pool Service
+//!
+//! Balances new sessions across a pool of upstreams in weighted
+//! round-robin order and pins each client to its upstream using an
+//! affinity cookie. Requests matching a header or cookie override are
+//! always routed to the override's upstream, enabling canary rollouts.
+//!
+//! Upstreams marked for discovery have their hostname periodically
+//! re-resolved, respecting the DNS TTL, by a background thread and every
+//! resolved address becomes a separate pool member. Members keep the
+//! upstream url, so the hostname is still used for the `Host` header, SNI
+//! and certificate verification, and only connect to their address. The
+//! thread exits once no balancer built with discovery remains, such as
+//! after a configuration reload disabling it.
+//!
+//! Upstreams removed from a pool, either by a configuration reload or a
+//! drain request through the admin api, keep receiving requests from
+//! their existing sessions for a grace period while new sessions are sent
+//! elsewhere. Once the grace period expires the remaining sessions are
+//! moved to another upstream. Pool state is process global and keyed by
+//! pool name so it survives configuration reloads.
+
+use std::{
+    collections::{BTreeMap, HashMap},
+    fmt,
+    hash::{DefaultHasher, Hash, Hasher},
+    net::{IpAddr, SocketAddr},
+    sync::{Arc, Mutex, OnceLock, Weak, mpsc},
+    time::{Duration, Instant},
+};
+
+use actix_http::{BoxedPayloadStream, Payload, encoding::Decoder};
+use actix_web::{
+    Error, HttpRequest, HttpResponse,
+    cookie::{Cookie, SameSite},
+    error::{ErrorBadGateway, ErrorGatewayTimeout, ErrorServiceUnavailable},
+    http::header::{self, HeaderName},
+    web,
+};
+use awc::{ClientResponse, error::SendRequestError};
+
+use super::{
+    hop_by_hop,
+    resolver::{resolve, system_resolver},
+    retry::{self, Failure, Policy, Replay},
+};
+
+/// Shortest interval between upstream discovery lookups.
+const MIN_DISCOVERY_INTERVAL: Duration = Duration::from_secs(1);
+/// Longest interval between upstream discovery lookups.
+const MAX_DISCOVERY_INTERVAL: Duration = Duration::from_secs(30);
+/// Time registration waits for the initial discovery lookup.
+const DISCOVERY_WAIT: Duration = Duration::from_secs(5);
+
+/// Configured pool upstream.
+#[derive(Clone, Debug)]
+pub struct Upstream {
+    /// Upstream base url.
+    pub url: String,
+    /// Relative share of new sessions.
+    pub weight: u32,
+    /// Expand the url's hostname into a member for every resolved address.
+    pub discover: bool,
+}
+
+impl Upstream {
+    /// Hostname resolved for discovery.
+    fn host(&self) -> Option<String> {
+        let uri: actix_web::http::Uri = self.url.parse().ok()?;
+        let host = uri.host()?.trim_matches(['[', ']']);
+        host.parse::<IpAddr>().is_err().then(|| host.to_owned())
+    }
+
+    /// Target connecting to the resolved address of the upstream.
+    fn with_addr(&self, ip: IpAddr) -> Option<Target> {
+        let uri: actix_web::http::Uri = self.url.parse().ok()?;
+        let scheme = uri.scheme_str().unwrap_or("http");
+        let port = uri
+            .port_u16()
+            .unwrap_or(if scheme == "https" { 443 } else { 80 });
+        Some(Target {
+            url: self.url.clone(),
+            addr: Some(SocketAddr::new(ip, port)),
+        })
+    }
+}
+
+/// Upstream url and the address connected to instead of resolving it.
+#[derive(Clone, Debug, PartialEq, Eq, Hash)]
+pub struct Target {
+    /// Upstream base url.
+    pub url: String,
+    /// Address connected to for discovered members.
+    pub addr: Option<SocketAddr>,
+}
+
+impl Target {
+    fn new(url: &str) -> Self {
+        Self {
+            url: url.to_owned(),
+            addr: None,
+        }
+    }
+}
+
+impl fmt::Display for Target {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self.addr {
+            Some(addr) => write!(f, "{} ({addr})", self.url),
+            None => write!(f, "{}", self.url),
+        }
+    }
+}
+
+/// Keeps the discovery thread of a pool running while held.
+#[derive(Debug)]
+pub struct Discovery;
+
+/// Upstream member of a pool.
+#[derive(Clone, Debug)]
+struct Member {
+    /// Stable identifier stored in the affinity cookie.
+    id: String,
+    /// Upstream url and connect address.
+    target: Target,
+    /// Relative share of new sessions.
+    weight: u32,
+    /// Smooth weighted round-robin selection state.
+    current: i64,
+    /// Member is still part of the configured pool.
+    configured: bool,
+    /// Member was drained through the admin api.
+    drained: bool,
+    /// Deadline existing sessions are moved elsewhere.
+    leaving: Option<Instant>,
+}
+
+impl Member {
+    fn new(target: &Target, weight: u32) -> Self {
+        let mut hasher = DefaultHasher::new();
+        target.url.hash(&mut hasher);
+        if let Some(addr) = target.addr {
+            addr.hash(&mut hasher);
+        }
+        Self {
+            id: format!("{:016x}", hasher.finish()),
+            target: target.clone(),
+            weight,
+            current: 0,
+            configured: true,
+            drained: false,
+            leaving: None,
+        }
+    }
+
+    /// Check if member accepts new sessions.
+    #[inline]
+    fn active(&self) -> bool {
+        self.leaving.is_none()
+    }
+
+    /// Check if member still serves existing sessions.
+    #[inline]
+    fn serving(&self, now: Instant) -> bool {
+        self.leaving.is_none_or(|until| until > now)
+    }
+
+    /// Begin removing the member after the grace period.
+    fn leave(&mut self, grace: Duration) {
+        if self.leaving.is_none() {
+            log::info!("upstream {} leaving pool in {grace:?}", self.target);
+            self.leaving = Some(Instant::now() + grace);
+        }
+    }
+}
+
+/// Snapshot of a pool member's state.
+#[derive(Clone, Debug)]
+pub struct Status {
+    /// Pool name.
+    pub pool: String,
+    /// Member identifier.
+    pub id: String,
+    /// Upstream base url.
+    pub url: String,
+    /// Address connected to for discovered members.
+    pub addr: Option<SocketAddr>,
+    /// Relative share of new sessions.
+    pub weight: u32,
+    /// Member was drained through the admin api.
+    pub drained: bool,
+    /// Remaining grace period for existing sessions.
+    pub leaving: Option<Duration>,
+}
+
+/// Shared upstream pool state.
+#[derive(Debug, Default)]
+pub struct Pool {
+    members: Mutex<Vec<Member>>,
+    grace: Mutex<Duration>,
+    upstreams: Mutex<Vec<Upstream>>,
+    discovered: Mutex<HashMap<String, Vec<IpAddr>>>,
+    discovery: Mutex<Weak<Discovery>>,
+}
+
+/// Pick the next eligible member by smooth weighted round-robin.
+///
+/// Returns the index of the selected member.
+fn weighted(members: &mut [Member], eligible: impl Fn(&Member) -> bool) -> Option<usize> {
+    let total: i64 = members
+        .iter()
+        .filter(|m| eligible(m))
+        .map(|m| m.weight as i64)
+        .sum();
+    if total == 0 {
+        return None;
+    }
+    let mut best: Option<(usize, i64)> = None;
+    for (n, member) in members.iter_mut().enumerate() {
+        if !eligible(member) || member.weight == 0 {
+            continue;
+        }
+        member.current += member.weight as i64;
+        if best.is_none_or(|(_, current)| member.current > current) {
+            best = Some((n, member.current));
+        }
+    }
+    let (n, _) = best?;
+    members[n].current -= total;
+    Some(n)
+}
+
+impl Pool {
+    /// Reconcile pool members with the configured upstreams.
+    ///
+    /// Upstreams missing from the configuration begin leaving the pool
+    /// while re-added upstreams immediately accept new sessions again.
+    fn sync(&self, upstreams: &[(Target, u32)], grace: Duration) {
+        *self.grace.lock().expect("pool grace poisoned") = grace;
+        let mut members = self.members.lock().expect("pool members poisoned");
+        for member in members.iter_mut() {
+            let configured = upstreams
+                .iter()
+                .find(|(target, _)| target == &member.target);
+            member.configured = configured.is_some();
+            if let Some((_, weight)) = configured {
+                member.weight = *weight;
+            }
+            match member.configured {
+                true if !member.drained => member.leaving = None,
+                true => {}
+                false => member.leave(grace),
+            }
+        }
+        for (target, weight) in upstreams {
+            if !members.iter().any(|m| &m.target == target) {
+                members.push(Member::new(target, *weight));
+            }
+        }
+    }
+
+    /// Expand configured upstreams into member targets and weights.
+    ///
+    /// Discovered upstreams without resolved addresses are kept as-is.
+    fn expand(&self) -> Vec<(Target, u32)> {
+        let upstreams = self.upstreams.lock().expect("pool upstreams poisoned");
+        let discovered = self.discovered.lock().expect("pool discovery poisoned");
+        upstreams
+            .iter()
+            .flat_map(|upstream| {
+                match discovered.get(&upstream.url).filter(|_| upstream.discover) {
+                    Some(addrs) => addrs
+                        .iter()
+                        .filter_map(|ip| upstream.with_addr(*ip))
+                        .map(|target| (target, upstream.weight))
+                        .collect(),
+                    None => vec![(Target::new(&upstream.url), upstream.weight)],
+                }
+            })
+            .collect()
+    }
+
+    /// Re-resolve discovered upstreams.
+    ///
+    /// Returns the time until the earliest resolved addresses expire.
+    async fn refresh(&self, resolver: &hickory_resolver::TokioAsyncResolver) -> Duration {
+        let upstreams: Vec<Upstream> = self
+            .upstreams
+            .lock()
+            .expect("pool upstreams poisoned")
+            .iter()
+            .filter(|upstream| upstream.discover)
+            .cloned()
+            .collect();
+        let mut next = MAX_DISCOVERY_INTERVAL;
+        for upstream in upstreams {
+            let Some(host) = upstream.host() else {
+                continue;
+            };
+            match resolve(resolver, &host).await {
+                Ok((mut addrs, valid_until)) => {
+                    addrs.sort();
+                    next = next.min(valid_until.saturating_duration_since(Instant::now()));
+                    let mut discovered = self.discovered.lock().expect("pool discovery poisoned");
+                    if discovered.get(&upstream.url) != Some(&addrs) {
+                        log::info!("discovered {host:?} upstream addresses {addrs:?}");
+                        discovered.insert(upstream.url.clone(), addrs);
+                    }
+                }
+                Err(err) => {
+                    log::warn!("failed to discover {host:?} upstreams: {err}");
+                    next = next.min(MIN_DISCOVERY_INTERVAL * 5);
+                }
+            }
+        }
+        next.max(MIN_DISCOVERY_INTERVAL)
+    }
+
+    /// Select upstream for the session identified by the affinity cookie.
+    ///
+    /// Returns the upstream target and its id if a new session was assigned.
+    fn select(&self, session: Option<&str>) -> Option<(Target, Option<String>)> {
+        let now = Instant::now();
+        let mut members = self.members.lock().expect("pool members poisoned");
+        members.retain(|m| {
+            let removed = !m.configured && !m.serving(now);
+            if removed {
+                log::info!("upstream {} removed from pool", m.target);
+            }
+            !removed
+        });
+        if let Some(member) = session
+            .and_then(|id| members.iter().find(|m| m.id == id))
+            .filter(|m| m.serving(now))
+        {
+            return Some((member.target.clone(), None));
+        }
+        let n = weighted(&mut members, Member::active)?;
+        Some((members[n].target.clone(), Some(members[n].id.clone())))
+    }
+
+    /// Find a serving member with the upstream url.
+    ///
+    /// Returns the upstream target.
+    fn lookup(&self, url: &str) -> Option<Target> {
+        let now = Instant::now();
+        let members = self.members.lock().expect("pool members poisoned");
+        members
+            .iter()
+            .find(|m| m.target.url == url && m.serving(now))
+            .map(|m| m.target.clone())
+    }
+
+    /// Select another active upstream after the listed upstreams failed.
+    ///
+    /// Returns the upstream target and its id.
+    fn failover(&self, failed: &[Target]) -> Option<(Target, String)> {
+        let mut members = self.members.lock().expect("pool members poisoned");
+        let n = weighted(&mut members, |m| m.active() && !failed.contains(&m.target))?;
+        Some((members[n].target.clone(), members[n].id.clone()))
+    }
+
+    /// Drain or restore the member with the specified id.
+    ///
+    /// Returns false if no such member exists.
+    fn drain(&self, id: &str, enable: bool) -> bool {
+        let grace = *self.grace.lock().expect("pool grace poisoned");
+        let mut members = self.members.lock().expect("pool members poisoned");
+        let Some(member) = members.iter_mut().find(|m| m.id == id) else {
+            return false;
+        };
+        member.drained = enable;
+        match enable {
+            true => member.leave(grace),
+            false if member.configured => member.leaving = None,
+            false => {}
+        }
+        true
+    }
+}
+
+fn pools() -> &'static Mutex<BTreeMap<String, Arc<Pool>>> {
+    static POOLS: OnceLock<Mutex<BTreeMap<String, Arc<Pool>>>> = OnceLock::new();
+    POOLS.get_or_init(Default::default)
+}
+
+/// Spawn background thread re-resolving the pool's discovered upstreams.
+///
+/// The thread exits once every holder of the returned handle is dropped.
+/// Waits a short time for the initial lookup so members are available
+/// before the first request is served.
+fn spawn_discovery(pool: Arc<Pool>) -> Arc<Discovery> {
+    let handle = Arc::new(Discovery);
+    let alive = Arc::downgrade(&handle);
+    let (ready, wait) = mpsc::channel();
+    std::thread::Builder::new()
+        .name("bob-discovery".to_owned())
+        .spawn(move || {
+            let runtime = tokio::runtime::Builder::new_current_thread()
+                .enable_all()
+                .build()
+                .expect("failed to build discovery runtime");
+            let resolver = runtime.block_on(async { system_resolver() });
+            let mut ready = Some(ready);
+            while alive.strong_count() > 0 {
+                let next = runtime.block_on(pool.refresh(&resolver));
+                let grace = *pool.grace.lock().expect("pool grace poisoned");
+                pool.sync(&pool.expand(), grace);
+                if let Some(ready) = ready.take() {
+                    let _ = ready.send(());
+                }
+                std::thread::sleep(next);
+            }
+            log::debug!("pool upstream discovery stopped");
+        })
+        .expect("failed to spawn discovery thread");
+    let _ = wait.recv_timeout(DISCOVERY_WAIT);
+    handle
+}
+
+/// Register the named pool and reconcile it with the configured upstreams.
+///
+/// Returns the discovery handle the balancer must hold when any upstream
+/// is discovered, starting the discovery thread if none is running.
+pub fn register(
+    name: &str,
+    upstreams: &[Upstream],
+    grace: Duration,
+) -> (Arc<Pool>, Option<Arc<Discovery>>) {
+    let pool = {
+        let mut pools = pools().lock().expect("pools poisoned");
+        Arc::clone(pools.entry(name.to_owned()).or_default())
+    };
+    *pool.upstreams.lock().expect("pool upstreams poisoned") = upstreams.to_vec();
+    *pool.grace.lock().expect("pool grace poisoned") = grace;
+    let discovery = upstreams.iter().any(|u| u.discover).then(|| {
+        let mut running = pool.discovery.lock().expect("pool discovery poisoned");
+        running.upgrade().unwrap_or_else(|| {
+            let handle = spawn_discovery(Arc::clone(&pool));
+            *running = Arc::downgrade(&handle);
+            handle
+        })
+    });
+    pool.sync(&pool.expand(), grace);
+    (pool, discovery)
+}
+
+/// Drain or restore a pool member.
+///
+/// Returns false if the pool or member does not exist.
+pub fn drain(name: &str, id: &str, enable: bool) -> bool {
+    let pool = pools().lock().expect("pools poisoned").get(name).cloned();
+    pool.is_some_and(|pool| pool.drain(id, enable))
+}
+
+/// List the state of every pool member.
+pub fn list() -> Vec<Status> {
+    let now = Instant::now();
+    let pools = pools().lock().expect("pools poisoned");
+    pools
+        .iter()
+        .flat_map(|(name, pool)| {
+            let members = pool.members.lock().expect("pool members poisoned");
+            members
+                .iter()
+                .map(|m| Status {
+                    pool: name.clone(),
+                    id: m.id.clone(),
+                    url: m.target.url.clone(),
+                    addr: m.target.addr,
+                    weight: m.weight,
+                    drained: m.drained,
+                    leaving: m.leaving.map(|until| until.saturating_duration_since(now)),
+                })
+                .collect::<Vec<_>>()
+        })
+        .collect()
+}
+
+/// Request attribute checked by a routing override.
+#[derive(Clone, Debug)]
+pub enum Matcher {
+    Header(HeaderName),
+    Cookie(String),
+}
+
+/// Routing override sending matching requests to a fixed upstream.
+#[derive(Clone, Debug)]
+pub struct Override {
+    /// Request attribute checked.
+    pub matcher: Matcher,
+    /// Required attribute value. Any value matches when `None`.
+    pub value: Option<String>,
+    /// Upstream base url receiving matching requests.
+    pub upstream: String,
+}
+
+impl Override {
+    /// Check if the request matches the override.
+    fn matches(&self, req: &HttpRequest) -> bool {
+        let value = match &self.matcher {
+            Matcher::Header(name) => req
+                .headers()
+                .get(name)
+                .and_then(|v| v.to_str().ok())
+                .map(str::to_owned),
+            Matcher::Cookie(name) => req.cookie(name).map(|c| c.value().to_owned()),
+        };
+        match (value, self.value.as_deref()) {
+            (Some(value), Some(expected)) => value == expected,
+            (Some(_), None) => true,
+            (None, _) => false,
+        }
+    }
+}
+
+/// Sticky upstream pool service.
+#[derive(Clone)]
+pub struct Balancer {
+    pool: Arc<Pool>,
+    discovery: Option<Arc<Discovery>>,
+    cookie: String,
+    client: awc::Client,
+    policy: Arc<Policy>,
+    overrides: Arc<Vec<Override>>,
+}
+
+/// Request body sent upstream.
+enum Body {
+    /// Streamed once from the client.
+    Stream(actix_web::dev::Payload),
+    /// Buffered for replay across attempts.
+    Buffered(web::Bytes),
+}
+
+impl Balancer {
+    /// Create new balancer for the pool using the affinity cookie and client.
+    ///
+    /// The pool keeps discovering upstreams while the balancer holds the
+    /// discovery handle.
+    pub fn new(
+        pool: Arc<Pool>,
+        discovery: Option<Arc<Discovery>>,
+        cookie: String,
+        client: awc::Client,
+        policy: Policy,
+        overrides: Vec<Override>,
+    ) -> Self {
+        Self {
+            pool,
+            discovery,
+            cookie,
+            client,
+            policy: Arc::new(policy),
+            overrides: Arc::new(overrides),
+        }
+    }
+
+    /// Send the request to a single upstream.
+    async fn send(
+        &self,
+        req: &HttpRequest,
+        upstream: &Target,
+        body: Body,
+    ) -> Result<ClientResponse<Decoder<Payload<BoxedPayloadStream>>>, Failure> {
+        let path = req
+            .uri()
+            .path_and_query()
+            .map(|p| p.as_str())
+            .unwrap_or("/");
+        let url = format!("{}{path}", upstream.url.trim_end_matches('/'));
+        log::debug!("pool upstream resolved to {url:?} via {upstream}");
+
+        let mut request = self
+            .client
+            .request_from(url.as_str(), req.head())
+            .no_decompress();
+        if let Some(addr) = upstream.addr {
+            request = request.address(addr);
+        }
+        // `100-continue` lets the upstream refuse a body before it is sent
+        if matches!(&body, Body::Buffered(bytes) if bytes.is_empty()) {
+            request.headers_mut().remove(header::EXPECT);
+        }
+        hop_by_hop::remove(request.headers_mut());
+        if let Some(peer) = req.peer_addr() {
+            let prior = req
+                .headers()
+                .get("x-forwarded-for")
+                .and_then(|v| v.to_str().ok());
+            let forwarded = match prior {
+                Some(prior) => format!("{prior}, {}", peer.ip()),
+                None => peer.ip().to_string(),
+            };
+            request = request.insert_header(("x-forwarded-for", forwarded));
+        }
+        if let Some(limit) = self.policy.try_timeout {
+            request = request.timeout(limit);
+        }
+        let result = match body {
+            Body::Stream(payload) => request.send_stream(payload).await,
+            Body::Buffered(bytes) => request.send_body(bytes).await,
+        };
+        result.map_err(|err| {
+            log::error!("pool upstream {upstream} failed: {err:?}");
+            match err {
+                SendRequestError::Timeout => Failure::Timeout,
+                _ => Failure::Connect,
+            }
+        })
+    }
+
+    /// Proxy request to the upstream assigned to the client session.
+    ///
+    /// Requests matching an override skip session assignment and are
+    /// sent to the override's upstream while it is serving.
+    ///
+    /// Failed attempts are retried against other upstreams in the pool
+    /// according to the retry policy.
+    pub async fn handle(
+        &self,
+        req: HttpRequest,
+        payload: web::Payload,
+    ) -> Result<HttpResponse, Error> {
+        let session = req.cookie(&self.cookie);
+        let routed = self
+            .overrides
+            .iter()
+            .find(|o| o.matches(&req))
+            .and_then(|o| self.pool.lookup(&o.upstream));
+        let (mut upstream, mut assigned) = match routed {
+            Some(upstream) => (upstream, None),
+            None => self
+                .pool
+                .select(session.as_ref().map(|c| c.value()))
+                .ok_or_else(|| {
+                    log::error!("upstream pool has no active upstreams");
+                    ErrorServiceUnavailable("no upstream available")
+                })?,
+        };
+
+        let mut retries =
+            match retry::replayable(req.headers()) && self.policy.permits(req.method()) {
+                true => self.policy.retries,
+                false => 0,
+            };
+        let mut body = None;
+        let mut buffered = web::Bytes::new();
+        match retries {
+            0 => body = Some(Body::Stream(payload.into_inner())),
+            _ => match retry::read(payload.into_inner()).await? {
+                Replay::Buffered(bytes) => buffered = bytes,
+                Replay::Streamed(payload) => {
+                    log::debug!("request body too large to replay. not retrying");
+                    retries = 0;
+                    body = Some(Body::Stream(payload));
+                }
+            },
+        }
+
+        let mut failed = Vec::new();
+        let response = loop {
+            let attempt = body
+                .take()
+                .unwrap_or_else(|| Body::Buffered(buffered.clone()));
+            let failure = match self.send(&req, &upstream, attempt).await {
+                Ok(res) if !self.policy.covers(Failure::Status(res.status())) => break res,
+                Ok(res) if failed.len() as u32 >= retries => break res,
+                Ok(res) => Failure::Status(res.status()),
+                Err(failure) if failed.len() as u32 >= retries || !self.policy.covers(failure) => {
+                    return Err(match failure {
+                        Failure::Timeout => ErrorGatewayTimeout("upstream timed out"),
+                        _ => ErrorBadGateway("upstream request failed"),
+                    });
+                }
+                Err(failure) => failure,
+            };
+            failed.push(upstream.clone());
+            // fall back to retrying the same upstream when no other remains
+            let (next, id) = self
+                .pool
+                .failover(&failed)
+                .unwrap_or_else(|| (upstream.clone(), String::new()));
+            log::warn!("retrying pool request after {failure:?} from {upstream} on {next}");
+            if !id.is_empty() {
+                assigned = Some(id);
+            }
+            upstream = next;
+        };
+
+        let mut builder = HttpResponse::build(response.status());
+        let mut headers = response.headers().clone();
+        hop_by_hop::remove(&mut headers);
+        headers
+            .into_iter()
+            .fold(&mut builder, |b, (h, v)| b.append_header((h, v)));
+        if let Some(id) = assigned {
+            let cookie = Cookie::build(self.cookie.as_str(), id)
+                .path("/")
+                .http_only(true)
+                .same_site(SameSite::Lax)
+                .finish();
+            builder.cookie(cookie);
+        }
+        Ok(builder.streaming(response))
+    }
+}
+
+impl std::fmt::Debug for Balancer {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("Balancer")
+            .field("pool", &self.pool)
+            .field("discovery", &self.discovery.is_some())
+            .field("cookie", &self.cookie)
+            .field("policy", &self.policy)
+            .field("overrides", &self.overrides)
+            .finish()
+    }
+}
@@ -0,0 +1,180 @@
+//! Proxy Buffering Middleware
+//!
+//! Reads complete request bodies from clients before they are forwarded
+//! and complete response bodies from upstreams before they are returned,
+//! so slow clients never hold upstream connections open. Bodies are kept
+//! in a [`Spool`], spilling to temporary files beyond the in-memory limit,
+//! and anything past the maximum buffered size is streamed through as is.
+//! Upgrades, gRPC and server-sent event streams are never buffered.
+
+use std::{pin::Pin, rc::Rc};
+
+use actix_web::{
+    Error,
+    body::{BodySize, BodyStream, BoxBody, MessageBody, SizedStream},
+    dev::{Payload, Service, ServiceRequest, ServiceResponse, Transform},
+    error::ErrorInternalServerError,
+    http::{StatusCode, header},
+    web::Bytes,
+};
+use futures_core::Stream;
+
+use super::{
+    LocalBoxFuture,
+    spool::{self, BodyChunks, Chain, Spool},
+};
+
+/// Proxy buffering settings.
+#[derive(Clone, Debug)]
+pub struct Settings {
+    /// Buffer request bodies before forwarding them upstream.
+    pub requests: bool,
+    /// Buffer response bodies before returning them to the client.
+    pub responses: bool,
+    /// Largest body buffered before the remainder is streamed.
+    pub max_size: Option<usize>,
+    /// Body spooling settings.
+    pub spool: spool::Settings,
+}
+
+/// Log failed spool operations and convert them into an error.
+fn internal(err: std::io::Error) -> Error {
+    log::error!("proxy buffering failed: {err:?}");
+    ErrorInternalServerError("proxy buffering failure")
+}
+
+/// Check if the content type is streamed incrementally by design.
+fn streaming(headers: &header::HeaderMap) -> bool {
+    headers
+        .get(header::CONTENT_TYPE)
+        .and_then(|v| v.to_str().ok())
+        .is_some_and(|ctype| {
+            let ctype = ctype.trim_start().to_ascii_lowercase();
+            ctype.starts_with("application/grpc") || ctype.starts_with("text/event-stream")
+        })
+}
+
+/// Check if the request body should be buffered.
+fn buffer_request(req: &ServiceRequest) -> bool {
+    let headers = req.headers();
+    if headers.contains_key(header::UPGRADE) || streaming(headers) {
+        return false;
+    }
+    // requests without a body have nothing to buffer
+    headers.contains_key(header::TRANSFER_ENCODING)
+        || headers
+            .get(header::CONTENT_LENGTH)
+            .and_then(|len| len.to_str().ok())
+            .and_then(|len| len.parse::<u64>().ok())
+            .is_some_and(|len| len > 0)
+}
+
+/// Check if the response body should be buffered.
+fn buffer_response<B: MessageBody>(res: &ServiceResponse<B>) -> bool {
+    if matches!(
+        res.response().body().size(),
+        BodySize::None | BodySize::Sized(0)
+    ) {
+        return false;
+    }
+    let headers = res.headers();
+    let disabled = headers
+        .get("x-accel-buffering")
+        .is_some_and(|v| v.as_bytes().eq_ignore_ascii_case(b"no"));
+    res.status() != StatusCode::SWITCHING_PROTOCOLS && !disabled && !streaming(headers)
+}
+
+/// Read the body into the spool until complete or the maximum is reached.
+async fn spool<S, E>(mut body: S, settings: &Settings) -> Result<Chain<S>, Error>
+where
+    S: Stream<Item = Result<Bytes, E>> + Unpin,
+    E: Into<Error>,
+{
+    let mut spool = Spool::new(settings.spool.clone());
+    let mut rest = None;
+    while let Some(chunk) = std::future::poll_fn(|cx| Pin::new(&mut body).poll_next(cx)).await {
+        let chunk = chunk.map_err(Into::into)?;
+        spool.push(chunk).await.map_err(internal)?;
+        if settings.max_size.is_some_and(|max| spool.size() >= max) {
+            log::debug!("proxy buffer limit reached. streaming remaining body");
+            rest = Some(body);
+            break;
+        }
+    }
+    let replay = spool.finish().await.map_err(internal)?;
+    Ok(Chain::new(replay, rest))
+}
+
+/// Proxy buffering middleware.
+#[derive(Clone)]
+pub struct ProxyBuffer(Rc<Settings>);
+
+impl ProxyBuffer {
+    /// Create new proxy buffering middleware from settings.
+    pub fn new(settings: Settings) -> Self {
+        Self(Rc::new(settings))
+    }
+}
+
+impl<S, B> Transform<S, ServiceRequest> for ProxyBuffer
+where
+    S: Service<ServiceRequest, Response = ServiceResponse<B>, Error = Error> + 'static,
+    B: MessageBody + 'static,
+{
+    type Response = ServiceResponse<BoxBody>;
+    type Error = Error;
+    type Transform = ProxyBufferService<S>;
+    type InitError = ();
+    type Future = std::future::Ready<Result<Self::Transform, Self::InitError>>;
+
+    fn new_transform(&self, service: S) -> Self::Future {
+        std::future::ready(Ok(ProxyBufferService {
+            service: Rc::new(service),
+            settings: Rc::clone(&self.0),
+        }))
+    }
+}
+
+/// Service produced by [`ProxyBuffer`] middleware.
+pub struct ProxyBufferService<S> {
+    service: Rc<S>,
+    settings: Rc<Settings>,
+}
+
+impl<S, B> Service<ServiceRequest> for ProxyBufferService<S>
+where
+    S: Service<ServiceRequest, Response = ServiceResponse<B>, Error = Error> + 'static,
+    B: MessageBody + 'static,
+{
+    type Response = ServiceResponse<BoxBody>;
+    type Error = Error;
+    type Future = LocalBoxFuture<Result<Self::Response, Self::Error>>;
+
+    actix_web::dev::forward_ready!(service);
+
+    fn call(&self, mut req: ServiceRequest) -> Self::Future {
+        let service = Rc::clone(&self.service);
+        let settings = Rc::clone(&self.settings);
+        Box::pin(async move {
+            if settings.requests && buffer_request(&req) {
+                let body = spool(req.take_payload(), &settings).await?;
+                req.set_payload(Payload::Stream {
+                    payload: Box::pin(body),
+                });
+            }
+
+            let res = service.call(req).await?;
+            if !settings.responses || !buffer_response(&res) {
+                return Ok(res.map_into_boxed_body());
+            }
+            let (request, response) = res.into_parts();
+            let (response, body) = response.into_parts();
+            let body = spool(BodyChunks(Box::pin(body)), &settings).await?;
+            let res = match body.size() {
+                Some(size) => response.set_body(SizedStream::new(size, body).boxed()),
+                None => response.set_body(BodyStream::new(body).boxed()),
+            };
+            Ok(ServiceResponse::new(request, res))
+        })
+    }
+}
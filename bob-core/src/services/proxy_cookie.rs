@@ -0,0 +1,162 @@
+//! Proxied Cookie Rewriting Middleware
+//!
+//! Rewrites the `Domain` and `Path` attributes of `Set-Cookie` headers
+//! returned by upstreams so cookies issued for an internal hostname or
+//! an upstream path are accepted by browsers under the public address,
+//! similar to nginx's `proxy_cookie_domain` and `proxy_cookie_path`.
+
+use std::rc::Rc;
+
+use actix_web::{
+    Error,
+    body::MessageBody,
+    dev::{Service, ServiceRequest, ServiceResponse, Transform},
+    http::header::{self, HeaderValue},
+};
+
+use super::LocalBoxFuture;
+
+/// Proxied cookie rewriting settings.
+#[derive(Clone, Debug)]
+pub struct Settings {
+    /// Upstream cookie domains mapped to their public replacement.
+    pub domains: Vec<(String, String)>,
+    /// Upstream cookie path prefixes mapped to their public replacement.
+    pub paths: Vec<(String, String)>,
+}
+
+impl Settings {
+    /// Replace a matching cookie domain.
+    fn domain(&self, domain: &str) -> Option<&str> {
+        let domain = domain.trim_start_matches('.');
+        self.domains
+            .iter()
+            .find(|(from, _)| from.trim_start_matches('.').eq_ignore_ascii_case(domain))
+            .map(|(_, to)| to.as_str())
+    }
+
+    /// Replace a matching cookie path prefix.
+    fn path(&self, path: &str) -> Option<String> {
+        self.paths.iter().find_map(|(from, to)| {
+            let rest = path.strip_prefix(from.as_str())?;
+            let boundary = from.ends_with('/') || rest.is_empty() || rest.starts_with('/');
+            boundary.then(|| match rest.strip_prefix('/') {
+                Some(rest) if to.ends_with('/') => format!("{to}{rest}"),
+                _ => format!("{to}{rest}"),
+            })
+        })
+    }
+
+    /// Rewrite the attributes of a single `Set-Cookie` value.
+    fn rewrite(&self, cookie: &str) -> Option<String> {
+        let mut changed = false;
+        let parts: Vec<String> = cookie
+            .split(';')
+            .enumerate()
+            .map(|(n, part)| {
+                let attr = part.trim();
+                let Some((name, value)) = attr.split_once('=').filter(|_| n > 0) else {
+                    return attr.to_owned();
+                };
+                let value = value.trim();
+                let new = match name.trim() {
+                    name if name.eq_ignore_ascii_case("domain") => {
+                        self.domain(value).map(str::to_owned)
+                    }
+                    name if name.eq_ignore_ascii_case("path") => self.path(value),
+                    _ => None,
+                };
+                match new {
+                    // an empty replacement drops the attribute entirely
+                    Some(new) if new.is_empty() => {
+                        changed = true;
+                        String::new()
+                    }
+                    Some(new) => {
+                        changed = true;
+                        format!("{}={new}", name.trim())
+                    }
+                    None => attr.to_owned(),
+                }
+            })
+            .filter(|part| !part.is_empty())
+            .collect();
+        changed.then(|| parts.join("; "))
+    }
+}
+
+/// Proxied cookie rewriting middleware.
+#[derive(Clone)]
+pub struct ProxyCookie(Rc<Settings>);
+
+impl ProxyCookie {
+    /// Create new proxied cookie rewriting middleware from settings.
+    pub fn new(settings: Settings) -> Self {
+        Self(Rc::new(settings))
+    }
+}
+
+impl<S, B> Transform<S, ServiceRequest> for ProxyCookie
+where
+    S: Service<ServiceRequest, Response = ServiceResponse<B>, Error = Error> + 'static,
+    B: MessageBody + 'static,
+{
+    type Response = ServiceResponse<B>;
+    type Error = Error;
+    type Transform = ProxyCookieService<S>;
+    type InitError = ();
+    type Future = std::future::Ready<Result<Self::Transform, Self::InitError>>;
+
+    fn new_transform(&self, service: S) -> Self::Future {
+        std::future::ready(Ok(ProxyCookieService {
+            service: Rc::new(service),
+            settings: Rc::clone(&self.0),
+        }))
+    }
+}
+
+/// Service produced by [`ProxyCookie`] middleware.
+pub struct ProxyCookieService<S> {
+    service: Rc<S>,
+    settings: Rc<Settings>,
+}
+
+impl<S, B> Service<ServiceRequest> for ProxyCookieService<S>
+where
+    S: Service<ServiceRequest, Response = ServiceResponse<B>, Error = Error> + 'static,
+    B: MessageBody + 'static,
+{
+    type Response = ServiceResponse<B>;
+    type Error = Error;
+    type Future = LocalBoxFuture<Result<Self::Response, Self::Error>>;
+
+    actix_web::dev::forward_ready!(service);
+
+    fn call(&self, req: ServiceRequest) -> Self::Future {
+        let service = Rc::clone(&self.service);
+        let settings = Rc::clone(&self.settings);
+        Box::pin(async move {
+            let mut res = service.call(req).await?;
+            let headers = res.headers_mut();
+            if !headers.contains_key(header::SET_COOKIE) {
+                return Ok(res);
+            }
+            let cookies: Vec<HeaderValue> = headers
+                .get_all(header::SET_COOKIE)
+                .map(|value| {
+                    value
+                        .to_str()
+                        .ok()
+                        .and_then(|v| settings.rewrite(v))
+                        .and_then(|v| HeaderValue::try_from(v).ok())
+                        .unwrap_or_else(|| value.clone())
+                })
+                .collect();
+            headers.remove(header::SET_COOKIE);
+            for cookie in cookies {
+                headers.append(header::SET_COOKIE, cookie);
+            }
+            Ok(res)
+        })
+    }
+}
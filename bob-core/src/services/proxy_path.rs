@@ -0,0 +1,137 @@
+//! Proxied Path Rewriting Middleware
+//!
+//! Rewrites the request path forwarded to an upstream by stripping a
+//! leading prefix, applying regex substitutions, and adding a new prefix
+//! in that order. Only the path below the location the proxy is mounted
+//! at is rewritten and the query string is preserved.
+
+use std::rc::Rc;
+
+use actix_web::{
+    Error,
+    body::MessageBody,
+    dev::{Service, ServiceRequest, ServiceResponse, Transform},
+    http::{
+        Uri,
+        uri::{Parts, PathAndQuery},
+    },
+};
+use regex::Regex;
+
+use super::LocalBoxFuture;
+
+/// Proxied path rewriting settings.
+#[derive(Clone, Debug, Default)]
+pub struct Settings {
+    /// Prefix removed from the forwarded path.
+    pub strip_prefix: Option<String>,
+    /// Regex substitutions with the first matching pattern applied.
+    pub rewrite: Vec<(Regex, String)>,
+    /// Prefix added to the forwarded path.
+    pub add_prefix: Option<String>,
+}
+
+impl Settings {
+    /// Rewrite the path forwarded upstream.
+    fn rewrite(&self, path: &str) -> String {
+        let mut path = path.to_owned();
+        if let Some(prefix) = self.strip_prefix.as_deref() {
+            let prefix = prefix.trim_end_matches('/');
+            if let Some(rest) = path.strip_prefix(prefix)
+                && (rest.is_empty() || rest.starts_with('/'))
+            {
+                path = format!("/{}", rest.trim_start_matches('/'));
+            }
+        }
+        if let Some((pattern, replace)) = self.rewrite.iter().find(|(p, _)| p.is_match(&path)) {
+            path = pattern.replace(&path, replace.as_str()).into_owned();
+        }
+        if let Some(prefix) = self.add_prefix.as_deref() {
+            let prefix = prefix.trim_end_matches('/');
+            path = format!("{prefix}/{}", path.trim_start_matches('/'));
+        }
+        match path.starts_with('/') {
+            true => path,
+            false => format!("/{path}"),
+        }
+    }
+}
+
+/// Build the request uri with the replaced path.
+fn replace_path(uri: &Uri, path: &str) -> Option<Uri> {
+    let path_and_query = match uri.query() {
+        Some(query) => format!("{path}?{query}"),
+        None => path.to_owned(),
+    };
+    let mut parts = Parts::from(uri.clone());
+    parts.path_and_query = Some(PathAndQuery::try_from(path_and_query).ok()?);
+    Uri::from_parts(parts).ok()
+}
+
+/// Proxied path rewriting middleware.
+#[derive(Clone)]
+pub struct ProxyPath(Rc<Settings>);
+
+impl ProxyPath {
+    /// Create new proxied path rewriting middleware from settings.
+    pub fn new(settings: Settings) -> Self {
+        Self(Rc::new(settings))
+    }
+}
+
+impl<S, B> Transform<S, ServiceRequest> for ProxyPath
+where
+    S: Service<ServiceRequest, Response = ServiceResponse<B>, Error = Error> + 'static,
+    B: MessageBody + 'static,
+{
+    type Response = ServiceResponse<B>;
+    type Error = Error;
+    type Transform = ProxyPathService<S>;
+    type InitError = ();
+    type Future = std::future::Ready<Result<Self::Transform, Self::InitError>>;
+
+    fn new_transform(&self, service: S) -> Self::Future {
+        std::future::ready(Ok(ProxyPathService {
+            service: Rc::new(service),
+            settings: Rc::clone(&self.0),
+        }))
+    }
+}
+
+/// Service produced by [`ProxyPath`] middleware.
+pub struct ProxyPathService<S> {
+    service: Rc<S>,
+    settings: Rc<Settings>,
+}
+
+impl<S, B> Service<ServiceRequest> for ProxyPathService<S>
+where
+    S: Service<ServiceRequest, Response = ServiceResponse<B>, Error = Error> + 'static,
+    B: MessageBody + 'static,
+{
+    type Response = ServiceResponse<B>;
+    type Error = Error;
+    type Future = LocalBoxFuture<Result<Self::Response, Self::Error>>;
+
+    actix_web::dev::forward_ready!(service);
+
+    fn call(&self, mut req: ServiceRequest) -> Self::Future {
+        let path = req.path().to_owned();
+        let rest = req.match_info().unprocessed().to_owned();
+        let rewritten = self.settings.rewrite(&rest);
+        if rewritten != rest {
+            let mount = path.strip_suffix(rest.as_str()).unwrap_or_default();
+            let rewritten = format!("{}{rewritten}", mount.trim_end_matches('/'));
+            match replace_path(req.uri(), &rewritten) {
+                Some(uri) => {
+                    log::debug!("rewrote proxied path {path:?} => {rewritten:?}");
+                    req.match_info_mut().get_mut().update(&uri);
+                    req.head_mut().uri = uri;
+                }
+                None => log::warn!("invalid rewritten proxy path {rewritten:?}"),
+            }
+        }
+        let service = Rc::clone(&self.service);
+        Box::pin(async move { service.call(req).await })
+    }
+}
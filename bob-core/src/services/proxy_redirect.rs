@@ -0,0 +1,158 @@
+//! Proxied Redirect Rewriting Middleware
+//!
+//! Upstreams often build absolute redirects from their own internal
+//! address. This middleware rewrites `Location` and `Refresh` response
+//! headers matching a configured upstream prefix back to the public
+//! address the proxy is mounted at, similar to nginx's `proxy_redirect`.
+
+use std::rc::Rc;
+
+use actix_web::{
+    Error,
+    body::MessageBody,
+    dev::{Service, ServiceRequest, ServiceResponse, Transform},
+    http::header::{self, HeaderValue},
+};
+
+use super::LocalBoxFuture;
+
+/// Single prefix replacement rule.
+#[derive(Clone, Debug)]
+pub struct Rule {
+    /// Upstream url prefix matched against redirects.
+    pub from: String,
+    /// Replacement prefix.
+    ///
+    /// The public path the proxy is mounted at when `None`.
+    pub to: Option<String>,
+}
+
+/// Proxied redirect rewriting settings.
+#[derive(Clone, Debug)]
+pub struct Settings {
+    /// Rules checked in order with the first match applied.
+    pub rules: Vec<Rule>,
+}
+
+/// Check if the url starts with the prefix on a path boundary.
+///
+/// Returns the remainder of the url following the prefix.
+fn strip_prefix<'a>(url: &'a str, prefix: &str) -> Option<&'a str> {
+    let head = url.get(..prefix.len())?;
+    if !head.eq_ignore_ascii_case(prefix) {
+        return None;
+    }
+    let rest = &url[prefix.len()..];
+    let boundary = prefix.ends_with('/') || rest.is_empty() || rest.starts_with(['/', '?', '#']);
+    boundary.then_some(rest)
+}
+
+/// Join replacement prefix and the remaining url.
+fn join(to: &str, rest: &str) -> String {
+    let base = to.trim_end_matches('/');
+    match rest.chars().next() {
+        Some('/') => format!("{base}{rest}"),
+        Some('?' | '#') if base.is_empty() => format!("/{rest}"),
+        Some('?' | '#') => format!("{to}{rest}"),
+        Some(_) => format!("{base}/{rest}"),
+        None if base.is_empty() || to.ends_with('/') => format!("{base}/"),
+        None => base.to_owned(),
+    }
+}
+
+impl Settings {
+    /// Rewrite redirect url using the first matching rule.
+    fn rewrite(&self, url: &str, mount: &str) -> Option<String> {
+        self.rules.iter().find_map(|rule| {
+            let rest = strip_prefix(url, &rule.from)?;
+            Some(join(rule.to.as_deref().unwrap_or(mount), rest))
+        })
+    }
+
+    /// Rewrite the url embedded in a `Refresh` header value.
+    fn rewrite_refresh(&self, value: &str, mount: &str) -> Option<String> {
+        let lower = value.to_ascii_lowercase();
+        let start = lower.find("url=")? + 4;
+        let url = value[start..].trim().trim_matches(['"', '\'']);
+        let rewritten = self.rewrite(url, mount)?;
+        Some(format!("{}{rewritten}", &value[..start]))
+    }
+}
+
+/// Proxied redirect rewriting middleware.
+#[derive(Clone)]
+pub struct ProxyRedirect(Rc<Settings>);
+
+impl ProxyRedirect {
+    /// Create new proxied redirect rewriting middleware from settings.
+    pub fn new(settings: Settings) -> Self {
+        Self(Rc::new(settings))
+    }
+}
+
+impl<S, B> Transform<S, ServiceRequest> for ProxyRedirect
+where
+    S: Service<ServiceRequest, Response = ServiceResponse<B>, Error = Error> + 'static,
+    B: MessageBody + 'static,
+{
+    type Response = ServiceResponse<B>;
+    type Error = Error;
+    type Transform = ProxyRedirectService<S>;
+    type InitError = ();
+    type Future = std::future::Ready<Result<Self::Transform, Self::InitError>>;
+
+    fn new_transform(&self, service: S) -> Self::Future {
+        std::future::ready(Ok(ProxyRedirectService {
+            service: Rc::new(service),
+            settings: Rc::clone(&self.0),
+        }))
+    }
+}
+
+/// Service produced by [`ProxyRedirect`] middleware.
+pub struct ProxyRedirectService<S> {
+    service: Rc<S>,
+    settings: Rc<Settings>,
+}
+
+impl<S, B> Service<ServiceRequest> for ProxyRedirectService<S>
+where
+    S: Service<ServiceRequest, Response = ServiceResponse<B>, Error = Error> + 'static,
+    B: MessageBody + 'static,
+{
+    type Response = ServiceResponse<B>;
+    type Error = Error;
+    type Future = LocalBoxFuture<Result<Self::Response, Self::Error>>;
+
+    actix_web::dev::forward_ready!(service);
+
+    fn call(&self, req: ServiceRequest) -> Self::Future {
+        let path = req.path();
+        let mount = path
+            .strip_suffix(req.match_info().unprocessed())
+            .unwrap_or_default()
+            .to_owned();
+        let service = Rc::clone(&self.service);
+        let settings = Rc::clone(&self.settings);
+        Box::pin(async move {
+            let mut res = service.call(req).await?;
+            let headers = res.headers_mut();
+            let location = headers
+                .get(header::LOCATION)
+                .and_then(|v| v.to_str().ok())
+                .and_then(|v| settings.rewrite(v, &mount));
+            if let Some(location) = location.and_then(|v| HeaderValue::try_from(v).ok()) {
+                log::debug!("rewrote proxied location to {location:?}");
+                headers.insert(header::LOCATION, location);
+            }
+            let refresh = headers
+                .get(header::REFRESH)
+                .and_then(|v| v.to_str().ok())
+                .and_then(|v| settings.rewrite_refresh(v, &mount));
+            if let Some(refresh) = refresh.and_then(|v| HeaderValue::try_from(v).ok()) {
+                headers.insert(header::REFRESH, refresh);
+            }
+            Ok(res)
+        })
+    }
+}
@@ -0,0 +1,163 @@
+//! Stalled Client Reaper Middleware
+//!
+//! Detects clients that stop reading a streaming response and releases
+//! the response body (and any upstream connection behind it) once
+//! no progress is made within the configured timeout.
+
+use std::{
+    cell::RefCell,
+    pin::Pin,
+    rc::{Rc, Weak},
+    task::{Context, Poll},
+    time::{Duration, Instant},
+};
+
+use actix_web::{
+    Error,
+    body::{BodySize, BoxBody, MessageBody},
+    dev::{Service, ServiceRequest, ServiceResponse, Transform},
+    web::Bytes,
+};
+
+use super::LocalBoxFuture;
+use crate::metrics;
+
+const REAPED_METRIC: &str = "bob_stalled_connections_total";
+
+/// Stalled client reaper middleware.
+#[derive(Clone)]
+pub struct Reaper {
+    timeout: Duration,
+}
+
+impl Reaper {
+    /// Create new reaper closing responses stalled for longer than timeout.
+    pub fn new(timeout: Duration) -> Self {
+        Self { timeout }
+    }
+}
+
+impl<S, B> Transform<S, ServiceRequest> for Reaper
+where
+    S: Service<ServiceRequest, Response = ServiceResponse<B>, Error = Error> + 'static,
+    B: MessageBody + 'static,
+{
+    type Response = ServiceResponse<ReaperBody>;
+    type Error = Error;
+    type Transform = ReaperService<S>;
+    type InitError = ();
+    type Future = std::future::Ready<Result<Self::Transform, Self::InitError>>;
+
+    fn new_transform(&self, service: S) -> Self::Future {
+        std::future::ready(Ok(ReaperService {
+            service: Rc::new(service),
+            timeout: self.timeout,
+        }))
+    }
+}
+
+/// Service produced by [`Reaper`] middleware.
+pub struct ReaperService<S> {
+    service: Rc<S>,
+    timeout: Duration,
+}
+
+impl<S, B> Service<ServiceRequest> for ReaperService<S>
+where
+    S: Service<ServiceRequest, Response = ServiceResponse<B>, Error = Error> + 'static,
+    B: MessageBody + 'static,
+{
+    type Response = ServiceResponse<ReaperBody>;
+    type Error = Error;
+    type Future = LocalBoxFuture<Result<Self::Response, Self::Error>>;
+
+    actix_web::dev::forward_ready!(service);
+
+    fn call(&self, req: ServiceRequest) -> Self::Future {
+        let service = Rc::clone(&self.service);
+        let timeout = self.timeout;
+        Box::pin(async move {
+            let res = service.call(req).await?;
+            let peer = res.request().peer_addr();
+            Ok(res.map_body(move |_, body| {
+                let state = Rc::new(RefCell::new(State {
+                    body: Some(body.boxed()),
+                    waiting_since: None,
+                    reaped: false,
+                }));
+                actix_web::rt::spawn(watchdog(Rc::downgrade(&state), timeout, peer));
+                ReaperBody(state)
+            }))
+        })
+    }
+}
+
+/// Shared response streaming state.
+struct State {
+    body: Option<BoxBody>,
+    /// Instant the last chunk was handed to the client.
+    waiting_since: Option<Instant>,
+    reaped: bool,
+}
+
+/// Periodically check for stalled progress until the body completes.
+async fn watchdog(
+    state: Weak<RefCell<State>>,
+    timeout: Duration,
+    peer: Option<std::net::SocketAddr>,
+) {
+    loop {
+        actix_web::rt::time::sleep(timeout / 2).await;
+        let Some(state) = state.upgrade() else { return };
+        let mut state = state.borrow_mut();
+        if state.body.is_none() {
+            return;
+        }
+        let stalled = state
+            .waiting_since
+            .map(|since| since.elapsed() >= timeout)
+            .unwrap_or(false);
+        if stalled {
+            log::warn!("closing stalled response to {peer:?} after {timeout:?}");
+            metrics::counter(REAPED_METRIC).inc();
+            state.body = None;
+            state.reaped = true;
+            return;
+        }
+    }
+}
+
+/// Response body wrapper tracking client read progress.
+pub struct ReaperBody(Rc<RefCell<State>>);
+
+impl MessageBody for ReaperBody {
+    type Error = Box<dyn std::error::Error>;
+
+    fn size(&self) -> BodySize {
+        match self.0.borrow().body.as_ref() {
+            Some(body) => body.size(),
+            None => BodySize::Stream,
+        }
+    }
+
+    fn poll_next(
+        self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+    ) -> Poll<Option<Result<Bytes, Self::Error>>> {
+        let mut state = self.0.borrow_mut();
+        state.waiting_since = None;
+        if state.reaped {
+            return Poll::Ready(Some(Err("client stalled while streaming response".into())));
+        }
+        let Some(body) = state.body.as_mut() else {
+            return Poll::Ready(None);
+        };
+        let poll = Pin::new(body).poll_next(cx);
+        match &poll {
+            Poll::Ready(Some(Ok(_))) => state.waiting_since = Some(Instant::now()),
+            Poll::Ready(_) => state.body = None,
+            Poll::Pending => {}
+        }
+        poll
+    }
+}
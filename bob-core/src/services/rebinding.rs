@@ -0,0 +1,166 @@
+//! DNS Rebinding Protection Middleware
+//!
+//! Rejects requests whose `Host` header does not match the expected
+//! names for the server, or whose host resolves to private address
+//! ranges, preventing DNS rebinding attacks against local services.
+
+use std::{
+    cell::RefCell,
+    collections::HashMap,
+    net::{IpAddr, ToSocketAddrs},
+    rc::Rc,
+    time::{Duration, Instant},
+};
+
+use actix_web::{
+    Error, HttpResponse,
+    body::{EitherBody, MessageBody},
+    dev::{Service, ServiceRequest, ServiceResponse, Transform},
+};
+
+use super::LocalBoxFuture;
+pub use super::is_private;
+use crate::config::{DomainMatch, request_host, split_host};
+
+/// Duration host resolution results are cached for.
+const CACHE_TTL: Duration = Duration::from_secs(60);
+
+/// Maximum number of cached host resolution results.
+const CACHE_SIZE: usize = 1024;
+
+/// DNS rebinding protection settings.
+#[derive(Clone, Debug)]
+pub struct Settings {
+    /// Host names always permitted.
+    pub allowed: Vec<DomainMatch>,
+    /// Permit requests addressed directly to an IP literal.
+    pub allow_ip_hosts: bool,
+    /// Reject hosts resolving to private/loopback/link-local ranges.
+    pub block_private: bool,
+}
+
+/// DNS rebinding protection middleware.
+#[derive(Clone)]
+pub struct Rebinding(Rc<Settings>);
+
+impl Rebinding {
+    /// Create new rebinding protection middleware from settings.
+    pub fn new(settings: Settings) -> Self {
+        Self(Rc::new(settings))
+    }
+}
+
+impl<S, B> Transform<S, ServiceRequest> for Rebinding
+where
+    S: Service<ServiceRequest, Response = ServiceResponse<B>, Error = Error> + 'static,
+    B: MessageBody + 'static,
+{
+    type Response = ServiceResponse<EitherBody<B>>;
+    type Error = Error;
+    type Transform = RebindingService<S>;
+    type InitError = ();
+    type Future = std::future::Ready<Result<Self::Transform, Self::InitError>>;
+
+    fn new_transform(&self, service: S) -> Self::Future {
+        std::future::ready(Ok(RebindingService {
+            service: Rc::new(service),
+            settings: Rc::clone(&self.0),
+            cache: Default::default(),
+        }))
+    }
+}
+
+/// Service produced by [`Rebinding`] middleware.
+pub struct RebindingService<S> {
+    service: Rc<S>,
+    settings: Rc<Settings>,
+    cache: Rc<RefCell<HashMap<String, (Instant, bool)>>>,
+}
+
+impl<S> RebindingService<S> {
+    /// Resolve host and check for private addresses using the cache.
+    ///
+    /// Hosts that fail to resolve are treated as private without being
+    /// cached, so an unverifiable host is never let through.
+    async fn resolves_private(
+        cache: &RefCell<HashMap<String, (Instant, bool)>>,
+        host: String,
+    ) -> bool {
+        if let Some((at, private)) = cache.borrow().get(&host)
+            && at.elapsed() < CACHE_TTL
+        {
+            return *private;
+        }
+        let lookup = host.clone();
+        let resolved = actix_web::web::block(move || {
+            (lookup.as_str(), 0)
+                .to_socket_addrs()
+                .map(|addrs| addrs.map(|addr| addr.ip()).collect::<Vec<_>>())
+        })
+        .await;
+        let private = match resolved {
+            Ok(Ok(addrs)) if !addrs.is_empty() => addrs.iter().any(is_private),
+            Ok(Ok(_)) => {
+                log::debug!("rebinding host {host:?} resolved to no addresses");
+                return true;
+            }
+            Ok(Err(err)) => {
+                log::debug!("rebinding host {host:?} failed to resolve: {err}");
+                return true;
+            }
+            Err(err) => {
+                log::error!("rebinding host {host:?} resolution failed: {err}");
+                return true;
+            }
+        };
+
+        let mut cache = cache.borrow_mut();
+        if cache.len() >= CACHE_SIZE {
+            cache.retain(|_, (at, _)| at.elapsed() < CACHE_TTL);
+        }
+        if cache.len() < CACHE_SIZE {
+            cache.insert(host, (Instant::now(), private));
+        }
+        private
+    }
+}
+
+impl<S, B> Service<ServiceRequest> for RebindingService<S>
+where
+    S: Service<ServiceRequest, Response = ServiceResponse<B>, Error = Error> + 'static,
+    B: MessageBody + 'static,
+{
+    type Response = ServiceResponse<EitherBody<B>>;
+    type Error = Error;
+    type Future = LocalBoxFuture<Result<Self::Response, Self::Error>>;
+
+    actix_web::dev::forward_ready!(service);
+
+    fn call(&self, req: ServiceRequest) -> Self::Future {
+        let service = Rc::clone(&self.service);
+        let settings = Rc::clone(&self.settings);
+        let cache = Rc::clone(&self.cache);
+        Box::pin(async move {
+            let host = request_host(req.head())
+                .map(|h| split_host(h).0.to_ascii_lowercase())
+                .unwrap_or_default();
+
+            let allowed = if settings.allowed.iter().any(|d| d.matches(&host)) {
+                true
+            } else if host.parse::<IpAddr>().is_ok() {
+                settings.allow_ip_hosts
+            } else if !settings.allowed.is_empty() {
+                false
+            } else {
+                !settings.block_private || !Self::resolves_private(&cache, host.clone()).await
+            };
+
+            if !allowed {
+                log::warn!("rejected request for unexpected host {host:?}");
+                let res = HttpResponse::Forbidden().finish();
+                return Ok(req.into_response(res).map_into_right_body());
+            }
+            service.call(req).await.map(|res| res.map_into_left_body())
+        })
+    }
+}
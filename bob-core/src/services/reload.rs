@@ -0,0 +1,175 @@
+//! Hot-Reload Middleware Wrapper
+//!
+//! Rebuilds an inner middleware from its factory whenever a shared
+//! generation counter is bumped, which is driven by a [`Watcher`]
+//! polling a set of files for modifications.
+
+use std::{
+    cell::RefCell,
+    fmt::Debug,
+    path::PathBuf,
+    rc::Rc,
+    sync::{
+        Arc, OnceLock,
+        atomic::{AtomicU64, Ordering},
+    },
+    task::{Context, Poll},
+    time::{Duration, SystemTime},
+};
+
+use actix_web::{
+    Error,
+    dev::{Service, ServiceRequest, Transform},
+};
+
+use super::{LocalBoxFuture, RcService};
+
+/// Shared file modification watcher.
+///
+/// The watcher thread is only spawned once regardless
+/// of how many times the watcher is cloned or started.
+#[derive(Clone, Default)]
+pub struct Watcher(Arc<OnceLock<Arc<AtomicU64>>>);
+
+impl Watcher {
+    /// Start watching files (if not already started) and
+    /// return generation counter bumped on every change.
+    pub fn watch(&self, paths: &[PathBuf], interval: Duration) -> Arc<AtomicU64> {
+        self.0
+            .get_or_init(|| spawn_watcher(paths.to_vec(), interval))
+            .clone()
+    }
+}
+
+impl Debug for Watcher {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "Watcher {{}}")
+    }
+}
+
+#[inline]
+fn modified(paths: &[PathBuf]) -> Vec<Option<SystemTime>> {
+    paths
+        .iter()
+        .map(|p| std::fs::metadata(p).and_then(|m| m.modified()).ok())
+        .collect()
+}
+
+/// Spawn background thread polling file modification times.
+fn spawn_watcher(paths: Vec<PathBuf>, interval: Duration) -> Arc<AtomicU64> {
+    let generation = Arc::new(AtomicU64::new(0));
+    let counter = Arc::clone(&generation);
+    std::thread::Builder::new()
+        .name("bob-watcher".to_owned())
+        .spawn(move || {
+            let mut last = modified(&paths);
+            loop {
+                std::thread::sleep(interval);
+                let current = modified(&paths);
+                if current != last {
+                    log::info!("detected changes in {paths:?}. reloading");
+                    counter.fetch_add(1, Ordering::SeqCst);
+                    last = current;
+                }
+            }
+        })
+        .expect("failed to spawn watcher thread");
+    generation
+}
+
+/// Middleware rebuilt from factory whenever the generation changes.
+pub struct Reload<F> {
+    factory: Rc<F>,
+    generation: Arc<AtomicU64>,
+}
+
+impl<F> Reload<F> {
+    /// Create new reloadable middleware from factory and generation counter.
+    pub fn new(factory: F, generation: Arc<AtomicU64>) -> Self {
+        Self {
+            factory: Rc::new(factory),
+            generation,
+        }
+    }
+}
+
+impl<S, F, T> Transform<S, ServiceRequest> for Reload<F>
+where
+    S: Service<ServiceRequest, Error = Error> + 'static,
+    F: Fn() -> T + 'static,
+    T: Transform<RcService<S>, ServiceRequest, Error = Error, InitError = ()> + 'static,
+    T::Transform: 'static,
+    T::Response: 'static,
+{
+    type Response = T::Response;
+    type Error = Error;
+    type Transform = ReloadService<S, F, T::Transform>;
+    type InitError = ();
+    type Future = LocalBoxFuture<Result<Self::Transform, Self::InitError>>;
+
+    fn new_transform(&self, service: S) -> Self::Future {
+        let service = Rc::new(service);
+        let factory = Rc::clone(&self.factory);
+        let generation = Arc::clone(&self.generation);
+        Box::pin(async move {
+            let current = generation.load(Ordering::SeqCst);
+            let inner = (factory)()
+                .new_transform(RcService(Rc::clone(&service)))
+                .await?;
+            Ok(ReloadService {
+                service,
+                factory,
+                generation,
+                current: Rc::new(RefCell::new((current, Rc::new(inner)))),
+            })
+        })
+    }
+}
+
+/// Service produced by [`Reload`] middleware.
+pub struct ReloadService<S, F, T> {
+    service: Rc<S>,
+    factory: Rc<F>,
+    generation: Arc<AtomicU64>,
+    current: Rc<RefCell<(u64, Rc<T>)>>,
+}
+
+impl<S, F, I, T> Service<ServiceRequest> for ReloadService<S, F, T>
+where
+    S: Service<ServiceRequest, Error = Error> + 'static,
+    F: Fn() -> I + 'static,
+    I: Transform<RcService<S>, ServiceRequest, Transform = T, Error = Error, InitError = ()>,
+    T: Service<ServiceRequest, Error = Error> + 'static,
+{
+    type Response = T::Response;
+    type Error = Error;
+    type Future = LocalBoxFuture<Result<Self::Response, Self::Error>>;
+
+    #[inline]
+    fn poll_ready(&self, cx: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
+        self.service.poll_ready(cx)
+    }
+
+    fn call(&self, req: ServiceRequest) -> Self::Future {
+        let generation = self.generation.load(Ordering::SeqCst);
+        let service = Rc::clone(&self.service);
+        let factory = Rc::clone(&self.factory);
+        let current = Rc::clone(&self.current);
+        Box::pin(async move {
+            let (version, mut inner) = {
+                let current = current.borrow();
+                (current.0, Rc::clone(&current.1))
+            };
+            if version != generation {
+                match (factory)().new_transform(RcService(service)).await {
+                    Ok(service) => {
+                        inner = Rc::new(service);
+                        *current.borrow_mut() = (generation, Rc::clone(&inner));
+                    }
+                    Err(_) => log::error!("failed to rebuild middleware after reload"),
+                }
+            }
+            inner.call(req).await
+        })
+    }
+}
@@ -0,0 +1,146 @@
+//! Request-ID Middleware
+//!
+//! Assigns every request an identifier, either honoring a valid one sent
+//! by the client or generating a new one. The identifier is written back
+//! into the request headers so reverse-proxied upstreams and FastCGI
+//! applications receive it, stored in the request extensions for
+//! logging, and returned to the client in the response.
+
+use std::{
+    collections::hash_map::RandomState,
+    hash::BuildHasher,
+    rc::Rc,
+    sync::{
+        OnceLock,
+        atomic::{AtomicU64, Ordering},
+    },
+    time::{SystemTime, UNIX_EPOCH},
+};
+
+use actix_web::{
+    Error, HttpMessage,
+    body::MessageBody,
+    dev::{Service, ServiceRequest, ServiceResponse, Transform},
+    http::header::{HeaderName, HeaderValue},
+};
+
+use super::LocalBoxFuture;
+
+/// Longest incoming identifier honored.
+const MAX_LENGTH: usize = 200;
+
+/// Identifier assigned to the request.
+///
+/// Inserted into request extensions by [`RequestId`] middleware.
+#[derive(Clone, Debug)]
+pub struct Id(pub String);
+
+/// Request-ID settings.
+#[derive(Clone, Debug)]
+pub struct Settings {
+    /// Header carrying the request identifier.
+    pub header: HeaderName,
+    /// Honor identifiers sent by the client.
+    pub honor_incoming: bool,
+    /// Return the identifier in the response.
+    pub response: bool,
+}
+
+/// Generate a new unique request identifier.
+fn generate() -> String {
+    static SEED: OnceLock<RandomState> = OnceLock::new();
+    static COUNTER: AtomicU64 = AtomicU64::new(0);
+    let seed = SEED.get_or_init(RandomState::new);
+    let count = COUNTER.fetch_add(1, Ordering::Relaxed);
+    let now = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .unwrap_or_default()
+        .as_nanos();
+    let high = seed.hash_one((count, now, 0u8));
+    let low = seed.hash_one((count, now, 1u8));
+    format!("{high:016x}{low:016x}")
+}
+
+/// Check incoming identifier is safe to log and forward.
+#[inline]
+fn valid(id: &str) -> bool {
+    !id.is_empty()
+        && id.len() <= MAX_LENGTH
+        && id
+            .bytes()
+            .all(|b| b.is_ascii_alphanumeric() || matches!(b, b'-' | b'_' | b'.' | b':' | b'/'))
+}
+
+/// Request-ID middleware.
+#[derive(Clone)]
+pub struct RequestId(Rc<Settings>);
+
+impl RequestId {
+    /// Create new request-id middleware from settings.
+    pub fn new(settings: Settings) -> Self {
+        Self(Rc::new(settings))
+    }
+}
+
+impl<S, B> Transform<S, ServiceRequest> for RequestId
+where
+    S: Service<ServiceRequest, Response = ServiceResponse<B>, Error = Error> + 'static,
+    B: MessageBody + 'static,
+{
+    type Response = ServiceResponse<B>;
+    type Error = Error;
+    type Transform = RequestIdService<S>;
+    type InitError = ();
+    type Future = std::future::Ready<Result<Self::Transform, Self::InitError>>;
+
+    fn new_transform(&self, service: S) -> Self::Future {
+        std::future::ready(Ok(RequestIdService {
+            service: Rc::new(service),
+            settings: Rc::clone(&self.0),
+        }))
+    }
+}
+
+/// Service produced by [`RequestId`] middleware.
+pub struct RequestIdService<S> {
+    service: Rc<S>,
+    settings: Rc<Settings>,
+}
+
+impl<S, B> Service<ServiceRequest> for RequestIdService<S>
+where
+    S: Service<ServiceRequest, Response = ServiceResponse<B>, Error = Error> + 'static,
+    B: MessageBody + 'static,
+{
+    type Response = ServiceResponse<B>;
+    type Error = Error;
+    type Future = LocalBoxFuture<Result<Self::Response, Self::Error>>;
+
+    actix_web::dev::forward_ready!(service);
+
+    fn call(&self, mut req: ServiceRequest) -> Self::Future {
+        let settings = Rc::clone(&self.settings);
+        let value = req
+            .headers()
+            .get(&settings.header)
+            .filter(|_| settings.honor_incoming)
+            .filter(|v| v.to_str().is_ok_and(valid))
+            .cloned()
+            .unwrap_or_else(|| {
+                HeaderValue::try_from(generate()).expect("generated request id is valid header")
+            });
+        let id = value.to_str().unwrap_or_default().to_owned();
+        req.headers_mut()
+            .insert(settings.header.clone(), value.clone());
+        req.extensions_mut().insert(Id(id));
+
+        let service = Rc::clone(&self.service);
+        Box::pin(async move {
+            let mut res = service.call(req).await?;
+            if settings.response {
+                res.headers_mut().insert(settings.header.clone(), value);
+            }
+            Ok(res)
+        })
+    }
+}
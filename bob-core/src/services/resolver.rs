@@ -0,0 +1,155 @@
+//! Upstream DNS Resolver
+//!
+//! Resolves upstream hosts through the system's configured nameservers,
+//! reusing the resolved addresses only as long as their DNS TTL allows so
+//! upstreams changing addresses are picked up on the next connection.
+//! When resolution fails, the last successfully resolved addresses or
+//! statically configured ones are used instead, so resolver outages don't
+//! take down otherwise healthy upstreams.
+
+use std::{
+    cell::{OnceCell, RefCell},
+    collections::HashMap,
+    net::{IpAddr, SocketAddr},
+    rc::Rc,
+    time::{Duration, Instant},
+};
+
+use actix_tls::connect::Resolve;
+use futures_core::future::LocalBoxFuture;
+use hickory_resolver::{
+    TokioAsyncResolver,
+    config::{ResolverConfig, ResolverOpts},
+};
+
+/// Upstream resolver settings.
+#[derive(Clone, Debug, Default)]
+pub struct Settings {
+    /// Static addresses used when resolution fails.
+    pub fallback: Vec<IpAddr>,
+    /// Reuse the last successfully resolved addresses when resolution fails.
+    pub last_known: bool,
+    /// Longest time resolved addresses are reused regardless of their TTL.
+    pub max_ttl: Option<Duration>,
+}
+
+/// Build an async resolver from the system configuration.
+///
+/// Falls back to the default public resolver configuration when
+/// the system configuration cannot be read.
+pub fn system_resolver() -> TokioAsyncResolver {
+    TokioAsyncResolver::tokio_from_system_conf().unwrap_or_else(|err| {
+        log::warn!("failed to read system dns config ({err}). using defaults");
+        TokioAsyncResolver::tokio(ResolverConfig::default(), ResolverOpts::default())
+    })
+}
+
+/// Resolve the host into its addresses and the instant they expire.
+pub async fn resolve(
+    resolver: &TokioAsyncResolver,
+    host: &str,
+) -> Result<(Vec<IpAddr>, Instant), String> {
+    let lookup = resolver
+        .lookup_ip(host)
+        .await
+        .map_err(|err| err.to_string())?;
+    let addrs: Vec<IpAddr> = lookup.iter().collect();
+    match addrs.is_empty() {
+        true => Err("no addresses found".to_owned()),
+        false => Ok((addrs, lookup.valid_until())),
+    }
+}
+
+/// Addresses resolved for a host.
+#[derive(Debug)]
+struct Cached {
+    addrs: Vec<IpAddr>,
+    expires: Instant,
+}
+
+/// TTL respecting DNS resolver with last-known and static fallback addresses.
+#[derive(Clone, Default)]
+pub struct UpstreamResolver {
+    settings: Rc<Settings>,
+    dns: Rc<OnceCell<TokioAsyncResolver>>,
+    cache: Rc<RefCell<HashMap<String, Cached>>>,
+}
+
+impl UpstreamResolver {
+    /// Create new upstream resolver from settings.
+    pub fn new(settings: Settings) -> Self {
+        Self {
+            settings: Rc::new(settings),
+            dns: Default::default(),
+            cache: Default::default(),
+        }
+    }
+
+    /// Retrieve cached addresses for host if they have not expired.
+    fn cached(&self, host: &str, now: Instant) -> Option<Vec<IpAddr>> {
+        self.cache
+            .borrow()
+            .get(host)
+            .filter(|cached| cached.expires > now)
+            .map(|cached| cached.addrs.clone())
+    }
+
+    /// Retrieve fallback addresses for host when resolution failed.
+    fn fallback(&self, host: &str) -> Option<Vec<IpAddr>> {
+        if self.settings.last_known
+            && let Some(cached) = self.cache.borrow().get(host)
+        {
+            return Some(cached.addrs.clone());
+        }
+        let addrs = self.settings.fallback.clone();
+        (!addrs.is_empty()).then_some(addrs)
+    }
+}
+
+impl Resolve for UpstreamResolver {
+    fn lookup<'a>(
+        &'a self,
+        host: &'a str,
+        port: u16,
+    ) -> LocalBoxFuture<'a, Result<Vec<SocketAddr>, Box<dyn std::error::Error>>> {
+        Box::pin(async move {
+            let with_port = |addrs: Vec<IpAddr>| {
+                addrs
+                    .into_iter()
+                    .map(|ip| SocketAddr::new(ip, port))
+                    .collect::<Vec<_>>()
+            };
+            if let Ok(ip) = host.parse::<IpAddr>() {
+                return Ok(with_port(vec![ip]));
+            }
+            let now = Instant::now();
+            if let Some(addrs) = self.cached(host, now) {
+                return Ok(with_port(addrs));
+            }
+
+            let dns = self.dns.get_or_init(system_resolver);
+            match resolve(dns, host).await {
+                Ok((addrs, valid_until)) => {
+                    let expires = match self.settings.max_ttl {
+                        Some(max) => valid_until.min(now + max),
+                        None => valid_until,
+                    };
+                    log::trace!("resolved {host:?} to {addrs:?} until {expires:?}");
+                    let cached = Cached {
+                        addrs: addrs.clone(),
+                        expires,
+                    };
+                    self.cache.borrow_mut().insert(host.to_owned(), cached);
+                    Ok(with_port(addrs))
+                }
+                Err(error) => match self.fallback(host) {
+                    Some(addrs) => {
+                        log::warn!("failed to resolve {host:?} ({error}). using {addrs:?}");
+                        Ok(with_port(addrs))
+                    }
+                    None => Err(error.into()),
+                },
+            }
+        })
+    }
+}
@@ -0,0 +1,261 @@
+//! Upstream Retry Middleware
+//!
+//! Retries proxied requests failing with a transient upstream error.
+//! Only idempotent methods are retried unless the policy opts in.
+//! Request bodies are buffered so they can be replayed, which limits
+//! retries to requests with a body no larger than [`MAX_REPLAY_BODY`];
+//! larger bodies are streamed to a single attempt.
+//! Errors raised by the proxy itself are classified as connection errors
+//! or timeouts while upstream replies are matched by status code.
+
+use std::{
+    pin::Pin,
+    rc::Rc,
+    task::{Context, Poll},
+    time::Duration,
+};
+
+use actix_web::{
+    Error,
+    body::MessageBody,
+    dev::{Payload, Service, ServiceRequest, ServiceResponse, Transform},
+    error::PayloadError,
+    http::{Method, StatusCode, header},
+    web::{Bytes, BytesMut},
+};
+use futures_core::Stream;
+
+use super::LocalBoxFuture;
+
+/// Largest request body buffered for replay.
+pub const MAX_REPLAY_BODY: u64 = 1024 * 1024;
+
+/// Upstream failure eligible for retry.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Failure {
+    /// Connecting to or communicating with the upstream failed.
+    Connect,
+    /// The upstream did not respond in time.
+    Timeout,
+    /// The upstream replied with the status.
+    Status(StatusCode),
+}
+
+/// Upstream retry policy.
+#[derive(Clone, Debug, Default)]
+pub struct Policy {
+    /// Number of additional attempts made after the first.
+    pub retries: u32,
+    /// Failures retried.
+    pub retry_on: Vec<Failure>,
+    /// Time allowed for each individual attempt.
+    pub try_timeout: Option<Duration>,
+    /// Retry methods that are not idempotent such as `POST` and `PATCH`.
+    pub non_idempotent: bool,
+}
+
+impl Policy {
+    /// Check if the failure is retried by the policy.
+    #[inline]
+    pub fn covers(&self, failure: Failure) -> bool {
+        self.retry_on.contains(&failure)
+    }
+
+    /// Check if requests using the method may be retried.
+    pub fn permits(&self, method: &Method) -> bool {
+        self.non_idempotent
+            || matches!(
+                *method,
+                Method::GET
+                    | Method::HEAD
+                    | Method::PUT
+                    | Method::DELETE
+                    | Method::OPTIONS
+                    | Method::TRACE
+            )
+    }
+}
+
+/// Check if the request body can be buffered for replay.
+pub fn replayable(headers: &header::HeaderMap) -> bool {
+    if headers.contains_key(header::TRANSFER_ENCODING) {
+        return false;
+    }
+    match headers.get(header::CONTENT_LENGTH) {
+        None => true,
+        Some(len) => len
+            .to_str()
+            .ok()
+            .and_then(|len| len.parse::<u64>().ok())
+            .is_some_and(|len| len <= MAX_REPLAY_BODY),
+    }
+}
+
+/// Classify an error raised by the proxy service.
+fn classify_error(status: StatusCode) -> Failure {
+    match status {
+        StatusCode::GATEWAY_TIMEOUT | StatusCode::REQUEST_TIMEOUT => Failure::Timeout,
+        _ => Failure::Connect,
+    }
+}
+
+/// Buffer the complete request payload for replay.
+pub async fn buffer(mut payload: Payload) -> Result<actix_web::web::Bytes, Error> {
+    let mut data = BytesMut::new();
+    while let Some(chunk) = std::future::poll_fn(|cx| Pin::new(&mut payload).poll_next(cx)).await {
+        let chunk = chunk?;
+        if (data.len() + chunk.len()) as u64 > MAX_REPLAY_BODY {
+            return Err(actix_web::error::ErrorPayloadTooLarge(
+                "request body too large",
+            ));
+        }
+        data.extend_from_slice(&chunk);
+    }
+    Ok(data.freeze())
+}
+
+/// Request body read for replay.
+pub enum Replay {
+    /// Complete body buffered for every attempt.
+    Buffered(Bytes),
+    /// Body larger than [`MAX_REPLAY_BODY`] which can only be sent once.
+    Streamed(Payload),
+}
+
+/// Buffer the request payload for replay when it fits [`MAX_REPLAY_BODY`].
+///
+/// Larger bodies are returned as a payload replaying the data already
+/// read ahead of the rest of the stream.
+pub async fn read(mut payload: Payload) -> Result<Replay, Error> {
+    let mut data = BytesMut::new();
+    while let Some(chunk) = std::future::poll_fn(|cx| Pin::new(&mut payload).poll_next(cx)).await {
+        let chunk = chunk?;
+        data.extend_from_slice(&chunk);
+        if data.len() as u64 > MAX_REPLAY_BODY {
+            let stream = Prefixed {
+                head: Some(data.freeze()),
+                rest: payload,
+            };
+            return Ok(Replay::Streamed(Payload::Stream {
+                payload: Box::pin(stream),
+            }));
+        }
+    }
+    Ok(Replay::Buffered(data.freeze()))
+}
+
+/// Payload yielding already read data before the remaining stream.
+struct Prefixed {
+    head: Option<Bytes>,
+    rest: Payload,
+}
+
+impl Stream for Prefixed {
+    type Item = Result<Bytes, PayloadError>;
+
+    fn poll_next(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Self::Item>> {
+        if let Some(head) = self.head.take() {
+            return Poll::Ready(Some(Ok(head)));
+        }
+        Pin::new(&mut self.rest).poll_next(cx)
+    }
+}
+
+/// Upstream retry middleware.
+#[derive(Clone)]
+pub struct Retry(Rc<Policy>);
+
+impl Retry {
+    /// Create new upstream retry middleware from the policy.
+    pub fn new(policy: Policy) -> Self {
+        Self(Rc::new(policy))
+    }
+}
+
+impl<S, B> Transform<S, ServiceRequest> for Retry
+where
+    S: Service<ServiceRequest, Response = ServiceResponse<B>, Error = Error> + 'static,
+    B: MessageBody + 'static,
+{
+    type Response = ServiceResponse<B>;
+    type Error = Error;
+    type Transform = RetryService<S>;
+    type InitError = ();
+    type Future = std::future::Ready<Result<Self::Transform, Self::InitError>>;
+
+    fn new_transform(&self, service: S) -> Self::Future {
+        std::future::ready(Ok(RetryService {
+            service: Rc::new(service),
+            policy: Rc::clone(&self.0),
+        }))
+    }
+}
+
+/// Service produced by [`Retry`] middleware.
+pub struct RetryService<S> {
+    service: Rc<S>,
+    policy: Rc<Policy>,
+}
+
+impl<S, B> Service<ServiceRequest> for RetryService<S>
+where
+    S: Service<ServiceRequest, Response = ServiceResponse<B>, Error = Error> + 'static,
+    B: MessageBody + 'static,
+{
+    type Response = ServiceResponse<B>;
+    type Error = Error;
+    type Future = LocalBoxFuture<Result<Self::Response, Self::Error>>;
+
+    actix_web::dev::forward_ready!(service);
+
+    fn call(&self, req: ServiceRequest) -> Self::Future {
+        let service = Rc::clone(&self.service);
+        let policy = Rc::clone(&self.policy);
+        Box::pin(async move {
+            if policy.retries == 0 || !policy.permits(req.method()) || !replayable(req.headers()) {
+                return service.call(req).await;
+            }
+            let (http, payload) = req.into_parts();
+            let body = match read(payload).await? {
+                Replay::Buffered(body) => body,
+                Replay::Streamed(payload) => {
+                    log::debug!("request body too large to replay. not retrying");
+                    return service
+                        .call(ServiceRequest::from_parts(http, payload))
+                        .await;
+                }
+            };
+
+            let mut attempt = 0;
+            loop {
+                let req = ServiceRequest::from_parts(http.clone(), Payload::from(body.clone()));
+                let call = service.call(req);
+                let result = match policy.try_timeout {
+                    Some(limit) => match actix_web::rt::time::timeout(limit, call).await {
+                        Ok(result) => result,
+                        Err(_) => Err(actix_web::error::ErrorGatewayTimeout(
+                            "upstream attempt timed out",
+                        )),
+                    },
+                    None => call.await,
+                };
+                let failure = match &result {
+                    Ok(res) => match res.response().error() {
+                        Some(err) => classify_error(err.as_response_error().status_code()),
+                        None => Failure::Status(res.status()),
+                    },
+                    Err(err) => classify_error(err.as_response_error().status_code()),
+                };
+                if attempt >= policy.retries || !policy.covers(failure) {
+                    return result;
+                }
+                attempt += 1;
+                log::warn!(
+                    "retrying {} {} after upstream failure {failure:?} (attempt {attempt})",
+                    http.method(),
+                    http.path()
+                );
+            }
+        })
+    }
+}
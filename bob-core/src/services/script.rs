@@ -0,0 +1,288 @@
+//! Embedded Scripting Middleware
+//!
+//! Evaluates sandboxed [Rhai](https://rhai.rs) snippets against every
+//! request and response for the long tail of header and redirect rules
+//! declarative configuration can't express.
+//!
+//! The request script sees a `request` object map with `method`, `path`,
+//! `query`, `host`, `peer` and `headers` entries. Changes to
+//! `request.headers` are applied before the request is passed on, and
+//! assigning a `response` map answers the request directly:
+//!
+//! ```rhai
+//! if request.headers["x-legacy"] == "1" {
+//!     response = redirect(308, "/v2" + request.path);
+//! }
+//! request.headers["x-script"] = "seen";
+//! ```
+//!
+//! The response script additionally sees a `response` map with `status`
+//! and `headers` entries whose changes are applied to the response.
+
+use std::{rc::Rc, sync::Arc};
+
+use actix_web::{
+    Error, HttpResponse,
+    body::{BoxBody, MessageBody},
+    dev::{Service, ServiceRequest, ServiceResponse, Transform},
+    http::{
+        StatusCode,
+        header::{HeaderMap, HeaderName, HeaderValue},
+    },
+};
+use anyhow::anyhow;
+use rhai::{AST, Dynamic, Engine, Map, Scope};
+
+use super::LocalBoxFuture;
+
+/// Convert headers into a script map of lowercase names.
+///
+/// Repeated headers are joined with a comma.
+fn headers_map(headers: &HeaderMap) -> Map {
+    let mut map = Map::new();
+    for name in headers.keys() {
+        let value: Vec<&str> = headers
+            .get_all(name)
+            .filter_map(|v| v.to_str().ok())
+            .collect();
+        map.insert(name.as_str().into(), value.join(", ").into());
+    }
+    map
+}
+
+/// Apply changes made to a script header map.
+fn apply_headers(headers: &mut HeaderMap, original: &Map, map: &Map) {
+    for name in original
+        .keys()
+        .filter(|name| !map.contains_key(name.as_str()))
+    {
+        if let Ok(name) = HeaderName::from_bytes(name.as_bytes()) {
+            headers.remove(name);
+        }
+    }
+    for (name, value) in map {
+        let value = value.to_string();
+        if original.get(name).is_some_and(|v| v.to_string() == value) {
+            continue;
+        }
+        match (
+            HeaderName::from_bytes(name.as_bytes()),
+            HeaderValue::from_str(&value),
+        ) {
+            (Ok(name), Ok(value)) => {
+                headers.insert(name, value);
+            }
+            _ => log::warn!("script set invalid header {name:?}: {value:?}"),
+        }
+    }
+}
+
+/// Build response from a script response map.
+fn build_response(map: Map) -> HttpResponse {
+    let status = map
+        .get("status")
+        .and_then(|v| v.as_int().ok())
+        .and_then(|code| StatusCode::from_u16(code as u16).ok())
+        .unwrap_or(StatusCode::OK);
+    let mut res = HttpResponse::build(status);
+    if let Some(headers) = map.get("headers").and_then(|v| v.read_lock::<Map>()) {
+        for (name, value) in headers.iter() {
+            res.insert_header((name.as_str(), value.to_string()));
+        }
+    }
+    match map.get("body") {
+        Some(body) if !body.is_unit() => res.body(body.to_string()),
+        _ => res.finish(),
+    }
+}
+
+/// Response map answering with the status and body.
+fn respond(status: i64, body: &str) -> Map {
+    let mut map = Map::new();
+    map.insert("status".into(), status.into());
+    map.insert("body".into(), body.into());
+    map
+}
+
+/// Response map redirecting to the location.
+fn redirect(status: i64, location: &str) -> Map {
+    let mut headers = Map::new();
+    headers.insert("location".into(), location.into());
+    let mut map = Map::new();
+    map.insert("status".into(), status.into());
+    map.insert("headers".into(), headers.into());
+    map
+}
+
+/// Compiled request and response scripts shared between every worker.
+pub struct Scripts {
+    engine: Engine,
+    request: Option<AST>,
+    response: Option<AST>,
+}
+
+impl Scripts {
+    /// Compile scripts within a sandboxed engine.
+    pub fn compile(
+        request: Option<&str>,
+        response: Option<&str>,
+        max_operations: u64,
+    ) -> anyhow::Result<Self> {
+        let mut engine = Engine::new();
+        engine
+            .set_max_operations(max_operations)
+            .set_max_call_levels(32)
+            .set_max_expr_depths(64, 32)
+            .set_max_string_size(1024 * 1024)
+            .set_max_array_size(10_000)
+            .set_max_map_size(10_000);
+        engine.on_print(|text| log::info!("script: {text}"));
+        engine.on_debug(|text, _, pos| log::debug!("script {pos}: {text}"));
+        engine.register_fn("respond", respond);
+        engine.register_fn("redirect", redirect);
+
+        let compile = |kind: &str, source: Option<&str>| {
+            source
+                .map(|source| engine.compile(source))
+                .transpose()
+                .map_err(|err| anyhow!("invalid {kind} script: {err}"))
+        };
+        let request = compile("request", request)?;
+        let response = compile("response", response)?;
+        if request.is_none() && response.is_none() {
+            return Err(anyhow!("script requires a request or response script"));
+        }
+        Ok(Self {
+            engine,
+            request,
+            response,
+        })
+    }
+}
+
+/// Scripting middleware.
+#[derive(Clone)]
+pub struct Script(Arc<Scripts>);
+
+impl Script {
+    /// Create new scripting middleware from compiled scripts.
+    pub fn new(scripts: Arc<Scripts>) -> Self {
+        Self(scripts)
+    }
+}
+
+impl<S, B> Transform<S, ServiceRequest> for Script
+where
+    S: Service<ServiceRequest, Response = ServiceResponse<B>, Error = Error> + 'static,
+    B: MessageBody + 'static,
+{
+    type Response = ServiceResponse<BoxBody>;
+    type Error = Error;
+    type Transform = ScriptService<S>;
+    type InitError = ();
+    type Future = std::future::Ready<Result<Self::Transform, Self::InitError>>;
+
+    fn new_transform(&self, service: S) -> Self::Future {
+        std::future::ready(Ok(ScriptService {
+            service: Rc::new(service),
+            scripts: Arc::clone(&self.0),
+        }))
+    }
+}
+
+/// Service produced by [`Script`] middleware.
+pub struct ScriptService<S> {
+    service: Rc<S>,
+    scripts: Arc<Scripts>,
+}
+
+/// Script object describing the request.
+fn request_map(req: &ServiceRequest) -> Map {
+    let info = req.connection_info();
+    let mut map = Map::new();
+    map.insert("method".into(), req.method().as_str().into());
+    map.insert("path".into(), req.path().into());
+    map.insert("query".into(), req.query_string().into());
+    map.insert("host".into(), info.host().into());
+    let peer = info.realip_remote_addr().unwrap_or_default();
+    map.insert("peer".into(), peer.into());
+    map.insert("headers".into(), headers_map(req.headers()).into());
+    map
+}
+
+/// Header map stored within a script object.
+#[inline]
+fn get_headers(map: &Map) -> Map {
+    map.get("headers")
+        .and_then(|v| v.read_lock::<Map>().map(|m| m.clone()))
+        .unwrap_or_default()
+}
+
+impl<S, B> Service<ServiceRequest> for ScriptService<S>
+where
+    S: Service<ServiceRequest, Response = ServiceResponse<B>, Error = Error> + 'static,
+    B: MessageBody + 'static,
+{
+    type Response = ServiceResponse<BoxBody>;
+    type Error = Error;
+    type Future = LocalBoxFuture<Result<Self::Response, Self::Error>>;
+
+    actix_web::dev::forward_ready!(service);
+
+    fn call(&self, mut req: ServiceRequest) -> Self::Future {
+        let service = Rc::clone(&self.service);
+        let scripts = Arc::clone(&self.scripts);
+        Box::pin(async move {
+            let request = request_map(&req);
+            let mut scope = Scope::new();
+            scope.push("request", request.clone());
+            scope.push("response", Dynamic::UNIT);
+
+            if let Some(ast) = scripts.request.as_ref() {
+                if let Err(err) = scripts.engine.run_ast_with_scope(&mut scope, ast) {
+                    log::error!("request script failed: {err}");
+                    let res = HttpResponse::InternalServerError().finish();
+                    return Ok(req.into_response(res));
+                }
+                if let Some(map) = scope.get_value::<Map>("response") {
+                    return Ok(req.into_response(build_response(map)));
+                }
+                if let Some(map) = scope.get_value::<Map>("request") {
+                    let original = get_headers(&request);
+                    apply_headers(req.headers_mut(), &original, &get_headers(&map));
+                }
+            }
+
+            let mut res = service.call(req).await?.map_into_boxed_body();
+            let Some(ast) = scripts.response.as_ref() else {
+                return Ok(res);
+            };
+
+            let mut response = Map::new();
+            let status = res.status().as_u16() as i64;
+            response.insert("status".into(), status.into());
+            response.insert("headers".into(), headers_map(res.headers()).into());
+            scope.set_value("response", response.clone());
+            if let Err(err) = scripts.engine.run_ast_with_scope(&mut scope, ast) {
+                log::error!("response script failed: {err}");
+                let (req, _) = res.into_parts();
+                let res = HttpResponse::InternalServerError().finish();
+                return Ok(ServiceResponse::new(req, res));
+            }
+            let Some(map) = scope.get_value::<Map>("response") else {
+                return Ok(res);
+            };
+            if let Some(status) = map
+                .get("status")
+                .and_then(|v| v.as_int().ok())
+                .filter(|code| *code != status)
+                .and_then(|code| StatusCode::from_u16(code as u16).ok())
+            {
+                *res.response_mut().status_mut() = status;
+            }
+            let original = get_headers(&response);
+            apply_headers(res.headers_mut(), &original, &get_headers(&map));
+            Ok(res)
+        })
+    }
+}
@@ -0,0 +1,155 @@
+//! Slow Request Body Protection
+//!
+//! Drops requests whose body is trickled in slower than a minimum
+//! transfer-rate or takes longer than a maximum read time, protecting
+//! against slowloris-style connection exhaustion.
+
+use std::{
+    future::Future,
+    pin::Pin,
+    rc::Rc,
+    task::{Context, Poll},
+    time::{Duration, Instant},
+};
+
+use actix_web::{
+    Error,
+    dev::{Payload, Service, ServiceRequest, ServiceResponse, Transform},
+    error::PayloadError,
+    rt::time::{Sleep, sleep},
+    web::Bytes,
+};
+use futures_core::Stream;
+
+use super::RcService;
+
+/// Interval between transfer-rate checks.
+const CHECK_INTERVAL: Duration = Duration::from_secs(1);
+
+/// Slow request body settings.
+#[derive(Clone, Debug)]
+pub struct Settings {
+    /// Maximum time allowed to read the complete body.
+    pub body_timeout: Option<Duration>,
+    /// Minimum body transfer-rate in bytes per second.
+    pub min_rate: Option<u64>,
+    /// Time allowed before the transfer-rate is enforced.
+    pub grace: Duration,
+}
+
+/// Slow request body protection middleware.
+#[derive(Clone)]
+pub struct SlowClient(Rc<Settings>);
+
+impl SlowClient {
+    /// Create new slow request protection middleware from settings.
+    pub fn new(settings: Settings) -> Self {
+        Self(Rc::new(settings))
+    }
+}
+
+impl<S, B> Transform<S, ServiceRequest> for SlowClient
+where
+    S: Service<ServiceRequest, Response = ServiceResponse<B>, Error = Error> + 'static,
+{
+    type Response = ServiceResponse<B>;
+    type Error = Error;
+    type Transform = SlowClientService<S>;
+    type InitError = ();
+    type Future = std::future::Ready<Result<Self::Transform, Self::InitError>>;
+
+    fn new_transform(&self, service: S) -> Self::Future {
+        std::future::ready(Ok(SlowClientService {
+            service: RcService(Rc::new(service)),
+            settings: Rc::clone(&self.0),
+        }))
+    }
+}
+
+/// Service produced by [`SlowClient`] middleware.
+pub struct SlowClientService<S> {
+    service: RcService<S>,
+    settings: Rc<Settings>,
+}
+
+impl<S, B> Service<ServiceRequest> for SlowClientService<S>
+where
+    S: Service<ServiceRequest, Response = ServiceResponse<B>, Error = Error> + 'static,
+{
+    type Response = ServiceResponse<B>;
+    type Error = Error;
+    type Future = S::Future;
+
+    actix_web::dev::forward_ready!(service);
+
+    fn call(&self, mut req: ServiceRequest) -> Self::Future {
+        let payload = RatePayload {
+            payload: req.take_payload(),
+            settings: Rc::clone(&self.settings),
+            started: Instant::now(),
+            received: 0,
+            timer: Box::pin(sleep(CHECK_INTERVAL)),
+        };
+        req.set_payload(Payload::Stream {
+            payload: Box::pin(payload),
+        });
+        self.service.call(req)
+    }
+}
+
+/// Payload stream wrapper enforcing read time and transfer-rate.
+struct RatePayload {
+    payload: Payload,
+    settings: Rc<Settings>,
+    started: Instant,
+    received: u64,
+    timer: Pin<Box<Sleep>>,
+}
+
+impl RatePayload {
+    /// Check transfer progress returning an error when too slow.
+    fn check(&self) -> Result<(), PayloadError> {
+        let elapsed = self.started.elapsed();
+        if self.settings.body_timeout.is_some_and(|t| elapsed > t) {
+            return Err(timed_out("request body read timeout"));
+        }
+        if let Some(min_rate) = self.settings.min_rate
+            && elapsed > self.settings.grace
+            && (self.received as f64 / elapsed.as_secs_f64()) < min_rate as f64
+        {
+            return Err(timed_out("request body below minimum transfer-rate"));
+        }
+        Ok(())
+    }
+}
+
+#[inline]
+fn timed_out(msg: &str) -> PayloadError {
+    log::debug!("{msg}. dropping connection");
+    PayloadError::Io(std::io::Error::new(std::io::ErrorKind::TimedOut, msg))
+}
+
+impl Stream for RatePayload {
+    type Item = Result<Bytes, PayloadError>;
+
+    fn poll_next(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Self::Item>> {
+        let this = self.get_mut();
+        match Pin::new(&mut this.payload).poll_next(cx) {
+            Poll::Ready(Some(Ok(chunk))) => {
+                this.received += chunk.len() as u64;
+                Poll::Ready(Some(Ok(chunk)))
+            }
+            Poll::Pending => {
+                while this.timer.as_mut().poll(cx).is_ready() {
+                    if let Err(err) = this.check() {
+                        return Poll::Ready(Some(Err(err)));
+                    }
+                    let next = Instant::now() + CHECK_INTERVAL;
+                    this.timer.as_mut().reset(next.into());
+                }
+                Poll::Pending
+            }
+            poll => poll,
+        }
+    }
+}
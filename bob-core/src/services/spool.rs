@@ -0,0 +1,238 @@
+//! Spooled Body Buffering
+//!
+//! Retains streamed bodies in memory up to a limit and spills anything
+//! beyond it to a temporary file, so middleware inspecting complete
+//! bodies can replay them afterwards without holding them in memory.
+
+use std::{
+    error::Error as StdError,
+    fs::File,
+    future::Future,
+    io::{self, Read, Seek, Write},
+    path::PathBuf,
+    pin::Pin,
+    task::{Context, Poll, ready},
+};
+
+use actix_web::{
+    Error,
+    body::MessageBody,
+    error::ErrorInternalServerError,
+    rt::task::{JoinHandle, spawn_blocking},
+    web::{self, Bytes, BytesMut},
+};
+use futures_core::Stream;
+
+/// Size of chunks read back from spill files.
+const CHUNK_SIZE: usize = 64 * 1024;
+
+/// Spool buffering settings.
+#[derive(Clone, Debug)]
+pub struct Settings {
+    /// Bytes retained in memory before spilling to disk.
+    pub memory_limit: usize,
+    /// Directory spill files are created in.
+    pub directory: PathBuf,
+}
+
+/// Body buffer retaining chunks in memory and spilling to disk.
+#[derive(Debug)]
+pub struct Spool {
+    settings: Settings,
+    memory: BytesMut,
+    spill: Option<File>,
+    len: usize,
+}
+
+impl Spool {
+    /// Create new empty spool.
+    pub fn new(settings: Settings) -> Self {
+        Self {
+            settings,
+            memory: BytesMut::new(),
+            spill: None,
+            len: 0,
+        }
+    }
+
+    /// Total number of bytes retained.
+    #[inline]
+    pub fn size(&self) -> usize {
+        self.len
+    }
+
+    /// Retain the next body chunk.
+    pub async fn push(&mut self, chunk: Bytes) -> io::Result<()> {
+        self.len += chunk.len();
+        if self.spill.is_none() && self.memory.len() + chunk.len() <= self.settings.memory_limit {
+            self.memory.extend_from_slice(&chunk);
+            return Ok(());
+        }
+        let file = match self.spill.take() {
+            Some(file) => file,
+            None => {
+                // unnamed owner-only file removed by the os once closed
+                let directory = self.settings.directory.clone();
+                log::debug!("spilling body to {directory:?}");
+                web::block(move || tempfile::tempfile_in(directory))
+                    .await
+                    .map_err(io::Error::other)??
+            }
+        };
+        let file = web::block(move || {
+            let mut file = file;
+            file.write_all(&chunk)?;
+            Ok::<_, io::Error>(file)
+        })
+        .await
+        .map_err(io::Error::other)??;
+        self.spill = Some(file);
+        Ok(())
+    }
+
+    /// Complete buffering and replay the retained body as a stream.
+    pub async fn finish(self) -> io::Result<Replay> {
+        let spill = match self.spill {
+            Some(file) => {
+                let file = web::block(move || {
+                    let mut file = file;
+                    file.flush()?;
+                    file.rewind()?;
+                    Ok::<_, io::Error>(file)
+                })
+                .await
+                .map_err(io::Error::other)??;
+                Some(Reader::Idle(file))
+            }
+            None => None,
+        };
+        Ok(Replay {
+            size: self.len as u64,
+            memory: (!self.memory.is_empty()).then(|| self.memory.freeze()),
+            spill,
+        })
+    }
+}
+
+/// Spill file read state.
+enum Reader {
+    Idle(File),
+    Reading(JoinHandle<io::Result<(File, Bytes)>>),
+    Done,
+}
+
+/// Stream replaying a spooled body.
+pub struct Replay {
+    size: u64,
+    memory: Option<Bytes>,
+    spill: Option<Reader>,
+}
+
+impl Stream for Replay {
+    type Item = io::Result<Bytes>;
+
+    fn poll_next(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Self::Item>> {
+        let this = self.get_mut();
+        if let Some(memory) = this.memory.take() {
+            return Poll::Ready(Some(Ok(memory)));
+        }
+        let Some(reader) = this.spill.as_mut() else {
+            return Poll::Ready(None);
+        };
+        loop {
+            match std::mem::replace(reader, Reader::Done) {
+                Reader::Idle(mut file) => {
+                    *reader = Reader::Reading(spawn_blocking(move || {
+                        let mut buf = vec![0; CHUNK_SIZE];
+                        let n = file.read(&mut buf)?;
+                        buf.truncate(n);
+                        Ok((file, Bytes::from(buf)))
+                    }));
+                }
+                Reader::Reading(mut handle) => {
+                    let result = match Pin::new(&mut handle).poll(cx) {
+                        Poll::Ready(result) => result,
+                        Poll::Pending => {
+                            *reader = Reader::Reading(handle);
+                            return Poll::Pending;
+                        }
+                    };
+                    let (file, chunk) = match result.map_err(io::Error::other) {
+                        Ok(Ok(read)) => read,
+                        Ok(Err(err)) | Err(err) => return Poll::Ready(Some(Err(err))),
+                    };
+                    if chunk.is_empty() {
+                        this.spill = None;
+                        return Poll::Ready(None);
+                    }
+                    *reader = Reader::Idle(file);
+                    return Poll::Ready(Some(Ok(chunk)));
+                }
+                Reader::Done => return Poll::Ready(None),
+            }
+        }
+    }
+}
+
+/// Stream replaying a spooled body followed by the remaining body stream.
+pub struct Chain<S> {
+    replay: Option<Replay>,
+    rest: Option<S>,
+}
+
+impl<S> Chain<S> {
+    /// Replay the spooled body before continuing with the remaining stream.
+    pub fn new(replay: Replay, rest: Option<S>) -> Self {
+        Self {
+            replay: Some(replay),
+            rest,
+        }
+    }
+
+    /// Total body size if known ahead of time.
+    pub fn size(&self) -> Option<u64> {
+        match self.rest {
+            Some(_) => None,
+            None => self.replay.as_ref().map(|replay| replay.size),
+        }
+    }
+}
+
+impl<S, E> Stream for Chain<S>
+where
+    S: Stream<Item = Result<Bytes, E>> + Unpin,
+    E: From<io::Error>,
+{
+    type Item = Result<Bytes, E>;
+
+    fn poll_next(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Self::Item>> {
+        let this = self.get_mut();
+        if let Some(replay) = this.replay.as_mut() {
+            match ready!(Pin::new(replay).poll_next(cx)) {
+                Some(chunk) => return Poll::Ready(Some(chunk.map_err(E::from))),
+                None => this.replay = None,
+            }
+        }
+        match this.rest.as_mut() {
+            Some(rest) => Pin::new(rest).poll_next(cx),
+            None => Poll::Ready(None),
+        }
+    }
+}
+
+/// Adapts a [`MessageBody`] into a chunk stream.
+pub struct BodyChunks<B>(pub Pin<Box<B>>);
+
+impl<B> Stream for BodyChunks<B>
+where
+    B: MessageBody,
+    B::Error: Into<Box<dyn StdError>>,
+{
+    type Item = Result<Bytes, Error>;
+
+    fn poll_next(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Self::Item>> {
+        self.0.as_mut().poll_next(cx).map(|chunk| {
+            chunk.map(|chunk| chunk.map_err(|err| ErrorInternalServerError(err.into())))
+        })
+    }
+}
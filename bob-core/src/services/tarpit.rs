@@ -0,0 +1,194 @@
+//! Request Tarpit Middleware
+//!
+//! Holds requests from matching clients (bad bots, abusive ranges and
+//! banned addresses) for a configured delay before answering them, slowing
+//! down scanners far more than a fast `403 Forbidden` would. The number of
+//! requests held at once is capped so the tarpit cannot exhaust the server.
+
+use std::{
+    net::IpAddr,
+    rc::Rc,
+    sync::{
+        Arc,
+        atomic::{AtomicUsize, Ordering},
+    },
+    time::Duration,
+};
+
+use actix_web::{
+    Error, HttpResponse,
+    body::{EitherBody, MessageBody},
+    dev::{Service, ServiceRequest, ServiceResponse, Transform},
+    http::{StatusCode, header},
+};
+
+use super::LocalBoxFuture;
+use crate::{
+    config::{IpMatch, PathMatch},
+    metrics,
+};
+
+/// Tarpit settings.
+#[derive(Clone, Debug)]
+pub struct Settings {
+    /// Client addresses, CIDR ranges or address ranges to delay.
+    pub ips: Vec<IpMatch>,
+    /// Lowercase substrings matched against the user-agent.
+    pub user_agents: Vec<String>,
+    /// Path globs to delay.
+    pub paths: Vec<PathMatch>,
+    /// Delay clients currently banned by the ipban middleware.
+    #[cfg(feature = "ipban")]
+    pub banned: bool,
+    /// Time requests are held before being answered.
+    pub delay: Duration,
+    /// Response status code.
+    pub status: StatusCode,
+    /// Pass delayed requests on rather than answering them.
+    pub forward: bool,
+    /// Maximum number of requests held at once.
+    pub max_held: usize,
+}
+
+impl Settings {
+    /// Check if the request belongs to a tarpitted client.
+    fn matches(&self, req: &ServiceRequest) -> bool {
+        let ip = req.peer_addr().map(|addr| addr.ip().to_canonical());
+        if ip.is_some_and(|ip| self.ip_matches(&ip)) {
+            return true;
+        }
+        if self.paths.iter().any(|p| p.matches(req.path())) {
+            return true;
+        }
+        !self.user_agents.is_empty()
+            && req
+                .headers()
+                .get(header::USER_AGENT)
+                .and_then(|v| v.to_str().ok())
+                .map(|agent| agent.to_ascii_lowercase())
+                .is_some_and(|agent| self.user_agents.iter().any(|p| agent.contains(p)))
+    }
+
+    /// Check if the client address is tarpitted.
+    fn ip_matches(&self, ip: &IpAddr) -> bool {
+        #[cfg(feature = "ipban")]
+        if self.banned && super::ban::banned(ip).is_some() {
+            return true;
+        }
+        self.ips.iter().any(|m| m.contains(ip))
+    }
+}
+
+/// Count of requests currently held shared between workers.
+#[derive(Clone, Default)]
+pub struct Held(Arc<AtomicUsize>);
+
+impl std::fmt::Debug for Held {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "Held({})", self.0.load(Ordering::Relaxed))
+    }
+}
+
+impl Held {
+    /// Reserve a slot for a held request if below the limit.
+    fn acquire(&self, max: usize) -> Option<HeldSlot> {
+        self.0
+            .fetch_update(Ordering::AcqRel, Ordering::Acquire, |n| {
+                (n < max).then_some(n + 1)
+            })
+            .ok()
+            .map(|_| HeldSlot(Arc::clone(&self.0)))
+    }
+}
+
+/// Reserved held request slot released on drop.
+struct HeldSlot(Arc<AtomicUsize>);
+
+impl Drop for HeldSlot {
+    fn drop(&mut self) {
+        self.0.fetch_sub(1, Ordering::AcqRel);
+    }
+}
+
+/// Tarpit middleware.
+#[derive(Clone)]
+pub struct Tarpit {
+    settings: Rc<Settings>,
+    held: Held,
+}
+
+impl Tarpit {
+    /// Create new tarpit middleware from settings and shared held count.
+    pub fn new(settings: Settings, held: Held) -> Self {
+        Self {
+            settings: Rc::new(settings),
+            held,
+        }
+    }
+}
+
+impl<S, B> Transform<S, ServiceRequest> for Tarpit
+where
+    S: Service<ServiceRequest, Response = ServiceResponse<B>, Error = Error> + 'static,
+    B: MessageBody + 'static,
+{
+    type Response = ServiceResponse<EitherBody<B>>;
+    type Error = Error;
+    type Transform = TarpitService<S>;
+    type InitError = ();
+    type Future = std::future::Ready<Result<Self::Transform, Self::InitError>>;
+
+    fn new_transform(&self, service: S) -> Self::Future {
+        std::future::ready(Ok(TarpitService {
+            service: Rc::new(service),
+            settings: Rc::clone(&self.settings),
+            held: self.held.clone(),
+        }))
+    }
+}
+
+/// Service produced by [`Tarpit`] middleware.
+pub struct TarpitService<S> {
+    service: Rc<S>,
+    settings: Rc<Settings>,
+    held: Held,
+}
+
+impl<S, B> Service<ServiceRequest> for TarpitService<S>
+where
+    S: Service<ServiceRequest, Response = ServiceResponse<B>, Error = Error> + 'static,
+    B: MessageBody + 'static,
+{
+    type Response = ServiceResponse<EitherBody<B>>;
+    type Error = Error;
+    type Future = LocalBoxFuture<Result<Self::Response, Self::Error>>;
+
+    actix_web::dev::forward_ready!(service);
+
+    fn call(&self, req: ServiceRequest) -> Self::Future {
+        let service = Rc::clone(&self.service);
+        if !self.settings.matches(&req) {
+            return Box::pin(async move { Ok(service.call(req).await?.map_into_left_body()) });
+        }
+
+        let settings = Rc::clone(&self.settings);
+        let slot = self.held.acquire(settings.max_held);
+        log::debug!(
+            "tarpit matched {:?} {} (held: {})",
+            req.peer_addr(),
+            req.path(),
+            slot.is_some()
+        );
+        metrics::counter("bob_tarpit_requests_total").inc();
+        Box::pin(async move {
+            if let Some(_slot) = slot {
+                actix_web::rt::time::sleep(settings.delay).await;
+            }
+            if settings.forward {
+                return Ok(service.call(req).await?.map_into_left_body());
+            }
+            let res = HttpResponse::build(settings.status).finish();
+            Ok(req.into_response(res).map_into_right_body())
+        })
+    }
+}
@@ -0,0 +1,78 @@
+//! Route Tracing Middleware
+//!
+//! Records the servers and directives entered by a request so offline
+//! route testing can report which parts of the assembled application
+//! handled it.
+
+use actix_web::{
+    Error, HttpMessage,
+    body::MessageBody,
+    dev::{Service, ServiceRequest, ServiceResponse, Transform},
+};
+
+/// Server or directive entered by the request.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Hop {
+    /// Index of the server within the configuration.
+    Server(usize),
+    /// Index of the directive within the last entered server.
+    Directive(usize),
+}
+
+/// Hops entered by the request in order.
+#[derive(Clone, Debug, Default)]
+pub struct Trace(pub Vec<Hop>);
+
+/// Middleware recording the hop into the request [`Trace`].
+#[derive(Clone, Copy, Debug)]
+pub struct Traced(pub Hop);
+
+impl<S, B> Transform<S, ServiceRequest> for Traced
+where
+    S: Service<ServiceRequest, Response = ServiceResponse<B>, Error = Error> + 'static,
+    B: MessageBody + 'static,
+{
+    type Response = ServiceResponse<B>;
+    type Error = Error;
+    type Transform = TracedService<S>;
+    type InitError = ();
+    type Future = std::future::Ready<Result<Self::Transform, Self::InitError>>;
+
+    fn new_transform(&self, service: S) -> Self::Future {
+        std::future::ready(Ok(TracedService {
+            service,
+            hop: self.0,
+        }))
+    }
+}
+
+/// Service produced by [`Traced`] middleware.
+pub struct TracedService<S> {
+    service: S,
+    hop: Hop,
+}
+
+impl<S, B> Service<ServiceRequest> for TracedService<S>
+where
+    S: Service<ServiceRequest, Response = ServiceResponse<B>, Error = Error> + 'static,
+    B: MessageBody + 'static,
+{
+    type Response = ServiceResponse<B>;
+    type Error = Error;
+    type Future = S::Future;
+
+    actix_web::dev::forward_ready!(service);
+
+    fn call(&self, req: ServiceRequest) -> Self::Future {
+        {
+            let mut extensions = req.extensions_mut();
+            match extensions.get_mut::<Trace>() {
+                Some(trace) => trace.0.push(self.hop),
+                None => {
+                    extensions.insert(Trace(vec![self.hop]));
+                }
+            }
+        }
+        self.service.call(req)
+    }
+}
@@ -0,0 +1,439 @@
+//! Streaming Body Transformation Pipeline
+//!
+//! Response bodies are passed chunk by chunk through an ordered list of
+//! [`BodyFilter`] implementations. Each filter decides per response
+//! whether it applies and keeps its own streaming state, so new
+//! transformations only implement the chunk handling rather than the
+//! buffering and body plumbing.
+
+#[cfg(feature = "compress")]
+use std::io::Write;
+use std::{
+    collections::VecDeque,
+    io,
+    pin::Pin,
+    rc::Rc,
+    task::{Context, Poll, ready},
+};
+
+use actix_web::{
+    Error, HttpRequest,
+    body::{BodySize, BoxBody, MessageBody},
+    dev::{ResponseHead, Service, ServiceRequest, ServiceResponse, Transform},
+    http::header,
+    web::{Bytes, BytesMut},
+};
+
+use super::LocalBoxFuture;
+
+/// Response body transformation applied by the pipeline.
+///
+/// Implementations act as factories producing a fresh [`Filter`]
+/// for every response they apply to.
+pub trait BodyFilter {
+    /// Begin filtering a response.
+    ///
+    /// Response headers may be adjusted to reflect the transformation.
+    /// Returns `None` to leave the response untouched.
+    fn start(&self, req: &HttpRequest, head: &mut ResponseHead) -> Option<Box<dyn Filter>>;
+}
+
+/// Per-response streaming state of a [`BodyFilter`].
+pub trait Filter {
+    /// Transform a single body chunk, pushing any output to `out`.
+    fn chunk(&mut self, chunk: Bytes, out: &mut Vec<Bytes>) -> io::Result<()>;
+
+    /// Flush remaining buffered output once the body is complete.
+    fn finish(&mut self, _out: &mut Vec<Bytes>) -> io::Result<()> {
+        Ok(())
+    }
+}
+
+/// Check if response is already content-encoded.
+#[inline]
+fn is_encoded(head: &ResponseHead) -> bool {
+    head.headers()
+        .get(header::CONTENT_ENCODING)
+        .is_some_and(|v| v.as_bytes() != b"identity")
+}
+
+/// Find first position of `needle` within `haystack`.
+#[inline]
+fn find(haystack: &[u8], needle: &[u8]) -> Option<usize> {
+    haystack.windows(needle.len()).position(|w| w == needle)
+}
+
+/// Replace occurrences of a byte pattern within the body.
+///
+/// Matches spanning chunk boundaries are handled by holding back
+/// the trailing bytes which could begin a match.
+pub struct Substitute {
+    pattern: Bytes,
+    replace: Bytes,
+    limit: Option<usize>,
+}
+
+impl Substitute {
+    /// Replace every occurrence of `pattern` with `replace`.
+    pub fn new(pattern: impl Into<Bytes>, replace: impl Into<Bytes>) -> Self {
+        Self {
+            pattern: pattern.into(),
+            replace: replace.into(),
+            limit: None,
+        }
+    }
+
+    /// Limit the number of replacements made per response.
+    pub fn limit(mut self, limit: usize) -> Self {
+        self.limit = Some(limit);
+        self
+    }
+}
+
+impl BodyFilter for Substitute {
+    fn start(&self, _req: &HttpRequest, head: &mut ResponseHead) -> Option<Box<dyn Filter>> {
+        if self.pattern.is_empty() || is_encoded(head) {
+            return None;
+        }
+        Some(Box::new(SubstituteFilter {
+            pattern: self.pattern.clone(),
+            replace: self.replace.clone(),
+            remaining: self.limit,
+            pending: BytesMut::new(),
+        }))
+    }
+}
+
+/// Streaming state of [`Substitute`].
+struct SubstituteFilter {
+    pattern: Bytes,
+    replace: Bytes,
+    remaining: Option<usize>,
+    pending: BytesMut,
+}
+
+impl Filter for SubstituteFilter {
+    fn chunk(&mut self, chunk: Bytes, out: &mut Vec<Bytes>) -> io::Result<()> {
+        if self.remaining == Some(0) && self.pending.is_empty() {
+            out.push(chunk);
+            return Ok(());
+        }
+        self.pending.extend_from_slice(&chunk);
+        let mut output = BytesMut::with_capacity(self.pending.len());
+        let mut start = 0;
+        while self.remaining != Some(0)
+            && let Some(pos) = find(&self.pending[start..], &self.pattern)
+        {
+            output.extend_from_slice(&self.pending[start..start + pos]);
+            output.extend_from_slice(&self.replace);
+            start += pos + self.pattern.len();
+            self.remaining = self.remaining.map(|n| n - 1);
+        }
+        let keep = match self.remaining {
+            Some(0) => 0,
+            _ => (self.pattern.len() - 1).min(self.pending.len() - start),
+        };
+        let emit = self.pending.len() - keep;
+        output.extend_from_slice(&self.pending[start..emit]);
+        let _ = self.pending.split_to(emit);
+        out.push(output.freeze());
+        Ok(())
+    }
+
+    fn finish(&mut self, out: &mut Vec<Bytes>) -> io::Result<()> {
+        out.push(self.pending.split().freeze());
+        Ok(())
+    }
+}
+
+/// Insert content before the first occurrence of a marker.
+///
+/// Commonly used to inject snippets before `</head>` or `</body>`.
+pub struct Inject(Substitute);
+
+impl Inject {
+    /// Insert `content` before the first `marker` within the body.
+    pub fn new(marker: &str, content: &str) -> Self {
+        let replace = format!("{content}{marker}");
+        Self(Substitute::new(marker.to_owned(), replace).limit(1))
+    }
+}
+
+impl BodyFilter for Inject {
+    #[inline]
+    fn start(&self, req: &HttpRequest, head: &mut ResponseHead) -> Option<Box<dyn Filter>> {
+        self.0.start(req, head)
+    }
+}
+
+/// Re-chunk the body into chunks of a fixed size.
+pub struct Rechunk(pub usize);
+
+impl BodyFilter for Rechunk {
+    fn start(&self, _req: &HttpRequest, _head: &mut ResponseHead) -> Option<Box<dyn Filter>> {
+        Some(Box::new(RechunkFilter {
+            size: self.0.max(1),
+            pending: BytesMut::new(),
+        }))
+    }
+}
+
+/// Streaming state of [`Rechunk`].
+struct RechunkFilter {
+    size: usize,
+    pending: BytesMut,
+}
+
+impl Filter for RechunkFilter {
+    fn chunk(&mut self, chunk: Bytes, out: &mut Vec<Bytes>) -> io::Result<()> {
+        self.pending.extend_from_slice(&chunk);
+        while self.pending.len() >= self.size {
+            out.push(self.pending.split_to(self.size).freeze());
+        }
+        Ok(())
+    }
+
+    fn finish(&mut self, out: &mut Vec<Bytes>) -> io::Result<()> {
+        out.push(self.pending.split().freeze());
+        Ok(())
+    }
+}
+
+/// Streaming zstd compression for clients accepting it.
+#[cfg(feature = "compress")]
+pub struct Zstd(pub i32);
+
+#[cfg(feature = "compress")]
+impl BodyFilter for Zstd {
+    fn start(&self, req: &HttpRequest, head: &mut ResponseHead) -> Option<Box<dyn Filter>> {
+        use actix_web::http::header::HeaderValue;
+        let accepts = req
+            .headers()
+            .get(header::ACCEPT_ENCODING)
+            .and_then(|v| v.to_str().ok())
+            .is_some_and(|v| {
+                v.split(',')
+                    .any(|e| e.split(';').next().unwrap_or_default().trim() == "zstd")
+            });
+        if !accepts || is_encoded(head) {
+            return None;
+        }
+        let encoder = zstd::stream::write::Encoder::new(Vec::new(), self.0).ok()?;
+        let headers = head.headers_mut();
+        headers.insert(header::CONTENT_ENCODING, HeaderValue::from_static("zstd"));
+        headers.append(header::VARY, HeaderValue::from_static("accept-encoding"));
+        Some(Box::new(ZstdFilter(Some(encoder))))
+    }
+}
+
+/// Streaming state of [`Zstd`].
+#[cfg(feature = "compress")]
+struct ZstdFilter(Option<zstd::stream::write::Encoder<'static, Vec<u8>>>);
+
+#[cfg(feature = "compress")]
+impl Filter for ZstdFilter {
+    fn chunk(&mut self, chunk: Bytes, out: &mut Vec<Bytes>) -> io::Result<()> {
+        let encoder = self.0.as_mut().ok_or(io::ErrorKind::BrokenPipe)?;
+        encoder.write_all(&chunk)?;
+        out.push(std::mem::take(encoder.get_mut()).into());
+        Ok(())
+    }
+
+    fn finish(&mut self, out: &mut Vec<Bytes>) -> io::Result<()> {
+        let encoder = self.0.take().ok_or(io::ErrorKind::BrokenPipe)?;
+        out.push(encoder.finish()?.into());
+        Ok(())
+    }
+}
+
+/// Ordered set of body filters applied to eligible responses.
+#[derive(Clone, Default)]
+pub struct Pipeline {
+    filters: Vec<Rc<dyn BodyFilter>>,
+    content_types: Vec<String>,
+}
+
+impl Pipeline {
+    /// Append filter to the end of the pipeline.
+    pub fn filter<F: BodyFilter + 'static>(mut self, filter: F) -> Self {
+        self.filters.push(Rc::new(filter));
+        self
+    }
+
+    /// Limit the pipeline to response content-type prefixes.
+    pub fn content_types(mut self, content_types: Vec<String>) -> Self {
+        self.content_types = content_types;
+        self
+    }
+
+    /// Check if pipeline applies to the response content-type.
+    fn applies_to(&self, head: &ResponseHead) -> bool {
+        let ctype = head
+            .headers()
+            .get(header::CONTENT_TYPE)
+            .and_then(|v| v.to_str().ok())
+            .unwrap_or_default();
+        self.content_types.is_empty()
+            || self
+                .content_types
+                .iter()
+                .any(|prefix| ctype.starts_with(prefix.as_str()))
+    }
+
+    /// Start every applicable filter for the response.
+    fn start(&self, req: &HttpRequest, head: &mut ResponseHead) -> Vec<Box<dyn Filter>> {
+        self.filters
+            .iter()
+            .filter_map(|filter| filter.start(req, head))
+            .collect()
+    }
+}
+
+/// Body transformation middleware.
+#[derive(Clone)]
+pub struct BodyTransform(Rc<Pipeline>);
+
+impl BodyTransform {
+    /// Create new transformation middleware from a filter pipeline.
+    pub fn new(pipeline: Pipeline) -> Self {
+        Self(Rc::new(pipeline))
+    }
+}
+
+impl<S, B> Transform<S, ServiceRequest> for BodyTransform
+where
+    S: Service<ServiceRequest, Response = ServiceResponse<B>, Error = Error> + 'static,
+    B: MessageBody + 'static,
+{
+    type Response = ServiceResponse<BoxBody>;
+    type Error = Error;
+    type Transform = BodyTransformService<S>;
+    type InitError = ();
+    type Future = std::future::Ready<Result<Self::Transform, Self::InitError>>;
+
+    fn new_transform(&self, service: S) -> Self::Future {
+        std::future::ready(Ok(BodyTransformService {
+            service: Rc::new(service),
+            pipeline: Rc::clone(&self.0),
+        }))
+    }
+}
+
+/// Service produced by [`BodyTransform`] middleware.
+pub struct BodyTransformService<S> {
+    service: Rc<S>,
+    pipeline: Rc<Pipeline>,
+}
+
+impl<S, B> Service<ServiceRequest> for BodyTransformService<S>
+where
+    S: Service<ServiceRequest, Response = ServiceResponse<B>, Error = Error> + 'static,
+    B: MessageBody + 'static,
+{
+    type Response = ServiceResponse<BoxBody>;
+    type Error = Error;
+    type Future = LocalBoxFuture<Result<Self::Response, Self::Error>>;
+
+    actix_web::dev::forward_ready!(service);
+
+    fn call(&self, req: ServiceRequest) -> Self::Future {
+        let service = Rc::clone(&self.service);
+        let pipeline = Rc::clone(&self.pipeline);
+        Box::pin(async move {
+            let res = service.call(req).await?;
+            let empty = matches!(
+                res.response().body().size(),
+                BodySize::None | BodySize::Sized(0)
+            );
+            if empty || !pipeline.applies_to(res.response().head()) {
+                return Ok(res.map_into_boxed_body());
+            }
+
+            let (req, res) = res.into_parts();
+            let (mut head, body) = res.into_parts();
+            let filters = pipeline.start(&req, head.head_mut());
+            if filters.is_empty() {
+                return Ok(ServiceResponse::new(
+                    req,
+                    head.set_body(body).map_into_boxed_body(),
+                ));
+            }
+            head.headers_mut().remove(header::CONTENT_LENGTH);
+            let body = FilteredBody {
+                body: body.boxed(),
+                filters,
+                ready: VecDeque::new(),
+                done: false,
+            };
+            Ok(ServiceResponse::new(
+                req,
+                head.set_body(body).map_into_boxed_body(),
+            ))
+        })
+    }
+}
+
+/// Response body streamed through a set of started filters.
+pub struct FilteredBody {
+    body: BoxBody,
+    filters: Vec<Box<dyn Filter>>,
+    ready: VecDeque<Bytes>,
+    done: bool,
+}
+
+impl FilteredBody {
+    /// Pass chunk through every filter, finishing them at end of body.
+    fn process(&mut self, chunk: Option<Bytes>) -> io::Result<()> {
+        let mut input: Vec<Bytes> = chunk.into_iter().collect();
+        for filter in self.filters.iter_mut() {
+            let mut output = Vec::with_capacity(input.len());
+            for chunk in input {
+                filter.chunk(chunk, &mut output)?;
+            }
+            if self.done {
+                filter.finish(&mut output)?;
+            }
+            input = output;
+        }
+        self.ready
+            .extend(input.into_iter().filter(|chunk| !chunk.is_empty()));
+        Ok(())
+    }
+}
+
+impl MessageBody for FilteredBody {
+    type Error = Box<dyn std::error::Error>;
+
+    #[inline]
+    fn size(&self) -> BodySize {
+        BodySize::Stream
+    }
+
+    fn poll_next(
+        self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+    ) -> Poll<Option<Result<Bytes, Self::Error>>> {
+        let this = self.get_mut();
+        loop {
+            if let Some(chunk) = this.ready.pop_front() {
+                return Poll::Ready(Some(Ok(chunk)));
+            }
+            if this.done {
+                return Poll::Ready(None);
+            }
+            let chunk = match ready!(Pin::new(&mut this.body).poll_next(cx)) {
+                Some(Ok(chunk)) => Some(chunk),
+                Some(Err(err)) => return Poll::Ready(Some(Err(err))),
+                None => {
+                    this.done = true;
+                    None
+                }
+            };
+            if let Err(err) = this.process(chunk) {
+                this.done = true;
+                this.ready.clear();
+                return Poll::Ready(Some(Err(err.into())));
+            }
+        }
+    }
+}
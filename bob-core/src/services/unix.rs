@@ -0,0 +1,46 @@
+//! Unix Socket Upstream Connector
+//!
+//! Connects http clients to an upstream listening on a unix socket
+//! rather than a tcp address. The requested host is ignored and every
+//! connection is opened against the configured socket path.
+
+use std::{path::PathBuf, rc::Rc};
+
+use actix_service::Service;
+use actix_tls::connect::{ConnectError, ConnectInfo, Connection};
+use actix_web::{http::Uri, rt::net::UnixStream};
+use futures_core::future::LocalBoxFuture;
+
+/// Unix socket connector for [`awc::Connector`].
+#[derive(Clone, Debug)]
+pub struct UnixConnector {
+    path: Rc<PathBuf>,
+}
+
+impl UnixConnector {
+    /// Create new connector for the unix socket path.
+    pub fn new(path: PathBuf) -> Self {
+        Self {
+            path: Rc::new(path),
+        }
+    }
+}
+
+impl Service<ConnectInfo<Uri>> for UnixConnector {
+    type Response = Connection<Uri, UnixStream>;
+    type Error = ConnectError;
+    type Future = LocalBoxFuture<'static, Result<Self::Response, Self::Error>>;
+
+    actix_service::always_ready!();
+
+    fn call(&self, req: ConnectInfo<Uri>) -> Self::Future {
+        let path = Rc::clone(&self.path);
+        Box::pin(async move {
+            let io = UnixStream::connect(path.as_path()).await.map_err(|err| {
+                log::error!("failed to connect to upstream socket {path:?}: {err}");
+                ConnectError::Io(err)
+            })?;
+            Ok(Connection::new(req.request().clone(), io))
+        })
+    }
+}
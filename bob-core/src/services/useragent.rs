@@ -0,0 +1,162 @@
+//! User-Agent and Bot Signature Filter Middleware
+//!
+//! Matches the request `User-Agent` against ordered rules of case
+//! insensitive signatures. Matching clients are blocked, tarpitted or
+//! served a static response unless they match the allow list.
+
+use std::{rc::Rc, time::Duration};
+
+use actix_web::{
+    Error, HttpResponse,
+    body::{EitherBody, MessageBody},
+    dev::{Service, ServiceRequest, ServiceResponse, Transform},
+    http::{StatusCode, header},
+};
+
+use super::LocalBoxFuture;
+
+/// Action taken for clients matching a rule.
+#[derive(Clone, Debug)]
+pub enum Action {
+    /// Reject the request immediately.
+    Block,
+    /// Delay the client before rejecting the request.
+    Tarpit(Duration),
+    /// Serve a static response body.
+    Respond {
+        /// Response body.
+        body: String,
+        /// Response content-type.
+        content_type: String,
+    },
+}
+
+/// Signature rule applied to matching user-agents.
+#[derive(Clone, Debug)]
+pub struct Rule {
+    /// Lowercased signatures searched for within the user-agent.
+    pub signatures: Vec<String>,
+    /// Match requests without a user-agent.
+    pub empty: bool,
+    /// Action taken on match.
+    pub action: Action,
+    /// Response status code.
+    pub status: StatusCode,
+}
+
+impl Rule {
+    /// Check if rule matches the lowercased user-agent.
+    fn matches(&self, agent: Option<&str>) -> bool {
+        match agent {
+            Some(agent) => self.signatures.iter().any(|s| agent.contains(s.as_str())),
+            None => self.empty,
+        }
+    }
+
+    /// Build rule response for matching request.
+    fn response(&self) -> HttpResponse {
+        match &self.action {
+            Action::Respond { body, content_type } => HttpResponse::build(self.status)
+                .insert_header((header::CONTENT_TYPE, content_type.as_str()))
+                .body(body.clone()),
+            _ => HttpResponse::build(self.status).finish(),
+        }
+    }
+}
+
+/// User-agent filter settings.
+#[derive(Clone, Debug, Default)]
+pub struct Settings {
+    /// Lowercased signatures exempt from every rule.
+    pub allow: Vec<String>,
+    /// Ordered rules evaluated against the user-agent.
+    pub rules: Vec<Rule>,
+}
+
+impl Settings {
+    /// Find first rule matching the request user-agent.
+    fn find(&self, req: &ServiceRequest) -> Option<&Rule> {
+        let agent = req
+            .headers()
+            .get(header::USER_AGENT)
+            .and_then(|v| v.to_str().ok())
+            .filter(|v| !v.trim().is_empty())
+            .map(|v| v.to_ascii_lowercase());
+        let agent = agent.as_deref();
+        if agent.is_some_and(|agent| self.allow.iter().any(|s| agent.contains(s.as_str()))) {
+            return None;
+        }
+        self.rules.iter().find(|rule| rule.matches(agent))
+    }
+}
+
+/// User-agent filter middleware.
+#[derive(Clone)]
+pub struct UserAgentFilter(Rc<Settings>);
+
+impl UserAgentFilter {
+    /// Create new user-agent filter middleware from settings.
+    pub fn new(settings: Settings) -> Self {
+        Self(Rc::new(settings))
+    }
+}
+
+impl<S, B> Transform<S, ServiceRequest> for UserAgentFilter
+where
+    S: Service<ServiceRequest, Response = ServiceResponse<B>, Error = Error> + 'static,
+    B: MessageBody + 'static,
+{
+    type Response = ServiceResponse<EitherBody<B>>;
+    type Error = Error;
+    type Transform = UserAgentFilterService<S>;
+    type InitError = ();
+    type Future = std::future::Ready<Result<Self::Transform, Self::InitError>>;
+
+    fn new_transform(&self, service: S) -> Self::Future {
+        std::future::ready(Ok(UserAgentFilterService {
+            service: Rc::new(service),
+            settings: Rc::clone(&self.0),
+        }))
+    }
+}
+
+/// Service produced by [`UserAgentFilter`] middleware.
+pub struct UserAgentFilterService<S> {
+    service: Rc<S>,
+    settings: Rc<Settings>,
+}
+
+impl<S, B> Service<ServiceRequest> for UserAgentFilterService<S>
+where
+    S: Service<ServiceRequest, Response = ServiceResponse<B>, Error = Error> + 'static,
+    B: MessageBody + 'static,
+{
+    type Response = ServiceResponse<EitherBody<B>>;
+    type Error = Error;
+    type Future = LocalBoxFuture<Result<Self::Response, Self::Error>>;
+
+    actix_web::dev::forward_ready!(service);
+
+    fn call(&self, req: ServiceRequest) -> Self::Future {
+        if let Some(rule) = self.settings.find(&req) {
+            log::debug!(
+                "user-agent filter matched {:?} {:?}",
+                req.peer_addr(),
+                req.headers().get(header::USER_AGENT)
+            );
+            let res = rule.response();
+            let delay = match rule.action {
+                Action::Tarpit(delay) => Some(delay),
+                _ => None,
+            };
+            return Box::pin(async move {
+                if let Some(delay) = delay {
+                    actix_web::rt::time::sleep(delay).await;
+                }
+                Ok(req.into_response(res).map_into_right_body())
+            });
+        }
+        let service = Rc::clone(&self.service);
+        Box::pin(async move { Ok(service.call(req).await?.map_into_left_body()) })
+    }
+}
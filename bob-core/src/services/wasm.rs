@@ -0,0 +1,571 @@
+//! WebAssembly Filter Middleware
+//!
+//! Runs request and response filters compiled to WebAssembly inside a
+//! sandbox using a small proxy-wasm inspired ABI, so filters written in
+//! any language can inspect and modify headers and bodies or answer the
+//! request directly.
+//!
+//! Filters export their `memory`, an allocator `bob_alloc(size) -> ptr`
+//! used by the host to return values, and optional `on_request() -> i32`
+//! and `on_response() -> i32` hooks. Hooks return `0` to continue or any
+//! other value to stop, answering with the response given to
+//! `send_response` or `403 Forbidden` otherwise.
+//!
+//! Host functions are imported from the `bob` module and return `0` on
+//! success, `1` when the value is not found and `2` for invalid
+//! arguments. Values are returned by writing the pointer and length of
+//! an allocation to the `ret_ptr` and `ret_len` addresses.
+//!
+//! | Import | Parameters |
+//! |--------|------------|
+//! | `get_header` | `map, name_ptr, name_len, ret_ptr, ret_len` |
+//! | `set_header` | `map, name_ptr, name_len, value_ptr, value_len` |
+//! | `remove_header` | `map, name_ptr, name_len` |
+//! | `get_property` | `name_ptr, name_len, ret_ptr, ret_len` |
+//! | `get_body` | `ret_ptr, ret_len` |
+//! | `set_body` | `ptr, len` |
+//! | `send_response` | `status, body_ptr, body_len` |
+//! | `log` | `level, ptr, len` |
+//!
+//! Header map `0` selects the request and `1` the response headers.
+//! Properties include `method`, `path`, `query`, `host`, `peer`,
+//! `config` and, once the response is available, `status`.
+
+use std::{collections::HashMap, path::Path, pin::Pin, rc::Rc, sync::Arc};
+
+use actix_web::{
+    Error, HttpResponse,
+    body::{self, BoxBody, MessageBody},
+    dev::{Payload, Service, ServiceRequest, ServiceResponse, Transform},
+    http::{
+        StatusCode,
+        header::{self, HeaderMap, HeaderName, HeaderValue},
+    },
+    web::{self, Bytes, BytesMut},
+};
+use anyhow::{Context, anyhow};
+use futures_core::Stream;
+use wasmtime::{
+    Caller, Engine, Instance, InstancePre, Linker, Memory, Module, Store, StoreLimits,
+    StoreLimitsBuilder,
+};
+
+use super::LocalBoxFuture;
+
+/// Host function completed successfully.
+const OK: i32 = 0;
+/// Requested value does not exist.
+const NOT_FOUND: i32 = 1;
+/// Host function received an invalid argument.
+const BAD_ARGUMENT: i32 = 2;
+
+/// Host import module name.
+const IMPORT_MODULE: &str = "bob";
+
+/// Default instruction budget available to a filter per request.
+pub const DEFAULT_FUEL: u64 = 100_000_000;
+/// Default linear memory available to a filter instance.
+pub const DEFAULT_MAX_MEMORY: usize = 64 * 1024 * 1024;
+
+/// Per-request state exposed to the filter.
+#[derive(Default)]
+pub struct State {
+    request: HeaderMap,
+    response: HeaderMap,
+    properties: HashMap<&'static str, String>,
+    body: Option<Bytes>,
+    body_changed: bool,
+    reply: Option<(StatusCode, Bytes)>,
+    limits: StoreLimits,
+}
+
+impl State {
+    /// Header map selected by the filter.
+    #[inline]
+    fn headers(&mut self, map: i32) -> Option<&mut HeaderMap> {
+        match map {
+            0 => Some(&mut self.request),
+            1 => Some(&mut self.response),
+            _ => None,
+        }
+    }
+}
+
+/// Exported filter memory.
+fn memory(caller: &mut Caller<'_, State>) -> anyhow::Result<Memory> {
+    caller
+        .get_export("memory")
+        .and_then(|export| export.into_memory())
+        .context("wasm filter does not export memory")
+}
+
+/// Copy bytes out of filter memory.
+fn read(caller: &mut Caller<'_, State>, ptr: i32, len: i32) -> anyhow::Result<Vec<u8>> {
+    let memory = memory(caller)?;
+    let (ptr, len) = (ptr as u32 as usize, len as u32 as usize);
+    // never allocate more than the filter could have written
+    if ptr
+        .checked_add(len)
+        .is_none_or(|end| end > memory.data_size(&*caller))
+    {
+        return Err(anyhow!("wasm filter read out of bounds"));
+    }
+    let mut buf = vec![0; len];
+    memory.read(&*caller, ptr, &mut buf)?;
+    Ok(buf)
+}
+
+/// Copy bytes into a filter allocation and return its location.
+fn write(
+    caller: &mut Caller<'_, State>,
+    data: &[u8],
+    ret_ptr: i32,
+    ret_len: i32,
+) -> anyhow::Result<i32> {
+    let alloc = caller
+        .get_export("bob_alloc")
+        .and_then(|export| export.into_func())
+        .context("wasm filter does not export bob_alloc")?
+        .typed::<i32, i32>(&*caller)?;
+    let ptr = alloc.call(&mut *caller, data.len() as i32)?;
+    let memory = memory(caller)?;
+    memory.write(&mut *caller, ptr as u32 as usize, data)?;
+    memory.write(
+        &mut *caller,
+        ret_ptr as u32 as usize,
+        &(ptr as u32).to_le_bytes(),
+    )?;
+    let len = (data.len() as u32).to_le_bytes();
+    memory.write(&mut *caller, ret_len as u32 as usize, &len)?;
+    Ok(OK)
+}
+
+/// Define the host functions available to filters.
+fn define(linker: &mut Linker<State>) -> anyhow::Result<()> {
+    linker.func_wrap(
+        IMPORT_MODULE,
+        "get_header",
+        |mut caller: Caller<'_, State>, map: i32, ptr: i32, len: i32, rptr: i32, rlen: i32| {
+            let name = read(&mut caller, ptr, len)?;
+            let Ok(name) = HeaderName::from_bytes(&name) else {
+                return Ok(BAD_ARGUMENT);
+            };
+            let Some(headers) = caller.data_mut().headers(map) else {
+                return Ok(BAD_ARGUMENT);
+            };
+            let values: Vec<&[u8]> = headers.get_all(&name).map(|v| v.as_bytes()).collect();
+            if values.is_empty() {
+                return Ok(NOT_FOUND);
+            }
+            let value = values.join(&b", "[..]);
+            write(&mut caller, &value, rptr, rlen)
+        },
+    )?;
+    linker.func_wrap(
+        IMPORT_MODULE,
+        "set_header",
+        |mut caller: Caller<'_, State>, map: i32, ptr: i32, len: i32, vptr: i32, vlen: i32| {
+            let name = read(&mut caller, ptr, len)?;
+            let value = read(&mut caller, vptr, vlen)?;
+            let (Ok(name), Ok(value)) = (
+                HeaderName::from_bytes(&name),
+                HeaderValue::from_bytes(&value),
+            ) else {
+                return Ok(BAD_ARGUMENT);
+            };
+            let Some(headers) = caller.data_mut().headers(map) else {
+                return Ok(BAD_ARGUMENT);
+            };
+            headers.insert(name, value);
+            anyhow::Ok(OK)
+        },
+    )?;
+    linker.func_wrap(
+        IMPORT_MODULE,
+        "remove_header",
+        |mut caller: Caller<'_, State>, map: i32, ptr: i32, len: i32| {
+            let name = read(&mut caller, ptr, len)?;
+            let Ok(name) = HeaderName::from_bytes(&name) else {
+                return Ok(BAD_ARGUMENT);
+            };
+            let Some(headers) = caller.data_mut().headers(map) else {
+                return Ok(BAD_ARGUMENT);
+            };
+            headers.remove(name);
+            anyhow::Ok(OK)
+        },
+    )?;
+    linker.func_wrap(
+        IMPORT_MODULE,
+        "get_property",
+        |mut caller: Caller<'_, State>, ptr: i32, len: i32, rptr: i32, rlen: i32| {
+            let name = read(&mut caller, ptr, len)?;
+            let name = String::from_utf8_lossy(&name);
+            let Some(value) = caller.data().properties.get(name.as_ref()).cloned() else {
+                return Ok(NOT_FOUND);
+            };
+            write(&mut caller, value.as_bytes(), rptr, rlen)
+        },
+    )?;
+    linker.func_wrap(
+        IMPORT_MODULE,
+        "get_body",
+        |mut caller: Caller<'_, State>, rptr: i32, rlen: i32| {
+            let Some(body) = caller.data().body.clone() else {
+                return Ok(NOT_FOUND);
+            };
+            write(&mut caller, &body, rptr, rlen)
+        },
+    )?;
+    linker.func_wrap(
+        IMPORT_MODULE,
+        "set_body",
+        |mut caller: Caller<'_, State>, ptr: i32, len: i32| {
+            let body = read(&mut caller, ptr, len)?;
+            let state = caller.data_mut();
+            state.body = Some(body.into());
+            state.body_changed = true;
+            anyhow::Ok(OK)
+        },
+    )?;
+    linker.func_wrap(
+        IMPORT_MODULE,
+        "send_response",
+        |mut caller: Caller<'_, State>, status: i32, ptr: i32, len: i32| {
+            let Ok(status) = StatusCode::from_u16(status as u16) else {
+                return Ok(BAD_ARGUMENT);
+            };
+            let body = read(&mut caller, ptr, len)?;
+            caller.data_mut().reply = Some((status, body.into()));
+            anyhow::Ok(OK)
+        },
+    )?;
+    linker.func_wrap(
+        IMPORT_MODULE,
+        "log",
+        |mut caller: Caller<'_, State>, level: i32, ptr: i32, len: i32| {
+            let message = read(&mut caller, ptr, len)?;
+            let level = match level {
+                0 => log::Level::Trace,
+                1 => log::Level::Debug,
+                2 => log::Level::Info,
+                3 => log::Level::Warn,
+                _ => log::Level::Error,
+            };
+            log::log!(level, "wasm filter: {}", String::from_utf8_lossy(&message));
+            anyhow::Ok(())
+        },
+    )?;
+    Ok(())
+}
+
+/// Compiled filter shared between every worker.
+pub struct Filter {
+    engine: Engine,
+    pre: InstancePre<State>,
+    on_request: bool,
+    on_response: bool,
+}
+
+impl Filter {
+    /// Compile filter module and resolve its imports.
+    pub fn load(path: &Path) -> anyhow::Result<Self> {
+        let mut config = wasmtime::Config::new();
+        config.consume_fuel(true);
+        let engine = Engine::new(&config)?;
+        let module = Module::from_file(&engine, path)
+            .with_context(|| format!("failed to compile wasm filter {path:?}"))?;
+        for export in ["memory", "bob_alloc"] {
+            if module.get_export(export).is_none() {
+                return Err(anyhow!("wasm filter {path:?} does not export {export}"));
+            }
+        }
+        let on_request = module.get_export("on_request").is_some();
+        let on_response = module.get_export("on_response").is_some();
+        if !on_request && !on_response {
+            return Err(anyhow!(
+                "wasm filter {path:?} exports neither on_request nor on_response"
+            ));
+        }
+
+        let mut linker = Linker::new(&engine);
+        define(&mut linker)?;
+        let pre = linker
+            .instantiate_pre(&module)
+            .with_context(|| format!("failed to link wasm filter {path:?}"))?;
+        Ok(Self {
+            engine,
+            pre,
+            on_request,
+            on_response,
+        })
+    }
+
+    /// Instantiate filter for a single request.
+    fn instantiate(&self, mut state: State, fuel: u64, memory: usize) -> anyhow::Result<Running> {
+        state.limits = StoreLimitsBuilder::new()
+            .memory_size(memory)
+            .instances(1)
+            .build();
+        let mut store = Store::new(&self.engine, state);
+        store.limiter(|state| &mut state.limits);
+        store.set_fuel(fuel)?;
+        let instance = self.pre.instantiate(&mut store)?;
+        Ok(Running { store, instance })
+    }
+}
+
+/// Filter instance processing a single request.
+struct Running {
+    store: Store<State>,
+    instance: Instance,
+}
+
+impl Running {
+    /// Call filter hook and report if processing should continue.
+    fn call(&mut self, name: &str) -> anyhow::Result<bool> {
+        let func = self
+            .instance
+            .get_typed_func::<(), i32>(&mut self.store, name)?;
+        Ok(func.call(&mut self.store, ())? == 0)
+    }
+
+    /// Call filter hook on the blocking pool so guests never stall the worker.
+    async fn hook(mut self, name: &'static str) -> Result<(Self, anyhow::Result<bool>), Error> {
+        Ok(web::block(move || {
+            let result = self.call(name);
+            (self, result)
+        })
+        .await?)
+    }
+
+    /// Response answering the request instead of the wrapped service.
+    fn reply(&mut self) -> HttpResponse {
+        match self.store.data_mut().reply.take() {
+            Some((status, body)) => HttpResponse::build(status).body(body),
+            None => HttpResponse::Forbidden().finish(),
+        }
+    }
+}
+
+/// Wasm filter middleware settings.
+pub struct Settings {
+    /// Compiled filter module.
+    pub filter: Arc<Filter>,
+    /// Filter specific configuration exposed as the `config` property.
+    pub config: String,
+    /// Buffer request bodies for the filter.
+    pub request_body: bool,
+    /// Buffer response bodies for the filter.
+    pub response_body: bool,
+    /// Maximum buffered body size in bytes.
+    pub max_body_size: usize,
+    /// Fuel available to the filter per request.
+    pub fuel: u64,
+    /// Maximum linear memory of a filter instance in bytes.
+    pub max_memory: usize,
+    /// Pass requests on unfiltered when the filter fails.
+    pub fail_open: bool,
+}
+
+/// Wasm filter middleware.
+#[derive(Clone)]
+pub struct Wasm(Rc<Settings>);
+
+impl Wasm {
+    /// Create new wasm filter middleware from settings.
+    pub fn new(settings: Settings) -> Self {
+        Self(Rc::new(settings))
+    }
+}
+
+impl<S, B> Transform<S, ServiceRequest> for Wasm
+where
+    S: Service<ServiceRequest, Response = ServiceResponse<B>, Error = Error> + 'static,
+    B: MessageBody + 'static,
+{
+    type Response = ServiceResponse<BoxBody>;
+    type Error = Error;
+    type Transform = WasmService<S>;
+    type InitError = ();
+    type Future = std::future::Ready<Result<Self::Transform, Self::InitError>>;
+
+    fn new_transform(&self, service: S) -> Self::Future {
+        std::future::ready(Ok(WasmService {
+            service: Rc::new(service),
+            settings: Rc::clone(&self.0),
+        }))
+    }
+}
+
+/// Service produced by [`Wasm`] middleware.
+pub struct WasmService<S> {
+    service: Rc<S>,
+    settings: Rc<Settings>,
+}
+
+/// Collect request payload up to the size limit.
+async fn buffer(mut payload: Payload, limit: usize) -> Result<Option<Bytes>, Error> {
+    let mut data = BytesMut::new();
+    while let Some(chunk) = std::future::poll_fn(|cx| Pin::new(&mut payload).poll_next(cx)).await {
+        let chunk = chunk?;
+        if data.len() + chunk.len() > limit {
+            return Ok(None);
+        }
+        data.extend_from_slice(&chunk);
+    }
+    Ok(Some(data.freeze()))
+}
+
+/// Request state exposed to the filter.
+fn request_state(req: &ServiceRequest, config: &str) -> State {
+    let info = req.connection_info();
+    let properties = HashMap::from([
+        ("method", req.method().to_string()),
+        ("path", req.path().to_owned()),
+        ("query", req.query_string().to_owned()),
+        ("host", info.host().to_owned()),
+        (
+            "peer",
+            info.realip_remote_addr().unwrap_or_default().to_owned(),
+        ),
+        ("config", config.to_owned()),
+    ]);
+    State {
+        request: req.headers().clone(),
+        properties,
+        ..Default::default()
+    }
+}
+
+impl<S, B> Service<ServiceRequest> for WasmService<S>
+where
+    S: Service<ServiceRequest, Response = ServiceResponse<B>, Error = Error> + 'static,
+    B: MessageBody + 'static,
+{
+    type Response = ServiceResponse<BoxBody>;
+    type Error = Error;
+    type Future = LocalBoxFuture<Result<Self::Response, Self::Error>>;
+
+    actix_web::dev::forward_ready!(service);
+
+    fn call(&self, mut req: ServiceRequest) -> Self::Future {
+        let service = Rc::clone(&self.service);
+        let settings = Rc::clone(&self.settings);
+        Box::pin(async move {
+            let mut state = request_state(&req, &settings.config);
+            if settings.request_body && settings.filter.on_request {
+                let Some(data) = buffer(req.take_payload(), settings.max_body_size).await? else {
+                    let res = HttpResponse::PayloadTooLarge().finish();
+                    return Ok(req.into_response(res));
+                };
+                req.set_payload(Payload::from(data.clone()));
+                state.body = Some(data);
+            }
+
+            let filter = &settings.filter;
+            let mut running = match filter.instantiate(state, settings.fuel, settings.max_memory) {
+                Ok(running) => running,
+                Err(err) if settings.fail_open => {
+                    log::error!("wasm filter failed to start: {err:?}");
+                    return Ok(service.call(req).await?.map_into_boxed_body());
+                }
+                Err(err) => {
+                    log::error!("wasm filter failed to start: {err:?}");
+                    let res = HttpResponse::InternalServerError().finish();
+                    return Ok(req.into_response(res));
+                }
+            };
+
+            // request phase
+            if settings.filter.on_request {
+                let (next, result) = running.hook("on_request").await?;
+                running = next;
+                match result {
+                    Ok(true) => {}
+                    Ok(false) => return Ok(req.into_response(running.reply())),
+                    Err(err) if settings.fail_open => {
+                        log::error!("wasm filter on_request failed: {err:?}");
+                        return Ok(service.call(req).await?.map_into_boxed_body());
+                    }
+                    Err(err) => {
+                        log::error!("wasm filter on_request failed: {err:?}");
+                        let res = HttpResponse::InternalServerError().finish();
+                        return Ok(req.into_response(res));
+                    }
+                }
+                let state = running.store.data_mut();
+                *req.headers_mut() = std::mem::take(&mut state.request);
+                if state.body_changed
+                    && let Some(body) = state.body.take()
+                {
+                    let length = HeaderValue::from(body.len());
+                    req.headers_mut().insert(header::CONTENT_LENGTH, length);
+                    req.headers_mut().remove(header::TRANSFER_ENCODING);
+                    req.set_payload(Payload::from(body));
+                }
+                state.body = None;
+                state.body_changed = false;
+            }
+
+            let res = service.call(req).await?.map_into_boxed_body();
+            if !settings.filter.on_response {
+                return Ok(res);
+            }
+
+            // response phase
+            let (req, res) = res.into_parts();
+            let (mut head, body) = res.into_parts();
+            let state = running.store.data_mut();
+            state.response = head.headers().clone();
+            let status = head.status().as_u16().to_string();
+            state.properties.insert("status", status);
+            let mut body = Some(body);
+            if settings.response_body {
+                let data = body::to_bytes_limited(body.take().unwrap(), settings.max_body_size)
+                    .await
+                    .map_err(|_| {
+                        actix_web::error::ErrorInternalServerError("response body too large")
+                    })?
+                    .map_err(|err| {
+                        let err: Box<dyn std::error::Error> = err.into();
+                        actix_web::error::ErrorInternalServerError(err.to_string())
+                    })?;
+                state.body = Some(data);
+            }
+
+            let (mut running, result) = running.hook("on_response").await?;
+            let res = match result {
+                Ok(true) => {
+                    let state = running.store.data_mut();
+                    *head.headers_mut() = std::mem::take(&mut state.response);
+                    // buffered or replaced bodies are taken from the filter state
+                    let data = match body.is_none() || state.body_changed {
+                        true => state.body.take(),
+                        false => None,
+                    };
+                    match data {
+                        Some(data) => {
+                            head.headers_mut().remove(header::CONTENT_LENGTH);
+                            head.set_body(data).map_into_boxed_body()
+                        }
+                        None => head.set_body(body.unwrap_or_else(|| BoxBody::new(()))),
+                    }
+                }
+                Ok(false) => running.reply(),
+                Err(err) => {
+                    log::error!("wasm filter on_response failed: {err:?}");
+                    match (settings.fail_open, body) {
+                        (true, Some(body)) => head.set_body(body),
+                        (true, None) => {
+                            let data = running.store.data_mut().body.take().unwrap_or_default();
+                            head.set_body(data).map_into_boxed_body()
+                        }
+                        (false, _) => HttpResponse::InternalServerError().finish(),
+                    }
+                }
+            };
+            Ok(ServiceResponse::new(req, res))
+        })
+    }
+}
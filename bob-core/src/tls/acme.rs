@@ -0,0 +1,555 @@
+//! On-Demand ACME Certificate Issuance
+//!
+//! Issues certificates from an ACME certificate authority the first time
+//! a TLS handshake arrives for an unknown server name matching the
+//! configured allowlist. Ownership is proven with the `tls-alpn-01`
+//! challenge, answered by the same resolver, so no http route is needed.
+//! Issued certificates are kept in a [`CertStore`], cached in memory and
+//! renewed in the background once they near expiry.
+//!
+//! Certificate resolution never touches the store. Loading stored
+//! certificates and issuance both run on their own thread, so handshakes
+//! for a name without a cached certificate are answered with the fallback
+//! certificate until it is loaded or issued rather than stalling the
+//! worker that must also answer the challenge.
+
+use std::{
+    collections::{HashMap, HashSet, VecDeque},
+    sync::{Arc, Mutex, RwLock},
+    time::{Duration, Instant, SystemTime},
+};
+
+use anyhow::{Context, Result, bail};
+use instant_acme::{
+    Account, AccountCredentials, AuthorizationStatus, ChallengeType, Identifier, NewAccount,
+    NewOrder, Order, OrderStatus,
+};
+use rcgen::{CertificateParams, CustomExtension, DistinguishedName, KeyPair};
+use rustls::{
+    crypto::aws_lc_rs::sign::any_supported_type,
+    pki_types::{PrivateKeyDer, PrivatePkcs8KeyDer},
+    sign::CertifiedKey,
+};
+use tokio::sync::mpsc::{Receiver, Sender, channel, error::TrySendError};
+
+use super::store::{self, CertStore};
+use crate::{
+    config::{DomainMatch, HookCfg},
+    hooks,
+};
+
+/// ALPN protocol negotiated by `tls-alpn-01` validation requests.
+pub const ACME_TLS_ALPN: &[u8] = b"acme-tls/1";
+
+/// Let's Encrypt production directory.
+pub const LETS_ENCRYPT: &str = "https://acme-v02.api.letsencrypt.org/directory";
+
+/// Interval between order status checks.
+const POLL_INTERVAL: Duration = Duration::from_secs(2);
+
+/// Maximum order status checks before issuance is abandoned.
+const POLL_ATTEMPTS: usize = 30;
+
+/// Store key holding the account credentials.
+const ACCOUNT_KEY: &str = "account.json";
+
+/// Maximum names waiting to be loaded or issued.
+const QUEUE_SIZE: usize = 64;
+
+/// Time allowed for the approval url to answer.
+const ASK_TIMEOUT: Duration = Duration::from_secs(5);
+
+/// On-demand issuance settings.
+#[derive(Clone, Debug)]
+pub struct Settings {
+    /// Server names certificates may be issued for.
+    pub allow: Vec<DomainMatch>,
+    /// ACME directory url.
+    pub directory: String,
+    /// Contact urls registered with the account.
+    pub contact: Vec<String>,
+    /// Store certificates and account credentials are kept in.
+    pub store: Arc<dyn CertStore>,
+    /// Url approving names before issuance.
+    pub ask: Option<String>,
+    /// Maximum certificates issued within the window.
+    pub max_issuances: usize,
+    /// Maximum certificates issued for a single name within the window.
+    pub max_name_issuances: usize,
+    /// Window used to rate limit issuance.
+    pub window: Duration,
+    /// Time before retrying a failed issuance for the same name.
+    pub retry_after: Duration,
+    /// Renew certificates once they expire within this period.
+    pub renew_before: Duration,
+    /// Bundle the `www` or apex counterpart and aliases on one certificate.
+    pub bundle: bool,
+    /// Literal server names bundled together when `bundle` is enabled.
+    pub aliases: Vec<String>,
+    /// Host the server's `canonical` redirect sends other names to.
+    pub canonical: Option<String>,
+    /// Hooks run after a certificate is issued or renewed.
+    pub hooks: Vec<HookCfg>,
+}
+
+/// Certificate held in memory with its expiry.
+struct Cached {
+    key: Arc<CertifiedKey>,
+    expires: SystemTime,
+}
+
+/// Issuance state shared between the resolver and issuing thread.
+struct State {
+    settings: Settings,
+    certs: RwLock<HashMap<String, Cached>>,
+    challenges: RwLock<HashMap<String, Arc<CertifiedKey>>>,
+    pending: Mutex<HashSet<String>>,
+    failures: Mutex<HashMap<String, Instant>>,
+    issued: Mutex<VecDeque<(Instant, String)>>,
+}
+
+impl State {
+    /// Keep the certificate for the server name in memory.
+    fn cache(&self, name: &str, key: Arc<CertifiedKey>, expires: SystemTime) {
+        self.certs
+            .write()
+            .expect("acme cert lock poisoned")
+            .insert(name.to_owned(), Cached { key, expires });
+    }
+
+    /// Load a stored certificate for the server name into memory.
+    fn load(&self, name: &str) -> Result<Option<(Arc<CertifiedKey>, SystemTime)>> {
+        let Some(stored) = store::load(self.settings.store.as_ref(), name)? else {
+            return Ok(None);
+        };
+        let (key, expires) = (stored.key, stored.expires);
+        self.cache(name, Arc::clone(&key), expires);
+        Ok(Some((key, expires)))
+    }
+
+    /// Check if the name may be issued a certificate.
+    fn allowed(&self, name: &str) -> bool {
+        !name.is_empty() && self.settings.allow.iter().any(|d| d.matches(name))
+    }
+
+    /// Names requested on the same certificate as the server name.
+    fn bundle(&self, name: &str) -> Vec<String> {
+        let mut names = vec![name.to_owned()];
+        if !self.settings.bundle {
+            return names;
+        }
+        // names redirected to the canonical host are bundled with it,
+        // otherwise with their `www` or apex counterpart
+        let counterpart = match self.settings.canonical.as_deref() {
+            Some(canonical) if canonical != name => canonical.to_owned(),
+            _ => match name.strip_prefix("www.") {
+                Some(apex) if apex.contains('.') => apex.to_owned(),
+                Some(_) => String::new(),
+                None => format!("www.{name}"),
+            },
+        };
+        let aliases = match self.settings.aliases.iter().any(|a| a == name) {
+            true => self.settings.aliases.as_slice(),
+            false => &[],
+        };
+        for alias in std::iter::once(&counterpart).chain(aliases) {
+            if self.allowed(alias) && !names.contains(alias) {
+                names.push(alias.clone());
+            }
+        }
+        names
+    }
+
+    /// Check if the certificate should be renewed.
+    fn renew_due(&self, expires: SystemTime) -> bool {
+        expires
+            .checked_sub(self.settings.renew_before)
+            .is_none_or(|renew| renew <= SystemTime::now())
+    }
+
+    /// Reserve an issuance for the name within the rate limits.
+    fn reserve(&self, name: &str) -> bool {
+        let mut issued = self.issued.lock().expect("acme rate lock poisoned");
+        while issued
+            .front()
+            .is_some_and(|(at, _)| at.elapsed() >= self.settings.window)
+        {
+            issued.pop_front();
+        }
+        if issued.len() >= self.settings.max_issuances {
+            return false;
+        }
+        let count = issued.iter().filter(|(_, n)| n == name).count();
+        if count >= self.settings.max_name_issuances {
+            return false;
+        }
+        issued.push_back((Instant::now(), name.to_owned()));
+        true
+    }
+
+    /// Mark issuance for the name complete, recording any failure.
+    fn finish(&self, name: &str, failed: bool) {
+        if failed {
+            let mut failures = self.failures.lock().expect("acme failure lock poisoned");
+            failures.retain(|_, at| at.elapsed() < self.settings.retry_after);
+            failures.insert(name.to_owned(), Instant::now());
+        }
+        self.pending
+            .lock()
+            .expect("acme pending lock poisoned")
+            .remove(name);
+    }
+}
+
+/// Generate the self-signed `tls-alpn-01` challenge certificate.
+fn challenge_key(name: &str, digest: &[u8]) -> Result<Arc<CertifiedKey>> {
+    let mut params = CertificateParams::new(vec![name.to_owned()])?;
+    params.custom_extensions = vec![CustomExtension::new_acme_identifier(digest)];
+    let key = KeyPair::generate()?;
+    let cert = params.self_signed(&key)?;
+    let der = PrivateKeyDer::Pkcs8(PrivatePkcs8KeyDer::from(key.serialize_der()));
+    Ok(Arc::new(CertifiedKey::new(
+        vec![cert.der().clone()],
+        any_supported_type(&der).context("failed to wrap challenge key")?,
+    )))
+}
+
+/// On-demand certificate manager used by the TLS resolver.
+pub struct OnDemand {
+    state: Arc<State>,
+    queue: Sender<String>,
+}
+
+impl std::fmt::Debug for OnDemand {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("OnDemand")
+            .field("settings", &self.state.settings)
+            .finish()
+    }
+}
+
+impl OnDemand {
+    /// Create manager and spawn its issuing thread.
+    ///
+    /// The thread exits once the manager is dropped.
+    pub fn new(settings: Settings) -> Result<Self> {
+        let state = Arc::new(State {
+            settings,
+            certs: Default::default(),
+            challenges: Default::default(),
+            pending: Default::default(),
+            failures: Default::default(),
+            issued: Default::default(),
+        });
+        let (queue, rx) = channel(QUEUE_SIZE);
+        let worker = Arc::clone(&state);
+        std::thread::Builder::new()
+            .name("bob-acme".to_owned())
+            .spawn(move || actix_web::rt::System::new().block_on(issuer(worker, rx)))
+            .context("failed to spawn acme issuer")?;
+        Ok(Self { state, queue })
+    }
+
+    /// Retrieve the pending challenge certificate for the server name.
+    pub fn challenge(&self, name: &str) -> Option<Arc<CertifiedKey>> {
+        self.state
+            .challenges
+            .read()
+            .expect("acme challenge lock poisoned")
+            .get(&name.to_ascii_lowercase())
+            .cloned()
+    }
+
+    /// Retrieve the certificate for the server name.
+    ///
+    /// Allowed names without a certificate are queued for issuance.
+    pub fn resolve(&self, name: &str) -> Option<Arc<CertifiedKey>> {
+        let name = name.to_ascii_lowercase();
+        if !self.state.allowed(&name) {
+            return None;
+        }
+        let cached = self
+            .state
+            .certs
+            .read()
+            .expect("acme cert lock poisoned")
+            .get(&name)
+            .map(|c| (Arc::clone(&c.key), c.expires));
+        match cached {
+            Some((key, expires)) => {
+                if self.state.renew_due(expires) {
+                    self.request(&name);
+                }
+                Some(key)
+            }
+            // stored certificates are loaded by the issuer in the background
+            None => {
+                self.request(&name);
+                None
+            }
+        }
+    }
+
+    /// Queue issuance unless already pending or recently failed.
+    fn request(&self, name: &str) {
+        let failed = self
+            .state
+            .failures
+            .lock()
+            .expect("acme failure lock poisoned")
+            .get(name)
+            .is_some_and(|at| at.elapsed() < self.state.settings.retry_after);
+        if failed {
+            return;
+        }
+        let mut pending = self
+            .state
+            .pending
+            .lock()
+            .expect("acme pending lock poisoned");
+        if !pending.insert(name.to_owned()) {
+            return;
+        }
+        match self.queue.try_send(name.to_owned()) {
+            Ok(()) => {}
+            Err(TrySendError::Full(_)) => {
+                log::debug!("acme queue full. dropping request for {name:?}");
+                pending.remove(name);
+            }
+            Err(TrySendError::Closed(_)) => {
+                pending.remove(name);
+            }
+        }
+    }
+}
+
+/// Process queued issuance requests one at a time.
+async fn issuer(state: Arc<State>, mut rx: Receiver<String>) {
+    let mut account = None;
+    while let Some(name) = rx.recv().await {
+        let store = Arc::clone(&state.settings.store);
+        let lookup = name.clone();
+        let stored = actix_web::rt::task::spawn_blocking(move || {
+            store::load(store.as_ref(), &lookup).map(|cert| cert.map(|c| (c.key, c.expires)))
+        })
+        .await
+        .map_err(anyhow::Error::from)
+        .and_then(|loaded| loaded);
+        match stored {
+            Ok(Some((key, expires))) => {
+                state.cache(&name, key, expires);
+                if !state.renew_due(expires) {
+                    state.finish(&name, false);
+                    continue;
+                }
+            }
+            Ok(None) => {}
+            Err(err) => {
+                log::error!("failed to load stored certificate for {name:?}: {err:?}");
+                state.finish(&name, true);
+                continue;
+            }
+        }
+        let mut names = state.bundle(&name);
+        if let Some(ask) = state.settings.ask.clone() {
+            let lookup = names.clone();
+            let approved = actix_web::rt::task::spawn_blocking(move || {
+                lookup
+                    .into_iter()
+                    .map(|domain| approved(&ask, &domain).map(|_| domain))
+                    .collect::<Vec<_>>()
+            })
+            .await;
+            let mut results = match approved {
+                Ok(results) => results.into_iter(),
+                Err(err) => {
+                    log::error!("acme approval task failed: {err:?}");
+                    state.finish(&name, true);
+                    continue;
+                }
+            };
+            if let Some(Err(err)) = results.next() {
+                log::warn!("acme issuance for {name:?} not approved: {err:?}");
+                state.finish(&name, true);
+                continue;
+            }
+            names.truncate(1);
+            for result in results {
+                match result {
+                    Ok(alias) => names.push(alias),
+                    Err(err) => log::warn!("acme alias of {name:?} not approved: {err:?}"),
+                }
+            }
+        }
+        if !state.reserve(&name) {
+            log::warn!("acme issuance rate limit reached. deferring {name:?}");
+            state.finish(&name, true);
+            continue;
+        }
+        if account.is_none() {
+            account = match load_account(&state.settings).await {
+                Ok(account) => Some(account),
+                Err(err) => {
+                    log::error!("acme account unavailable: {err:?}");
+                    state.finish(&name, true);
+                    continue;
+                }
+            };
+        }
+        let account = account.as_ref().expect("acme account loaded");
+        log::info!("issuing certificate for {names:?}");
+        match issue(&state, account, &names).await {
+            Ok(()) => {
+                log::info!("issued certificate for {name:?}");
+                state.finish(&name, false);
+                hooks::run_all(state.settings.hooks.clone(), hooks::Event::CertRenewed).await;
+            }
+            Err(err) => {
+                log::error!("certificate issuance for {name:?} failed: {err:?}");
+                state.finish(&name, true);
+            }
+        }
+    }
+    log::debug!("acme issuer stopped");
+}
+
+/// Ask the approval url whether a certificate may be issued for the name.
+fn approved(ask: &str, name: &str) -> Result<()> {
+    ureq::get(ask)
+        .query("domain", name)
+        .timeout(ASK_TIMEOUT)
+        .call()
+        .context("approval request failed")?;
+    Ok(())
+}
+
+/// Load stored account credentials or register a new account.
+async fn load_account(settings: &Settings) -> Result<Account> {
+    let stored = settings
+        .store
+        .get(ACCOUNT_KEY)
+        .context("failed to read acme account")?;
+    if let Some(data) = stored {
+        let credentials: AccountCredentials =
+            serde_json::from_slice(&data).context("invalid acme account")?;
+        return Account::from_credentials(credentials)
+            .await
+            .context("failed to restore acme account");
+    }
+    let contact: Vec<&str> = settings.contact.iter().map(|c| c.as_str()).collect();
+    let (account, credentials) = Account::create(
+        &NewAccount {
+            contact: &contact,
+            terms_of_service_agreed: true,
+            only_return_existing: false,
+        },
+        &settings.directory,
+        None,
+    )
+    .await
+    .context("failed to register acme account")?;
+    let data = serde_json::to_vec_pretty(&credentials)?;
+    settings
+        .store
+        .put(ACCOUNT_KEY, &data)
+        .context("failed to store acme account")?;
+    log::info!("registered acme account with {:?}", settings.directory);
+    Ok(account)
+}
+
+/// Wait for the order to leave the pending and processing states.
+async fn wait(order: &mut Order, until: OrderStatus) -> Result<()> {
+    for _ in 0..POLL_ATTEMPTS {
+        let status = order.refresh().await?.status;
+        match status {
+            status if status == until => return Ok(()),
+            OrderStatus::Invalid => bail!("order became invalid"),
+            _ => actix_web::rt::time::sleep(POLL_INTERVAL).await,
+        }
+    }
+    bail!("order did not become {until:?} in time")
+}
+
+/// Complete an order for the server names and store the certificate.
+///
+/// The certificate is stored and cached under every name it covers.
+async fn issue(state: &State, account: &Account, names: &[String]) -> Result<()> {
+    let identifiers: Vec<_> = names.iter().cloned().map(Identifier::Dns).collect();
+    let mut order = account
+        .new_order(&NewOrder {
+            identifiers: &identifiers,
+        })
+        .await?;
+
+    let mut ready = Vec::new();
+    let mut pending = Vec::new();
+    for authz in order.authorizations().await? {
+        match authz.status {
+            AuthorizationStatus::Pending => {}
+            AuthorizationStatus::Valid => continue,
+            status => bail!("authorization is {status:?}"),
+        }
+        let Identifier::Dns(name) = &authz.identifier;
+        let challenge = authz
+            .challenges
+            .iter()
+            .find(|c| c.r#type == ChallengeType::TlsAlpn01)
+            .context("tls-alpn-01 challenge not offered")?;
+        let digest = order.key_authorization(challenge).digest();
+        let key = challenge_key(name, digest.as_ref())?;
+        state
+            .challenges
+            .write()
+            .expect("acme challenge lock poisoned")
+            .insert(name.to_owned(), key);
+        pending.push(name.to_owned());
+        ready.push(challenge.url.clone());
+    }
+    let validated = async {
+        for url in ready.iter() {
+            order.set_challenge_ready(url).await?;
+        }
+        wait(&mut order, OrderStatus::Ready).await
+    }
+    .await;
+    {
+        let mut challenges = state
+            .challenges
+            .write()
+            .expect("acme challenge lock poisoned");
+        for name in pending.iter() {
+            challenges.remove(name);
+        }
+    }
+    validated?;
+
+    let key = KeyPair::generate()?;
+    let mut params = CertificateParams::new(names.to_vec())?;
+    params.distinguished_name = DistinguishedName::new();
+    let csr = params.serialize_request(&key)?;
+    order.finalize(csr.der()).await?;
+    let mut chain = None;
+    for _ in 0..POLL_ATTEMPTS {
+        chain = order.certificate().await?;
+        if chain.is_some() {
+            break;
+        }
+        actix_web::rt::time::sleep(POLL_INTERVAL).await;
+    }
+    let chain = chain.context("certificate was not issued in time")?;
+
+    let pem = key.serialize_pem();
+    for name in names {
+        store::save(
+            state.settings.store.as_ref(),
+            name,
+            chain.as_bytes(),
+            pem.as_bytes(),
+        )
+        .with_context(|| format!("failed to store certificate for {name:?}"))?;
+        state
+            .load(name)?
+            .context("issued certificate missing from storage")?;
+    }
+    Ok(())
+}
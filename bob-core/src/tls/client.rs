@@ -0,0 +1,118 @@
+//! TLS Client Certificate Validation Utils
+
+use std::{path::Path, sync::Arc};
+
+use actix_tls::connect::rustls_0_23::webpki_roots_cert_store;
+use anyhow::{Context, Result};
+use rustls::{
+    RootCertStore,
+    client::danger::{ServerCertVerified, ServerCertVerifier},
+    pki_types::{CertificateDer, PrivateKeyDer, ServerName, UnixTime, pem::PemObject},
+};
+
+/// Build Client TLS Configuration Setting
+pub fn build_tls_config(verify_ssl: bool) -> rustls::ClientConfig {
+    let mut config = rustls::ClientConfig::builder()
+        .with_root_certificates(webpki_roots_cert_store())
+        .with_no_client_auth();
+    config.alpn_protocols = vec![b"h2".to_vec(), b"http/1.1".to_vec()];
+    if !verify_ssl {
+        config
+            .dangerous()
+            .set_certificate_verifier(Arc::new(NoCertificateVerification));
+    }
+    config
+}
+
+/// Build Client TLS Configuration with a private trust store and client identity.
+///
+/// Certificates in `ca_file` replace the default webpki roots when given.
+/// The `identity` certificate chain and private key are presented to
+/// upstreams requesting client authentication.
+pub fn build_custom_tls_config(
+    verify_ssl: bool,
+    ca_file: Option<&Path>,
+    identity: Option<(&Path, &Path)>,
+) -> Result<rustls::ClientConfig> {
+    let roots = match ca_file {
+        None => webpki_roots_cert_store(),
+        Some(path) => {
+            let mut roots = RootCertStore::empty();
+            for cert in CertificateDer::pem_file_iter(path)
+                .with_context(|| format!("failed to read ca file {path:?}"))?
+            {
+                let cert = cert.with_context(|| format!("invalid certificate in {path:?}"))?;
+                roots
+                    .add(cert)
+                    .with_context(|| format!("invalid ca certificate in {path:?}"))?;
+            }
+            if roots.is_empty() {
+                anyhow::bail!("no certificates found in ca file {path:?}");
+            }
+            roots
+        }
+    };
+    let builder = rustls::ClientConfig::builder().with_root_certificates(roots);
+    let mut config = match identity {
+        None => builder.with_no_client_auth(),
+        Some((certs, key)) => {
+            let chain = CertificateDer::pem_file_iter(certs)
+                .with_context(|| format!("failed to read client certificate {certs:?}"))?
+                .collect::<Result<Vec<_>, _>>()
+                .with_context(|| format!("invalid client certificate {certs:?}"))?;
+            let key = PrivateKeyDer::from_pem_file(key)
+                .with_context(|| format!("invalid client key {key:?}"))?;
+            builder
+                .with_client_auth_cert(chain, key)
+                .context("invalid client certificate and key pair")?
+        }
+    };
+    config.alpn_protocols = vec![b"h2".to_vec(), b"http/1.1".to_vec()];
+    if !verify_ssl {
+        config
+            .dangerous()
+            .set_certificate_verifier(Arc::new(NoCertificateVerification));
+    }
+    Ok(config)
+}
+
+/// No Verification TLS Configuration
+#[derive(Debug)]
+pub(crate) struct NoCertificateVerification;
+
+impl ServerCertVerifier for NoCertificateVerification {
+    fn verify_server_cert(
+        &self,
+        _end_entity: &CertificateDer<'_>,
+        _intermediates: &[CertificateDer<'_>],
+        _server_name: &ServerName<'_>,
+        _ocsp_response: &[u8],
+        _now: UnixTime,
+    ) -> Result<ServerCertVerified, rustls::Error> {
+        Ok(rustls::client::danger::ServerCertVerified::assertion())
+    }
+
+    fn verify_tls12_signature(
+        &self,
+        _message: &[u8],
+        _cert: &CertificateDer<'_>,
+        _dss: &rustls::DigitallySignedStruct,
+    ) -> Result<rustls::client::danger::HandshakeSignatureValid, rustls::Error> {
+        Ok(rustls::client::danger::HandshakeSignatureValid::assertion())
+    }
+
+    fn verify_tls13_signature(
+        &self,
+        _message: &[u8],
+        _cert: &CertificateDer<'_>,
+        _dss: &rustls::DigitallySignedStruct,
+    ) -> Result<rustls::client::danger::HandshakeSignatureValid, rustls::Error> {
+        Ok(rustls::client::danger::HandshakeSignatureValid::assertion())
+    }
+
+    fn supported_verify_schemes(&self) -> Vec<rustls::SignatureScheme> {
+        rustls::crypto::aws_lc_rs::default_provider()
+            .signature_verification_algorithms
+            .supported_schemes()
+    }
+}
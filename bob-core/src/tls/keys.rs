@@ -0,0 +1,60 @@
+//! Encrypted Private Keys
+//!
+//! Loads passphrase protected private keys so they never need to be
+//! stored on disk in plaintext. Supports PKCS#8 `ENCRYPTED PRIVATE KEY`
+//! PEM files and PKCS#12 (`.p12`/`.pfx`) bundles containing both the
+//! private key and its certificate chain.
+
+use std::path::Path;
+
+use anyhow::{Context, Result, anyhow};
+use pkcs8::{EncryptedPrivateKeyInfo, der::SecretDocument};
+use rustls::pki_types::{CertificateDer, PrivateKeyDer, PrivatePkcs8KeyDer};
+
+/// Check if the key file is a PKCS#12 bundle by its extension.
+pub fn is_pkcs12(path: &Path) -> bool {
+    path.extension()
+        .and_then(|ext| ext.to_str())
+        .is_some_and(|ext| ext.eq_ignore_ascii_case("p12") || ext.eq_ignore_ascii_case("pfx"))
+}
+
+/// Decrypt a PKCS#8 `ENCRYPTED PRIVATE KEY` PEM with the passphrase.
+pub fn decrypt_pem(pem: &[u8], passphrase: &str) -> Result<PrivateKeyDer<'static>> {
+    let pem = std::str::from_utf8(pem).context("private key is not valid pem")?;
+    let (label, document) =
+        SecretDocument::from_pem(pem).map_err(|err| anyhow!("invalid private key pem: {err}"))?;
+    if label != "ENCRYPTED PRIVATE KEY" {
+        anyhow::bail!(
+            "expected an ENCRYPTED PRIVATE KEY but found {label:?} \
+             (convert with `openssl pkcs8 -topk8`)"
+        );
+    }
+    let info = EncryptedPrivateKeyInfo::try_from(document.as_bytes())
+        .map_err(|err| anyhow!("invalid encrypted private key: {err}"))?;
+    let key = info
+        .decrypt(passphrase)
+        .map_err(|err| anyhow!("failed to decrypt private key: {err}"))?;
+    Ok(PrivateKeyDer::Pkcs8(PrivatePkcs8KeyDer::from(
+        key.as_bytes().to_vec(),
+    )))
+}
+
+/// Load the certificate chain and private key from a PKCS#12 bundle.
+pub fn load_pkcs12(
+    path: &Path,
+    passphrase: &str,
+) -> Result<(Vec<CertificateDer<'static>>, PrivateKeyDer<'static>)> {
+    let data = std::fs::read(path).with_context(|| format!("failed to read {path:?}"))?;
+    let keystore = p12_keystore::KeyStore::from_pkcs12(&data, passphrase)
+        .map_err(|err| anyhow!("failed to decrypt {path:?}: {err}"))?;
+    let (_, chain) = keystore
+        .private_key_chain()
+        .with_context(|| format!("{path:?} does not contain a private key"))?;
+    let certs = chain
+        .chain()
+        .iter()
+        .map(|cert| CertificateDer::from(cert.as_der().to_vec()))
+        .collect();
+    let key = PrivateKeyDer::Pkcs8(PrivatePkcs8KeyDer::from(chain.key().to_vec()));
+    Ok((certs, key))
+}
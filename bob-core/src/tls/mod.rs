@@ -0,0 +1,11 @@
+//! TLS Client/Server Configuration
+
+#[cfg(feature = "acme")]
+pub mod acme;
+pub mod client;
+#[cfg(feature = "keystore")]
+pub mod keys;
+#[cfg(feature = "ocsp")]
+pub mod ocsp;
+pub mod server;
+pub mod store;
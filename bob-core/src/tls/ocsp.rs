@@ -0,0 +1,211 @@
+//! OCSP Stapling
+//!
+//! Fetches OCSP responses for loaded certificates from the responder
+//! named in their Authority Information Access extension and staples
+//! them to TLS handshakes, so clients need not contact the certificate
+//! authority themselves. Responses are refreshed periodically from a
+//! background thread which exits once its certificates are dropped.
+
+use std::{
+    io::Read,
+    sync::{Arc, RwLock, Weak},
+    time::{Duration, Instant},
+};
+
+use anyhow::{Context, Result, anyhow, bail};
+use rustls::{pki_types::CertificateDer, sign::CertifiedKey};
+use sha1::{Digest, Sha1};
+use x509_parser::{
+    certificate::X509Certificate,
+    extensions::{GeneralName, ParsedExtension},
+    oid_registry::OID_PKIX_ACCESS_DESCRIPTOR_OCSP,
+};
+
+/// Certificate whose stapled OCSP response is replaced on refresh.
+pub type Stapled = Arc<RwLock<Arc<CertifiedKey>>>;
+
+/// DER encoded sha1 `AlgorithmIdentifier` used to identify certificates.
+const SHA1_ALGORITHM: &[u8] = &[
+    0x30, 0x09, 0x06, 0x05, 0x2b, 0x0e, 0x03, 0x02, 0x1a, 0x05, 0x00,
+];
+
+/// Largest OCSP response accepted from a responder.
+const MAX_RESPONSE: u64 = 64 * 1024;
+
+/// Longest delay before retrying a failed fetch.
+const MAX_RETRY: Duration = Duration::from_secs(5 * 60);
+
+/// OCSP stapling settings.
+#[derive(Clone, Debug)]
+pub struct Settings {
+    /// Interval between response refreshes.
+    pub refresh: Duration,
+    /// Time allowed for the responder to reply.
+    pub timeout: Duration,
+}
+
+/// Prepared OCSP query for a single certificate.
+struct Job {
+    key: Weak<RwLock<Arc<CertifiedKey>>>,
+    responder: String,
+    request: Vec<u8>,
+    settings: Settings,
+    next: Instant,
+}
+
+/// Encode DER tag-length-value.
+fn der(tag: u8, content: &[u8]) -> Vec<u8> {
+    let mut out = vec![tag];
+    match content.len() {
+        len if len < 0x80 => out.push(len as u8),
+        len => {
+            let bytes: Vec<u8> = len
+                .to_be_bytes()
+                .into_iter()
+                .skip_while(|b| *b == 0)
+                .collect();
+            out.push(0x80 | bytes.len() as u8);
+            out.extend(bytes);
+        }
+    }
+    out.extend(content);
+    out
+}
+
+/// Find the OCSP responder url of the certificate.
+fn responder(cert: &X509Certificate) -> Option<String> {
+    cert.extensions()
+        .iter()
+        .find_map(|ext| match ext.parsed_extension() {
+            ParsedExtension::AuthorityInfoAccess(aia) => aia
+                .accessdescs
+                .iter()
+                .filter(|desc| desc.access_method == OID_PKIX_ACCESS_DESCRIPTOR_OCSP)
+                .find_map(|desc| match &desc.access_location {
+                    GeneralName::URI(uri) => Some(uri.to_string()),
+                    _ => None,
+                }),
+            _ => None,
+        })
+}
+
+/// Build the DER encoded OCSP request for the leaf certificate.
+///
+/// Returns the responder url and request, or `None` without a responder.
+fn prepare(chain: &[CertificateDer]) -> Result<Option<(String, Vec<u8>)>> {
+    let [leaf, issuer, ..] = chain else {
+        bail!("certificate chain does not include its issuer");
+    };
+    let (_, leaf) = x509_parser::parse_x509_certificate(leaf.as_ref())
+        .map_err(|err| anyhow!("invalid certificate: {err}"))?;
+    let Some(url) = responder(&leaf) else {
+        return Ok(None);
+    };
+    let (_, issuer) = x509_parser::parse_x509_certificate(issuer.as_ref())
+        .map_err(|err| anyhow!("invalid issuer certificate: {err}"))?;
+
+    let name_hash = Sha1::digest(issuer.subject().as_raw());
+    let key_hash = Sha1::digest(issuer.public_key().subject_public_key.data.as_ref());
+    let mut cert_id = SHA1_ALGORITHM.to_vec();
+    cert_id.extend(der(0x04, &name_hash));
+    cert_id.extend(der(0x04, &key_hash));
+    cert_id.extend(der(0x02, leaf.raw_serial()));
+
+    let request = der(0x30, &der(0x30, &der(0x30, &der(0x30, &cert_id))));
+    Ok(Some((url, der(0x30, &request))))
+}
+
+/// Check the OCSP response reports a successful status.
+///
+/// Certificate status and signatures are verified by clients.
+fn successful(response: &[u8]) -> bool {
+    // OCSPResponse ::= SEQUENCE { responseStatus ENUMERATED, ... }
+    let offset = match response.get(1) {
+        Some(len) if len & 0x80 == 0 => 2,
+        Some(len) => 2 + (len & 0x7f) as usize,
+        None => return false,
+    };
+    response.first() == Some(&0x30) && response.get(offset..offset + 3) == Some(&[0x0a, 0x01, 0x00])
+}
+
+/// Query the responder for a fresh OCSP response.
+fn fetch(job: &Job) -> Result<Vec<u8>> {
+    let response = ureq::post(&job.responder)
+        .timeout(job.settings.timeout)
+        .set("Content-Type", "application/ocsp-request")
+        .send_bytes(&job.request)
+        .context("ocsp request failed")?;
+    let mut body = Vec::new();
+    response
+        .into_reader()
+        .take(MAX_RESPONSE)
+        .read_to_end(&mut body)
+        .context("failed to read ocsp response")?;
+    if !successful(&body) {
+        bail!("ocsp responder returned an unsuccessful response");
+    }
+    Ok(body)
+}
+
+/// Refresh the job, stapling the new response to its certificate.
+///
+/// Returns false once the certificate has been dropped.
+fn refresh(job: &mut Job) -> bool {
+    let Some(key) = job.key.upgrade() else {
+        return false;
+    };
+    match fetch(job) {
+        Ok(response) => {
+            let mut current = key.write().expect("ocsp staple lock poisoned");
+            let mut stapled = CertifiedKey::clone(&current);
+            stapled.ocsp = Some(response);
+            *current = Arc::new(stapled);
+            log::debug!("stapled ocsp response from {:?}", job.responder);
+            job.next = Instant::now() + job.settings.refresh;
+        }
+        Err(err) => {
+            log::warn!("ocsp refresh from {:?} failed: {err:?}", job.responder);
+            job.next = Instant::now() + job.settings.refresh.min(MAX_RETRY);
+        }
+    }
+    true
+}
+
+/// Staple OCSP responses to the certificates in the background.
+///
+/// Certificates without an OCSP responder are skipped.
+pub fn spawn(keys: Vec<(Stapled, Settings)>) -> Result<()> {
+    let mut jobs = Vec::new();
+    for (key, settings) in keys {
+        let chain = key.read().expect("ocsp staple lock poisoned").cert.clone();
+        match prepare(&chain) {
+            Ok(Some((responder, request))) => jobs.push(Job {
+                key: Arc::downgrade(&key),
+                responder,
+                request,
+                settings,
+                next: Instant::now(),
+            }),
+            Ok(None) => log::debug!("certificate has no ocsp responder"),
+            Err(err) => log::warn!("ocsp stapling unavailable: {err:?}"),
+        }
+    }
+    if jobs.is_empty() {
+        return Ok(());
+    }
+    std::thread::Builder::new()
+        .name("bob-ocsp".to_owned())
+        .spawn(move || {
+            while !jobs.is_empty() {
+                let now = Instant::now();
+                jobs.retain_mut(|job| job.next > now || refresh(job));
+                let next = jobs.iter().map(|job| job.next).min();
+                if let Some(next) = next {
+                    std::thread::sleep(next.saturating_duration_since(Instant::now()));
+                }
+            }
+            log::debug!("ocsp stapling stopped");
+        })
+        .context("failed to spawn ocsp stapling")?;
+    Ok(())
+}
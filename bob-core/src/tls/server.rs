@@ -0,0 +1,255 @@
+//! TLS "Server Name Indication" (SNI)
+
+use std::{
+    any::Any,
+    path::PathBuf,
+    sync::{Arc, RwLock},
+};
+
+use crate::{
+    config::{DomainMatch, ServerConfig},
+    metrics,
+};
+use actix_tls::accept::rustls_0_23::TlsStream;
+use actix_web::{dev::Extensions, rt::net::TcpStream};
+use anyhow::{Context, Result};
+use rustls::{
+    crypto::aws_lc_rs::sign::any_supported_type,
+    pki_types::{CertificateDer, PrivateKeyDer, pem::PemObject},
+    server::{ClientHello, ResolvesServerCert},
+    sign::CertifiedKey,
+};
+
+/// Negotiated TLS connection details attached to every request.
+#[derive(Clone, Debug)]
+pub struct TlsInfo {
+    pub sni: Option<String>,
+    pub alpn: Option<String>,
+    pub version: String,
+    pub cipher: String,
+}
+
+/// Collect [`TlsInfo`] for new connections into connection data.
+///
+/// Registered with every listener connection service.
+pub(crate) fn on_connect(conn: &dyn Any, ext: &mut Extensions) {
+    let Some(stream) = conn.downcast_ref::<TlsStream<TcpStream>>() else {
+        return;
+    };
+    let (_, session) = stream.get_ref();
+    let info = TlsInfo {
+        sni: session.server_name().map(|s| s.to_owned()),
+        alpn: session
+            .alpn_protocol()
+            .map(|p| String::from_utf8_lossy(p).into_owned()),
+        version: session
+            .protocol_version()
+            .map(|v| format!("{v:?}"))
+            .unwrap_or_else(|| "-".to_owned()),
+        cipher: session
+            .negotiated_cipher_suite()
+            .map(|c| format!("{:?}", c.suite()))
+            .unwrap_or_else(|| "-".to_owned()),
+    };
+    let labels = format!("version={:?},cipher={:?}", info.version, info.cipher);
+    metrics::counter(&format!("bob_tls_connections_total{{{labels}}}")).inc();
+    ext.insert(info);
+}
+
+/// Build SNI Server Configuration
+#[inline]
+pub(crate) fn build_tls_config(config: &[ServerConfig]) -> Result<rustls::ServerConfig> {
+    let resolver = TlsResolver::new(config)?;
+    #[cfg(feature = "acme")]
+    let on_demand = !resolver.on_demand.is_empty();
+    #[cfg_attr(not(feature = "acme"), allow(unused_mut))]
+    let mut tls = rustls::ServerConfig::builder()
+        .with_no_client_auth()
+        .with_cert_resolver(Arc::new(resolver));
+    // actix prepends its own http protocols to any configured here
+    #[cfg(feature = "acme")]
+    if on_demand {
+        tls.alpn_protocols = vec![super::acme::ACME_TLS_ALPN.to_vec()];
+    }
+    Ok(tls)
+}
+
+/// Read PEM certificate chain from file
+#[inline]
+fn read_certs(certs: &PathBuf) -> Result<Vec<CertificateDer<'static>>> {
+    Ok(CertificateDer::pem_file_iter(certs)
+        .context("failed to read tls certificate")?
+        .map(|pem| pem.expect("invalid pem"))
+        .collect())
+}
+
+/// Generate [`CertifiedKey`] from Cert/PrivKey files
+///
+/// Encrypted keys and PKCS#12 bundles are decrypted using the passphrase.
+fn certified_key(
+    certs: Option<&PathBuf>,
+    key: &PathBuf,
+    passphrase: Option<&str>,
+) -> Result<Arc<CertifiedKey>> {
+    let missing = || anyhow::anyhow!("missing tls certificate");
+    let (certs, private_key) = match passphrase {
+        #[cfg(feature = "keystore")]
+        _ if super::keys::is_pkcs12(key) => {
+            let (chain, private_key) =
+                super::keys::load_pkcs12(key, passphrase.unwrap_or_default())?;
+            match certs {
+                Some(certs) => (read_certs(certs)?, private_key),
+                None => (chain, private_key),
+            }
+        }
+        #[cfg(feature = "keystore")]
+        Some(passphrase) => {
+            let pem = std::fs::read(key).context("failed to read private tls key")?;
+            (
+                read_certs(certs.ok_or_else(missing)?)?,
+                super::keys::decrypt_pem(&pem, passphrase).context("invalid private tls key")?,
+            )
+        }
+        _ => (
+            read_certs(certs.ok_or_else(missing)?)?,
+            PrivateKeyDer::from_pem_file(key).context("invalid private tls key")?,
+        ),
+    };
+    Ok(Arc::new(CertifiedKey {
+        cert: certs,
+        key: any_supported_type(&private_key).context("failed to wrap private key")?,
+        ocsp: None,
+    }))
+}
+
+/// Individual [`ServerConfig`] TLS Configuration
+#[derive(Debug)]
+struct TlsEntry {
+    domains: Vec<DomainMatch>,
+    sans: Vec<String>,
+    key: Arc<RwLock<Arc<CertifiedKey>>>,
+}
+
+impl TlsEntry {
+    #[inline]
+    fn matches(&self, name: &str) -> bool {
+        match self.sans.is_empty() {
+            true => self.domains.is_empty() || self.domains.iter().any(|d| d.matches(name)),
+            false => self.sans.iter().any(|san| super::store::covers(san, name)),
+        }
+    }
+    #[inline]
+    fn key(&self) -> Arc<CertifiedKey> {
+        Arc::clone(&self.key.read().expect("tls key lock poisoned"))
+    }
+}
+
+/// Global TLS SNI configuration controls
+#[derive(Debug)]
+pub struct TlsResolver {
+    entries: Vec<TlsEntry>,
+    fallback: Option<usize>,
+    #[cfg(feature = "acme")]
+    on_demand: Vec<super::acme::OnDemand>,
+}
+
+impl TlsResolver {
+    #[inline]
+    pub fn new(config: &[ServerConfig]) -> Result<Self> {
+        let mut entries = Vec::new();
+        let mut fallback = None;
+        #[cfg(feature = "ocsp")]
+        let mut stapled = Vec::new();
+        for srv in config.iter() {
+            for ssl in srv.listen.iter().filter_map(|l| l.ssl.as_ref()) {
+                #[cfg(feature = "ocsp")]
+                let first = entries.len();
+                if let Some(store) = ssl.store.as_ref() {
+                    let store = store.build()?;
+                    let certs = super::store::discover(store.as_ref())?;
+                    for cert in certs.into_iter().filter(|c| !c.names.is_empty()) {
+                        log::debug!("discovered certificate for {:?}", cert.names);
+                        entries.push(TlsEntry {
+                            domains: vec![],
+                            sans: cert.names,
+                            key: Arc::new(RwLock::new(cert.key)),
+                        });
+                    }
+                }
+                if let Some(key) = ssl.certificate_key.as_ref() {
+                    #[cfg(feature = "keystore")]
+                    let passphrase = ssl.key_passphrase.as_ref().map(|p| p.load()).transpose()?;
+                    #[cfg(not(feature = "keystore"))]
+                    let passphrase: Option<String> = None;
+                    let key = certified_key(ssl.certificate.as_ref(), key, passphrase.as_deref())?;
+                    let key = Arc::new(RwLock::new(key));
+                    let domains = srv.server_name.clone();
+                    if srv.default_server && fallback.is_none() {
+                        fallback = Some(entries.len());
+                    }
+                    entries.push(TlsEntry {
+                        domains,
+                        sans: vec![],
+                        key,
+                    });
+                }
+                #[cfg(feature = "ocsp")]
+                if let Some(ocsp) = ssl.ocsp.as_ref() {
+                    stapled.extend(
+                        entries[first..]
+                            .iter()
+                            .map(|entry| (Arc::clone(&entry.key), ocsp.settings())),
+                    );
+                }
+            }
+        }
+        #[cfg(feature = "ocsp")]
+        super::ocsp::spawn(stapled)?;
+        #[cfg(feature = "acme")]
+        let on_demand = config
+            .iter()
+            .filter(|srv| !srv.disable)
+            .filter_map(|srv| srv.on_demand_tls.as_ref().map(|cfg| (srv, cfg)))
+            .map(|(srv, cfg)| cfg.settings(srv).and_then(super::acme::OnDemand::new))
+            .collect::<Result<Vec<_>>>()?;
+        Ok(Self {
+            entries,
+            fallback,
+            #[cfg(feature = "acme")]
+            on_demand,
+        })
+    }
+
+    /// Certificate issued on demand for the server name.
+    #[cfg(feature = "acme")]
+    #[inline]
+    fn issued(&self, name: &str) -> Option<Arc<CertifiedKey>> {
+        self.on_demand.iter().find_map(|o| o.resolve(name))
+    }
+
+    /// Certificates are only issued on demand with the `acme` feature.
+    #[cfg(not(feature = "acme"))]
+    #[inline]
+    fn issued(&self, _name: &str) -> Option<Arc<CertifiedKey>> {
+        None
+    }
+}
+
+impl ResolvesServerCert for TlsResolver {
+    fn resolve(&self, client_hello: ClientHello) -> Option<Arc<CertifiedKey>> {
+        let name = client_hello.server_name().unwrap_or_default();
+        #[cfg(feature = "acme")]
+        if client_hello
+            .alpn()
+            .is_some_and(|mut protos| protos.any(|p| p == super::acme::ACME_TLS_ALPN))
+        {
+            return self.on_demand.iter().find_map(|o| o.challenge(name));
+        }
+        self.entries
+            .iter()
+            .find(|entry| entry.matches(name))
+            .map(|entry| entry.key())
+            .or_else(|| self.issued(name))
+            .or_else(|| self.fallback.map(|i| self.entries[i].key()))
+    }
+}
@@ -0,0 +1,549 @@
+//! TLS Certificate Storage Backends
+//!
+//! Certificates and related data are stored as named blobs within a
+//! [`CertStore`] so the listener resolver and ACME issuance can share
+//! certificates between clustered bob instances. Certificate chains are
+//! stored as `<name>.pem` (or `<name>.crt`) with their private key as
+//! `<name>.key`, and every pair within a store may be discovered with
+//! the server names its certificate covers.
+
+use std::{
+    fmt,
+    io::{self, BufRead, BufReader, Read, Write},
+    net::{TcpStream, ToSocketAddrs},
+    path::PathBuf,
+    process::{Command, Stdio},
+    sync::Arc,
+    time::{Duration, Instant, SystemTime},
+};
+
+use actix_tls::connect::rustls_0_23::webpki_roots_cert_store;
+use anyhow::{Context, Result, anyhow};
+use rustls::{
+    ClientConfig, ClientConnection, StreamOwned,
+    crypto::aws_lc_rs::sign::any_supported_type,
+    pki_types::{CertificateDer, PrivateKeyDer, ServerName, pem::PemObject},
+    sign::CertifiedKey,
+};
+use x509_parser::extensions::GeneralName;
+
+/// Interval used to poll for command completion.
+const POLL_INTERVAL: Duration = Duration::from_millis(10);
+
+/// Certificate chain file extensions recognized during discovery.
+const CERT_EXTENSIONS: &[&str] = &["pem", "crt"];
+
+/// Named blob storage for certificates, keys and account data.
+///
+/// Calls block and are made during TLS handshakes, so implementations
+/// should be quick and are cached by their callers.
+pub trait CertStore: fmt::Debug + Send + Sync {
+    /// Retrieve the blob stored under the key.
+    fn get(&self, key: &str) -> io::Result<Option<Vec<u8>>>;
+    /// Store the blob under the key, replacing any existing value.
+    fn put(&self, key: &str, data: &[u8]) -> io::Result<()>;
+    /// List every key within the store.
+    fn list(&self) -> io::Result<Vec<String>>;
+}
+
+/// Certificate loaded from a store.
+#[derive(Debug)]
+pub struct StoredCert {
+    /// Server names covered by the certificate.
+    pub names: Vec<String>,
+    /// Certificate chain and signing key.
+    pub key: Arc<CertifiedKey>,
+    /// Expiry of the leaf certificate.
+    pub expires: SystemTime,
+}
+
+/// Check if the certificate subject alternative name covers the server name.
+///
+/// Wildcards only ever cover a single leftmost label.
+pub fn covers(san: &str, name: &str) -> bool {
+    let name = name.trim_end_matches('.');
+    match san.strip_prefix("*.") {
+        Some(suffix) => name
+            .split_once('.')
+            .is_some_and(|(label, rest)| !label.is_empty() && rest.eq_ignore_ascii_case(suffix)),
+        None => san.eq_ignore_ascii_case(name),
+    }
+}
+
+/// Build [`CertifiedKey`] from PEM encoded chain and private key.
+pub fn certified_key_pem(chain: &[u8], key: &[u8]) -> Result<Arc<CertifiedKey>> {
+    let certs = CertificateDer::pem_slice_iter(chain)
+        .collect::<Result<Vec<_>, _>>()
+        .context("invalid tls certificate")?;
+    let private_key = PrivateKeyDer::from_pem_slice(key).context("invalid private tls key")?;
+    Ok(Arc::new(CertifiedKey {
+        cert: certs,
+        key: any_supported_type(&private_key).context("failed to wrap private key")?,
+        ocsp: None,
+    }))
+}
+
+/// Parse the server names and expiry of the leaf certificate.
+pub fn leaf_info(chain: &[CertificateDer]) -> Result<(Vec<String>, SystemTime)> {
+    let leaf = chain.first().context("empty certificate chain")?;
+    let (_, cert) = x509_parser::parse_x509_certificate(leaf.as_ref())
+        .map_err(|err| anyhow!("invalid certificate: {err}"))?;
+    let mut names: Vec<String> = cert
+        .subject_alternative_name()
+        .ok()
+        .flatten()
+        .map(|san| {
+            san.value
+                .general_names
+                .iter()
+                .filter_map(|name| match name {
+                    GeneralName::DNSName(dns) => Some(dns.to_ascii_lowercase()),
+                    _ => None,
+                })
+                .collect()
+        })
+        .unwrap_or_default();
+    if names.is_empty() {
+        let common = cert.subject().iter_common_name().next();
+        names.extend(
+            common
+                .and_then(|cn| cn.as_str().ok())
+                .map(|cn| cn.to_ascii_lowercase()),
+        );
+    }
+    let seconds = cert.validity().not_after.timestamp();
+    let expires = SystemTime::UNIX_EPOCH + Duration::from_secs(seconds.max(0) as u64);
+    Ok((names, expires))
+}
+
+/// Load the certificate stored under the name.
+pub fn load(store: &dyn CertStore, name: &str) -> Result<Option<StoredCert>> {
+    let Some(key) = store.get(&format!("{name}.key"))? else {
+        return Ok(None);
+    };
+    let chain = CERT_EXTENSIONS
+        .iter()
+        .map(|ext| store.get(&format!("{name}.{ext}")))
+        .find_map(|chain| chain.transpose())
+        .transpose()?;
+    let Some(chain) = chain else {
+        return Ok(None);
+    };
+    let key = certified_key_pem(&chain, &key).with_context(|| format!("invalid {name:?}"))?;
+    let (names, expires) = leaf_info(&key.cert)?;
+    Ok(Some(StoredCert {
+        names,
+        key,
+        expires,
+    }))
+}
+
+/// Store the PEM encoded chain and private key under the name.
+pub fn save(store: &dyn CertStore, name: &str, chain: &[u8], key: &[u8]) -> io::Result<()> {
+    store.put(&format!("{name}.key"), key)?;
+    store.put(&format!("{name}.pem"), chain)
+}
+
+/// Load every certificate with a matching private key in the store.
+///
+/// Invalid pairs are logged and skipped.
+pub fn discover(store: &dyn CertStore) -> Result<Vec<StoredCert>> {
+    let keys = store.list().context("failed to list certificate store")?;
+    let mut certs = Vec::new();
+    for name in keys.iter().filter_map(|k| k.strip_suffix(".key")) {
+        match load(store, name) {
+            Ok(Some(cert)) => certs.push(cert),
+            Ok(None) => log::debug!("certificate store key {name:?} has no certificate"),
+            Err(err) => log::warn!("skipping stored certificate {name:?}: {err:?}"),
+        }
+    }
+    Ok(certs)
+}
+
+/// Check the key cannot escape its store namespace.
+#[inline]
+fn valid_key(key: &str) -> io::Result<()> {
+    match key.is_empty() || key.starts_with('.') || key.contains(['/', '\\', '\0']) {
+        true => Err(io::Error::new(
+            io::ErrorKind::InvalidInput,
+            format!("invalid store key {key:?}"),
+        )),
+        false => Ok(()),
+    }
+}
+
+/// Store keeping every blob as a file within a directory.
+#[derive(Debug)]
+pub struct DirectoryStore {
+    path: PathBuf,
+}
+
+impl DirectoryStore {
+    /// Open the directory store, creating the directory when missing.
+    pub fn new(path: PathBuf) -> io::Result<Self> {
+        std::fs::create_dir_all(&path)?;
+        Ok(Self { path })
+    }
+}
+
+impl CertStore for DirectoryStore {
+    fn get(&self, key: &str) -> io::Result<Option<Vec<u8>>> {
+        valid_key(key)?;
+        match std::fs::read(self.path.join(key)) {
+            Ok(data) => Ok(Some(data)),
+            Err(err) if err.kind() == io::ErrorKind::NotFound => Ok(None),
+            Err(err) => Err(err),
+        }
+    }
+
+    fn put(&self, key: &str, data: &[u8]) -> io::Result<()> {
+        use std::os::unix::fs::OpenOptionsExt;
+        valid_key(key)?;
+        // write then rename so readers never observe partial files
+        let path = self.path.join(key);
+        let tmp = self.path.join(format!(".{key}.tmp"));
+        let mut file = std::fs::OpenOptions::new()
+            .write(true)
+            .create(true)
+            .truncate(true)
+            .mode(0o600)
+            .open(&tmp)?;
+        file.write_all(data)?;
+        file.sync_all()?;
+        std::fs::rename(tmp, path)
+    }
+
+    fn list(&self) -> io::Result<Vec<String>> {
+        let mut keys = Vec::new();
+        for entry in std::fs::read_dir(&self.path)? {
+            let entry = entry?;
+            if !entry.file_type()?.is_file() {
+                continue;
+            }
+            if let Some(name) = entry.file_name().to_str()
+                && !name.starts_with('.')
+            {
+                keys.push(name.to_owned());
+            }
+        }
+        Ok(keys)
+    }
+}
+
+/// Redis reply values.
+enum Reply {
+    Nil,
+    Data(Vec<u8>),
+    Array(Vec<Reply>),
+}
+
+/// Redis connection stream, optionally wrapped in TLS.
+trait Transport: Read + Write + Send {}
+
+impl<T: Read + Write + Send> Transport for T {}
+
+/// Buffered redis connection.
+type Conn = BufReader<Box<dyn Transport>>;
+
+/// Keys requested per `SCAN` iteration.
+const SCAN_COUNT: &[u8] = b"100";
+
+/// Store keeping every blob as a redis string.
+///
+/// A connection is opened per operation since operations are rare and
+/// their results are cached by callers.
+pub struct RedisStore {
+    address: String,
+    tls: Option<(ServerName<'static>, Arc<ClientConfig>)>,
+    password: Option<String>,
+    database: Option<u32>,
+    prefix: String,
+    timeout: Duration,
+}
+
+impl std::fmt::Debug for RedisStore {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("RedisStore")
+            .field("address", &self.address)
+            .field("tls", &self.tls.is_some())
+            .field("password", &self.password.as_ref().map(|_| "<redacted>"))
+            .field("database", &self.database)
+            .field("prefix", &self.prefix)
+            .field("timeout", &self.timeout)
+            .finish()
+    }
+}
+
+impl RedisStore {
+    /// Create redis store from a `redis[s]://[:password@]host[:port][/db]` url.
+    ///
+    /// `rediss://` urls connect using TLS verified against the webpki roots.
+    pub fn new(url: &str, prefix: String, timeout: Duration) -> Result<Self> {
+        let (tls, rest) = match url.split_once("://") {
+            Some(("redis", rest)) => (false, rest),
+            Some(("rediss", rest)) => (true, rest),
+            _ => anyhow::bail!("redis url must start with redis:// or rediss://"),
+        };
+        let (auth, rest) = match rest.rsplit_once('@') {
+            Some((auth, rest)) => (Some(auth), rest),
+            None => (None, rest),
+        };
+        let (host, database) = match rest.split_once('/') {
+            Some((host, "")) => (host, None),
+            Some((host, db)) => (host, Some(db.parse().context("invalid redis database")?)),
+            None => (rest, None),
+        };
+        let (address, hostname) = match host.rsplit_once(':') {
+            Some((name, port)) if port.parse::<u16>().is_ok() => (host.to_owned(), name),
+            _ => (format!("{host}:6379"), host),
+        };
+        let tls = match tls {
+            true => {
+                let hostname = hostname.trim_start_matches('[').trim_end_matches(']');
+                let name = ServerName::try_from(hostname.to_owned())
+                    .context("invalid redis tls server name")?;
+                let config = ClientConfig::builder()
+                    .with_root_certificates(webpki_roots_cert_store())
+                    .with_no_client_auth();
+                Some((name, Arc::new(config)))
+            }
+            false => None,
+        };
+        let password = auth
+            .map(|auth| auth.rsplit_once(':').map(|(_, p)| p).unwrap_or(auth))
+            .filter(|p| !p.is_empty())
+            .map(|p| p.to_owned());
+        Ok(Self {
+            address,
+            tls,
+            password,
+            database,
+            prefix,
+            timeout,
+        })
+    }
+
+    /// Open an authenticated connection to the configured database.
+    fn connect(&self) -> io::Result<Conn> {
+        let mut last = None;
+        let mut stream = None;
+        for addr in self.address.to_socket_addrs()? {
+            match TcpStream::connect_timeout(&addr, self.timeout) {
+                Ok(conn) => {
+                    stream = Some(conn);
+                    break;
+                }
+                Err(err) => last = Some(err),
+            }
+        }
+        let stream = stream.ok_or_else(|| {
+            last.unwrap_or_else(|| io::Error::other("redis address did not resolve"))
+        })?;
+        stream.set_read_timeout(Some(self.timeout))?;
+        stream.set_write_timeout(Some(self.timeout))?;
+        let stream: Box<dyn Transport> = match self.tls.as_ref() {
+            Some((name, config)) => {
+                let session = ClientConnection::new(Arc::clone(config), name.clone())
+                    .map_err(io::Error::other)?;
+                Box::new(StreamOwned::new(session, stream))
+            }
+            None => Box::new(stream),
+        };
+        let mut conn = BufReader::new(stream);
+        if let Some(password) = self.password.as_ref() {
+            command(&mut conn, &[b"AUTH", password.as_bytes()])?;
+        }
+        if let Some(db) = self.database {
+            command(&mut conn, &[b"SELECT", db.to_string().as_bytes()])?;
+        }
+        Ok(conn)
+    }
+}
+
+/// Send a command and read its reply.
+fn command(conn: &mut Conn, args: &[&[u8]]) -> io::Result<Reply> {
+    let mut message = format!("*{}\r\n", args.len()).into_bytes();
+    for arg in args {
+        message.extend(format!("${}\r\n", arg.len()).as_bytes());
+        message.extend(*arg);
+        message.extend(b"\r\n");
+    }
+    conn.get_mut().write_all(&message)?;
+    reply(conn)
+}
+
+/// Read a single RESP reply.
+fn reply(conn: &mut Conn) -> io::Result<Reply> {
+    let mut line = String::new();
+    conn.read_line(&mut line)?;
+    let line = line.trim_end();
+    let (kind, value) = line.split_at_checked(1).unwrap_or(("", ""));
+    let invalid = || io::Error::new(io::ErrorKind::InvalidData, "invalid redis reply");
+    match kind {
+        "+" | ":" => Ok(Reply::Data(value.as_bytes().to_vec())),
+        "-" => Err(io::Error::other(format!("redis error: {value}"))),
+        "$" => {
+            let Ok(len) = value.parse::<usize>() else {
+                return Ok(Reply::Nil);
+            };
+            let mut data = vec![0; len + 2];
+            conn.read_exact(&mut data)?;
+            data.truncate(len);
+            Ok(Reply::Data(data))
+        }
+        "*" => {
+            let Ok(count) = value.parse::<usize>() else {
+                return Ok(Reply::Nil);
+            };
+            (0..count)
+                .map(|_| reply(conn))
+                .collect::<io::Result<_>>()
+                .map(Reply::Array)
+        }
+        _ => Err(invalid()),
+    }
+}
+
+impl CertStore for RedisStore {
+    fn get(&self, key: &str) -> io::Result<Option<Vec<u8>>> {
+        let key = format!("{}{key}", self.prefix);
+        match command(&mut self.connect()?, &[b"GET", key.as_bytes()])? {
+            Reply::Data(data) => Ok(Some(data)),
+            _ => Ok(None),
+        }
+    }
+
+    fn put(&self, key: &str, data: &[u8]) -> io::Result<()> {
+        let key = format!("{}{key}", self.prefix);
+        command(&mut self.connect()?, &[b"SET", key.as_bytes(), data]).map(|_| ())
+    }
+
+    fn list(&self) -> io::Result<Vec<String>> {
+        let invalid = || io::Error::new(io::ErrorKind::InvalidData, "invalid redis scan reply");
+        let pattern = format!("{}*", self.prefix);
+        let mut conn = self.connect()?;
+        let mut cursor = b"0".to_vec();
+        let mut keys = Vec::new();
+        // iterate with SCAN so large databases are never blocked
+        loop {
+            let args: [&[u8]; 6] = [
+                b"SCAN",
+                &cursor,
+                b"MATCH",
+                pattern.as_bytes(),
+                b"COUNT",
+                SCAN_COUNT,
+            ];
+            let Reply::Array(reply) = command(&mut conn, &args)? else {
+                return Err(invalid());
+            };
+            let mut reply = reply.into_iter();
+            let (Some(Reply::Data(next)), Some(Reply::Array(batch))) = (reply.next(), reply.next())
+            else {
+                return Err(invalid());
+            };
+            keys.extend(
+                batch
+                    .into_iter()
+                    .filter_map(|key| match key {
+                        Reply::Data(key) => String::from_utf8(key).ok(),
+                        _ => None,
+                    })
+                    .filter_map(|key| key.strip_prefix(&self.prefix).map(|k| k.to_owned())),
+            );
+            if next == b"0" {
+                break;
+            }
+            cursor = next;
+        }
+        keys.sort();
+        keys.dedup();
+        Ok(keys)
+    }
+}
+
+/// Store delegating every operation to an external program.
+///
+/// The program is run as `<command> [args] get|put|list [key]`. `get`
+/// writes the blob to stdout (nothing when missing), `put` reads the
+/// blob from stdin and `list` writes one key per line. Enables storing
+/// certificates in any backend such as S3 through a small script.
+#[derive(Debug)]
+pub struct CommandStore {
+    command: PathBuf,
+    args: Vec<String>,
+    timeout: Duration,
+}
+
+impl CommandStore {
+    /// Create command store invoking the program with arguments.
+    pub fn new(command: PathBuf, args: Vec<String>, timeout: Duration) -> Self {
+        Self {
+            command,
+            args,
+            timeout,
+        }
+    }
+
+    /// Run the program for the operation returning its output.
+    fn run(&self, op: &[&str], stdin: Option<&[u8]>) -> io::Result<Vec<u8>> {
+        let mut child = Command::new(&self.command)
+            .args(&self.args)
+            .args(op)
+            .stdin(Stdio::piped())
+            .stdout(Stdio::piped())
+            .stderr(Stdio::inherit())
+            .spawn()?;
+        let mut input = child.stdin.take().expect("missing child stdin");
+        let data = stdin.map(|d| d.to_vec()).unwrap_or_default();
+        let writer = std::thread::spawn(move || input.write_all(&data));
+        let mut output = child.stdout.take().expect("missing child stdout");
+        let reader = std::thread::spawn(move || {
+            let mut buf = Vec::new();
+            output.read_to_end(&mut buf).map(|_| buf)
+        });
+
+        let deadline = Instant::now() + self.timeout;
+        loop {
+            if let Some(status) = child.try_wait()? {
+                let _ = writer.join();
+                let stdout = reader
+                    .join()
+                    .map_err(|_| io::Error::other("stdout reader panicked"))??;
+                return match status.success() {
+                    true => Ok(stdout),
+                    false => Err(io::Error::other(format!("store exited with {status}"))),
+                };
+            }
+            if Instant::now() >= deadline {
+                let _ = child.kill();
+                let _ = child.wait();
+                return Err(io::Error::new(io::ErrorKind::TimedOut, "store timed out"));
+            }
+            std::thread::sleep(POLL_INTERVAL);
+        }
+    }
+}
+
+impl CertStore for CommandStore {
+    fn get(&self, key: &str) -> io::Result<Option<Vec<u8>>> {
+        valid_key(key)?;
+        let data = self.run(&["get", key], None)?;
+        Ok((!data.is_empty()).then_some(data))
+    }
+
+    fn put(&self, key: &str, data: &[u8]) -> io::Result<()> {
+        valid_key(key)?;
+        self.run(&["put", key], Some(data)).map(|_| ())
+    }
+
+    fn list(&self) -> io::Result<Vec<String>> {
+        let output = self.run(&["list"], None)?;
+        Ok(String::from_utf8_lossy(&output)
+            .lines()
+            .map(|l| l.trim())
+            .filter(|l| !l.is_empty())
+            .map(|l| l.to_owned())
+            .collect())
+    }
+}
@@ -0,0 +1,282 @@
+//! Public request matcher abstractions for bob directives.
+//!
+//! Downstream crates implement [`Matcher`] and register a
+//! [`MatcherFactory`] using [`register`] before the configuration
+//! is loaded. Registered matchers may then be referenced by name
+//! within a directive's `guards` configuration:
+//!
+//! ```yaml
+//! directives:
+//!   - location: /admin
+//!     guards:
+//!       - matcher: header
+//!         name: X-Admin
+//!         value: "1"
+//! ```
+
+use std::{
+    collections::HashMap,
+    fmt::Debug,
+    sync::{Arc, OnceLock, RwLock},
+};
+
+#[cfg(feature = "schema")]
+use schemars::JsonSchema;
+
+use actix_web::guard::{Guard, GuardContext};
+use serde::{Deserialize, Serialize, de::Error};
+
+/// Configuration key used to select the matcher implementation.
+pub const MATCHER_KEY: &str = "matcher";
+
+/// Request matcher used to restrict when a directive applies.
+pub trait Matcher: Debug + Send + Sync {
+    /// Check if the request matches.
+    fn matches(&self, ctx: &GuardContext<'_>) -> bool;
+}
+
+/// Factory used to construct [`Matcher`] instances from configuration.
+pub trait MatcherFactory: Send + Sync {
+    /// Build matcher from the raw configuration value.
+    ///
+    /// The value includes the `matcher` key used to select the factory.
+    fn build(&self, config: serde_yaml::Value) -> Result<Arc<dyn Matcher>, String>;
+}
+
+impl<F> MatcherFactory for F
+where
+    F: Fn(serde_yaml::Value) -> Result<Arc<dyn Matcher>, String> + Send + Sync,
+{
+    #[inline]
+    fn build(&self, config: serde_yaml::Value) -> Result<Arc<dyn Matcher>, String> {
+        (self)(config)
+    }
+}
+
+type Registry = RwLock<HashMap<String, Arc<dyn MatcherFactory>>>;
+
+/// Global matcher registry including the builtin matchers.
+fn registry() -> &'static Registry {
+    static REGISTRY: OnceLock<Registry> = OnceLock::new();
+    REGISTRY.get_or_init(|| {
+        let mut map: HashMap<String, Arc<dyn MatcherFactory>> = HashMap::new();
+        map.insert("flag".to_owned(), Arc::new(from_config::<FlagMatcher>));
+        map.insert("header".to_owned(), Arc::new(from_config::<HeaderMatcher>));
+        map.insert("method".to_owned(), Arc::new(from_config::<MethodMatcher>));
+        map.insert("query".to_owned(), Arc::new(from_config::<QueryMatcher>));
+        RwLock::new(map)
+    })
+}
+
+/// Register a new matcher factory under the specified name.
+///
+/// Registering an existing name replaces the previous factory.
+pub fn register<F: MatcherFactory + 'static>(name: &str, factory: F) {
+    registry()
+        .write()
+        .expect("matcher registry poisoned")
+        .insert(name.to_owned(), Arc::new(factory));
+}
+
+/// List all registered matcher names.
+pub fn registered() -> Vec<String> {
+    let mut names: Vec<String> = registry()
+        .read()
+        .expect("matcher registry poisoned")
+        .keys()
+        .cloned()
+        .collect();
+    names.sort();
+    names
+}
+
+/// Build matcher from configuration using the registered factories.
+pub fn build(config: serde_yaml::Value) -> Result<Arc<dyn Matcher>, String> {
+    let name = config
+        .get(MATCHER_KEY)
+        .and_then(|v| v.as_str())
+        .ok_or_else(|| format!("missing `{MATCHER_KEY}` field"))?
+        .to_owned();
+    let factory = registry()
+        .read()
+        .expect("matcher registry poisoned")
+        .get(&name)
+        .cloned()
+        .ok_or_else(|| format!("unknown matcher {name:?}"))?;
+    factory.build(config)
+}
+
+/// Build matcher by deserializing config directly into its type.
+pub fn from_config<M>(mut config: serde_yaml::Value) -> Result<Arc<dyn Matcher>, String>
+where
+    M: Matcher + for<'de> Deserialize<'de> + 'static,
+{
+    if let Some(map) = config.as_mapping_mut() {
+        map.remove(MATCHER_KEY);
+    }
+    let matcher: M = serde_yaml::from_value(config).map_err(|e| e.to_string())?;
+    Ok(Arc::new(matcher))
+}
+
+/// Configured matcher usable as an [`actix_web::guard::Guard`].
+#[derive(Clone, Debug)]
+pub struct MatcherGuard(pub Arc<dyn Matcher>);
+
+impl Guard for MatcherGuard {
+    #[inline]
+    fn check(&self, ctx: &GuardContext<'_>) -> bool {
+        self.0.matches(ctx)
+    }
+}
+
+impl<'de> Deserialize<'de> for MatcherGuard {
+    fn deserialize<D>(deserializer: D) -> Result<Self, D::Error>
+    where
+        D: serde::Deserializer<'de>,
+    {
+        let value = serde_yaml::Value::deserialize(deserializer)?;
+        build(value).map(Self).map_err(D::Error::custom)
+    }
+}
+
+impl Serialize for MatcherGuard {
+    /// Serialize as the debug representation of the built matcher.
+    fn serialize<S>(&self, serializer: S) -> Result<S::Ok, S::Error>
+    where
+        S: serde::Serializer,
+    {
+        serializer.collect_str(&format_args!("{:?}", self.0))
+    }
+}
+
+#[cfg(feature = "schema")]
+impl JsonSchema for MatcherGuard {
+    fn schema_name() -> std::borrow::Cow<'static, str> {
+        "MatcherGuard".into()
+    }
+    fn schema_id() -> std::borrow::Cow<'static, str> {
+        concat!(module_path!(), "::MatcherGuard").into()
+    }
+    fn json_schema(_gen: &mut schemars::SchemaGenerator) -> schemars::Schema {
+        schemars::json_schema!({
+            "type": "object",
+            "required": [MATCHER_KEY],
+            "properties": { MATCHER_KEY: { "type": "string" } }
+        })
+    }
+}
+
+/// Request-scoped feature flags attached to request extensions.
+///
+/// Inserted by the server after verifying a signed debug token.
+#[derive(Clone, Debug, Default)]
+pub struct RequestFlags(pub Vec<String>);
+
+impl RequestFlags {
+    /// Check if the flag is enabled for the request.
+    #[inline]
+    pub fn contains(&self, flag: &str) -> bool {
+        self.0.iter().any(|f| f == flag)
+    }
+}
+
+/// Builtin matcher checking an enabled [`RequestFlags`] flag.
+#[derive(Debug, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct FlagMatcher {
+    name: String,
+}
+
+impl Matcher for FlagMatcher {
+    fn matches(&self, ctx: &GuardContext<'_>) -> bool {
+        ctx.req_data()
+            .get::<RequestFlags>()
+            .is_some_and(|flags| flags.contains(&self.name))
+    }
+}
+
+/// Builtin matcher comparing a request header.
+///
+/// Matches header presence when no value is specified.
+#[derive(Debug, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct HeaderMatcher {
+    name: String,
+    value: Option<String>,
+}
+
+impl Matcher for HeaderMatcher {
+    fn matches(&self, ctx: &GuardContext<'_>) -> bool {
+        let mut values = ctx.head().headers.get_all(self.name.as_str());
+        match self.value.as_ref() {
+            Some(expect) => values.any(|v| v.to_str().is_ok_and(|v| v == expect)),
+            None => values.next().is_some(),
+        }
+    }
+}
+
+/// Builtin matcher comparing the request method.
+#[derive(Debug, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct MethodMatcher {
+    methods: Vec<String>,
+}
+
+impl Matcher for MethodMatcher {
+    fn matches(&self, ctx: &GuardContext<'_>) -> bool {
+        let method = ctx.head().method.as_str();
+        self.methods.iter().any(|m| m.eq_ignore_ascii_case(method))
+    }
+}
+
+/// Builtin matcher comparing a query parameter.
+///
+/// Names and values are percent-decoded before comparison.
+/// Matches parameter presence when no value is specified.
+#[derive(Debug, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct QueryMatcher {
+    name: String,
+    value: Option<String>,
+}
+
+impl Matcher for QueryMatcher {
+    fn matches(&self, ctx: &GuardContext<'_>) -> bool {
+        let query = ctx.head().uri.query().unwrap_or_default();
+        form_urlencoded::parse(query.as_bytes())
+            .filter(|(k, _)| *k == self.name)
+            .any(|(_, v)| self.value.as_deref().is_none_or(|expect| expect == v))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use actix_web::test::TestRequest;
+
+    use super::*;
+
+    fn query(name: &str, value: Option<&str>, uri: &str) -> bool {
+        let matcher = QueryMatcher {
+            name: name.to_owned(),
+            value: value.map(|v| v.to_owned()),
+        };
+        let req = TestRequest::with_uri(uri).to_srv_request();
+        matcher.matches(&req.guard_ctx())
+    }
+
+    #[test]
+    fn query_presence() {
+        assert!(query("debug", None, "/?debug"));
+        assert!(query("debug", None, "/?a=1&debug=0"));
+        assert!(!query("debug", None, "/?a=1"));
+        assert!(!query("debug", None, "/"));
+    }
+
+    #[test]
+    fn query_decoded() {
+        assert!(query("mode", Some("a b"), "/?mode=a+b"));
+        assert!(query("mode", Some("a b"), "/?mode=a%20b"));
+        assert!(query("mode", Some("x&y"), "/?m%6Fde=x%26y"));
+        assert!(!query("mode", Some("x"), "/?mode=x%26y"));
+    }
+}
@@ -0,0 +1,633 @@
+//! Administrative Control API
+//!
+//! Optional local listener used to inspect and control the running
+//! process: reload configuration, stop the server, view the resolved
+//! configuration, check upstream health and directive budgets, toggle
+//! drain mode, drain upstream pool members, drain and exit, manage ip
+//! bans and dump metrics. Every endpoint except the health checks requires
+//! the configured bearer token.
+
+use std::{
+    fmt::Write,
+    sync::{Mutex, OnceLock},
+    time::Instant,
+};
+
+use actix_web::{
+    App, Error, HttpResponse, HttpServer,
+    body::MessageBody,
+    dev::{ServerHandle, ServiceRequest, ServiceResponse},
+    middleware::{Next, from_fn},
+    web,
+};
+use anyhow::{Context, Result};
+use bob_core::config::AdminCfg;
+#[cfg(feature = "rproxy")]
+use bob_core::config::UpstreamAddr;
+use bob_core::{metrics, services::drain};
+use tokio::sync::Notify;
+
+use crate::cli::Config;
+
+/// Currently running configuration and its hash.
+struct Current {
+    config: Config,
+    hash: String,
+    loaded: Instant,
+}
+
+fn current() -> &'static Mutex<Option<Current>> {
+    static CURRENT: OnceLock<Mutex<Option<Current>>> = OnceLock::new();
+    CURRENT.get_or_init(Default::default)
+}
+
+fn reload_notify() -> &'static Notify {
+    static RELOAD: OnceLock<Notify> = OnceLock::new();
+    RELOAD.get_or_init(Notify::new)
+}
+
+fn stop_notify() -> &'static Notify {
+    static STOP: OnceLock<Notify> = OnceLock::new();
+    STOP.get_or_init(Notify::new)
+}
+
+fn respawn_notify() -> &'static Notify {
+    static RESPAWN: OnceLock<Notify> = OnceLock::new();
+    RESPAWN.get_or_init(Notify::new)
+}
+
+fn drain_notify() -> &'static Notify {
+    static DRAIN: OnceLock<Notify> = OnceLock::new();
+    DRAIN.get_or_init(Notify::new)
+}
+
+fn started() -> Instant {
+    static STARTED: OnceLock<Instant> = OnceLock::new();
+    *STARTED.get_or_init(Instant::now)
+}
+
+/// Publish the running configuration to the admin api.
+pub fn publish(config: &Config, hash: &str) {
+    started();
+    let mut current = current().lock().expect("admin state poisoned");
+    *current = Some(Current {
+        config: config.clone(),
+        hash: hash.to_owned(),
+        loaded: Instant::now(),
+    });
+}
+
+/// Request a configuration reload from the main server loop.
+#[inline]
+pub fn request_reload() {
+    reload_notify().notify_one();
+}
+
+/// Wait until a configuration reload is requested.
+#[inline]
+pub async fn reload_requested() {
+    reload_notify().notified().await
+}
+
+/// Request the main server loop rebuild the server to apply listener changes.
+#[inline]
+pub fn request_respawn() {
+    respawn_notify().notify_one();
+}
+
+/// Wait until the server must be rebuilt.
+#[inline]
+pub async fn respawn_requested() {
+    respawn_notify().notified().await
+}
+
+/// Request a graceful shutdown from the main server loop.
+#[inline]
+pub fn request_stop() {
+    stop_notify().notify_one();
+}
+
+/// Wait until a graceful shutdown is requested.
+#[inline]
+pub async fn stop_requested() {
+    stop_notify().notified().await
+}
+
+/// Request connections be drained before exiting.
+#[inline]
+pub fn request_drain() {
+    drain_notify().notify_one();
+}
+
+/// Wait until a drain and exit is requested.
+#[inline]
+pub async fn drain_requested() {
+    drain_notify().notified().await
+}
+
+/// Admin settings of the first enabled server configuring them.
+fn admin_cfg(config: &Config) -> Option<&AdminCfg> {
+    config
+        .iter()
+        .filter(|cfg| !cfg.disable)
+        .find_map(|cfg| cfg.admin.as_ref())
+}
+
+/// Reject requests without the bearer token of the running configuration.
+async fn authorize(
+    req: ServiceRequest,
+    next: Next<impl MessageBody>,
+) -> Result<ServiceResponse<impl MessageBody>, Error> {
+    let open = matches!(req.path(), "/-/healthy" | "/-/ready");
+    let allowed = open || {
+        let current = current().lock().expect("admin state poisoned");
+        current
+            .as_ref()
+            .and_then(|c| admin_cfg(&c.config))
+            .is_some_and(|admin| admin.authorized(req.request()))
+    };
+    if !allowed {
+        let res = HttpResponse::Unauthorized()
+            .insert_header(("WWW-Authenticate", "Bearer"))
+            .finish();
+        return Ok(req.into_response(res).map_into_right_body());
+    }
+    Ok(next.call(req).await?.map_into_left_body())
+}
+
+/// Running admin api listener.
+pub struct Listener {
+    admin: AdminCfg,
+    handle: ServerHandle,
+}
+
+impl Listener {
+    /// Check if the listener is bound where the settings ask for.
+    fn bound(&self, admin: &AdminCfg) -> bool {
+        self.admin.listen() == admin.listen() && self.admin.socket == admin.socket
+    }
+}
+
+/// Rebind the admin api when the reloaded configuration moves it.
+///
+/// The new listener is bound before the previous one is stopped, and the
+/// previous listener keeps serving if the new one cannot be bound.
+pub async fn reconfigure(running: Option<Listener>, config: &Config) -> Option<Listener> {
+    let wanted = admin_cfg(config);
+    if let (Some(running), Some(admin)) = (running.as_ref(), wanted)
+        && running.bound(admin)
+    {
+        return Some(Listener {
+            admin: admin.clone(),
+            handle: running.handle.clone(),
+        });
+    }
+    let next = match spawn(config) {
+        Ok(next) => next,
+        Err(err) => {
+            log::error!("failed to rebind admin listener: {err:?}");
+            return running;
+        }
+    };
+    if let Some(running) = running {
+        running.handle.stop(true).await;
+    }
+    next
+}
+
+/// Spawn admin api server using the first enabled admin configuration.
+pub fn spawn(config: &Config) -> Result<Option<Listener>> {
+    let Some(admin) = admin_cfg(config) else {
+        return Ok(None);
+    };
+
+    let server = HttpServer::new(|| {
+        let app = App::new()
+            .wrap(from_fn(authorize))
+            .route("/status", web::get().to(status))
+            .route("/config", web::get().to(resolved_config))
+            .route("/reload", web::post().to(reload))
+            .route("/stop", web::post().to(stop))
+            .route("/-/quitquitquit", web::post().to(quit))
+            .route("/-/healthy", web::get().to(healthy))
+            .route("/-/ready", web::get().to(ready))
+            .route("/upstreams", web::get().to(upstreams))
+            .route("/budgets", web::get().to(budgets))
+            .route("/drain", web::get().to(drain_status))
+            .route("/drain", web::put().to(drain_enable))
+            .route("/drain", web::delete().to(drain_disable))
+            .route("/listeners", web::get().to(listeners))
+            .route("/listeners/{name}/pause", web::post().to(listener_pause))
+            .route("/listeners/{name}/resume", web::post().to(listener_resume))
+            .route("/stats", web::get().to(stats));
+        #[cfg(feature = "rproxy")]
+        let app = app
+            .route("/pools", web::get().to(pools))
+            .route("/pools/{name}/{id}/drain", web::put().to(pool_drain))
+            .route("/pools/{name}/{id}/drain", web::delete().to(pool_restore));
+        #[cfg(feature = "ipban")]
+        let app = app
+            .route("/bans", web::get().to(bans))
+            .route("/bans", web::delete().to(bans_clear))
+            .route("/bans/{ip}", web::delete().to(bans_remove));
+        app
+    })
+    .workers(1)
+    .disable_signals();
+
+    let server = match admin.socket.as_ref() {
+        #[cfg(unix)]
+        Some(path) => {
+            use std::os::unix::fs::FileTypeExt;
+            let stale = std::fs::symlink_metadata(path).is_ok_and(|m| m.file_type().is_socket());
+            if stale {
+                std::fs::remove_file(path).context("failed to remove stale admin socket")?;
+            }
+            log::info!("spawning admin listener {path:?}");
+            server.bind_uds(path)
+        }
+        #[cfg(not(unix))]
+        Some(_) => anyhow::bail!("admin unix sockets are unsupported on this platform"),
+        None => {
+            log::info!("spawning admin listener {:?}", admin.listen());
+            server.bind(admin.listen())
+        }
+    }
+    .context("failed to bind admin listener")?;
+    let server = server.run();
+    let handle = server.handle();
+    actix_web::rt::spawn(server);
+    Ok(Some(Listener {
+        admin: admin.clone(),
+        handle,
+    }))
+}
+
+/// Report process status summary.
+async fn status() -> HttpResponse {
+    let current = current().lock().expect("admin state poisoned");
+    let mut body = String::new();
+    let state = if drain::draining() { "draining" } else { "ok" };
+    let _ = writeln!(body, "status: {state}");
+    let _ = writeln!(body, "pid: {}", std::process::id());
+    let _ = writeln!(body, "uptime: {}s", started().elapsed().as_secs());
+    if let Some(current) = current.as_ref() {
+        let _ = writeln!(body, "config_hash: {}", current.hash);
+        let _ = writeln!(body, "config_age: {}s", current.loaded.elapsed().as_secs());
+        let _ = writeln!(body, "servers: {}", current.config.len());
+    }
+    HttpResponse::Ok().content_type("text/plain").body(body)
+}
+
+/// Report the resolved running configuration as yaml.
+async fn resolved_config() -> HttpResponse {
+    let current = current().lock().expect("admin state poisoned");
+    let Some(current) = current.as_ref() else {
+        return HttpResponse::ServiceUnavailable().finish();
+    };
+    match serde_yaml::to_string(&current.config) {
+        Ok(body) => HttpResponse::Ok()
+            .content_type("application/yaml")
+            .body(body),
+        Err(err) => {
+            log::error!("failed to serialize running config: {err}");
+            HttpResponse::InternalServerError().finish()
+        }
+    }
+}
+
+/// Trigger configuration reload.
+async fn reload() -> HttpResponse {
+    log::info!("configuration reload requested via admin api");
+    request_reload();
+    HttpResponse::Accepted()
+        .content_type("text/plain")
+        .body("reload requested\n")
+}
+
+/// Trigger graceful shutdown.
+async fn stop() -> HttpResponse {
+    log::info!("shutdown requested via admin api");
+    request_stop();
+    HttpResponse::Accepted()
+        .content_type("text/plain")
+        .body("stop requested\n")
+}
+
+/// Drain connections for the drain period then exit.
+async fn quit() -> HttpResponse {
+    log::info!("drain and exit requested via admin api");
+    request_drain();
+    HttpResponse::Accepted()
+        .content_type("text/plain")
+        .body("draining\n")
+}
+
+/// Report process liveness.
+async fn healthy() -> HttpResponse {
+    HttpResponse::Ok().content_type("text/plain").body("ok\n")
+}
+
+/// Report process readiness failing once draining begins.
+async fn ready() -> HttpResponse {
+    bob_core::services::drain::readiness()
+}
+
+/// Check reachability of all configured reverse-proxy upstreams.
+async fn upstreams() -> HttpResponse {
+    #[cfg(feature = "rproxy")]
+    let body = {
+        let upstreams: Vec<(usize, String, UpstreamAddr)> = {
+            let current = current().lock().expect("admin state poisoned");
+            current
+                .iter()
+                .flat_map(|c| c.config.iter().enumerate())
+                .filter(|(_, cfg)| !cfg.disable)
+                .flat_map(|(n, cfg)| {
+                    cfg.upstreams()
+                        .map(move |(location, addr)| (n, location, addr))
+                })
+                .collect()
+        };
+        let mut body = String::new();
+        for (n, location, addr) in upstreams {
+            let health = match check_upstream(&addr).await {
+                Ok(elapsed) => format!("up {}ms", elapsed.as_millis()),
+                Err(err) => format!("down ({err})"),
+            };
+            let _ = writeln!(body, "server[{n}] {location} {addr} {health}");
+        }
+        body
+    };
+
+    #[cfg(not(feature = "rproxy"))]
+    let body = String::new();
+
+    HttpResponse::Ok().content_type("text/plain").body(body)
+}
+
+/// Attempt a connection to the upstream and report connect time.
+#[cfg(feature = "rproxy")]
+async fn check_upstream(addr: &UpstreamAddr) -> Result<std::time::Duration> {
+    use actix_web::rt::{
+        net::{TcpStream, UnixStream},
+        time::timeout,
+    };
+
+    let uri = match addr {
+        UpstreamAddr::Http(uri) => &uri.0,
+        UpstreamAddr::Unix(path) => {
+            let start = Instant::now();
+            timeout(std::time::Duration::from_secs(2), UnixStream::connect(path))
+                .await
+                .context("connect timeout")??;
+            return Ok(start.elapsed());
+        }
+    };
+    let host = uri.host().context("missing host")?;
+    let port = uri.port_u16().unwrap_or(match uri.scheme_str() {
+        Some("https") => 443,
+        _ => 80,
+    });
+    let start = Instant::now();
+    timeout(
+        std::time::Duration::from_secs(2),
+        TcpStream::connect((host.trim_matches(['[', ']']), port)),
+    )
+    .await
+    .context("connect timeout")??;
+    Ok(start.elapsed())
+}
+
+/// Report budget state of all directives with a configured budget.
+async fn budgets() -> HttpResponse {
+    let current = current().lock().expect("admin state poisoned");
+    let mut body = String::new();
+    let budgets = current
+        .iter()
+        .flat_map(|c| c.config.iter().enumerate())
+        .filter(|(_, cfg)| !cfg.disable)
+        .flat_map(|(n, cfg)| cfg.directives.iter().map(move |d| (n, d)))
+        .filter_map(|(n, d)| d.budget.as_ref().map(|b| (n, d, b)));
+    for (n, directive, budget) in budgets {
+        let location = directive.location.as_deref().unwrap_or("/");
+        let summary = budget.state.summary();
+        let state = if summary.degraded { "degraded" } else { "ok" };
+        let _ = writeln!(
+            body,
+            "server[{n}] {location} {state} requests={} slow={} errors={}",
+            summary.total, summary.slow, summary.errors
+        );
+    }
+    HttpResponse::Ok().content_type("text/plain").body(body)
+}
+
+#[inline]
+fn drain_response() -> HttpResponse {
+    let state = if drain::draining() { "on" } else { "off" };
+    HttpResponse::Ok()
+        .content_type("text/plain")
+        .body(format!("drain: {state}\n"))
+}
+
+/// Report drain mode status.
+async fn drain_status() -> HttpResponse {
+    drain_response()
+}
+
+/// Enable drain mode.
+async fn drain_enable() -> HttpResponse {
+    log::info!("drain mode enabled via admin api");
+    drain::set_draining(true);
+    drain_response()
+}
+
+/// Disable drain mode.
+async fn drain_disable() -> HttpResponse {
+    log::info!("drain mode disabled via admin api");
+    drain::set_draining(false);
+    drain_response()
+}
+
+/// List configured listeners and their state.
+async fn listeners() -> HttpResponse {
+    let current = current().lock().expect("admin state poisoned");
+    let mut body = String::new();
+    let listeners = current
+        .iter()
+        .flat_map(|c| c.config.iter().enumerate())
+        .filter(|(_, cfg)| !cfg.disable)
+        .flat_map(|(n, cfg)| cfg.listen.iter().map(move |l| (n, l)));
+    for (n, listen) in listeners {
+        let address = listen.bind_address();
+        let label = listen
+            .label
+            .as_ref()
+            .map(|label| format!(" label={label}"))
+            .unwrap_or_default();
+        let tls = if listen.ssl.is_some() { " tls" } else { "" };
+        let state = if drain::is_paused(listen) {
+            "paused"
+        } else {
+            "active"
+        };
+        let _ = writeln!(body, "server[{n}] {address}{label}{tls} {state}");
+    }
+    HttpResponse::Ok().content_type("text/plain").body(body)
+}
+
+/// Check if any configured listener has the label or `host:port` address.
+fn listener_exists(name: &str) -> bool {
+    let current = current().lock().expect("admin state poisoned");
+    current
+        .iter()
+        .flat_map(|c| c.config.iter())
+        .filter(|cfg| !cfg.disable)
+        .flat_map(|cfg| cfg.listen.iter())
+        .any(|listen| listen.label.as_deref() == Some(name) || listen.bind_address() == name)
+}
+
+/// Stop accepting new connections on the listeners with the label or address.
+async fn listener_pause(name: web::Path<String>) -> HttpResponse {
+    let name = name.into_inner();
+    if !listener_exists(&name) {
+        return HttpResponse::NotFound()
+            .content_type("text/plain")
+            .body(format!("listener {name}: not found\n"));
+    }
+    if drain::pause(&name) {
+        log::info!("listener {name} paused via admin api");
+        request_respawn();
+    }
+    HttpResponse::Accepted()
+        .content_type("text/plain")
+        .body(format!("listener {name}: pausing\n"))
+}
+
+/// Resume accepting connections on the listeners with the label or address.
+async fn listener_resume(name: web::Path<String>) -> HttpResponse {
+    let name = name.into_inner();
+    match drain::resume(&name) {
+        true => {
+            log::info!("listener {name} resumed via admin api");
+            request_respawn();
+            HttpResponse::Accepted()
+                .content_type("text/plain")
+                .body(format!("listener {name}: resuming\n"))
+        }
+        false => HttpResponse::NotFound()
+            .content_type("text/plain")
+            .body(format!("listener {name}: not paused\n")),
+    }
+}
+
+/// Dump all collected metrics.
+async fn stats() -> HttpResponse {
+    let body = metrics::snapshot()
+        .into_iter()
+        .fold(String::new(), |mut body, (name, value)| {
+            let _ = writeln!(body, "{name} {value}");
+            body
+        });
+    HttpResponse::Ok().content_type("text/plain").body(body)
+}
+
+/// List the state of every upstream pool member.
+#[cfg(feature = "rproxy")]
+async fn pools() -> HttpResponse {
+    let body =
+        bob_core::services::pool::list()
+            .into_iter()
+            .fold(String::new(), |mut body, member| {
+                let state = match member.leaving {
+                    Some(left) if member.drained => format!("drained {}s", left.as_secs()),
+                    Some(left) => format!("leaving {}s", left.as_secs()),
+                    None => "active".to_owned(),
+                };
+                let url = match member.addr {
+                    Some(addr) => format!("{} ({addr})", member.url),
+                    None => member.url,
+                };
+                let _ = writeln!(
+                    body,
+                    "{} {} {url} {state} weight={}",
+                    member.pool, member.id, member.weight
+                );
+                body
+            });
+    HttpResponse::Ok().content_type("text/plain").body(body)
+}
+
+/// Drain an upstream pool member after its grace period.
+#[cfg(feature = "rproxy")]
+async fn pool_drain(path: web::Path<(String, String)>) -> HttpResponse {
+    pool_set_drain(path.into_inner(), true)
+}
+
+/// Return a drained upstream pool member to service.
+#[cfg(feature = "rproxy")]
+async fn pool_restore(path: web::Path<(String, String)>) -> HttpResponse {
+    pool_set_drain(path.into_inner(), false)
+}
+
+#[cfg(feature = "rproxy")]
+fn pool_set_drain((name, id): (String, String), enable: bool) -> HttpResponse {
+    let state = if enable { "draining" } else { "restored" };
+    match bob_core::services::pool::drain(&name, &id, enable) {
+        true => {
+            log::info!("admin {state} upstream {id} in pool {name:?}");
+            HttpResponse::Ok()
+                .content_type("text/plain")
+                .body(format!("{name} {id}: {state}\n"))
+        }
+        false => HttpResponse::NotFound()
+            .content_type("text/plain")
+            .body(format!("{name} {id}: no such upstream\n")),
+    }
+}
+
+/// List all active client bans.
+#[cfg(feature = "ipban")]
+async fn bans() -> HttpResponse {
+    use bob_core::services::ban;
+    let now = Instant::now();
+    let body = ban::list()
+        .into_iter()
+        .fold(String::new(), |mut body, (ip, ban)| {
+            let remaining = ban.until.saturating_duration_since(now).as_secs();
+            let _ = writeln!(
+                body,
+                "{ip} {remaining}s offenses={} reason={:?}",
+                ban.offenses, ban.reason
+            );
+            body
+        });
+    HttpResponse::Ok().content_type("text/plain").body(body)
+}
+
+/// Clear all active client bans.
+#[cfg(feature = "ipban")]
+async fn bans_clear() -> HttpResponse {
+    let count = bob_core::services::ban::clear();
+    log::info!("admin cleared {count} bans");
+    HttpResponse::Ok()
+        .content_type("text/plain")
+        .body(format!("cleared {count} bans\n"))
+}
+
+/// Remove the ban for a single client address.
+#[cfg(feature = "ipban")]
+async fn bans_remove(ip: web::Path<std::net::IpAddr>) -> HttpResponse {
+    let ip = ip.into_inner();
+    match bob_core::services::ban::unban(&ip) {
+        true => {
+            log::info!("admin removed ban for {ip}");
+            HttpResponse::Ok()
+                .content_type("text/plain")
+                .body(format!("{ip}: unbanned\n"))
+        }
+        false => HttpResponse::NotFound()
+            .content_type("text/plain")
+            .body(format!("{ip}: not banned\n")),
+    }
+}
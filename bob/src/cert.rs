@@ -0,0 +1,264 @@
+//! Local Development Certificates
+//!
+//! Generates a certificate authority kept in the user data directory
+//! and signs leaf certificates for the requested hostnames with it, so
+//! local https testing only requires trusting the authority once. The
+//! authority can optionally be installed into the system trust store.
+
+use std::{
+    io::Write,
+    path::{Path, PathBuf},
+    process::Command,
+};
+
+use anyhow::{Context, Result, anyhow};
+use bob_cli::CertCmd;
+use rcgen::{
+    BasicConstraints, Certificate, CertificateParams, DistinguishedName, DnType,
+    ExtendedKeyUsagePurpose, IsCa, KeyPair, KeyUsagePurpose,
+};
+use time::OffsetDateTime;
+
+/// Filename of the certificate authority certificate.
+const CA_CERT: &str = "ca.pem";
+
+/// Filename of the certificate authority private key.
+const CA_KEY: &str = "ca-key.pem";
+
+/// Validity of a newly generated certificate authority.
+const CA_VALIDITY: time::Duration = time::Duration::days(10 * 365);
+
+/// Default certificate authority directory within the user data directory.
+fn default_ca_dir() -> Result<PathBuf> {
+    std::env::var_os("XDG_DATA_HOME")
+        .map(PathBuf::from)
+        .or_else(|| std::env::var_os("HOME").map(|home| Path::new(&home).join(".local/share")))
+        .or_else(|| std::env::var_os("APPDATA").map(PathBuf::from))
+        .map(|dir| dir.join("bob"))
+        .context("unable to determine data directory (use --ca-dir)")
+}
+
+/// Write file only readable by the current user.
+fn write_private(path: &Path, data: &str) -> Result<()> {
+    let mut options = std::fs::OpenOptions::new();
+    options.write(true).create(true).truncate(true);
+    #[cfg(unix)]
+    std::os::unix::fs::OpenOptionsExt::mode(&mut options, 0o600);
+    options
+        .open(path)
+        .and_then(|mut file| file.write_all(data.as_bytes()))
+        .with_context(|| format!("failed to write {path:?}"))
+}
+
+/// Local certificate authority used to sign leaf certificates.
+struct Authority {
+    cert: Certificate,
+    key: KeyPair,
+    path: PathBuf,
+}
+
+impl Authority {
+    /// Load the certificate authority from the directory or create it.
+    fn load_or_create(dir: &Path) -> Result<Self> {
+        let path = dir.join(CA_CERT);
+        let key_path = dir.join(CA_KEY);
+        if path.exists() && key_path.exists() {
+            let pem = std::fs::read_to_string(&path)
+                .with_context(|| format!("failed to read {path:?}"))?;
+            let key = std::fs::read_to_string(&key_path)
+                .with_context(|| format!("failed to read {key_path:?}"))?;
+            let key = KeyPair::from_pem(&key).context("invalid certificate authority key")?;
+            // re-signing the parsed params reproduces the issuer name and key id
+            let cert = CertificateParams::from_ca_cert_pem(&pem)
+                .context("invalid certificate authority")?
+                .self_signed(&key)
+                .context("failed to load certificate authority")?;
+            return Ok(Self { cert, key, path });
+        }
+
+        std::fs::create_dir_all(dir).with_context(|| format!("failed to create {dir:?}"))?;
+        let user = std::env::var("USER")
+            .or_else(|_| std::env::var("USERNAME"))
+            .unwrap_or_else(|_| "local".to_owned());
+        let now = OffsetDateTime::now_utc();
+        let mut params = CertificateParams::default();
+        params.distinguished_name = DistinguishedName::new();
+        params
+            .distinguished_name
+            .push(DnType::CommonName, format!("bob development CA ({user})"));
+        params
+            .distinguished_name
+            .push(DnType::OrganizationName, "bob development CA");
+        params.is_ca = IsCa::Ca(BasicConstraints::Constrained(0));
+        params.key_usages = vec![
+            KeyUsagePurpose::KeyCertSign,
+            KeyUsagePurpose::CrlSign,
+            KeyUsagePurpose::DigitalSignature,
+        ];
+        params.not_before = now - time::Duration::days(1);
+        params.not_after = now + CA_VALIDITY;
+        let key = KeyPair::generate().context("failed to generate key")?;
+        let cert = params
+            .self_signed(&key)
+            .context("failed to generate certificate authority")?;
+
+        write_private(&key_path, &key.serialize_pem())?;
+        std::fs::write(&path, cert.pem()).with_context(|| format!("failed to write {path:?}"))?;
+        println!("created new local certificate authority at {path:?}");
+        Ok(Self { cert, key, path })
+    }
+
+    /// Issue a server certificate for the hostnames.
+    fn issue(&self, hosts: &[String], valid: std::time::Duration) -> Result<(String, String)> {
+        let now = OffsetDateTime::now_utc();
+        let mut params =
+            CertificateParams::new(hosts.to_vec()).context("invalid certificate hostnames")?;
+        params.distinguished_name = DistinguishedName::new();
+        params
+            .distinguished_name
+            .push(DnType::CommonName, hosts[0].to_owned());
+        params.key_usages = vec![
+            KeyUsagePurpose::DigitalSignature,
+            KeyUsagePurpose::KeyEncipherment,
+        ];
+        params.extended_key_usages = vec![ExtendedKeyUsagePurpose::ServerAuth];
+        params.use_authority_key_identifier_extension = true;
+        params.not_before = now - time::Duration::days(1);
+        params.not_after = now + valid;
+        let key = KeyPair::generate().context("failed to generate key")?;
+        let cert = params
+            .signed_by(&key, &self.cert, &self.key)
+            .context("failed to sign certificate")?;
+        Ok((cert.pem(), key.serialize_pem()))
+    }
+
+    /// Add the certificate authority to the system trust store.
+    fn install(&self) -> Result<()> {
+        let path = self.path.to_string_lossy();
+        match std::env::consts::OS {
+            "macos" => run(
+                "security",
+                &[
+                    "add-trusted-cert",
+                    "-d",
+                    "-r",
+                    "trustRoot",
+                    "-k",
+                    "/Library/Keychains/System.keychain",
+                    &path,
+                ],
+            ),
+            "windows" => run("certutil", &["-addstore", "-f", "ROOT", &path]),
+            _ => self.install_linux(),
+        }
+    }
+
+    /// Add the certificate authority to the debian, fedora or arch trust store.
+    fn install_linux(&self) -> Result<()> {
+        let stores: [(&str, &str, &[&str]); 3] = [
+            (
+                "/usr/local/share/ca-certificates",
+                "update-ca-certificates",
+                &[],
+            ),
+            (
+                "/etc/pki/ca-trust/source/anchors",
+                "update-ca-trust",
+                &["extract"],
+            ),
+            (
+                "/etc/ca-certificates/trust-source/anchors",
+                "trust",
+                &["extract-compat"],
+            ),
+        ];
+        let (dir, command, args) = stores
+            .into_iter()
+            .find(|(dir, _, _)| Path::new(dir).is_dir())
+            .ok_or_else(|| anyhow!("no supported system trust store found"))?;
+        let target = Path::new(dir).join("bob-development-ca.crt");
+        std::fs::copy(&self.path, &target).with_context(|| {
+            format!("failed to copy certificate authority to {target:?} (try again with sudo)")
+        })?;
+        run(command, args)
+    }
+}
+
+/// Run trust store command reporting failures.
+fn run(command: &str, args: &[&str]) -> Result<()> {
+    let status = Command::new(command)
+        .args(args)
+        .status()
+        .with_context(|| format!("failed to run {command:?}"))?;
+    match status.success() {
+        true => Ok(()),
+        false => Err(anyhow!(
+            "{command:?} failed with {status} (try again with sudo)"
+        )),
+    }
+}
+
+/// Generate a throwaway self-signed certificate for the hostnames.
+///
+/// Files are written to a per-process temporary directory and the
+/// certificate also covers loopback names. Returns certificate and key paths.
+pub fn self_signed(hosts: &[&str]) -> Result<(PathBuf, PathBuf)> {
+    let mut names = vec![
+        "localhost".to_owned(),
+        "127.0.0.1".to_owned(),
+        "::1".to_owned(),
+    ];
+    for host in hosts {
+        if !host.is_empty() && !names.iter().any(|name| name == host) {
+            names.push(host.to_string());
+        }
+    }
+    let certified =
+        rcgen::generate_simple_self_signed(names).context("failed to generate certificate")?;
+    let dir = std::env::temp_dir().join(format!("bob-self-signed-{}", std::process::id()));
+    std::fs::create_dir_all(&dir).with_context(|| format!("failed to create {dir:?}"))?;
+    let cert_path = dir.join("cert.pem");
+    let key_path = dir.join("key.pem");
+    std::fs::write(&cert_path, certified.cert.pem())
+        .with_context(|| format!("failed to write {cert_path:?}"))?;
+    write_private(&key_path, &certified.key_pair.serialize_pem())?;
+    Ok((cert_path, key_path))
+}
+
+/// Filename prefix of the certificate for the hostname.
+fn file_stem(host: &str) -> String {
+    host.replace('*', "_wildcard").replace(':', "_")
+}
+
+/// Generate certificate for the hostnames and exit.
+pub fn execute(cmd: CertCmd) -> Result<()> {
+    let dir = match cmd.ca_dir {
+        Some(dir) => dir,
+        None => default_ca_dir()?,
+    };
+    let authority = Authority::load_or_create(&dir)?;
+    if cmd.install {
+        authority.install()?;
+        println!("installed local certificate authority {:?}", authority.path);
+    }
+    if cmd.hosts.is_empty() {
+        return Ok(());
+    }
+
+    let (cert, key) = authority.issue(&cmd.hosts, cmd.valid.0)?;
+    std::fs::create_dir_all(&cmd.output)
+        .with_context(|| format!("failed to create {:?}", cmd.output))?;
+    let stem = file_stem(&cmd.hosts[0]);
+    let cert_path = cmd.output.join(format!("{stem}.pem"));
+    let key_path = cmd.output.join(format!("{stem}-key.pem"));
+    std::fs::write(&cert_path, cert).with_context(|| format!("failed to write {cert_path:?}"))?;
+    write_private(&key_path, &key)?;
+
+    println!("certificate for {:?} written to", cmd.hosts);
+    println!("  certificate: {cert_path:?}");
+    println!("  key:         {key_path:?}");
+    if !cmd.install {
+        println!("run `bob cert --install` to trust the local certificate authority");
+    }
+    Ok(())
+}
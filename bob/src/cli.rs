@@ -1,10 +1,13 @@
 //! CLI actions and [`Config`] compilation
 
+use std::path::PathBuf;
+
 use anyhow::{Context, Result};
 use bob_cli::*;
+use bob_core::config::modules::*;
+use bob_core::config::*;
 
-use crate::config::modules::*;
-use crate::config::*;
+use crate::control::{self, Action};
 
 /// Compilation of [`ServerConfig`] instances
 pub type Config = Vec<ServerConfig>;
@@ -16,26 +19,94 @@ macro_rules! run_and_exit {
     }};
 }
 
+/// Cli settings re-applied whenever the configuration is reloaded.
+#[derive(Clone, Debug, Default)]
+pub struct Overrides {
+    path: Option<PathBuf>,
+    sanitize: Option<bool>,
+    log: Option<bool>,
+    workers: Option<usize>,
+    drain_then_exit: bool,
+}
+
+impl Overrides {
+    /// Drain connections before exiting on graceful shutdown signals.
+    #[inline]
+    pub fn drain_then_exit(&self) -> bool {
+        self.drain_then_exit
+    }
+
+    /// Re-read configuration from its source file.
+    pub fn reload(&self) -> Result<Config> {
+        let path = self
+            .path
+            .as_ref()
+            .context("configuration was not loaded from a file")?;
+        self.apply(read_config(path)?)
+    }
+
+    /// Apply cli overrides and validate configuration.
+    fn apply(&self, mut config: Config) -> Result<Config> {
+        config.iter_mut().for_each(|config| {
+            config.sanitize_errors = config.sanitize_errors.or(self.sanitize);
+            config.logging.disable = self.log.map(|b| !b).unwrap_or_default();
+            config.runtime.workers = self.workers.or(config.runtime.workers);
+        });
+        prepare_config(&config)?;
+        Ok(config)
+    }
+}
+
 /// Build configuration or run command based on cli settings.
-pub fn build_config(cli: Cli) -> Result<Config> {
-    let mut config: Config = match cli.command.unwrap_or_default() {
-        Command::Run(cfg) => run_cmd(cfg),
+pub fn build_config(cli: Cli) -> Result<(Config, Overrides)> {
+    #[cfg(feature = "plugins")]
+    for path in cli.plugins.iter() {
+        bob_core::plugin::load(path)?;
+    }
+    let mut overrides = Overrides {
+        path: None,
+        sanitize: cli.sanitize,
+        log: cli.log,
+        workers: cli.workers,
+        drain_then_exit: cli.drain_then_exit,
+    };
+    let config: Config = match cli.command.unwrap_or_default() {
+        Command::Run(cfg) => {
+            overrides.path = Some(cfg.config.clone());
+            run_cmd(cfg)
+        }
         #[cfg(feature = "fileserver")]
         Command::FileServer(cfg) => fileserver_cmd(cfg),
         #[cfg(feature = "fastcgi")]
         Command::Fastcgi(cfg) => fastcgi_cmd(cfg),
         #[cfg(feature = "rproxy")]
         Command::ReverseProxy(cfg) => rproxy_cmd(cfg),
+        Command::Static(cfg) => static_cmd(cfg),
+        Command::Redirect(cfg) => redirect_cmd(cfg),
         #[cfg(feature = "authn")]
         Command::Passwd(cfg) => run_and_exit!(execute_passwd(cfg)),
+        Command::Reload(cfg) => run_and_exit!(control::execute(cfg, Action::Reload)),
+        Command::Stop(cfg) => run_and_exit!(control::execute(cfg, Action::Stop)),
+        Command::Status(cfg) => run_and_exit!(control::execute(cfg, Action::Status)),
+        Command::DebugToken(cfg) => run_and_exit!(execute_debug_token(cfg)),
+        Command::PrintConfig(cfg) => run_and_exit!(print_config(cfg, &overrides)),
+        Command::RouteTest(cfg) => {
+            let config = overrides.apply(read_config(&cfg.config)?)?;
+            run_and_exit!(crate::route_test::execute(&config, cfg))
+        }
+        #[cfg(feature = "cert")]
+        Command::Cert(cfg) => run_and_exit!(crate::cert::execute(cfg)),
+        #[cfg(feature = "convert")]
+        Command::Convert(cfg) => run_and_exit!(crate::convert::execute(cfg)),
+        #[cfg(feature = "replay")]
+        Command::Replay(_) | Command::Diff(_) => {
+            unreachable!("tooling commands are dispatched before config is built")
+        }
         #[cfg(feature = "schema")]
         Command::Schema(cfg) => run_and_exit!(build_schema(cfg)),
     }?;
-    config.iter_mut().for_each(|config| {
-        config.sanitize_errors = config.sanitize_errors.or(cli.sanitize);
-        config.logging.disable = cli.log.map(|b| !b).unwrap_or_default();
-    });
-    Ok(config)
+    let config = overrides.apply(config)?;
+    Ok((config, overrides))
 }
 
 /// Read config specified in [`RunCmd`]
@@ -44,13 +115,60 @@ fn run_cmd(cmd: RunCmd) -> Result<Config> {
 }
 
 /// Convert string into [`Vec<ListenCfg>`]
-#[cfg(any(feature = "fileserver", feature = "rproxy"))]
+///
+/// Addresses without a host (such as `:8000`) bind every interface.
 #[inline]
 fn convert_addr(addr: &str) -> Result<Vec<ListenCfg>> {
     use std::net::ToSocketAddrs;
+    let addr = match addr.starts_with(':') {
+        true => format!("0.0.0.0{addr}"),
+        false => addr.to_owned(),
+    };
     Ok(addr.to_socket_addrs()?.map(|addr| addr.into()).collect())
 }
 
+/// Convert every string into [`Vec<ListenCfg>`]
+#[cfg(any(feature = "fileserver", feature = "fastcgi", feature = "rproxy"))]
+fn convert_addrs(addrs: &[String]) -> Result<Vec<ListenCfg>> {
+    let mut listen = Vec::new();
+    for addr in addrs {
+        listen.extend(convert_addr(addr).with_context(|| format!("invalid address {addr:?}"))?);
+    }
+    Ok(listen)
+}
+
+/// Enable https on quick command listeners when requested by [`TlsArgs`].
+///
+/// Returns the url scheme the listeners are reachable with.
+#[cfg_attr(not(feature = "cert"), allow(unused_variables))]
+fn apply_tls(listen: &mut [ListenCfg], addrs: &[&str], tls: TlsArgs) -> Result<&'static str> {
+    let (certificate, certificate_key) = match (tls.cert, tls.key) {
+        (Some(cert), Some(key)) => (cert, key),
+        #[cfg(feature = "cert")]
+        _ if tls.self_signed => {
+            let hosts: Vec<&str> = addrs
+                .iter()
+                .map(|addr| {
+                    addr.rsplit_once(':')
+                        .map(|(host, _)| host.trim_matches(['[', ']']))
+                        .unwrap_or(addr)
+                })
+                .collect();
+            crate::cert::self_signed(&hosts)?
+        }
+        _ => return Ok("http"),
+    };
+    let ssl = SSLCfg {
+        certificate: Some(certificate),
+        certificate_key: Some(certificate_key),
+        ..Default::default()
+    };
+    listen
+        .iter_mut()
+        .for_each(|listen| listen.ssl = Some(ssl.clone()));
+    Ok("https")
+}
+
 /// Run password hash generation and exit.
 #[cfg(feature = "authn")]
 fn execute_passwd(cmd: GenPasswdCmd) -> Result<()> {
@@ -83,14 +201,59 @@ fn execute_passwd(cmd: GenPasswdCmd) -> Result<()> {
     Ok(())
 }
 
+/// Print the fully resolved configuration and exit.
+///
+/// Output is yaml reflecting parsed defaults and cli overrides with
+/// secrets redacted.
+fn print_config(cmd: RunCmd, overrides: &Overrides) -> Result<()> {
+    let config = overrides.apply(read_config(&cmd.config)?)?;
+    println!("# config: {:?}", cmd.config);
+    println!("# config hash: {}", config_hash(&config));
+    print!(
+        "{}",
+        serde_yaml::to_string(&config).context("failed to serialize config")?
+    );
+    Ok(())
+}
+
+/// Print signed debug flag token and exit.
+fn execute_debug_token(cmd: DebugTokenCmd) -> Result<()> {
+    let secret = match cmd.secret {
+        Some(secret) => secret,
+        None => read_config(&cmd.config)?
+            .into_iter()
+            .filter(|cfg| !cfg.disable)
+            .find_map(|cfg| cfg.debug_flags.map(|flags| flags.secret.0))
+            .context("config has no debug_flags secret configured")?,
+    };
+    if let Some(flag) = cmd
+        .flags
+        .iter()
+        .find(|f| f.is_empty() || f.contains(['.', '+']) || f.contains(char::is_whitespace))
+    {
+        return Err(anyhow::anyhow!("invalid debug flag {flag:?}"));
+    }
+    let token = bob_core::services::flags::sign(&secret, &cmd.flags, cmd.ttl.0);
+    println!("{token}");
+    Ok(())
+}
+
 /// Build JSON schema for configuration
 #[cfg(feature = "schema")]
 fn build_schema(cmd: SchemaCmd) -> Result<()> {
     use std::io::Write;
 
     let schema = schemars::schema_for!(Config);
-    let data = serde_json::to_string_pretty(&schema)?;
-    let mut file = std::fs::File::create(cmd.output)?;
+    let (data, default) = match cmd.format {
+        SchemaFormat::Json => (serde_json::to_string_pretty(&schema)?, "schema.json"),
+        SchemaFormat::Markdown => {
+            let data = crate::schema::markdown(schema.as_value())
+                .context("failed to render config reference")?;
+            (data, "config-reference.md")
+        }
+    };
+    let output = cmd.output.unwrap_or_else(|| PathBuf::from(default));
+    let mut file = std::fs::File::create(output)?;
     write!(file, "{data}").context("failed to write schema")?;
     Ok(())
 }
@@ -98,19 +261,45 @@ fn build_schema(cmd: SchemaCmd) -> Result<()> {
 /// Fileserver config generation
 #[cfg(feature = "fileserver")]
 fn fileserver_cmd(cmd: FileServerCmd) -> Result<Config> {
+    // sharing binds the lan interface (and loopback) on the first listen port
+    #[cfg(feature = "share")]
+    let (addrs, share) = match cmd.share {
+        true => {
+            let lan = crate::share::lan_addr()?;
+            let port = cmd.listen[0]
+                .rsplit_once(':')
+                .and_then(|(_, port)| port.parse::<u16>().ok())
+                .context("invalid listen port")?;
+            let addrs = vec![format!("{lan}:{port}"), format!("127.0.0.1:{port}")];
+            (addrs, Some((lan, port)))
+        }
+        false => (cmd.listen, None),
+    };
+    #[cfg(not(feature = "share"))]
+    let addrs = cmd.listen;
+    let mut listen = convert_addrs(&addrs).context("invalid listen address")?;
+    let addrs: Vec<&str> = addrs.iter().map(String::as_str).collect();
+    let scheme = apply_tls(&mut listen, &addrs, cmd.tls)?;
+    #[cfg(feature = "share")]
+    if let Some((lan, port)) = share {
+        crate::share::announce(scheme, lan, port)?;
+    }
     if cmd.open {
-        let _ = open::that(format!("http://{}", cmd.listen))
+        let _ = open::that(format!("{scheme}://{}", addrs[0]))
             .inspect_err(|err| log::error!("failed to open browser: {err:?}"));
     }
     Ok(vec![ServerConfig {
         index: cmd.index,
-        listen: convert_addr(&cmd.listen).context("invalid listen address")?,
+        listen,
+        #[cfg(feature = "authn")]
+        middleware: auth_middleware(cmd.auth)?,
         directives: vec![
             ModuleConfig::FileServer(fileserver::Config {
                 root: Some(cmd.root),
                 hidden_files: cmd.show_hidden,
                 index_files: cmd.browse.unwrap_or_default(),
                 async_threshold: None,
+                integrity: None,
             })
             .into(),
         ],
@@ -121,9 +310,12 @@ fn fileserver_cmd(cmd: FileServerCmd) -> Result<Config> {
 /// FastCGI config generation
 #[cfg(feature = "fastcgi")]
 fn fastcgi_cmd(cmd: FastCgiCmd) -> Result<Config> {
+    let mut listen = convert_addrs(&cmd.listen).context("invalid listen address")?;
+    let addrs: Vec<&str> = cmd.listen.iter().map(String::as_str).collect();
+    apply_tls(&mut listen, &addrs, cmd.tls)?;
     Ok(vec![ServerConfig {
         index: cmd.index,
-        listen: convert_addr(&cmd.listen).context("invalid listen address")?,
+        listen,
         sanitize_errors: Some(false),
         directives: vec![
             ModuleConfig::FastCGI(fastcgi::Config {
@@ -136,20 +328,104 @@ fn fastcgi_cmd(cmd: FastCgiCmd) -> Result<Config> {
     }])
 }
 
+/// Basic-auth middleware for quick commands when requested by [`AuthArgs`].
+///
+/// Inline credentials are hashed into a temporary htpasswd file.
+#[cfg(feature = "authn")]
+fn auth_middleware(auth: AuthArgs) -> Result<Vec<Middleware>> {
+    use actix_authn::basic::crypt::bcrypt;
+    use std::io::Write;
+
+    let mut htpasswd = auth.htpasswd;
+    if !auth.auth.is_empty() {
+        let mut records = String::new();
+        for credentials in auth.auth {
+            let (user, password) = credentials
+                .split_once(':')
+                .context("--auth must be formatted as user:pass")?;
+            let hash = bcrypt::hash(password.to_owned()).context("failed to hash password")?;
+            records.push_str(&format!("{user}:{}\n", hash.as_str()));
+        }
+        let path = std::env::temp_dir().join(format!("bob-auth-{}.htpasswd", std::process::id()));
+        let mut options = std::fs::OpenOptions::new();
+        options.write(true).create(true).truncate(true);
+        #[cfg(unix)]
+        std::os::unix::fs::OpenOptionsExt::mode(&mut options, 0o600);
+        options
+            .open(&path)
+            .and_then(|mut file| file.write_all(records.as_bytes()))
+            .with_context(|| format!("failed to write {path:?}"))?;
+        htpasswd.push(path);
+    }
+    Ok(match htpasswd.is_empty() {
+        true => vec![],
+        false => vec![Middleware::basic_auth(htpasswd)],
+    })
+}
+
+/// Static response config generation
+fn static_cmd(cmd: StaticCmd) -> Result<Config> {
+    let body = match cmd.body_file {
+        Some(path) => Some(
+            std::fs::read_to_string(&path)
+                .with_context(|| format!("failed to read body file {path:?}"))?,
+        ),
+        None => cmd.body,
+    };
+    let mut listen = convert_addr(&cmd.listen).context("invalid listen address")?;
+    apply_tls(&mut listen, &[&cmd.listen], cmd.tls)?;
+    Ok(vec![ServerConfig {
+        listen,
+        directives: vec![
+            ModuleConfig::Static(rstatic::Config {
+                body,
+                content_type: cmd.content_type,
+                headers: cmd.header.into_iter().map(|h| (h.0, h.1)).collect(),
+                status_code: Some(cmd.status),
+            })
+            .into(),
+        ],
+        ..Default::default()
+    }])
+}
+
+/// Redirect config generation
+fn redirect_cmd(cmd: RedirectCmd) -> Result<Config> {
+    let mut listen = convert_addr(&cmd.from).context("invalid from address")?;
+    apply_tls(&mut listen, &[&cmd.from], cmd.tls)?;
+    Ok(vec![ServerConfig {
+        listen,
+        directives: vec![
+            ModuleConfig::Redirect(redirect::Config {
+                redirect: cmd.to,
+                status_code: Some(cmd.status),
+                preserve_path: cmd.preserve_path,
+            })
+            .into(),
+        ],
+        ..Default::default()
+    }])
+}
+
 /// Reverse-Proxy config generation
 #[cfg(feature = "rproxy")]
 fn rproxy_cmd(cmd: RevProxyCmd) -> Result<Config> {
+    let mut listen = convert_addrs(&cmd.from).context("invalid from address")?;
+    let addrs: Vec<&str> = cmd.from.iter().map(String::as_str).collect();
+    let scheme = apply_tls(&mut listen, &addrs, cmd.tls)?;
     if cmd.open {
-        let _ = open::that(format!("http://{}", cmd.from))
+        let _ = open::that(format!("{scheme}://{}", addrs[0]))
             .inspect_err(|err| log::error!("failed to open browser: {err:?}"));
     }
     let downstream = cmd.header_down.into_iter().map(|h| (h.0, h.1)).collect();
     let upstream = cmd.header_up.into_iter().map(|h| (h.0, h.1)).collect();
     Ok(vec![ServerConfig {
-        listen: convert_addr(&cmd.from).context("invalid from address")?,
+        listen,
+        #[cfg(feature = "authn")]
+        middleware: auth_middleware(cmd.auth)?,
         directives: vec![
             ModuleConfig::ReverseProxy(rproxy::Config {
-                resolve: cmd.to,
+                resolve: cmd.to.into(),
                 timeout: Some(cmd.timeout),
                 verify_ssl: Some(cmd.insecure),
                 change_host: cmd.change_host_header,
@@ -158,6 +434,32 @@ fn rproxy_cmd(cmd: RevProxyCmd) -> Result<Config> {
                 max_redirects: None,
                 initial_conn_size: None,
                 initial_window_size: None,
+                fallback_addresses: vec![],
+                last_known_fallback: false,
+                dns_max_ttl: None,
+                proxy_redirect: Default::default(),
+                cookie_domain: Default::default(),
+                cookie_path: Default::default(),
+                hop_by_hop_headers: vec![],
+                strip_prefix: None,
+                add_prefix: None,
+                rewrite: vec![],
+                client_certificate: None,
+                client_key: None,
+                ca_file: None,
+                retries: None,
+                retry_on: vec![],
+                try_timeout: None,
+                mirror: None,
+                buffer_requests: false,
+                buffer_responses: false,
+                buffer_memory_size: None,
+                buffer_max_size: None,
+                buffer_spill_dir: None,
+                grpc: false,
+                http2_prior_knowledge: false,
+                intercept_errors: vec![],
+                connection_pool: Default::default(),
             })
             .into(),
         ],
@@ -0,0 +1,179 @@
+//! Running Instance Control
+//!
+//! Implements `bob reload`, `bob stop` and `bob status` by talking to
+//! a running instance through its admin api or, when none is
+//! configured, by signalling the process listed in its pid file.
+
+use std::{
+    io::{Read, Write},
+    path::PathBuf,
+    process::Command,
+    time::Duration,
+};
+
+use anyhow::{Context, Result, anyhow};
+use bob_cli::ControlCmd;
+use bob_core::config::{ServerConfig, read_config};
+
+/// Timeout used when talking to the admin api.
+const ADMIN_TIMEOUT: Duration = Duration::from_secs(30);
+
+/// Control action sent to a running instance.
+#[derive(Debug, Clone, Copy)]
+pub enum Action {
+    Reload,
+    Stop,
+    Status,
+}
+
+impl Action {
+    /// Admin api method and path implementing action.
+    fn endpoint(&self) -> (&'static str, &'static str) {
+        match self {
+            Self::Reload => ("POST", "/reload"),
+            Self::Stop => ("POST", "/stop"),
+            Self::Status => ("GET", "/status"),
+        }
+    }
+    /// Signal implementing action.
+    fn signal(&self) -> &'static str {
+        match self {
+            Self::Reload => "HUP",
+            Self::Stop => "TERM",
+            Self::Status => "0",
+        }
+    }
+}
+
+/// Method used to reach a running instance.
+enum Target {
+    Admin(String, Option<String>),
+    Pid(u32),
+}
+
+/// Execute control action against a running instance.
+pub fn execute(cmd: ControlCmd, action: Action) -> Result<()> {
+    match resolve(cmd)? {
+        Target::Admin(admin, token) => {
+            let (method, path) = action.endpoint();
+            let (status, body) = admin_request(&admin, token.as_deref(), method, path)
+                .with_context(|| format!("failed to reach admin api {admin:?}"))?;
+            print!("{body}");
+            match status {
+                200..=299 => Ok(()),
+                status => Err(anyhow!("admin api responded with status {status}")),
+            }
+        }
+        Target::Pid(pid) => {
+            signal(pid, action.signal())?;
+            match action {
+                Action::Status => println!("status: running\npid: {pid}"),
+                _ => println!("sent SIG{} to {pid}", action.signal()),
+            }
+            Ok(())
+        }
+    }
+}
+
+/// Determine how to reach the running instance.
+fn resolve(cmd: ControlCmd) -> Result<Target> {
+    if let Some(pid) = cmd.pid {
+        return Ok(Target::Pid(pid));
+    }
+    let config = match (cmd.admin.as_ref(), cmd.token.as_ref()) {
+        (Some(_), Some(_)) => vec![],
+        _ => read_config(&cmd.config)?,
+    };
+    let enabled = || config.iter().filter(|cfg| !cfg.disable);
+    let configured = enabled().find_map(|cfg| cfg.admin.as_ref());
+    let token = cmd.token.or_else(|| {
+        configured
+            .and_then(|a| a.token.as_ref())
+            .map(|t| t.0.clone())
+    });
+    if let Some(admin) = cmd.admin {
+        return Ok(Target::Admin(admin, token));
+    }
+    if let Some(admin) = configured {
+        let addr = match admin.socket.as_ref() {
+            Some(socket) => socket.to_string_lossy().into_owned(),
+            None => admin.listen().to_owned(),
+        };
+        return Ok(Target::Admin(addr, token));
+    }
+    match pid_file(&config) {
+        Some(path) => {
+            let pid = std::fs::read_to_string(&path)
+                .with_context(|| format!("failed to read pid file {path:?}"))?;
+            let pid = pid.trim().parse().context("invalid pid file")?;
+            Ok(Target::Pid(pid))
+        }
+        None => Err(anyhow!("config has no admin api or pid file configured")),
+    }
+}
+
+/// Pid file configured by the first enabled server.
+pub fn pid_file(config: &[ServerConfig]) -> Option<PathBuf> {
+    config
+        .iter()
+        .filter(|cfg| !cfg.disable)
+        .find_map(|cfg| cfg.runtime.pid_file.clone())
+}
+
+/// Send signal to process using the system `kill` command.
+fn signal(pid: u32, signal: &str) -> Result<()> {
+    let status = Command::new("kill")
+        .arg(format!("-{signal}"))
+        .arg(pid.to_string())
+        .status()
+        .context("failed to run kill")?;
+    match status.success() {
+        true => Ok(()),
+        false => Err(anyhow!("process {pid} is not running")),
+    }
+}
+
+/// Send a minimal HTTP/1.1 request to the admin api.
+fn admin_request(
+    admin: &str,
+    token: Option<&str>,
+    method: &str,
+    path: &str,
+) -> Result<(u16, String)> {
+    let auth = token
+        .map(|token| format!("Authorization: Bearer {token}\r\n"))
+        .unwrap_or_default();
+    let request = format!(
+        "{method} {path} HTTP/1.1\r\nHost: localhost\r\n{auth}Content-Length: 0\r\nConnection: close\r\n\r\n"
+    );
+    let mut response = vec![];
+
+    #[cfg(unix)]
+    if admin.contains('/') {
+        let mut stream = std::os::unix::net::UnixStream::connect(admin)?;
+        stream.set_read_timeout(Some(ADMIN_TIMEOUT))?;
+        stream.write_all(request.as_bytes())?;
+        stream.read_to_end(&mut response)?;
+        return parse_response(&response);
+    }
+
+    let mut stream = std::net::TcpStream::connect(admin)?;
+    stream.set_read_timeout(Some(ADMIN_TIMEOUT))?;
+    stream.write_all(request.as_bytes())?;
+    stream.read_to_end(&mut response)?;
+    parse_response(&response)
+}
+
+/// Parse status code and body from raw HTTP/1.1 response.
+fn parse_response(response: &[u8]) -> Result<(u16, String)> {
+    let response = String::from_utf8_lossy(response);
+    let (head, body) = response
+        .split_once("\r\n\r\n")
+        .context("incomplete admin response")?;
+    let status = head
+        .split_whitespace()
+        .nth(1)
+        .and_then(|s| s.parse().ok())
+        .context("invalid admin response status")?;
+    Ok((status, body.to_owned()))
+}
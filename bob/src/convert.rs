@@ -0,0 +1,804 @@
+//! Configuration Importer
+//!
+//! Translates a basic nginx.conf or Caddyfile into an equivalent bob
+//! config to ease migration. Only the common subset is understood: server
+//! blocks, locations, proxying, file serving, redirects and basic-auth.
+//! Anything else is reported as a warning and left for manual review.
+
+use std::{
+    io::Write,
+    path::{Path, PathBuf},
+};
+
+use anyhow::{Context, Result, anyhow, bail};
+use base64::{Engine, prelude::BASE64_STANDARD};
+use bob_cli::{ConvertCmd, ConvertFormat};
+use bob_core::config::ServerConfig;
+use serde::Serialize;
+
+/// Generated server block.
+#[derive(Debug, Default, Serialize)]
+struct Server {
+    listen: Vec<Listen>,
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    server_name: Vec<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    root: Option<String>,
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    index: Vec<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    max_body_size: Option<u64>,
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    middleware: Vec<Component>,
+    directives: Vec<Directive>,
+}
+
+/// Generated listener.
+#[derive(Debug, PartialEq, Serialize)]
+struct Listen {
+    #[serde(skip_serializing_if = "Option::is_none")]
+    host: Option<String>,
+    port: u16,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    ssl: Option<Ssl>,
+}
+
+/// Generated listener certificate settings.
+#[derive(Clone, Debug, Default, PartialEq, Serialize)]
+struct Ssl {
+    certificate: String,
+    certificate_key: String,
+}
+
+/// Generated directive grouping modules under a location.
+#[derive(Debug, Serialize)]
+struct Directive {
+    location: String,
+    construct: Vec<Component>,
+}
+
+/// Generated module or middleware.
+#[derive(Debug, Clone, Serialize)]
+#[serde(untagged)]
+enum Component {
+    Module(Module),
+    Middleware(Middleware),
+}
+
+#[derive(Debug, Clone, Serialize)]
+#[serde(tag = "module", rename_all = "lowercase")]
+enum Module {
+    Fileserver {
+        #[serde(skip_serializing_if = "Option::is_none")]
+        root: Option<String>,
+        #[serde(skip_serializing_if = "std::ops::Not::not")]
+        index_files: bool,
+    },
+    Rproxy {
+        resolve: String,
+    },
+    Redirect {
+        redirect: String,
+        status_code: u16,
+        #[serde(skip_serializing_if = "std::ops::Not::not")]
+        preserve_path: bool,
+    },
+    Static {
+        #[serde(skip_serializing_if = "Option::is_none")]
+        body: Option<String>,
+        status_code: u16,
+    },
+}
+
+#[derive(Debug, Clone, Serialize)]
+#[serde(tag = "middleware", rename_all = "snake_case")]
+enum Middleware {
+    BasicAuth { htpasswd: Vec<String> },
+}
+
+impl Server {
+    /// Add component to the directive matching the location.
+    fn push(&mut self, location: &str, component: Component) {
+        match self.directives.iter_mut().find(|d| d.location == location) {
+            Some(directive) => directive.construct.push(component),
+            None => self.directives.push(Directive {
+                location: location.to_owned(),
+                construct: vec![component],
+            }),
+        }
+    }
+
+    /// Order directives and components the way bob evaluates them.
+    fn finish(mut self) -> Self {
+        if self.listen.is_empty() {
+            self.listen.push(Listen {
+                host: None,
+                port: 80,
+                ssl: None,
+            });
+        }
+        // dual-stack wildcard listeners already cover the ipv6 wildcard
+        let wildcard: Vec<u16> = self
+            .listen
+            .iter()
+            .filter(|l| l.host.is_none())
+            .map(|l| l.port)
+            .collect();
+        self.listen
+            .retain(|l| !(l.host.as_deref() == Some("::") && wildcard.contains(&l.port)));
+        self.listen.dedup();
+        // longest prefix first mirrors nginx and caddy location matching
+        self.directives
+            .sort_by_key(|d| std::cmp::Reverse(d.location.len()));
+        for directive in self.directives.iter_mut() {
+            directive
+                .construct
+                .sort_by_key(|c| matches!(c, Component::Middleware(_)));
+        }
+        self
+    }
+}
+
+/// Converted configuration with notes on anything left untranslated.
+#[derive(Default)]
+struct Converted {
+    servers: Vec<Server>,
+    warnings: Vec<String>,
+    /// Directory generated files are written to.
+    dir: PathBuf,
+    files: Vec<(PathBuf, String)>,
+}
+
+impl Converted {
+    #[inline]
+    fn warn(&mut self, msg: impl Into<String>) {
+        self.warnings.push(msg.into());
+    }
+}
+
+/// Lexical token shared by nginx and Caddyfile syntax.
+#[derive(Debug)]
+enum Token {
+    Word(String),
+    Open,
+    Close,
+    End,
+}
+
+/// Split configuration into tokens.
+///
+/// Caddyfile statements end with a newline and braces only open or close
+/// blocks as standalone words, so placeholders like `{uri}` survive.
+fn tokenize(src: &str, caddy: bool) -> Result<Vec<Token>> {
+    let mut tokens = Vec::new();
+    let mut chars = src.chars().peekable();
+    while let Some(c) = chars.next() {
+        match c {
+            '#' => while chars.next_if(|c| *c != '\n').is_some() {},
+            '\n' if caddy => tokens.push(Token::End),
+            ';' if !caddy => tokens.push(Token::End),
+            '{' if !caddy => tokens.push(Token::Open),
+            '}' if !caddy => tokens.push(Token::Close),
+            '"' | '\'' => {
+                let mut word = String::new();
+                loop {
+                    match chars.next() {
+                        Some('\\') => word.extend(chars.next()),
+                        Some(q) if q == c => break,
+                        Some(c) => word.push(c),
+                        None => bail!("unterminated quoted string"),
+                    }
+                }
+                tokens.push(Token::Word(word));
+            }
+            c if c.is_whitespace() => {}
+            c => {
+                let mut word = c.to_string();
+                while let Some(c) = chars
+                    .next_if(|c| !c.is_whitespace() && (caddy || !matches!(c, ';' | '{' | '}')))
+                {
+                    word.push(c);
+                }
+                tokens.push(match word.as_str() {
+                    "{" => Token::Open,
+                    "}" => Token::Close,
+                    _ => Token::Word(word),
+                });
+            }
+        }
+    }
+    Ok(tokens)
+}
+
+/// Configuration statement with optional nested block.
+#[derive(Debug)]
+struct Node {
+    args: Vec<String>,
+    block: Option<Vec<Node>>,
+}
+
+impl Node {
+    #[inline]
+    fn name(&self) -> &str {
+        self.args.first().map(String::as_str).unwrap_or_default()
+    }
+    #[inline]
+    fn arg(&self, n: usize) -> Option<&str> {
+        self.args.get(n).map(String::as_str)
+    }
+    #[inline]
+    fn block(&self) -> &[Node] {
+        self.block.as_deref().unwrap_or_default()
+    }
+}
+
+/// Build statement tree from tokens.
+fn parse(tokens: &mut impl Iterator<Item = Token>, nested: bool) -> Result<Vec<Node>> {
+    let mut nodes = Vec::new();
+    let mut args = Vec::new();
+    while let Some(token) = tokens.next() {
+        match token {
+            Token::Word(word) => args.push(word),
+            Token::End if args.is_empty() => {}
+            Token::End => nodes.push(Node {
+                args: std::mem::take(&mut args),
+                block: None,
+            }),
+            Token::Open => {
+                let block = parse(tokens, true)?;
+                nodes.push(Node {
+                    args: std::mem::take(&mut args),
+                    block: Some(block),
+                });
+            }
+            Token::Close if nested => {
+                if !args.is_empty() {
+                    nodes.push(Node { args, block: None });
+                }
+                return Ok(nodes);
+            }
+            Token::Close => bail!("unexpected closing brace"),
+        }
+    }
+    if nested {
+        bail!("missing closing brace");
+    }
+    if !args.is_empty() {
+        nodes.push(Node { args, block: None });
+    }
+    Ok(nodes)
+}
+
+/// Parse size with an optional nginx unit suffix.
+fn parse_size(size: &str) -> Option<u64> {
+    let size = size.to_ascii_lowercase();
+    let (num, unit) = match size.char_indices().last()? {
+        (i, 'k') => (&size[..i], 1 << 10),
+        (i, 'm') => (&size[..i], 1 << 20),
+        (i, 'g') => (&size[..i], 1 << 30),
+        _ => (size.as_str(), 1),
+    };
+    num.parse::<u64>().ok().map(|n| n * unit)
+}
+
+/// Resolve a redirect target, stripping trailing request path variables.
+fn redirect_target(url: &str, path_vars: &[&str], host: Option<&str>) -> (String, bool) {
+    let (url, preserve) = path_vars
+        .iter()
+        .find_map(|var| url.strip_suffix(var))
+        .map(|url| (url, true))
+        .unwrap_or((url, false));
+    let url = match host {
+        Some(host) => url
+            .replace("$host", host)
+            .replace("$server_name", host)
+            .replace("{host}", host),
+        None => url.to_owned(),
+    };
+    (url, preserve)
+}
+
+/// Translate nginx statements.
+fn convert_nginx(nodes: &[Node], out: &mut Converted) {
+    const IGNORED: &[&str] = &[
+        "user",
+        "worker_processes",
+        "pid",
+        "events",
+        "error_log",
+        "access_log",
+        "include",
+        "types",
+        "default_type",
+        "sendfile",
+        "tcp_nopush",
+        "tcp_nodelay",
+        "keepalive_timeout",
+    ];
+    for node in nodes {
+        match node.name() {
+            "http" => convert_nginx(node.block(), out),
+            "server" => {
+                let server = nginx_server(node.block(), out);
+                out.servers.push(server.finish());
+            }
+            name if IGNORED.contains(&name) => {}
+            name => out.warn(format!("nginx: unsupported directive {name:?}")),
+        }
+    }
+}
+
+/// Translate nginx listen address.
+fn nginx_listen(addr: &str) -> Option<(Option<String>, u16)> {
+    if let Ok(port) = addr.parse() {
+        return Some((None, port));
+    }
+    let (host, port) = addr.rsplit_once(':')?;
+    let host = host.trim_start_matches('[').trim_end_matches(']');
+    let host = (host != "*").then(|| host.to_owned());
+    Some((host, port.parse().ok()?))
+}
+
+/// Translate nginx basic-auth settings.
+fn nginx_auth(block: &[Node]) -> Option<Component> {
+    let enabled = block
+        .iter()
+        .any(|n| n.name() == "auth_basic" && n.arg(1) != Some("off"));
+    let file = block
+        .iter()
+        .find(|n| n.name() == "auth_basic_user_file")
+        .and_then(|n| n.arg(1))?;
+    enabled.then(|| {
+        Component::Middleware(Middleware::BasicAuth {
+            htpasswd: vec![file.to_owned()],
+        })
+    })
+}
+
+/// Translate nginx return statement into a redirect.
+fn nginx_return(node: &Node, host: Option<&str>, out: &mut Converted) -> Option<Module> {
+    let (code, url) = match (node.arg(1), node.arg(2)) {
+        (Some(code), Some(url)) => (code.parse().ok()?, url),
+        (Some(url), None) if url.contains("://") => (302, url),
+        _ => (0, ""),
+    };
+    if !(300..400).contains(&code) {
+        out.warn(format!("nginx: unsupported return {:?}", &node.args[1..]));
+        return None;
+    }
+    let (redirect, preserve_path) = redirect_target(url, &["$request_uri"], host);
+    if redirect.contains('$') {
+        out.warn(format!("nginx: unresolved variables in redirect {url:?}"));
+    }
+    Some(Module::Redirect {
+        redirect,
+        status_code: code,
+        preserve_path,
+    })
+}
+
+/// Translate nginx server block.
+fn nginx_server(block: &[Node], out: &mut Converted) -> Server {
+    let mut server = Server::default();
+    let mut ssl_ports = Vec::new();
+    let mut certificate = None;
+    let mut certificate_key = None;
+    for node in block {
+        match node.name() {
+            "listen" => match node.arg(1).and_then(nginx_listen) {
+                Some((host, port)) => {
+                    if node.args.iter().any(|a| a == "ssl") {
+                        ssl_ports.push(port);
+                    }
+                    server.listen.push(Listen {
+                        host,
+                        port,
+                        ssl: None,
+                    });
+                }
+                None => out.warn(format!("nginx: unsupported listen {:?}", node.arg(1))),
+            },
+            "server_name" => server.server_name.extend(
+                node.args
+                    .iter()
+                    .skip(1)
+                    .filter(|name| *name != "_" && !name.is_empty())
+                    .cloned(),
+            ),
+            "root" => server.root = node.arg(1).map(str::to_owned),
+            "index" => server.index = node.args[1..].to_vec(),
+            "ssl_certificate" => certificate = node.arg(1).map(str::to_owned),
+            "ssl_certificate_key" => certificate_key = node.arg(1).map(str::to_owned),
+            "client_max_body_size" => {
+                server.max_body_size = node.arg(1).and_then(parse_size).filter(|n| *n > 0)
+            }
+            "auth_basic" | "auth_basic_user_file" => {}
+            "location" => nginx_location(node, &mut server, out),
+            "return" => {
+                let host = (server.server_name.len() == 1).then(|| server.server_name[0].clone());
+                if let Some(module) = nginx_return(node, host.as_deref(), out) {
+                    server.push("/", Component::Module(module));
+                }
+            }
+            "ssl_protocols" | "ssl_ciphers" | "ssl_prefer_server_ciphers" => {}
+            name => out.warn(format!("nginx: unsupported server directive {name:?}")),
+        }
+    }
+    if let Some(auth) = nginx_auth(block) {
+        server.middleware.push(auth);
+    }
+    match (certificate, certificate_key) {
+        (Some(certificate), Some(certificate_key)) => {
+            let ssl = Ssl {
+                certificate,
+                certificate_key,
+            };
+            for listen in server.listen.iter_mut() {
+                if ssl_ports.contains(&listen.port) {
+                    listen.ssl = Some(ssl.clone());
+                }
+            }
+        }
+        _ if !ssl_ports.is_empty() => {
+            out.warn("nginx: ssl listener without certificate and key");
+        }
+        _ => {}
+    }
+    server
+}
+
+/// Translate nginx location block.
+fn nginx_location(node: &Node, server: &mut Server, out: &mut Converted) {
+    let (modifier, path) = match (node.arg(1), node.arg(2)) {
+        (Some(modifier), Some(path)) => (modifier, path),
+        (Some(path), None) => ("", path),
+        _ => return out.warn("nginx: location without path"),
+    };
+    if !matches!(modifier, "" | "=" | "^~") {
+        return out.warn(format!(
+            "nginx: regex location {modifier} {path:?} must be converted manually"
+        ));
+    }
+    let host = (server.server_name.len() == 1).then(|| server.server_name[0].clone());
+    let mut root = None;
+    let mut index_files = false;
+    let mut modules = Vec::new();
+    for child in node.block() {
+        match child.name() {
+            "proxy_pass" => match child.arg(1) {
+                Some(url) => modules.push(Module::Rproxy {
+                    resolve: url.to_owned(),
+                }),
+                None => out.warn(format!("nginx: proxy_pass without url in {path:?}")),
+            },
+            // nginx appends the location to root while alias replaces it
+            "root" => {
+                root = child
+                    .arg(1)
+                    .map(|r| format!("{}/{}", r.trim_end_matches('/'), path.trim_matches('/')))
+            }
+            "alias" => root = child.arg(1).map(str::to_owned),
+            "autoindex" => index_files = child.arg(1) == Some("on"),
+            "return" => modules.extend(nginx_return(child, host.as_deref(), out)),
+            "auth_basic" | "auth_basic_user_file" | "index" => {}
+            name => out.warn(format!(
+                "nginx: unsupported location directive {name:?} in {path:?}"
+            )),
+        }
+    }
+    if root.is_some() || index_files {
+        modules.push(Module::Fileserver { root, index_files });
+    }
+    if modules.is_empty() && server.root.is_some() {
+        modules.push(Module::Fileserver {
+            root: None,
+            index_files: false,
+        });
+    }
+    for module in modules {
+        server.push(path, Component::Module(module));
+    }
+    if let Some(auth) = nginx_auth(node.block()) {
+        server.push(path, auth);
+    }
+}
+
+/// Translate Caddyfile statements.
+fn convert_caddy(nodes: &[Node], out: &mut Converted) {
+    let mut nodes = nodes;
+    // global options block
+    if nodes
+        .first()
+        .is_some_and(|n| n.args.is_empty() && n.block.is_some())
+    {
+        out.warn("caddy: global options are not converted");
+        nodes = &nodes[1..];
+    }
+    // a single site may be written without braces
+    if nodes.first().is_some_and(|n| n.block.is_none()) {
+        let site = &nodes[0];
+        out.servers
+            .push(caddy_site(&site.args, &nodes[1..], out).finish());
+        return;
+    }
+    for node in nodes {
+        match node.name() {
+            name if name.starts_with('(') => {
+                out.warn(format!("caddy: snippet {name} is not converted"))
+            }
+            _ => out
+                .servers
+                .push(caddy_site(&node.args, node.block(), out).finish()),
+        }
+    }
+}
+
+/// Translate Caddyfile path matcher into a location.
+///
+/// A leading path is only a matcher when the directive's required
+/// arguments follow it, matching how caddy disambiguates `redir /new`.
+fn caddy_matcher<'a>(
+    args: &'a [String],
+    required: usize,
+    out: &mut Converted,
+) -> (String, &'a [String]) {
+    match args.first().map(String::as_str) {
+        Some(m) if m.starts_with('/') && args.len() > required => {
+            let path = m.trim_end_matches('*').trim_end_matches('/');
+            let path = if path.is_empty() { "/" } else { path };
+            (path.to_owned(), &args[1..])
+        }
+        Some("*") => ("/".to_owned(), &args[1..]),
+        Some(m) if m.starts_with('@') => {
+            out.warn(format!(
+                "caddy: named matcher {m} must be converted manually"
+            ));
+            ("/".to_owned(), &args[1..])
+        }
+        _ => ("/".to_owned(), args),
+    }
+}
+
+/// Translate Caddyfile site block.
+fn caddy_site(addresses: &[String], block: &[Node], out: &mut Converted) -> Server {
+    let mut server = Server::default();
+    let mut tls = None;
+    for node in block {
+        if node.name() == "tls" {
+            match (node.arg(1), node.arg(2)) {
+                (Some(cert), Some(key)) => {
+                    tls = Some(Ssl {
+                        certificate: cert.to_owned(),
+                        certificate_key: key.to_owned(),
+                    })
+                }
+                _ => out.warn(format!("caddy: unsupported tls {:?}", &node.args[1..])),
+            }
+        }
+    }
+    for address in addresses {
+        let address = address.trim_end_matches(',');
+        let (scheme, rest) = match address.split_once("://") {
+            Some((scheme, rest)) => (Some(scheme), rest),
+            None => (None, address),
+        };
+        let (host, port) = match rest.rsplit_once(':') {
+            Some((host, port)) if port.parse::<u16>().is_ok() => (host, port.parse().ok()),
+            _ => (rest, None),
+        };
+        if !host.is_empty() {
+            server.server_name.push(host.to_owned());
+        }
+        let https = tls.is_some() && scheme != Some("http");
+        let port = match (port, scheme) {
+            (Some(port), _) => port,
+            (None, Some("http")) => 80,
+            (None, _) if https => 443,
+            (None, _) => {
+                out.warn(format!(
+                    "caddy: automatic https for {address:?} is not converted, listening on port 80"
+                ));
+                80
+            }
+        };
+        server.listen.push(Listen {
+            host: None,
+            port,
+            ssl: https.then(|| tls.clone()).flatten(),
+        });
+    }
+    caddy_directives(block, "/", &mut server, out);
+    server
+}
+
+/// Translate Caddyfile directives within a site or route block.
+fn caddy_directives(block: &[Node], location: &str, server: &mut Server, out: &mut Converted) {
+    for node in block {
+        let required = match node.name() {
+            "" | "tls" => continue,
+            "root" => {
+                server.root = node.args.last().filter(|_| node.args.len() > 1).cloned();
+                continue;
+            }
+            "reverse_proxy" | "redir" => 1,
+            _ => 0,
+        };
+        let (path, args) = caddy_matcher(&node.args[1..], required, out);
+        let path = match (location, path.as_str()) {
+            (location, "/") => location.to_owned(),
+            ("/", path) => path.to_owned(),
+            (location, path) => format!("{location}{path}"),
+        };
+        match node.name() {
+            "file_server" => {
+                let index_files = args.iter().any(|a| a == "browse");
+                server.push(
+                    &path,
+                    Component::Module(Module::Fileserver {
+                        root: None,
+                        index_files,
+                    }),
+                );
+            }
+            "reverse_proxy" => {
+                let Some(upstream) = args.first() else {
+                    out.warn(format!("caddy: reverse_proxy without upstream in {path:?}"));
+                    continue;
+                };
+                if args.len() > 1 {
+                    out.warn(format!(
+                        "caddy: only the first reverse_proxy upstream is converted in {path:?}"
+                    ));
+                }
+                let resolve = match upstream.contains("://") {
+                    true => upstream.to_owned(),
+                    false => format!("http://{}", upstream.trim_start_matches(':')),
+                };
+                let resolve = match resolve.starts_with("http://:") {
+                    true => resolve.replacen("http://:", "http://localhost:", 1),
+                    false => resolve,
+                };
+                server.push(&path, Component::Module(Module::Rproxy { resolve }));
+            }
+            "redir" => {
+                let Some(to) = args.first() else {
+                    out.warn(format!("caddy: redir without target in {path:?}"));
+                    continue;
+                };
+                let status_code = match args.get(1).map(String::as_str) {
+                    None | Some("temporary") => 302,
+                    Some("permanent") => 301,
+                    Some(code) => code.parse().unwrap_or(302),
+                };
+                let host = (server.server_name.len() == 1).then(|| server.server_name[0].clone());
+                let (redirect, preserve_path) = redirect_target(to, &["{uri}"], host.as_deref());
+                if redirect.contains('{') {
+                    out.warn(format!("caddy: unresolved placeholders in redirect {to:?}"));
+                }
+                let module = Module::Redirect {
+                    redirect,
+                    status_code,
+                    preserve_path,
+                };
+                server.push(&path, Component::Module(module));
+            }
+            "respond" => {
+                let status_code = args.iter().find_map(|a| a.parse().ok()).unwrap_or(200);
+                let body = args.first().filter(|a| a.parse::<u16>().is_err()).cloned();
+                let module = Module::Static { body, status_code };
+                server.push(&path, Component::Module(module));
+            }
+            "basicauth" | "basic_auth" => {
+                let file = caddy_htpasswd(node.block(), server, out);
+                let auth = Component::Middleware(Middleware::BasicAuth {
+                    htpasswd: vec![file.to_string_lossy().into_owned()],
+                });
+                match path.as_str() {
+                    "/" => server.middleware.push(auth),
+                    path => server.push(path, auth),
+                }
+            }
+            "handle" | "handle_path" | "route" => match &node.block {
+                Some(block) => caddy_directives(block, &path, server, out),
+                None => out.warn(format!("caddy: {} without block", node.name())),
+            },
+            name => out.warn(format!("caddy: unsupported directive {name:?}")),
+        }
+    }
+}
+
+/// Collect Caddyfile basic-auth accounts into a htpasswd file.
+fn caddy_htpasswd(block: &[Node], server: &Server, out: &mut Converted) -> PathBuf {
+    let mut content = String::new();
+    for account in block {
+        let (Some(user), Some(hash)) = (account.arg(0), account.arg(1)) else {
+            continue;
+        };
+        // older caddy versions require base64 encoded bcrypt hashes
+        let hash = match hash.starts_with("$2") {
+            true => hash.to_owned(),
+            false => BASE64_STANDARD
+                .decode(hash)
+                .ok()
+                .and_then(|h| String::from_utf8(h).ok())
+                .unwrap_or_else(|| hash.to_owned()),
+        };
+        content.push_str(&format!("{user}:{hash}\n"));
+    }
+    let name = server
+        .server_name
+        .first()
+        .map(|name| name.replace('*', "_wildcard"))
+        .unwrap_or_else(|| "default".to_owned());
+    let file = out.dir.join(format!("{name}-{}.htpasswd", out.files.len()));
+    out.files.push((file.clone(), content));
+    file
+}
+
+/// Detect input format from the filename.
+fn detect_format(path: &Path) -> ConvertFormat {
+    let name = path
+        .file_name()
+        .map(|name| name.to_string_lossy().to_lowercase())
+        .unwrap_or_default();
+    match name.contains("caddy") {
+        true => ConvertFormat::Caddy,
+        false => ConvertFormat::Nginx,
+    }
+}
+
+/// Convert nginx or Caddyfile configuration to bob config and exit.
+pub fn execute(cmd: ConvertCmd) -> Result<()> {
+    let src = std::fs::read_to_string(&cmd.input)
+        .with_context(|| format!("failed to read {:?}", cmd.input))?;
+    let format = cmd.format.unwrap_or_else(|| detect_format(&cmd.input));
+    let caddy = matches!(format, ConvertFormat::Caddy);
+    let tokens = tokenize(&src, caddy)?;
+    let nodes = parse(&mut tokens.into_iter(), false)
+        .with_context(|| format!("failed to parse {:?}", cmd.input))?;
+
+    let mut out = Converted {
+        dir: cmd
+            .output
+            .as_deref()
+            .and_then(Path::parent)
+            .map(Path::to_path_buf)
+            .unwrap_or_default(),
+        ..Default::default()
+    };
+    match format {
+        ConvertFormat::Nginx => convert_nginx(&nodes, &mut out),
+        ConvertFormat::Caddy => convert_caddy(&nodes, &mut out),
+    }
+    if out.servers.is_empty() {
+        return Err(anyhow!("no server blocks found in {:?}", cmd.input));
+    }
+
+    for (path, content) in out.files.iter() {
+        std::fs::write(path, content).with_context(|| format!("failed to write {path:?}"))?;
+        out.warnings
+            .push(format!("basic-auth accounts written to {path:?}"));
+    }
+
+    let yaml = serde_yaml::to_string(&out.servers).context("failed to serialize config")?;
+    if let Err(err) = serde_yaml::from_str::<Vec<ServerConfig>>(&yaml) {
+        out.warn(format!(
+            "generated config does not load with this build: {err}"
+        ));
+    }
+    let mut config = String::new();
+    for warning in out.warnings.iter() {
+        eprintln!("warning: {warning}");
+        config.push_str(&format!("# warning: {warning}\n"));
+    }
+    config.push_str(&yaml);
+    match &cmd.output {
+        Some(path) => {
+            std::fs::write(path, config).with_context(|| format!("failed to write {path:?}"))?
+        }
+        None => std::io::stdout()
+            .write_all(config.as_bytes())
+            .context("failed to write config")?,
+    }
+    Ok(())
+}
@@ -0,0 +1,111 @@
+//! Process Log Initialization
+//!
+//! Configures the process-wide [`env_logger`] instance from the
+//! loaded configuration. `BOB_LOG` filters are applied last and
+//! override any configured levels.
+
+use std::io::Write;
+
+use anyhow::{Context, Result};
+use bob_core::config::{LogColor, LogFormat, LogTarget, ProcessLogCfg, ServerConfig};
+use env_logger::{Builder, Target, WriteStyle};
+
+/// Environment variable overriding configured log filters.
+const LOG_ENV: &str = "BOB_LOG";
+
+/// Initialize process logger using the first enabled process log config.
+pub fn init(config: &[ServerConfig]) -> Result<()> {
+    let default = ProcessLogCfg::default();
+    let cfg = config
+        .iter()
+        .filter(|cfg| !cfg.disable)
+        .find_map(|cfg| cfg.logging.process.as_ref())
+        .unwrap_or(&default);
+
+    let mut builder = Builder::new();
+    builder.filter(
+        None,
+        cfg.level
+            .as_ref()
+            .map(|l| l.0)
+            .unwrap_or(log::LevelFilter::Warn),
+    );
+    if !cfg.modules.contains_key("bob") {
+        builder.filter(Some("bob"), log::LevelFilter::Info);
+    }
+    if !cfg.modules.contains_key("actix_web::middleware::logger") {
+        builder.filter(
+            Some("actix_web::middleware::logger"),
+            log::LevelFilter::Info,
+        );
+    }
+    for (module, level) in cfg.modules.iter() {
+        builder.filter(Some(module), level.0);
+    }
+    builder.parse_env(LOG_ENV);
+
+    builder.target(match &cfg.target {
+        LogTarget::Stderr => Target::Stderr,
+        LogTarget::Stdout => Target::Stdout,
+        LogTarget::File(path) => {
+            let file = std::fs::OpenOptions::new()
+                .create(true)
+                .append(true)
+                .open(path)
+                .with_context(|| format!("failed to open log file {path:?}"))?;
+            Target::Pipe(Box::new(file))
+        }
+    });
+    builder.write_style(match cfg.color {
+        LogColor::Auto => WriteStyle::Auto,
+        LogColor::Always => WriteStyle::Always,
+        LogColor::Never => WriteStyle::Never,
+    });
+
+    let show_module = cfg.show_module;
+    match cfg.format {
+        LogFormat::Pretty => {
+            builder.format_target(show_module);
+        }
+        LogFormat::Compact => {
+            builder.format_timestamp(None).format_target(show_module);
+        }
+        LogFormat::Json => {
+            builder.format(move |buf, record| {
+                write!(
+                    buf,
+                    "{{\"time\":\"{}\",\"level\":\"{}\"",
+                    buf.timestamp(),
+                    record.level()
+                )?;
+                if show_module {
+                    write!(buf, ",\"module\":\"{}\"", escape(record.target()))?;
+                }
+                writeln!(
+                    buf,
+                    ",\"message\":\"{}\"}}",
+                    escape(&record.args().to_string())
+                )
+            });
+        }
+    }
+
+    builder.try_init().context("failed to initialize logger")
+}
+
+/// Escape string for inclusion within a json string literal.
+fn escape(s: &str) -> String {
+    let mut out = String::with_capacity(s.len());
+    for c in s.chars() {
+        match c {
+            '"' => out.push_str("\\\""),
+            '\\' => out.push_str("\\\\"),
+            '\n' => out.push_str("\\n"),
+            '\r' => out.push_str("\\r"),
+            '\t' => out.push_str("\\t"),
+            c if (c as u32) < 0x20 => out.push_str(&format!("\\u{:04x}", c as u32)),
+            c => out.push(c),
+        }
+    }
+    out
+}
@@ -1,16 +1,31 @@
 #![doc = include_str!("../../README.md")]
 #![cfg_attr(feature = "doc", feature(doc_cfg))]
 
-use actix_chain::{Chain, Link};
-use actix_web::{App, HttpServer, middleware::Logger};
+use std::{pin::Pin, task::Poll};
+
+use actix_web::dev::Server;
 use anyhow::{Context, Result};
+use bob_core::{
+    config::{self, ServerConfig},
+    hooks, lowest, services, spawn_server,
+};
 use clap::Parser;
 
+mod admin;
+#[cfg(feature = "cert")]
+mod cert;
 mod cli;
-mod config;
-mod tls;
-
-use crate::config::{ServerConfig, Spec};
+mod control;
+#[cfg(feature = "convert")]
+mod convert;
+mod logging;
+#[cfg(feature = "replay")]
+mod replay;
+mod route_test;
+#[cfg(feature = "schema")]
+mod schema;
+#[cfg(feature = "share")]
+mod share;
 
 //TODO: simple bot detector/challenger system? - anubis lite
 
@@ -22,126 +37,235 @@ use crate::config::{ServerConfig, Spec};
 // - fileserver [DONE]
 // - revproxy   [DONE]
 // - fastcgi    [DONE]
-// - static
-// - redirect
+// - static     [DONE]
+// - redirect   [DONE]
 //  (all the modules basically...)
 //  (fileserver should auto-open browser when tty)
 //  (info logging should probably be enabled by default)
 
-//TODO: hot-reload option for when config changes?
 //TODO: daemonize option?
 
-#[inline]
-fn logger(config: &ServerConfig) -> Logger {
-    #[cfg(not(feature = "ipware"))]
-    let log = Logger::default();
-
-    #[cfg(feature = "ipware")]
-    let log = match config.logging.use_ipware.unwrap_or(true) {
-        false => Logger::default(),
-        true => Logger::new(r#"%{ip}xo "%r" %s %b "%{Referer}i" "%{User-Agent}i" %T"#)
-            .custom_response_replace("ip", |res| {
-                res.request()
-                    .peer_addr()
-                    .map(|r| r.ip().to_string())
-                    .unwrap_or_default()
-            }),
+#[actix_web::main]
+async fn main() -> Result<()> {
+    let cli = bob_cli::Cli::parse();
+    #[cfg(feature = "replay")]
+    let cli = match cli.command {
+        Some(bob_cli::Command::Replay(cmd)) => return replay::run(cmd).await,
+        Some(bob_cli::Command::Diff(cmd)) => return replay::diff(cmd).await,
+        command => bob_cli::Cli { command, ..cli },
     };
+    let (mut config, overrides) = cli::build_config(cli)?;
+    logging::init(&config)?;
+    config::connect_config(&config).await?;
+    let mut admin = admin::spawn(&config)?;
+    #[cfg(unix)]
+    actix_web::rt::spawn(reload_signal());
+    let pid_file = control::pid_file(&config);
+    if let Some(path) = pid_file.as_ref() {
+        std::fs::write(path, format!("{}\n", std::process::id()))
+            .with_context(|| format!("failed to write pid file {path:?}"))?;
+    }
 
-    log.log_level(
-        config
-            .logging
-            .log_level
-            .clone()
-            .map(|l| l.0)
-            .unwrap_or(log::Level::Info),
-    )
-}
+    let mut signal = std::pin::pin!(shutdown_signal());
+    let mut reloaded = false;
+    let mut hash = config::config_hash(&config);
+    let mut server = Box::pin(spawn_server(&config, hash.clone())?);
+    loop {
+        log::info!("loaded configuration (hash: {hash})");
+        admin::publish(&config, &hash);
 
-/// Assemble [`actix_chain::Chain`] from server configuration instance.
-fn assemble_chain(config: &ServerConfig) -> Chain {
-    let mut chain = Chain::default();
-    chain = config
-        .server_name
-        .clone()
-        .into_iter()
-        .fold(chain, |chain, domain| chain.guard(domain));
-
-    let spec = Spec { config };
-    for directive in config.directives.iter() {
-        let location = directive.location.clone().unwrap_or_default();
-        let prefix = location.trim_start_matches('/');
-
-        let link: Link = directive
-            .construct
-            .iter()
-            .fold(Chain::new(prefix), |chain, c| c.apply(chain, &spec))
-            .into();
-
-        chain.push_link(link);
-    }
+        let handle = server.handle();
+        let paused = services::drain::paused_listeners();
+        log::info!("server listening and ready!");
+        if reloaded {
+            hooks::fire(&config, hooks::Event::PostReload).await;
+        }
 
-    chain = config
-        .middleware
-        .iter()
-        .fold(chain, |chain, m| m.wrap(chain, &spec));
-    if config.sanitize_errors.unwrap_or(true) {
-        chain = chain.wrap(actix_sanitize::Sanitizer::default());
-    }
-    if !config.logging.disable {
-        chain = chain.wrap(logger(config));
+        let next = loop {
+            let drain = overrides.drain_then_exit();
+            match next_action(signal.as_mut(), server.as_mut(), drain).await {
+                Action::Exited(result) => return result.context("server spawn failed"),
+                Action::Drain => {
+                    log::info!("drain requested. draining connections before exit");
+                    hooks::fire(&config, hooks::Event::PreShutdown).await;
+                    if let Some(result) = drain_connections(&config, server.as_mut()).await {
+                        return result.context("server spawn failed");
+                    }
+                    actix_web::rt::spawn(handle.stop(true));
+                    let result = server.await.context("server spawn failed");
+                    if let Some(path) = pid_file.as_ref() {
+                        let _ = std::fs::remove_file(path);
+                    }
+                    return result;
+                }
+                Action::Shutdown(graceful) => {
+                    log::info!("shutdown signal received. stopping server");
+                    hooks::fire(&config, hooks::Event::PreShutdown).await;
+                    actix_web::rt::spawn(handle.stop(graceful));
+                    let result = server.await.context("server spawn failed");
+                    if let Some(path) = pid_file.as_ref() {
+                        let _ = std::fs::remove_file(path);
+                    }
+                    return result;
+                }
+                Action::Reload => match reload_config(&config, &overrides).await {
+                    Ok(next) => break (next, true),
+                    Err(err) => log::error!("configuration reload failed: {err:?}"),
+                },
+                Action::Respawn => match spawn_server(&config, hash.clone()) {
+                    Ok(next) => break ((config.clone(), hash.clone(), next), false),
+                    Err(err) => {
+                        log::error!("failed to apply listener changes: {err:?}");
+                        services::drain::set_paused(paused.clone());
+                    }
+                },
+            }
+        };
+
+        // listeners of the new server are bound before the old ones close
+        let ((next, next_hash, next_server), reload) = next;
+        match reload {
+            true => log::info!("configuration reloaded. draining previous server"),
+            false => log::info!("listeners changed. draining previous server"),
+        }
+        let previous = std::mem::replace(&mut server, Box::pin(next_server));
+        actix_web::rt::spawn(async move {
+            handle.stop(true).await;
+            if let Err(err) = previous.await {
+                log::error!("previous server shutdown failed: {err:?}");
+            }
+        });
+        if reload {
+            admin = admin::reconfigure(admin.take(), &next).await;
+        }
+        config = next;
+        hash = next_hash;
+        reloaded = reload;
     }
+}
 
-    chain
+/// Run pre-reload hooks then read, connect and bind the new configuration.
+///
+/// The running server keeps serving until the returned server is bound.
+async fn reload_config(
+    config: &[ServerConfig],
+    overrides: &cli::Overrides,
+) -> Result<(cli::Config, String, Server)> {
+    hooks::fire(config, hooks::Event::PreReload).await;
+    let next = overrides.reload()?;
+    config::connect_config(&next).await?;
+    let hash = config::config_hash(&next);
+    let server = spawn_server(&next, hash.clone())?;
+    Ok((next, hash, server))
 }
 
-#[actix_web::main]
-async fn main() -> Result<()> {
-    env_logger::builder()
-        .format_target(false)
-        .filter(None, log::LevelFilter::Warn)
-        .filter(Some("bob"), log::LevelFilter::Info)
-        .filter(
-            Some("actix_web::middleware::logger"),
-            log::LevelFilter::Info,
-        )
-        .parse_env("BOB_LOG")
-        .init();
+/// Stop accepting connections and wait for the drain period.
+///
+/// Readiness fails and connections accepted from now on are refused
+/// while existing connections finish. Returns early if the server exits.
+async fn drain_connections(
+    config: &[ServerConfig],
+    mut server: Pin<&mut Server>,
+) -> Option<std::io::Result<()>> {
+    let period = lowest(config, |c| c.runtime.drain_period.as_ref().map(|d| d.0))
+        .unwrap_or(std::time::Duration::from_secs(15));
+    services::drain::set_draining(true);
+    services::drain::refuse_new_connections();
 
-    let cli = bob_cli::Cli::parse();
-    let config = cli::build_config(cli)?;
-
-    let sconfig = config.clone();
-    let mut server = HttpServer::new(move || {
-        sconfig
-            .iter()
-            .map(assemble_chain)
-            .fold(App::new(), |app, cfg| app.service(cfg))
-    });
-
-    server = config
-        .iter()
-        .filter(|cfg| !cfg.disable)
-        .flat_map(|cfg| cfg.listen.iter())
-        .filter(|listen| listen.ssl.is_none())
-        .map(|addr| addr.address())
-        .try_fold(server, |s, addr| {
-            log::info!("spawning listener {addr:?}");
-            s.bind(addr)
-        })?;
-
-    let sslcfg = tls::server::build_tls_config(&config)?;
-    server = config
-        .iter()
-        .filter(|cfg| !cfg.disable)
-        .flat_map(|cfg| cfg.listen.iter())
-        .filter(|listen| listen.ssl.is_some())
-        .map(|addr| addr.address())
-        .try_fold(server, |s, addr| {
-            log::info!("spawning tls listener {addr:?}");
-            s.bind_rustls_0_23(addr, sslcfg.clone())
-        })?;
-
-    log::info!("server listening and ready!");
-    server.run().await.context("server spawn failed")
+    log::info!("waiting {period:?} for connections to drain");
+    let mut sleep = std::pin::pin!(actix_web::rt::time::sleep(period));
+    std::future::poll_fn(|cx| {
+        if let Poll::Ready(result) = server.as_mut().poll(cx) {
+            return Poll::Ready(Some(result));
+        }
+        sleep.as_mut().poll(cx).map(|_| None)
+    })
+    .await
+}
+
+/// Next action taken by the main server loop.
+enum Action {
+    Reload,
+    Respawn,
+    Drain,
+    Shutdown(bool),
+    Exited(std::io::Result<()>),
+}
+
+/// Wait for shutdown signal, admin request or server exit.
+async fn next_action(
+    mut signal: Pin<&mut impl Future<Output = bool>>,
+    mut server: Pin<&mut Server>,
+    drain: bool,
+) -> Action {
+    let mut reload = std::pin::pin!(admin::reload_requested());
+    let mut respawn = std::pin::pin!(admin::respawn_requested());
+    let mut stop = std::pin::pin!(admin::stop_requested());
+    let mut quit = std::pin::pin!(admin::drain_requested());
+    std::future::poll_fn(|cx| {
+        if let Poll::Ready(graceful) = signal.as_mut().poll(cx) {
+            return Poll::Ready(match graceful && drain {
+                true => Action::Drain,
+                false => Action::Shutdown(graceful),
+            });
+        }
+        if let Poll::Ready(result) = server.as_mut().poll(cx) {
+            return Poll::Ready(Action::Exited(result));
+        }
+        if reload.as_mut().poll(cx).is_ready() {
+            return Poll::Ready(Action::Reload);
+        }
+        if respawn.as_mut().poll(cx).is_ready() {
+            return Poll::Ready(Action::Respawn);
+        }
+        if stop.as_mut().poll(cx).is_ready() {
+            return Poll::Ready(Action::Shutdown(true));
+        }
+        if quit.as_mut().poll(cx).is_ready() {
+            return Poll::Ready(Action::Drain);
+        }
+        Poll::Pending
+    })
+    .await
+}
+
+/// Request configuration reload whenever SIGHUP is received.
+#[cfg(unix)]
+async fn reload_signal() {
+    use actix_web::rt::signal::unix::{SignalKind, signal};
+    let mut hangup = signal(SignalKind::hangup()).expect("failed to bind SIGHUP");
+    while hangup.recv().await.is_some() {
+        log::info!("reload signal received");
+        admin::request_reload();
+    }
+}
+
+/// Wait for shutdown signal and report if shutdown should be graceful.
+async fn shutdown_signal() -> bool {
+    // matches actix-web defaults: SIGTERM stops gracefully while
+    // SIGINT and SIGQUIT force an immediate shutdown.
+    let mut ctrl_c = std::pin::pin!(actix_web::rt::signal::ctrl_c());
+
+    #[cfg(unix)]
+    {
+        use actix_web::rt::signal::unix::{SignalKind, signal};
+        let mut term = signal(SignalKind::terminate()).expect("failed to bind SIGTERM");
+        let mut quit = signal(SignalKind::quit()).expect("failed to bind SIGQUIT");
+        std::future::poll_fn(|cx| {
+            if term.poll_recv(cx).is_ready() {
+                return Poll::Ready(true);
+            }
+            if quit.poll_recv(cx).is_ready() || ctrl_c.as_mut().poll(cx).is_ready() {
+                return Poll::Ready(false);
+            }
+            Poll::Pending
+        })
+        .await
+    }
+
+    #[cfg(not(unix))]
+    {
+        let _ = ctrl_c.await;
+        false
+    }
 }
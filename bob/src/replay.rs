@@ -0,0 +1,405 @@
+//! HTTP Request Replay Tool
+//!
+//! Reads HAR archives or raw http request dumps and replays them
+//! against a target url or a server started from a local config
+//! for reproducing incidents and regression testing config changes.
+//! The same traffic can also be sent to two targets and the
+//! responses compared to validate a migration before cutover.
+
+use std::{
+    collections::{BTreeMap, BTreeSet},
+    path::{Path, PathBuf},
+    sync::Arc,
+    time::Instant,
+};
+
+use actix_web::{http::Method, web::Bytes};
+use anyhow::{Context, Result, anyhow};
+use bob_cli::{DiffCmd, ReplayCmd};
+use bob_core::{config, tls::client::build_tls_config};
+use sha2::{Digest, Sha256};
+use tokio::sync::Semaphore;
+
+use crate::cli::Config;
+
+/// Request headers never copied from captured requests.
+const SKIP_HEADERS: &[&str] = &[
+    "connection",
+    "content-length",
+    "keep-alive",
+    "transfer-encoding",
+    "upgrade",
+];
+
+/// Response headers always excluded when comparing responses.
+const VOLATILE_HEADERS: &[&str] = &["date"];
+
+/// Captured request ready to be replayed.
+#[derive(Debug)]
+struct Captured {
+    method: Method,
+    path: String,
+    headers: Vec<(String, String)>,
+    body: Bytes,
+}
+
+/// Result of a single replayed request.
+struct Outcome {
+    status: Option<u16>,
+    headers: BTreeMap<String, Vec<String>>,
+    size: usize,
+    digest: String,
+    elapsed: std::time::Duration,
+    error: Option<String>,
+}
+
+/// Run replay command until all requests are complete.
+pub async fn run(cmd: ReplayCmd) -> Result<()> {
+    let requests = read_inputs(&cmd.input)?;
+
+    let mut verify_ssl = !cmd.insecure;
+    let (target, server) = match (cmd.target.as_ref(), cmd.config.as_ref()) {
+        (Some(target), _) => (target.0.to_string(), None),
+        (None, Some(path)) => {
+            let config = config::read_config(path)?;
+            config::prepare_config(&config)?;
+            crate::logging::init(&config)?;
+            config::connect_config(&config).await?;
+            let target = local_target(&config)?;
+            let server = bob_core::spawn_server(&config, config::config_hash(&config))?;
+            // local listeners are reached by address rather than server name
+            verify_ssl = false;
+            (target, Some(server))
+        }
+        (None, None) => return Err(anyhow!("replay requires a target or config")),
+    };
+    let target = target.trim_end_matches('/').to_owned();
+    let handle = server.as_ref().map(|s| s.handle());
+    if let Some(server) = server {
+        actix_web::rt::spawn(server);
+    }
+
+    let client = build_client(cmd.timeout.0, verify_ssl);
+    let permits = Arc::new(Semaphore::new(cmd.concurrency.max(1)));
+    let total = requests.len();
+    let mut tasks = Vec::with_capacity(total);
+    for request in requests {
+        let permit = Arc::clone(&permits).acquire_owned().await?;
+        let client = client.clone();
+        let url = format!("{target}{}", request.path);
+        tasks.push(actix_web::rt::spawn(async move {
+            let outcome = replay(&client, &url, &request).await;
+            drop(permit);
+            (request, outcome)
+        }));
+    }
+
+    let mut failed = 0;
+    for task in tasks {
+        let (request, outcome) = task.await?;
+        let elapsed = outcome.elapsed.as_millis();
+        match (outcome.status, outcome.error) {
+            (Some(status), _) => println!(
+                "{status} {} {} {}B {elapsed}ms",
+                request.method, request.path, outcome.size
+            ),
+            (None, error) => {
+                failed += 1;
+                let error = error.unwrap_or_default();
+                println!(
+                    "ERR {} {} {elapsed}ms {error}",
+                    request.method, request.path
+                );
+            }
+        }
+    }
+    println!("replayed {total} requests ({failed} failed)");
+
+    if let Some(handle) = handle {
+        handle.stop(true).await;
+    }
+    match failed {
+        0 => Ok(()),
+        n => Err(anyhow!("{n} requests failed")),
+    }
+}
+
+/// Send requests to two targets and report response differences.
+pub async fn diff(cmd: DiffCmd) -> Result<()> {
+    let requests = read_inputs(&cmd.input)?;
+    let baseline = cmd.baseline.0.to_string().trim_end_matches('/').to_owned();
+    let candidate = cmd.candidate.0.to_string().trim_end_matches('/').to_owned();
+    let ignore: BTreeSet<String> = VOLATILE_HEADERS
+        .iter()
+        .map(|h| h.to_string())
+        .chain(cmd.ignore_header.iter().map(|h| h.to_ascii_lowercase()))
+        .collect();
+
+    let client = build_client(cmd.timeout.0, !cmd.insecure);
+    let permits = Arc::new(Semaphore::new(cmd.concurrency.max(1)));
+    let total = requests.len();
+    let mut tasks = Vec::with_capacity(total);
+    for request in requests {
+        let permit = Arc::clone(&permits).acquire_owned().await?;
+        let client = client.clone();
+        let left = format!("{baseline}{}", request.path);
+        let right = format!("{candidate}{}", request.path);
+        tasks.push(actix_web::rt::spawn(async move {
+            let left = replay(&client, &left, &request).await;
+            let right = replay(&client, &right, &request).await;
+            drop(permit);
+            (request, left, right)
+        }));
+    }
+
+    let mut differ = 0;
+    for task in tasks {
+        let (request, left, right) = task.await?;
+        let changes = compare(&left, &right, &ignore, cmd.bodies);
+        if changes.is_empty() {
+            continue;
+        }
+        differ += 1;
+        println!("DIFF {} {}", request.method, request.path);
+        changes.iter().for_each(|change| println!("  {change}"));
+    }
+    println!("compared {total} requests ({differ} differ)");
+
+    match differ {
+        0 => Ok(()),
+        n => Err(anyhow!("{n} responses differ")),
+    }
+}
+
+/// Describe differences between baseline and candidate responses.
+fn compare(
+    left: &Outcome,
+    right: &Outcome,
+    ignore: &BTreeSet<String>,
+    bodies: bool,
+) -> Vec<String> {
+    let mut changes = vec![];
+    let show = |o: &Outcome| match (o.status, o.error.as_ref()) {
+        (Some(status), _) => status.to_string(),
+        (None, error) => format!("error ({})", error.map(|e| e.as_str()).unwrap_or("-")),
+    };
+    if left.status != right.status || left.error.is_some() || right.error.is_some() {
+        changes.push(format!("status: {} != {}", show(left), show(right)));
+        return changes;
+    }
+
+    let names: BTreeSet<&String> = left.headers.keys().chain(right.headers.keys()).collect();
+    for name in names.into_iter().filter(|name| !ignore.contains(*name)) {
+        let (a, b) = (left.headers.get(name), right.headers.get(name));
+        if a != b {
+            let show = |v: Option<&Vec<String>>| match v {
+                Some(v) => format!("{:?}", v.join(", ")),
+                None => "-".to_owned(),
+            };
+            changes.push(format!("header {name}: {} != {}", show(a), show(b)));
+        }
+    }
+    if bodies && left.digest != right.digest {
+        changes.push(format!(
+            "body: sha256 {} ({}B) != {} ({}B)",
+            left.digest, left.size, right.digest, right.size
+        ));
+    }
+    changes
+}
+
+/// Build http client used to replay requests.
+fn build_client(timeout: std::time::Duration, verify_ssl: bool) -> awc::Client {
+    let mut connector = awc::Connector::new();
+    if !verify_ssl {
+        connector = connector.rustls_0_23(Arc::new(build_tls_config(false)));
+    }
+    awc::ClientBuilder::new()
+        .connector(connector)
+        .no_default_headers()
+        .timeout(timeout)
+        .finish()
+}
+
+/// Send a single captured request and drain its response.
+async fn replay(client: &awc::Client, url: &str, request: &Captured) -> Outcome {
+    let start = Instant::now();
+    let mut req = client.request(request.method.clone(), url);
+    for (name, value) in request.headers.iter() {
+        req = req.append_header((name.as_str(), value.as_str()));
+    }
+    let result = async {
+        let mut res = req
+            .send_body(request.body.clone())
+            .await
+            .map_err(|err| err.to_string())?;
+        let body = res
+            .body()
+            .limit(usize::MAX)
+            .await
+            .map_err(|err| err.to_string())?;
+        let mut headers: BTreeMap<String, Vec<String>> = BTreeMap::new();
+        for (name, value) in res.headers().iter() {
+            let value = String::from_utf8_lossy(value.as_bytes()).into_owned();
+            headers
+                .entry(name.as_str().to_owned())
+                .or_default()
+                .push(value);
+        }
+        Ok::<_, String>((res.status().as_u16(), headers, body))
+    }
+    .await;
+    let elapsed = start.elapsed();
+    match result {
+        Ok((status, headers, body)) => Outcome {
+            status: Some(status),
+            headers,
+            size: body.len(),
+            digest: Sha256::digest(&body)
+                .iter()
+                .map(|b| format!("{b:02x}"))
+                .collect(),
+            elapsed,
+            error: None,
+        },
+        Err(error) => Outcome {
+            status: None,
+            headers: BTreeMap::new(),
+            size: 0,
+            digest: String::new(),
+            elapsed,
+            error: Some(error),
+        },
+    }
+}
+
+/// Read captured requests from all input files.
+fn read_inputs(paths: &[PathBuf]) -> Result<Vec<Captured>> {
+    let mut requests = vec![];
+    for path in paths.iter() {
+        let found = read_input(path).with_context(|| format!("failed to read {path:?}"))?;
+        requests.extend(found);
+    }
+    if requests.is_empty() {
+        return Err(anyhow!("no requests found to replay"));
+    }
+    Ok(requests)
+}
+
+/// Determine base url of the first enabled listener within config.
+fn local_target(config: &Config) -> Result<String> {
+    let listen = config
+        .iter()
+        .filter(|cfg| !cfg.disable)
+        .flat_map(|cfg| cfg.listen.iter())
+        .next()
+        .context("config has no listeners")?;
+    let scheme = if listen.ssl.is_some() {
+        "https"
+    } else {
+        "http"
+    };
+    let host = match listen.host() {
+        "0.0.0.0" => "127.0.0.1",
+        "::" | "[::]" => "[::1]",
+        host => host,
+    };
+    Ok(format!("{scheme}://{host}:{}", listen.port))
+}
+
+/// Read captured requests from a HAR archive or raw request dump.
+fn read_input(path: &Path) -> Result<Vec<Captured>> {
+    let data = std::fs::read(path)?;
+    let is_har = path.extension().is_some_and(|ext| ext == "har")
+        || data.iter().find(|b| !b.is_ascii_whitespace()) == Some(&b'{');
+    match is_har {
+        true => read_har(&data),
+        false => read_raw(&data).map(|r| vec![r]),
+    }
+}
+
+/// Parse all request entries from a HAR archive.
+fn read_har(data: &[u8]) -> Result<Vec<Captured>> {
+    let har: serde_json::Value = serde_json::from_slice(data).context("invalid har file")?;
+    let entries = har["log"]["entries"]
+        .as_array()
+        .context("har missing log entries")?;
+    entries
+        .iter()
+        .enumerate()
+        .map(|(n, entry)| {
+            let request = &entry["request"];
+            let method = request["method"].as_str().unwrap_or("GET");
+            let url = request["url"]
+                .as_str()
+                .with_context(|| format!("entry[{n}] missing url"))?;
+            let uri: actix_web::http::Uri = url
+                .parse()
+                .with_context(|| format!("entry[{n}] invalid url"))?;
+            let mut headers: Vec<(String, String)> = request["headers"]
+                .as_array()
+                .into_iter()
+                .flatten()
+                .filter_map(|h| Some((h["name"].as_str()?, h["value"].as_str()?)))
+                .filter(|(name, _)| !name.starts_with(':'))
+                .filter(|(name, _)| keep_header(name))
+                .map(|(name, value)| (name.to_owned(), value.to_owned()))
+                .collect();
+            if let Some(authority) = uri.authority()
+                && !headers.iter().any(|(k, _)| k.eq_ignore_ascii_case("host"))
+            {
+                headers.push(("host".to_owned(), authority.to_string()));
+            }
+            let body = request["postData"]["text"].as_str().unwrap_or_default();
+            Ok(Captured {
+                method: Method::from_bytes(method.as_bytes())
+                    .with_context(|| format!("entry[{n}] invalid method"))?,
+                path: uri
+                    .path_and_query()
+                    .map(|p| p.to_string())
+                    .unwrap_or_else(|| "/".to_owned()),
+                headers,
+                body: Bytes::copy_from_slice(body.as_bytes()),
+            })
+        })
+        .collect()
+}
+
+/// Parse a single raw HTTP/1.x request dump.
+fn read_raw(data: &[u8]) -> Result<Captured> {
+    let (head, body) = split_head(data);
+    let head = std::str::from_utf8(head).context("request head is not utf-8")?;
+    let mut lines = head.lines();
+    let line = lines.next().context("missing request line")?;
+    let mut parts = line.split_whitespace();
+    let method = parts.next().context("missing request method")?;
+    let path = parts.next().context("missing request path")?;
+    let headers = lines
+        .filter_map(|line| line.split_once(':'))
+        .map(|(k, v)| (k.trim().to_owned(), v.trim().to_owned()))
+        .filter(|(k, _)| keep_header(k))
+        .collect();
+    Ok(Captured {
+        method: Method::from_bytes(method.as_bytes()).context("invalid request method")?,
+        path: path.to_owned(),
+        headers,
+        body: Bytes::copy_from_slice(body),
+    })
+}
+
+/// Split raw request into head and body at the first blank line.
+fn split_head(data: &[u8]) -> (&[u8], &[u8]) {
+    let crlf = data.windows(4).position(|w| w == b"\r\n\r\n");
+    let lf = data.windows(2).position(|w| w == b"\n\n");
+    match (crlf, lf) {
+        (Some(c), Some(l)) if l < c => (&data[..l], &data[l + 2..]),
+        (Some(c), _) => (&data[..c], &data[c + 4..]),
+        (None, Some(l)) => (&data[..l], &data[l + 2..]),
+        (None, None) => (data, &[]),
+    }
+}
+
+#[inline]
+fn keep_header(name: &str) -> bool {
+    !SKIP_HEADERS.iter().any(|h| h.eq_ignore_ascii_case(name))
+}
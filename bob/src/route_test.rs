@@ -0,0 +1,165 @@
+//! Offline Route Testing
+//!
+//! Reports which server, directive, modules and middleware handle a
+//! request by serving it through the application assembled with
+//! [`bob_core::assemble_app`] within [`actix_web::test`], without
+//! binding any sockets.
+
+use actix_web::{
+    HttpMessage,
+    dev::Service,
+    http::{Method, Uri, header},
+    test::{self, TestRequest},
+};
+use anyhow::{Context, Result, anyhow};
+use bob_cli::RouteTestCmd;
+use bob_core::{
+    config::{Component, ServerConfig},
+    services::trace::{Hop, Trace},
+};
+
+/// Name of the configured module or middleware variant.
+fn variant(debug: String) -> String {
+    debug
+        .split(['(', ' ', '{'])
+        .next()
+        .unwrap_or_default()
+        .to_owned()
+}
+
+/// Report how the configuration routes the request and exit.
+pub fn execute(config: &[ServerConfig], cmd: RouteTestCmd) -> Result<()> {
+    // the cli already runs within the actix runtime, so the request is
+    // served from a separate system
+    let config = config.to_vec();
+    std::thread::spawn(move || actix_web::rt::System::new().block_on(run(&config, cmd)))
+        .join()
+        .map_err(|_| anyhow!("route test panicked"))?
+}
+
+/// Serve the request through the assembled application and report it.
+async fn run(config: &[ServerConfig], cmd: RouteTestCmd) -> Result<()> {
+    let method = Method::from_bytes(cmd.method.to_uppercase().as_bytes())
+        .with_context(|| format!("invalid request method {:?}", cmd.method))?;
+    let uri: Uri = cmd
+        .url
+        .parse()
+        .with_context(|| format!("invalid request url {:?}", cmd.url))?;
+    let host = cmd
+        .host
+        .clone()
+        .or_else(|| uri.authority().map(|a| a.to_string()));
+
+    let mut req = TestRequest::default()
+        .method(method.clone())
+        .uri(uri.path_and_query().map(|p| p.as_str()).unwrap_or("/"));
+    if let Some(host) = host.as_deref() {
+        req = req.insert_header((header::HOST, host));
+    }
+    for header in cmd.header.iter() {
+        req = req.insert_header((header.0.as_str(), header.1.as_str()));
+    }
+    if let Some(peer) = cmd.peer {
+        req = req.peer_addr(peer);
+    }
+    let listener = cmd.listener.as_deref();
+    print!(
+        "request: {method} {}{}",
+        host.as_deref().unwrap_or("-"),
+        uri.path()
+    );
+    match listener {
+        Some(label) => println!(" (listener: {label:?})"),
+        None => println!(),
+    }
+
+    bob_core::config::connect_config(config).await?;
+    let hash = bob_core::config::config_hash(config);
+    let app = test::init_service(bob_core::assemble_app(config, listener, &hash, true)?).await;
+    let (trace, status) = match app.call(req.to_request()).await {
+        Ok(res) => (
+            res.request().extensions().get::<Trace>().cloned(),
+            res.status(),
+        ),
+        Err(err) => (None, err.as_response_error().status_code()),
+    };
+    let hops = trace.map(|t| t.0).unwrap_or_default();
+
+    // the last server entered is the one answering the request
+    let entered = hops.iter().rposition(|hop| matches!(hop, Hop::Server(_)));
+    let Some(position) = entered else {
+        println!("server:  none matches the host");
+        println!("status:  {status}");
+        return Ok(());
+    };
+    let Hop::Server(n) = hops[position] else {
+        unreachable!("position is a server hop")
+    };
+    let server = &config[n];
+    let names: Vec<&str> = server.server_name.iter().map(|n| n.0.as_str()).collect();
+    println!("server:  server[{n}] (server_name: {names:?})");
+
+    let middleware: Vec<String> = server
+        .middleware
+        .iter()
+        .map(|m| variant(format!("{m:?}")))
+        .collect();
+    if !middleware.is_empty() {
+        println!(
+            "server middleware (outermost last): {}",
+            middleware.join(", ")
+        );
+    }
+
+    let directives: Vec<usize> = hops[position + 1..]
+        .iter()
+        .filter_map(|hop| match hop {
+            Hop::Directive(d) => Some(*d),
+            Hop::Server(_) => None,
+        })
+        .collect();
+    let Some((last, passed)) = directives.split_last() else {
+        println!("handler: no directive, answered by the server itself");
+        println!("status:  {status}");
+        return Ok(());
+    };
+    if !passed.is_empty() {
+        let passed: Vec<String> = passed
+            .iter()
+            .map(|d| {
+                let location = server.directives[*d].location.as_deref().unwrap_or("/");
+                format!("directives[{d}] ({location:?})")
+            })
+            .collect();
+        println!("passed on from: {}", passed.join(", "));
+    }
+    let directive = &server.directives[*last];
+    let location = directive.location.as_deref().unwrap_or("/");
+    println!("directive: directives[{last}] (location: {location:?})");
+
+    // middleware only wrap the components constructed before them
+    let construct: Vec<&Component> = directive.construct.iter().collect();
+    for (c, component) in construct.iter().enumerate() {
+        let Component::Module(module) = component else {
+            continue;
+        };
+        let wrapped: Vec<String> = construct[c + 1..]
+            .iter()
+            .filter_map(|c| c.middleware())
+            .map(|m| variant(format!("{m:?}")))
+            .collect();
+        print!(
+            "  module: {} (construct[{c}])",
+            variant(format!("{:?}", module.module))
+        );
+        if let Some(next) = module.next.as_ref() {
+            print!(" passes on {next:?}");
+        }
+        match wrapped.is_empty() {
+            true => println!(),
+            false => println!(" wrapped by {}", wrapped.join(", ")),
+        }
+    }
+    println!("status:  {status}");
+    Ok(())
+}
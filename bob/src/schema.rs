@@ -0,0 +1,263 @@
+//! Markdown Configuration Reference
+//!
+//! Renders the generated json schema as a human readable reference listing
+//! every configuration key with its type, default and documentation. Module
+//! and middleware variants link to a section describing their settings.
+
+use std::{
+    collections::{BTreeMap, BTreeSet, VecDeque},
+    fmt::{Result, Write},
+};
+
+use serde_json::{Map, Value};
+
+/// Split the `Default is` sentence used by doc comments from the description.
+fn split_default(description: &str) -> (String, Option<String>) {
+    let mut default = None;
+    let mut lines = Vec::new();
+    for line in description.lines().map(str::trim) {
+        match line.strip_prefix("Default is ") {
+            Some(value) => default = Some(value.trim_end_matches('.').to_owned()),
+            None if !line.is_empty() => lines.push(line),
+            None => {}
+        }
+    }
+    (lines.join(" "), default)
+}
+
+/// Escape text placed within a markdown table cell.
+#[inline]
+fn escape(text: &str) -> String {
+    text.replace('|', "\\|")
+}
+
+/// Description of the schema split from its documented default.
+#[inline]
+fn describe(schema: &Value) -> (String, Option<String>) {
+    schema
+        .get("description")
+        .and_then(Value::as_str)
+        .map(split_default)
+        .unwrap_or_default()
+}
+
+/// Reference generator tracking which definitions still need a section.
+struct Reference<'a> {
+    defs: &'a Map<String, Value>,
+    queue: VecDeque<String>,
+    seen: BTreeSet<String>,
+    titles: BTreeMap<String, String>,
+}
+
+impl<'a> Reference<'a> {
+    /// Heading of the definition.
+    fn title(&self, name: &str) -> String {
+        self.titles
+            .get(name)
+            .cloned()
+            .unwrap_or_else(|| name.to_owned())
+    }
+
+    /// Link to the referenced definition, queueing its section.
+    fn link(&mut self, pointer: &str, title: Option<String>) -> String {
+        let name = pointer.rsplit('/').next().unwrap_or(pointer).to_owned();
+        if self.seen.insert(name.clone()) {
+            self.queue.push_back(name.clone());
+            if let Some(title) = title {
+                self.titles.insert(name.clone(), title);
+            }
+        }
+        format!("[{}](#{})", self.title(&name), name.to_lowercase())
+    }
+
+    /// Human readable type of the schema.
+    fn type_name(&mut self, schema: &Value) -> String {
+        if let Some(pointer) = schema.get("$ref").and_then(Value::as_str) {
+            return self.link(pointer, None);
+        }
+        if let Some(values) = schema.get("enum").and_then(Value::as_array) {
+            let values: Vec<String> = values.iter().map(|v| format!("`{v}`")).collect();
+            return values.join(" \\| ");
+        }
+        if let Some(value) = schema.get("const") {
+            return format!("`{value}`");
+        }
+        for key in ["oneOf", "anyOf", "allOf"] {
+            if let Some(variants) = schema.get(key).and_then(Value::as_array) {
+                let names: Vec<String> = variants
+                    .iter()
+                    .map(|variant| self.type_name(variant))
+                    .filter(|name| name != "null")
+                    .collect();
+                return names.join(" \\| ");
+            }
+        }
+        let types: Vec<&str> = match schema.get("type") {
+            Some(Value::String(kind)) => vec![kind.as_str()],
+            Some(Value::Array(kinds)) => kinds.iter().filter_map(Value::as_str).collect(),
+            _ => Vec::new(),
+        };
+        let names: Vec<String> = types
+            .into_iter()
+            .filter(|kind| *kind != "null")
+            .map(|kind| match kind {
+                "array" => match schema.get("items") {
+                    Some(items) => format!("list of {}", self.type_name(items)),
+                    None => "list".to_owned(),
+                },
+                "object" => match schema.get("additionalProperties") {
+                    Some(value) if value.is_object() => {
+                        format!("map of {}", self.type_name(value))
+                    }
+                    _ => "object".to_owned(),
+                },
+                kind => kind.to_owned(),
+            })
+            .collect();
+        match names.is_empty() {
+            true => "any".to_owned(),
+            false => names.join(" \\| "),
+        }
+    }
+
+    /// Table of object properties, skipping variant tags.
+    fn properties(&mut self, out: &mut String, schema: &Value, skip: Option<&str>) -> Result {
+        let Some(properties) = schema.get("properties").and_then(Value::as_object) else {
+            return Ok(());
+        };
+        let required: Vec<&str> = schema
+            .get("required")
+            .and_then(Value::as_array)
+            .map(|keys| keys.iter().filter_map(Value::as_str).collect())
+            .unwrap_or_default();
+        let rows: Vec<(&String, &Value)> = properties
+            .iter()
+            .filter(|(key, _)| Some(key.as_str()) != skip)
+            .collect();
+        if rows.is_empty() {
+            return Ok(());
+        }
+        writeln!(out, "| Key | Type | Default | Description |")?;
+        writeln!(out, "|-----|------|---------|-------------|")?;
+        for (key, property) in rows {
+            let (description, default) = describe(property);
+            let default = match property.get("default") {
+                Some(value) => format!("`{value}`"),
+                None => default.unwrap_or_default(),
+            };
+            let key = match required.contains(&key.as_str()) {
+                true => format!("`{key}` (required)"),
+                false => format!("`{key}`"),
+            };
+            let kind = self.type_name(property);
+            writeln!(
+                out,
+                "| {key} | {kind} | {} | {} |",
+                escape(&default),
+                escape(&description)
+            )?;
+        }
+        writeln!(out)
+    }
+
+    /// Describe an enum variant, linking internally tagged variant settings.
+    fn variant(&mut self, out: &mut String, variant: &Value) -> Result {
+        let (description, _) = describe(variant);
+        let tag = variant
+            .get("properties")
+            .and_then(Value::as_object)
+            .and_then(|properties| {
+                properties.iter().find_map(|(key, property)| {
+                    let value = property.get("const").or_else(|| {
+                        property
+                            .get("enum")
+                            .and_then(Value::as_array)
+                            .and_then(|values| values.first())
+                    })?;
+                    Some((key.clone(), value.as_str()?.to_owned()))
+                })
+            });
+        let Some((key, value)) = tag else {
+            let kind = self.type_name(variant);
+            return match description.is_empty() {
+                true => writeln!(out, "- {kind}"),
+                false => writeln!(out, "- {kind}: {description}"),
+            };
+        };
+        writeln!(out, "#### `{key}: {value}`\n")?;
+        if !description.is_empty() {
+            writeln!(out, "{description}\n")?;
+        }
+        let target = variant.get("$ref").or_else(|| {
+            variant
+                .get("allOf")
+                .and_then(Value::as_array)
+                .and_then(|all| all.iter().find_map(|schema| schema.get("$ref")))
+        });
+        if let Some(pointer) = target.and_then(Value::as_str) {
+            let link = self.link(pointer, Some(format!("{value} {key}")));
+            writeln!(out, "Settings: {link}\n")?;
+        }
+        self.properties(out, variant, Some(&key))
+    }
+
+    /// Section documenting the definition.
+    fn section(&mut self, out: &mut String, name: &str) -> Result {
+        let defs = self.defs;
+        let Some(schema) = defs.get(name) else {
+            return Ok(());
+        };
+        writeln!(out, "<a id=\"{}\"></a>\n", name.to_lowercase())?;
+        writeln!(out, "### {}\n", self.title(name))?;
+        let (description, default) = describe(schema);
+        if !description.is_empty() {
+            writeln!(out, "{description}\n")?;
+        }
+        if let Some(default) = default {
+            writeln!(out, "Default is {default}\n")?;
+        }
+        if let Some(values) = schema.get("enum").and_then(Value::as_array) {
+            let values: Vec<String> = values.iter().map(|v| format!("`{v}`")).collect();
+            writeln!(out, "Values: {}\n", values.join(", "))?;
+        }
+        self.properties(out, schema, None)?;
+        for key in ["oneOf", "anyOf"] {
+            let Some(variants) = schema.get(key).and_then(Value::as_array) else {
+                continue;
+            };
+            for variant in variants {
+                self.variant(out, variant)?;
+            }
+            writeln!(out)?;
+        }
+        Ok(())
+    }
+}
+
+/// Render json schema as a markdown configuration reference.
+pub fn markdown(schema: &Value) -> std::result::Result<String, std::fmt::Error> {
+    let empty = Map::new();
+    let defs = schema
+        .get("$defs")
+        .and_then(Value::as_object)
+        .unwrap_or(&empty);
+    let mut reference = Reference {
+        defs,
+        queue: VecDeque::new(),
+        seen: BTreeSet::new(),
+        titles: BTreeMap::new(),
+    };
+
+    let mut out = String::new();
+    writeln!(out, "# Configuration Reference\n")?;
+    writeln!(
+        out,
+        "Generated from the configuration schema with `bob schema --format markdown`.\n"
+    )?;
+    let root = reference.type_name(schema);
+    writeln!(out, "The configuration file is a {root}.\n")?;
+    while let Some(name) = reference.queue.pop_front() {
+        reference.section(&mut out, &name)?;
+    }
+    Ok(out)
+}
@@ -0,0 +1,45 @@
+//! LAN File Sharing
+//!
+//! Announces a quick file server on the local network by printing the
+//! urls it is reachable with and a terminal QR code, so phones and other
+//! nearby devices can open it without typing the address.
+
+use std::net::{IpAddr, Ipv4Addr, UdpSocket};
+
+use anyhow::{Context, Result};
+use qrcode::{QrCode, render::unicode::Dense1x2};
+
+/// Address of the interface routing towards the wider network.
+///
+/// Connecting a UDP socket only selects a route and sends no packets.
+pub fn lan_addr() -> Result<IpAddr> {
+    let socket = UdpSocket::bind((Ipv4Addr::UNSPECIFIED, 0))
+        .and_then(|socket| {
+            socket.connect((Ipv4Addr::new(192, 0, 2, 1), 9))?;
+            Ok(socket)
+        })
+        .context("unable to determine lan address")?;
+    let ip = socket.local_addr()?.ip();
+    if ip.is_unspecified() || ip.is_loopback() {
+        anyhow::bail!("no lan interface available to share on");
+    }
+    Ok(ip)
+}
+
+/// Print reachable urls and a QR code of the LAN url.
+pub fn announce(scheme: &str, lan: IpAddr, port: u16) -> Result<()> {
+    let url = format!("{scheme}://{lan}:{port}/");
+    let qr = QrCode::new(url.as_bytes()).context("failed to generate qr code")?;
+    // inverted colors keep the code scannable on dark terminal themes
+    let image = qr
+        .render::<Dense1x2>()
+        .dark_color(Dense1x2::Light)
+        .light_color(Dense1x2::Dark)
+        .quiet_zone(true)
+        .build();
+    println!("sharing on your local network:");
+    println!("  local:   {scheme}://localhost:{port}/");
+    println!("  network: {url}");
+    println!("{image}");
+    Ok(())
+}
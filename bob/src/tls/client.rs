@@ -1,65 +0,0 @@
-//! TLS Client Certificate Validation Utils
-
-use std::sync::Arc;
-
-use actix_tls::connect::rustls_0_23::webpki_roots_cert_store;
-use anyhow::Result;
-use rustls::{
-    client::danger::{ServerCertVerified, ServerCertVerifier},
-    pki_types::{CertificateDer, ServerName, UnixTime},
-};
-
-/// Build Client TLS Configuration Setting
-pub fn build_tls_config(verify_ssl: bool) -> rustls::ClientConfig {
-    let mut config = rustls::ClientConfig::builder()
-        .with_root_certificates(webpki_roots_cert_store())
-        .with_no_client_auth();
-    config.alpn_protocols = vec![b"h2".to_vec(), b"http/1.1".to_vec()];
-    if !verify_ssl {
-        config
-            .dangerous()
-            .set_certificate_verifier(Arc::new(NoCertificateVerification));
-    }
-    config
-}
-
-/// No Verification TLS Configuration
-#[derive(Debug)]
-pub(crate) struct NoCertificateVerification;
-
-impl ServerCertVerifier for NoCertificateVerification {
-    fn verify_server_cert(
-        &self,
-        _end_entity: &CertificateDer<'_>,
-        _intermediates: &[CertificateDer<'_>],
-        _server_name: &ServerName<'_>,
-        _ocsp_response: &[u8],
-        _now: UnixTime,
-    ) -> Result<ServerCertVerified, rustls::Error> {
-        Ok(rustls::client::danger::ServerCertVerified::assertion())
-    }
-
-    fn verify_tls12_signature(
-        &self,
-        _message: &[u8],
-        _cert: &CertificateDer<'_>,
-        _dss: &rustls::DigitallySignedStruct,
-    ) -> Result<rustls::client::danger::HandshakeSignatureValid, rustls::Error> {
-        Ok(rustls::client::danger::HandshakeSignatureValid::assertion())
-    }
-
-    fn verify_tls13_signature(
-        &self,
-        _message: &[u8],
-        _cert: &CertificateDer<'_>,
-        _dss: &rustls::DigitallySignedStruct,
-    ) -> Result<rustls::client::danger::HandshakeSignatureValid, rustls::Error> {
-        Ok(rustls::client::danger::HandshakeSignatureValid::assertion())
-    }
-
-    fn supported_verify_schemes(&self) -> Vec<rustls::SignatureScheme> {
-        rustls::crypto::aws_lc_rs::default_provider()
-            .signature_verification_algorithms
-            .supported_schemes()
-    }
-}
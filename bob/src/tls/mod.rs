@@ -1,4 +0,0 @@
-//! TLS Client/Server Configuration
-
-pub mod client;
-pub mod server;